@@ -0,0 +1,81 @@
+//! Mide el tiempo de validar el merkle root de bloques grandes, como los que se descargan en la IBD.
+//! Se corre con: cargo bench --bench merkle_root
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use bitcoin::{
+    block_store::BlockStore,
+    messages::block::{merge_hashes, merkle_root, Block},
+    structs::hash256::Hash256,
+};
+
+/// Cantidades de transacciones de los bloques que se miden.
+const BLOCK_SIZES: [usize; 3] = [100, 1000, 4000];
+/// Tiempo durante el que se repite cada medicion.
+const MEASURE_TIME: Duration = Duration::from_secs(2);
+
+/// Devuelve un bloque con la cantidad de transacciones recibida, repitiendo las del bloque de prueba.
+/// Se quita el witness de las transacciones y se actualiza el merkle root para que el bloque sea valido.
+fn block_with(transactions: usize) -> Block {
+    let mut block = BlockStore::open(String::from("tests"))
+        .and_then(|store| store.get(String::from("test_block")))
+        .expect("tests/blocks/test_block.bin should be a valid block");
+    let test_transactions = block.transactions.clone();
+    block.transactions = test_transactions
+        .into_iter()
+        .cycle()
+        .take(transactions)
+        .collect();
+    for transaction in &mut block.transactions {
+        for input in &mut transaction.inputs {
+            input.witness.clear();
+        }
+    }
+    let txids = block.transactions.iter().map(|tx| tx.hash()).collect();
+    block.header.merkle_root = merkle_root(txids).unwrap_or(Hash256::ZERO);
+    block
+}
+
+/// Calcula el merkle root en un solo thread, para comparar con merkle_root.
+fn sequential_merkle_root(mut level: Vec<Hash256>) -> Option<Hash256> {
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| merge_hashes(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level.first().copied()
+}
+
+/// Repite la funcion durante MEASURE_TIME y muestra el tiempo promedio de cada llamada.
+fn measure(name: &str, function: impl Fn()) {
+    let start = Instant::now();
+    let mut iterations = 0_u32;
+    while start.elapsed() < MEASURE_TIME {
+        function();
+        iterations += 1;
+    }
+    println!("{:<40} {:>12.2?}/iter", name, start.elapsed() / iterations);
+}
+
+fn main() {
+    for size in BLOCK_SIZES {
+        let block = block_with(size);
+        measure(&format!("create_merkle_root ({} txs)", size), || {
+            black_box(&block)
+                .create_merkle_root()
+                .expect("the block should be valid");
+        });
+
+        let hashes: Vec<Hash256> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        measure(&format!("merkle_root ({} hashes)", size), || {
+            black_box(merkle_root(black_box(hashes.clone())));
+        });
+        measure(&format!("sequential merkle_root ({} hashes)", size), || {
+            black_box(sequential_merkle_root(black_box(hashes.clone())));
+        });
+    }
+}
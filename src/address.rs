@@ -0,0 +1,329 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    encoding::{
+        base58,
+        bech32::{decode_segwit_address, encode_segwit_address},
+    },
+    error::CustomError,
+    network::Network,
+    structs::script::{classify::ScriptType, Script},
+};
+
+/// Tamaño en bytes de una direccion base58 decodificada sin el checksum: prefijo y hash.
+const BASE58_ADDRESS_SIZE: usize = 21;
+/// Redes con un hrp bech32 propio. Signet comparte el de testnet.
+const BECH32_NETWORKS: [Network; 3] = [Network::Mainnet, Network::Testnet, Network::Regtest];
+/// Redes con prefijos base58 propios. Signet y regtest comparten los de testnet.
+const BASE58_NETWORKS: [Network; 2] = [Network::Mainnet, Network::Testnet];
+
+/// Payload es el destino de los fondos enviados a una direccion.
+/// - PubkeyHash: Hash de una public key, de una direccion P2PKH.
+/// - ScriptHash: Hash de un redeem script, de una direccion P2SH.
+/// - WitnessProgram: Version del witness y witness program de una direccion SegWit, por ejemplo P2WPKH, P2WSH o P2TR.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    PubkeyHash(Vec<u8>),
+    ScriptHash(Vec<u8>),
+    WitnessProgram(u8, Vec<u8>),
+}
+
+/// Address es una direccion a la que se pueden enviar fondos, en base58check (P2PKH o P2SH)
+/// o en bech32 y bech32m (SegWit).
+/// Los elementos son:
+/// - network: Red a la que pertenece la direccion. Las direcciones de testnet tambien son las de signet,
+///   y las base58 de testnet tambien son las de regtest.
+/// - payload: Destino de los fondos.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub network: Network,
+    pub payload: Payload,
+}
+
+impl Address {
+    /// Devuelve la direccion P2PKH de un hash de public key en la red recibida.
+    pub fn p2pkh(pubkey_hash: &[u8], network: Network) -> Self {
+        Self {
+            network,
+            payload: Payload::PubkeyHash(pubkey_hash.to_vec()),
+        }
+    }
+
+    /// Devuelve la direccion P2SH de un hash de redeem script en la red recibida.
+    pub fn p2sh(script_hash: &[u8], network: Network) -> Self {
+        Self {
+            network,
+            payload: Payload::ScriptHash(script_hash.to_vec()),
+        }
+    }
+
+    /// Devuelve la direccion P2WPKH de un hash de public key en la red recibida.
+    pub fn p2wpkh(pubkey_hash: &[u8], network: Network) -> Self {
+        Self {
+            network,
+            payload: Payload::WitnessProgram(0, pubkey_hash.to_vec()),
+        }
+    }
+
+    /// Devuelve la direccion a la que paga un script pubkey P2PKH, P2SH, P2WPKH, P2WSH o P2TR en la red recibida.
+    /// Es la operacion inversa de script_pubkey.
+    /// Devuelve CustomError::Validation si el script pubkey no es de ninguno de esos tipos.
+    pub fn from_script_pubkey(script_pubkey: &[u8], network: Network) -> Result<Self, CustomError> {
+        let payload = match ScriptType::classify(script_pubkey) {
+            ScriptType::P2PKH(pubkey_hash) => Payload::PubkeyHash(pubkey_hash),
+            ScriptType::P2SH(script_hash) => Payload::ScriptHash(script_hash),
+            ScriptType::P2WPKH(program) | ScriptType::P2WSH(program) => {
+                Payload::WitnessProgram(0, program)
+            }
+            ScriptType::P2TR(program) => Payload::WitnessProgram(1, program),
+            _ => {
+                return Err(CustomError::Validation(String::from(
+                    "Unsupported script pubkey",
+                )))
+            }
+        };
+        Ok(Self { network, payload })
+    }
+
+    /// Decodifica una direccion de cualquier red.
+    /// Devuelve CustomError::Validation con el motivo si la direccion no es valida.
+    pub fn decode(address: &str) -> Result<Self, CustomError> {
+        let address = address.trim();
+        if address.is_empty() {
+            return Err(invalid_address("the address is empty"));
+        }
+        let lowercase = address.to_lowercase();
+        for network in BECH32_NETWORKS {
+            let hrp = network.bech32_hrp();
+            if lowercase.starts_with(&format!("{}1", hrp)) {
+                let (witness_version, program) =
+                    decode_segwit_address(hrp, address).map_err(|error| {
+                        invalid_address(&format!("it is not valid bech32, {}", error))
+                    })?;
+                return Ok(Self {
+                    network,
+                    payload: Payload::WitnessProgram(witness_version, program),
+                });
+            }
+        }
+        decode_base58(address)
+    }
+
+    /// Decodifica una direccion de la red recibida.
+    /// Devuelve CustomError::Validation con el motivo si la direccion no es valida o es de otra red.
+    pub fn parse(address: &str, network: Network) -> Result<Self, CustomError> {
+        let decoded = Self::decode(address)?;
+        if !decoded.is_for(network) {
+            return Err(invalid_address(&format!(
+                "it is a {} address, but the node is running on {}",
+                decoded.network.name(),
+                network.name()
+            )));
+        }
+        Ok(decoded)
+    }
+
+    /// Devuelve true si la direccion se puede usar en la red recibida.
+    pub fn is_for(&self, network: Network) -> bool {
+        match self.network {
+            Network::Mainnet => network == Network::Mainnet,
+            Network::Regtest => network == Network::Regtest,
+            Network::Testnet | Network::Signet => network != Network::Mainnet,
+        }
+    }
+
+    /// Devuelve el hash de la public key de una direccion P2PKH o P2WPKH, o None si la direccion es de otro tipo.
+    pub fn pubkey_hash(&self) -> Option<&[u8]> {
+        match &self.payload {
+            Payload::PubkeyHash(pubkey_hash) => Some(pubkey_hash),
+            Payload::WitnessProgram(0, program) if program.len() == 20 => Some(program),
+            _ => None,
+        }
+    }
+
+    /// Devuelve true si la direccion es SegWit, codificada en bech32 o bech32m.
+    pub fn is_segwit(&self) -> bool {
+        matches!(self.payload, Payload::WitnessProgram(..))
+    }
+
+    /// Devuelve el script pubkey que paga a la direccion.
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        match &self.payload {
+            Payload::PubkeyHash(pubkey_hash) => Script::p2pkh(pubkey_hash).serialize(),
+            Payload::ScriptHash(script_hash) => Script::p2sh(script_hash).serialize(),
+            Payload::WitnessProgram(witness_version, program) => {
+                Script::witness_program(*witness_version, program).serialize()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    /// Codifica la direccion con los prefijos base58 o el hrp bech32 de su red.
+    /// Devuelve fmt::Error si el witness program no es valido para su version.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prefix, hash) = match &self.payload {
+            Payload::PubkeyHash(pubkey_hash) => (self.network.p2pkh_prefix(), pubkey_hash),
+            Payload::ScriptHash(script_hash) => (self.network.p2sh_prefix(), script_hash),
+            Payload::WitnessProgram(witness_version, program) => {
+                let address =
+                    encode_segwit_address(self.network.bech32_hrp(), *witness_version, program)
+                        .map_err(|_| fmt::Error)?;
+                return write!(f, "{}", address);
+            }
+        };
+        let mut payload = vec![prefix];
+        payload.extend(hash);
+        write!(f, "{}", base58::encode_check(&payload))
+    }
+}
+
+impl FromStr for Address {
+    type Err = CustomError;
+
+    /// Decodifica una direccion de la red seleccionada.
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Self::parse(address, Network::current())
+    }
+}
+
+/// Decodifica una direccion P2PKH o P2SH en base58check.
+fn decode_base58(address: &str) -> Result<Address, CustomError> {
+    let payload = base58::decode_check(address)
+        .map_err(|error| invalid_address(&format!("it is not valid base58check, {}", error)))?;
+    if payload.len() != BASE58_ADDRESS_SIZE {
+        return Err(invalid_address("it has an invalid length"));
+    }
+
+    let hash = payload[1..].to_vec();
+    for network in BASE58_NETWORKS {
+        let payload = match payload[0] {
+            prefix if prefix == network.p2pkh_prefix() => Payload::PubkeyHash(hash),
+            prefix if prefix == network.p2sh_prefix() => Payload::ScriptHash(hash),
+            _ => continue,
+        };
+        return Ok(Address { network, payload });
+    }
+    Err(invalid_address("its prefix is not of a known address type"))
+}
+
+fn invalid_address(reason: &str) -> CustomError {
+    CustomError::Validation(format!("Invalid address, {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p2pkh_address(pubkey_hash: &[u8]) -> String {
+        Address::p2pkh(pubkey_hash, Network::Testnet).to_string()
+    }
+
+    const PUBKEY_HASH: [u8; 20] = [7; 20];
+
+    fn mainnet_p2sh_address() -> String {
+        let mut payload = vec![Network::Mainnet.p2sh_prefix()];
+        payload.extend(PUBKEY_HASH);
+        base58::encode_check(&payload)
+    }
+
+    #[test]
+    fn decodes_addresses_of_each_network() {
+        let testnet = Address::decode(&p2pkh_address(&PUBKEY_HASH)).unwrap();
+        assert_eq!(testnet.network, Network::Testnet);
+        assert_eq!(testnet.payload, Payload::PubkeyHash(PUBKEY_HASH.to_vec()));
+        assert_eq!(
+            testnet.script_pubkey(),
+            Script::p2pkh(&PUBKEY_HASH).serialize()
+        );
+
+        let mainnet = Address::decode(&mainnet_p2sh_address()).unwrap();
+        assert_eq!(mainnet.network, Network::Mainnet);
+        assert_eq!(
+            mainnet.script_pubkey(),
+            Script::p2sh(&PUBKEY_HASH).serialize()
+        );
+
+        for network in BECH32_NETWORKS {
+            let address = encode_segwit_address(network.bech32_hrp(), 0, &PUBKEY_HASH).unwrap();
+            let segwit = Address::decode(&address.to_uppercase()).unwrap();
+            assert_eq!(segwit.network, network);
+            assert_eq!(
+                segwit.script_pubkey(),
+                Script::p2wpkh(&PUBKEY_HASH).serialize()
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_addresses_of_each_network() {
+        for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+            for address in [
+                Address::p2pkh(&PUBKEY_HASH, network),
+                Address::p2sh(&PUBKEY_HASH, network),
+                Address::p2wpkh(&PUBKEY_HASH, network),
+            ] {
+                let encoded = address.to_string();
+                let decoded = Address::parse(&encoded, network).unwrap();
+                assert_eq!(decoded.payload, address.payload);
+                assert_eq!(
+                    Address::from_script_pubkey(&decoded.script_pubkey(), network).unwrap(),
+                    address
+                );
+            }
+        }
+        assert!(Address::p2wpkh(&PUBKEY_HASH, Network::Mainnet)
+            .to_string()
+            .starts_with("bc1q"));
+        assert_eq!(
+            Address::p2wpkh(&PUBKEY_HASH, Network::Testnet).pubkey_hash(),
+            Some(&PUBKEY_HASH[..])
+        );
+        assert_eq!(
+            Address::p2sh(&PUBKEY_HASH, Network::Testnet).pubkey_hash(),
+            None
+        );
+        assert!(Address::from_script_pubkey(&[0x6a, 0x01, 0x00], Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn rejects_addresses_of_another_network() {
+        let mainnet = encode_segwit_address("bc", 0, &PUBKEY_HASH).unwrap();
+        assert!(Address::parse(&mainnet, Network::Mainnet).is_ok());
+        let Err(CustomError::Validation(reason)) = Address::parse(&mainnet, Network::Testnet)
+        else {
+            panic!("mainnet address accepted on testnet")
+        };
+        assert!(reason.contains("mainnet address"));
+
+        let testnet = p2pkh_address(&PUBKEY_HASH);
+        assert!(Address::parse(&testnet, Network::Mainnet).is_err());
+        assert!(Address::parse(&testnet, Network::Signet).is_ok());
+        assert!(Address::parse(&testnet, Network::Regtest).is_ok());
+
+        let regtest = encode_segwit_address("bcrt", 0, &PUBKEY_HASH).unwrap();
+        assert!(Address::parse(&regtest, Network::Regtest).is_ok());
+        assert!(Address::parse(&regtest, Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn reports_why_an_address_is_invalid() {
+        let reason = |address: &str| match Address::decode(address) {
+            Err(CustomError::Validation(reason)) => reason,
+            _ => panic!("invalid address {} accepted", address),
+        };
+        let address = p2pkh_address(&PUBKEY_HASH);
+        let mut typo = address.clone();
+        let last = typo.pop().unwrap();
+        typo.push(if last == 'z' { 'y' } else { 'z' });
+
+        assert!(reason("").contains("empty"));
+        assert!(reason(&address.replacen(&address[1..2], "0", 1)).contains("base58"));
+        assert!(reason(&typo).contains("checksum"));
+        assert!(reason(&base58::encode_check(&PUBKEY_HASH)).contains("length"));
+
+        let mut segwit = encode_segwit_address("tb", 0, &PUBKEY_HASH).unwrap();
+        segwit.pop();
+        assert!(reason(&format!("{}q", segwit)).contains("bech32"));
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+};
+
+use bitcoin_hashes::{sha256d, Hash};
+
+use crate::error::{CustomError, StorageError};
+
+/// Bytes iniciales de los archivos guardados con write_atomic.
+const STORE_MAGIC: &[u8; 4] = b"RZST";
+/// Version del formato de los archivos guardados con write_atomic.
+const STORE_VERSION: u32 = 1;
+/// Tamaño en bytes del encabezado: magic, version, longitud y checksum del contenido.
+const STORE_HEADER_SIZE: usize = 20;
+/// Extension del archivo temporal donde se escribe el contenido nuevo antes de reemplazar al anterior.
+const TEMP_EXTENSION: &str = "tmp";
+/// Extension de la copia del contenido anterior, que se usa si el archivo principal esta dañado.
+const BACKUP_EXTENSION: &str = "bak";
+
+/// Guarda el contenido en el archivo de forma atomica: primero lo escribe en un archivo temporal,
+/// fuerza su escritura en disco y recien entonces lo renombra sobre el archivo anterior, que se conserva como backup.
+/// Ante un corte de luz el archivo queda con el contenido anterior o con el nuevo, nunca a medio escribir.
+/// El formato es: magic (4 bytes) | version (4 bytes) | longitud del contenido (8 bytes) | checksum (4 bytes) | contenido.
+pub fn write_atomic(path: &str, payload: &[u8]) -> Result<(), CustomError> {
+    let mut buffer = STORE_MAGIC.to_vec();
+    buffer.extend(STORE_VERSION.to_le_bytes());
+    buffer.extend((payload.len() as u64).to_le_bytes());
+    buffer.extend(checksum(payload));
+    buffer.extend(payload);
+
+    let temp_path = sibling_path(path, TEMP_EXTENSION);
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&buffer)?;
+    file.sync_all()?;
+    drop(file);
+
+    if Path::new(path).exists() {
+        fs::rename(path, sibling_path(path, BACKUP_EXTENSION))?;
+    }
+    fs::rename(&temp_path, path)?;
+    sync_parent_dir(path)
+}
+
+/// Lee el contenido de un archivo guardado con write_atomic, reparando las escrituras interrumpidas:
+/// - Descarta el archivo temporal de una escritura que no llego a completarse.
+/// - Si el archivo no existe o su checksum no coincide, usa el backup con el contenido anterior y lo restaura.
+///
+/// Los archivos sin encabezado, guardados antes de este formato, se leen completos solo si no hay backup,
+/// ya que write_atomic siempre deja uno: si existe, un archivo sin encabezado se considera dañado.
+/// Devuelve un contenido vacio si no existe el archivo ni su backup.
+/// Devuelve CustomError si el archivo esta dañado y no tiene un backup valido.
+pub fn read_atomic(path: &str) -> Result<Vec<u8>, CustomError> {
+    let temp_path = sibling_path(path, TEMP_EXTENSION);
+    if Path::new(&temp_path).exists() {
+        fs::remove_file(&temp_path)?;
+    }
+
+    let backup_path = sibling_path(path, BACKUP_EXTENSION);
+    let has_backup = Path::new(&backup_path).exists();
+    let stored = read_file(path)?;
+    if let Some(payload) = stored
+        .as_deref()
+        .and_then(|buffer| decode(buffer, !has_backup))
+    {
+        return Ok(payload);
+    }
+
+    let backup = read_file(&backup_path)?;
+    match (stored, backup.as_deref().map(|buffer| decode(buffer, true))) {
+        (_, Some(Some(payload))) => {
+            fs::copy(&backup_path, path)?;
+            Ok(payload)
+        }
+        (None, None) => Ok(vec![]),
+        _ => Err(CustomError::Storage(StorageError::CorruptedStore)),
+    }
+}
+
+/// Borra el archivo junto con su temporal y su backup, para que el contenido anterior no se restaure al leerlo.
+pub fn remove_atomic(path: &str) -> Result<(), CustomError> {
+    for path in [
+        path.to_string(),
+        sibling_path(path, TEMP_EXTENSION),
+        sibling_path(path, BACKUP_EXTENSION),
+    ] {
+        if Path::new(&path).exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Devuelve el contenido de un archivo, o None si no existe.
+fn read_file(path: &str) -> Result<Option<Vec<u8>>, CustomError> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let mut buffer = vec![];
+    file.read_to_end(&mut buffer)?;
+    Ok(Some(buffer))
+}
+
+/// Valida el encabezado del archivo y devuelve su contenido, o None si esta dañado.
+/// Los archivos sin magic se devuelven completos si accept_legacy es true, ya que se guardaron antes de este formato.
+fn decode(buffer: &[u8], accept_legacy: bool) -> Option<Vec<u8>> {
+    if !buffer.starts_with(STORE_MAGIC) {
+        return accept_legacy.then(|| buffer.to_vec());
+    }
+    if buffer.len() < STORE_HEADER_SIZE {
+        return None;
+    }
+    let version = u32::from_le_bytes(buffer[4..8].try_into().ok()?);
+    let length = u64::from_le_bytes(buffer[8..16].try_into().ok()?) as usize;
+    let payload = &buffer[STORE_HEADER_SIZE..];
+    if version != STORE_VERSION
+        || payload.len() != length
+        || buffer[16..STORE_HEADER_SIZE] != checksum(payload)
+    {
+        return None;
+    }
+    Some(payload.to_vec())
+}
+
+/// Devuelve los primeros 4 bytes del doble SHA256 del contenido.
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let hash = sha256d::Hash::hash(payload).to_byte_array();
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn sibling_path(path: &str, extension: &str) -> String {
+    format!("{}.{}", path, extension)
+}
+
+/// Fuerza la escritura en disco del directorio del archivo, para que el renombre no se pierda ante un corte de luz.
+fn sync_parent_dir(path: &str) -> Result<(), CustomError> {
+    let parent = match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_keeping_a_backup() {
+        let path = "tests/test_atomic_write.bin";
+        remove_atomic(path).unwrap();
+        assert!(read_atomic(path).unwrap().is_empty());
+
+        write_atomic(path, b"first").unwrap();
+        write_atomic(path, b"second").unwrap();
+        assert_eq!(read_atomic(path).unwrap(), b"second");
+        assert_eq!(
+            decode(
+                &fs::read(sibling_path(path, BACKUP_EXTENSION)).unwrap(),
+                false
+            )
+            .unwrap(),
+            b"first"
+        );
+        assert!(!Path::new(&sibling_path(path, TEMP_EXTENSION)).exists());
+
+        remove_atomic(path).unwrap();
+    }
+
+    #[test]
+    fn repairs_torn_writes_from_the_backup() {
+        let path = "tests/test_atomic_repair.bin";
+        remove_atomic(path).unwrap();
+        write_atomic(path, b"first").unwrap();
+        write_atomic(path, b"second").unwrap();
+
+        // escritura interrumpida antes del renombre
+        fs::write(sibling_path(path, TEMP_EXTENSION), b"RZST\x01").unwrap();
+        assert_eq!(read_atomic(path).unwrap(), b"second");
+        assert!(!Path::new(&sibling_path(path, TEMP_EXTENSION)).exists());
+
+        // archivo principal dañado
+        let mut stored = fs::read(path).unwrap();
+        stored.truncate(stored.len() - 2);
+        fs::write(path, stored).unwrap();
+        assert_eq!(read_atomic(path).unwrap(), b"first");
+        assert_eq!(read_atomic(path).unwrap(), b"first");
+
+        // corte entre el renombre del backup y el del archivo nuevo
+        fs::remove_file(path).unwrap();
+        assert_eq!(read_atomic(path).unwrap(), b"first");
+
+        fs::write(path, b"RZST\x01\x00\x00\x00").unwrap();
+        fs::remove_file(sibling_path(path, BACKUP_EXTENSION)).unwrap();
+        assert!(matches!(
+            read_atomic(path),
+            Err(CustomError::Storage(StorageError::CorruptedStore))
+        ));
+
+        remove_atomic(path).unwrap();
+    }
+
+    #[test]
+    fn reads_files_saved_before_the_format() {
+        let path = "tests/test_atomic_legacy.bin";
+        remove_atomic(path).unwrap();
+        fs::write(path, b"legacy content").unwrap();
+        assert_eq!(read_atomic(path).unwrap(), b"legacy content");
+
+        // el primer guardado conserva el archivo anterior como backup
+        write_atomic(path, b"new content").unwrap();
+        assert_eq!(read_atomic(path).unwrap(), b"new content");
+        remove_atomic(path).unwrap();
+    }
+
+    #[test]
+    fn headerless_file_with_backup_is_corrupted() {
+        let path = "tests/test_atomic_headerless.bin";
+        remove_atomic(path).unwrap();
+        write_atomic(path, b"first").unwrap();
+        write_atomic(path, b"second").unwrap();
+
+        // el encabezado se daño y el archivo ya no empieza con el magic
+        let mut stored = fs::read(path).unwrap();
+        stored[0] = 0;
+        fs::write(path, stored).unwrap();
+        assert_eq!(read_atomic(path).unwrap(), b"first");
+
+        fs::write(path, b"garbage").unwrap();
+        fs::write(sibling_path(path, BACKUP_EXTENSION), b"RZST").unwrap();
+        assert!(matches!(
+            read_atomic(path),
+            Err(CustomError::Storage(StorageError::CorruptedStore))
+        ));
+
+        remove_atomic(path).unwrap();
+    }
+}
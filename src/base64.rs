@@ -0,0 +1,89 @@
+use crate::error::CustomError;
+
+/// Alfabeto estandar de base64 (RFC 4648).
+const CHARSET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// Caracter de relleno hasta completar un grupo de 4 caracteres.
+const PADDING: u8 = b'=';
+
+/// Codifica los bytes en base64 (RFC 4648), con relleno.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | ((*byte as u32) << (16 - 8 * i))
+        });
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(CHARSET[((group >> (18 - 6 * i)) & 0x3f) as usize] as char),
+                false => encoded.push(PADDING as char),
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodifica un texto en base64 (RFC 4648), con relleno.
+/// Devuelve CustomError si el largo no es multiplo de 4, si tiene caracteres invalidos o si el relleno no es valido.
+pub fn decode(text: &str) -> Result<Vec<u8>, CustomError> {
+    let invalid = || CustomError::Validation(String::from("Invalid base64"));
+    let chunks = text.as_bytes().chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return Err(invalid());
+    }
+
+    let last_index = chunks.len().saturating_sub(1);
+    let mut decoded = vec![];
+    for (index, chunk) in chunks.enumerate() {
+        let is_last = index == last_index;
+        let padding = chunk
+            .iter()
+            .rev()
+            .take_while(|byte| **byte == PADDING)
+            .count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return Err(invalid());
+        }
+        let mut group = 0u32;
+        for (i, byte) in chunk[..4 - padding].iter().enumerate() {
+            let value = CHARSET
+                .iter()
+                .position(|char| char == byte)
+                .ok_or_else(invalid)?;
+            group |= (value as u32) << (18 - 6 * i);
+        }
+        let bytes = group.to_be_bytes();
+        decoded.extend(&bytes[1..4 - padding]);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_4648_test_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (text, encoded) in vectors {
+            assert_eq!(encode(text.as_bytes()), encoded);
+            assert_eq!(decode(encoded).unwrap(), text.as_bytes());
+        }
+        assert_eq!(encode(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn invalid_base64_is_rejected() {
+        assert!(decode("Zm9").is_err());
+        assert!(decode("Zm9v!A==").is_err());
+        assert!(decode("Zg==Zm9v").is_err());
+        assert!(decode("Z===").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    network::Network,
+    structs::{block_header::BlockHeader, hash256::Hash256},
+};
+
+/// Cantidad de hashes consecutivos al inicio del block locator, antes de empezar a espaciarlos.
+const DENSE_HASHES: usize = 10;
+
+/// Construye un block locator para pedir headers con GetHeaders a partir de una cadena de headers
+/// ordenada de la mas antigua a la mas reciente.
+/// Contiene los hashes de los ultimos 10 headers y luego cada vez mas espaciados, duplicando la distancia en
+/// cada paso, terminando en el genesis de la red. Asi tiene O(log n) hashes y permite que un peer encuentre
+/// el ultimo header en comun aunque la cadena haya quedado en un fork o el peer este en otra rama.
+pub fn block_locator(headers: &[BlockHeader]) -> Vec<Hash256> {
+    let mut locator: Vec<Hash256> = locator_indexes(headers.len())
+        .into_iter()
+        .map(|index| *headers[index].hash())
+        .collect();
+    locator.push(Network::current().genesis_hash());
+    locator
+}
+
+/// Devuelve las posiciones de los headers que incluye el block locator de una cadena de len headers,
+/// de la mas reciente a la mas antigua y sin el genesis.
+pub fn locator_indexes(len: usize) -> Vec<usize> {
+    let mut indexes = vec![];
+    let mut step = 1;
+    let mut height = len;
+    while height > 0 {
+        indexes.push(height - 1);
+        if indexes.len() >= DENSE_HASHES {
+            step *= 2;
+        }
+        height = height.saturating_sub(step);
+    }
+    indexes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(count: usize) -> Vec<BlockHeader> {
+        (0..count)
+            .map(|index| BlockHeader {
+                version: 1,
+                prev_block_hash: Hash256::ZERO,
+                merkle_root: Hash256::ZERO,
+                timestamp: 0,
+                bits: 0,
+                nonce: 0,
+                hash: {
+                    let mut hash = [0; 32];
+                    hash[..4].copy_from_slice(&(index as u32 + 1).to_le_bytes());
+                    hash.into()
+                },
+                broadcasted: false,
+                block_downloaded: false,
+            })
+            .collect()
+    }
+
+    fn heights(locator: &[Hash256]) -> Vec<u32> {
+        locator[..locator.len() - 1]
+            .iter()
+            .map(|hash| {
+                u32::from_le_bytes([
+                    hash.as_bytes()[0],
+                    hash.as_bytes()[1],
+                    hash.as_bytes()[2],
+                    hash.as_bytes()[3],
+                ])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_chain_locator() {
+        assert_eq!(block_locator(&[]), vec![Network::current().genesis_hash()]);
+    }
+
+    #[test]
+    fn locator_is_exponentially_spaced() {
+        let locator = block_locator(&headers(30));
+        assert_eq!(
+            heights(&locator),
+            vec![30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 19, 15, 7]
+        );
+        assert_eq!(locator.last().unwrap(), &Network::current().genesis_hash());
+
+        let locator = block_locator(&headers(100_000));
+        assert_eq!(
+            heights(&locator)[..10],
+            [100_000, 99_999, 99_998, 99_997, 99_996, 99_995, 99_994, 99_993, 99_992, 99_991]
+        );
+        assert!(locator.len() < 30);
+    }
+}
@@ -0,0 +1,297 @@
+use std::io::Read;
+
+use bitcoin_hashes::{sha256, sha256d, Hash, HashEngine};
+
+use crate::{
+    error::{CustomError, NetworkError, ParseError},
+    messages::{block::Block, transaction::Transaction},
+    parser::{BufferParser, Parser},
+    structs::block_header::BlockHeader,
+};
+
+/// Tamaño del header de un bloque.
+const BLOCK_HEADER_SIZE: usize = 80;
+/// Tamaño maximo de una transaccion, es el peso maximo de un bloque (BIP 141).
+/// Una transaccion que no termina dentro de este tamaño no es valida y el parser la descarta.
+pub const MAX_TRANSACTION_SIZE: usize = 4_000_000;
+/// Tamaño de los bloques de bytes que se leen de un reader.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// BlockStreamParser parsea un bloque a medida que llegan sus bytes, sin esperar a tener el payload completo.
+/// Solo conserva los bytes de la transaccion que todavia no termino de llegar, por lo que la memoria usada
+/// depende del tamaño de la transaccion mas grande y no del tamaño del bloque.
+/// Los elementos son:
+/// - remaining: Cantidad de bytes del payload que todavia no se recibieron.
+/// - pending: Bytes recibidos que todavia no forman un elemento completo del bloque.
+/// - header: Header del bloque, una vez recibido.
+/// - tx_count: Cantidad de transacciones del bloque, una vez recibida.
+/// - transactions: Transacciones parseadas hasta el momento.
+/// - checksum: Checksum del payload informado en el header del mensaje.
+/// - engine: Hash de los bytes recibidos, para verificar el checksum sin conservar el payload.
+pub struct BlockStreamParser {
+    remaining: usize,
+    pending: Vec<u8>,
+    header: Option<BlockHeader>,
+    tx_count: Option<usize>,
+    transactions: Vec<Transaction>,
+    checksum: [u8; 4],
+    engine: sha256::HashEngine,
+}
+
+impl BlockStreamParser {
+    /// Crea el parser de un bloque cuyo payload tiene el tamaño y el checksum recibidos.
+    pub fn new(payload_size: usize, checksum: [u8; 4]) -> Self {
+        Self {
+            remaining: payload_size,
+            pending: vec![],
+            header: None,
+            tx_count: None,
+            transactions: vec![],
+            checksum,
+            engine: sha256d::Hash::engine(),
+        }
+    }
+
+    /// Procesa los bytes recibidos y devuelve cuantos consumio.
+    /// Nunca consume mas bytes que los que le faltan al payload, los siguientes pertenecen al proximo mensaje.
+    /// Devuelve CustomError si:
+    /// - El header, la cantidad de transacciones o alguna transaccion no son validos.
+    /// - Una transaccion supera MAX_TRANSACTION_SIZE.
+    /// - El payload termina antes de la ultima transaccion o tiene bytes de sobra.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<usize, CustomError> {
+        let consumed = bytes.len().min(self.remaining);
+        self.pending.extend_from_slice(&bytes[..consumed]);
+        self.engine.input(&bytes[..consumed]);
+        self.remaining -= consumed;
+
+        let parsed = self.parse_pending()?;
+        self.pending.drain(..parsed);
+
+        if self.pending.len() > MAX_TRANSACTION_SIZE {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        if self.remaining == 0 && !(self.is_complete() && self.pending.is_empty()) {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(consumed)
+    }
+
+    /// Devuelve true si ya se recibio el payload completo y se parsearon todas las transacciones.
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0 && self.tx_count == Some(self.transactions.len())
+    }
+
+    /// Devuelve el bloque parseado.
+    /// Devuelve CustomError si:
+    /// - Todavia no se recibio el bloque completo.
+    /// - El checksum del payload no coincide con el del header, es decir si el bloque llego dañado.
+    pub fn finish(self) -> Result<Block, CustomError> {
+        if !self.is_complete() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        if sha256d::Hash::from_engine(self.engine)[..4] != self.checksum {
+            return Err(CustomError::Network(NetworkError::InvalidChecksum));
+        }
+        match self.header {
+            Some(header) => Ok(Block::new(header, self.transactions)),
+            None => Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid)),
+        }
+    }
+
+    /// Parsea todos los elementos completos de los bytes pendientes y devuelve cuantos bytes uso.
+    fn parse_pending(&mut self) -> Result<usize, CustomError> {
+        let mut parser = BufferParser::new(&self.pending);
+        if self.header.is_none() {
+            let Ok(header) = parser.extract_buffer(BLOCK_HEADER_SIZE) else { return Ok(0) };
+            self.header = Some(BlockHeader::parse(header.to_vec())?);
+        }
+        if self.tx_count.is_none() {
+            let parsed = self.pending.len() - parser.len();
+            let Ok(tx_count) = parser.extract_varint() else { return Ok(parsed) };
+            self.tx_count = Some(tx_count as usize);
+        }
+        while Some(self.transactions.len()) != self.tx_count {
+            let Some(size) = transaction_size(parser.remaining()) else { break };
+            let mut transaction_parser = BufferParser::new(parser.extract_buffer(size)?);
+            self.transactions
+                .push(Transaction::parse_from_parser(&mut transaction_parser)?);
+        }
+        Ok(self.pending.len() - parser.len())
+    }
+}
+
+/// Lee un bloque de un reader de a partes, con el tamaño y el checksum de payload recibidos.
+/// Devuelve CustomError si no se puede leer del reader o el bloque no es valido.
+pub fn read_block<R: Read>(
+    reader: &mut R,
+    payload_size: usize,
+    checksum: [u8; 4],
+) -> Result<Block, CustomError> {
+    let mut parser = BlockStreamParser::new(payload_size, checksum);
+    let mut buffer = vec![0; READ_CHUNK_SIZE.min(payload_size)];
+    while !parser.is_complete() {
+        let size = match reader.read(&mut buffer) {
+            Ok(0) => return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid)),
+            Ok(size) => size,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error.into()),
+        };
+        parser.feed(&buffer[..size])?;
+    }
+    parser.finish()
+}
+
+/// Devuelve el tamaño de la transaccion serializada al inicio del buffer, o None si todavia no esta completa.
+/// Recorre la serializacion de la misma forma que Transaction::parse_from_parser, sin copiar sus campos.
+fn transaction_size(buffer: &[u8]) -> Option<usize> {
+    let mut parser = BufferParser::new(buffer);
+    parser.extract_u32().ok()?;
+    let mut tx_in_count = parser.extract_varint().ok()?;
+    let segwit = tx_in_count == 0;
+    if segwit {
+        parser.extract_u8().ok()?;
+        tx_in_count = parser.extract_varint().ok()?;
+    }
+    for _ in 0..tx_in_count {
+        parser.extract_buffer(36).ok()?;
+        skip_var_bytes(&mut parser)?;
+        parser.extract_u32().ok()?;
+    }
+    for _ in 0..parser.extract_varint().ok()? {
+        parser.extract_u64().ok()?;
+        skip_var_bytes(&mut parser)?;
+    }
+    if segwit {
+        for _ in 0..tx_in_count {
+            for _ in 0..parser.extract_varint().ok()? {
+                skip_var_bytes(&mut parser)?;
+            }
+        }
+    }
+    parser.extract_u32().ok()?;
+    Some(buffer.len() - parser.len())
+}
+
+/// Avanza el parser sobre un campo precedido por su largo en varint.
+fn skip_var_bytes(parser: &mut BufferParser) -> Option<()> {
+    let size = usize::try_from(parser.extract_varint().ok()?).ok()?;
+    parser.extract_buffer(size).ok()?;
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::message::Message;
+
+    use super::*;
+
+    /// Devuelve el payload del bloque de prueba, sin los bytes que el archivo tiene de sobra.
+    fn test_block() -> Vec<u8> {
+        let buffer = fs::read("tests/blocks/test_block.bin").unwrap();
+        Block::parse(&mut buffer.as_slice()).unwrap().serialize()
+    }
+
+    fn checksum(payload: &[u8]) -> [u8; 4] {
+        let hash = sha256d::Hash::hash(payload);
+        [hash[0], hash[1], hash[2], hash[3]]
+    }
+
+    #[test]
+    fn parses_a_block_fed_in_small_chunks() {
+        let buffer = test_block();
+        let expected = Block::parse(&mut buffer.as_slice()).unwrap();
+
+        let largest_transaction = expected
+            .transactions
+            .iter()
+            .map(|transaction| transaction.serialize().len())
+            .max()
+            .unwrap();
+
+        let mut parser = BlockStreamParser::new(buffer.len(), checksum(&buffer));
+        for chunk in buffer.chunks(7) {
+            assert!(!parser.is_complete());
+            assert_eq!(parser.feed(chunk).unwrap(), chunk.len());
+            // solo se conserva la transaccion que todavia no termino de llegar
+            assert!(parser.pending.len() < largest_transaction.max(BLOCK_HEADER_SIZE) + 7);
+        }
+        assert!(parser.is_complete());
+
+        let block = parser.finish().unwrap();
+        assert_eq!(block.serialize(), expected.serialize());
+        assert!(block.create_merkle_root().is_ok());
+    }
+
+    #[test]
+    fn does_not_consume_the_next_message() {
+        let mut buffer = test_block();
+        let payload_size = buffer.len();
+        let payload_checksum = checksum(&buffer);
+        buffer.extend([1, 2, 3]);
+
+        let mut parser = BlockStreamParser::new(payload_size, payload_checksum);
+        assert_eq!(parser.feed(&buffer).unwrap(), payload_size);
+        assert_eq!(parser.finish().unwrap().transactions.len(), 20);
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversized_payloads() {
+        let buffer = test_block();
+
+        let truncated = &buffer[..buffer.len() - 1];
+        let mut parser = BlockStreamParser::new(truncated.len(), checksum(truncated));
+        assert!(parser.feed(truncated).is_err());
+
+        let mut padded = buffer.clone();
+        padded.push(0);
+        let mut parser = BlockStreamParser::new(padded.len(), checksum(&padded));
+        assert!(parser.feed(&padded).is_err());
+
+        let mut parser = BlockStreamParser::new(buffer.len(), checksum(&buffer));
+        parser.feed(&buffer[..100]).unwrap();
+        assert!(!parser.is_complete());
+        assert!(parser.finish().is_err());
+    }
+
+    #[test]
+    fn reads_a_block_from_a_reader() {
+        let buffer = test_block();
+        let payload_checksum = checksum(&buffer);
+        let block = read_block(&mut buffer.as_slice(), buffer.len(), payload_checksum).unwrap();
+        assert_eq!(block.serialize(), buffer);
+
+        assert!(read_block(&mut &buffer[..200], buffer.len(), payload_checksum).is_err());
+    }
+
+    #[test]
+    fn rejects_blocks_with_invalid_checksum() {
+        let mut buffer = test_block();
+        let payload_checksum = checksum(&buffer);
+        // se modifica el lock time de la ultima transaccion, el bloque se sigue pudiendo parsear
+        let last = buffer.len() - 1;
+        buffer[last] ^= 1;
+
+        let mut parser = BlockStreamParser::new(buffer.len(), payload_checksum);
+        parser.feed(&buffer).unwrap();
+        assert!(parser.is_complete());
+        assert!(matches!(
+            parser.finish(),
+            Err(CustomError::Network(NetworkError::InvalidChecksum))
+        ));
+    }
+
+    #[test]
+    fn transaction_size_waits_for_the_whole_transaction() {
+        let buffer = test_block();
+        let block = Block::parse(&mut buffer.as_slice()).unwrap();
+        let transaction = block.transactions[1].serialize();
+
+        assert_eq!(transaction_size(&transaction), Some(transaction.len()));
+        assert_eq!(
+            transaction_size(&transaction[..transaction.len() - 1]),
+            None
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashSet,
+    fs::{read, read_dir, remove_file, write},
+    io::ErrorKind,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    download_checkpoint::DownloadCheckpoint,
+    error::{CustomError, ParseError, StorageError},
+    headers_store::{HeaderChain, HEADERS_READ_BATCH},
+    messages::block::Block,
+    structs::hash256::Hash256,
+};
+
+/// Cantidad minima de bloques recientes que se conservan al podar, para poder deshacer reorganizaciones.
+pub const MIN_BLOCKS_TO_KEEP: usize = 288;
+
+/// PruneMode indica que bloques se conservan en disco una vez procesados.
+/// - Disabled: Se conservan todos los bloques.
+/// - WalletOnly: Se conservan los bloques con transacciones de las wallets y los ultimos MIN_BLOCKS_TO_KEEP.
+/// - KeepLast: Se conservan los ultimos n bloques, al menos MIN_BLOCKS_TO_KEEP.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PruneMode {
+    Disabled,
+    WalletOnly,
+    KeepLast(usize),
+}
+
+impl FromStr for PruneMode {
+    type Err = CustomError;
+
+    /// Lee un modo de poda: false para no podar, wallet para conservar los bloques de las wallets,
+    /// o la cantidad de bloques recientes a conservar.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "false" | "disabled" | "none" => Ok(PruneMode::Disabled),
+            "wallet" | "wallet_only" => Ok(PruneMode::WalletOnly),
+            count => match usize::from_str(count) {
+                Ok(0) => Ok(PruneMode::Disabled),
+                Ok(count) => Ok(PruneMode::KeepLast(count.max(MIN_BLOCKS_TO_KEEP))),
+                Err(_) => Err(CustomError::Validation(format!(
+                    "Invalid prune mode {value}"
+                ))),
+            },
+        }
+    }
+}
+
+/// BlockStore guarda los bloques descargados en disco, un archivo por bloque, y los poda segun el PruneMode.
+/// La poda avanza en orden de altura: los bloques por debajo de prune_height ya fueron revisados y solo
+/// se conservan los que indica el modo, por lo que no se vuelven a descargar.
+/// Los elementos son:
+/// - store_path: Path de la carpeta store, los bloques se guardan en su directorio blocks.
+/// - prune_mode: Modo de poda de los bloques.
+/// - prune_height: Cantidad de headers de la cadena cuyos bloques ya se podaron, se guarda en disco.
+/// - downloads: DownloadCheckpoint con los bloques descargados, para no volver a pedirlos al reiniciar el nodo.
+pub struct BlockStore {
+    store_path: String,
+    prune_mode: PruneMode,
+    prune_height: usize,
+    downloads: DownloadCheckpoint,
+}
+
+impl BlockStore {
+    /// Abre el store de bloques, restaurando hasta donde se podaron y los bloques descargados.
+    /// Devuelve CustomError si el archivo de poda o el checkpoint de descargas no son validos.
+    pub fn open(store_path: String) -> Result<Self, CustomError> {
+        let path = format!("{}/prune_height.bin", store_path);
+        let prune_height = match Path::new(&path).exists() {
+            true => {
+                let bytes: [u8; 8] = read(path)?
+                    .try_into()
+                    .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+                u64::from_le_bytes(bytes) as usize
+            }
+            false => 0,
+        };
+
+        Ok(Self {
+            downloads: DownloadCheckpoint::open(&store_path)?,
+            store_path,
+            prune_mode: PruneMode::Disabled,
+            prune_height,
+        })
+    }
+
+    /// Actualiza el modo de poda de los bloques.
+    pub fn set_prune_mode(&mut self, prune_mode: PruneMode) {
+        self.prune_mode = prune_mode;
+    }
+
+    /// Devuelve la cantidad de headers de la cadena cuyos bloques ya se podaron.
+    pub fn prune_height(&self) -> usize {
+        self.prune_height
+    }
+
+    /// Guarda un bloque en su archivo y lo registra como descargado.
+    pub fn save(&mut self, block: &Block) -> Result<(), CustomError> {
+        block.save(self.block_path(&block.header.hash_as_string()))?;
+        self.downloads.record(*block.header.hash())
+    }
+
+    /// Devuelve true si el bloque ya se descargo y sigue guardado.
+    pub fn is_downloaded(&self, block_hash: &Hash256) -> bool {
+        self.downloads.is_downloaded(block_hash)
+    }
+
+    /// Guarda el checkpoint de los bloques descargados.
+    pub fn flush(&mut self) -> Result<(), CustomError> {
+        self.downloads.save()
+    }
+
+    /// Devuelve el bloque correspondiente al hash pasado por parametro.
+    /// Devuelve CustomError si el bloque no esta guardado, porque se podo o todavia no se descargo.
+    pub fn get(&self, block_string_hash: String) -> Result<Block, CustomError> {
+        let path = self.block_path(&block_string_hash);
+        if !Path::new(&path).exists() {
+            return Err(CustomError::Storage(StorageError::BlockNotStored));
+        }
+        Block::restore(path)
+    }
+
+    /// Devuelve la cantidad de bloques guardados.
+    pub fn count(&self) -> Result<usize, CustomError> {
+        Ok(read_dir(format!("{}/blocks", self.store_path))?.count())
+    }
+
+    /// Elimina del disco los bloques de la cadena que ya no se conservan segun el modo de poda,
+    /// y devuelve la cantidad de bloques eliminados.
+    /// Los bloques tienen que haber sido procesados por el UTXO, ya que no se pueden volver a leer.
+    /// wallet_blocks son los hashes de los bloques con transacciones de las wallets.
+    /// Los headers de los bloques a eliminar se leen por lotes de HEADERS_READ_BATCH.
+    pub fn prune(
+        &mut self,
+        headers: &impl HeaderChain,
+        wallet_blocks: &HashSet<Hash256>,
+    ) -> Result<usize, CustomError> {
+        let keep = match self.prune_mode {
+            PruneMode::Disabled => return Ok(0),
+            PruneMode::WalletOnly => MIN_BLOCKS_TO_KEEP,
+            PruneMode::KeepLast(count) => count.max(MIN_BLOCKS_TO_KEEP),
+        };
+        let prune_until = headers.len().saturating_sub(keep);
+        if prune_until <= self.prune_height {
+            return Ok(0);
+        }
+
+        let mut removed = 0;
+        let mut start = self.prune_height;
+        while start < prune_until {
+            let count = HEADERS_READ_BATCH.min(prune_until - start);
+            for header in headers.read_range(start, count)? {
+                if self.prune_mode == PruneMode::WalletOnly && wallet_blocks.contains(header.hash())
+                {
+                    continue;
+                }
+                self.downloads.forget(header.hash());
+                match remove_file(self.block_path(&header.hash_as_string())) {
+                    Ok(()) => removed += 1,
+                    Err(error) if error.kind() == ErrorKind::NotFound => {}
+                    Err(_) => return Err(CustomError::Storage(StorageError::CannotRemoveFile)),
+                }
+            }
+            start += count;
+        }
+        self.set_prune_height(prune_until)?;
+        Ok(removed)
+    }
+
+    /// Ajusta hasta donde se podo al descartar los headers a partir de la posicion len en una reorganizacion.
+    pub fn rollback(&mut self, len: usize) -> Result<(), CustomError> {
+        if len < self.prune_height {
+            self.set_prune_height(len)?;
+        }
+        Ok(())
+    }
+
+    fn set_prune_height(&mut self, prune_height: usize) -> Result<(), CustomError> {
+        write(
+            format!("{}/prune_height.bin", self.store_path),
+            (prune_height as u64).to_le_bytes(),
+        )?;
+        self.prune_height = prune_height;
+        Ok(())
+    }
+
+    fn block_path(&self, block_string_hash: &String) -> String {
+        format!("{}/blocks/{}.bin", self.store_path, block_string_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use crate::structs::block_header::{hash_as_string, BlockHeader};
+
+    use super::*;
+
+    fn test_block() -> Block {
+        BlockStore::open(String::from("tests"))
+            .unwrap()
+            .get(String::from("test_block"))
+            .unwrap()
+    }
+
+    /// Guarda copias del bloque de prueba con distintos hashes, como si fueran bloques consecutivos de la cadena.
+    fn store_chain(store: &mut BlockStore, count: u8) -> Vec<BlockHeader> {
+        (0..count)
+            .map(|i| {
+                let mut block = test_block();
+                block.header.hash = Hash256::new([i; 32]);
+                store.save(&block).unwrap();
+                block.header
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prune_mode_from_str() {
+        assert_eq!(PruneMode::from_str("false").unwrap(), PruneMode::Disabled);
+        assert_eq!(PruneMode::from_str("0").unwrap(), PruneMode::Disabled);
+        assert_eq!(
+            PruneMode::from_str("Wallet").unwrap(),
+            PruneMode::WalletOnly
+        );
+        assert_eq!(
+            PruneMode::from_str("1000").unwrap(),
+            PruneMode::KeepLast(1000)
+        );
+        assert_eq!(
+            PruneMode::from_str("10").unwrap(),
+            PruneMode::KeepLast(MIN_BLOCKS_TO_KEEP)
+        );
+        assert!(PruneMode::from_str("all").is_err());
+    }
+
+    #[test]
+    fn missing_block_is_not_stored() {
+        let store = BlockStore::open(String::from("tests")).unwrap();
+        assert!(matches!(
+            store.get(String::from("missing_block")),
+            Err(CustomError::Storage(StorageError::BlockNotStored))
+        ));
+    }
+
+    #[test]
+    fn prune_keeps_last_blocks() {
+        let store_path = String::from("tests/test_block_store_last");
+        create_dir_all(format!("{}/blocks", store_path)).unwrap();
+        let mut store = BlockStore::open(store_path.clone()).unwrap();
+        let mut headers = store_chain(&mut store, 10);
+
+        assert_eq!(store.prune(&headers, &HashSet::new()).unwrap(), 0);
+
+        // en la cadena hay MIN_BLOCKS_TO_KEEP bloques mas que no estan guardados
+        let last_header = headers[9].clone();
+        headers.extend(vec![last_header; MIN_BLOCKS_TO_KEEP - 6]);
+        store.set_prune_mode(PruneMode::KeepLast(MIN_BLOCKS_TO_KEEP));
+        assert_eq!(store.prune(&headers, &HashSet::new()).unwrap(), 4);
+        assert_eq!(store.count().unwrap(), 6);
+        assert!(store.get(hash_as_string(&Hash256::new([0; 32]))).is_err());
+        assert!(store.get(hash_as_string(&Hash256::new([4; 32]))).is_ok());
+
+        // la poda se restaura al reabrir el store
+        let restored = BlockStore::open(store_path.clone()).unwrap();
+        assert_eq!(restored.prune_height(), 4);
+
+        remove_dir_all(store_path).unwrap();
+    }
+
+    #[test]
+    fn prune_keeps_wallet_blocks() {
+        let store_path = String::from("tests/test_block_store_wallet");
+        create_dir_all(format!("{}/blocks", store_path)).unwrap();
+        let mut store = BlockStore::open(store_path.clone()).unwrap();
+        let mut headers = store_chain(&mut store, 10);
+        let last_header = headers[9].clone();
+        headers.extend(vec![last_header; MIN_BLOCKS_TO_KEEP]);
+
+        store.set_prune_mode(PruneMode::WalletOnly);
+        let wallet_blocks = HashSet::from([Hash256::new([3; 32]), Hash256::new([7; 32])]);
+        assert_eq!(store.prune(&headers, &wallet_blocks).unwrap(), 8);
+        assert!(store.get(hash_as_string(&Hash256::new([3; 32]))).is_ok());
+        assert!(store.get(hash_as_string(&Hash256::new([7; 32]))).is_ok());
+        assert!(store.get(hash_as_string(&Hash256::new([9; 32]))).is_err());
+
+        store.rollback(5).unwrap();
+        assert_eq!(store.prune_height(), 5);
+
+        remove_dir_all(store_path).unwrap();
+    }
+}
@@ -0,0 +1,651 @@
+use std::{fmt, ops::Add};
+
+use crate::{
+    error::{ChainError, CustomError},
+    network::Network,
+    structs::{
+        block_header::{target_from_bits, BlockHeader},
+        hash256::Hash256,
+    },
+    utils::get_current_timestamp,
+};
+
+/// Cantidad de bloques entre cada ajuste de dificultad.
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: usize = 2016;
+/// Tiempo esperado entre bloques, en segundos.
+const TARGET_SPACING: u32 = 10 * 60;
+/// Tiempo esperado para cada periodo de ajuste de dificultad (dos semanas), en segundos.
+const TARGET_TIMESPAN: u64 = 14 * 24 * 60 * 60;
+/// Cantidad de bloques anteriores con los que se calcula el median time past.
+const MEDIAN_TIME_SPAN: usize = 11;
+/// Tiempo maximo en segundos que un header puede estar adelantado respecto al reloj local.
+const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+/// Valida que los headers recibidos extiendan la cadena de headers del nodo.
+/// La cadena no incluye al bloque genesis, por lo que el header en la posicion i tiene altura i + 1.
+/// Para cada header verifica:
+/// - Que su prev_block_hash sea el hash del header anterior.
+/// - Que sus bits sean los de la dificultad requerida, con el ajuste cada 2016 bloques y la regla
+///   de dificultad minima de testnet y regtest.
+/// - Que su hash cumpla la proof of work de sus bits.
+/// - Que su timestamp sea mayor al median time past y no este a mas de dos horas en el futuro.
+/// - Que su version sea al menos la exigida por los soft forks activos a su height (BIP 34, 66 y 65).
+///
+/// Los headers hasta el ultimo checkpoint de la red solo validan que esten encadenados, su proof of work
+/// y que coincidan con los checkpoints, lo que acelera la sincronizacion inicial.
+pub fn validate_headers(chain: &[BlockHeader], headers: &[BlockHeader]) -> Result<(), CustomError> {
+    validate_headers_from(0, chain, headers)
+}
+
+/// Valida que los headers recibidos extiendan la cadena de headers del nodo, como validate_headers,
+/// recibiendo solo los ultimos headers de la cadena a partir de la posicion start.
+/// Alcanza con los ultimos DIFFICULTY_ADJUSTMENT_INTERVAL headers, o todos si la cadena es mas corta,
+/// para calcular la dificultad requerida y el median time past.
+pub fn validate_headers_from(
+    start: usize,
+    chain: &[BlockHeader],
+    headers: &[BlockHeader],
+) -> Result<(), CustomError> {
+    validate_headers_with_checkpoints(start, chain, headers, Network::current().checkpoints())
+}
+
+/// Devuelve la height del ultimo checkpoint de la red, o 0 si no tiene checkpoints.
+/// No se aceptan forks anteriores a esa height.
+pub fn last_checkpoint_height() -> usize {
+    Network::current()
+        .checkpoints()
+        .last()
+        .map(|(height, _)| *height)
+        .unwrap_or(0)
+}
+
+fn validate_headers_with_checkpoints(
+    start: usize,
+    chain: &[BlockHeader],
+    headers: &[BlockHeader],
+    checkpoints: &[(usize, &str)],
+) -> Result<(), CustomError> {
+    let now = get_current_timestamp()?;
+    let last_checkpoint = checkpoints.last().map(|(height, _)| *height).unwrap_or(0);
+    let mut prev_block_hash = chain
+        .last()
+        .map(|header| *header.hash())
+        .unwrap_or(Network::current().genesis_hash());
+
+    for (index, header) in headers.iter().enumerate() {
+        let view = ChainView {
+            start,
+            chain,
+            headers: &headers[..index],
+        };
+        let height = start + chain.len() + index + 1;
+
+        if header.prev_block_hash != prev_block_hash {
+            return Err(CustomError::Chain(ChainError::BlockChainBroken));
+        }
+        if let Some((_, checkpoint)) = checkpoints
+            .iter()
+            .find(|(checkpoint_height, _)| *checkpoint_height == height)
+        {
+            if header.hash().to_string() != *checkpoint {
+                return Err(CustomError::Chain(ChainError::HeaderCheckpointMismatch));
+            }
+        }
+        if height <= last_checkpoint {
+            if !check_proof_of_work(header.hash(), header.bits) {
+                return Err(CustomError::Chain(ChainError::HeaderInvalidPoW));
+            }
+            prev_block_hash = *header.hash();
+            continue;
+        }
+        if header.bits != next_work_required(&view, height, header.timestamp) {
+            return Err(CustomError::Chain(ChainError::HeaderInvalidDifficulty));
+        }
+        if !check_proof_of_work(header.hash(), header.bits) {
+            return Err(CustomError::Chain(ChainError::HeaderInvalidPoW));
+        }
+        if header.timestamp <= median_time_past(&view, height)
+            || header.timestamp as u64 > now + MAX_FUTURE_BLOCK_TIME
+        {
+            return Err(CustomError::Chain(ChainError::HeaderInvalidTimestamp));
+        }
+        if header.version < Network::current().min_block_version(height) {
+            return Err(CustomError::Chain(ChainError::HeaderInvalidVersion));
+        }
+
+        prev_block_hash = *header.hash();
+    }
+    Ok(())
+}
+
+/// Devuelve true si el hash cumple la proof of work de los bits recibidos.
+pub fn check_proof_of_work(hash: &Hash256, bits: u32) -> bool {
+    let target = U256::from_compact(bits);
+    if target == U256::ZERO || target > U256::from_compact(Network::current().pow_limit_bits()) {
+        return false;
+    }
+    U256::from_le_bytes(hash.as_bytes()) <= target
+}
+
+/// Calcula la dificultad de un nuevo periodo a partir del tiempo que tardo el periodo anterior.
+/// El tiempo se limita a entre un cuarto y cuatro veces el esperado.
+pub fn calculate_next_work(last_bits: u32, last_timestamp: u32, first_timestamp: u32) -> u32 {
+    let timespan = (last_timestamp as i64 - first_timestamp as i64)
+        .clamp((TARGET_TIMESPAN / 4) as i64, (TARGET_TIMESPAN * 4) as i64);
+
+    let target = U256::from_compact(last_bits)
+        .mul_u64(timespan as u64)
+        .div_u64(TARGET_TIMESPAN);
+    target
+        .min(U256::from_compact(Network::current().pow_limit_bits()))
+        .to_compact()
+}
+
+/// ChainWork es el trabajo (proof of work) acumulado por una cadena de headers, la cantidad esperada de hashes
+/// necesarios para generarla. La cadena valida es la de mas trabajo, no la mas larga.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChainWork(U256);
+
+impl ChainWork {
+    pub const ZERO: ChainWork = ChainWork(U256::ZERO);
+
+    /// Devuelve el trabajo acumulado por los headers recibidos.
+    pub fn of(headers: &[BlockHeader]) -> Self {
+        ChainWork(chain_work(headers))
+    }
+
+    /// Devuelve el trabajo como entero de 256 bits en big endian.
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0_u8; 32];
+        for (chunk, word) in bytes.chunks_mut(8).zip(self.0 .0) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Interpreta el trabajo de un entero de 256 bits en big endian.
+    pub fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        ChainWork(U256::from_be_bytes(bytes))
+    }
+}
+
+impl Add for ChainWork {
+    type Output = ChainWork;
+
+    /// Devuelve la suma del trabajo de ambas cadenas.
+    fn add(self, other: ChainWork) -> Self {
+        ChainWork(self.0.add(other.0))
+    }
+}
+
+impl fmt::Display for ChainWork {
+    /// Muestra el trabajo en hexadecimal con 64 digitos, como el chainwork de Bitcoin Core.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for word in self.0 .0 {
+            write!(f, "{:016x}", word)?;
+        }
+        Ok(())
+    }
+}
+
+/// Suma el trabajo de los headers, el trabajo de cada uno es 2^256 / (target + 1).
+fn chain_work(headers: &[BlockHeader]) -> U256 {
+    headers.iter().fold(U256::ZERO, |work, header| {
+        let target = U256::from_compact(header.bits);
+        if target == U256::ZERO {
+            return work;
+        }
+        // 2^256 no entra en 256 bits, se calcula como (2^256 - 1 - target) / (target + 1) + 1
+        let header_work = target
+            .not()
+            .div(target.add(U256::from_u64(1)))
+            .add(U256::from_u64(1));
+        work.add(header_work)
+    })
+}
+
+/// Vista de la cadena de headers del nodo seguida de los headers nuevos ya validados.
+/// De la cadena solo se tienen los headers a partir de la posicion start.
+struct ChainView<'a> {
+    start: usize,
+    chain: &'a [BlockHeader],
+    headers: &'a [BlockHeader],
+}
+
+impl ChainView<'_> {
+    /// Devuelve el timestamp y los bits del header de la altura recibida.
+    fn get(&self, height: usize) -> (u32, u32) {
+        let header = match height {
+            0 => {
+                let network = Network::current();
+                return (network.genesis_timestamp(), network.pow_limit_bits());
+            }
+            height if height <= self.start + self.chain.len() => {
+                &self.chain[height - self.start - 1]
+            }
+            height => &self.headers[height - self.start - self.chain.len() - 1],
+        };
+        (header.timestamp, header.bits)
+    }
+}
+
+/// Devuelve los bits que debe tener el header de la altura recibida.
+/// En testnet y regtest, si pasaron mas de 20 minutos desde el bloque anterior se permite la dificultad minima,
+/// y si no, se usa la del ultimo bloque del periodo que no tenga la dificultad minima.
+/// Regtest no ajusta la dificultad, por lo que se mantiene la del bloque anterior.
+fn next_work_required(view: &ChainView, height: usize, timestamp: u32) -> u32 {
+    let network = Network::current();
+    let pow_limit_bits = network.pow_limit_bits();
+    let (last_timestamp, last_bits) = view.get(height - 1);
+
+    if !network.retargets_difficulty() {
+        return last_bits;
+    }
+    if !height.is_multiple_of(DIFFICULTY_ADJUSTMENT_INTERVAL) {
+        if !network.allows_min_difficulty_blocks() {
+            return last_bits;
+        }
+        if timestamp > last_timestamp.saturating_add(TARGET_SPACING * 2) {
+            return pow_limit_bits;
+        }
+        let mut height = height - 1;
+        while height > 0
+            && !height.is_multiple_of(DIFFICULTY_ADJUSTMENT_INTERVAL)
+            && view.get(height).1 == pow_limit_bits
+        {
+            height -= 1;
+        }
+        return view.get(height).1;
+    }
+
+    let (first_timestamp, _) = view.get(height - DIFFICULTY_ADJUSTMENT_INTERVAL);
+    calculate_next_work(last_bits, last_timestamp, first_timestamp)
+}
+
+/// Devuelve la mediana de los timestamps de los MEDIAN_TIME_SPAN headers anteriores a la altura recibida.
+fn median_time_past(view: &ChainView, height: usize) -> u32 {
+    let mut timestamps: Vec<u32> = (height.saturating_sub(MEDIAN_TIME_SPAN)..height)
+        .map(|height| view.get(height).0)
+        .collect();
+    timestamps.sort_unstable();
+    timestamps[timestamps.len() / 2]
+}
+
+/// Entero sin signo de 256 bits, con las palabras de 64 bits de la mas significativa a la menos significativa.
+/// Solo implementa las operaciones necesarias para comparar hashes con targets y ajustar la dificultad.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct U256([u64; 4]);
+
+impl U256 {
+    const ZERO: U256 = U256([0; 4]);
+
+    fn from_u64(value: u64) -> Self {
+        U256([0, 0, 0, value])
+    }
+
+    /// Interpreta un hash en little endian, como se guarda en los headers.
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut words = [0_u64; 4];
+        for (index, chunk) in bytes.chunks(8).take(4).enumerate() {
+            let mut word = [0_u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            words[3 - index] = u64::from_le_bytes(word);
+        }
+        U256(words)
+    }
+
+    /// Interpreta un entero en big endian.
+    fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        let mut words = [0_u64; 4];
+        for (word, chunk) in words.iter_mut().zip(bytes.chunks(8)) {
+            *word = u64::from_be_bytes(chunk.try_into().unwrap_or_default());
+        }
+        U256(words)
+    }
+
+    /// Convierte los bits de un header a su target.
+    /// Los bits negativos o que no entran en 256 bits se convierten en cero, un target que ningun hash cumple.
+    fn from_compact(bits: u32) -> Self {
+        match target_from_bits(bits) {
+            Some(target) => U256::from_be_bytes(&target),
+            None => U256::ZERO,
+        }
+    }
+
+    /// Convierte el target al formato compacto de los bits de un header.
+    fn to_compact(self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut compact = if size <= 3 {
+            self.low_u64() << (8 * (3 - size))
+        } else {
+            self.shr(8 * (size - 3)).low_u64()
+        };
+        if compact & 0x00800000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+        compact as u32 | (size << 24)
+    }
+
+    fn low_u64(&self) -> u64 {
+        self.0[3]
+    }
+
+    /// Devuelve la cantidad de bits significativos.
+    fn bits(&self) -> u32 {
+        for (index, word) in self.0.iter().enumerate() {
+            if *word != 0 {
+                return (4 - index as u32) * 64 - word.leading_zeros();
+            }
+        }
+        0
+    }
+
+    fn shl(self, shift: u32) -> Self {
+        let words = (shift / 64) as usize;
+        let bits = shift % 64;
+        let mut result = [0_u64; 4];
+        for (index, word) in result.iter_mut().enumerate() {
+            let source = index + words;
+            if source < 4 {
+                *word |= self.0[source] << bits;
+                if bits > 0 && source + 1 < 4 {
+                    *word |= self.0[source + 1] >> (64 - bits);
+                }
+            }
+        }
+        U256(result)
+    }
+
+    fn shr(self, shift: u32) -> Self {
+        let words = (shift / 64) as usize;
+        let bits = shift % 64;
+        let mut result = [0_u64; 4];
+        for (index, word) in result.iter_mut().enumerate() {
+            if index >= words {
+                let source = index - words;
+                *word |= self.0[source] >> bits;
+                if bits > 0 && source > 0 {
+                    *word |= self.0[source - 1] << (64 - bits);
+                }
+            }
+        }
+        U256(result)
+    }
+
+    fn mul_u64(self, value: u64) -> Self {
+        let mut result = [0_u64; 4];
+        let mut carry = 0_u128;
+        for index in (0..4).rev() {
+            let product = self.0[index] as u128 * value as u128 + carry;
+            result[index] = product as u64;
+            carry = product >> 64;
+        }
+        U256(result)
+    }
+
+    fn not(self) -> Self {
+        U256(self.0.map(|word| !word))
+    }
+
+    fn add(self, other: Self) -> Self {
+        let mut result = [0_u64; 4];
+        let mut carry = false;
+        for index in (0..4).rev() {
+            let (sum, overflow_a) = self.0[index].overflowing_add(other.0[index]);
+            let (sum, overflow_b) = sum.overflowing_add(carry as u64);
+            result[index] = sum;
+            carry = overflow_a || overflow_b;
+        }
+        U256(result)
+    }
+
+    fn sub(self, other: Self) -> Self {
+        let mut result = [0_u64; 4];
+        let mut borrow = false;
+        for index in (0..4).rev() {
+            let (difference, overflow_a) = self.0[index].overflowing_sub(other.0[index]);
+            let (difference, overflow_b) = difference.overflowing_sub(borrow as u64);
+            result[index] = difference;
+            borrow = overflow_a || overflow_b;
+        }
+        U256(result)
+    }
+
+    /// Division entera bit a bit. El divisor no puede ser cero.
+    fn div(self, divisor: Self) -> Self {
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..self.bits()).rev() {
+            remainder = remainder.shl(1);
+            remainder.0[3] |= (self.0[3 - (bit / 64) as usize] >> (bit % 64)) & 1;
+            if remainder >= divisor {
+                remainder = remainder.sub(divisor);
+                quotient.0[3 - (bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+        quotient
+    }
+
+    fn div_u64(self, value: u64) -> Self {
+        let mut result = [0_u64; 4];
+        let mut remainder = 0_u128;
+        for (index, word) in self.0.iter().enumerate() {
+            let current = (remainder << 64) | *word as u128;
+            result[index] = (current / value as u128) as u64;
+            remainder = current % value as u128;
+        }
+        U256(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::headers_store::HeadersStore;
+
+    use super::*;
+
+    fn test_headers() -> Vec<BlockHeader> {
+        HeadersStore::open("tests/test_headers.bin".to_string())
+            .unwrap()
+            .read_range(0, 2)
+            .unwrap()
+    }
+
+    fn header(timestamp: u32, bits: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block_hash: Hash256::ZERO,
+            merkle_root: Hash256::ZERO,
+            timestamp,
+            bits,
+            nonce: 0,
+            hash: Hash256::ZERO,
+            broadcasted: false,
+            block_downloaded: false,
+        }
+    }
+
+    #[test]
+    fn compact_target_roundtrip() {
+        for bits in [0x1d00ffff, 0x1c05a3f4, 0x1b0404cb, 0x05009234] {
+            assert_eq!(U256::from_compact(bits).to_compact(), bits);
+        }
+        assert_eq!(
+            U256::from_compact(0x1d00ffff),
+            U256([0x00000000ffff0000, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn difficulty_retarget() {
+        assert_eq!(
+            calculate_next_work(0x1d00ffff, 1262152739, 1261130161),
+            0x1d00d86a
+        );
+        // el tiempo del periodo se limita a un cuarto y cuatro veces el esperado
+        assert_eq!(
+            calculate_next_work(0x1c05a3f4, 1279297671, 1279008237),
+            0x1c0168fd
+        );
+        assert_eq!(
+            calculate_next_work(0x1c387f6f, 1269211443, 1263163443),
+            0x1d00e1fd
+        );
+        assert_eq!(
+            calculate_next_work(0x1d00ffff, 1233061996, 1231006505),
+            0x1d00ffff
+        );
+    }
+
+    #[test]
+    fn chain_work_comparison() {
+        // el trabajo de un header con la dificultad minima es 0x100010001
+        assert_eq!(
+            chain_work(&[header(0, Network::Testnet.pow_limit_bits())]),
+            U256::from_u64(0x100010001)
+        );
+        assert_eq!(
+            U256::from_u64(1000).div(U256::from_u64(7)),
+            U256::from_u64(142)
+        );
+
+        let easy = vec![header(0, Network::Testnet.pow_limit_bits()); 3];
+        let hard = vec![header(0, 0x1c05a3f4)];
+        assert!(ChainWork::of(&hard) > ChainWork::of(&easy));
+        assert!(ChainWork::of(&easy) < ChainWork::of(&hard));
+        assert_eq!(ChainWork::of(&easy), ChainWork::of(&easy));
+
+        let work = ChainWork::of(&easy);
+        assert_eq!(work, ChainWork::of(&easy[..1]) + ChainWork::of(&easy[1..]));
+        assert_eq!(ChainWork::from_be_bytes(&work.to_be_bytes()), work);
+        assert_eq!(
+            work.to_string(),
+            "0000000000000000000000000000000000000000000000000000000300030003"
+        );
+    }
+
+    #[test]
+    fn testnet_minimum_difficulty() {
+        let chain = vec![header(1000, 0x1c05a3f4), header(1600, 0x1d00ffff)];
+        let view = ChainView {
+            start: 0,
+            chain: &chain,
+            headers: &[],
+        };
+        assert_eq!(next_work_required(&view, 3, 2000), 0x1c05a3f4);
+        assert_eq!(
+            next_work_required(&view, 3, 2801),
+            Network::Testnet.pow_limit_bits()
+        );
+    }
+
+    #[test]
+    fn validate_testnet_headers() {
+        let headers = test_headers();
+        assert!(validate_headers(&[], &headers).is_ok());
+        assert!(validate_headers(&headers[..1], &headers[1..]).is_ok());
+    }
+
+    #[test]
+    fn headers_not_extending_the_chain() {
+        let headers = test_headers();
+        assert!(matches!(
+            validate_headers(&[], &headers[1..]),
+            Err(CustomError::Chain(ChainError::BlockChainBroken))
+        ));
+        assert!(matches!(
+            validate_headers(&headers, &headers[1..]),
+            Err(CustomError::Chain(ChainError::BlockChainBroken))
+        ));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut headers = test_headers();
+        headers[1].bits = 0x1c05a3f4;
+        assert!(matches!(
+            validate_headers_with_checkpoints(0, &headers[..1], &headers[1..], &[]),
+            Err(CustomError::Chain(ChainError::HeaderInvalidDifficulty))
+        ));
+
+        let mut headers = test_headers();
+        headers[1].hash = Hash256::new([0xff; 32]);
+        assert!(matches!(
+            validate_headers(&headers[..1], &headers[1..]),
+            Err(CustomError::Chain(ChainError::HeaderInvalidPoW))
+        ));
+
+        let mut headers = test_headers();
+        headers[1].timestamp = Network::Testnet.genesis_timestamp();
+        assert!(matches!(
+            validate_headers_with_checkpoints(0, &headers[..1], &headers[1..], &[]),
+            Err(CustomError::Chain(ChainError::HeaderInvalidTimestamp))
+        ));
+
+        let mut headers = test_headers();
+        headers[1].timestamp =
+            (get_current_timestamp().unwrap() + MAX_FUTURE_BLOCK_TIME + 60) as u32;
+        assert!(matches!(
+            validate_headers_with_checkpoints(0, &headers[..1], &headers[1..], &[]),
+            Err(CustomError::Chain(ChainError::HeaderInvalidTimestamp))
+        ));
+    }
+
+    #[test]
+    fn headers_with_obsolete_version() {
+        let mut headers = test_headers();
+        // una cadena que termina justo antes de la activacion de BIP 34 en testnet
+        let chain = vec![headers[0].clone(); 21110];
+
+        headers[1].version = 1;
+        assert!(matches!(
+            validate_headers_with_checkpoints(0, &chain, &headers[1..2], &[]),
+            Err(CustomError::Chain(ChainError::HeaderInvalidVersion))
+        ));
+
+        headers[1].version = 2;
+        assert!(validate_headers_with_checkpoints(0, &chain, &headers[1..2], &[]).is_ok());
+    }
+
+    #[test]
+    fn validate_headers_from_the_last_headers_of_the_chain() {
+        let mut headers = test_headers();
+        let chain = vec![headers[0].clone(); 21110];
+        let start = chain.len() - DIFFICULTY_ADJUSTMENT_INTERVAL;
+
+        // la altura de los headers se calcula a partir de la posicion del primero recibido
+        headers[1].version = 1;
+        assert!(matches!(
+            validate_headers_with_checkpoints(start, &chain[start..], &headers[1..2], &[]),
+            Err(CustomError::Chain(ChainError::HeaderInvalidVersion))
+        ));
+
+        headers[1].version = 2;
+        assert!(
+            validate_headers_with_checkpoints(start, &chain[start..], &headers[1..2], &[]).is_ok()
+        );
+    }
+
+    #[test]
+    fn headers_below_checkpoint() {
+        let headers = test_headers();
+        let checkpoint = headers[1].hash().to_string();
+
+        // debajo del ultimo checkpoint no se validan la dificultad ni el timestamp
+        let mut modified = test_headers();
+        modified[1].timestamp = Network::Testnet.genesis_timestamp();
+        assert!(validate_headers_with_checkpoints(
+            0,
+            &modified[..1],
+            &modified[1..],
+            &[(2, checkpoint.as_str())]
+        )
+        .is_ok());
+
+        assert!(matches!(
+            validate_headers_with_checkpoints(0, &[], &headers, &[(1, checkpoint.as_str())]),
+            Err(CustomError::Chain(ChainError::HeaderCheckpointMismatch))
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::{self, Write};
+
+use crate::{
+    error::CustomError,
+    parser::{Parser, VarIntSerialize},
+    structs::hash256::Hash256,
+};
+
+/// Codec representa un campo de un mensaje del protocolo que se serializa siempre de la misma forma,
+/// sin importar el mensaje en el que aparece.
+/// Es lo que necesita impl_bitcoin_codec! para generar la serializacion y el parseo de un mensaje a partir de sus campos.
+pub trait Codec: Sized {
+    /// Escribe el campo serializado en el writer.
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    /// Extrae el campo del parser.
+    /// Devuelve CustomError si no quedan bytes suficientes o no son validos para el campo.
+    fn decode(parser: &mut impl Parser) -> Result<Self, CustomError>;
+}
+
+/// Implementa Codec para enteros, que se serializan en little endian.
+macro_rules! impl_integer_codec {
+    ($($integer:ty => $extract:ident),* $(,)?) => {
+        $(
+            impl Codec for $integer {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn decode(parser: &mut impl Parser) -> Result<Self, CustomError> {
+                    parser.$extract()
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_codec!(
+    u8 => extract_u8,
+    u16 => extract_u16,
+    u32 => extract_u32,
+    u64 => extract_u64,
+    i32 => extract_i32,
+    i64 => extract_i64,
+);
+
+/// Los booleanos se serializan en un byte, cualquier valor distinto de 0 es true.
+impl Codec for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&[*self as u8])
+    }
+
+    fn decode(parser: &mut impl Parser) -> Result<Self, CustomError> {
+        Ok(parser.extract_u8()? != 0)
+    }
+}
+
+impl Codec for Hash256 {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.as_bytes())
+    }
+
+    fn decode(parser: &mut impl Parser) -> Result<Self, CustomError> {
+        Ok(parser.extract_hash32()?.into())
+    }
+}
+
+/// Los buffers de largo variable se serializan precedidos por su largo como varint.
+impl Codec for Vec<u8> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().write_varint(writer)?;
+        writer.write_all(self)
+    }
+
+    fn decode(parser: &mut impl Parser) -> Result<Self, CustomError> {
+        let size = parser.extract_varint()? as usize;
+        parser.extract_bytes(size)
+    }
+}
+
+#[macro_export]
+/// Implementa el trait Message para un mensaje cuyo payload son sus campos serializados uno a continuacion del otro.
+/// Recibe el mensaje, su comando y sus campos en el orden en que se serializan, cuyos tipos deben implementar Codec.
+/// El parseo devuelve CustomError si sobran o faltan bytes para los campos, por lo que un mensaje sin campos
+/// solo acepta un payload vacio.
+/// Por ejemplo: impl_bitcoin_codec!(Ping, Command::Ping, nonce);
+macro_rules! impl_bitcoin_codec {
+    ($message:ident, $command:expr $(, $field:ident)* $(,)?) => {
+        impl $crate::message::Message for $message {
+            const COMMAND: $crate::message::Command = $command;
+
+            fn serialize_into<W: ::std::io::Write>(&self, _writer: &mut W) -> ::std::io::Result<()> {
+                $($crate::codec::Codec::encode(&self.$field, _writer)?;)*
+                Ok(())
+            }
+
+            fn parse(reader: &mut impl ::std::io::Read) -> Result<Self, $crate::error::CustomError> {
+                let mut _parser = $crate::parser::StreamParser::new(reader);
+                let message = $message {
+                    $($field: $crate::codec::Codec::decode(&mut _parser)?,)*
+                };
+                if !$crate::parser::Parser::is_empty(&mut _parser) {
+                    return Err($crate::error::CustomError::Parse(
+                        $crate::error::ParseError::SerializedBufferIsInvalid,
+                    ));
+                }
+                Ok(message)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::{Command, Message};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct TestMessage {
+        flag: bool,
+        height: u32,
+        hash: Hash256,
+        data: Vec<u8>,
+    }
+
+    crate::impl_bitcoin_codec!(TestMessage, Command::Ping, flag, height, hash, data);
+
+    fn test_message() -> TestMessage {
+        TestMessage {
+            flag: true,
+            height: 0x0102,
+            hash: Hash256::from([7; 32]),
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn fields_are_serialized_in_order() {
+        let buffer = test_message().serialize();
+        assert_eq!(buffer[..5], [1, 0x02, 0x01, 0, 0]);
+        assert_eq!(buffer[5..37], [7; 32]);
+        assert_eq!(buffer[37..], [3, 1, 2, 3]);
+        assert_eq!(
+            TestMessage::parse(&mut buffer.as_slice()).unwrap(),
+            test_message()
+        );
+    }
+
+    #[test]
+    fn payloads_with_missing_or_extra_bytes_are_rejected() {
+        let mut buffer = test_message().serialize();
+        buffer.push(0);
+        assert!(TestMessage::parse(&mut buffer.as_slice()).is_err());
+
+        buffer.truncate(buffer.len() - 2);
+        assert!(TestMessage::parse(&mut buffer.as_slice()).is_err());
+    }
+}
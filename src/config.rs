@@ -1,19 +1,53 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::str::FromStr;
+use std::vec::IntoIter;
 
-use crate::error::CustomError;
+use crate::block_store::PruneMode;
+use crate::discovery::SeedDiscovery;
+use crate::error::{CustomError, NetworkError, ParseError, StorageError};
+use crate::exchange_rate::{DEFAULT_EXCHANGE_RATE_INTERVAL, DEFAULT_EXCHANGE_RATE_URL};
+use crate::logger::{LogLevel, LoggerOptions};
+use crate::loops::tcp_listener_loop::DEFAULT_MAX_INBOUND;
+use crate::network::Network;
+use crate::peer::NODE_NETWORK;
+use crate::states::peer_score_state::DEFAULT_BAN_TIME;
+use crate::transaction_builder::{DEFAULT_MAX_FEE, DEFAULT_SPEND_MIN_CONFIRMATIONS};
 
 #[derive(Debug)]
 
 /// Config es una estructura que contiene los valores de configuracion del nodo.
-/// Estos valores se leen de un archivo de configuracion.
+/// Estos valores se leen de un archivo de configuracion y se pueden reemplazar con argumentos de la linea de comandos.
 /// Los valores son:
-/// - seed: semilla DNS para obtener direcciones IP.
+/// - seed: semilla DNS para obtener direcciones IP, por defecto las semillas de la red.
 /// - protocol_version: version del protocolo.
-/// - port: puerto en el que escucha el nodo.
+/// - port: puerto de los peers y en el que escucha el nodo, por defecto el de la red (0 si no se configuro).
+/// - log_file: archivo donde se guardan los logs.
+/// - npeers: cantidad de peers a los que se conecta el nodo.
+/// - client_only: si es true el nodo no acepta conexiones entrantes.
+/// - max_inbound: cantidad maxima de peers entrantes que acepta el nodo.
+/// - store_path: directorio donde se guardan los datos del nodo.
+/// - filters_start_height: altura desde la que se escanean filtros compactos (BIP 157), opcional.
+/// - log_options: nivel de los logs y rotacion del archivo de logs, opcionales.
+/// - network: red a la que se conecta el nodo, por defecto testnet.
+/// - peers: direcciones host:port de peers a los que conectarse antes que a los del seed, opcional.
+/// - rpc_port: puerto local del servidor JSON-RPC, si no se configura el servidor no se inicia.
+/// - rpc_user: usuario del servidor JSON-RPC, opcional. Si no se configura se usa el archivo cookie del directorio de datos.
+/// - rpc_password: password del servidor JSON-RPC, opcional y junto al usuario.
+/// - metrics_port: puerto local del servidor de metricas para Prometheus, si no se configura el servidor no se inicia.
+/// - headless: si es true el nodo corre sin interfaz grafica.
+/// - ban_time: tiempo en segundos que se banea a un peer que se comporta mal.
+/// - prune: modo de poda de los bloques guardados en disco, por defecto se conservan todos.
+/// - max_fee: fee total maximo en satoshis de las transacciones que crea la wallet.
+/// - spend_min_confirmations: confirmaciones minimas de las UTXO que se gastan al crear transacciones.
+/// - exchange_rate_url: url de la API del precio de bitcoin en monedas fiat, si se configura vacia no se muestra el precio.
+/// - exchange_rate_interval: tiempo en segundos entre cada actualizacion del precio.
+/// - desktop_notifications: si es true los pagos recibidos tambien se notifican en el escritorio.
+/// - proxy: direccion ip:port de un proxy SOCKS5 por el que se conecta a los peers, opcional.
+/// - dark_mode: si es true la interfaz usa la variante oscura del tema.
 pub struct Config {
     pub seed: String,
     pub protocol_version: i32,
@@ -21,23 +55,102 @@ pub struct Config {
     pub log_file: String,
     pub npeers: u8,
     pub client_only: bool,
+    pub max_inbound: usize,
     pub store_path: String,
+    pub filters_start_height: Option<usize>,
+    pub log_options: LoggerOptions,
+    pub network: Network,
+    pub peers: Vec<String>,
+    pub rpc_port: Option<u16>,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    pub metrics_port: Option<u16>,
+    pub headless: bool,
+    pub ban_time: u64,
+    pub prune: PruneMode,
+    pub max_fee: u64,
+    pub spend_min_confirmations: usize,
+    pub exchange_rate_url: Option<String>,
+    pub exchange_rate_interval: u64,
+    pub desktop_notifications: bool,
+    pub proxy: Option<SocketAddr>,
+    pub dark_mode: bool,
 }
 
 impl Config {
     /// Lee un archivo de configuracion y devuelve un Config con los valores leidos.
-    /// El archivo de configuracion debe tener el siguiente formato:
-    /// {NOMBRE}={VALOR}
-    /// Debe incluir todos los valores econtrados en la estructura Config.
+    /// El archivo de configuracion tiene formato TOML, sin tablas:
+    /// {nombre} = {valor}
+    /// Los strings pueden ir entre comillas, las listas entre corchetes y las lineas que comienzan con # son comentarios.
+    /// Los nombres no distinguen mayusculas, por lo que tambien se acepta el formato {NOMBRE}={VALOR}.
+    /// Debe incluir los valores requeridos: protocol_version, log y npeers.
+    /// En regtest, que no tiene semillas DNS, tambien debe incluir seed o peers.
     /// Devuelve CustomError si:
     /// - No se pudo encontrar el archivo.
     /// - El archivo tiene un formato invalido.
     /// - El archivo no contiene todos los valores requeridos.
     pub fn from_file(path: &str) -> Result<Self, CustomError> {
-        let file = File::open(path).map_err(|_| CustomError::ConfigMissingFile)?;
+        let file =
+            File::open(path).map_err(|_| CustomError::Storage(StorageError::ConfigMissingFile))?;
         Self::from_reader(file)
     }
 
+    /// Reemplaza los valores del config con los argumentos de la linea de comandos.
+    /// Cada argumento tiene el formato --{nombre} {valor} o --{nombre}={valor}, con el nombre de un valor del
+    /// archivo de configuracion en minusculas y separado con guiones (por ejemplo --data-dir o --log-level).
+    /// El argumento --peers se puede repetir para agregar varios peers.
+    /// Devuelve CustomError si algun argumento no tiene ese formato o su valor no es valido.
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), CustomError> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(arg) = arg.strip_prefix("--") else { return Err(CustomError::Parse(ParseError::ConfigInvalid)) };
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, value),
+                None => match args.next() {
+                    Some(value) => (arg, value.as_str()),
+                    None => return Err(CustomError::Parse(ParseError::ConfigInvalid)),
+                },
+            };
+            self.load_setting(&name.replace('-', "_"), value)?;
+        }
+        Self::check_required_values(self)
+    }
+
+    /// Devuelve el puerto configurado, o el puerto por defecto de la red si no se configuro.
+    pub fn get_port(&self) -> u16 {
+        match self.port {
+            0 => self.network.default_port(),
+            port => port,
+        }
+    }
+
+    /// Devuelve las direcciones a las que se puede conectar el nodo:
+    /// primero las de los peers configurados y luego las que resuelve el seed o, si no se configuro,
+    /// las que devuelve SeedDiscovery a partir de las semillas DNS de la red, con los peers fijos como respaldo.
+    /// Devuelve CustomError si no se pudo resolver ninguna direccion.
+    pub fn peer_addresses(&self) -> Result<IntoIter<SocketAddr>, CustomError> {
+        let mut addresses = vec![];
+        for peer in &self.peers {
+            let resolved = peer
+                .to_socket_addrs()
+                .map_err(|_| CustomError::Network(NetworkError::CannotResolveSeedAddress))?;
+            addresses.extend(resolved);
+        }
+
+        let discovery = match self.seed.is_empty() {
+            true => SeedDiscovery::new(self.network, self.get_port(), NODE_NETWORK),
+            false => SeedDiscovery::from_seed(&self.seed, self.get_port()),
+        };
+        if let Ok(discovered) = discovery.discover() {
+            addresses.extend(discovered);
+        }
+
+        if addresses.is_empty() {
+            return Err(CustomError::Network(NetworkError::CannotResolveSeedAddress));
+        }
+        Ok(addresses.into_iter())
+    }
+
     /// Crea un config a partir de cualquier implementacion del trait Read
     /// con el contenido en el formato mencionado en la documentacion de from_file.
     /// Devuelve CustomError si:
@@ -54,19 +167,37 @@ impl Config {
             log_file: String::new(),
             npeers: 0,
             client_only: false,
+            max_inbound: DEFAULT_MAX_INBOUND,
             store_path: String::from("store"),
+            filters_start_height: None,
+            log_options: LoggerOptions::default(),
+            network: Network::Testnet,
+            peers: vec![],
+            rpc_port: None,
+            rpc_user: None,
+            rpc_password: None,
+            metrics_port: None,
+            headless: false,
+            ban_time: DEFAULT_BAN_TIME,
+            prune: PruneMode::Disabled,
+            max_fee: DEFAULT_MAX_FEE,
+            spend_min_confirmations: DEFAULT_SPEND_MIN_CONFIRMATIONS,
+            exchange_rate_url: Some(String::from(DEFAULT_EXCHANGE_RATE_URL)),
+            exchange_rate_interval: DEFAULT_EXCHANGE_RATE_INTERVAL,
+            desktop_notifications: true,
+            proxy: None,
+            dark_mode: false,
         };
 
         for line in reader.lines() {
-            let current_line = line.map_err(|_| CustomError::ConfigInvalid)?;
-
-            let setting: Vec<&str> = current_line.split('=').collect();
-
-            // ['KEY', 'VALUE'].len() == 2
-            if setting.len() != 2 {
-                return Err(CustomError::ConfigInvalid);
+            let current_line = line.map_err(|_| CustomError::Parse(ParseError::ConfigInvalid))?;
+            let current_line = strip_comment(&current_line).trim();
+            if current_line.is_empty() {
+                continue;
             }
-            Self::load_setting(&mut config, setting[0], setting[1])?;
+
+            let Some((name, value)) = current_line.split_once('=') else { return Err(CustomError::Parse(ParseError::ConfigInvalid)) };
+            Self::load_setting(&mut config, name.trim(), value.trim())?;
         }
 
         Self::check_required_values(&config)?;
@@ -76,20 +207,20 @@ impl Config {
 
     /// Verifica que todos los valores requeridos esten cargados en el config.
     fn check_required_values(config: &Config) -> Result<(), CustomError> {
-        if config.seed.is_empty() {
-            return Err(CustomError::ConfigMissingValue);
+        if config.seed.is_empty()
+            && config.peers.is_empty()
+            && config.network.dns_seeds().is_empty()
+        {
+            return Err(CustomError::Parse(ParseError::ConfigMissingValue));
         }
         if config.protocol_version == 0 {
-            return Err(CustomError::ConfigMissingValue);
-        }
-        if config.port == 0 {
-            return Err(CustomError::ConfigMissingValue);
+            return Err(CustomError::Parse(ParseError::ConfigMissingValue));
         }
         if config.log_file.is_empty() {
-            return Err(CustomError::ConfigMissingValue);
+            return Err(CustomError::Parse(ParseError::ConfigMissingValue));
         }
         if config.npeers == 0 {
-            return Err(CustomError::ConfigMissingValue);
+            return Err(CustomError::Parse(ParseError::ConfigMissingValue));
         }
         Ok(())
     }
@@ -99,29 +230,279 @@ impl Config {
     /// - El "name" no es un nombre valido.
     /// - El "value" no se pudo convertir al tipo esperado.
     fn load_setting(&mut self, name: &str, value: &str) -> Result<(), CustomError> {
-        match name {
-            "SEED" => self.seed = String::from(value),
-            "PROTOCOL_VERSION" => {
-                self.protocol_version =
-                    i32::from_str(value).map_err(|_| CustomError::ConfigErrorReadingValue)?
-            }
-            "PORT" => {
-                self.port =
-                    u16::from_str(value).map_err(|_| CustomError::ConfigErrorReadingValue)?
-            }
-            "LOG" => self.log_file = String::from(value),
-            "NPEERS" => {
-                self.npeers =
-                    u8::from_str(value).map_err(|_| CustomError::ConfigErrorReadingValue)?
-            }
-            "STORE_PATH" => self.store_path = String::from(value),
-            "CLIENT_ONLY" => self.client_only = value == "true",
+        let name = name.to_lowercase();
+        if name == "peers" {
+            self.peers.extend(parse_list(value)?);
+            return Ok(());
+        }
+
+        let value = unquote(value);
+        match name.as_str() {
+            "seed" => self.seed = String::from(value),
+            "protocol_version" => {
+                self.protocol_version = i32::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "port" => {
+                self.port = u16::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "log" | "log_file" => self.log_file = String::from(value),
+            "log_level" => {
+                self.log_options.level = LogLevel::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "log_max_size" => {
+                self.log_options.max_file_size = u64::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "log_max_files" => {
+                self.log_options.max_files = usize::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "npeers" | "connections" => {
+                self.npeers = u8::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "store_path" | "data_dir" => self.store_path = String::from(value),
+            "client_only" => self.client_only = value == "true",
+            "listen" => self.client_only = value != "true",
+            "max_inbound" => {
+                self.max_inbound = usize::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "filters_start_height" => {
+                self.filters_start_height = Some(
+                    usize::from_str(value)
+                        .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?,
+                )
+            }
+            "rpc_port" => {
+                self.rpc_port = Some(
+                    u16::from_str(value)
+                        .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?,
+                )
+            }
+            "rpc_user" | "rpcuser" => self.rpc_user = Some(String::from(value)),
+            "rpc_password" | "rpcpassword" => self.rpc_password = Some(String::from(value)),
+            "metrics_port" => {
+                self.metrics_port = Some(
+                    u16::from_str(value)
+                        .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?,
+                )
+            }
+            "headless" => self.headless = value == "true",
+            "ban_time" => {
+                self.ban_time = u64::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "prune" => {
+                self.prune = PruneMode::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "max_fee" => {
+                self.max_fee = u64::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "spend_min_confirmations" => {
+                self.spend_min_confirmations = usize::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
+            "exchange_rate_url" => {
+                self.exchange_rate_url = match value.is_empty() {
+                    true => None,
+                    false => Some(String::from(value)),
+                }
+            }
+            "exchange_rate_interval" => {
+                self.exchange_rate_interval = match u64::from_str(value) {
+                    Ok(interval) if interval > 0 => interval,
+                    _ => return Err(CustomError::Parse(ParseError::ConfigErrorReadingValue)),
+                }
+            }
+            "desktop_notifications" => self.desktop_notifications = value == "true",
+            "dark_mode" => self.dark_mode = value == "true",
+            "proxy" => {
+                self.proxy = match value.is_empty() {
+                    true => None,
+                    false => Some(
+                        SocketAddr::from_str(value)
+                            .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?,
+                    ),
+                }
+            }
+            "network" => {
+                self.network = Network::from_str(value)
+                    .map_err(|_| CustomError::Parse(ParseError::ConfigErrorReadingValue))?
+            }
             _ => (),
         }
         Ok(())
     }
 }
 
+/// Settings son los valores del config que se pueden modificar desde la ventana de preferencias de la interfaz.
+/// Los campos son:
+/// - network: red a la que se conecta el nodo.
+/// - store_path: directorio donde se guardan los datos del nodo.
+/// - max_fee: fee total maximo en satoshis de las transacciones que crea la wallet.
+/// - spend_min_confirmations: confirmaciones minimas de las UTXO que se gastan al crear transacciones.
+/// - proxy: proxy SOCKS5 por el que se conecta a los peers, opcional.
+/// - npeers: cantidad de peers a los que se conecta el nodo.
+/// - dark_mode: si es true la interfaz usa la variante oscura del tema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub network: Network,
+    pub store_path: String,
+    pub max_fee: u64,
+    pub spend_min_confirmations: usize,
+    pub proxy: Option<SocketAddr>,
+    pub npeers: u8,
+    pub dark_mode: bool,
+}
+
+impl Settings {
+    /// Devuelve los valores del config que se pueden modificar.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            network: config.network,
+            store_path: config.store_path.clone(),
+            max_fee: config.max_fee,
+            spend_min_confirmations: config.spend_min_confirmations,
+            proxy: config.proxy,
+            npeers: config.npeers,
+            dark_mode: config.dark_mode,
+        }
+    }
+
+    /// Devuelve true si cambio algun valor que solo se aplica al reiniciar el nodo:
+    /// la red, el directorio de datos o la cantidad de peers.
+    pub fn requires_restart(&self, previous: &Settings) -> bool {
+        self.network != previous.network
+            || self.store_path != previous.store_path
+            || self.npeers != previous.npeers
+    }
+
+    /// Guarda los valores en el archivo de configuracion.
+    /// Reemplaza el valor de las lineas que ya los configuran, aunque usen otro nombre como DATA_DIR, y agrega
+    /// al final los que faltan con el formato de las demas lineas. El resto del archivo se conserva, salvo los
+    /// comentarios al final de las lineas reemplazadas.
+    /// El archivo nuevo se escribe en un archivo temporal que luego se renombra sobre el anterior.
+    /// Devuelve CustomError si no se puede leer o escribir el archivo.
+    pub fn save(&self, path: &str) -> Result<(), CustomError> {
+        let content = fs::read_to_string(path)
+            .map_err(|_| CustomError::Storage(StorageError::ConfigMissingFile))?;
+        let temp_path = format!("{}.tmp", path);
+        fs::write(&temp_path, self.update(&content))?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Devuelve el contenido de un archivo de configuracion con los valores reemplazados.
+    fn update(&self, content: &str) -> String {
+        let entries = self.entries();
+        let mut saved = vec![false; entries.len()];
+        let mut lines = vec![];
+        let mut uppercase = true;
+        for line in content.lines() {
+            let Some((name, value)) = strip_comment(line).split_once('=') else {
+                lines.push(line.to_string());
+                continue;
+            };
+            uppercase &= name.trim() == name.trim().to_uppercase();
+            let setting = name.trim().to_lowercase();
+            match entries
+                .iter()
+                .position(|(names, _)| names.contains(&setting.as_str()))
+            {
+                // se conserva el espaciado alrededor del =
+                Some(index) => {
+                    let spacing = &value[..value.len() - value.trim_start().len()];
+                    lines.push(format!("{}={}{}", name, spacing, entries[index].1));
+                    saved[index] = true;
+                }
+                None => lines.push(line.to_string()),
+            }
+        }
+
+        for ((names, value), saved) in entries.iter().zip(saved) {
+            // los valores opcionales sin configurar no se agregan
+            if saved || value.is_empty() {
+                continue;
+            }
+            lines.push(match uppercase {
+                true => format!("{}={}", names[0].to_uppercase(), value),
+                false => format!("{} = {}", names[0], value),
+            });
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Devuelve los nombres con los que se puede configurar cada valor, el primero es el que se usa al agregarlo,
+    /// y su valor en el formato del archivo.
+    fn entries(&self) -> Vec<(&'static [&'static str], String)> {
+        vec![
+            (&["network"], self.network.name().to_string()),
+            (&["data_dir", "store_path"], quote(&self.store_path)),
+            (&["max_fee"], self.max_fee.to_string()),
+            (
+                &["spend_min_confirmations"],
+                self.spend_min_confirmations.to_string(),
+            ),
+            (
+                &["proxy"],
+                self.proxy
+                    .map(|proxy| proxy.to_string())
+                    .unwrap_or_default(),
+            ),
+            (&["npeers", "connections"], self.npeers.to_string()),
+            (&["dark_mode"], self.dark_mode.to_string()),
+        ]
+    }
+}
+
+/// Agrega comillas a un string si contiene un # o espacios, que de otra forma se leerian como un comentario
+/// o se recortarian.
+fn quote(value: &str) -> String {
+    match value.contains('#') || value.trim() != value {
+        true => format!("\"{}\"", value),
+        false => value.to_string(),
+    }
+}
+
+/// Elimina el comentario de una linea, ignorando los # que estan dentro de un string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Quita las comillas de un string, si las tiene.
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Lee una lista de strings entre corchetes, o un unico valor si no tiene corchetes.
+fn parse_list(value: &str) -> Result<Vec<String>, CustomError> {
+    let Some(items) = value.strip_prefix('[') else { return Ok(vec![String::from(unquote(value))]) };
+    let Some(items) = items.strip_suffix(']') else { return Err(CustomError::Parse(ParseError::ConfigErrorReadingValue)) };
+    Ok(items
+        .split(',')
+        .map(|item| unquote(item.trim()))
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -131,7 +512,10 @@ mod tests {
         let content = "KEY".as_bytes();
         let config = Config::from_reader(content);
         assert!(config.is_err());
-        assert!(matches!(config, Err(CustomError::ConfigInvalid)));
+        assert!(matches!(
+            config,
+            Err(CustomError::Parse(ParseError::ConfigInvalid))
+        ));
     }
 
     #[test]
@@ -139,12 +523,16 @@ mod tests {
         let content = "SEED=seed.test\n".as_bytes();
         let config = Config::from_reader(content);
         assert!(config.is_err());
-        assert!(matches!(config, Err(CustomError::ConfigMissingValue)));
+        assert!(matches!(
+            config,
+            Err(CustomError::Parse(ParseError::ConfigMissingValue))
+        ));
     }
 
     #[test]
     fn config_con_valor_vacio() {
         let content = "SEED=\n\
+        NETWORK=regtest\n\
         PROTOCOL_VERSION=1234\n\
         LOG=log.txt\n\
         NPEERS=5\n\
@@ -154,7 +542,10 @@ mod tests {
             .as_bytes();
         let config = Config::from_reader(content);
         assert!(config.is_err());
-        assert!(matches!(config, Err(CustomError::ConfigMissingValue)));
+        assert!(matches!(
+            config,
+            Err(CustomError::Parse(ParseError::ConfigMissingValue))
+        ));
     }
 
     #[test]
@@ -190,7 +581,63 @@ mod tests {
         assert_eq!(4321, config.port);
         assert_eq!(false, config.client_only);
         assert_eq!("store", config.store_path);
+        assert_eq!(None, config.filters_start_height);
+        assert_eq!(DEFAULT_MAX_INBOUND, config.max_inbound);
+
+        Ok(())
+    }
 
+    #[test]
+    fn config_con_conexiones_entrantes() -> Result<(), CustomError> {
+        let content = "SEED=seed.test
+        PROTOCOL_VERSION=7000
+        LOG=log.txt
+        NPEERS=5
+        LISTEN=false
+        MAX_INBOUND=16"
+            .as_bytes();
+        let config = Config::from_reader(content)?;
+        assert!(config.client_only);
+        assert_eq!(16, config.max_inbound);
+
+        let content = "SEED=seed.test
+        PROTOCOL_VERSION=7000
+        LOG=log.txt
+        NPEERS=5
+        MAX_INBOUND=-1"
+            .as_bytes();
+        let config = Config::from_reader(content);
+        assert!(matches!(
+            config,
+            Err(CustomError::Parse(ParseError::ConfigErrorReadingValue))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_altura_de_filtros() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        PORT=4321\n\
+        FILTERS_START_HEIGHT=2400000"
+            .as_bytes();
+        let config = Config::from_reader(content)?;
+        assert_eq!(Some(2400000), config.filters_start_height);
+
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        PORT=4321\n\
+        FILTERS_START_HEIGHT=abc"
+            .as_bytes();
+        let config = Config::from_reader(content);
+        assert!(matches!(
+            config,
+            Err(CustomError::Parse(ParseError::ConfigErrorReadingValue))
+        ));
         Ok(())
     }
 
@@ -233,4 +680,364 @@ mod tests {
         assert_eq!("custom", config.store_path);
         Ok(())
     }
+
+    #[test]
+    fn config_con_opciones_de_logs() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        LOG_LEVEL=debug\n\
+        LOG_MAX_SIZE=1024\n\
+        LOG_MAX_FILES=3\n\
+        NPEERS=5\n\
+        PORT=4321"
+            .as_bytes();
+        let config = Config::from_reader(content)?;
+        assert_eq!(LogLevel::Debug, config.log_options.level);
+        assert_eq!(1024, config.log_options.max_file_size);
+        assert_eq!(3, config.log_options.max_files);
+
+        let content = "LOG_LEVEL=verbose".as_bytes();
+        let config = Config::from_reader(content);
+        assert!(matches!(
+            config,
+            Err(CustomError::Parse(ParseError::ConfigErrorReadingValue))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_formato_toml() -> Result<(), CustomError> {
+        let content = "# nodo de regtest\n\
+        network = \"regtest\"\n\
+        peers = [\"127.0.0.1:18444\", \"127.0.0.1:18445\"]\n\
+        \n\
+        protocol_version = 70015\n\
+        port = 18444 # puerto por defecto\n\
+        log = \"log#1.txt\"\n\
+        connections = 2\n\
+        data_dir = \"regtest-store\""
+            .as_bytes();
+        let config = Config::from_reader(content)?;
+        assert_eq!(Network::Regtest, config.network);
+        assert_eq!(vec!["127.0.0.1:18444", "127.0.0.1:18445"], config.peers);
+        assert_eq!("", config.seed);
+        assert_eq!(70015, config.protocol_version);
+        assert_eq!(18444, config.port);
+        assert_eq!("log#1.txt", config.log_file);
+        assert_eq!(2, config.npeers);
+        assert_eq!("regtest-store", config.store_path);
+
+        let content = "network = \"litecoin\"".as_bytes();
+        let config = Config::from_reader(content);
+        assert!(matches!(
+            config,
+            Err(CustomError::Parse(ParseError::ConfigErrorReadingValue))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_argumentos() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        PORT=4321"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        let args: Vec<String> = [
+            "--network",
+            "signet",
+            "--data-dir=signet-store",
+            "--npeers",
+            "8",
+            "--log-level",
+            "warn",
+            "--peers",
+            "127.0.0.1:38333",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        config.apply_args(&args)?;
+        assert_eq!(Network::Signet, config.network);
+        assert_eq!("signet-store", config.store_path);
+        assert_eq!(8, config.npeers);
+        assert_eq!(LogLevel::Warning, config.log_options.level);
+        assert_eq!(vec!["127.0.0.1:38333"], config.peers);
+
+        let args = vec![String::from("--npeers")];
+        assert!(matches!(
+            config.apply_args(&args),
+            Err(CustomError::Parse(ParseError::ConfigInvalid))
+        ));
+        let args = vec![String::from("npeers=3")];
+        assert!(matches!(
+            config.apply_args(&args),
+            Err(CustomError::Parse(ParseError::ConfigInvalid))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_valores_de_la_red() -> Result<(), CustomError> {
+        let content = "PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        NETWORK=mainnet"
+            .as_bytes();
+        let config = Config::from_reader(content)?;
+        assert_eq!("", config.seed);
+        assert_eq!(8333, config.get_port());
+
+        let content = "PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        NETWORK=regtest\n\
+        PEERS=127.0.0.1:18444\n\
+        PORT=18555"
+            .as_bytes();
+        let config = Config::from_reader(content)?;
+        assert_eq!(18555, config.get_port());
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_servidor_rpc() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(None, config.rpc_port);
+        assert!(!config.headless);
+
+        assert_eq!(None, config.rpc_user);
+
+        let args: Vec<String> = [
+            "--rpc-port",
+            "18332",
+            "--headless",
+            "true",
+            "--rpc-user",
+            "user",
+            "--rpc-password",
+            "secret",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        config.apply_args(&args)?;
+        assert_eq!(Some(18332), config.rpc_port);
+        assert!(config.headless);
+        assert_eq!(Some(String::from("user")), config.rpc_user);
+        assert_eq!(Some(String::from("secret")), config.rpc_password);
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_servidor_de_metricas() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        METRICS_PORT=9332"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(Some(9332), config.metrics_port);
+
+        let args: Vec<String> = ["--metrics-port", "9333"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        config.apply_args(&args)?;
+        assert_eq!(Some(9333), config.metrics_port);
+
+        let args: Vec<String> = ["--metrics-port", "abc"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert!(matches!(
+            config.apply_args(&args),
+            Err(CustomError::Parse(ParseError::ConfigErrorReadingValue))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_tiempo_de_baneo() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(DEFAULT_BAN_TIME, config.ban_time);
+
+        config.apply_args(&[String::from("--ban-time=3600")])?;
+        assert_eq!(3600, config.ban_time);
+        assert!(config.apply_args(&[String::from("--ban-time=-1")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn config_con_poda_de_bloques() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        PRUNE=wallet"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(PruneMode::WalletOnly, config.prune);
+
+        config.apply_args(&[String::from("--prune=1000")])?;
+        assert_eq!(PruneMode::KeepLast(1000), config.prune);
+        assert!(config.apply_args(&[String::from("--prune=all")]).is_err());
+        Ok(())
+    }
+    #[test]
+    fn config_con_fee_maximo() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(DEFAULT_MAX_FEE, config.max_fee);
+
+        config.apply_args(&[String::from("--max-fee=50000")])?;
+        assert_eq!(50000, config.max_fee);
+        assert!(config.apply_args(&[String::from("--max-fee=0.1")]).is_err());
+        Ok(())
+    }
+    #[test]
+    fn config_con_confirmaciones_minimas() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        SPEND_MIN_CONFIRMATIONS=6"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(6, config.spend_min_confirmations);
+
+        config.apply_args(&[String::from("--spend-min-confirmations=3")])?;
+        assert_eq!(3, config.spend_min_confirmations);
+        assert!(config
+            .apply_args(&[String::from("--spend-min-confirmations=-1")])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn config_con_precio_fiat() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        EXCHANGE_RATE_URL=\"\""
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(None, config.exchange_rate_url);
+        assert_eq!(
+            DEFAULT_EXCHANGE_RATE_INTERVAL,
+            config.exchange_rate_interval
+        );
+
+        config.apply_args(&[
+            String::from("--exchange-rate-url=http://localhost:8000/price"),
+            String::from("--exchange-rate-interval=60"),
+        ])?;
+        assert_eq!(
+            Some(String::from("http://localhost:8000/price")),
+            config.exchange_rate_url
+        );
+        assert_eq!(60, config.exchange_rate_interval);
+        assert!(config
+            .apply_args(&[String::from("--exchange-rate-interval=0")])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn config_con_notificaciones() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert!(config.desktop_notifications);
+        assert!(!config.dark_mode);
+
+        config.apply_args(&[
+            String::from("--desktop-notifications=false"),
+            String::from("--dark-mode=true"),
+        ])?;
+        assert!(!config.desktop_notifications);
+        assert!(config.dark_mode);
+        Ok(())
+    }
+    #[test]
+    fn config_con_proxy() -> Result<(), CustomError> {
+        let content = "SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS=5\n\
+        PROXY=127.0.0.1:9050"
+            .as_bytes();
+        let mut config = Config::from_reader(content)?;
+        assert_eq!(Some(SocketAddr::from(([127, 0, 0, 1], 9050))), config.proxy);
+
+        config.apply_args(&[String::from("--proxy=")])?;
+        assert_eq!(None, config.proxy);
+        assert!(config
+            .apply_args(&[String::from("--proxy=localhost")])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn settings_se_guardan_en_el_archivo() -> Result<(), CustomError> {
+        let content = "# nodo de pruebas\n\
+        SEED=seed.test\n\
+        PROTOCOL_VERSION=7000\n\
+        LOG=log.txt\n\
+        NPEERS = 5 # peers salientes\n\
+        DATA_DIR=store";
+        let config = Config::from_reader(content.as_bytes())?;
+        let mut settings = Settings::from_config(&config);
+        assert!(!settings.requires_restart(&Settings::from_config(&config)));
+
+        settings.network = Network::Regtest;
+        settings.store_path = String::from("regtest #1");
+        settings.max_fee = 50000;
+        settings.npeers = 8;
+        assert!(settings.requires_restart(&Settings::from_config(&config)));
+
+        let updated = settings.update(content);
+        assert_eq!(
+            updated,
+            "# nodo de pruebas\n\
+            SEED=seed.test\n\
+            PROTOCOL_VERSION=7000\n\
+            LOG=log.txt\n\
+            NPEERS = 8\n\
+            DATA_DIR=\"regtest #1\"\n\
+            NETWORK=regtest\n\
+            MAX_FEE=50000\n\
+            SPEND_MIN_CONFIRMATIONS=1\n\
+            DARK_MODE=false\n"
+        );
+        let config = Config::from_reader(updated.as_bytes())?;
+        assert_eq!(settings, Settings::from_config(&config));
+
+        settings.proxy = Some(SocketAddr::from(([127, 0, 0, 1], 9050)));
+        let updated = settings.update("seed = \"seed.test\"\nprotocol_version = 7000\nproxy = \n");
+        assert!(updated.starts_with(
+            "seed = \"seed.test\"\nprotocol_version = 7000\nproxy = 127.0.0.1:9050\n"
+        ));
+        assert!(updated.contains("\ndata_dir = \"regtest #1\"\n"));
+        Ok(())
+    }
 }
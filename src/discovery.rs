@@ -0,0 +1,255 @@
+use std::{collections::HashSet, net::SocketAddr};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+
+use crate::{
+    error::{CustomError, NetworkError},
+    network::Network,
+    utils::get_addresses,
+};
+
+/// SeedResolver resuelve el nombre de una semilla DNS en direcciones de peers.
+/// Se recibe como parametro del descubrimiento para poder probarlo sin acceso a la red.
+pub trait SeedResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, CustomError>;
+}
+
+/// DnsResolver resuelve las semillas con el resolver del sistema operativo.
+pub struct DnsResolver;
+
+impl SeedResolver for DnsResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, CustomError> {
+        Ok(get_addresses(host.to_string(), port)?.collect())
+    }
+}
+
+/// SeedDiscovery obtiene direcciones de peers consultando varias semillas DNS.
+/// Las semillas se consultan en orden aleatorio, para repartir la carga entre ellas en cada inicio del nodo,
+/// y las direcciones obtenidas se deduplican y se mezclan para no conectarse siempre a los mismos peers.
+/// Si ninguna semilla devuelve direcciones se recurre a la lista de peers fijos de la red.
+/// Los elementos son:
+/// - seeds: Nombres de las semillas DNS a consultar.
+/// - fixed_peers: Peers fijos con los servicios que ofrecen, usados si fallan todas las semillas.
+/// - port: Puerto de los peers que devuelven las semillas.
+/// - required_services: Servicios que deben ofrecer los peers, 0 si no se filtran.
+/// - resolver: SeedResolver con el que se resuelven las semillas.
+pub struct SeedDiscovery<R: SeedResolver> {
+    pub seeds: Vec<String>,
+    pub fixed_peers: Vec<(SocketAddr, u64)>,
+    pub port: u16,
+    pub required_services: u64,
+    pub resolver: R,
+}
+
+impl SeedDiscovery<DnsResolver> {
+    /// Crea el descubrimiento con las semillas DNS y los peers fijos de la red.
+    /// Los peers fijos que no tienen una direccion valida se ignoran.
+    pub fn new(network: Network, port: u16, required_services: u64) -> Self {
+        let fixed_peers = network
+            .fixed_seeds()
+            .iter()
+            .filter_map(|(address, services)| Some((address.parse().ok()?, *services)))
+            .collect();
+        Self {
+            seeds: network
+                .dns_seeds()
+                .iter()
+                .map(|seed| seed.to_string())
+                .collect(),
+            fixed_peers,
+            port,
+            required_services,
+            resolver: DnsResolver,
+        }
+    }
+
+    /// Crea el descubrimiento con una semilla configurada por el usuario, sin peers fijos ni filtro de servicios,
+    /// ya que la semilla puede no soportar los subdominios de filtrado.
+    pub fn from_seed(seed: &str, port: u16) -> Self {
+        Self {
+            seeds: vec![seed.to_string()],
+            fixed_peers: vec![],
+            port,
+            required_services: 0,
+            resolver: DnsResolver,
+        }
+    }
+}
+
+impl<R: SeedResolver> SeedDiscovery<R> {
+    /// Devuelve las direcciones de peers obtenidas de las semillas, sin repetidas y en orden aleatorio.
+    /// Si se requieren servicios, se consulta primero el subdominio de filtrado de cada semilla
+    /// (por ejemplo x9.seed.bitcoin.sipa.be) y, si no lo soporta, la semilla sin filtrar.
+    /// Las semillas que no se pueden resolver se ignoran, y si ninguna devuelve direcciones
+    /// se devuelven los peers fijos que ofrecen los servicios requeridos.
+    /// Devuelve CustomError si no se obtuvo ninguna direccion.
+    pub fn discover(&self) -> Result<Vec<SocketAddr>, CustomError> {
+        let mut seeds: Vec<&String> = self.seeds.iter().collect();
+        shuffle(&mut seeds);
+
+        let mut seen = HashSet::new();
+        let mut addresses = vec![];
+        for seed in seeds {
+            for address in self.resolve_seed(seed) {
+                if seen.insert(address) {
+                    addresses.push(address);
+                }
+            }
+        }
+
+        if addresses.is_empty() {
+            addresses = self
+                .fixed_peers
+                .iter()
+                .filter(|(_, services)| services & self.required_services == self.required_services)
+                .map(|(address, _)| *address)
+                .collect();
+        }
+        if addresses.is_empty() {
+            return Err(CustomError::Network(NetworkError::CannotResolveSeedAddress));
+        }
+        shuffle(&mut addresses);
+        Ok(addresses)
+    }
+
+    fn resolve_seed(&self, seed: &str) -> Vec<SocketAddr> {
+        if self.required_services != 0 {
+            let filtered = format!("x{:x}.{}", self.required_services, seed);
+            if let Ok(addresses) = self.resolver.resolve(&filtered, self.port) {
+                if !addresses.is_empty() {
+                    return addresses;
+                }
+            }
+        }
+        self.resolver.resolve(seed, self.port).unwrap_or_default()
+    }
+}
+
+/// Mezcla los elementos con el algoritmo de Fisher-Yates.
+pub fn shuffle<T>(items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        let j = (OsRng.next_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, net::Ipv4Addr};
+
+    use crate::peer::NODE_NETWORK;
+
+    use super::*;
+
+    /// Resolver de prueba, con las direcciones de cada nombre y los nombres consultados.
+    struct FakeResolver {
+        hosts: HashMap<String, Vec<SocketAddr>>,
+        queried: RefCell<Vec<String>>,
+    }
+
+    impl FakeResolver {
+        fn new(hosts: Vec<(&str, Vec<u8>)>) -> Self {
+            let hosts = hosts
+                .into_iter()
+                .map(|(host, ips)| (host.to_string(), ips.into_iter().map(address).collect()))
+                .collect();
+            Self {
+                hosts,
+                queried: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    impl SeedResolver for FakeResolver {
+        fn resolve(&self, host: &str, _port: u16) -> Result<Vec<SocketAddr>, CustomError> {
+            self.queried.borrow_mut().push(host.to_string());
+            self.hosts
+                .get(host)
+                .cloned()
+                .ok_or(CustomError::Network(NetworkError::CannotResolveSeedAddress))
+        }
+    }
+
+    fn address(host: u8) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::new(10, 0, 0, host), 18333))
+    }
+
+    fn discovery(
+        resolver: FakeResolver,
+        fixed_peers: Vec<(SocketAddr, u64)>,
+    ) -> SeedDiscovery<FakeResolver> {
+        SeedDiscovery {
+            seeds: vec![
+                "seed.a".to_string(),
+                "seed.b".to_string(),
+                "seed.c".to_string(),
+            ],
+            fixed_peers,
+            port: 18333,
+            required_services: NODE_NETWORK,
+            resolver,
+        }
+    }
+
+    #[test]
+    fn merges_and_deduplicates_the_addresses_of_every_seed() {
+        let resolver = FakeResolver::new(vec![
+            ("x1.seed.a", vec![1, 2]),
+            ("seed.b", vec![2, 3]),
+            ("x1.seed.c", vec![]),
+            ("seed.c", vec![4]),
+        ]);
+        let discovery = discovery(resolver, vec![(address(9), NODE_NETWORK)]);
+
+        let mut addresses = discovery.discover().unwrap();
+        addresses.sort();
+        assert_eq!(
+            addresses,
+            vec![address(1), address(2), address(3), address(4)]
+        );
+
+        // las semillas sin subdominio de filtrado se consultan sin filtrar
+        let queried = discovery.resolver.queried.borrow();
+        assert!(queried.contains(&"x1.seed.b".to_string()));
+        assert!(queried.contains(&"seed.b".to_string()));
+        assert!(!queried.contains(&"seed.a".to_string()));
+    }
+
+    #[test]
+    fn falls_back_to_fixed_peers_with_the_required_services() {
+        let resolver = FakeResolver::new(vec![]);
+        let fixed_peers = vec![
+            (address(1), 0x409),
+            (address(2), 0x408),
+            (address(3), NODE_NETWORK),
+        ];
+        let discovery = discovery(resolver, fixed_peers);
+
+        let mut addresses = discovery.discover().unwrap();
+        addresses.sort();
+        assert_eq!(addresses, vec![address(1), address(3)]);
+        assert_eq!(discovery.resolver.queried.borrow().len(), 6);
+    }
+
+    #[test]
+    fn fails_without_seeds_nor_fixed_peers() {
+        let resolver = FakeResolver::new(vec![]);
+        let discovery = discovery(resolver, vec![(address(1), 0x408)]);
+        assert!(matches!(
+            discovery.discover(),
+            Err(CustomError::Network(NetworkError::CannotResolveSeedAddress))
+        ));
+    }
+
+    #[test]
+    fn fixed_peers_of_the_networks_are_valid() {
+        for network in [Network::Mainnet, Network::Testnet] {
+            let discovery = SeedDiscovery::new(network, network.default_port(), NODE_NETWORK);
+            assert_eq!(discovery.fixed_peers.len(), network.fixed_seeds().len());
+            assert!(discovery
+                .fixed_peers
+                .iter()
+                .all(|(address, _)| address.port() == network.default_port()));
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashSet,
+    fs::{read_dir, File},
+    io::ErrorKind,
+    path::Path,
+};
+
+use crate::{
+    atomic_file::{read_atomic, write_atomic},
+    error::{CustomError, ParseError},
+    structs::{block_header::hash_as_string, hash256::Hash256},
+    utils::hex_to_bytes,
+};
+
+/// Cantidad de bloques descargados tras la cual se vuelve a guardar el checkpoint.
+const CHECKPOINT_INTERVAL: usize = 100;
+
+/// DownloadCheckpoint guarda en disco los hashes de los bloques ya descargados, para que al reiniciar el nodo
+/// en medio de la descarga inicial se retome donde quedo en lugar de volver a pedirlos.
+/// Un bloque se registra una vez guardado en su archivo. El checkpoint se guarda cada CHECKPOINT_INTERVAL bloques
+/// y al cerrar el nodo: primero fuerza la escritura en disco de los bloques registrados desde el anterior y luego
+/// reemplaza el archivo con write_atomic, por lo que nunca incluye un bloque cuyo archivo pueda estar incompleto.
+/// Si el archivo no existe, por ejemplo en un store de una version anterior, se inicializa con los bloques guardados.
+/// Los elementos son:
+/// - path: Path del archivo donde se guarda el checkpoint.
+/// - blocks_path: Path del directorio donde se guardan los bloques.
+/// - downloaded: Hashes de los bloques descargados.
+/// - unsaved: Hashes de los bloques descargados que todavia no se guardaron en el checkpoint.
+pub struct DownloadCheckpoint {
+    path: String,
+    blocks_path: String,
+    downloaded: HashSet<Hash256>,
+    unsaved: Vec<Hash256>,
+}
+
+impl DownloadCheckpoint {
+    /// Abre el checkpoint del store, o lo inicializa con los bloques guardados en su directorio blocks si no existe.
+    /// Devuelve CustomError si el archivo no es valido.
+    pub fn open(store_path: &str) -> Result<Self, CustomError> {
+        let path = format!("{}/downloaded_blocks.bin", store_path);
+        let blocks_path = format!("{}/blocks", store_path);
+        let buffer = read_atomic(&path)?;
+        if buffer.len() % 32 != 0 {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+
+        let mut downloaded = HashSet::new();
+        for chunk in buffer.chunks(32) {
+            downloaded.insert(Hash256::from_slice(chunk)?);
+        }
+        if !Path::new(&path).exists() {
+            downloaded = stored_blocks(&blocks_path)?;
+        }
+        Ok(Self {
+            path,
+            blocks_path,
+            downloaded,
+            unsaved: vec![],
+        })
+    }
+
+    /// Registra un bloque guardado en su archivo, y guarda el checkpoint si se registraron CHECKPOINT_INTERVAL
+    /// bloques desde el anterior.
+    pub fn record(&mut self, block_hash: Hash256) -> Result<(), CustomError> {
+        if !self.downloaded.insert(block_hash) {
+            return Ok(());
+        }
+        self.unsaved.push(block_hash);
+        if self.unsaved.len() >= CHECKPOINT_INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Quita un bloque que se elimino del disco, por ejemplo al podarlo.
+    pub fn forget(&mut self, block_hash: &Hash256) {
+        self.downloaded.remove(block_hash);
+    }
+
+    /// Devuelve true si el bloque ya se descargo.
+    pub fn is_downloaded(&self, block_hash: &Hash256) -> bool {
+        self.downloaded.contains(block_hash)
+    }
+
+    /// Fuerza la escritura en disco de los bloques registrados desde el ultimo guardado y guarda el checkpoint.
+    /// Los bloques cuyo archivo ya no existe se quitan del checkpoint.
+    pub fn save(&mut self) -> Result<(), CustomError> {
+        for block_hash in self.unsaved.drain(..) {
+            let path = format!("{}/{}.bin", self.blocks_path, hash_as_string(&block_hash));
+            match File::open(path) {
+                Ok(file) => file.sync_all()?,
+                Err(error) if error.kind() == ErrorKind::NotFound => {
+                    self.downloaded.remove(&block_hash);
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        let mut buffer = Vec::with_capacity(self.downloaded.len() * 32);
+        for block_hash in &self.downloaded {
+            buffer.extend(block_hash.as_bytes());
+        }
+        write_atomic(&self.path, &buffer)
+    }
+}
+
+/// Devuelve los hashes de los bloques guardados en el directorio, a partir del nombre de sus archivos.
+fn stored_blocks(blocks_path: &str) -> Result<HashSet<Hash256>, CustomError> {
+    let entries = match read_dir(blocks_path) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut blocks = HashSet::new();
+    for entry in entries {
+        let file_name = entry?.file_name();
+        let Some(name) = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".bin"))
+        else {
+            continue;
+        };
+        if let Some(hash) = hex_to_bytes(name).and_then(|bytes| Hash256::from_slice(&bytes).ok()) {
+            blocks.insert(hash);
+        }
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use super::*;
+
+    #[test]
+    fn checkpoint_is_restored() {
+        let store_path = "tests/test_download_checkpoint";
+        create_dir_all(format!("{}/blocks", store_path)).unwrap();
+        let saved = Hash256::new([1; 32]);
+        let lost = Hash256::new([2; 32]);
+        write(
+            format!("{}/blocks/{}.bin", store_path, hash_as_string(&saved)),
+            [0],
+        )
+        .unwrap();
+
+        // sin archivo se inicializa con los bloques guardados
+        let mut checkpoint = DownloadCheckpoint::open(store_path).unwrap();
+        assert!(checkpoint.is_downloaded(&saved));
+        assert!(!checkpoint.is_downloaded(&lost));
+
+        // un bloque registrado cuyo archivo no existe no se guarda en el checkpoint
+        checkpoint.record(lost).unwrap();
+        assert!(checkpoint.is_downloaded(&lost));
+        checkpoint.save().unwrap();
+        assert!(!checkpoint.is_downloaded(&lost));
+
+        let mut restored = DownloadCheckpoint::open(store_path).unwrap();
+        assert!(restored.is_downloaded(&saved));
+        assert!(!restored.is_downloaded(&lost));
+
+        restored.forget(&saved);
+        restored.save().unwrap();
+        assert!(!DownloadCheckpoint::open(store_path)
+            .unwrap()
+            .is_downloaded(&saved));
+
+        remove_dir_all(store_path).unwrap();
+    }
+}
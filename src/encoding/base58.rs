@@ -0,0 +1,84 @@
+use bitcoin_hashes::{sha256d, Hash};
+
+use super::EncodingError;
+
+/// Largo en bytes del checksum de base58check.
+const CHECKSUM_LENGTH: usize = 4;
+
+/// Codifica datos en base58 con el alfabeto de Bitcoin.
+pub fn encode(data: &[u8]) -> String {
+    bs58::encode(data).into_string()
+}
+
+/// Decodifica un texto en base58 con el alfabeto de Bitcoin.
+/// Devuelve EncodingError::InvalidCharacter si tiene un caracter fuera del alfabeto.
+pub fn decode(text: &str) -> Result<Vec<u8>, EncodingError> {
+    bs58::decode(text).into_vec().map_err(|error| match error {
+        bs58::decode::Error::InvalidCharacter { character, .. } => {
+            EncodingError::InvalidCharacter(character)
+        }
+        bs58::decode::Error::NonAsciiCharacter { index } => {
+            EncodingError::InvalidCharacter(text[index..].chars().next().unwrap_or_default())
+        }
+        _ => EncodingError::InvalidLength,
+    })
+}
+
+/// Codifica datos en base58 agregando los 4 bytes de checksum (base58check).
+pub fn encode_check(payload: &[u8]) -> String {
+    let mut data = payload.to_vec();
+    data.extend(&checksum(payload));
+    encode(&data)
+}
+
+/// Decodifica un texto en base58check y devuelve los datos sin el checksum.
+/// Devuelve EncodingError si tiene caracteres invalidos, es demasiado corto o el checksum no coincide.
+pub fn decode_check(text: &str) -> Result<Vec<u8>, EncodingError> {
+    let mut data = decode(text)?;
+    if data.len() < CHECKSUM_LENGTH {
+        return Err(EncodingError::InvalidLength);
+    }
+    let expected = data.split_off(data.len() - CHECKSUM_LENGTH);
+    if checksum(&data) != *expected {
+        return Err(EncodingError::InvalidChecksum);
+    }
+    Ok(data)
+}
+
+/// Los primeros 4 bytes del doble sha256 de los datos.
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let hash = sha256d::Hash::hash(payload).to_byte_array();
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_check() {
+        // direccion P2PKH de mainnet del hash 0x00...00
+        let mut payload = vec![0x00];
+        payload.extend([0; 20]);
+        let text = encode_check(&payload);
+        assert_eq!(text, "1111111111111111111114oLvT2");
+        assert_eq!(decode_check(&text).unwrap(), payload);
+    }
+
+    #[test]
+    fn decode_invalid_texts() {
+        let text = encode_check(&[0x6f, 1, 2, 3]);
+
+        let mut typo = text.clone();
+        let last = typo.pop().unwrap();
+        typo.push(if last == 'z' { 'y' } else { 'z' });
+        assert_eq!(decode_check(&typo), Err(EncodingError::InvalidChecksum));
+
+        assert_eq!(
+            decode_check(&format!("{}0", text)),
+            Err(EncodingError::InvalidCharacter('0'))
+        );
+        assert_eq!(decode_check("ñ"), Err(EncodingError::InvalidCharacter('ñ')));
+        assert_eq!(decode_check("11"), Err(EncodingError::InvalidLength));
+    }
+}
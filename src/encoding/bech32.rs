@@ -0,0 +1,267 @@
+use super::EncodingError;
+
+/// Alfabeto de 32 caracteres utilizado por bech32 (BIP 173).
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+/// Largo del checksum en caracteres.
+const CHECKSUM_LENGTH: usize = 6;
+/// Largo maximo de un texto bech32.
+const MAX_LENGTH: usize = 90;
+/// Version maxima de un witness program.
+const MAX_WITNESS_VERSION: u8 = 16;
+
+/// Variant es la variante del checksum de bech32.
+/// - Bech32: Checksum de BIP 173, usado por los witness programs de version 0.
+/// - Bech32m: Checksum de BIP 350, usado por los witness programs de version 1 en adelante, como taproot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+impl Variant {
+    /// Devuelve la variante que usan las direcciones de una version de witness.
+    pub fn for_witness_version(witness_version: u8) -> Self {
+        match witness_version {
+            0 => Self::Bech32,
+            _ => Self::Bech32m,
+        }
+    }
+
+    /// Constante con la que se combina el polymod del checksum.
+    fn constant(self) -> u32 {
+        match self {
+            Self::Bech32 => 1,
+            Self::Bech32m => 0x2bc830a3,
+        }
+    }
+}
+
+/// Codifica datos de 5 bits en bech32, con el hrp y la variante de checksum recibidos.
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> String {
+    let mut text = format!("{}1", hrp);
+    for value in data.iter().chain(&create_checksum(hrp, data, variant)) {
+        text.push(CHARSET[*value as usize] as char);
+    }
+    text
+}
+
+/// Decodifica un texto bech32 y devuelve su hrp en minusculas, los datos de 5 bits sin el checksum
+/// y la variante de checksum que usa.
+/// Devuelve EncodingError si el texto mezcla mayusculas y minusculas, tiene caracteres invalidos,
+/// no tiene separador o el checksum no coincide con ninguna variante.
+pub fn decode(text: &str) -> Result<(String, Vec<u8>, Variant), EncodingError> {
+    if text.len() > MAX_LENGTH {
+        return Err(EncodingError::InvalidLength);
+    }
+    if text.to_lowercase() != text && text.to_uppercase() != text {
+        return Err(EncodingError::MixedCase);
+    }
+    let text = text.to_lowercase();
+    let Some(separator) = text.rfind('1') else { return Err(EncodingError::MissingSeparator) };
+    let hrp = &text[..separator];
+    if hrp.is_empty() || text.len() < separator + 1 + CHECKSUM_LENGTH {
+        return Err(EncodingError::InvalidLength);
+    }
+
+    let mut values = vec![];
+    for character in text[separator + 1..].chars() {
+        let Some(value) = CHARSET.iter().position(|c| *c as char == character) else { return Err(EncodingError::InvalidCharacter(character)) };
+        values.push(value as u8);
+    }
+    let variant = match polymod(&[expand_hrp(hrp), values.clone()].concat()) {
+        checksum if checksum == Variant::Bech32.constant() => Variant::Bech32,
+        checksum if checksum == Variant::Bech32m.constant() => Variant::Bech32m,
+        _ => return Err(EncodingError::InvalidChecksum),
+    };
+    values.truncate(values.len() - CHECKSUM_LENGTH);
+    Ok((hrp.to_string(), values, variant))
+}
+
+/// Codifica una direccion SegWit a partir del hrp de la red, la version del witness y el witness program,
+/// en bech32 para la version 0 (BIP 173) y en bech32m para las demas (BIP 350).
+/// Devuelve EncodingError::InvalidWitnessProgram si la version o el largo del witness program no son validos.
+pub fn encode_segwit_address(
+    hrp: &str,
+    witness_version: u8,
+    program: &[u8],
+) -> Result<String, EncodingError> {
+    validate_program(witness_version, program)?;
+    let mut data = vec![witness_version];
+    data.extend(convert_bits(program, 8, 5, true)?);
+    Ok(encode(
+        hrp,
+        &data,
+        Variant::for_witness_version(witness_version),
+    ))
+}
+
+/// Decodifica una direccion SegWit de la red con el hrp recibido.
+/// Devuelve la version del witness y el witness program.
+/// Devuelve EncodingError si la direccion no es de esa red, no es un texto bech32 valido,
+/// su checksum no es el de la version del witness o el witness program no es valido.
+pub fn decode_segwit_address(hrp: &str, address: &str) -> Result<(u8, Vec<u8>), EncodingError> {
+    let (address_hrp, data, variant) = decode(address)?;
+    if address_hrp != hrp {
+        return Err(EncodingError::UnexpectedHrp(address_hrp));
+    }
+    let Some((witness_version, program)) = data.split_first() else { return Err(EncodingError::InvalidLength) };
+    if *witness_version > MAX_WITNESS_VERSION {
+        return Err(EncodingError::InvalidWitnessProgram);
+    }
+    if variant != Variant::for_witness_version(*witness_version) {
+        return Err(EncodingError::InvalidVariant);
+    }
+    let program = convert_bits(program, 5, 8, false)?;
+    validate_program(*witness_version, &program)?;
+    Ok((*witness_version, program))
+}
+
+/// Los witness programs tienen entre 2 y 40 bytes, y los de version 0 son de 20 bytes (P2WPKH) o 32 bytes (P2WSH).
+fn validate_program(witness_version: u8, program: &[u8]) -> Result<(), EncodingError> {
+    let valid = match witness_version {
+        0 => program.len() == 20 || program.len() == 32,
+        1..=MAX_WITNESS_VERSION => (2..=40).contains(&program.len()),
+        _ => false,
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(EncodingError::InvalidWitnessProgram),
+    }
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn expand_hrp(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|c| c & 31));
+    expanded
+}
+
+fn create_checksum(hrp: &str, data: &[u8], variant: Variant) -> Vec<u8> {
+    let mut values = expand_hrp(hrp);
+    values.extend(data);
+    values.extend([0; CHECKSUM_LENGTH]);
+    let checksum = polymod(&values) ^ variant.constant();
+    (0..CHECKSUM_LENGTH)
+        .map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8)
+        .collect()
+}
+
+/// Reagrupa los bits de data de grupos de from bits a grupos de to bits.
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, EncodingError> {
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+    let max_value = (1 << to) - 1;
+    let mut result = vec![];
+    for value in data {
+        accumulator = (accumulator << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((accumulator >> bits) & max_value) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((accumulator << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || (accumulator << (to - bits)) & max_value != 0 {
+        return Err(EncodingError::InvalidPadding);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY_HASH: [u8; 20] = [
+        0x75, 0x1e, 0x76, 0xe8, 0x19, 0x91, 0x96, 0xd4, 0x54, 0x94, 0x1c, 0x45, 0xd1, 0xb3, 0xa3,
+        0x23, 0xf1, 0x43, 0x3b, 0xd6,
+    ];
+
+    #[test]
+    fn encode_p2wpkh_address() {
+        assert_eq!(
+            encode_segwit_address("tb", 0, &PUBKEY_HASH).unwrap(),
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"
+        );
+        assert_eq!(
+            encode_segwit_address("bc", 0, &PUBKEY_HASH).unwrap(),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+    }
+
+    #[test]
+    fn decode_p2wpkh_address() {
+        let (version, program) =
+            decode_segwit_address("tb", "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(program, PUBKEY_HASH.to_vec());
+
+        let (_, program) =
+            decode_segwit_address("tb", "TB1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KXPJZSX").unwrap();
+        assert_eq!(program, PUBKEY_HASH.to_vec());
+    }
+
+    #[test]
+    fn encode_and_decode_bech32m_addresses() {
+        // vector de BIP 350: witness program de version 1 con dos veces el hash
+        let program = [PUBKEY_HASH, PUBKEY_HASH].concat();
+        let address = "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y";
+        assert_eq!(encode_segwit_address("bc", 1, &program).unwrap(), address);
+        assert_eq!(
+            decode_segwit_address("bc", address).unwrap(),
+            (1, program.clone())
+        );
+
+        // una direccion de version 1 con checksum bech32 no es valida
+        let mut data = vec![1];
+        data.extend(convert_bits(&program, 8, 5, true).unwrap());
+        let bech32_address = encode("bc", &data, Variant::Bech32);
+        assert_eq!(
+            decode_segwit_address("bc", &bech32_address),
+            Err(EncodingError::InvalidVariant)
+        );
+    }
+
+    #[test]
+    fn decode_invalid_addresses() {
+        // checksum invalido
+        assert_eq!(
+            decode_segwit_address("tb", "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsy"),
+            Err(EncodingError::InvalidChecksum)
+        );
+        // direccion de mainnet
+        assert_eq!(
+            decode_segwit_address("tb", "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(EncodingError::UnexpectedHrp(String::from("bc")))
+        );
+        // mayusculas y minusculas mezcladas
+        assert_eq!(
+            decode_segwit_address("tb", "tb1qW508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"),
+            Err(EncodingError::MixedCase)
+        );
+        // direccion P2PKH
+        assert!(decode_segwit_address("tb", "mscatccDgq7azndWHFTzvEuZuywCsUvTRu").is_err());
+        // version 0 con un witness program de largo invalido
+        assert_eq!(
+            encode_segwit_address("tb", 0, &[0; 21]),
+            Err(EncodingError::InvalidWitnessProgram)
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::error::{CustomError, ParseError};
+
+pub mod base58;
+pub mod bech32;
+
+/// EncodingError es el motivo por el que un texto no se puede decodificar en base58check o bech32.
+/// - InvalidCharacter: El texto tiene un caracter que no pertenece al alfabeto.
+/// - InvalidLength: El texto es demasiado corto o demasiado largo.
+/// - InvalidChecksum: El checksum no coincide con los datos, por ejemplo por un error de tipeo.
+/// - MixedCase: El texto mezcla mayusculas y minusculas, lo que bech32 no permite.
+/// - MissingSeparator: El texto bech32 no tiene el separador 1 entre el hrp y los datos.
+/// - UnexpectedHrp: El hrp del texto bech32 no es el esperado, recibe el encontrado.
+/// - InvalidPadding: Los datos bech32 tienen bits de relleno que no son cero.
+/// - InvalidWitnessProgram: La version del witness o el largo del witness program no son validos.
+/// - InvalidVariant: El checksum no es el de la version del witness, bech32 para la 0 y bech32m para las demas.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodingError {
+    InvalidCharacter(char),
+    InvalidLength,
+    InvalidChecksum,
+    MixedCase,
+    MissingSeparator,
+    UnexpectedHrp(String),
+    InvalidPadding,
+    InvalidWitnessProgram,
+    InvalidVariant,
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCharacter(character) => write!(f, "invalid character '{}'", character),
+            Self::InvalidLength => write!(f, "invalid length"),
+            Self::InvalidChecksum => write!(f, "the checksum does not match, it may have a typo"),
+            Self::MixedCase => write!(f, "mixes upper and lower case"),
+            Self::MissingSeparator => write!(f, "missing separator"),
+            Self::UnexpectedHrp(hrp) => write!(f, "unexpected prefix {}", hrp),
+            Self::InvalidPadding => write!(f, "invalid padding"),
+            Self::InvalidWitnessProgram => {
+                write!(f, "unsupported witness version or program length")
+            }
+            Self::InvalidVariant => write!(f, "wrong checksum variant for its witness version"),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+/// Los errores de codificacion se conservan como origen del ParseError, para informar su motivo.
+impl From<EncodingError> for CustomError {
+    fn from(error: EncodingError) -> Self {
+        CustomError::Parse(ParseError::Encoding(error))
+    }
+}
@@ -1,115 +1,300 @@
 use core::fmt;
 use std::{
     io::{Error, ErrorKind},
+    net::SocketAddrV6,
     sync::{
         mpsc::{RecvError, SendError},
-        PoisonError,
+        Arc, PoisonError,
     },
     time::SystemTimeError,
 };
 
+use crate::{encoding::EncodingError, message::Command};
+
 #[derive(Debug, Clone)]
 
 /// Custom error es un enum con los posibles errores que pueden ocurrir en el programa.
-/// Cada variante representa un error distinto.
+/// Los errores de cada area se agrupan en su propio enum, y los que ocurren en la conexion con un peer
+/// se envuelven en Peer para no perder que peer y que mensaje los causaron.
 /// Cada variante debe tener un metodo description que devuelve un string con la descripcion del error.
 pub enum CustomError {
-    TransactionNotFound,
-    ConfigInvalid,
-    ConfigMissingValue,
-    ConfigMissingFile,
-    ConfigErrorReadingValue,
-    CannotResolveSeedAddress,
-    CannotConnectToNode,
-    CannotHandshakeNode,
-    SerializedBufferIsInvalid,
-    BlockChainBroken,
-    CommandNotImplemented,
+    Network(NetworkError),
+    Parse(ParseError),
+    Wallet(WalletError),
+    Storage(StorageError),
+    Chain(ChainError),
+    Peer {
+        address: SocketAddrV6,
+        command: Option<Command>,
+        source: Box<CustomError>,
+    },
     Logging,
-    CannotReadMessageHeader,
-    CannotOpenFile,
     CannotSendMessageToChannel,
-    CloneFailed,
     CannotLockGuard,
     CannotReceiveMessageFromChannel,
-    CannotRemoveFile,
-    FileOperationInterrupted,
-    HeaderInvalidPoW,
-    InvalidMerkleRoot,
     UnknownError,
     CannotInitGUI,
     CannotGetTimestamp,
-    WalletNotFound,
     Validation(String),
-    CannotReadStream,
-    CannotSendToStream,
     MissingGUIElement,
-    InsufficientFunds,
     CannotStartEventLoop,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// NetworkError son los errores de la comunicacion con los peers y con otros servidores.
+pub enum NetworkError {
+    CannotResolveSeedAddress,
+    CannotConnectToNode,
+    CannotHandshakeNode,
+    CannotReadMessageHeader,
+    CannotReadStream,
+    CannotSendToStream,
+    CloneFailed,
+    PeerNotSynced,
+    PeerNotSupported,
+    PeerTimedOut,
+    InvalidChecksum,
+    CommandNotImplemented,
+    CannotStartRpcServer,
+    CannotStartMetricsServer,
+    CannotFetchExchangeRates,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// ParseError son los errores al interpretar datos serializados, archivos de configuracion o valores ingresados.
+pub enum ParseError {
+    SerializedBufferIsInvalid,
+    ConfigInvalid,
+    ConfigMissingValue,
+    ConfigErrorReadingValue,
     InvalidValue,
-    CannotSignTx,
+    InvalidPaymentUri,
+    PaymentUriUnsupportedParam,
+    InvalidPsbt,
+    Encoding(EncodingError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// WalletError son los errores de las operaciones con las wallets, sus transacciones y sus contactos.
+pub enum WalletError {
+    TransactionNotFound,
+    WalletNotFound,
+    InsufficientFunds,
     InvalidFee,
     InvalidTransferFields,
-    PeerNotSynced,
+    CannotSignTx,
+    WalletsLocked,
+    InvalidPassphrase,
+    CannotBumpFee,
+    WatchOnlyWallet,
+    ContactNotFound,
+    ContactAlreadyExists,
+    CannotGenerateQrCode,
+    IncompletePsbt,
+    HardwareWalletNotFound,
+    HardwareWallet(String),
+    DustOutput,
+    AbsurdFee,
+    AmountExceedsBalance,
+    CannotCpfp,
+}
+
+#[derive(Debug, Clone)]
+/// StorageError son los errores al leer o escribir los archivos del nodo.
+/// Io conserva el error de entrada/salida original, que se comparte con Arc porque no se puede clonar.
+pub enum StorageError {
+    ConfigMissingFile,
+    Io(Arc<Error>),
+    CannotRemoveFile,
+    CorruptedStore,
+    BlockNotStored,
+    CannotUndoBlock,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// ChainError son los errores de validacion de los headers y bloques de la cadena.
+pub enum ChainError {
+    BlockChainBroken,
+    HeaderInvalidPoW,
+    HeaderInvalidDifficulty,
+    HeaderInvalidTimestamp,
+    HeaderInvalidVersion,
+    HeaderCheckpointMismatch,
+    HeaderLowWorkChain,
+    InvalidMerkleRoot,
+    InvalidWitnessCommitment,
+    InvalidSignetSolution,
 }
 
 impl CustomError {
     /// Devuelve un string con la descripcion del error.
     pub fn description(&self) -> &str {
         match self {
-            Self::TransactionNotFound => "transaction not found",
-            Self::ConfigInvalid => "invalid config file",
-            Self::ConfigMissingValue => "missing config values",
-            Self::ConfigMissingFile => "missing config file",
-            Self::ConfigErrorReadingValue => "error reading config value",
-            Self::CannotResolveSeedAddress => "cannot resolve seed address",
-            Self::CannotConnectToNode => "cannot connect to node",
-            Self::CannotHandshakeNode => "cannot handshake with node",
-            Self::SerializedBufferIsInvalid => "serialized buffer is invalid",
-            Self::BlockChainBroken => "blockchain is broken",
-            Self::CommandNotImplemented => "command not implemented",
+            Self::Network(error) => error.description(),
+            Self::Parse(error) => error.description(),
+            Self::Wallet(error) => error.description(),
+            Self::Storage(error) => error.description(),
+            Self::Chain(error) => error.description(),
+            Self::Peer { source, .. } => source.description(),
             Self::Logging => "couldn't send log",
-            Self::CannotReadMessageHeader => "cannot read message header",
-            Self::CannotOpenFile => "cannot open file",
             Self::CannotSendMessageToChannel => "receiving end of a channel is disconected",
-            Self::CloneFailed => "couldn't clone endpoint",
             Self::CannotLockGuard => "another user of mutex panicked while holding the mutex,",
             Self::CannotReceiveMessageFromChannel => {
                 "cannot receive message from channel because sender has disconnected"
             }
-            Self::CannotRemoveFile => "cannot remove file",
-            Self::FileOperationInterrupted => "file operation interrupted",
-            Self::HeaderInvalidPoW => "header hash does not satisfy the proof of work dificulty",
-            Self::InvalidMerkleRoot => "invalid merkle root",
             Self::UnknownError => "unknown error",
             Self::CannotInitGUI => "cannot init GUI",
             Self::CannotGetTimestamp => "cannot get timestamp",
-            Self::WalletNotFound => "wallet not found",
             Self::Validation(_) => "validation error",
-            Self::CannotReadStream => "cannot read stream",
-            Self::CannotSendToStream => "cannot send message to stream",
             Self::MissingGUIElement => "missing GUI element",
-            Self::InsufficientFunds => "Insufficient funds to make transaction",
             Self::CannotStartEventLoop => "cannot start event loop",
+        }
+    }
+
+    /// Agrega al error el peer con el que ocurrio y el comando del mensaje que se estaba procesando.
+    /// Si el error ya tenia el peer, solo se completa el comando si faltaba.
+    pub fn with_peer(self, address: SocketAddrV6, command: Option<Command>) -> Self {
+        match self {
+            Self::Peer {
+                address,
+                command: previous,
+                source,
+            } => Self::Peer {
+                address,
+                command: previous.or(command),
+                source,
+            },
+            error => Self::Peer {
+                address,
+                command,
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// Devuelve el error original, sin el contexto del peer.
+    pub fn root(&self) -> &CustomError {
+        match self {
+            Self::Peer { source, .. } => source.root(),
+            error => error,
+        }
+    }
+}
+
+impl NetworkError {
+    /// Devuelve un string con la descripcion del error.
+    pub fn description(&self) -> &str {
+        match self {
+            Self::CannotResolveSeedAddress => "cannot resolve seed address",
+            Self::CannotConnectToNode => "cannot connect to node",
+            Self::CannotHandshakeNode => "cannot handshake with node",
+            Self::CannotReadMessageHeader => "cannot read message header",
+            Self::CannotReadStream => "cannot read stream",
+            Self::CannotSendToStream => "cannot send message to stream",
+            Self::CloneFailed => "couldn't clone endpoint",
+            Self::PeerNotSynced => "peer not synced",
+            Self::PeerNotSupported => "peer protocol version or services are not supported",
+            Self::PeerTimedOut => "peer did not answer the ping in time",
+            Self::InvalidChecksum => "message checksum does not match its payload",
+            Self::CommandNotImplemented => "command not implemented",
+            Self::CannotStartRpcServer => "cannot start RPC server",
+            Self::CannotStartMetricsServer => "cannot start metrics server",
+            Self::CannotFetchExchangeRates => "cannot fetch the exchange rates",
+        }
+    }
+}
+
+impl ParseError {
+    /// Devuelve un string con la descripcion del error.
+    pub fn description(&self) -> &str {
+        match self {
+            Self::SerializedBufferIsInvalid => "serialized buffer is invalid",
+            Self::ConfigInvalid => "invalid config file",
+            Self::ConfigMissingValue => "missing config values",
+            Self::ConfigErrorReadingValue => "error reading config value",
             Self::InvalidValue => "invalid value",
-            Self::CannotSignTx => "cannot sign transaction",
+            Self::InvalidPaymentUri => "invalid payment URI",
+            Self::PaymentUriUnsupportedParam => {
+                "payment URI requires a parameter that is not supported"
+            }
+            Self::InvalidPsbt => "invalid PSBT",
+            Self::Encoding(_) => "invalid encoding",
+        }
+    }
+}
+
+impl WalletError {
+    /// Devuelve un string con la descripcion del error.
+    pub fn description(&self) -> &str {
+        match self {
+            Self::TransactionNotFound => "transaction not found",
+            Self::WalletNotFound => "wallet not found",
+            Self::InsufficientFunds => "Insufficient funds to make transaction",
             Self::InvalidFee => "invalid fee",
             Self::InvalidTransferFields => "invalid transfer fields",
-            Self::PeerNotSynced => "peer not synced",
+            Self::CannotSignTx => "cannot sign transaction",
+            Self::WalletsLocked => "wallets are encrypted, a passphrase is required",
+            Self::InvalidPassphrase => "invalid passphrase",
+            Self::CannotBumpFee => "transaction cannot be replaced with a higher fee",
+            Self::WatchOnlyWallet => "wallet is watch-only, it cannot sign transactions",
+            Self::ContactNotFound => "contact not found",
+            Self::ContactAlreadyExists => "a contact with that label already exists",
+            Self::CannotGenerateQrCode => "payment URI is too long for a QR code",
+            Self::IncompletePsbt => "PSBT is not finalized, some inputs are missing signatures",
+            Self::HardwareWalletNotFound => "no Ledger or Trezor hardware wallet is connected",
+            Self::HardwareWallet(_) => "hardware wallet error",
+            Self::DustOutput => "output value is below the dust limit of 546 satoshis",
+            Self::AbsurdFee => "fee is above the maximum fee allowed (MAX_FEE)",
+            Self::AmountExceedsBalance => "amount to send exceeds the spendable balance",
+            Self::CannotCpfp => "transaction has no unspent outputs of the wallet to accelerate it",
+        }
+    }
+}
+
+impl StorageError {
+    /// Devuelve un string con la descripcion del error.
+    pub fn description(&self) -> &str {
+        match self {
+            Self::ConfigMissingFile => "missing config file",
+            Self::Io(error) => match error.kind() {
+                ErrorKind::NotFound
+                | ErrorKind::PermissionDenied
+                | ErrorKind::AlreadyExists
+                | ErrorKind::InvalidInput => "cannot open file",
+                ErrorKind::Interrupted => "file operation interrupted",
+                _ => "input/output error",
+            },
+            Self::CannotRemoveFile => "cannot remove file",
+            Self::CorruptedStore => "state file is corrupted and has no valid backup",
+            Self::BlockNotStored => "block is not stored, it was pruned or not downloaded yet",
+            Self::CannotUndoBlock => "cannot undo block, missing undo data",
+        }
+    }
+}
+
+impl ChainError {
+    /// Devuelve un string con la descripcion del error.
+    pub fn description(&self) -> &str {
+        match self {
+            Self::BlockChainBroken => "blockchain is broken",
+            Self::HeaderInvalidPoW => "header hash does not satisfy the proof of work dificulty",
+            Self::HeaderInvalidDifficulty => "header bits do not match the required difficulty",
+            Self::HeaderInvalidTimestamp => "header timestamp is out of the allowed range",
+            Self::HeaderInvalidVersion => "header version is obsolete for its height",
+            Self::HeaderCheckpointMismatch => "headers conflict with a checkpoint of the network",
+            Self::HeaderLowWorkChain => {
+                "headers belong to a chain with less work than the current one"
+            }
+            Self::InvalidMerkleRoot => "invalid merkle root",
+            Self::InvalidWitnessCommitment => "invalid witness commitment",
+            Self::InvalidSignetSolution => "block signature does not solve the signet challenge",
         }
     }
 }
 
 impl From<Error> for CustomError {
     fn from(error: Error) -> Self {
-        match error.kind() {
-            ErrorKind::NotFound => CustomError::CannotOpenFile,
-            ErrorKind::PermissionDenied => CustomError::CannotOpenFile,
-            ErrorKind::AlreadyExists => CustomError::CannotOpenFile,
-            ErrorKind::InvalidInput => CustomError::CannotOpenFile,
-            ErrorKind::Interrupted => CustomError::FileOperationInterrupted,
-            _ => CustomError::UnknownError,
-        }
+        CustomError::Storage(StorageError::Io(Arc::new(error)))
     }
 }
 impl<T> From<SendError<T>> for CustomError {
@@ -135,8 +320,212 @@ impl From<SystemTimeError> for CustomError {
     }
 }
 
+impl From<NetworkError> for CustomError {
+    fn from(error: NetworkError) -> Self {
+        CustomError::Network(error)
+    }
+}
+
+impl From<ParseError> for CustomError {
+    fn from(error: ParseError) -> Self {
+        CustomError::Parse(error)
+    }
+}
+
+impl From<WalletError> for CustomError {
+    fn from(error: WalletError) -> Self {
+        CustomError::Wallet(error)
+    }
+}
+
+impl From<StorageError> for CustomError {
+    fn from(error: StorageError) -> Self {
+        CustomError::Storage(error)
+    }
+}
+
+impl From<ChainError> for CustomError {
+    fn from(error: ChainError) -> Self {
+        CustomError::Chain(error)
+    }
+}
+
 impl fmt::Display for CustomError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Error: {}", self.description())
+        match self {
+            Self::Peer {
+                address,
+                command: Some(command),
+                source,
+            } => write!(
+                f,
+                "{} (peer {}, {} message)",
+                source,
+                address,
+                command.as_str()
+            ),
+            Self::Peer {
+                address, source, ..
+            } => write!(f, "{} (peer {})", source, address),
+            error => write!(f, "Error: {}", error.description()),
+        }
+    }
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Encoding(error) => write!(f, "{}: {}", self.description(), error),
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}: {}", self.description(), error),
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// El origen de un error con el contexto del peer es el error que lo causo, y el de un error de un area
+/// es el error de su enum. Solo los errores de almacenamiento de entrada/salida y los de codificacion
+/// tienen a su vez un origen.
+impl std::error::Error for CustomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Peer { source, .. } => Some(source.as_ref()),
+            Self::Network(error) => Some(error),
+            Self::Parse(error) => Some(error),
+            Self::Wallet(error) => Some(error),
+            Self::Storage(error) => Some(error),
+            Self::Chain(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Encoding(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl std::error::Error for WalletError {}
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+impl std::error::Error for ChainError {}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, io, net::Ipv6Addr};
+
+    use super::*;
+
+    fn peer_address() -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv6Addr::LOCALHOST, 18333, 0, 0)
+    }
+
+    #[test]
+    fn peer_context_is_kept_once() {
+        let error = CustomError::Network(NetworkError::InvalidChecksum)
+            .with_peer(peer_address(), Some(Command::Headers))
+            .with_peer(peer_address(), None);
+
+        assert_eq!(
+            error.to_string(),
+            "Error: message checksum does not match its payload (peer [::1]:18333, headers message)"
+        );
+        assert!(matches!(
+            error.root(),
+            CustomError::Network(NetworkError::InvalidChecksum)
+        ));
+        assert_eq!(
+            error.description(),
+            "message checksum does not match its payload"
+        );
+    }
+
+    #[test]
+    fn source_chains_to_the_area_error() {
+        let error = CustomError::from(ParseError::SerializedBufferIsInvalid)
+            .with_peer(peer_address(), Some(Command::Block));
+
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "Error: serialized buffer is invalid");
+        let area = source.source().unwrap();
+        assert_eq!(area.to_string(), "serialized buffer is invalid");
+        assert!(area.source().is_none());
+        assert!(CustomError::Logging.source().is_none());
+    }
+
+    #[test]
+    fn encoding_errors_keep_their_reason() {
+        let error = CustomError::from(EncodingError::InvalidChecksum);
+
+        assert!(matches!(
+            error,
+            CustomError::Parse(ParseError::Encoding(EncodingError::InvalidChecksum))
+        ));
+        assert_eq!(error.to_string(), "Error: invalid encoding");
+        let parse = error.source().unwrap();
+        assert_eq!(
+            parse.to_string(),
+            "invalid encoding: the checksum does not match, it may have a typo"
+        );
+        let encoding = parse
+            .source()
+            .unwrap()
+            .downcast_ref::<EncodingError>()
+            .unwrap();
+        assert_eq!(encoding, &EncodingError::InvalidChecksum);
+    }
+
+    #[test]
+    fn io_errors_keep_their_kind_and_message() {
+        let error = CustomError::from(io::Error::new(ErrorKind::NotFound, "headers.bin"));
+
+        assert_eq!(error.description(), "cannot open file");
+        assert_eq!(error.to_string(), "Error: cannot open file");
+        let storage = error.source().unwrap();
+        assert_eq!(storage.to_string(), "cannot open file: headers.bin");
+        let io = storage
+            .source()
+            .unwrap()
+            .downcast_ref::<io::Error>()
+            .unwrap();
+        assert_eq!(io.kind(), ErrorKind::NotFound);
+
+        let error = CustomError::from(io::Error::from(ErrorKind::UnexpectedEof));
+        assert_eq!(error.description(), "input/output error");
     }
 }
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use ureq::Agent;
+
+use crate::{
+    error::{CustomError, NetworkError},
+    json::JsonValue,
+};
+
+/// Url por defecto del precio de bitcoin en dolares y euros, de la API publica de CoinGecko.
+pub const DEFAULT_EXCHANGE_RATE_URL: &str =
+    "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies=usd,eur";
+/// Tiempo en segundos entre cada actualizacion del precio por defecto.
+pub const DEFAULT_EXCHANGE_RATE_INTERVAL: u64 = 300;
+/// Tiempo maximo en segundos que se espera la respuesta de la API de precios.
+const REQUEST_TIMEOUT: u64 = 10;
+/// Cantidad de satoshis en un bitcoin.
+const SATOSHIS_PER_BITCOIN: f64 = 100_000_000.0;
+
+/// ExchangeRates es el precio de un bitcoin en monedas fiat.
+/// Los elementos son:
+/// - usd: Precio en dolares.
+/// - eur: Precio en euros.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExchangeRates {
+    pub usd: f64,
+    pub eur: f64,
+}
+
+impl ExchangeRates {
+    /// Devuelve el valor aproximado de un monto en satoshis, en dolares y en euros.
+    pub fn fiat_value(&self, satoshis: i64) -> (f64, f64) {
+        let btc = satoshis as f64 / SATOSHIS_PER_BITCOIN;
+        (btc * self.usd, btc * self.eur)
+    }
+
+    /// Devuelve el valor aproximado de un monto en satoshis como texto, por ejemplo "≈ 12.34 USD / 11.50 EUR".
+    pub fn format(&self, satoshis: i64) -> String {
+        let (usd, eur) = self.fiat_value(satoshis);
+        format!("≈ {:.2} USD / {:.2} EUR", usd, eur)
+    }
+}
+
+/// RateProvider obtiene el precio de bitcoin en monedas fiat.
+/// Permite cambiar la fuente del precio, por ejemplo otra API o un precio fijo, sin modificar a quien lo muestra.
+pub trait RateProvider: Send {
+    /// Devuelve el nombre con el que se muestra el provider en los logs.
+    fn name(&self) -> String;
+
+    /// Devuelve el precio actual de bitcoin.
+    fn get_rates(&self) -> Result<ExchangeRates, CustomError>;
+}
+
+/// HttpRateProvider obtiene el precio de una API HTTP que responde con el formato de CoinGecko:
+/// {"bitcoin":{"usd":<precio>,"eur":<precio>}}
+/// Los elementos son:
+/// - url: Url de la API.
+/// - agent: Cliente HTTP con el que se hacen los pedidos.
+pub struct HttpRateProvider {
+    url: String,
+    agent: Agent,
+}
+
+impl HttpRateProvider {
+    /// Crea un provider que pide el precio a la url recibida.
+    pub fn new(url: String) -> Self {
+        let agent = Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(REQUEST_TIMEOUT)))
+            .build()
+            .into();
+        Self { url, agent }
+    }
+}
+
+impl RateProvider for HttpRateProvider {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn get_rates(&self) -> Result<ExchangeRates, CustomError> {
+        let body = self
+            .agent
+            .get(&self.url)
+            .call()
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|_| CustomError::Network(NetworkError::CannotFetchExchangeRates))?;
+        parse_rates(&body)
+    }
+}
+
+/// Lee el precio de bitcoin de la respuesta de la API.
+/// Devuelve CustomError si la respuesta no es un JSON valido o no tiene un precio positivo en cada moneda.
+fn parse_rates(body: &str) -> Result<ExchangeRates, CustomError> {
+    let value = JsonValue::parse(body)
+        .map_err(|_| CustomError::Network(NetworkError::CannotFetchExchangeRates))?;
+    let bitcoin = value.get("bitcoin");
+    let rate = |currency: &str| {
+        bitcoin
+            .and_then(|bitcoin| bitcoin.get(currency))
+            .and_then(JsonValue::as_f64)
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .ok_or(CustomError::Network(NetworkError::CannotFetchExchangeRates))
+    };
+    Ok(ExchangeRates {
+        usd: rate("usd")?,
+        eur: rate("eur")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_coingecko_response() {
+        let rates = parse_rates(r#"{"bitcoin":{"usd":65000.5,"eur":60000}}"#).unwrap();
+        assert_eq!(
+            rates,
+            ExchangeRates {
+                usd: 65000.5,
+                eur: 60000.0
+            }
+        );
+
+        assert!(parse_rates(r#"{"bitcoin":{"usd":65000}}"#).is_err());
+        assert!(parse_rates(r#"{"bitcoin":{"usd":-1,"eur":60000}}"#).is_err());
+        assert!(parse_rates(r#"{"error":"rate limited"}"#).is_err());
+        assert!(parse_rates("<html></html>").is_err());
+    }
+
+    #[test]
+    fn fiat_value_of_satoshis() {
+        let rates = ExchangeRates {
+            usd: 60000.0,
+            eur: 50000.0,
+        };
+        assert_eq!(rates.fiat_value(50_000), (30.0, 25.0));
+        assert_eq!(rates.format(150_000_000), "≈ 90000.00 USD / 75000.00 EUR");
+        assert_eq!(rates.format(-1000), "≈ -0.60 USD / -0.50 EUR");
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+
+use crate::{messages::block::Block, states::utxo_state::UTXO};
+
+/// Cantidad de bloques recientes que se consideran para estimar el fee.
+const RECENT_BLOCKS: usize = 6;
+/// Fee minimo en satoshis por byte virtual que retransmiten los nodos.
+pub const MIN_FEE_RATE: u64 = 1;
+
+/// Prioridad con la que se quiere que una transaccion sea incluida en un bloque.
+/// Cada prioridad corresponde a un percentil de los fees pagados en los bloques recientes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeePriority {
+    Low,
+    Medium,
+    High,
+}
+
+impl FeePriority {
+    fn percentile(&self) -> usize {
+        match self {
+            FeePriority::Low => 25,
+            FeePriority::Medium => 50,
+            FeePriority::High => 90,
+        }
+    }
+}
+
+/// FeeEstimator estima el fee de una transaccion a partir de los fees pagados por las transacciones
+/// de los ultimos bloques descargados.
+/// El fee de una transaccion solo se conoce si todos los outputs que gasta estan en el UTXO,
+/// por lo que las transacciones que gastan outputs desconocidos no se consideran.
+/// Los elementos son:
+/// - blocks: Fees en satoshis por byte virtual de las transacciones de cada bloque reciente.
+pub struct FeeEstimator {
+    blocks: VecDeque<Vec<u64>>,
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeeEstimator {
+    /// Inicializa el estimador sin bloques, estimando MIN_FEE_RATE para todas las prioridades.
+    pub fn new() -> Self {
+        Self {
+            blocks: VecDeque::new(),
+        }
+    }
+
+    /// Registra los fees pagados por las transacciones de un bloque.
+    /// Debe llamarse antes de actualizar el UTXO con el bloque, ya que se buscan en el los outputs gastados.
+    pub fn record_block(&mut self, block: &Block, utxo: &UTXO) {
+        let mut fee_rates = vec![];
+        // el coinbase no paga fee
+        'transactions: for tx in block.transactions.iter().skip(1) {
+            let mut inputs_value = 0;
+            for input in &tx.inputs {
+                let Some(value) = utxo.tx_set.get(&input.previous_output) else { continue 'transactions };
+                inputs_value += value.tx_out.value;
+            }
+            let outputs_value: u64 = tx.outputs.iter().map(|output| output.value).sum();
+            if inputs_value < outputs_value {
+                continue;
+            }
+            fee_rates.push((inputs_value - outputs_value) / tx.vsize());
+        }
+        if fee_rates.is_empty() {
+            return;
+        }
+
+        fee_rates.sort();
+        self.blocks.push_back(fee_rates);
+        if self.blocks.len() > RECENT_BLOCKS {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Devuelve el fee estimado en satoshis por byte virtual para la prioridad recibida.
+    /// Es el percentil correspondiente a la prioridad de los fees de los bloques recientes, y nunca menor a MIN_FEE_RATE.
+    pub fn estimate(&self, priority: FeePriority) -> u64 {
+        let mut fee_rates: Vec<u64> = self.blocks.iter().flatten().copied().collect();
+        if fee_rates.is_empty() {
+            return MIN_FEE_RATE;
+        }
+        fee_rates.sort();
+        let index = (fee_rates.len() - 1) * priority.percentile() / 100;
+        fee_rates[index].max(MIN_FEE_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        messages::transaction::Transaction,
+        states::utxo_state::UTXOValue,
+        structs::{
+            block_header::BlockHeader, hash256::Hash256, outpoint::OutPoint,
+            tx_input::TransactionInput, tx_output::TransactionOutput,
+        },
+    };
+
+    use super::*;
+
+    fn transaction(previous_output: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![TransactionInput {
+                previous_output,
+                script_sig: vec![],
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            outputs: vec![TransactionOutput {
+                value,
+                script_pubkey: vec![],
+            }],
+            lock_time: 0,
+        }
+    }
+
+    fn block(transactions: Vec<Transaction>) -> Block {
+        let header = BlockHeader {
+            version: 0,
+            prev_block_hash: Hash256::new([0; 32]),
+            merkle_root: Hash256::ZERO,
+            timestamp: 0,
+            bits: 0,
+            nonce: 0,
+            hash: Hash256::new([0; 32]),
+            broadcasted: false,
+            block_downloaded: false,
+        };
+        Block::new(header, transactions)
+    }
+
+    fn out_point(index: u32) -> OutPoint {
+        OutPoint {
+            hash: Hash256::new([1; 32]),
+            index,
+        }
+    }
+
+    #[test]
+    fn estimate_without_blocks() {
+        let fee_estimator = FeeEstimator::new();
+        assert_eq!(fee_estimator.estimate(FeePriority::High), MIN_FEE_RATE);
+    }
+
+    #[test]
+    fn estimate_from_recent_blocks() {
+        let mut utxo = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        for index in 0..10 {
+            utxo.tx_set.insert(
+                out_point(index),
+                UTXOValue {
+                    tx_out: TransactionOutput {
+                        value: 100000,
+                        script_pubkey: vec![],
+                    },
+                    block_hash: Hash256::new([0; 32]),
+                    block_timestamp: 0,
+                },
+            );
+        }
+
+        let mut transactions = vec![transaction(out_point(100), 0)];
+        for index in 0..10 {
+            let tx = transaction(out_point(index), 0);
+            let fee = (index as u64 + 1) * 10 * tx.vsize();
+            transactions.push(transaction(out_point(index), 100000 - fee));
+        }
+        // gasta un output desconocido, por lo que no se considera
+        transactions.push(transaction(out_point(200), 0));
+
+        let mut fee_estimator = FeeEstimator::new();
+        fee_estimator.record_block(&block(transactions), &utxo);
+
+        assert_eq!(fee_estimator.estimate(FeePriority::Low), 30);
+        assert_eq!(fee_estimator.estimate(FeePriority::Medium), 50);
+        assert_eq!(fee_estimator.estimate(FeePriority::High), 90);
+    }
+}
@@ -1,141 +1,282 @@
-use std::sync::{mpsc, Arc, Mutex};
+use std::{cell::Cell, collections::HashSet, rc::Rc, sync::mpsc};
 
 use gtk::{
-    traits::{BoxExt, ContainerExt, LabelExt, WidgetExt},
+    pango::EllipsizeMode,
+    traits::{BoxExt, ButtonExt, ContainerExt, LabelExt, ListBoxExt, WidgetExt},
     ListBox,
 };
 
 use crate::{
-    error::CustomError,
-    logger::{send_log, Log},
+    error::{CustomError, WalletError},
+    exchange_rate::ExchangeRates,
+    logger::{send_log, Log, LogEntry},
+    loops::node_action_loop::NodeAction,
     node_state::NodeState,
+    states::history_state::{Direction, HistoryEntry},
+    structs::{balances::Balances, hash256::Hash256},
 };
 
 use super::{
     init::{get_gui_element, GUIEvents},
-    table_cells::{side_label, value_label},
+    node_query::NodeQueries,
+    table_cells::{side_label, tx_hash_label, value_label},
 };
 
+/// Nombre de la fila de encabezado de la lista de transacciones pendientes.
+const HEADER_ROW_NAME: &str = "header";
+
+/// BalanceView son los datos del estado del nodo que muestra GUIBalance, obtenidos en una misma consulta.
+/// Si no hay una wallet activa, el balance es cero y no tiene transacciones pendientes.
+/// Los elementos son:
+/// - balances: Balance de la wallet activa desglosado en confirmado, pendiente e inmaduro.
+/// - pending_txs: Transacciones pendientes de la wallet activa.
+/// - exchange_rates: Precio de bitcoin en monedas fiat, None si todavia no se obtuvo.
+struct BalanceView {
+    balances: Balances,
+    pending_txs: Vec<HistoryEntry>,
+    exchange_rates: Option<ExchangeRates>,
+}
+
+impl BalanceView {
+    /// Obtiene los datos del estado del nodo.
+    fn from_node_state(node_state: &NodeState) -> Result<Self, CustomError> {
+        let (balances, pending_txs) = match (
+            node_state.get_active_wallet_balances(),
+            node_state.get_active_wallet_pending_txs(),
+        ) {
+            (Ok(balances), Ok(pending_txs)) => (balances, pending_txs),
+            (Err(CustomError::Wallet(WalletError::WalletNotFound)), _) => {
+                (Balances::default(), vec![])
+            }
+            (Err(error), _) | (_, Err(error)) => return Err(error),
+        };
+        Ok(Self {
+            balances,
+            pending_txs,
+            exchange_rates: node_state.get_exchange_rates(),
+        })
+    }
+}
+
+/// Refresh es el estado de la consulta con la que se actualiza GUIBalance.
+/// - Idle: No hay una consulta en curso.
+/// - Running: Hay una consulta en curso y los datos que va a devolver estan al dia.
+/// - Stale: Hay una consulta en curso, pero el estado del nodo cambio despues de pedirla y hay que repetirla.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refresh {
+    Idle,
+    Running,
+    Stale,
+}
+
 #[derive(Clone)]
 /// GUIBalance es una estructura que contiene los elementos de la interfaz grafica
 /// relacionados con el balance de la billetera y transacciones pendientes.
+/// Los datos se consultan con NodeQueries, por lo que el main loop de gtk no espera el lock del NodeState.
+/// Mientras una consulta esta en curso, los eventos que llegan no piden otra sino que la repiten al terminar.
 ///
 /// Los elementos son:
 /// - builder: Builder de gtk.
-/// - node_state_ref: Referencia al estado del nodo.
+/// - node_queries: Canal de consultas al estado del nodo.
 /// - logger_sender: Sender para enviar logs al logger.
-/// - available_balance: Balance disponible de la billetera.
-/// - pending_balance: Balance pendiente de la billetera.
+/// - node_action_sender: Sender para pedirle al nodo que reemplace o acelere una transaccion pendiente.
+/// - refresh: Estado de la consulta con la que se actualiza el balance.
 pub struct GUIBalance {
     pub builder: gtk::Builder,
-    pub node_state_ref: Arc<Mutex<NodeState>>,
-    pub logger_sender: mpsc::Sender<Log>,
-    pub available_balance: f64,
-    pub pending_balance: f64,
+    pub node_queries: NodeQueries,
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub node_action_sender: mpsc::Sender<NodeAction>,
+    pub refresh: Rc<Cell<Refresh>>,
 }
 
 impl GUIBalance {
     /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
     ///
-    /// Para WalletChanged: Actualiza el balance pendiente y disponible y las transacciones pendientes.
-    /// Para WalletsUpdated: Actualiza el balance pendiente y disponible y las transacciones pendientes.
-    /// Para NewPendingTx: Actualiza las transacciones pendientes y el balance pendinente.
-    pub fn handle_events(&mut self, message: &GUIEvents) {
-        let result = match message {
-            GUIEvents::WalletChanged => self.handle_wallet_changed(),
-            GUIEvents::NewPendingTx => self.handle_new_pending_tx(),
-            GUIEvents::WalletsUpdated => self.handle_wallet_updated(),
-            _ => Ok(()),
-        };
-
-        if let Err(error) = result {
-            send_log(&self.logger_sender, Log::Error(error));
+    /// Para WalletChanged: Actualiza el desglose del balance y las transacciones pendientes.
+    /// Para WalletsUpdated: Actualiza el desglose del balance y las transacciones pendientes.
+    /// Para NewPendingTx: Actualiza las transacciones pendientes y el desglose del balance.
+    /// Para ExchangeRatesUpdated: Actualiza el valor aproximado en monedas fiat del balance.
+    pub fn handle_events(&self, message: &GUIEvents) {
+        match message {
+            GUIEvents::WalletChanged
+            | GUIEvents::NewPendingTx
+            | GUIEvents::WalletsUpdated
+            | GUIEvents::ExchangeRatesUpdated => self.refresh(),
+            _ => {}
         }
     }
 
-    fn handle_wallet_changed(&mut self) -> Result<(), CustomError> {
-        self.update_available_balance()?;
-        self.update_pending_txs()
-    }
+    /// Pide los datos a mostrar al estado del nodo y los muestra cuando llega la respuesta.
+    /// Si ya hay una consulta en curso, se repite al terminar.
+    fn refresh(&self) {
+        if self.refresh.get() != Refresh::Idle {
+            self.refresh.set(Refresh::Stale);
+            return;
+        }
+        self.refresh.set(Refresh::Running);
 
-    fn handle_wallet_updated(&mut self) -> Result<(), CustomError> {
-        self.update_available_balance()?;
-        self.update_pending_txs()
+        let balance = self.clone();
+        let requested = self
+            .node_queries
+            .request(BalanceView::from_node_state, move |view| {
+                if let Err(error) = view.and_then(|view| balance.show(&view)) {
+                    send_log(&balance.logger_sender, Log::Error(error));
+                }
+                if balance.refresh.replace(Refresh::Idle) == Refresh::Stale {
+                    balance.refresh();
+                }
+            });
+        if let Err(error) = requested {
+            self.refresh.set(Refresh::Idle);
+            send_log(&self.logger_sender, Log::Error(error));
+        }
     }
 
-    fn handle_new_pending_tx(&mut self) -> Result<(), CustomError> {
-        self.update_pending_txs()
+    fn show(&self, view: &BalanceView) -> Result<(), CustomError> {
+        self.update_balances(&view.balances, view.exchange_rates)?;
+        self.update_pending_txs(&view.pending_txs)
     }
 
-    fn update_available_balance(&mut self) -> Result<(), CustomError> {
-        let node_state = self.node_state_ref.lock()?;
+    /// Actualiza la lista de transacciones pendientes de la wallet activa comparandola con las filas que ya muestra.
+    /// Elimina las filas de las transacciones que dejaron de estar pendientes, por ejemplo porque se confirmaron
+    /// y pasaron al historial, y agrega las de las nuevas en orden, sin repetir las que ya estaban.
+    fn update_pending_txs(&self, pending_transactions: &[HistoryEntry]) -> Result<(), CustomError> {
+        let pending_tx_list_box: gtk::ListBox =
+            get_gui_element(&self.builder, "pending-transactions-list")?;
+        let row_names: Vec<String> = pending_transactions.iter().map(pending_row_name).collect();
 
-        match node_state.get_active_wallet_balance() {
-            Ok(balance) => {
-                self.available_balance = balance as f64;
+        if pending_tx_list_box.children().is_empty() {
+            add_header_row(&pending_tx_list_box);
+        }
+        let mut shown_rows = HashSet::new();
+        for row in pending_tx_list_box.children() {
+            let row_name = row.widget_name().to_string();
+            if row_name == HEADER_ROW_NAME {
+                continue;
             }
-            Err(error) => {
-                send_log(&self.logger_sender, Log::Error(error));
+            if row_names.contains(&row_name) {
+                shown_rows.insert(row_name);
+            } else {
+                pending_tx_list_box.remove(&row);
             }
         }
-        drop(node_state);
-
-        self.update_balances()?;
 
+        // las filas que quedan estan en el mismo orden que las transacciones, por lo que cada transaccion nueva
+        // se inserta en su posicion, despues del encabezado
+        for (position, (entry, row_name)) in pending_transactions.iter().zip(row_names).enumerate()
+        {
+            if !shown_rows.contains(&row_name) {
+                let pending_tx_row = self.pending_tx_row(entry);
+                pending_tx_row.set_widget_name(&row_name);
+                pending_tx_list_box.insert(&pending_tx_row, position as i32 + 1);
+            }
+        }
         Ok(())
     }
 
-    fn update_pending_txs(&mut self) -> Result<(), CustomError> {
-        let pending_tx_list_box: gtk::ListBox =
-            get_gui_element(&self.builder, "pending-transactions-list")?;
-        let node_state_ref_clone = self.node_state_ref.clone();
-        let node_state = node_state_ref_clone.lock()?;
-        if node_state.get_active_wallet().is_none() {
-            return Ok(());
-        }
-        let pending_transactions = node_state.get_active_wallet_pending_txs()?;
-
-        self.pending_balance = 0.0;
-        reset_table(&pending_tx_list_box);
-        for movement in pending_transactions {
-            self.pending_balance += movement.value as f64;
-            let pending_tx_row = gtk::ListBoxRow::new();
-            let pending_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-            pending_box.set_homogeneous(true);
-
-            let side_label = side_label(movement.value);
-            pending_box.add(&side_label);
-            let value_label = value_label(movement.value);
-            pending_box.add(&value_label);
-
-            pending_tx_row.add(&pending_box);
-            pending_tx_row.show_all();
-            pending_tx_list_box.add(&pending_tx_row);
+    /// Fila con el hash, el sentido y el monto de una transaccion pendiente, y el boton para acelerarla.
+    fn pending_tx_row(&self, entry: &HistoryEntry) -> gtk::ListBoxRow {
+        let pending_tx_row = gtk::ListBoxRow::new();
+        let pending_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        pending_box.set_homogeneous(true);
+
+        let tx_hash_label = tx_hash_label(entry.tx_hash);
+        tx_hash_label.set_ellipsize(EllipsizeMode::Middle);
+        tx_hash_label.set_tooltip_text(Some(&entry.tx_hash.to_string()));
+        pending_box.add(&tx_hash_label);
+        let value = match entry.direction {
+            Direction::Sent => -(entry.amount as i64),
+            Direction::Received => entry.amount as i64,
+        };
+        pending_box.add(&side_label(value));
+        pending_box.add(&value_label(value));
+        match entry.direction {
+            Direction::Sent => pending_box.add(&self.bump_fee_button(entry.tx_hash)),
+            Direction::Received => pending_box.add(&self.cpfp_button(entry.tx_hash)),
         }
-        drop(node_state);
 
-        self.update_balances()?;
+        pending_tx_row.add(&pending_box);
+        pending_tx_row.show_all();
+        pending_tx_row
+    }
 
-        Ok(())
+    /// Boton que le pide al nodo reemplazar una transaccion enviada por otra con mas fee (BIP 125).
+    fn bump_fee_button(&self, tx_hash: Hash256) -> gtk::Button {
+        let button = gtk::Button::with_label("Bump fee");
+        let node_action_sender = self.node_action_sender.clone();
+        let logger_sender = self.logger_sender.clone();
+        button.connect_clicked(move |_| {
+            if node_action_sender
+                .send(NodeAction::BumpFee(tx_hash))
+                .is_err()
+            {
+                send_log(
+                    &logger_sender,
+                    Log::Error(CustomError::CannotSendMessageToChannel),
+                );
+            }
+        });
+        button
     }
 
-    fn update_balances(&self) -> Result<(), CustomError> {
+    /// Boton que le pide al nodo gastar los outputs recibidos en una transaccion pendiente con mas fee,
+    /// para que se confirme antes (CPFP).
+    fn cpfp_button(&self, tx_hash: Hash256) -> gtk::Button {
+        let button = gtk::Button::with_label("Accelerate");
+        button.set_tooltip_text(Some(
+            "Spend the received outputs paying a higher fee (child pays for parent)",
+        ));
+        let node_action_sender = self.node_action_sender.clone();
+        let logger_sender = self.logger_sender.clone();
+        button.connect_clicked(move |_| {
+            if node_action_sender.send(NodeAction::Cpfp(tx_hash)).is_err() {
+                send_log(
+                    &logger_sender,
+                    Log::Error(CustomError::CannotSendMessageToChannel),
+                );
+            }
+        });
+        button
+    }
+
+    fn update_balances(
+        &self,
+        balances: &Balances,
+        exchange_rates: Option<ExchangeRates>,
+    ) -> Result<(), CustomError> {
         let available_balance: gtk::Label =
             get_gui_element(&self.builder, "label-available-balance")?;
         let pending_balance: gtk::Label = get_gui_element(&self.builder, "label-pending-balance")?;
+        let incoming_balance: gtk::Label =
+            get_gui_element(&self.builder, "label-incoming-balance")?;
+        let immature_balance: gtk::Label =
+            get_gui_element(&self.builder, "label-immature-balance")?;
         let total_balance: gtk::Label = get_gui_element(&self.builder, "label-total-balance")?;
         let transfer_balance: gtk::Label =
             get_gui_element(&self.builder, "label-transfer-balance")?;
 
-        let available_btc = self.available_balance / 100_000_000.0;
-        available_balance.set_text(format!("Balance:    {:.8} BTC", available_btc).as_str());
+        let available_btc = balances.confirmed as f64 / 100_000_000.0;
+        available_balance.set_text(format!("Confirmed:    {:.8} BTC", available_btc).as_str());
+
+        let pending_btc = balances.trusted_pending as f64 / 100_000_000.0;
+        pending_balance.set_text(format!("Pending (own):    {:.8} BTC", pending_btc).as_str());
 
-        let pending_btc = self.pending_balance / 100_000_000.0;
-        pending_balance.set_text(format!("Pending:    {:.8} BTC", pending_btc).as_str());
+        let incoming_btc = balances.untrusted_pending as f64 / 100_000_000.0;
+        incoming_balance
+            .set_text(format!("Pending (incoming):    {:.8} BTC", incoming_btc).as_str());
 
-        let total_satoshi = self.available_balance + self.pending_balance;
+        let immature_btc = balances.immature as f64 / 100_000_000.0;
+        immature_balance.set_text(format!("Immature:    {:.8} BTC", immature_btc).as_str());
+
+        let total_satoshi = balances.total() as f64;
         let total_btc = total_satoshi / 100_000_000.0;
-        let total_balance_string = format!("Total:	     {:.8} BTC", total_btc);
+        let mut total_balance_string = format!("Total:	     {:.8} BTC", total_btc);
+        if let Some(exchange_rates) = exchange_rates {
+            total_balance_string.push_str(&format!(
+                "    ({})",
+                exchange_rates.format(total_satoshi as i64)
+            ));
+        }
         let total_balance_string_satoshi = format!("Total:  {:.0} Sat", total_satoshi);
 
         total_balance.set_text(total_balance_string.as_str());
@@ -145,23 +286,25 @@ impl GUIBalance {
     }
 }
 
-fn reset_table(list_box: &ListBox) {
-    list_box.foreach(|child| {
-        list_box.remove(child);
-    });
-    let utxo_row = gtk::ListBoxRow::new();
-    let utxo_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    let side_label = gtk::Label::new(None);
-    let value_label = gtk::Label::new(None);
-
-    utxo_box.set_homogeneous(true);
-    side_label.set_markup("<b>Side</b>");
-    value_label.set_markup("<b>Value</b>");
-
-    utxo_box.add(&side_label);
-    utxo_box.add(&value_label);
-
-    utxo_row.add(&utxo_box);
-    utxo_row.show_all();
-    list_box.add(&utxo_row);
+/// Nombre de la fila de una transaccion pendiente, que la identifica al comparar la lista con el estado del nodo.
+/// Incluye el sentido y el monto, ya que una transaccion entre dos wallets cargadas se muestra distinta en cada una.
+fn pending_row_name(entry: &HistoryEntry) -> String {
+    format!("{}:{:?}:{}", entry.tx_hash, entry.direction, entry.amount)
+}
+
+fn add_header_row(list_box: &ListBox) {
+    let header_row = gtk::ListBoxRow::new();
+    header_row.set_widget_name(HEADER_ROW_NAME);
+    let header_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    header_box.set_homogeneous(true);
+
+    for title in ["Transaction", "Side", "Value", "Action"] {
+        let title_label = gtk::Label::new(None);
+        title_label.set_markup(&format!("<b>{}</b>", title));
+        header_box.add(&title_label);
+    }
+
+    header_row.add(&header_box);
+    header_row.show_all();
+    list_box.add(&header_row);
 }
@@ -1,4 +1,4 @@
-use std::sync::{mpsc::Sender, Arc, Mutex};
+use std::sync::mpsc::Sender;
 
 use gtk::{
     traits::{ContainerExt, LabelExt, WidgetExt},
@@ -7,12 +7,13 @@ use gtk::{
 
 use crate::{
     error::CustomError,
-    logger::{send_log, Log},
-    node_state::NodeState,
+    logger::{send_log, Log, LogEntry},
+    structs::block_header::BlockHeader,
 };
 
 use super::{
     init::{get_gui_element, GUIEvents},
+    node_query::NodeQueries,
     table_cells::{number_label, time_label, tx_hash_label},
 };
 
@@ -21,12 +22,13 @@ use super::{
 /// relacionados con los bloques. Muestra un listado de los ultimos 100 bloques (fecha de creacion, tx hash, version y nbits).
 /// Los elementos son:
 /// - builder: Builder de gtk.
-/// - node_state_ref: Referencia al estado del nodo.
+/// - node_queries: Canal de consultas al estado del nodo.
 /// - logger_sender: Sender para enviar logs al logger.
+/// - node_state_ready: Indica si el nodo ya se sincronizo y se pueden mostrar los bloques.
 pub struct GUIBlocks {
-    pub logger_sender: Sender<Log>,
+    pub logger_sender: Sender<LogEntry>,
     pub builder: gtk::Builder,
-    pub node_state_ref: Arc<Mutex<NodeState>>,
+    pub node_queries: NodeQueries,
     pub node_state_ready: bool,
 }
 
@@ -51,15 +53,25 @@ impl GUIBlocks {
         self.update_blocks()
     }
 
+    /// Pide los ultimos bloques al estado del nodo y los muestra cuando llega la respuesta.
     fn update_blocks(&self) -> Result<(), CustomError> {
         if !self.node_state_ready {
             return Ok(());
         }
-        let blocks_list_box: gtk::ListBox = get_gui_element(&self.builder, "blocks-list")?;
-        let node_state_ref_clone = self.node_state_ref.clone();
-        let node_state = node_state_ref_clone.lock()?;
-        let headers = node_state.get_last_headers(100);
+        let blocks = self.clone();
+        self.node_queries.request(
+            |node_state| Ok(node_state.get_last_headers(100)),
+            move |headers| {
+                if let Err(error) = headers.and_then(|headers| blocks.show_blocks(headers)) {
+                    send_log(&blocks.logger_sender, Log::Error(error));
+                }
+            },
+        )
+    }
 
+    /// Muestra los bloques recibidos con su altura, reemplazando las filas anteriores.
+    fn show_blocks(&self, headers: Vec<(usize, BlockHeader)>) -> Result<(), CustomError> {
+        let blocks_list_box: gtk::ListBox = get_gui_element(&self.builder, "blocks-list")?;
         reset_table(&blocks_list_box);
         for (height, header) in headers.into_iter() {
             let utxo_row = gtk::ListBoxRow::new();
@@ -68,7 +80,7 @@ impl GUIBlocks {
             utxo_box.set_margin_bottom(8);
 
             utxo_box.add(&time_label(header.timestamp));
-            utxo_box.add(&tx_hash_label(header.hash().clone()));
+            utxo_box.add(&tx_hash_label(*header.hash()));
             utxo_box.add(&number_label(height as i64));
             utxo_box.add(&number_label(header.bits as i64));
 
@@ -0,0 +1,177 @@
+use std::sync::mpsc::Sender;
+
+use gtk::{
+    traits::{ButtonExt, ContainerExt, EntryExt, LabelExt, WidgetExt},
+    ListBox,
+};
+
+use crate::{
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+    states::address_book_state::Contact,
+};
+
+use super::{
+    init::{get_gui_element, GUIEvents},
+    node_query::NodeQueries,
+};
+
+#[derive(Clone)]
+/// GUIContacts es una estructura que contiene los elementos de la interfaz grafica
+/// relacionados con la libreta de direcciones. Lista los contactos (nombre, direccion y eliminarlo) y permite guardar contactos nuevos.
+/// Los elementos son:
+/// - builder: Builder de gtk.
+/// - node_queries: Canal de consultas al estado del nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+pub struct GUIContacts {
+    pub logger_sender: Sender<LogEntry>,
+    pub builder: gtk::Builder,
+    pub node_queries: NodeQueries,
+}
+
+impl GUIContacts {
+    /// Inicializa la lista de contactos.
+    pub fn initialize(&self) -> Result<(), CustomError> {
+        self.update_contacts()
+    }
+
+    /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
+    /// Para ContactsUpdated: Actualiza la lista de contactos.
+    pub fn handle_events(&mut self, message: &GUIEvents) {
+        let result = match message {
+            GUIEvents::ContactsUpdated => self.update_contacts(),
+            _ => Ok(()),
+        };
+
+        if let Err(error) = result {
+            send_log(&self.logger_sender, Log::Error(error));
+        }
+    }
+
+    /// Establece los callbacks de los elementos de la interfaz grafica.
+    /// Para el boton de guardar contacto: Agrega el contacto ingresado, o si ya existe un contacto con ese nombre le cambia la direccion.
+    /// Los campos se vacian cuando se guardo el contacto.
+    pub fn handle_interactivity(&self) -> Result<(), CustomError> {
+        let save_button: gtk::Button = get_gui_element(&self.builder, "save-contact")?;
+        let label: gtk::Entry = get_gui_element(&self.builder, "contact-label")?;
+        let address: gtk::Entry = get_gui_element(&self.builder, "contact-address")?;
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+
+        save_button.connect_clicked(move |_| {
+            let contact_label = label.text().trim().to_string();
+            let contact_address = address.text().trim().to_string();
+            let (label, address) = (label.clone(), address.clone());
+            let response_logger_sender = logger_sender.clone();
+            let requested = node_queries.request_mut(
+                move |node_state| save_contact(node_state, contact_label, contact_address),
+                move |result| match result {
+                    Ok(()) => {
+                        label.set_text("");
+                        address.set_text("");
+                    }
+                    Err(error) => send_log(&response_logger_sender, Log::Error(error)),
+                },
+            );
+            if let Err(error) = requested {
+                send_log(&logger_sender, Log::Error(error));
+            }
+        });
+        Ok(())
+    }
+
+    /// Pide los contactos al estado del nodo y los muestra cuando llega la respuesta.
+    fn update_contacts(&self) -> Result<(), CustomError> {
+        let gui_contacts = self.clone();
+        self.node_queries.request(
+            |node_state| Ok(node_state.get_contacts()),
+            move |contacts| {
+                if let Err(error) =
+                    contacts.and_then(|contacts| gui_contacts.show_contacts(contacts))
+                {
+                    send_log(&gui_contacts.logger_sender, Log::Error(error));
+                }
+            },
+        )
+    }
+
+    /// Muestra los contactos recibidos, reemplazando las filas anteriores.
+    fn show_contacts(&self, contacts: Vec<Contact>) -> Result<(), CustomError> {
+        let contacts_list_box: gtk::ListBox = get_gui_element(&self.builder, "contacts-list")?;
+        reset_table(&contacts_list_box);
+
+        for contact in contacts {
+            let contact_row = gtk::ListBoxRow::new();
+            let contact_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+            let label = gtk::Label::new(Some(&contact.label));
+            label.set_width_request(200);
+            let address = gtk::Label::new(Some(&contact.address));
+            address.set_expand(true);
+
+            let remove_button = gtk::Button::new();
+            remove_button.set_label("Remove");
+            remove_button.set_width_request(128);
+            let node_queries = self.node_queries.clone();
+            let logger_sender = self.logger_sender.clone();
+            let contact_label = contact.label.clone();
+            remove_button.connect_clicked(move |_| {
+                let contact_label = contact_label.clone();
+                node_queries.change(
+                    move |node_state| node_state.remove_contact(&contact_label),
+                    &logger_sender,
+                );
+            });
+
+            contact_box.add(&label);
+            contact_box.add(&address);
+            contact_box.add(&remove_button);
+
+            contact_row.add(&contact_box);
+            contact_row.show_all();
+            contacts_list_box.add(&contact_row);
+        }
+        Ok(())
+    }
+}
+
+/// Agrega el contacto, o si ya existe un contacto con ese nombre le cambia la direccion.
+fn save_contact(
+    node_state: &mut NodeState,
+    label: String,
+    address: String,
+) -> Result<(), CustomError> {
+    match node_state.get_contact(&label) {
+        Some(_) => node_state.update_contact(&label, label.clone(), address),
+        None => node_state.add_contact(label, address),
+    }
+}
+
+fn reset_table(list_box: &ListBox) {
+    list_box.foreach(|child| {
+        list_box.remove(child);
+    });
+    let contact_row = gtk::ListBoxRow::new();
+    let contact_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let label_label = gtk::Label::new(None);
+    let address_label = gtk::Label::new(None);
+    let action_label = gtk::Label::new(None);
+
+    label_label.set_width_request(200);
+    label_label.set_markup("<b>Label</b>");
+
+    address_label.set_expand(true);
+    address_label.set_markup("<b>Address</b>");
+
+    action_label.set_width_request(128);
+    action_label.set_markup("<b>Action</b>");
+
+    contact_box.add(&label_label);
+    contact_box.add(&address_label);
+    contact_box.add(&action_label);
+
+    contact_row.add(&contact_box);
+    contact_row.show_all();
+    list_box.add(&contact_row);
+}
@@ -1,42 +1,94 @@
-use std::sync::{mpsc::Sender, Arc, Mutex};
+use std::sync::mpsc::Sender;
 
 use gtk::{
-    traits::{ContainerExt, LabelExt, WidgetExt},
-    ListBox,
+    traits::{
+        ButtonExt, ContainerExt, DialogExt, EntryExt, FileChooserExt, GtkWindowExt, LabelExt,
+        WidgetExt,
+    },
+    FileChooserAction, ListBox, ResponseType,
 };
 
 use crate::{
     error::CustomError,
-    logger::{send_log, Log},
+    logger::{send_log, Log, LogEntry},
     node_state::NodeState,
+    states::history_state::{Direction, HistoryEntry},
+    structs::hash256::Hash256,
 };
 
 use super::{
     init::{get_gui_element, GUIEvents},
-    table_cells::{merkle_proof_button, side_label, tx_hash_label, value_label},
+    node_query::NodeQueries,
+    table_cells::{
+        address_label, confirmations_label, merkle_proof_button, output_type_label, side_label,
+        tx_hash_label, value_label,
+    },
 };
 
+/// HistoryView son los datos del estado del nodo que muestra GUIHistory, obtenidos en una misma consulta.
+/// Los elementos son:
+/// - history: Historial de transacciones de la wallet activa.
+/// - best_height: Altura del ultimo bloque, para calcular las confirmaciones.
+/// - contact_labels: Nombre del contacto de la otra parte de cada transaccion, si esta en la libreta de direcciones.
+struct HistoryView {
+    history: Vec<HistoryEntry>,
+    best_height: usize,
+    contact_labels: Vec<Option<String>>,
+}
+
+impl HistoryView {
+    /// Obtiene los datos del estado del nodo, o None si no hay una wallet activa.
+    fn from_node_state(node_state: &NodeState) -> Result<Option<Self>, CustomError> {
+        if node_state.get_active_wallet().is_none() {
+            return Ok(None);
+        }
+        let (history, best_height) = node_state.get_active_wallet_history();
+        let contact_labels = history
+            .iter()
+            .map(|entry| {
+                entry
+                    .address
+                    .as_ref()
+                    .and_then(|address| node_state.get_contact_label(address))
+            })
+            .collect();
+        Ok(Some(Self {
+            history,
+            best_height,
+            contact_labels,
+        }))
+    }
+}
+
 #[derive(Clone)]
 /// GUIHistory es una estructura que contiene los elementos de la interfaz grafica
-/// relacionados con el historial de movimientos de una wallet y los lista (tx hash, enviado o recibido, valor y pedir el merkle proof de esa tx).
+/// relacionados con el historial de transacciones de una wallet y las lista (tx hash, direccion o contacto de la otra parte, enviado o recibido, valor, confirmaciones, etiqueta y pedir el merkle proof de esa tx), y permite exportarlas en CSV.
 /// Los elementos son:
 /// - builder: Builder de gtk.
-/// - node_state_ref: Referencia al estado del nodo.
+/// - node_queries: Canal de consultas al estado del nodo.
 /// - logger_sender: Sender para enviar logs al logger.
 pub struct GUIHistory {
-    pub logger_sender: Sender<Log>,
+    pub logger_sender: Sender<LogEntry>,
     pub builder: gtk::Builder,
-    pub node_state_ref: Arc<Mutex<NodeState>>,
+    pub node_queries: NodeQueries,
 }
 
 impl GUIHistory {
     /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
-    /// Para WalletChanged: Actualiza la lista de movimientos.
-    /// Para WalletsUpdated: Actualiza la lista de movimientos.
+    /// Para WalletChanged: Actualiza la lista de transacciones.
+    /// Para WalletsUpdated: Actualiza la lista de transacciones.
+    /// Para NewPendingTx: Actualiza la lista de transacciones.
+    /// Para NewBlock y NewHeaders: Actualiza las confirmaciones de las transacciones.
+    /// Para ContactsUpdated: Actualiza los nombres de los contactos de las transacciones.
+    /// Para TxLabelsUpdated: Actualiza las etiquetas de las transacciones.
     pub fn handle_events(&mut self, message: &GUIEvents) {
         let result = match message {
             GUIEvents::WalletChanged => self.update_txs(),
             GUIEvents::WalletsUpdated => self.update_txs(),
+            GUIEvents::NewPendingTx => self.update_txs(),
+            GUIEvents::NewBlock | GUIEvents::NewHeaders => self.update_txs(),
+            GUIEvents::ContactsUpdated => self.update_txs(),
+            GUIEvents::TxLabelsUpdated => self.update_txs(),
             _ => Ok(()),
         };
 
@@ -45,35 +97,120 @@ impl GUIHistory {
         }
     }
 
+    /// Establece los callbacks de los elementos de la interfaz grafica.
+    /// Para el boton de exportar: Pide el archivo donde guardar el historial de la wallet activa y lo exporta en CSV.
+    pub fn handle_interactivity(&self) -> Result<(), CustomError> {
+        let export_button: gtk::Button = get_gui_element(&self.builder, "export-history")?;
+        let main_window: gtk::Window = get_gui_element(&self.builder, "main-window")?;
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+
+        export_button.connect_clicked(move |_| {
+            let dialog = gtk::FileChooserDialog::with_buttons(
+                Some("Export transactions"),
+                Some(&main_window),
+                FileChooserAction::Save,
+                &[
+                    ("Cancel", ResponseType::Cancel),
+                    ("Export", ResponseType::Accept),
+                ],
+            );
+            dialog.set_do_overwrite_confirmation(true);
+            dialog.set_current_name("transactions.csv");
+
+            let node_queries = node_queries.clone();
+            let logger_sender = logger_sender.clone();
+            dialog.connect_response(move |dialog, response| {
+                if response == ResponseType::Accept {
+                    if let Some(path) = dialog.filename() {
+                        let path = path.to_string_lossy().to_string();
+                        if let Err(error) = export_history(&node_queries, &logger_sender, path) {
+                            send_log(&logger_sender, Log::Error(error));
+                        }
+                    }
+                }
+                dialog.close();
+            });
+            dialog.show_all();
+        });
+        Ok(())
+    }
+
+    /// Pide el historial de la wallet activa al estado del nodo y lo muestra cuando llega la respuesta.
     fn update_txs(&self) -> Result<(), CustomError> {
+        let history = self.clone();
+        self.node_queries
+            .request(HistoryView::from_node_state, move |view| {
+                let result = view.and_then(|view| match view {
+                    Some(view) => history.show_txs(view),
+                    None => Ok(()),
+                });
+                if let Err(error) = result {
+                    send_log(&history.logger_sender, Log::Error(error));
+                }
+            })
+    }
+
+    /// Muestra el historial de la wallet activa, reemplazando las filas anteriores.
+    fn show_txs(&self, view: HistoryView) -> Result<(), CustomError> {
         let history_list_box: gtk::ListBox = get_gui_element(&self.builder, "history-list")?;
-        let node_state_ref_clone = self.node_state_ref.clone();
-        let node_state = node_state_ref_clone.lock()?;
-        let Some(active_wallet) = node_state.get_active_wallet() else { return Ok(()) };
-        let history = active_wallet.get_history();
+        let HistoryView {
+            history,
+            best_height,
+            contact_labels,
+        } = view;
         reset_table(&history_list_box);
 
-        for movement in history.iter().rev() {
+        for (entry, contact_label) in history.into_iter().zip(contact_labels) {
             let history_row = gtk::ListBoxRow::new();
             let history_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
 
-            history_box.add(&tx_hash_label(movement.tx_hash.clone()));
-            history_box.add(&side_label(movement.value));
-            history_box.add(&value_label(movement.value));
+            let value = match entry.direction {
+                Direction::Received => entry.amount as i64,
+                Direction::Sent => -(entry.amount as i64),
+            };
+            let confirmations = entry.block_height.map(|_| entry.confirmations(best_height));
+
+            history_box.add(&tx_hash_label(entry.tx_hash));
+            history_box.add(&address_label(entry.address, contact_label));
+            history_box.add(&output_type_label(entry.output_type));
+            history_box.add(&side_label(value));
+            history_box.add(&value_label(value));
+            history_box.add(&confirmations_label(confirmations));
+            history_box.add(&self.label_entry(entry.tx_hash, entry.label));
             history_box.add(&merkle_proof_button(
-                movement.block_hash.clone(),
-                movement.tx_hash.clone(),
+                entry.block_hash,
+                entry.tx_hash,
                 self.logger_sender.clone(),
-                self.node_state_ref.clone(),
+                self.node_queries.clone(),
             ));
 
             history_row.add(&history_box);
             history_row.show_all();
             history_list_box.add(&history_row);
         }
-        drop(node_state);
         Ok(())
     }
+
+    /// Campo con la etiqueta de una transaccion, que se guarda al presionar Enter.
+    fn label_entry(&self, tx_hash: Hash256, label: Option<String>) -> gtk::Entry {
+        let label_entry = gtk::Entry::new();
+        label_entry.set_text(label.as_deref().unwrap_or_default());
+        label_entry.set_placeholder_text(Some("Add label"));
+        label_entry.set_tooltip_text(Some("Press Enter to save the label"));
+        label_entry.set_width_request(160);
+
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+        label_entry.connect_activate(move |label_entry| {
+            let label = label_entry.text().to_string();
+            node_queries.change(
+                move |node_state| node_state.set_tx_label(&tx_hash, &label),
+                &logger_sender,
+            );
+        });
+        label_entry
+    }
 }
 
 fn reset_table(list_box: &ListBox) {
@@ -83,28 +220,68 @@ fn reset_table(list_box: &ListBox) {
     let utxo_row = gtk::ListBoxRow::new();
     let utxo_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
     let tx_hash_label = gtk::Label::new(None);
+    let address_label = gtk::Label::new(None);
+    let output_type_label = gtk::Label::new(None);
     let side_label = gtk::Label::new(None);
     let value_label = gtk::Label::new(None);
+    let confirmations_label = gtk::Label::new(None);
+    let tx_label_label = gtk::Label::new(None);
     let action_label = gtk::Label::new(None);
 
     tx_hash_label.set_expand(true);
     tx_hash_label.set_markup("<b>Tx Hash</b>");
 
+    address_label.set_width_request(300);
+    address_label.set_markup("<b>Address</b>");
+
+    output_type_label.set_width_request(100);
+    output_type_label.set_markup("<b>Type</b>");
+
     side_label.set_width_request(92);
     side_label.set_markup("<b>Side</b>");
 
     value_label.set_width_request(128);
     value_label.set_markup("<b>Value</b>");
 
+    confirmations_label.set_width_request(100);
+    confirmations_label.set_markup("<b>Confirmations</b>");
+
+    tx_label_label.set_width_request(160);
+    tx_label_label.set_markup("<b>Label</b>");
+
     action_label.set_width_request(128);
     action_label.set_markup("<b>Action</b>");
 
     utxo_box.add(&tx_hash_label);
+    utxo_box.add(&address_label);
+    utxo_box.add(&output_type_label);
     utxo_box.add(&side_label);
     utxo_box.add(&value_label);
+    utxo_box.add(&confirmations_label);
+    utxo_box.add(&tx_label_label);
     utxo_box.add(&action_label);
 
     utxo_row.add(&utxo_box);
     utxo_row.show_all();
     list_box.add(&utxo_row);
 }
+
+/// Pide exportar el historial de la wallet activa en CSV al archivo recibido, y avisa al logger cuando termina.
+fn export_history(
+    node_queries: &NodeQueries,
+    logger_sender: &Sender<LogEntry>,
+    path: String,
+) -> Result<(), CustomError> {
+    let logger_sender = logger_sender.clone();
+    let exported_path = path.clone();
+    node_queries.request(
+        move |node_state| node_state.export_active_wallet_history(path),
+        move |result| match result {
+            Ok(()) => send_log(
+                &logger_sender,
+                Log::Message(format!("Transactions exported to {}", exported_path)),
+            ),
+            Err(error) => send_log(&logger_sender, Log::Error(error)),
+        },
+    )
+}
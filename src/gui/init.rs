@@ -1,4 +1,8 @@
-use std::sync::{mpsc, Arc, Mutex};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::{mpsc, Arc, Mutex},
+};
 
 use gtk::{
     glib::{self, Object, Receiver},
@@ -6,17 +10,35 @@ use gtk::{
 };
 
 use crate::{
-    error::CustomError, logger::Log, loops::node_action_loop::NodeAction, node_state::NodeState,
+    config::Settings,
+    error::CustomError,
+    logger::{LogEntry, LogLevel},
+    loops::node_action_loop::NodeAction,
+    node_state::NodeState,
+    notifications::Notification,
+    states::sync_progress_state::SyncMilestone,
 };
 
 use super::{
-    balance::GUIBalance, blocks::GUIBlocks, history::GUIHistory, logs::GUILogs,
-    transfer::GUITransfer, utxo::GUIUtxo, wallet::GUIWallet, window::GUIWindow,
+    balance::{GUIBalance, Refresh},
+    blocks::GUIBlocks,
+    contacts::GUIContacts,
+    history::GUIHistory,
+    logs::GUILogs,
+    node_query::NodeQueries,
+    peers::GUIPeers,
+    receive::GUIReceive,
+    settings::GUISettings,
+    theme,
+    transfer::GUITransfer,
+    utxo::GUIUtxo,
+    wallet::GUIWallet,
+    window::GUIWindow,
 };
 
 /// GUIEvents es un enum que contiene los eventos que se pueden recibir en el canal de eventos de la interfaz grafica.
 /// Los eventos son:
-/// - Log: Recibe un Log y lo muestra en la lista de logs.
+/// - Log: Recibe un LogEntry y lo muestra en la lista de logs.
 /// - WalletChanged: Se cambio la wallet activa.
 /// - WalletsUpdated: Se Actualizo alguna de las wallets cargadas.
 /// - NewPendingTx: Alguna de las wallets cargadas recibio una pending transaction.
@@ -24,8 +46,15 @@ use super::{
 /// - NewBlock: Llego un nuevo bloque.
 /// - TransactionSent: Se envio una transaccion del usuario.
 /// - NewHeaders: Hay nuevos Headers.
+/// - ContactsUpdated: Se modifico la libreta de direcciones.
+/// - TxLabelsUpdated: Se modifico la etiqueta de alguna transaccion.
+/// - ExchangeRatesUpdated: Se actualizo el precio de bitcoin en monedas fiat.
+/// - PaymentNotification: Alguna de las wallets cargadas recibio un pago o se confirmo, recibe la notificacion a mostrar.
+/// - PeersUpdated: Se conecto o desconecto un peer, o cambio la altura de su cadena.
+/// - SyncMilestone: El nodo alcanzo una etapa de la sincronizacion con la red.
+/// - PinMatrixRequest: La hardware wallet pide su PIN, recibe el sender por el que se envia el PIN ingresado o None si se cancela.
 pub enum GUIEvents {
-    Log(Log),
+    Log(LogEntry),
     WalletChanged,
     WalletsUpdated,
     NewPendingTx,
@@ -33,6 +62,13 @@ pub enum GUIEvents {
     NewBlock,
     TransactionSent,
     NewHeaders,
+    ContactsUpdated,
+    TxLabelsUpdated,
+    ExchangeRatesUpdated,
+    PaymentNotification(Notification),
+    PeersUpdated,
+    SyncMilestone(SyncMilestone),
+    PinMatrixRequest(mpsc::Sender<Option<String>>),
 }
 
 /// GUI es una estructura que contiene los elementos que manejan la interfaz grafica
@@ -46,6 +82,10 @@ pub enum GUIEvents {
 /// - utxo: GUIUtxo.
 /// - blocks: GUIBlocks.
 /// - transfer: GUITransfer.
+/// - receive: GUIReceive.
+/// - contacts: GUIContacts.
+/// - peers: GUIPeers.
+/// - settings: GUISettings.
 /// - window: GUIWindow.
 pub struct GUI {
     node_action_sender: mpsc::Sender<NodeAction>,
@@ -56,6 +96,10 @@ pub struct GUI {
     utxo: GUIUtxo,
     blocks: GUIBlocks,
     transfer: GUITransfer,
+    receive: GUIReceive,
+    contacts: GUIContacts,
+    peers: GUIPeers,
+    settings: GUISettings,
     window: GUIWindow,
 }
 
@@ -63,61 +107,100 @@ impl GUI {
     /// Inicializa la interfaz grafica.
     /// Crea los elementos de la interfaz grafica y los inicializa.
     /// Inicializa el ciclo de vida de la interfaz grafica (escuchar los GUIEvents).
+    /// Los cambios de las preferencias se guardan en el archivo de configuracion config_path.
     pub fn start(
         gui_receiver: Receiver<GUIEvents>,
         node_state_ref: Arc<Mutex<NodeState>>,
-        logger_sender: mpsc::Sender<Log>,
+        logger_sender: mpsc::Sender<LogEntry>,
         node_action_sender: mpsc::Sender<NodeAction>,
+        log_level: LogLevel,
+        settings: Settings,
+        config_path: String,
     ) -> Result<(), CustomError> {
         if gtk::init().is_err() {
             return Err(CustomError::CannotInitGUI);
         }
+        theme::load_style()?;
+        theme::set_dark_mode(settings.dark_mode);
 
         let glade_src = include_str!("gui.glade");
         let builder = gtk::Builder::from_string(glade_src);
+        let node_queries = NodeQueries::spawn(node_state_ref);
 
         let wallet = GUIWallet {
             builder: builder.clone(),
-            node_state_ref: node_state_ref.clone(),
+            node_queries: node_queries.clone(),
             logger_sender: logger_sender.clone(),
+            node_action_sender: node_action_sender.clone(),
         };
 
         let balance = GUIBalance {
             builder: builder.clone(),
-            node_state_ref: node_state_ref.clone(),
+            node_queries: node_queries.clone(),
             logger_sender: logger_sender.clone(),
-            available_balance: 0.0,
-            pending_balance: 0.0,
+            node_action_sender: node_action_sender.clone(),
+            refresh: Rc::new(Cell::new(Refresh::Idle)),
         };
 
         let logs = GUILogs {
             builder: builder.clone(),
             logger_sender: logger_sender.clone(),
+            log_level,
         };
 
         let history = GUIHistory {
             builder: builder.clone(),
             logger_sender: logger_sender.clone(),
-            node_state_ref: node_state_ref.clone(),
+            node_queries: node_queries.clone(),
         };
 
         let utxo = GUIUtxo {
             builder: builder.clone(),
             logger_sender: logger_sender.clone(),
-            node_state_ref: node_state_ref.clone(),
+            node_queries: node_queries.clone(),
         };
 
         let blocks = GUIBlocks {
             builder: builder.clone(),
             logger_sender: logger_sender.clone(),
-            node_state_ref: node_state_ref.clone(),
+            node_queries: node_queries.clone(),
             node_state_ready: false,
         };
 
         let transfer = GUITransfer {
             builder: builder.clone(),
             logger_sender: logger_sender.clone(),
-            node_state_ref,
+            node_queries: node_queries.clone(),
+            selected_inputs: Arc::new(Mutex::new(vec![])),
+            recipients: Rc::new(RefCell::new(vec![])),
+            contacts_store: gtk::ListStore::new(&[glib::Type::STRING, glib::Type::STRING]),
+        };
+
+        let receive = GUIReceive {
+            builder: builder.clone(),
+            logger_sender: logger_sender.clone(),
+            node_queries: node_queries.clone(),
+            qr_modules: Rc::new(RefCell::new(vec![])),
+        };
+
+        let contacts = GUIContacts {
+            builder: builder.clone(),
+            logger_sender: logger_sender.clone(),
+            node_queries: node_queries.clone(),
+        };
+
+        let peers = GUIPeers {
+            builder: builder.clone(),
+            logger_sender: logger_sender.clone(),
+            node_queries: node_queries.clone(),
+        };
+
+        let settings = GUISettings {
+            builder: builder.clone(),
+            logger_sender: logger_sender.clone(),
+            node_queries,
+            settings: Rc::new(RefCell::new(settings)),
+            config_path,
         };
 
         let window = GUIWindow {
@@ -134,6 +217,10 @@ impl GUI {
             utxo,
             blocks,
             transfer,
+            receive,
+            contacts,
+            peers,
+            settings,
             window,
         };
 
@@ -151,23 +238,36 @@ impl GUI {
         // initialize
         self.wallet.initialize()?;
         self.window.initialize()?;
+        self.logs.initialize()?;
+        self.receive.initialize()?;
+        self.contacts.initialize()?;
+        self.peers.initialize()?;
 
         // interactivity
         self.wallet.handle_interactivity()?;
+        self.logs.handle_interactivity()?;
         self.transfer
             .handle_interactivity(&self.node_action_sender)?;
+        self.receive.handle_interactivity()?;
+        self.contacts.handle_interactivity()?;
+        self.history.handle_interactivity()?;
+        self.settings.handle_interactivity()?;
+        self.window.handle_interactivity(&self.node_action_sender)?;
 
         Ok(())
     }
 
     fn gui_actions_loop(&self, gui_receiver: Receiver<GUIEvents>) -> Result<(), CustomError> {
-        let mut balance = self.balance.clone();
+        let balance = self.balance.clone();
         let logs = self.logs.clone();
         let mut transactions = self.history.clone();
         let window = self.window.clone();
         let mut transfer = self.transfer.clone();
         let mut utxo = self.utxo.clone();
         let mut blocks = self.blocks.clone();
+        let mut receive = self.receive.clone();
+        let mut contacts = self.contacts.clone();
+        let mut peers = self.peers.clone();
 
         gui_receiver.attach(None, move |message| {
             balance.handle_events(&message);
@@ -177,6 +277,9 @@ impl GUI {
             transfer.handle_events(&message);
             utxo.handle_events(&message);
             blocks.handle_events(&message);
+            receive.handle_events(&message);
+            contacts.handle_events(&message);
+            peers.handle_events(&message);
 
             glib::Continue(true)
         });
@@ -1,29 +1,98 @@
-use std::sync::mpsc;
+use std::{str::FromStr, sync::mpsc};
 
-use chrono::Local;
-use gtk::traits::{DialogExt, LabelExt, MessageDialogExt, WidgetExt};
+use gtk::traits::{
+    AdjustmentExt, ComboBoxExt, ContainerExt, DialogExt, LabelExt, ListBoxExt, MessageDialogExt,
+    ScrolledWindowExt, StyleContextExt, ToggleButtonExt, WidgetExt,
+};
 
 use crate::{
-    error::CustomError,
-    logger::{send_log, Log},
+    error::{CustomError, WalletError},
+    logger::{send_log, Log, LogEntry, LogLevel},
 };
 
 use super::init::{get_gui_element, GUIEvents};
 
+/// Cantidad maxima de logs que se muestran en la pestaña de logs, al superarla se descartan los mas antiguos.
+const MAX_LOG_ROWS: usize = 500;
+/// Niveles de los logs con el id del boton que los muestra u oculta en la pestaña de logs.
+const LEVEL_FILTERS: [(LogLevel, &str); 4] = [
+    (LogLevel::Debug, "logs-filter-debug"),
+    (LogLevel::Info, "logs-filter-info"),
+    (LogLevel::Warning, "logs-filter-warn"),
+    (LogLevel::Error, "logs-filter-error"),
+];
+
 #[derive(Clone)]
 /// GUILogs es una estructura que contiene los elementos de la interfaz grafica
-/// relacionados con los logs. Muestra los logs en la pantalla de carga y en la pantalla principal.
+/// relacionados con los logs. Muestra el ultimo log en la pantalla de carga y en la pantalla principal,
+/// y los logs recientes en la pestaña de logs, filtrados por nivel.
 /// Los elementos son:
 /// - builder: Builder de gtk.
 /// - logger_sender: Sender para enviar logs al logger.
+/// - log_level: Nivel de los logs con el que se inicio el logger.
 pub struct GUILogs {
     pub builder: gtk::Builder,
-    pub logger_sender: mpsc::Sender<Log>,
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub log_level: LogLevel,
 }
 
 impl GUILogs {
+    /// Muestra en el selector el nivel de los logs con el que se inicio el logger.
+    /// Filtra la lista de la pestaña de logs para mostrar solo los niveles cuyo boton esta activo.
+    pub fn initialize(&self) -> Result<(), CustomError> {
+        let log_level: gtk::ComboBoxText = get_gui_element(&self.builder, "log-level")?;
+        log_level.set_active_id(Some(&self.log_level.as_str().to_lowercase()));
+
+        let logs_list: gtk::ListBox = get_gui_element(&self.builder, "logs-list")?;
+        let mut filters = vec![];
+        for (level, id) in LEVEL_FILTERS {
+            let button: gtk::ToggleButton = get_gui_element(&self.builder, id)?;
+            filters.push((level, button));
+        }
+        logs_list.set_filter_func(Some(Box::new(move |row| {
+            filters.iter().any(|(level, button)| {
+                row.widget_name().as_str() == level.as_str() && button.is_active()
+            })
+        })));
+        Ok(())
+    }
+
+    /// Establece los callbacks de los elementos de la interfaz grafica.
+    /// Para el selector de nivel: Cambia el nivel minimo de los logs que se registran.
+    /// Para los botones de cada nivel: Vuelve a filtrar la lista de logs.
+    /// Para la lista de logs: Si esta activo el autoscroll, baja hasta el ultimo log cada vez que se agrega uno.
+    pub fn handle_interactivity(&self) -> Result<(), CustomError> {
+        let logs_list: gtk::ListBox = get_gui_element(&self.builder, "logs-list")?;
+        for (_, id) in LEVEL_FILTERS {
+            let button: gtk::ToggleButton = get_gui_element(&self.builder, id)?;
+            let logs_list = logs_list.clone();
+            button.connect_toggled(move |_| logs_list.invalidate_filter());
+        }
+
+        let logs_scroll: gtk::ScrolledWindow = get_gui_element(&self.builder, "logs-scroll")?;
+        let autoscroll: gtk::CheckButton = get_gui_element(&self.builder, "logs-autoscroll")?;
+        logs_scroll
+            .vadjustment()
+            .connect_changed(move |adjustment| {
+                if autoscroll.is_active() {
+                    adjustment.set_value(adjustment.upper() - adjustment.page_size());
+                }
+            });
+
+        let log_level: gtk::ComboBoxText = get_gui_element(&self.builder, "log-level")?;
+        let logger_sender = self.logger_sender.clone();
+        log_level.connect_changed(move |log_level| {
+            let Some(id) = log_level.active_id() else { return };
+            match LogLevel::from_str(&id) {
+                Ok(level) => send_log(&logger_sender, Log::SetLevel(level)),
+                Err(error) => send_log(&logger_sender, Log::Error(error)),
+            }
+        });
+        Ok(())
+    }
+
     /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
-    /// Para Log: Actualiza los logs en la interfaz.
+    /// Para Log: Actualiza los logs en la interfaz y lo agrega a la pestaña de logs.
     pub fn handle_events(&self, message: &GUIEvents) {
         let result = match message {
             GUIEvents::Log(log) => self.handle_log(log),
@@ -35,23 +104,24 @@ impl GUILogs {
         }
     }
 
-    fn handle_log(&self, log: &Log) -> Result<(), CustomError> {
+    fn handle_log(&self, entry: &LogEntry) -> Result<(), CustomError> {
         let logs: gtk::Label = get_gui_element(&self.builder, "logs")?;
         let load_screen_logs: gtk::Label = get_gui_element(&self.builder, "load-screen-logs")?;
         let dialog_error: gtk::MessageDialog = get_gui_element(&self.builder, "error-dialog")?;
+        self.add_log_row(entry)?;
 
-        match log {
-            Log::Message(string) => {
-                let current_time = Local::now();
-                let formatted_time = current_time.format("%Y-%m-%d %H:%M:%S");
-                let formatted_string = format!("[{}] {}", formatted_time, string);
+        match &entry.log {
+            Log::Debug(_) | Log::Message(_) | Log::Warning(_) | Log::SetLevel(_) => {
+                let formatted_time = entry.timestamp.format("%Y-%m-%d %H:%M:%S");
+                let formatted_string = format!("[{}] {}", formatted_time, entry.log.text());
                 logs.set_text(formatted_string.as_str());
                 load_screen_logs.set_text(formatted_string.as_str());
             }
             Log::Error(error) => {
                 dialog_error.set_text(Some("Error"));
                 match error {
-                    CustomError::Validation(ref explanation) => {
+                    CustomError::Validation(ref explanation)
+                    | CustomError::Wallet(WalletError::HardwareWallet(ref explanation)) => {
                         dialog_error.set_text(Some(error.description()));
                         dialog_error.set_secondary_text(Some(explanation.as_str()))
                     }
@@ -67,4 +137,28 @@ impl GUILogs {
 
         Ok(())
     }
+
+    /// Agrega el log al final de la lista de la pestaña de logs, descartando el mas antiguo si se supera MAX_LOG_ROWS.
+    /// El nombre de la fila es el nivel del log, que se usa para filtrarla.
+    fn add_log_row(&self, entry: &LogEntry) -> Result<(), CustomError> {
+        let Some(level) = entry.level() else { return Ok(()) };
+        let logs_list: gtk::ListBox = get_gui_element(&self.builder, "logs-list")?;
+
+        let label = gtk::Label::new(Some(&entry.line()));
+        label.set_halign(gtk::Align::Start);
+        label.set_selectable(true);
+        let row = gtk::ListBoxRow::new();
+        row.set_widget_name(level.as_str());
+        row.style_context()
+            .add_class(&format!("log-{}", level.as_str().to_lowercase()));
+        row.add(&label);
+        row.show_all();
+        logs_list.add(&row);
+
+        let rows = logs_list.children();
+        if rows.len() > MAX_LOG_ROWS {
+            logs_list.remove(&rows[0]);
+        }
+        Ok(())
+    }
 }
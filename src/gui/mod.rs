@@ -1,9 +1,15 @@
 pub mod balance;
 pub mod blocks;
+pub mod contacts;
 pub mod history;
 pub mod init;
 pub mod logs;
+pub mod node_query;
+pub mod peers;
+pub mod receive;
+pub mod settings;
 pub mod table_cells;
+pub mod theme;
 pub mod transfer;
 pub mod utxo;
 pub mod wallet;
@@ -0,0 +1,95 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use gtk::glib::{self, thread_guard::ThreadGuard};
+
+use crate::{
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+};
+
+/// Consulta al estado del nodo, que recibe el NodeState o el error al bloquearlo.
+type Query = Box<dyn FnOnce(Result<&mut NodeState, CustomError>) + Send>;
+
+#[derive(Clone)]
+/// NodeQueries es el canal de consultas al estado del nodo de la interfaz grafica.
+/// Las consultas se hacen en un thread aparte, en el orden en que se pidieron, para que el main loop de gtk
+/// no se bloquee esperando el lock del NodeState mientras el nodo procesa bloques, por ejemplo durante la descarga inicial.
+/// La respuesta de cada consulta se muestra desde el main loop con un callback de glib::idle_add.
+/// Los elementos son:
+/// - query_sender: Sender para enviar consultas al thread que las hace.
+pub struct NodeQueries {
+    query_sender: mpsc::Sender<Query>,
+}
+
+impl NodeQueries {
+    /// Inicializa el thread que hace las consultas, que termina cuando se liberan todos los NodeQueries.
+    pub fn spawn(node_state_ref: Arc<Mutex<NodeState>>) -> Self {
+        let (query_sender, query_receiver) = mpsc::channel::<Query>();
+        thread::spawn(move || {
+            for query in query_receiver {
+                match node_state_ref.lock() {
+                    Ok(mut node_state) => query(Ok(&mut *node_state)),
+                    Err(error) => query(Err(error.into())),
+                }
+            }
+        });
+        Self { query_sender }
+    }
+
+    /// Pide hacer una consulta al estado del nodo sin esperar la respuesta.
+    /// query se llama en el thread de las consultas y update con su resultado desde el main loop de gtk,
+    /// por lo que puede modificar los elementos de la interfaz grafica.
+    /// Solo debe llamarse desde el main loop de gtk.
+    /// Devuelve CustomError si el thread de las consultas termino.
+    pub fn request<T, Q, U>(&self, query: Q, update: U) -> Result<(), CustomError>
+    where
+        T: Send + 'static,
+        Q: FnOnce(&NodeState) -> Result<T, CustomError> + Send + 'static,
+        U: FnOnce(Result<T, CustomError>) + 'static,
+    {
+        self.request_mut(move |node_state| query(node_state), update)
+    }
+
+    /// Pide un cambio en el estado del nodo sin esperar a que termine, como request.
+    /// change se llama en el thread de las consultas con el NodeState mutable, por ejemplo para guardar un contacto,
+    /// y update con su resultado desde el main loop de gtk.
+    /// Solo debe llamarse desde el main loop de gtk.
+    /// Devuelve CustomError si el thread de las consultas termino.
+    pub fn request_mut<T, Q, U>(&self, change: Q, update: U) -> Result<(), CustomError>
+    where
+        T: Send + 'static,
+        Q: FnOnce(&mut NodeState) -> Result<T, CustomError> + Send + 'static,
+        U: FnOnce(Result<T, CustomError>) + 'static,
+    {
+        // update no puede cambiar de thread, por lo que viaja protegido y solo se usa al volver al main loop
+        let update = ThreadGuard::new(update);
+        self.query_sender
+            .send(Box::new(move |node_state| {
+                let response = node_state.and_then(change);
+                glib::idle_add_once(move || (update.into_inner())(response));
+            }))
+            .map_err(|_| CustomError::CannotSendMessageToChannel)
+    }
+
+    /// Pide un cambio en el estado del nodo cuyo unico resultado es un posible error, como request_mut.
+    /// El error del cambio, o el de pedirlo si el thread de las consultas termino, se envia al logger.
+    /// Solo debe llamarse desde el main loop de gtk.
+    pub fn change<Q>(&self, change: Q, logger_sender: &mpsc::Sender<LogEntry>)
+    where
+        Q: FnOnce(&mut NodeState) -> Result<(), CustomError> + Send + 'static,
+    {
+        let response_logger_sender = logger_sender.clone();
+        let requested = self.request_mut(change, move |result| {
+            if let Err(error) = result {
+                send_log(&response_logger_sender, Log::Error(error));
+            }
+        });
+        if let Err(error) = requested {
+            send_log(logger_sender, Log::Error(error));
+        }
+    }
+}
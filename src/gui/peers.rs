@@ -0,0 +1,215 @@
+use std::sync::mpsc::Sender;
+
+use gtk::{
+    glib,
+    traits::{ContainerExt, LabelExt, WidgetExt},
+    ListBox,
+};
+
+use crate::{
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+    states::peer_info_state::{MessageStats, NetTotals, PeerInfo},
+};
+
+use super::{
+    init::{get_gui_element, GUIEvents},
+    node_query::NodeQueries,
+};
+
+/// Tiempo en segundos entre cada actualizacion de la latencia y el trafico de los peers.
+const PEERS_REFRESH_INTERVAL: u32 = 5;
+/// Columnas de la lista de peers, con su ancho o None para las que se expanden.
+const PEER_COLUMNS: [(&str, Option<i32>); 7] = [
+    ("Address", None),
+    ("User Agent", None),
+    ("Version", Some(72)),
+    ("Ping", Some(72)),
+    ("Start Height", Some(92)),
+    ("Sent", Some(92)),
+    ("Received", Some(92)),
+];
+/// Columnas de la lista de trafico por tipo de mensaje, con su ancho o None para las que se expanden.
+const NET_TOTALS_COLUMNS: [(&str, Option<i32>); 5] = [
+    ("Command", None),
+    ("Messages Sent", Some(112)),
+    ("Sent", Some(92)),
+    ("Messages Received", Some(132)),
+    ("Received", Some(92)),
+];
+
+#[derive(Clone)]
+/// GUIPeers es una estructura que contiene los elementos de la interfaz grafica
+/// relacionados con los peers conectados. Los lista (direccion, user agent, version de protocolo, latencia,
+/// altura inicial y bytes enviados y recibidos) y muestra el trafico total del nodo y de cada tipo de mensaje.
+/// Los elementos son:
+/// - builder: Builder de gtk.
+/// - node_queries: Canal de consultas al estado del nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+pub struct GUIPeers {
+    pub logger_sender: Sender<LogEntry>,
+    pub builder: gtk::Builder,
+    pub node_queries: NodeQueries,
+}
+
+impl GUIPeers {
+    /// Actualiza periodicamente la lista de peers, ya que su latencia y su trafico cambian sin que se envie un GUIEvent.
+    pub fn initialize(&self) -> Result<(), CustomError> {
+        let peers = self.clone();
+        glib::timeout_add_seconds_local(PEERS_REFRESH_INTERVAL, move || {
+            if let Err(error) = peers.update_peers().and_then(|_| peers.update_net_totals()) {
+                send_log(&peers.logger_sender, Log::Error(error));
+            }
+            glib::Continue(true)
+        });
+        Ok(())
+    }
+
+    /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
+    /// Para PeersUpdated: Actualiza la lista de peers.
+    pub fn handle_events(&mut self, message: &GUIEvents) {
+        let result = match message {
+            GUIEvents::PeersUpdated => self.update_peers(),
+            _ => Ok(()),
+        };
+
+        if let Err(error) = result {
+            send_log(&self.logger_sender, Log::Error(error));
+        }
+    }
+
+    /// Pide los peers conectados al estado del nodo y los muestra cuando llega la respuesta.
+    fn update_peers(&self) -> Result<(), CustomError> {
+        let gui_peers = self.clone();
+        self.node_queries
+            .request(NodeState::get_peers_info, move |peers| {
+                if let Err(error) = peers.and_then(|peers| gui_peers.show_peers(peers)) {
+                    send_log(&gui_peers.logger_sender, Log::Error(error));
+                }
+            })
+    }
+
+    /// Muestra los peers recibidos, reemplazando las filas anteriores.
+    fn show_peers(&self, peers: Vec<PeerInfo>) -> Result<(), CustomError> {
+        let peers_list_box: gtk::ListBox = get_gui_element(&self.builder, "peers-list")?;
+        reset_table(&peers_list_box, &PEER_COLUMNS);
+        for peer in peers.iter() {
+            add_row(&peers_list_box, peer_cells(peer));
+        }
+        Ok(())
+    }
+
+    /// Pide el trafico del nodo al estado del nodo y lo muestra cuando llega la respuesta.
+    fn update_net_totals(&self) -> Result<(), CustomError> {
+        let gui_peers = self.clone();
+        self.node_queries
+            .request(NodeState::net_totals, move |totals| {
+                if let Err(error) = totals.and_then(|totals| gui_peers.show_net_totals(totals)) {
+                    send_log(&gui_peers.logger_sender, Log::Error(error));
+                }
+            })
+    }
+
+    /// Muestra el trafico total del nodo y el de cada tipo de mensaje.
+    fn show_net_totals(&self, totals: NetTotals) -> Result<(), CustomError> {
+        let totals_label: gtk::Label = get_gui_element(&self.builder, "net-totals")?;
+        let totals_list_box: gtk::ListBox = get_gui_element(&self.builder, "net-totals-list")?;
+
+        totals_label.set_text(&format!(
+            "Sent: {} - Received: {}",
+            format_bytes(totals.bytes_sent),
+            format_bytes(totals.bytes_received)
+        ));
+        reset_table(&totals_list_box, &NET_TOTALS_COLUMNS);
+        for (command, stats) in totals.by_command.iter() {
+            add_row(&totals_list_box, command_cells(command, stats));
+        }
+        Ok(())
+    }
+}
+
+/// Devuelve el texto de cada columna de un peer con su ancho, None para las columnas que se expanden.
+fn peer_cells(peer: &PeerInfo) -> Vec<(String, Option<i32>)> {
+    let ping = match peer.ping {
+        Some(ping) => format!("{} ms", ping.as_millis()),
+        None => "-".to_string(),
+    };
+    vec![
+        (peer.address.to_string(), None),
+        (peer.user_agent.clone(), None),
+        (peer.version.to_string(), Some(72)),
+        (ping, Some(72)),
+        (peer.start_height.to_string(), Some(92)),
+        (format_bytes(peer.bytes_sent), Some(92)),
+        (format_bytes(peer.bytes_received), Some(92)),
+    ]
+}
+
+/// Devuelve el texto de cada columna del trafico de un tipo de mensaje con su ancho.
+fn command_cells(command: &str, stats: &MessageStats) -> Vec<(String, Option<i32>)> {
+    vec![
+        (command.to_string(), None),
+        (stats.messages_sent.to_string(), Some(112)),
+        (format_bytes(stats.bytes_sent), Some(92)),
+        (stats.messages_received.to_string(), Some(132)),
+        (format_bytes(stats.bytes_received), Some(92)),
+    ]
+}
+
+/// Devuelve una cantidad de bytes como texto legible, por ejemplo "1.5 MB".
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, units[unit]),
+    }
+}
+
+/// Agrega una fila a la lista con el texto de cada columna.
+fn add_row(list_box: &ListBox, cells: Vec<(String, Option<i32>)>) {
+    let row = gtk::ListBoxRow::new();
+    let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+    for (text, width) in cells {
+        let label = gtk::Label::new(Some(&text));
+        set_column_width(&label, width);
+        row_box.add(&label);
+    }
+
+    row.add(&row_box);
+    row.show_all();
+    list_box.add(&row);
+}
+
+fn set_column_width(label: &gtk::Label, width: Option<i32>) {
+    match width {
+        Some(width) => label.set_width_request(width),
+        None => label.set_expand(true),
+    }
+}
+
+fn reset_table(list_box: &ListBox, columns: &[(&str, Option<i32>)]) {
+    list_box.foreach(|child| {
+        list_box.remove(child);
+    });
+    let row = gtk::ListBoxRow::new();
+    let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+    for (title, width) in columns {
+        let label = gtk::Label::new(None);
+        label.set_markup(&format!("<b>{}</b>", title));
+        set_column_width(&label, *width);
+        row_box.add(&label);
+    }
+
+    row.add(&row_box);
+    row.show_all();
+    list_box.add(&row);
+}
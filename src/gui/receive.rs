@@ -0,0 +1,154 @@
+use std::{cell::RefCell, rc::Rc, sync::mpsc::Sender};
+
+use gtk::traits::{ButtonExt, EntryExt, LabelExt, WidgetExt};
+
+use crate::{
+    error::{CustomError, ParseError, WalletError},
+    logger::{send_log, Log, LogEntry},
+    payment_uri::PaymentUri,
+};
+
+use super::{
+    init::{get_gui_element, GUIEvents},
+    node_query::NodeQueries,
+};
+
+/// Margen en modulos que se deja alrededor del codigo QR para que se pueda escanear.
+const QR_QUIET_ZONE: usize = 4;
+
+/// Modulos del codigo QR por fila, true si el modulo es oscuro.
+type QrModules = Rc<RefCell<Vec<Vec<bool>>>>;
+
+#[derive(Clone)]
+/// GUIReceive es una estructura que contiene los elementos de la interfaz grafica
+/// relacionados con recibir fondos. Muestra la URI de pago (BIP 21) de la direccion de recepcion
+/// de la wallet activa y su codigo QR, con el monto y el nombre opcionales.
+/// Los elementos son:
+/// - builder: Builder de gtk.
+/// - node_queries: Canal de consultas al estado del nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+/// - qr_modules: Modulos del codigo QR que se dibuja.
+pub struct GUIReceive {
+    pub logger_sender: Sender<LogEntry>,
+    pub builder: gtk::Builder,
+    pub node_queries: NodeQueries,
+    pub qr_modules: QrModules,
+}
+
+impl GUIReceive {
+    /// Establece como se dibuja el codigo QR.
+    pub fn initialize(&self) -> Result<(), CustomError> {
+        let drawing_area: gtk::DrawingArea = get_gui_element(&self.builder, "receive-qr")?;
+        let qr_modules = self.qr_modules.clone();
+
+        drawing_area.connect_draw(move |widget, context| {
+            let width = widget.allocated_width() as f64;
+            let height = widget.allocated_height() as f64;
+            context.set_source_rgb(1.0, 1.0, 1.0);
+            context.paint().ok();
+
+            let modules = qr_modules.borrow();
+            if modules.is_empty() {
+                return gtk::Inhibit(false);
+            }
+            let size = width.min(height);
+            let module_size = size / (modules.len() + 2 * QR_QUIET_ZONE) as f64;
+            let x_offset = (width - size) / 2.0 + QR_QUIET_ZONE as f64 * module_size;
+            let y_offset = (height - size) / 2.0 + QR_QUIET_ZONE as f64 * module_size;
+
+            context.set_source_rgb(0.0, 0.0, 0.0);
+            for (y, row) in modules.iter().enumerate() {
+                for (x, dark) in row.iter().enumerate() {
+                    if *dark {
+                        context.rectangle(
+                            x_offset + x as f64 * module_size,
+                            y_offset + y as f64 * module_size,
+                            module_size,
+                            module_size,
+                        );
+                    }
+                }
+            }
+            context.fill().ok();
+            gtk::Inhibit(false)
+        });
+        Ok(())
+    }
+
+    /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
+    /// Para WalletChanged, WalletsUpdated y NodeStateReady: Actualiza la URI de pago, ya que puede cambiar la direccion de recepcion.
+    pub fn handle_events(&mut self, message: &GUIEvents) {
+        let result = match message {
+            GUIEvents::WalletChanged | GUIEvents::WalletsUpdated | GUIEvents::NodeStateReady => {
+                self.update_payment_uri()
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(error) = result {
+            send_log(&self.logger_sender, Log::Error(error));
+        }
+    }
+
+    /// Establece los callbacks de los elementos de la interfaz grafica.
+    /// Para el boton de generar: Actualiza la URI de pago con el monto y el nombre ingresados.
+    pub fn handle_interactivity(&self) -> Result<(), CustomError> {
+        let generate_button: gtk::Button = get_gui_element(&self.builder, "receive-generate")?;
+        let receive = self.clone();
+
+        generate_button.connect_clicked(move |_| {
+            if let Err(error) = receive.update_payment_uri() {
+                send_log(&receive.logger_sender, Log::Error(error));
+            }
+        });
+        Ok(())
+    }
+
+    /// Pide la URI de pago con el monto y el nombre ingresados al estado del nodo y la muestra cuando llega la respuesta.
+    fn update_payment_uri(&self) -> Result<(), CustomError> {
+        let amount_entry: gtk::Entry = get_gui_element(&self.builder, "receive-amount")?;
+        let label_entry: gtk::Entry = get_gui_element(&self.builder, "receive-label")?;
+
+        let amount = match amount_entry.text().trim() {
+            "" => None,
+            amount => Some(
+                amount
+                    .parse::<u64>()
+                    .map_err(|_| CustomError::Parse(ParseError::InvalidValue))?,
+            ),
+        };
+        let label = Some(label_entry.text().trim().to_string());
+
+        let receive = self.clone();
+        self.node_queries.request(
+            move |node_state| node_state.get_active_wallet_payment_uri(amount, label),
+            move |uri| {
+                if let Err(error) = receive.show_payment_uri(uri) {
+                    send_log(&receive.logger_sender, Log::Error(error));
+                }
+            },
+        )
+    }
+
+    /// Muestra la URI de pago y su codigo QR. Si no hay una wallet activa, los deja vacios.
+    fn show_payment_uri(&self, uri: Result<PaymentUri, CustomError>) -> Result<(), CustomError> {
+        let uri_label: gtk::Label = get_gui_element(&self.builder, "receive-uri")?;
+        let drawing_area: gtk::DrawingArea = get_gui_element(&self.builder, "receive-qr")?;
+
+        let uri = match uri {
+            Ok(uri) => uri,
+            Err(CustomError::Wallet(WalletError::WalletNotFound)) => {
+                uri_label.set_text("");
+                self.qr_modules.borrow_mut().clear();
+                drawing_area.queue_draw();
+                return Ok(());
+            }
+            Err(error) => return Err(error),
+        };
+
+        *self.qr_modules.borrow_mut() = uri.qr_code()?;
+        uri_label.set_text(&uri.to_string());
+        drawing_area.queue_draw();
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::{cell::RefCell, net::SocketAddr, rc::Rc, str::FromStr, sync::mpsc};
+
+use gtk::traits::{ButtonExt, ComboBoxExt, DialogExt, EntryExt, ToggleButtonExt, WidgetExt};
+
+use crate::{
+    config::Settings,
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+    network::Network,
+    proxy,
+};
+
+use super::{init::get_gui_element, node_query::NodeQueries, theme};
+
+#[derive(Clone)]
+/// GUISettings es una estructura que contiene los elementos de la interfaz grafica
+/// relacionados con las preferencias del nodo. Muestra los valores de configuracion en uso y guarda los cambios
+/// en el archivo de configuracion. El fee maximo, las confirmaciones minimas, el proxy y el modo oscuro se aplican
+/// al guardarlos, el resto al reiniciar el nodo.
+/// Los elementos son:
+/// - builder: Builder de gtk.
+/// - logger_sender: Sender para enviar logs al logger.
+/// - node_queries: Canal de consultas al estado del nodo.
+/// - settings: Valores de configuracion en uso, actualizados cada vez que se guardan.
+/// - config_path: Path del archivo de configuracion con el que se inicio el nodo.
+pub struct GUISettings {
+    pub builder: gtk::Builder,
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub node_queries: NodeQueries,
+    pub settings: Rc<RefCell<Settings>>,
+    pub config_path: String,
+}
+
+impl GUISettings {
+    /// Establece los callbacks de los elementos de la interfaz grafica.
+    /// Para el boton de preferencias: Muestra el dialogo con los valores en uso.
+    /// Para el boton de guardar: Valida los valores ingresados, los guarda y cierra el dialogo.
+    /// Para el boton de cancelar: Cierra el dialogo sin guardar.
+    pub fn handle_interactivity(&self) -> Result<(), CustomError> {
+        let trigger: gtk::Button = get_gui_element(&self.builder, "settings-button")?;
+        let save: gtk::Button = get_gui_element(&self.builder, "settings-save")?;
+        let cancel: gtk::Button = get_gui_element(&self.builder, "settings-cancel")?;
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "settings-dialog")?;
+
+        let settings = self.clone();
+        let trigger_dialog = dialog.clone();
+        trigger.connect_clicked(move |_| {
+            if let Err(error) = settings.show_settings() {
+                send_log(&settings.logger_sender, Log::Error(error));
+                return;
+            }
+            trigger_dialog.run();
+            trigger_dialog.hide();
+        });
+
+        let settings = self.clone();
+        let save_dialog = dialog.clone();
+        save.connect_clicked(move |_| match settings.save_settings() {
+            Ok(()) => save_dialog.hide(),
+            Err(error) => send_log(&settings.logger_sender, Log::Error(error)),
+        });
+
+        cancel.connect_clicked(move |_| dialog.hide());
+        Ok(())
+    }
+
+    /// Completa los campos del dialogo con los valores en uso.
+    fn show_settings(&self) -> Result<(), CustomError> {
+        let settings = self.settings.borrow();
+        let network: gtk::ComboBoxText = get_gui_element(&self.builder, "settings-network")?;
+        network.set_active_id(Some(settings.network.name()));
+        self.entry("settings-data-dir")?
+            .set_text(&settings.store_path);
+        self.entry("settings-max-fee")?
+            .set_text(&settings.max_fee.to_string());
+        self.entry("settings-min-confirmations")?
+            .set_text(&settings.spend_min_confirmations.to_string());
+        self.entry("settings-proxy")?.set_text(
+            &settings
+                .proxy
+                .map(|proxy| proxy.to_string())
+                .unwrap_or_default(),
+        );
+        self.entry("settings-npeers")?
+            .set_text(&settings.npeers.to_string());
+        let dark_mode: gtk::CheckButton = get_gui_element(&self.builder, "settings-dark-mode")?;
+        dark_mode.set_active(settings.dark_mode);
+        Ok(())
+    }
+
+    /// Lee los valores ingresados en el dialogo.
+    /// Devuelve CustomError::Validation si alguno no es valido.
+    fn read_settings(&self) -> Result<Settings, CustomError> {
+        let network: gtk::ComboBoxText = get_gui_element(&self.builder, "settings-network")?;
+        let network = Network::from_str(network.active_id().as_deref().unwrap_or_default())?;
+
+        let store_path = self.entry("settings-data-dir")?.text().trim().to_string();
+        if store_path.is_empty() {
+            return Err(CustomError::Validation(
+                "The data directory cannot be empty".to_string(),
+            ));
+        }
+        let max_fee = parse_field(&self.entry("settings-max-fee")?, "Max fee")?;
+        let spend_min_confirmations = parse_field(
+            &self.entry("settings-min-confirmations")?,
+            "Min confirmations",
+        )?;
+        let proxy = match self.entry("settings-proxy")?.text().trim() {
+            "" => None,
+            proxy => Some(SocketAddr::from_str(proxy).map_err(|_| {
+                CustomError::Validation(format!("Invalid proxy {proxy}, expected ip:port"))
+            })?),
+        };
+        let npeers: u8 = parse_field(&self.entry("settings-npeers")?, "Connections")?;
+        if npeers == 0 {
+            return Err(CustomError::Validation(
+                "Connections must be at least 1".to_string(),
+            ));
+        }
+        let dark_mode: gtk::CheckButton = get_gui_element(&self.builder, "settings-dark-mode")?;
+
+        Ok(Settings {
+            network,
+            store_path,
+            max_fee,
+            spend_min_confirmations,
+            proxy,
+            npeers,
+            dark_mode: dark_mode.is_active(),
+        })
+    }
+
+    /// Guarda los valores ingresados en el archivo de configuracion y aplica los que no requieren reiniciar el nodo.
+    /// El fee maximo y las confirmaciones minimas se aplican al estado del nodo con NodeQueries.
+    fn save_settings(&self) -> Result<(), CustomError> {
+        let settings = self.read_settings()?;
+        settings.save(&self.config_path)?;
+
+        let (max_fee, spend_min_confirmations) =
+            (settings.max_fee, settings.spend_min_confirmations);
+        self.node_queries.change(
+            move |node_state| {
+                node_state.set_max_fee(max_fee);
+                node_state.set_spend_min_confirmations(spend_min_confirmations);
+                Ok(())
+            },
+            &self.logger_sender,
+        );
+        proxy::select(settings.proxy);
+        theme::set_dark_mode(settings.dark_mode);
+
+        let message = match settings.requires_restart(&self.settings.borrow()) {
+            true => "Settings saved, restart the node to apply the network, data directory and connections",
+            false => "Settings saved",
+        };
+        send_log(&self.logger_sender, Log::Message(message.to_string()));
+        self.settings.replace(settings);
+        Ok(())
+    }
+
+    fn entry(&self, name: &str) -> Result<gtk::Entry, CustomError> {
+        get_gui_element(&self.builder, name)
+    }
+}
+
+/// Lee el numero ingresado en un campo.
+/// Devuelve CustomError::Validation con el nombre del campo si no es un numero valido.
+fn parse_field<T: FromStr>(entry: &gtk::Entry, name: &str) -> Result<T, CustomError> {
+    entry
+        .text()
+        .trim()
+        .parse()
+        .map_err(|_| CustomError::Validation(format!("{name} must be a positive number")))
+}
@@ -1,23 +1,20 @@
-use std::sync::{mpsc::Sender, Arc, Mutex};
+use std::sync::mpsc::Sender;
 
 use chrono::{DateTime, Local, NaiveDateTime};
 use gtk::traits::{ButtonExt, ContainerExt, LabelExt, WidgetExt};
 
 use crate::{
-    logger::{send_log, Log},
-    node_state::NodeState,
-    structs::block_header::hash_as_string,
+    logger::{send_log, Log, LogEntry},
+    structs::{hash256::Hash256, script::classify::OutputType},
 };
 
+use super::node_query::NodeQueries;
+
 /// Genera un label formateado para un hash en formato hexadecimal y lo devuelve.
-pub fn tx_hash_label(mut tx_hash: Vec<u8>) -> gtk::Label {
+pub fn tx_hash_label(tx_hash: Hash256) -> gtk::Label {
     let tx_hash_label = gtk::Label::new(None);
 
-    tx_hash.reverse();
-    let mut tx_hash_string = hash_as_string(tx_hash.clone());
-    tx_hash_string.make_ascii_lowercase();
-
-    tx_hash_label.set_text(tx_hash_string.as_str());
+    tx_hash_label.set_text(tx_hash.to_string().as_str());
 
     tx_hash_label.set_expand(true);
 
@@ -55,13 +52,14 @@ pub fn value_label(value: i64) -> gtk::Label {
     value_label
 }
 
-/// Genera un boton para pedir el merkle proof de una transaccion y lo devuelve.
+/// Genera un boton para generar y verificar el merkle proof de una transaccion y lo devuelve.
 /// Si el bloque no esta en la base de datos, no se muestra el boton.
+/// El proof se genera y verifica con NodeQueries, y el resultado se envia al logger al terminar.
 pub fn merkle_proof_button(
-    block_hash: Option<Vec<u8>>,
-    tx_hash: Vec<u8>,
-    logger_sender: Sender<Log>,
-    node_state_ref: Arc<Mutex<NodeState>>,
+    block_hash: Option<Hash256>,
+    tx_hash: Hash256,
+    logger_sender: Sender<LogEntry>,
+    node_queries: NodeQueries,
 ) -> gtk::Box {
     let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
 
@@ -69,31 +67,39 @@ pub fn merkle_proof_button(
         let button = gtk::Button::new();
 
         button.set_label("Merkle Proof");
-        let block_hash_string = hash_as_string(block_hash);
         button.connect_clicked(move |_| {
-            let node_state = node_state_ref.lock().unwrap();
-            let block = match node_state.get_block(block_hash_string.clone()) {
-                Ok(block) => block,
-                Err(error) => {
-                    send_log(&logger_sender, Log::Error(error));
-                    return;
-                }
-            };
-            let (mp_flags, mp_hashes) = match block.generate_merkle_path(tx_hash.to_vec()) {
-                Ok((mp_flags, mp_hashes)) => (mp_flags, mp_hashes),
-                Err(error) => {
-                    send_log(&logger_sender, Log::Error(error));
-                    return;
-                }
-            };
-            send_log(
-                &logger_sender,
-                Log::Message(format!("Merkle Flags: {:?}", mp_flags)),
-            );
-            send_log(
-                &logger_sender,
-                Log::Message(format!("Merkle Hashes: {:?}", mp_hashes)),
+            let response_logger_sender = logger_sender.clone();
+            let requested = node_queries.request(
+                move |node_state| {
+                    let proof = node_state.get_merkle_proof(&block_hash, &tx_hash)?;
+                    let confirmations =
+                        node_state.verify_merkle_proof(&block_hash, &proof, &tx_hash)?;
+                    Ok((proof, confirmations))
+                },
+                move |response| match response {
+                    Ok((proof, confirmations)) => {
+                        send_log(
+                            &response_logger_sender,
+                            Log::Message(format!(
+                                "Merkle proof verified against block header, transaction has {} confirmations",
+                                confirmations
+                            )),
+                        );
+                        send_log(
+                            &response_logger_sender,
+                            Log::Message(format!("Merkle Flags: {:?}", proof.flags)),
+                        );
+                        send_log(
+                            &response_logger_sender,
+                            Log::Message(format!("Merkle Hashes: {:?}", proof.hashes)),
+                        );
+                    }
+                    Err(error) => send_log(&response_logger_sender, Log::Error(error)),
+                },
             );
+            if let Err(error) = requested {
+                send_log(&logger_sender, Log::Error(error));
+            }
         });
 
         button_box.add(&button);
@@ -103,6 +109,24 @@ pub fn merkle_proof_button(
     button_box
 }
 
+/// Genera un label con la direccion de la otra parte de una transaccion y lo devuelve.
+/// Si la direccion esta en la libreta de direcciones, muestra el nombre del contacto y la direccion queda como tooltip.
+pub fn address_label(address: Option<String>, contact_label: Option<String>) -> gtk::Label {
+    let address_label = gtk::Label::new(None);
+    match (contact_label, address) {
+        (Some(contact_label), address) => {
+            address_label.set_text(&contact_label);
+            address_label.set_tooltip_text(address.as_deref());
+        }
+        (None, Some(address)) => address_label.set_text(&address),
+        (None, None) => address_label.set_text("-"),
+    }
+
+    address_label.set_width_request(300);
+
+    address_label
+}
+
 /// Genera un label formateado que indica si se recibe o se envia en la transaccion.
 /// Si el valor es positivo, se recibe, sino se envia
 pub fn side_label(value: i64) -> gtk::Label {
@@ -125,3 +149,28 @@ pub fn number_label(value: i64) -> gtk::Label {
 
     number_label
 }
+
+/// Genera un label con las confirmaciones de una transaccion y lo devuelve.
+/// Si la transaccion esta pendiente, muestra Pending.
+pub fn confirmations_label(confirmations: Option<usize>) -> gtk::Label {
+    let confirmations_label = match confirmations {
+        Some(confirmations) => gtk::Label::new(Some(confirmations.to_string().as_str())),
+        None => gtk::Label::new(Some("Pending")),
+    };
+
+    confirmations_label.set_width_request(100);
+
+    confirmations_label
+}
+
+/// Genera un label con el tipo de output de una transaccion y lo devuelve.
+/// Si no se conoce el tipo, muestra un guion.
+pub fn output_type_label(output_type: Option<OutputType>) -> gtk::Label {
+    let output_type_label = gtk::Label::new(Some(
+        output_type.map_or("-", |output_type| output_type.name()),
+    ));
+
+    output_type_label.set_width_request(100);
+
+    output_type_label
+}
@@ -0,0 +1,29 @@
+use gtk::prelude::{CssProviderExt, ObjectExt};
+
+use crate::error::CustomError;
+
+/// Hoja de estilos de la interfaz.
+const STYLE: &str = include_str!("style.css");
+
+/// Carga la hoja de estilos de la interfaz para todas las ventanas, con prioridad sobre el tema de gtk.
+/// Devuelve CustomError si la hoja de estilos no es valida o no hay una pantalla.
+pub fn load_style() -> Result<(), CustomError> {
+    let provider = gtk::CssProvider::new();
+    provider
+        .load_from_data(STYLE.as_bytes())
+        .map_err(|_| CustomError::CannotInitGUI)?;
+    let screen = gtk::gdk::Screen::default().ok_or(CustomError::CannotInitGUI)?;
+    gtk::StyleContext::add_provider_for_screen(
+        &screen,
+        &provider,
+        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
+    Ok(())
+}
+
+/// Elige la variante oscura o clara del tema de gtk.
+pub fn set_dark_mode(dark_mode: bool) {
+    if let Some(settings) = gtk::Settings::default() {
+        settings.set_property("gtk-application-prefer-dark-theme", dark_mode);
+    }
+}
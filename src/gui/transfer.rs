@@ -1,42 +1,230 @@
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    rc::Rc,
+    str::FromStr,
     sync::{mpsc::Sender, Arc, Mutex},
 };
 
-use gtk::traits::{ButtonExt, DialogExt, EntryExt, LabelExt, WidgetExt};
+use gtk::{
+    prelude::{GtkListStoreExtManual, TreeModelExtManual},
+    traits::{
+        ButtonExt, ComboBoxExt, ComboBoxTextExt, ContainerExt, DialogExt, EntryCompletionExt,
+        EntryExt, GridExt, GtkListStoreExt, GtkWindowExt, LabelExt, ToggleButtonExt, WidgetExt,
+    },
+    DialogFlags, ResponseType,
+};
 
 use crate::{
-    error::CustomError,
-    logger::{send_log, Log},
+    address::Address,
+    error::{CustomError, ParseError, WalletError},
+    exchange_rate::ExchangeRates,
+    fee_estimator::FeePriority,
+    logger::{send_log, Log, LogEntry},
     loops::node_action_loop::NodeAction,
     node_state::NodeState,
+    payment_uri::PaymentUri,
+    states::utxo_state::UTXOValue,
+    structs::{
+        outpoint::OutPoint, script::classify::ScriptType, transaction_summary::TransactionSummary,
+    },
+    transaction_builder::{Fee, Payment},
+    utils::hex_to_bytes,
+};
+
+use super::{
+    init::{get_gui_element, GUIEvents},
+    node_query::NodeQueries,
+    table_cells::{number_label, output_type_label, tx_hash_label, value_label},
 };
 
-use super::init::{get_gui_element, GUIEvents};
+/// Filas del formulario de envio con los destinatarios del pago, en el orden en que se muestran.
+type Recipients = Rc<RefCell<Vec<Recipient>>>;
+
+const FEE_PRIORITIES: [(&str, &str, FeePriority); 3] = [
+    ("low", "Low priority", FeePriority::Low),
+    ("medium", "Medium priority", FeePriority::Medium),
+    ("high", "High priority", FeePriority::High),
+];
+
+/// Recipient es una fila del formulario de envio con uno de los destinatarios del pago.
+/// Los elementos son:
+/// - row: Fila que contiene los campos del destinatario.
+/// - label: Numero del destinatario.
+/// - pubkey: Campo de la direccion o el nombre del contacto.
+/// - value: Campo del monto en satoshis.
+/// - subtract_fee: Check para descontar el fee del monto del destinatario.
+pub struct Recipient {
+    pub row: gtk::Box,
+    pub label: gtk::Label,
+    pub pubkey: gtk::Entry,
+    pub value: gtk::Entry,
+    pub subtract_fee: gtk::CheckButton,
+}
+
+impl Recipient {
+    /// Lee los campos del destinatario, para resolverlos con el estado del nodo fuera del main loop de gtk.
+    /// Recibe el numero del destinatario en el formulario.
+    fn fields(&self, number: usize) -> RecipientFields {
+        RecipientFields {
+            number,
+            pubkey: self.pubkey.text().to_string(),
+            value: self.value.text().to_string(),
+            subtract_fee: self.subtract_fee.is_active(),
+        }
+    }
+}
+
+/// RecipientFields son los valores ingresados en la fila de un destinatario.
+/// Los elementos son:
+/// - number: Numero del destinatario en el formulario.
+/// - pubkey: Direccion o nombre del contacto ingresado.
+/// - value: Monto ingresado en satoshis.
+/// - subtract_fee: Indica si el fee se descuenta del monto del destinatario.
+struct RecipientFields {
+    number: usize,
+    pubkey: String,
+    value: String,
+    subtract_fee: bool,
+}
+
+/// FeeChoice es el fee elegido en el formulario de envio.
+/// - Fixed: Fee ingresado en satoshis.
+/// - Priority: Prioridad elegida, cuyo fee por byte se estima con el estado del nodo.
+#[derive(Clone, Copy)]
+enum FeeChoice {
+    Fixed(u64),
+    Priority(FeePriority),
+}
+
+/// PaymentForm son los valores del formulario de envio, leidos en el main loop de gtk.
+/// Se convierten en un Payment con el estado del nodo, que resuelve los contactos y el fee de la prioridad elegida.
+/// Los elementos son:
+/// - recipients: Campos de los destinatarios, en el orden del formulario.
+/// - fee: Fee elegido.
+/// - inputs: Outpoints elegidos en el coin control.
+/// - data: Datos del output OP_RETURN, si se ingresaron.
+struct PaymentForm {
+    recipients: Vec<RecipientFields>,
+    fee: FeeChoice,
+    inputs: Vec<OutPoint>,
+    data: Option<Vec<u8>>,
+}
+
+impl PaymentForm {
+    /// Lee el formulario de envio. Los destinatarios de la fila excluida, si se recibe alguna, se ignoran.
+    /// Devuelve CustomError si el fee o los datos OP_RETURN ingresados no son validos.
+    fn read(
+        builder: &gtk::Builder,
+        recipients: &[Recipient],
+        excluded_row: Option<&gtk::Box>,
+        selected_inputs: &Arc<Mutex<Vec<OutPoint>>>,
+    ) -> Result<Self, CustomError> {
+        Ok(Self {
+            recipients: recipients
+                .iter()
+                .enumerate()
+                .filter(|(_, recipient)| Some(&recipient.row) != excluded_row)
+                .map(|(i, recipient)| recipient.fields(i + 1))
+                .collect(),
+            fee: get_fee(builder)?,
+            inputs: selected_inputs.lock()?.clone(),
+            data: get_op_return_data(builder)?,
+        })
+    }
+
+    /// Crea el pago con los destinatarios del formulario, usando el estado del nodo para resolver los contactos
+    /// y el fee por byte de la prioridad elegida.
+    /// Devuelve CustomError si algun destinatario no es valido, indicando su numero.
+    fn into_payment(self, node_state: &NodeState) -> Result<Payment, CustomError> {
+        let subtract_fee_from = self
+            .recipients
+            .iter()
+            .find(|recipient| recipient.subtract_fee)
+            .map(|recipient| get_address(&recipient.pubkey, node_state))
+            .transpose()?;
+        Ok(Payment {
+            outputs: get_outputs(&self.recipients, node_state)?,
+            fee: match self.fee {
+                FeeChoice::Fixed(fee) => Fee::Fixed(fee),
+                FeeChoice::Priority(priority) => Fee::Rate(node_state.get_fee_rate(priority)),
+            },
+            inputs: self.inputs,
+            data: self.data,
+            subtract_fee_from,
+        })
+    }
+}
+
+/// CoinControlView son las UTXO que muestra el coin control, obtenidas en una misma consulta.
+/// Los elementos son:
+/// - wallet_utxo: UTXO que puede gastar la wallet activa, de mayor a menor valor.
+/// - locked: Indica para cada UTXO si esta bloqueada.
+struct CoinControlView {
+    wallet_utxo: Vec<(OutPoint, UTXOValue)>,
+    locked: Vec<bool>,
+}
 
-const TRANSFER_OUTPUTS: u8 = 3;
+impl CoinControlView {
+    /// Obtiene las UTXO de la wallet activa, sin las P2SH de sus multisigs. Si no hay una wallet activa no tiene UTXO.
+    fn from_node_state(node_state: &NodeState) -> Result<Self, CustomError> {
+        let mut wallet_utxo = match node_state.get_active_wallet_utxo() {
+            Ok(wallet_utxo) => wallet_utxo,
+            Err(CustomError::Wallet(WalletError::WalletNotFound)) => vec![],
+            Err(error) => return Err(error),
+        };
+        wallet_utxo.retain(|(_, value)| !value.tx_out.is_p2sh());
+        wallet_utxo.sort_by(|a, b| b.1.tx_out.value.cmp(&a.1.tx_out.value));
+        let locked = wallet_utxo
+            .iter()
+            .map(|(out_point, _)| node_state.is_utxo_locked(out_point))
+            .collect();
+        Ok(Self {
+            wallet_utxo,
+            locked,
+        })
+    }
+}
 
 #[derive(Clone)]
 /// GUITransfer es una estructura que contiene los elementos de la interfaz grafica
 /// relacionados con el envio de transacciones. Permite enviar transacciones a una o mas direcciones ingresando para cada una la pubkey y el monto, ademas del fee.
+/// Se pueden agregar tantos destinatarios como se quiera, que se pagan en una sola transaccion con un unico fee.
+/// En lugar de la pubkey puede ingresarse el nombre de un contacto de la libreta de direcciones, que se autocompleta,
+/// o pegarse una URI de pago (BIP 21) que completa la direccion, el monto y el nombre del destinatario.
+/// El fee puede ingresarse en satoshis o elegirse una prioridad, en cuyo caso se usa el fee por byte estimado a partir de los bloques recientes.
+/// En el coin control se pueden elegir las UTXO que financian la transaccion; si no se elige ninguna se seleccionan automaticamente.
+/// Opcionalmente se le puede poner una etiqueta a la transaccion, que se muestra en el historial.
 /// Los elementos son:
 /// - builder: Builder de gtk.
-/// - node_state_ref: Referencia al estado del nodo.
+/// - node_queries: Canal de consultas al estado del nodo.
 /// - logger_sender: Sender para enviar logs al logger.
+/// - selected_inputs: Outpoints elegidos en el coin control.
+/// - recipients: Destinatarios ingresados en el formulario de envio.
+/// - contacts_store: Contactos de la libreta de direcciones que autocompletan los campos de pubkey.
 pub struct GUITransfer {
-    pub logger_sender: Sender<Log>,
+    pub logger_sender: Sender<LogEntry>,
     pub builder: gtk::Builder,
-    pub node_state_ref: Arc<Mutex<NodeState>>,
+    pub node_queries: NodeQueries,
+    pub selected_inputs: Arc<Mutex<Vec<OutPoint>>>,
+    pub recipients: Recipients,
+    pub contacts_store: gtk::ListStore,
 }
 
 impl GUITransfer {
     /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
-    /// Para WalletChanged: Resetea los campos de la transaccion.
-    /// Para TransactionSent: Muestra un dialogo de transaccion enviada y resetea los campos.
+    /// Para WalletChanged: Resetea los campos de la transaccion y el coin control, y deshabilita el envio si la wallet es watch-only.
+    /// Para WalletsUpdated: Actualiza las UTXO del coin control, manteniendo las elegidas que siguen sin gastar.
+    /// Para TransactionSent: Muestra un dialogo de transaccion enviada y resetea los campos y el coin control.
+    /// Para NodeStateReady y NewBlock: Actualiza los fees estimados de cada prioridad.
+    /// Para ContactsUpdated: Actualiza los contactos que se autocompletan.
     pub fn handle_events(&mut self, message: &GUIEvents) {
         let result = match message {
-            GUIEvents::WalletChanged => self.reset_tx_fields(),
+            GUIEvents::WalletChanged => self.handle_wallet_changed(),
+            GUIEvents::WalletsUpdated => self.update_coin_control(),
             GUIEvents::TransactionSent => self.handle_sent_transaction(),
+            GUIEvents::NodeStateReady | GUIEvents::NewBlock => self.update_fee_estimates(),
+            GUIEvents::ContactsUpdated => self.update_contacts(),
+            GUIEvents::PinMatrixRequest(pin_sender) => self.show_pin_matrix(pin_sender.clone()),
             _ => Ok(()),
         };
 
@@ -45,114 +233,739 @@ impl GUITransfer {
         }
     }
     /// Establece los callbacks de los elementos de la interfaz grafica.
-    /// Para el boton de enviar transaccion: Envia la transaccion al nodo (o abre una ventana de error en caso de estar mal ingresada) con los valores leidos de la interfaz.
+    /// Para el boton de enviar transaccion: Envia la transaccion al nodo (o abre una ventana de error en caso de estar mal ingresada) con los valores leidos de la interfaz
+    /// y las UTXO elegidas en el coin control, junto a la etiqueta y los datos OP_RETURN ingresados.
+    /// Para el selector de prioridad: Habilita el campo del fee solo si se elige ingresarlo.
+    /// Para el boton de agregar destinatario: Agrega una fila para otro destinatario del pago.
+    /// Para los campos de pubkey: Autocompleta los contactos de la libreta de direcciones por nombre o direccion,
+    /// y si se pega una URI de pago completa el output con sus datos.
     pub fn handle_interactivity(
         &self,
         node_action_sender: &Sender<NodeAction>,
     ) -> Result<(), CustomError> {
+        self.add_recipient()?;
+        let add_recipient_button: gtk::Button = get_gui_element(&self.builder, "add-recipient")?;
+        let transfer = self.clone();
+        add_recipient_button.connect_clicked(move |_| {
+            if let Err(error) = transfer.add_recipient() {
+                send_log(&transfer.logger_sender, Log::Error(error));
+            }
+        });
+
         let send_button: gtk::Button = get_gui_element(&self.builder, "send-tx")?;
+        let fee_priority: gtk::ComboBoxText = get_gui_element(&self.builder, "tx-fee-priority")?;
+        let fee_entry: gtk::Entry = get_gui_element(&self.builder, "tx-fee")?;
+
+        fee_priority.connect_changed(move |fee_priority| {
+            fee_entry.set_sensitive(fee_priority.active_id().as_deref() == Some("custom"));
+        });
+
+        let node_action_sender = node_action_sender.clone();
+        let transfer = self.clone();
+        send_button.connect_clicked(move |_| {
+            if let Err(error) = transfer.send_transaction(&node_action_sender) {
+                send_log(&transfer.logger_sender, Log::Error(error));
+            }
+        });
+        self.update_contacts()
+    }
+
+    /// Pide el detalle de la transaccion del pago ingresado y, cuando llega, pide confirmarla antes de enviarla al nodo
+    /// junto a la etiqueta ingresada.
+    /// Devuelve CustomError si algun campo del formulario no es valido.
+    fn send_transaction(&self, node_action_sender: &Sender<NodeAction>) -> Result<(), CustomError> {
+        let form = PaymentForm::read(
+            &self.builder,
+            &self.recipients.borrow(),
+            None,
+            &self.selected_inputs,
+        )?;
+        let label = get_gui_element::<gtk::Entry>(&self.builder, "tx-label")?
+            .text()
+            .to_string();
 
-        let node_action_sender_clone = node_action_sender.clone();
         let builder = self.builder.clone();
         let logger_sender = self.logger_sender.clone();
+        let node_action_sender = node_action_sender.clone();
+        self.node_queries.request(
+            move |node_state| {
+                let payment = form.into_payment(node_state)?;
+                let summary = node_state.get_transaction_summary(payment.clone())?;
+                let fiat_value = node_state
+                    .get_exchange_rates()
+                    .map(|exchange_rates| {
+                        format!(" {}", exchange_rates.format(summary.amount() as i64))
+                    })
+                    .unwrap_or_default();
+                Ok((payment, summary, fiat_value))
+            },
+            move |response| {
+                let (payment, summary, fiat_value) = match response {
+                    Ok(response) => response,
+                    Err(error) => {
+                        send_log(&logger_sender, Log::Error(error));
+                        return;
+                    }
+                };
+                match confirm_transaction(&builder, &summary, &fiat_value) {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(error) => {
+                        send_log(&logger_sender, Log::Error(error));
+                        return;
+                    }
+                }
+                if node_action_sender
+                    .send(NodeAction::MakeTransaction((payment, label)))
+                    .is_err()
+                {
+                    send_log(
+                        &logger_sender,
+                        Log::Error(CustomError::CannotSendMessageToChannel),
+                    );
+                };
+            },
+        )
+    }
 
-        send_button.connect_clicked(move |_| {
-            let mut outputs = HashMap::new();
-            for i in 0..TRANSFER_OUTPUTS {
-                match get_output(&builder, i) {
-                    Ok(Some((pubkey, value))) => outputs.insert(pubkey, value),
-                    Ok(None) => continue,
+    fn handle_wallet_changed(&self) -> Result<(), CustomError> {
+        self.reset_tx_fields()?;
+        self.selected_inputs.lock()?.clear();
+        self.update_coin_control()?;
+
+        let send_button: gtk::Button = get_gui_element(&self.builder, "send-tx")?;
+        let logger_sender = self.logger_sender.clone();
+        self.node_queries.request(
+            |node_state| {
+                let watch_only = node_state
+                    .get_active_wallet()
+                    .map(|wallet| wallet.is_watch_only())
+                    .unwrap_or(false);
+                Ok((
+                    watch_only,
+                    node_state.active_wallet_signs_with_hardware_wallet(),
+                ))
+            },
+            move |response| {
+                let (watch_only, hardware_wallet) = match response {
+                    Ok(response) => response,
                     Err(error) => {
                         send_log(&logger_sender, Log::Error(error));
                         return;
                     }
                 };
+                send_button.set_sensitive(!watch_only || hardware_wallet);
+                send_button.set_tooltip_text(match (watch_only, hardware_wallet) {
+                    (true, true) => {
+                        Some("The transaction is signed on a connected Ledger or Trezor")
+                    }
+                    (true, false) => Some("Watch-only wallets cannot send transactions"),
+                    (false, _) => None,
+                });
+            },
+        )
+    }
+
+    fn reset_tx_fields(&self) -> Result<(), CustomError> {
+        let fee_entry: gtk::Entry = get_gui_element(&self.builder, "tx-fee")?;
+        fee_entry.set_text("0");
+        let label_entry: gtk::Entry = get_gui_element(&self.builder, "tx-label")?;
+        label_entry.set_text("");
+        let op_return_entry: gtk::Entry = get_gui_element(&self.builder, "tx-op-return")?;
+        op_return_entry.set_text("");
+        let information_label: gtk::Label = get_gui_element(&self.builder, "tx-information-label")?;
+        information_label.set_text("");
+
+        // queda un unico destinatario vacio
+        let recipients_box: gtk::Box = get_gui_element(&self.builder, "recipients")?;
+        for recipient in self.recipients.borrow_mut().drain(..) {
+            recipients_box.remove(&recipient.row);
+        }
+        self.add_recipient()
+    }
+
+    /// Agrega al formulario de envio una fila para otro destinatario, con autocompletado de contactos y URIs de pago.
+    /// Al quitar un destinatario se renumeran los demas; si es el unico solo se vacian sus campos.
+    fn add_recipient(&self) -> Result<(), CustomError> {
+        let recipients_box: gtk::Box = get_gui_element(&self.builder, "recipients")?;
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        let label = gtk::Label::new(None);
+        let pubkey = gtk::Entry::new();
+        pubkey.set_hexpand(true);
+        pubkey.set_placeholder_text(Some("PubKey or contact"));
+        pubkey.set_completion(Some(&contacts_completion(&pubkey)));
+        if let Some(completion) = pubkey.completion() {
+            completion.set_model(Some(&self.contacts_store));
+        }
+        let value = gtk::Entry::new();
+        value.set_placeholder_text(Some("Value (Sat)"));
+        value.set_input_purpose(gtk::InputPurpose::Digits);
+        let max_button = gtk::Button::with_label("Max");
+        max_button.set_tooltip_text(Some(
+            "Send everything left after the other recipients and the fee",
+        ));
+        let subtract_fee = gtk::CheckButton::with_label("Subtract fee");
+        subtract_fee.set_tooltip_text(Some(
+            "Deduct the fee from this amount instead of adding it on top",
+        ));
+        let remove_button = gtk::Button::with_label("Remove");
+
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+        let uri_value = value.clone();
+        pubkey.connect_changed(move |pubkey| {
+            if !PaymentUri::has_scheme(&pubkey.text()) {
+                return;
             }
+            if let Err(error) =
+                fill_output_from_uri(&node_queries, &logger_sender, pubkey, &uri_value)
+            {
+                send_log(&logger_sender, Log::Error(error));
+            }
+        });
 
-            let fee_entry: gtk::Entry = match get_gui_element(&builder, "tx-fee") {
-                Ok(fee_entry) => fee_entry,
-                Err(error) => {
-                    send_log(&logger_sender, Log::Error(error));
-                    return;
+        let transfer = self.clone();
+        let max_row = row.clone();
+        max_button.connect_clicked(move |_| {
+            if let Err(error) = transfer.fill_max_amount(&max_row) {
+                send_log(&transfer.logger_sender, Log::Error(error));
+            }
+        });
+
+        // el fee se descuenta de un unico destinatario
+        let recipients = self.recipients.clone();
+        subtract_fee.connect_toggled(move |subtract_fee| {
+            if !subtract_fee.is_active() {
+                return;
+            }
+            let Ok(recipients) = recipients.try_borrow() else { return };
+            for recipient in recipients.iter() {
+                if &recipient.subtract_fee != subtract_fee {
+                    recipient.subtract_fee.set_active(false);
                 }
-            };
+            }
+        });
+
+        let recipients = self.recipients.clone();
+        let removed_row = row.clone();
+        let removed_box = recipients_box.clone();
+        remove_button.connect_clicked(move |_| {
+            let mut recipients = recipients.borrow_mut();
+            if recipients.len() == 1 {
+                recipients[0].pubkey.set_text("");
+                recipients[0].pubkey.set_tooltip_text(None);
+                recipients[0].value.set_text("");
+                recipients[0].subtract_fee.set_active(false);
+                return;
+            }
+            recipients.retain(|recipient| recipient.row != removed_row);
+            removed_box.remove(&removed_row);
+            number_recipients(&recipients);
+        });
+
+        row.add(&label);
+        row.add(&pubkey);
+        row.add(&value);
+        row.add(&max_button);
+        row.add(&subtract_fee);
+        row.add(&remove_button);
+        row.show_all();
+        recipients_box.add(&row);
+
+        let mut recipients = self.recipients.borrow_mut();
+        recipients.push(Recipient {
+            row,
+            label,
+            pubkey,
+            value,
+            subtract_fee,
+        });
+        number_recipients(&recipients);
+        Ok(())
+    }
+
+    /// Lista las UTXO que puede gastar la wallet activa, de mayor a menor valor, con un check para elegirlas
+    /// y otro para bloquearlas, reservandolas para que no se gasten. Las UTXO bloqueadas no se pueden elegir.
+    /// Deja de elegir las que ya no estan entre las UTXO de la wallet.
+    fn update_coin_control(&self) -> Result<(), CustomError> {
+        let transfer = self.clone();
+        self.node_queries
+            .request(CoinControlView::from_node_state, move |response| {
+                if let Err(error) = response.and_then(|view| transfer.show_coin_control(view)) {
+                    send_log(&transfer.logger_sender, Log::Error(error));
+                }
+            })
+    }
+
+    fn show_coin_control(&self, view: CoinControlView) -> Result<(), CustomError> {
+        let list_box: gtk::ListBox = get_gui_element(&self.builder, "coin-control-list")?;
+        let label: gtk::Label = get_gui_element(&self.builder, "coin-control-label")?;
+        let CoinControlView {
+            wallet_utxo,
+            locked,
+        } = view;
 
-            match fee_entry.text().to_string().parse::<u64>() {
-                Ok(fee) => {
-                    if fee == 0 {
-                        send_log(&logger_sender, Log::Error(CustomError::InvalidFee));
+        let mut selected_inputs = self.selected_inputs.lock()?;
+        selected_inputs.retain(|selected| {
+            wallet_utxo
+                .iter()
+                .zip(&locked)
+                .any(|((out_point, _), locked)| out_point == selected && !locked)
+        });
+        update_coin_control_label(&label, &selected_inputs);
+
+        list_box.foreach(|child| {
+            list_box.remove(child);
+        });
+        for ((out_point, value), locked) in wallet_utxo.into_iter().zip(locked) {
+            let row = gtk::ListBoxRow::new();
+            let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+            let check_button = gtk::CheckButton::new();
+            check_button.set_active(selected_inputs.contains(&out_point));
+            check_button.set_sensitive(!locked);
+            let selected_inputs_ref = self.selected_inputs.clone();
+            let label = label.clone();
+            let check_out_point = out_point.clone();
+            check_button.connect_toggled(move |check_button| {
+                let Ok(mut selected_inputs) = selected_inputs_ref.lock() else { return };
+                selected_inputs.retain(|selected| selected != &check_out_point);
+                if check_button.is_active() {
+                    selected_inputs.push(check_out_point.clone());
+                }
+                update_coin_control_label(&label, &selected_inputs);
+            });
+
+            row_box.add(&check_button);
+            row_box.add(&tx_hash_label(out_point.hash));
+            row_box.add(&number_label(out_point.index as i64));
+            row_box.add(&output_type_label(Some(
+                ScriptType::classify(&value.tx_out.script_pubkey).output_type(),
+            )));
+            row_box.add(&value_label(value.tx_out.value as i64));
+            row_box.add(&self.lock_button(out_point, locked, &check_button));
+
+            row.add(&row_box);
+            row.show_all();
+            list_box.add(&row);
+        }
+        Ok(())
+    }
+
+    /// Genera el check para bloquear una UTXO del coin control.
+    /// Al bloquearla deja de estar elegida y no se puede volver a elegir hasta desbloquearla.
+    fn lock_button(
+        &self,
+        out_point: OutPoint,
+        locked: bool,
+        check_button: &gtk::CheckButton,
+    ) -> gtk::CheckButton {
+        let lock_button = gtk::CheckButton::with_label("Locked");
+        lock_button.set_active(locked);
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+        let check_button = check_button.clone();
+        lock_button.connect_toggled(move |lock_button| {
+            let lock = lock_button.is_active();
+            let out_point = out_point.clone();
+            let check_button = check_button.clone();
+            let response_logger_sender = logger_sender.clone();
+            let result = node_queries.request_mut(
+                move |node_state| match lock {
+                    true => node_state.lock_utxo(out_point),
+                    false => node_state.unlock_utxo(&out_point),
+                },
+                move |response| {
+                    if let Err(error) = response {
+                        send_log(&response_logger_sender, Log::Error(error));
+                        return;
+                    }
+                    if lock {
+                        check_button.set_active(false);
+                    }
+                    check_button.set_sensitive(!lock);
+                },
+            );
+            if let Err(error) = result {
+                send_log(&logger_sender, Log::Error(error));
+            }
+        });
+        lock_button
+    }
+
+    /// Completa el monto de un destinatario con el maximo que se le puede enviar con el fee elegido,
+    /// luego de pagar a los demas destinatarios, gastando las UTXO elegidas en el coin control o todas las de la wallet.
+    fn fill_max_amount(&self, row: &gtk::Box) -> Result<(), CustomError> {
+        let recipients = self.recipients.borrow();
+        let Some(recipient) = recipients.iter().find(|recipient| &recipient.row == row) else { return Ok(()) };
+        let pubkey = recipient.pubkey.text().to_string();
+        let value = recipient.value.clone();
+        let form = PaymentForm::read(&self.builder, &recipients, Some(row), &self.selected_inputs)?;
+
+        let logger_sender = self.logger_sender.clone();
+        self.node_queries.request(
+            move |node_state| {
+                let address = get_address(&pubkey, node_state)?;
+                let mut payment = form.into_payment(node_state)?;
+                payment.subtract_fee_from = None;
+                node_state.get_max_amount(payment, address)
+            },
+            move |response| match response {
+                Ok(amount) => value.set_text(&amount.to_string()),
+                Err(error) => send_log(&logger_sender, Log::Error(error)),
+            },
+        )
+    }
+
+    /// Actualiza los contactos de la libreta de direcciones que autocompletan los campos de pubkey.
+    fn update_contacts(&self) -> Result<(), CustomError> {
+        let contacts_store = self.contacts_store.clone();
+        let logger_sender = self.logger_sender.clone();
+        self.node_queries.request(
+            |node_state| Ok(node_state.get_contacts()),
+            move |response| {
+                let contacts = match response {
+                    Ok(contacts) => contacts,
+                    Err(error) => {
+                        send_log(&logger_sender, Log::Error(error));
                         return;
                     }
-                    if node_action_sender_clone
-                        .send(NodeAction::MakeTransaction((outputs, fee)))
-                        .is_err()
-                    {
-                        send_log(
-                            &logger_sender,
-                            Log::Error(CustomError::CannotSendMessageToChannel),
-                        );
-                    };
+                };
+                contacts_store.clear();
+                for contact in contacts {
+                    let display = format!("{} ({})", contact.label, contact.address);
+                    contacts_store
+                        .insert_with_values(None, &[(0, &display), (1, &contact.address)]);
                 }
-                Err(_) => {
-                    send_log(&logger_sender, Log::Error(CustomError::InvalidFee));
+            },
+        )
+    }
+
+    fn update_fee_estimates(&self) -> Result<(), CustomError> {
+        let fee_priority: gtk::ComboBoxText = get_gui_element(&self.builder, "tx-fee-priority")?;
+        let logger_sender = self.logger_sender.clone();
+        self.node_queries.request(
+            |node_state| {
+                Ok(FEE_PRIORITIES
+                    .iter()
+                    .map(|(_, _, priority)| node_state.get_fee_rate(*priority))
+                    .collect::<Vec<u64>>())
+            },
+            move |response| {
+                let fee_rates = match response {
+                    Ok(fee_rates) => fee_rates,
+                    Err(error) => {
+                        send_log(&logger_sender, Log::Error(error));
+                        return;
+                    }
+                };
+                let active_id = fee_priority.active_id();
+                fee_priority.remove_all();
+                fee_priority.append(Some("custom"), "Custom fee");
+                for ((id, label, _), fee_rate) in FEE_PRIORITIES.iter().zip(fee_rates) {
+                    fee_priority.append(Some(*id), &format!("{} ({} sat/vB)", label, fee_rate));
                 }
-            };
-        });
-        Ok(())
+                fee_priority.set_active_id(active_id.as_deref().or(Some("custom")));
+            },
+        )
     }
 
-    fn reset_tx_fields(&self) -> Result<(), CustomError> {
-        let fee_entry: gtk::Entry = get_gui_element(&self.builder, "tx-fee")?;
-        fee_entry.set_text("0");
+    /// Muestra el teclado para ingresar el PIN de la hardware wallet segun la matriz que muestra el dispositivo.
+    /// Envia por pin_sender las posiciones elegidas, o None si el usuario cancela.
+    fn show_pin_matrix(&self, pin_sender: Sender<Option<String>>) -> Result<(), CustomError> {
+        let main_window: gtk::Window = get_gui_element(&self.builder, "main-window")?;
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Unlock hardware wallet"),
+            Some(&main_window),
+            DialogFlags::MODAL,
+            &[
+                ("Cancel", ResponseType::Cancel),
+                ("Unlock", ResponseType::Accept),
+            ],
+        );
+        let pin = gtk::Entry::new();
+        pin.set_visibility(false);
+        pin.set_editable(false);
 
-        for i in 0..TRANSFER_OUTPUTS {
-            let receiver_pubkey: gtk::Entry =
-                get_gui_element(&self.builder, &format!("output-{}-pubkey", i))?;
-            receiver_pubkey.set_text("");
-            let receiver_value: gtk::Entry =
-                get_gui_element(&self.builder, &format!("output-{}-value", i))?;
-            receiver_value.set_text("");
-            let label: gtk::Label =
-                get_gui_element(&self.builder, &format!("tx-information-label{}", i))?;
-            label.set_text("");
+        // la matriz numera las posiciones como un teclado numerico, con el 7 arriba a la izquierda
+        let matrix = gtk::Grid::new();
+        for (index, position) in ["7", "8", "9", "4", "5", "6", "1", "2", "3"]
+            .into_iter()
+            .enumerate()
+        {
+            let button = gtk::Button::with_label("•");
+            let pin = pin.clone();
+            button.connect_clicked(move |_| pin.set_text(&format!("{}{}", pin.text(), position)));
+            matrix.attach(&button, (index % 3) as i32, (index / 3) as i32, 1, 1);
         }
+
+        let content = dialog.content_area();
+        content.add(&gtk::Label::new(Some(
+            "Enter the PIN following the layout shown on the device",
+        )));
+        content.add(&matrix);
+        content.add(&pin);
+
+        dialog.connect_response(move |dialog, response| {
+            let entered = (response == ResponseType::Accept).then(|| pin.text().to_string());
+            let _ = pin_sender.send(entered);
+            dialog.close();
+        });
+        dialog.show_all();
         Ok(())
     }
 
     fn handle_sent_transaction(&self) -> Result<(), CustomError> {
-        let dialog: gtk::MessageDialog = get_gui_element(&self.builder, "successful-tx-dialog")?;
+        let recipients: Vec<RecipientFields> = self
+            .recipients
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(i, recipient)| recipient.fields(i + 1))
+            .collect();
 
-        for i in 0..TRANSFER_OUTPUTS {
-            let label: gtk::Label =
-                get_gui_element(&self.builder, &format!("tx-information-label{}", i))?;
-            if let Ok(Some((pubkey, value))) = get_output(&self.builder, i) {
-                label.set_text(&format!("Transaction of {} sent to: {}", value, pubkey));
-            };
-        }
+        let transfer = self.clone();
+        self.node_queries.request(
+            move |node_state| {
+                let outputs: Vec<(String, u64)> = recipients
+                    .iter()
+                    .filter_map(|recipient| get_output(recipient, node_state).ok().flatten())
+                    .collect();
+                Ok((outputs, node_state.get_exchange_rates()))
+            },
+            move |response| {
+                if let Err(error) = response.and_then(|(outputs, exchange_rates)| {
+                    transfer.show_sent_transaction(outputs, exchange_rates)
+                }) {
+                    send_log(&transfer.logger_sender, Log::Error(error));
+                }
+            },
+        )
+    }
+
+    /// Muestra el dialogo de transaccion enviada con el monto de cada output, y resetea los campos y el coin control.
+    fn show_sent_transaction(
+        &self,
+        outputs: Vec<(String, u64)>,
+        exchange_rates: Option<ExchangeRates>,
+    ) -> Result<(), CustomError> {
+        let dialog: gtk::MessageDialog = get_gui_element(&self.builder, "successful-tx-dialog")?;
+        let label: gtk::Label = get_gui_element(&self.builder, "tx-information-label")?;
+        let information: Vec<String> = outputs
+            .into_iter()
+            .map(|(pubkey, value)| {
+                let fiat_value = exchange_rates
+                    .map(|exchange_rates| format!(" ({})", exchange_rates.format(value as i64)))
+                    .unwrap_or_default();
+                format!("Transaction of {}{} sent to: {}", value, fiat_value, pubkey)
+            })
+            .collect();
+        label.set_text(&information.join("\n"));
         dialog.run();
         dialog.hide();
 
         self.reset_tx_fields()?;
-        Ok(())
+        self.selected_inputs.lock()?.clear();
+        self.update_coin_control()
+    }
+}
+
+/// Devuelve el fee elegido: el ingresado en satoshis, o la prioridad elegida.
+fn get_fee(builder: &gtk::Builder) -> Result<FeeChoice, CustomError> {
+    let fee_priority: gtk::ComboBoxText = get_gui_element(builder, "tx-fee-priority")?;
+    let active_id = fee_priority.active_id();
+    if let Some((_, _, priority)) = FEE_PRIORITIES
+        .iter()
+        .find(|(id, _, _)| active_id.as_deref() == Some(*id))
+    {
+        return Ok(FeeChoice::Priority(*priority));
+    }
+
+    let fee_entry: gtk::Entry = get_gui_element(builder, "tx-fee")?;
+    match fee_entry.text().to_string().parse::<u64>() {
+        Ok(fee) if fee > 0 => Ok(FeeChoice::Fixed(fee)),
+        _ => Err(CustomError::Wallet(WalletError::InvalidFee)),
     }
 }
 
-fn get_output(builder: &gtk::Builder, i: u8) -> Result<Option<(String, u64)>, CustomError> {
-    let pubkey: gtk::Entry = get_gui_element(builder, &format!("output-{}-pubkey", i))?;
-    let value: gtk::Entry = get_gui_element(builder, &format!("output-{}-value", i))?;
+/// Devuelve los datos a agregar en un output OP_RETURN, ingresados como texto o en hexadecimal segun el formato elegido.
+/// Devuelve None si no se ingreso nada, y CustomError si el hexadecimal no es valido.
+fn get_op_return_data(builder: &gtk::Builder) -> Result<Option<Vec<u8>>, CustomError> {
+    let data_entry: gtk::Entry = get_gui_element(builder, "tx-op-return")?;
+    let data_format: gtk::ComboBoxText = get_gui_element(builder, "tx-op-return-format")?;
+    let text = data_entry.text().to_string();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    match data_format.active_id().as_deref() {
+        Some("hex") => hex_to_bytes(text.trim()).map(Some).ok_or_else(|| {
+            CustomError::Validation(String::from("OP_RETURN data is not valid hex"))
+        }),
+        _ => Ok(Some(text.into_bytes())),
+    }
+}
+
+/// Muestra en el titulo del coin control cuantas UTXO se eligieron, o que se seleccionan automaticamente si no hay ninguna.
+fn update_coin_control_label(label: &gtk::Label, selected_inputs: &[OutPoint]) {
+    match selected_inputs.len() {
+        0 => label.set_text("Coin control (automatic selection)"),
+        1 => label.set_text("Coin control (1 output selected)"),
+        selected => label.set_text(&format!("Coin control ({} outputs selected)", selected)),
+    }
+}
+
+/// Genera el autocompletado de contactos de un campo de pubkey.
+/// Muestra los contactos cuyo nombre o direccion comienzan con el texto ingresado, y al elegir uno completa su direccion.
+fn contacts_completion(entry: &gtk::Entry) -> gtk::EntryCompletion {
+    let completion = gtk::EntryCompletion::new();
+    completion.set_text_column(0);
+    completion.set_minimum_key_length(1);
+    completion.set_match_func(|completion, key, iter| {
+        let Some(model) = completion.model() else { return false };
+        let display = model.get::<String>(iter, 0).to_lowercase();
+        let address = model.get::<String>(iter, 1).to_lowercase();
+        display.starts_with(key) || address.starts_with(key)
+    });
+
+    let entry = entry.clone();
+    completion.connect_match_selected(move |_, model, iter| {
+        entry.set_text(&model.get::<String>(iter, 1));
+        gtk::Inhibit(true)
+    });
+    completion
+}
+
+/// Completa un output con los datos de la URI de pago ingresada en su campo de pubkey.
+/// Si la direccion es de un contacto se muestra su nombre, si no se muestra la direccion con el nombre de la URI como tooltip.
+/// Devuelve CustomError si la URI no es valida.
+fn fill_output_from_uri(
+    node_queries: &NodeQueries,
+    logger_sender: &Sender<LogEntry>,
+    receiver_pubkey: &gtk::Entry,
+    receiver_value: &gtk::Entry,
+) -> Result<(), CustomError> {
+    let uri = receiver_pubkey.text().trim().parse::<PaymentUri>()?;
+    let address = uri.address.clone();
+    let logger_sender = logger_sender.clone();
+    let receiver_pubkey = receiver_pubkey.clone();
+    let receiver_value = receiver_value.clone();
+    node_queries.request(
+        move |node_state| Ok(node_state.get_contact_label(&address)),
+        move |response| {
+            let contact_label = match response {
+                Ok(contact_label) => contact_label,
+                Err(error) => {
+                    send_log(&logger_sender, Log::Error(error));
+                    return;
+                }
+            };
+            match contact_label {
+                Some(contact_label) => {
+                    receiver_pubkey.set_text(&contact_label);
+                    receiver_pubkey.set_tooltip_text(Some(&uri.address));
+                }
+                None => {
+                    receiver_pubkey.set_text(&uri.address);
+                    receiver_pubkey.set_tooltip_text(uri.label.as_deref());
+                }
+            }
+            if let Some(amount) = uri.amount {
+                receiver_value.set_text(&amount.to_string());
+            }
+        },
+    )
+}
+
+/// Muestra el detalle de la transaccion de un pago, con su monto en moneda fiat si se conoce, y pide confirmarla antes de enviarla.
+/// Devuelve true si el usuario la confirmo.
+fn confirm_transaction(
+    builder: &gtk::Builder,
+    summary: &TransactionSummary,
+    fiat_value: &str,
+) -> Result<bool, CustomError> {
+    let dialog: gtk::MessageDialog = get_gui_element(builder, "confirm-tx-dialog")?;
+    let label: gtk::Label = get_gui_element(builder, "confirm-tx-label")?;
+    label.set_text(&format!(
+        "Sending {} sat{}\n\n{}",
+        summary.amount(),
+        fiat_value,
+        summary
+    ));
+    let response = dialog.run();
+    dialog.hide();
+    Ok(response == ResponseType::Ok)
+}
+
+/// Muestra el numero de cada destinatario segun su posicion en el formulario.
+fn number_recipients(recipients: &[Recipient]) {
+    for (i, recipient) in recipients.iter().enumerate() {
+        recipient.label.set_text(&format!("Receiver {}", i + 1));
+    }
+}
+
+/// Devuelve los outputs de todos los destinatarios ingresados, en el orden del formulario.
+/// Los destinatarios vacios se ignoran.
+/// Devuelve CustomError si algun destinatario no es valido o repite la direccion de otro, indicando su numero.
+fn get_outputs(
+    recipients: &[RecipientFields],
+    node_state: &NodeState,
+) -> Result<Vec<(String, u64)>, CustomError> {
+    let mut outputs: Vec<(String, u64)> = vec![];
+    for recipient in recipients {
+        let output = get_output(recipient, node_state).map_err(|error| {
+            let reason = match error {
+                CustomError::Validation(reason) => reason,
+                error => error.description().to_string(),
+            };
+            CustomError::Validation(format!("Receiver {}: {}", recipient.number, reason))
+        })?;
+        if let Some((address, value)) = output {
+            if outputs
+                .iter()
+                .any(|(other_address, _)| *other_address == address)
+            {
+                return Err(CustomError::Validation(format!(
+                    "Receiver {}: the address {} already receives another output",
+                    recipient.number, address
+                )));
+            }
+            outputs.push((address, value));
+        }
+    }
+    Ok(outputs)
+}
+
+/// Devuelve la direccion ingresada en un campo de pubkey.
+/// Si en lugar de una direccion se ingreso el nombre de un contacto, se usa su direccion.
+/// Devuelve CustomError::Validation con el motivo si no es una direccion valida de la red del nodo ni un contacto.
+fn get_address(text: &str, node_state: &NodeState) -> Result<String, CustomError> {
+    match Address::from_str(text) {
+        Ok(_) => Ok(text.trim().to_string()),
+        Err(error) => node_state
+            .get_contact(text.trim())
+            .map(|contact| contact.address)
+            .ok_or(error),
+    }
+}
 
-    if pubkey.text().to_string().is_empty() && value.text().to_string().is_empty() {
+/// Devuelve la direccion y el monto de un destinatario.
+/// Si en lugar de una direccion se ingreso el nombre de un contacto, se usa su direccion.
+fn get_output(
+    recipient: &RecipientFields,
+    node_state: &NodeState,
+) -> Result<Option<(String, u64)>, CustomError> {
+    if recipient.pubkey.is_empty() && recipient.value.is_empty() {
         return Ok(None);
     }
-    if pubkey.text().to_string().len() != 34 || value.text().to_string().is_empty() {
-        return Err(CustomError::InvalidTransferFields);
+    let address = get_address(&recipient.pubkey, node_state)?;
+    if recipient.value.is_empty() {
+        return Err(CustomError::Wallet(WalletError::InvalidTransferFields));
     }
 
-    let value = value
-        .text()
-        .to_string()
+    let value = recipient
+        .value
         .parse::<u64>()
-        .map_err(|_| CustomError::InvalidValue)?;
+        .map_err(|_| CustomError::Parse(ParseError::InvalidValue))?;
 
-    Ok(Some((pubkey.text().to_string(), value)))
+    Ok(Some((address, value)))
 }
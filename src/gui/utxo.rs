@@ -1,4 +1,4 @@
-use std::sync::{mpsc::Sender, Arc, Mutex, MutexGuard};
+use std::sync::mpsc::Sender;
 
 use gtk::{
     traits::{ContainerExt, LabelExt, WidgetExt},
@@ -7,7 +7,7 @@ use gtk::{
 
 use crate::{
     error::CustomError,
-    logger::{send_log, Log},
+    logger::{send_log, Log, LogEntry},
     node_state::NodeState,
     states::utxo_state::UTXOValue,
     structs::outpoint::OutPoint,
@@ -15,6 +15,7 @@ use crate::{
 
 use super::{
     init::{get_gui_element, GUIEvents},
+    node_query::NodeQueries,
     table_cells::{merkle_proof_button, time_label, tx_hash_label, value_label},
 };
 
@@ -23,12 +24,12 @@ use super::{
 /// relacionados con los UTXO de una wallet y los lista (tx hash, fecha de creacion, valor y pedir el merkle proof de esa tx).
 /// Los elementos son:
 /// - builder: Builder de gtk.
-/// - node_state_ref: Referencia al estado del nodo.
+/// - node_queries: Canal de consultas al estado del nodo.
 /// - logger_sender: Sender para enviar logs al logger.
 pub struct GUIUtxo {
-    pub logger_sender: Sender<Log>,
+    pub logger_sender: Sender<LogEntry>,
     pub builder: gtk::Builder,
-    pub node_state_ref: Arc<Mutex<NodeState>>,
+    pub node_queries: NodeQueries,
 }
 
 impl GUIUtxo {
@@ -47,25 +48,34 @@ impl GUIUtxo {
         }
     }
 
+    /// Pide las UTXO de la wallet activa al estado del nodo y las muestra cuando llega la respuesta.
     fn update_utxo(&self) -> Result<(), CustomError> {
-        let utxo_list_box: gtk::ListBox = get_gui_element(&self.builder, "utxo-list")?;
-        let node_state_ref_clone = self.node_state_ref.clone();
-        let node_state = node_state_ref_clone.lock()?;
-        let wallet_utxo = get_wallet_sorted_utxo(node_state)?;
+        let utxo = self.clone();
+        self.node_queries
+            .request(get_wallet_sorted_utxo, move |wallet_utxo| {
+                if let Err(error) = wallet_utxo.and_then(|wallet_utxo| utxo.show_utxo(wallet_utxo))
+                {
+                    send_log(&utxo.logger_sender, Log::Error(error));
+                }
+            })
+    }
 
+    /// Muestra las UTXO recibidas, reemplazando las filas anteriores.
+    fn show_utxo(&self, wallet_utxo: Vec<(OutPoint, UTXOValue)>) -> Result<(), CustomError> {
+        let utxo_list_box: gtk::ListBox = get_gui_element(&self.builder, "utxo-list")?;
         reset_table(&utxo_list_box);
         for (out_point, utxo_value) in wallet_utxo.iter() {
             let utxo_row = gtk::ListBoxRow::new();
             let utxo_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
 
-            utxo_box.add(&tx_hash_label(out_point.hash.clone()));
+            utxo_box.add(&tx_hash_label(out_point.hash));
             utxo_box.add(&time_label(utxo_value.block_timestamp));
             utxo_box.add(&value_label(utxo_value.tx_out.value as i64));
             utxo_box.add(&merkle_proof_button(
-                Some(utxo_value.block_hash.clone()),
-                out_point.hash.clone(),
+                Some(utxo_value.block_hash),
+                out_point.hash,
                 self.logger_sender.clone(),
-                self.node_state_ref.clone(),
+                self.node_queries.clone(),
             ));
 
             utxo_row.add(&utxo_box);
@@ -76,8 +86,9 @@ impl GUIUtxo {
     }
 }
 
+/// Devuelve las UTXO de la wallet activa, de la mas nueva a la mas vieja.
 fn get_wallet_sorted_utxo(
-    node_state: MutexGuard<'_, NodeState>,
+    node_state: &NodeState,
 ) -> Result<Vec<(OutPoint, UTXOValue)>, CustomError> {
     let mut wallet_utxo = node_state.get_active_wallet_utxo()?;
     wallet_utxo.sort_by(|a, b| {
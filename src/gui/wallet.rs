@@ -1,22 +1,36 @@
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::mpsc;
 
-use gtk::traits::{ButtonExt, ComboBoxExt, ComboBoxTextExt, DialogExt, EntryExt, WidgetExt};
+use gtk::{
+    traits::{
+        ButtonExt, ComboBoxExt, ComboBoxTextExt, DialogExt, EntryExt, FileChooserExt,
+        GtkMenuItemExt, GtkWindowExt, LabelExt, ToggleButtonExt, WidgetExt,
+    },
+    FileChooserAction, ResponseType,
+};
 
 use crate::{
     error::CustomError,
-    logger::{send_log, Log},
-    node_state::NodeState,
+    logger::{send_log, Log, LogEntry},
+    loops::node_action_loop::NodeAction,
+    structs::transaction_details::decode_raw_transaction_hex,
+    wallet::verify_message,
 };
 
-use super::init::get_gui_element;
+use super::{init::get_gui_element, node_query::NodeQueries};
 
 #[derive(Clone)]
 /// GUIWallet es una estructura que contiene los elementos de la interfaz grafica
 /// relacionados con la billetera. Permite agregar y cambiar de wallet y muestra la wallet activa.
+/// Los elementos son:
+/// - builder: Builder de la interfaz grafica.
+/// - node_queries: Canal de consultas al estado del nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+/// - node_action_sender: Sender para pedirle al nodo que barra los fondos de una private key importada.
 pub struct GUIWallet {
     pub builder: gtk::Builder,
-    pub node_state_ref: Arc<Mutex<NodeState>>,
-    pub logger_sender: mpsc::Sender<Log>,
+    pub node_queries: NodeQueries,
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub node_action_sender: mpsc::Sender<NodeAction>,
 }
 
 impl GUIWallet {
@@ -25,7 +39,7 @@ impl GUIWallet {
         let select_wallet_cb: gtk::ComboBoxText =
             get_gui_element(&self.builder, "select-wallet-combo-box")?;
 
-        update_wallet_combo_box(self.node_state_ref.clone(), select_wallet_cb)?;
+        update_wallet_combo_box(&self.node_queries, select_wallet_cb, &self.logger_sender);
 
         Ok(())
     }
@@ -35,29 +49,71 @@ impl GUIWallet {
     /// - handle_add_wallet_trigger: Muestra el dialogo para agregar una wallet.
     /// - handle_add_wallet_submit: Agrega la wallet ingresada a la lista de wallets.
     /// - cancel_add_wallet: Cancela el agregado de una wallet.
+    /// - handle_import_key_trigger: Muestra el dialogo para importar una private key en formato WIF.
+    /// - handle_import_key_submit: Agrega la private key como una wallet nueva o barre sus fondos hacia la wallet activa.
+    /// - cancel_import_key: Cancela la importacion de la private key.
+    /// - handle_backup_wallet_trigger: Pide el archivo y la passphrase con los que se guarda la copia de seguridad de la wallet activa.
+    /// - handle_restore_wallet_trigger: Pide el archivo y la passphrase de una copia de seguridad y restaura su wallet.
+    /// - handle_sign_message_trigger: Muestra el dialogo para firmar y verificar mensajes.
+    /// - handle_sign_message: Firma el mensaje con la clave de la wallet activa.
+    /// - handle_verify_message: Verifica que la firma del mensaje sea de la clave de la direccion ingresada.
+    /// - close_sign_message: Cierra el dialogo de firma de mensajes.
+    /// - handle_decode_tx_trigger: Muestra el dialogo para decodificar una transaccion en hexadecimal.
+    /// - handle_decode_tx: Muestra los inputs, los outputs y el txid de la transaccion ingresada.
+    /// - close_decode_tx: Cierra el dialogo de decodificacion de transacciones.
     /// - handle_change_wallet: Cambia la wallet activa.
+    /// - handle_encrypt_wallets_trigger: Muestra el dialogo para encriptar las wallets con una passphrase.
+    /// - handle_passphrase_submit: Desbloquea las wallets o establece la nueva passphrase.
+    /// - cancel_passphrase: Cancela el ingreso de la passphrase.
     ///
+    /// Si el archivo de wallets esta encriptado, pide la passphrase para desbloquearlas.
     pub fn handle_interactivity(&self) -> Result<(), CustomError> {
         self.handle_add_wallet_trigger()?;
         self.handle_add_wallet_submit()?;
         self.cancel_add_wallet()?;
+        self.handle_import_key_trigger()?;
+        self.handle_import_key_submit()?;
+        self.cancel_import_key()?;
+        self.handle_backup_wallet_trigger()?;
+        self.handle_restore_wallet_trigger()?;
+        self.handle_sign_message_trigger()?;
+        self.handle_sign_message()?;
+        self.handle_verify_message()?;
+        self.close_sign_message()?;
+        self.handle_decode_tx_trigger()?;
+        self.handle_decode_tx()?;
+        self.close_decode_tx()?;
         self.handle_change_wallet()?;
+        self.handle_encrypt_wallets_trigger()?;
+        self.handle_passphrase_submit()?;
+        self.cancel_passphrase()?;
 
-        Ok(())
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "passphrase-dialog")?;
+        let label: gtk::Label = get_gui_element(&self.builder, "passphrase-label")?;
+        let logger_sender = self.logger_sender.clone();
+        self.node_queries.request(
+            |node_state| Ok(node_state.wallets_locked()),
+            move |locked| match locked {
+                Ok(true) => {
+                    label.set_text("Insert the passphrase of your wallets");
+                    dialog.run();
+                    dialog.hide();
+                }
+                Ok(false) => {}
+                Err(error) => send_log(&logger_sender, Log::Error(error)),
+            },
+        )
     }
 
     fn handle_change_wallet(&self) -> Result<(), CustomError> {
         let select_wallet_cb: gtk::ComboBoxText =
             get_gui_element(&self.builder, "select-wallet-combo-box")?;
 
-        let node_state_ref = self.node_state_ref.clone();
-        let builder = self.builder.clone();
+        let node_queries = self.node_queries.clone();
         let logger_sender = self.logger_sender.clone();
 
-        select_wallet_cb.connect_changed(move |_| {
-            switch_active_wallet(&node_state_ref, &builder).unwrap_or_else(|error| {
-                send_log(&logger_sender, Log::Error(error));
-            });
+        select_wallet_cb.connect_changed(move |select_wallet_cb| {
+            switch_active_wallet(&node_queries, select_wallet_cb, &logger_sender);
         });
 
         Ok(())
@@ -83,42 +139,44 @@ impl GUIWallet {
         let privkey: gtk::Entry = get_gui_element(&self.builder, "add-wallet-privkey")?;
         let wallet_combobox: gtk::ComboBoxText =
             get_gui_element(&self.builder, "select-wallet-combo-box")?;
-        let node_state_ref = self.node_state_ref.clone();
+        let node_queries = self.node_queries.clone();
         let logger_sender = self.logger_sender.clone();
 
         action.connect_clicked(move |_| {
-            let mut node_state = match node_state_ref
-                .lock()
-                .map_err(|_| CustomError::CannotLockGuard)
-            {
-                Ok(node_state) => node_state,
-                Err(error) => {
-                    send_log(&logger_sender, Log::Error(error));
-                    return;
-                }
-            };
-            if let Err(error) = node_state.append_wallet(
+            let (new_name, new_pubkey, new_privkey) = (
                 name.text().to_string(),
                 pubkey.text().to_string(),
                 privkey.text().to_string(),
-            ) {
+            );
+            let (dialog, name, pubkey, privkey) = (
+                dialog.clone(),
+                name.clone(),
+                pubkey.clone(),
+                privkey.clone(),
+            );
+            let (update_queries, wallet_combobox) = (node_queries.clone(), wallet_combobox.clone());
+            let response_logger_sender = logger_sender.clone();
+            let requested = node_queries.request_mut(
+                move |node_state| node_state.append_wallet(new_name, new_pubkey, new_privkey),
+                move |result| {
+                    if let Err(error) = result {
+                        send_log(&response_logger_sender, Log::Error(error));
+                        return;
+                    }
+                    update_wallet_combo_box(
+                        &update_queries,
+                        wallet_combobox,
+                        &response_logger_sender,
+                    );
+                    name.set_text("");
+                    pubkey.set_text("");
+                    privkey.set_text("");
+                    dialog.hide();
+                },
+            );
+            if let Err(error) = requested {
                 send_log(&logger_sender, Log::Error(error));
-                drop(node_state);
-                return;
             }
-            drop(node_state);
-
-            update_wallet_combo_box(node_state_ref.clone(), wallet_combobox.clone())
-                .unwrap_or_else(|_| {
-                    send_log(
-                        &logger_sender,
-                        Log::Message("Error updating combo box".to_string()),
-                    )
-                });
-            name.set_text("");
-            pubkey.set_text("");
-            privkey.set_text("");
-            dialog.hide();
         });
 
         Ok(())
@@ -140,37 +198,510 @@ impl GUIWallet {
 
         Ok(())
     }
+
+    fn handle_import_key_trigger(&self) -> Result<(), CustomError> {
+        let trigger: gtk::Button = get_gui_element(&self.builder, "import-key-button")?;
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "import-key-dialog")?;
+
+        trigger.connect_clicked(move |_| {
+            dialog.run();
+            dialog.hide();
+        });
+
+        Ok(())
+    }
+
+    fn handle_import_key_submit(&self) -> Result<(), CustomError> {
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "import-key-dialog")?;
+        let action: gtk::Button = get_gui_element(&self.builder, "import-key-action")?;
+        let name: gtk::Entry = get_gui_element(&self.builder, "import-key-name")?;
+        let wif: gtk::Entry = get_gui_element(&self.builder, "import-key-wif")?;
+        let sweep: gtk::CheckButton = get_gui_element(&self.builder, "import-key-sweep")?;
+        let wallet_combobox: gtk::ComboBoxText =
+            get_gui_element(&self.builder, "select-wallet-combo-box")?;
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+        let node_action_sender = self.node_action_sender.clone();
+
+        action.connect_clicked(move |_| {
+            // el nodo crea y difunde la transaccion que barre los fondos de la clave
+            if sweep.is_active() {
+                if node_action_sender
+                    .send(NodeAction::SweepKey(wif.text().to_string()))
+                    .is_err()
+                {
+                    send_log(
+                        &logger_sender,
+                        Log::Error(CustomError::CannotSendMessageToChannel),
+                    );
+                    return;
+                }
+                name.set_text("");
+                wif.set_text("");
+                sweep.set_active(false);
+                dialog.hide();
+                return;
+            }
+
+            let (new_name, new_wif) = (name.text().to_string(), wif.text().to_string());
+            let (dialog, name, wif, sweep) =
+                (dialog.clone(), name.clone(), wif.clone(), sweep.clone());
+            let (update_queries, wallet_combobox) = (node_queries.clone(), wallet_combobox.clone());
+            let response_logger_sender = logger_sender.clone();
+            let requested = node_queries.request_mut(
+                move |node_state| node_state.import_key(new_name, &new_wif),
+                move |result| {
+                    if let Err(error) = result {
+                        send_log(&response_logger_sender, Log::Error(error));
+                        return;
+                    }
+                    update_wallet_combo_box(
+                        &update_queries,
+                        wallet_combobox,
+                        &response_logger_sender,
+                    );
+                    name.set_text("");
+                    wif.set_text("");
+                    sweep.set_active(false);
+                    dialog.hide();
+                },
+            );
+            if let Err(error) = requested {
+                send_log(&logger_sender, Log::Error(error));
+            }
+        });
+
+        Ok(())
+    }
+
+    fn cancel_import_key(&self) -> Result<(), CustomError> {
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "import-key-dialog")?;
+        let cancel: gtk::Button = get_gui_element(&self.builder, "import-key-cancel")?;
+        let name: gtk::Entry = get_gui_element(&self.builder, "import-key-name")?;
+        let wif: gtk::Entry = get_gui_element(&self.builder, "import-key-wif")?;
+        let sweep: gtk::CheckButton = get_gui_element(&self.builder, "import-key-sweep")?;
+
+        cancel.connect_clicked(move |_| {
+            name.set_text("");
+            wif.set_text("");
+            sweep.set_active(false);
+            dialog.hide();
+        });
+
+        Ok(())
+    }
+
+    fn handle_backup_wallet_trigger(&self) -> Result<(), CustomError> {
+        let trigger: gtk::MenuItem = get_gui_element(&self.builder, "backup-wallet-item")?;
+        let main_window: gtk::Window = get_gui_element(&self.builder, "main-window")?;
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+
+        trigger.connect_activate(move |_| {
+            let (dialog, passphrase) = backup_file_chooser(
+                "Back up wallet",
+                &main_window,
+                FileChooserAction::Save,
+                "Save",
+            );
+            dialog.set_do_overwrite_confirmation(true);
+            dialog.set_current_name("wallet-backup.bin");
+
+            let node_queries = node_queries.clone();
+            let logger_sender = logger_sender.clone();
+            dialog.connect_response(move |dialog, response| {
+                if response == ResponseType::Accept {
+                    if let Some(path) = dialog.filename() {
+                        let path = path.to_string_lossy().to_string();
+                        backup_wallet(
+                            &node_queries,
+                            path,
+                            passphrase.text().to_string(),
+                            &logger_sender,
+                        );
+                    }
+                }
+                dialog.close();
+            });
+            dialog.show_all();
+        });
+
+        Ok(())
+    }
+
+    fn handle_restore_wallet_trigger(&self) -> Result<(), CustomError> {
+        let trigger: gtk::MenuItem = get_gui_element(&self.builder, "restore-wallet-item")?;
+        let main_window: gtk::Window = get_gui_element(&self.builder, "main-window")?;
+        let wallet_combobox: gtk::ComboBoxText =
+            get_gui_element(&self.builder, "select-wallet-combo-box")?;
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+
+        trigger.connect_activate(move |_| {
+            let (dialog, passphrase) = backup_file_chooser(
+                "Restore wallet",
+                &main_window,
+                FileChooserAction::Open,
+                "Restore",
+            );
+
+            let node_queries = node_queries.clone();
+            let logger_sender = logger_sender.clone();
+            let wallet_combobox = wallet_combobox.clone();
+            dialog.connect_response(move |dialog, response| {
+                if response == ResponseType::Accept {
+                    if let Some(path) = dialog.filename() {
+                        let path = path.to_string_lossy().to_string();
+                        restore_wallet(
+                            &node_queries,
+                            path,
+                            passphrase.text().to_string(),
+                            wallet_combobox.clone(),
+                            &logger_sender,
+                        );
+                    }
+                }
+                dialog.close();
+            });
+            dialog.show_all();
+        });
+
+        Ok(())
+    }
+
+    fn handle_sign_message_trigger(&self) -> Result<(), CustomError> {
+        let trigger: gtk::Button = get_gui_element(&self.builder, "sign-message-button")?;
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "sign-message-dialog")?;
+
+        trigger.connect_clicked(move |_| {
+            dialog.run();
+            dialog.hide();
+        });
+
+        Ok(())
+    }
+
+    fn handle_sign_message(&self) -> Result<(), CustomError> {
+        let action: gtk::Button = get_gui_element(&self.builder, "sign-message-action")?;
+        let address: gtk::Entry = get_gui_element(&self.builder, "sign-message-address")?;
+        let message: gtk::Entry = get_gui_element(&self.builder, "sign-message-text")?;
+        let signature: gtk::Entry = get_gui_element(&self.builder, "sign-message-signature")?;
+        let result: gtk::Label = get_gui_element(&self.builder, "sign-message-result")?;
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+
+        action.connect_clicked(move |_| {
+            let text = message.text().to_string();
+            let (address, signature, result) = (address.clone(), signature.clone(), result.clone());
+            let response_logger_sender = logger_sender.clone();
+            let requested = node_queries.request(
+                move |node_state| node_state.sign_message(&text),
+                move |signed| match signed {
+                    Ok((signer_address, message_signature)) => {
+                        address.set_text(&signer_address);
+                        signature.set_text(&message_signature);
+                        result.set_text("Message signed with the key of the active wallet");
+                    }
+                    Err(error) => send_log(&response_logger_sender, Log::Error(error)),
+                },
+            );
+            if let Err(error) = requested {
+                send_log(&logger_sender, Log::Error(error));
+            }
+        });
+
+        Ok(())
+    }
+
+    fn handle_verify_message(&self) -> Result<(), CustomError> {
+        let verify: gtk::Button = get_gui_element(&self.builder, "sign-message-verify")?;
+        let address: gtk::Entry = get_gui_element(&self.builder, "sign-message-address")?;
+        let message: gtk::Entry = get_gui_element(&self.builder, "sign-message-text")?;
+        let signature: gtk::Entry = get_gui_element(&self.builder, "sign-message-signature")?;
+        let result: gtk::Label = get_gui_element(&self.builder, "sign-message-result")?;
+        let logger_sender = self.logger_sender.clone();
+
+        verify.connect_clicked(move |_| {
+            match verify_message(&address.text(), &signature.text(), &message.text()) {
+                Ok(true) => result.set_text("The signature is valid for this address"),
+                Ok(false) => result.set_text("The signature does not match this address"),
+                Err(error) => send_log(&logger_sender, Log::Error(error)),
+            }
+        });
+
+        Ok(())
+    }
+
+    fn close_sign_message(&self) -> Result<(), CustomError> {
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "sign-message-dialog")?;
+        let close: gtk::Button = get_gui_element(&self.builder, "sign-message-close")?;
+        let address: gtk::Entry = get_gui_element(&self.builder, "sign-message-address")?;
+        let message: gtk::Entry = get_gui_element(&self.builder, "sign-message-text")?;
+        let signature: gtk::Entry = get_gui_element(&self.builder, "sign-message-signature")?;
+        let result: gtk::Label = get_gui_element(&self.builder, "sign-message-result")?;
+
+        close.connect_clicked(move |_| {
+            address.set_text("");
+            message.set_text("");
+            signature.set_text("");
+            result.set_text("");
+            dialog.hide();
+        });
+
+        Ok(())
+    }
+
+    fn handle_decode_tx_trigger(&self) -> Result<(), CustomError> {
+        let trigger: gtk::Button = get_gui_element(&self.builder, "decode-tx-button")?;
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "decode-tx-dialog")?;
+
+        trigger.connect_clicked(move |_| {
+            dialog.run();
+            dialog.hide();
+        });
+
+        Ok(())
+    }
+
+    fn handle_decode_tx(&self) -> Result<(), CustomError> {
+        let action: gtk::Button = get_gui_element(&self.builder, "decode-tx-action")?;
+        let hex: gtk::Entry = get_gui_element(&self.builder, "decode-tx-hex")?;
+        let result: gtk::Label = get_gui_element(&self.builder, "decode-tx-result")?;
+        let logger_sender = self.logger_sender.clone();
+
+        action.connect_clicked(move |_| match decode_raw_transaction_hex(&hex.text()) {
+            Ok(details) => result.set_text(&details.to_string()),
+            Err(error) => {
+                result.set_text("");
+                send_log(&logger_sender, Log::Error(error));
+            }
+        });
+
+        Ok(())
+    }
+
+    fn close_decode_tx(&self) -> Result<(), CustomError> {
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "decode-tx-dialog")?;
+        let close: gtk::Button = get_gui_element(&self.builder, "decode-tx-close")?;
+        let hex: gtk::Entry = get_gui_element(&self.builder, "decode-tx-hex")?;
+        let result: gtk::Label = get_gui_element(&self.builder, "decode-tx-result")?;
+
+        close.connect_clicked(move |_| {
+            hex.set_text("");
+            result.set_text("");
+            dialog.hide();
+        });
+
+        Ok(())
+    }
+
+    fn handle_encrypt_wallets_trigger(&self) -> Result<(), CustomError> {
+        let trigger: gtk::Button = get_gui_element(&self.builder, "encrypt-wallets-button")?;
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "passphrase-dialog")?;
+        let label: gtk::Label = get_gui_element(&self.builder, "passphrase-label")?;
+
+        trigger.connect_clicked(move |_| {
+            label.set_text("Insert a new passphrase to encrypt your wallets");
+            dialog.run();
+            dialog.hide();
+        });
+
+        Ok(())
+    }
+
+    fn handle_passphrase_submit(&self) -> Result<(), CustomError> {
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "passphrase-dialog")?;
+        let action: gtk::Button = get_gui_element(&self.builder, "passphrase-action")?;
+        let passphrase: gtk::Entry = get_gui_element(&self.builder, "passphrase-entry")?;
+        let wallet_combobox: gtk::ComboBoxText =
+            get_gui_element(&self.builder, "select-wallet-combo-box")?;
+        let node_queries = self.node_queries.clone();
+        let logger_sender = self.logger_sender.clone();
+
+        action.connect_clicked(move |_| {
+            let new_passphrase = passphrase.text().to_string();
+            passphrase.set_text("");
+            let (update_queries, wallet_combobox) = (node_queries.clone(), wallet_combobox.clone());
+            let dialog = dialog.clone();
+            let response_logger_sender = logger_sender.clone();
+            let requested = node_queries.request_mut(
+                move |node_state| match node_state.wallets_locked() {
+                    true => node_state.unlock_wallets(new_passphrase),
+                    false => node_state.set_wallets_passphrase(new_passphrase),
+                },
+                move |result| {
+                    if let Err(error) = result {
+                        send_log(&response_logger_sender, Log::Error(error));
+                        return;
+                    }
+                    update_wallet_combo_box(
+                        &update_queries,
+                        wallet_combobox,
+                        &response_logger_sender,
+                    );
+                    dialog.hide();
+                },
+            );
+            if let Err(error) = requested {
+                send_log(&logger_sender, Log::Error(error));
+            }
+        });
+
+        Ok(())
+    }
+
+    fn cancel_passphrase(&self) -> Result<(), CustomError> {
+        let dialog: gtk::Dialog = get_gui_element(&self.builder, "passphrase-dialog")?;
+        let cancel: gtk::Button = get_gui_element(&self.builder, "passphrase-cancel")?;
+        let passphrase: gtk::Entry = get_gui_element(&self.builder, "passphrase-entry")?;
+
+        cancel.connect_clicked(move |_| {
+            passphrase.set_text("");
+            dialog.hide();
+        });
+
+        Ok(())
+    }
 }
 
+/// Pide cambiar la wallet activa por la elegida en el combobox, y al terminar lo deja en la wallet activa.
 fn switch_active_wallet(
-    node_state_ref: &Arc<Mutex<NodeState>>,
-    builder: &gtk::Builder,
-) -> Result<(), CustomError> {
-    let select_wallet_cb: gtk::ComboBoxText = get_gui_element(builder, "select-wallet-combo-box")?;
-
-    if let Some(active_pubkey) = select_wallet_cb.active_id() {
-        let mut node_state = node_state_ref.lock()?;
-        node_state.change_wallet(active_pubkey.to_string())?;
-        if let Some(active_wallet) = node_state.get_active_wallet() {
-            select_wallet_cb.set_active_id(Some(active_wallet.pubkey.as_str()));
-        } else {
-            select_wallet_cb.set_active_id(None);
-        }
-        drop(node_state);
-    }
-
-    Ok(())
+    node_queries: &NodeQueries,
+    select_wallet_cb: &gtk::ComboBoxText,
+    logger_sender: &mpsc::Sender<LogEntry>,
+) {
+    let Some(active_pubkey) = select_wallet_cb.active_id() else { return };
+    let active_pubkey = active_pubkey.to_string();
+    let select_wallet_cb = select_wallet_cb.clone();
+    let response_logger_sender = logger_sender.clone();
+    let requested = node_queries.request_mut(
+        move |node_state| {
+            node_state.change_wallet(active_pubkey)?;
+            Ok(node_state
+                .get_active_wallet()
+                .map(|active_wallet| active_wallet.pubkey.clone()))
+        },
+        move |active_pubkey| match active_pubkey {
+            Ok(active_pubkey) => select_wallet_cb.set_active_id(active_pubkey.as_deref()),
+            Err(error) => send_log(&response_logger_sender, Log::Error(error)),
+        },
+    );
+    if let Err(error) = requested {
+        send_log(logger_sender, Log::Error(error));
+    }
+}
+
+/// Crea el dialogo para elegir el archivo de una copia de seguridad, con un campo para su passphrase.
+fn backup_file_chooser(
+    title: &str,
+    main_window: &gtk::Window,
+    action: FileChooserAction,
+    accept_label: &str,
+) -> (gtk::FileChooserDialog, gtk::Entry) {
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some(title),
+        Some(main_window),
+        action,
+        &[
+            ("Cancel", ResponseType::Cancel),
+            (accept_label, ResponseType::Accept),
+        ],
+    );
+    let passphrase = gtk::Entry::new();
+    passphrase.set_visibility(false);
+    passphrase.set_placeholder_text(Some("Backup passphrase"));
+    dialog.set_extra_widget(&passphrase);
+    (dialog, passphrase)
 }
 
+/// Pide guardar la copia de seguridad de la wallet activa en path, y avisa al logger cuando termina.
+fn backup_wallet(
+    node_queries: &NodeQueries,
+    path: String,
+    passphrase: String,
+    logger_sender: &mpsc::Sender<LogEntry>,
+) {
+    let response_logger_sender = logger_sender.clone();
+    let backup_path = path.clone();
+    let requested = node_queries.request(
+        move |node_state| node_state.export_active_wallet_backup(&backup_path, &passphrase),
+        move |result| match result {
+            Ok(()) => send_log(
+                &response_logger_sender,
+                Log::Message(format!("Wallet backup saved to {}", path)),
+            ),
+            Err(error) => send_log(&response_logger_sender, Log::Error(error)),
+        },
+    );
+    if let Err(error) = requested {
+        send_log(logger_sender, Log::Error(error));
+    }
+}
+
+/// Pide restaurar la wallet de la copia de seguridad de path, y al terminar actualiza el combobox de wallets.
+fn restore_wallet(
+    node_queries: &NodeQueries,
+    path: String,
+    passphrase: String,
+    select_wallet_cb: gtk::ComboBoxText,
+    logger_sender: &mpsc::Sender<LogEntry>,
+) {
+    let update_queries = node_queries.clone();
+    let response_logger_sender = logger_sender.clone();
+    let backup_path = path.clone();
+    let requested = node_queries.request_mut(
+        move |node_state| node_state.restore_wallet_backup(&backup_path, &passphrase),
+        move |result| match result {
+            Ok(_) => {
+                send_log(
+                    &response_logger_sender,
+                    Log::Message(format!("Wallet restored from {}", path)),
+                );
+                update_wallet_combo_box(&update_queries, select_wallet_cb, &response_logger_sender);
+            }
+            Err(error) => send_log(&response_logger_sender, Log::Error(error)),
+        },
+    );
+    if let Err(error) = requested {
+        send_log(logger_sender, Log::Error(error));
+    }
+}
+
+/// Pide las wallets cargadas y al recibirlas las lista en el combobox, indicando las watch-only.
 fn update_wallet_combo_box(
-    node_state_ref: Arc<Mutex<NodeState>>,
+    node_queries: &NodeQueries,
     select_wallet_cb: gtk::ComboBoxText,
-) -> Result<(), CustomError> {
-    let node_state = node_state_ref.lock()?;
-    select_wallet_cb.remove_all();
-    for wallet in node_state.get_wallets() {
-        select_wallet_cb.append(Some(wallet.pubkey.as_str()), wallet.name.as_str());
-    }
-    drop(node_state);
-    Ok(())
+    logger_sender: &mpsc::Sender<LogEntry>,
+) {
+    let response_logger_sender = logger_sender.clone();
+    let requested = node_queries.request(
+        |node_state| {
+            Ok(node_state
+                .get_wallets()
+                .iter()
+                .map(|wallet| {
+                    let name = match wallet.is_watch_only() {
+                        true => format!("{} (watch-only)", wallet.name),
+                        false => wallet.name.clone(),
+                    };
+                    (wallet.pubkey.clone(), name)
+                })
+                .collect::<Vec<(String, String)>>())
+        },
+        move |wallets| match wallets {
+            Ok(wallets) => {
+                select_wallet_cb.remove_all();
+                for (pubkey, name) in wallets {
+                    select_wallet_cb.append(Some(pubkey.as_str()), &name);
+                }
+            }
+            Err(_) => send_log(
+                &response_logger_sender,
+                Log::Warning("Error updating combo box".to_string()),
+            ),
+        },
+    );
+    if let Err(error) = requested {
+        send_log(logger_sender, Log::Error(error));
+    }
 }
@@ -1,21 +1,32 @@
-use gtk::traits::{GtkWindowExt, WidgetExt};
-use std::sync::mpsc;
+use gtk::{
+    glib,
+    traits::{GtkWindowExt, LabelExt, ProgressBarExt, RevealerExt, WidgetExt},
+};
+use std::{sync::mpsc, time::Duration};
 
 use super::init::{get_gui_element, GUIEvents};
 use crate::{
     error::CustomError,
-    logger::{send_log, Log},
+    logger::{send_log, Log, LogEntry},
+    loops::node_action_loop::NodeAction,
+    notifications::Notification,
 };
 
+/// Tiempo en segundos que se muestra una notificacion en la ventana principal.
+const TOAST_DURATION: u32 = 6;
+/// Tiempo en milisegundos entre cada actualizacion del progreso de la sincronizacion en la ventana de carga.
+const SYNC_PROGRESS_INTERVAL: u64 = 500;
+
 #[derive(Clone)]
 /// GUIWindow es una estructura que contiene los elementos de la interfaz grafica
-/// relacionados con la ventana principal. Muestra la ventana principal y la ventana de carga.
+/// relacionados con la ventana principal. Muestra la ventana principal y la ventana de carga con el progreso
+/// de la sincronizacion, y las notificaciones de los pagos recibidos sobre la barra de estado.
 /// Los elementos son:
 /// - builder: Builder de gtk.
 /// - logger_sender: Sender para enviar logs al logger.
 pub struct GUIWindow {
     pub builder: gtk::Builder,
-    pub logger_sender: mpsc::Sender<Log>,
+    pub logger_sender: mpsc::Sender<LogEntry>,
 }
 
 impl GUIWindow {
@@ -25,6 +36,42 @@ impl GUIWindow {
         Ok(())
     }
 
+    /// Actualiza periodicamente la barra de progreso de la ventana de carga con el progreso de la sincronizacion,
+    /// que se le pide al nodo con NodeAction::GetSyncProgress. Deja de actualizarla cuando el nodo se sincroniza.
+    pub fn handle_interactivity(
+        &self,
+        node_action_sender: &mpsc::Sender<NodeAction>,
+    ) -> Result<(), CustomError> {
+        let progress_bar: gtk::ProgressBar = get_gui_element(&self.builder, "sync-progress")?;
+        let node_action_sender = node_action_sender.clone();
+        let (progress_sender, progress_receiver) = mpsc::channel();
+        let mut waiting_progress = false;
+
+        glib::timeout_add_local(Duration::from_millis(SYNC_PROGRESS_INTERVAL), move || {
+            if let Some(progress) = progress_receiver.try_iter().last() {
+                waiting_progress = false;
+                if progress.synced {
+                    progress_bar.set_fraction(1.0);
+                    return glib::Continue(false);
+                }
+                if progress.peer_height > 0 {
+                    progress_bar.set_fraction(progress.fraction());
+                    progress_bar.set_text(Some(&progress.description()));
+                }
+            }
+
+            // no se pide otro progreso hasta recibir la respuesta, para no llenar la cola de acciones del nodo
+            if waiting_progress {
+                return glib::Continue(true);
+            }
+            waiting_progress = true;
+            let sent =
+                node_action_sender.send(NodeAction::GetSyncProgress(progress_sender.clone()));
+            glib::Continue(sent.is_ok())
+        });
+        Ok(())
+    }
+
     fn show_loading_window(&self) -> Result<(), CustomError> {
         let load_window: gtk::Window = get_gui_element(&self.builder, "load-window")?;
         load_window.set_default_size(600, 400);
@@ -35,9 +82,16 @@ impl GUIWindow {
 
     /// Maneja los GUIEvents recibidos y hace las acciones acorde a cada envento.
     /// Para NodeStateReady: Muestra la ventana principal y oculta la de carga.
+    /// Para PaymentNotification: Muestra la notificacion durante unos segundos.
+    /// Para SyncMilestone: Muestra la etapa de la sincronizacion alcanzada como notificacion.
     pub fn handle_events(&self, message: &GUIEvents) {
         let result = match message {
             GUIEvents::NodeStateReady => self.handle_node_state_ready(),
+            GUIEvents::PaymentNotification(notification) => self.show_toast(notification),
+            GUIEvents::SyncMilestone(milestone) => self.show_toast(&Notification {
+                title: "Sync".to_string(),
+                body: milestone.description().to_string(),
+            }),
             _ => Ok(()),
         };
 
@@ -61,4 +115,24 @@ impl GUIWindow {
         main_window.show_all();
         Ok(())
     }
+
+    fn show_toast(&self, notification: &Notification) -> Result<(), CustomError> {
+        let toast: gtk::Revealer = get_gui_element(&self.builder, "toast")?;
+        let toast_label: gtk::Label = get_gui_element(&self.builder, "toast-label")?;
+        toast_label.set_markup(&format!(
+            "<b>{}</b>: {}",
+            glib::markup_escape_text(&notification.title),
+            glib::markup_escape_text(&notification.body)
+        ));
+        toast.set_reveal_child(true);
+
+        // solo se oculta si no se mostro otra notificacion mientras tanto
+        let text = toast_label.text();
+        glib::timeout_add_seconds_local_once(TOAST_DURATION, move || {
+            if toast_label.text() == text {
+                toast.set_reveal_child(false);
+            }
+        });
+        Ok(())
+    }
 }
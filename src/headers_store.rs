@@ -0,0 +1,549 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use bitcoin_hashes::{sha256d, Hash};
+
+use crate::{
+    chain_validator::ChainWork,
+    error::{CustomError, ParseError},
+    structs::{block_header::BlockHeader, hash256::Hash256},
+    utils::open_new_file,
+};
+
+/// Tamaño en bytes de un header en el archivo: los 80 bytes del header, su hash de 32 bytes
+/// y el trabajo acumulado por la cadena hasta el, de 32 bytes.
+const HEADER_RECORD_SIZE: usize = 144;
+/// Tamaño en bytes de un header en los archivos anteriores a la version 2, sin el trabajo acumulado.
+const LEGACY_RECORD_SIZE: usize = 112;
+/// Cantidad de headers que se leen del archivo por vez.
+pub const HEADERS_READ_BATCH: usize = 2000;
+/// Bytes iniciales del archivo de headers.
+const HEADERS_MAGIC: &[u8; 4] = b"RZHD";
+/// Version del formato del archivo de headers.
+const HEADERS_VERSION: u32 = 2;
+/// Tamaño en bytes del encabezado del archivo: magic y version.
+const HEADERS_PREFIX_SIZE: usize = 8;
+
+/// HeaderChain es una cadena de headers ordenada por altura, sin el genesis, que se lee por rangos
+/// sin necesidad de tenerla completa en memoria.
+pub trait HeaderChain {
+    /// Devuelve la cantidad de headers de la cadena.
+    fn len(&self) -> usize;
+
+    /// Devuelve hasta count headers a partir de la posicion start.
+    fn read_range(&self, start: usize, count: usize) -> Result<Vec<BlockHeader>, CustomError>;
+
+    /// Devuelve true si la cadena no tiene headers.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Devuelve el header en la posicion index, o None si no existe.
+    fn get(&self, index: usize) -> Result<Option<BlockHeader>, CustomError> {
+        Ok(self.read_range(index, 1)?.pop())
+    }
+
+    /// Devuelve la posicion del header con el hash recibido, o None si no esta en la cadena.
+    /// Recorre la cadena desde el final por lotes de HEADERS_READ_BATCH.
+    fn find_position(&self, block_hash: &Hash256) -> Result<Option<usize>, CustomError> {
+        let mut end = self.len();
+        while end > 0 {
+            let start = end.saturating_sub(HEADERS_READ_BATCH);
+            let headers = self.read_range(start, end - start)?;
+            if let Some(position) = headers
+                .iter()
+                .rposition(|header| header.hash() == block_hash)
+            {
+                return Ok(Some(start + position));
+            }
+            end = start;
+        }
+        Ok(None)
+    }
+
+    /// Devuelve la posicion del ultimo header con timestamp anterior o igual al recibido, o 0 si no hay ninguno,
+    /// como calculate_index_from_timestamp. Recorre la cadena desde el final por lotes de HEADERS_READ_BATCH.
+    fn index_from_timestamp(&self, timestamp: u32) -> Result<usize, CustomError> {
+        let mut end = self.len();
+        while end > 0 {
+            let start = end.saturating_sub(HEADERS_READ_BATCH);
+            let headers = self.read_range(start, end - start)?;
+            if let Some(position) = headers
+                .iter()
+                .rposition(|header| header.timestamp <= timestamp)
+            {
+                return Ok(start + position);
+            }
+            end = start;
+        }
+        Ok(0)
+    }
+}
+
+impl HeaderChain for Vec<BlockHeader> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn read_range(&self, start: usize, count: usize) -> Result<Vec<BlockHeader>, CustomError> {
+        let start = start.min(self.len());
+        let end = start.saturating_add(count).min(self.len());
+        Ok(self[start..end].to_vec())
+    }
+}
+
+/// HeadersStore guarda los headers en disco como registros de tamaño fijo, en orden de altura.
+/// Los headers nuevos se agregan al final del archivo, y se leen por rangos a medida que se necesitan,
+/// sin cargar el archivo completo en memoria. Al ser de tamaño fijo, cualquier header
+/// se puede leer directamente a partir de su posicion.
+/// Cada header se guarda junto al trabajo acumulado por la cadena hasta el, para elegir la cadena con mas trabajo
+/// sin recorrerla.
+/// El archivo empieza con un magic y la version del formato. Los archivos guardados antes de este formato
+/// no lo tienen, y los de la version 1 no guardan el trabajo acumulado: al leerlos se calcula,
+/// y se convierten al formato nuevo la primera vez que se modifican.
+/// Los elementos son:
+/// - path: Path del archivo donde se guardan los headers.
+/// - count: Cantidad de headers guardados.
+/// - offset: Posicion del primer header en el archivo, 0 si el archivo no tiene encabezado.
+/// - record_size: Tamaño en bytes de cada header en el archivo, segun su version.
+pub struct HeadersStore {
+    path: String,
+    count: usize,
+    offset: usize,
+    record_size: usize,
+}
+
+impl HeadersStore {
+    /// Abre el archivo de headers, creandolo si no existe.
+    /// Si la escritura de los ultimos headers se interrumpio, por ejemplo por un corte de luz,
+    /// descarta los headers incompletos o cuyo hash no coincide con su contenido.
+    /// Devuelve CustomError si un archivo sin encabezado no contiene una cantidad entera de headers.
+    pub fn open(path: String) -> Result<Self, CustomError> {
+        let temp_path = format!("{}.tmp", path);
+        if Path::new(&temp_path).exists() {
+            fs::remove_file(temp_path)?;
+        }
+
+        let mut file = open_new_file(path.clone(), true)?;
+        let size = file.metadata()?.len() as usize;
+        let mut prefix = [0_u8; HEADERS_PREFIX_SIZE];
+        let has_prefix = size >= HEADERS_PREFIX_SIZE && {
+            file.read_exact(&mut prefix)?;
+            prefix.starts_with(HEADERS_MAGIC)
+        };
+        if !has_prefix {
+            if !size.is_multiple_of(LEGACY_RECORD_SIZE) {
+                return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+            }
+            return Ok(Self {
+                path,
+                count: size / LEGACY_RECORD_SIZE,
+                offset: 0,
+                record_size: LEGACY_RECORD_SIZE,
+            });
+        }
+        let record_size = match u32::from_le_bytes([prefix[4], prefix[5], prefix[6], prefix[7]]) {
+            1 => LEGACY_RECORD_SIZE,
+            HEADERS_VERSION => HEADER_RECORD_SIZE,
+            _ => return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid)),
+        };
+
+        let mut store = Self {
+            path,
+            count: (size - HEADERS_PREFIX_SIZE) / record_size,
+            offset: HEADERS_PREFIX_SIZE,
+            record_size,
+        };
+        store.repair(size)?;
+        Ok(store)
+    }
+
+    /// Descarta el final del archivo si la ultima escritura quedo incompleta: el header parcial
+    /// y los headers, entre los ultimos HEADERS_READ_BATCH, cuyo hash no coincide con su contenido.
+    fn repair(&mut self, size: usize) -> Result<(), CustomError> {
+        let start = self.count.saturating_sub(HEADERS_READ_BATCH);
+        let mut file = self.open_file()?;
+        file.seek(SeekFrom::Start(self.position(start)))?;
+        let mut buffer = vec![0_u8; (self.count - start) * self.record_size];
+        file.read_exact(&mut buffer)?;
+
+        let valid = buffer
+            .chunks(self.record_size)
+            .position(|record| sha256d::Hash::hash(&record[..80])[..] != record[80..112])
+            .map_or(self.count, |invalid| start + invalid);
+        if valid < self.count || self.position(self.count) != size as u64 {
+            open_new_file(self.path.clone(), false)?.set_len(self.position(valid))?;
+            self.sync()?;
+            self.count = valid;
+        }
+        Ok(())
+    }
+
+    /// Devuelve la cantidad de headers guardados.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Devuelve true si no hay headers guardados.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Agrega los headers al final del archivo, cada uno con el trabajo acumulado por la cadena hasta el,
+    /// y fuerza su escritura en disco.
+    pub fn append(
+        &mut self,
+        headers: &[BlockHeader],
+        chain_work: &[ChainWork],
+    ) -> Result<(), CustomError> {
+        self.upgrade()?;
+        let mut writer = BufWriter::new(open_new_file(self.path.clone(), true)?);
+        for (header, work) in headers.iter().zip(chain_work) {
+            writer.write_all(&serialize_record(header, work))?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_data()?;
+
+        self.count += headers.len();
+        Ok(())
+    }
+
+    /// Fuerza la escritura en disco de los headers guardados.
+    pub fn sync(&self) -> Result<(), CustomError> {
+        self.open_file()?.sync_all()?;
+        Ok(())
+    }
+
+    /// Descarta los headers a partir de la posicion len, dejando len headers guardados.
+    pub fn truncate(&mut self, len: usize) -> Result<(), CustomError> {
+        if len >= self.count {
+            return Ok(());
+        }
+        self.upgrade()?;
+        open_new_file(self.path.clone(), false)?.set_len(self.position(len))?;
+        self.count = len;
+        Ok(())
+    }
+
+    /// Devuelve el header guardado en la posicion index, o None si no existe.
+    pub fn get(&self, index: usize) -> Result<Option<BlockHeader>, CustomError> {
+        Ok(self.read_range(index, 1)?.pop())
+    }
+
+    /// Devuelve hasta count headers a partir de la posicion start.
+    pub fn read_range(&self, start: usize, count: usize) -> Result<Vec<BlockHeader>, CustomError> {
+        if start >= self.count {
+            return Ok(vec![]);
+        }
+        let count = count.min(self.count - start);
+
+        let mut file = self.open_file()?;
+        file.seek(SeekFrom::Start(self.position(start)))?;
+        let mut total_work = ChainWork::ZERO;
+        let records = read_records(&mut file, count, self.record_size, &mut total_work)?;
+        Ok(records.into_iter().map(|(header, _)| header).collect())
+    }
+
+    /// Devuelve hasta count headers a partir de la posicion start, junto al trabajo acumulado por la cadena
+    /// hasta cada uno.
+    /// Los archivos de versiones anteriores no guardan el trabajo acumulado, por lo que se calcula
+    /// leyendo los headers desde el inicio por lotes de HEADERS_READ_BATCH.
+    pub fn read_range_with_chain_work(
+        &self,
+        start: usize,
+        count: usize,
+    ) -> Result<(Vec<BlockHeader>, Vec<ChainWork>), CustomError> {
+        if start >= self.count {
+            return Ok((vec![], vec![]));
+        }
+        let count = count.min(self.count - start);
+
+        let mut read = match self.record_size {
+            HEADER_RECORD_SIZE => start,
+            _ => 0,
+        };
+        let mut reader = BufReader::new(self.open_file()?);
+        reader.seek(SeekFrom::Start(self.position(read)))?;
+        let mut total_work = ChainWork::ZERO;
+        while read < start {
+            let batch_size = HEADERS_READ_BATCH.min(start - read);
+            read_records(&mut reader, batch_size, self.record_size, &mut total_work)?;
+            read += batch_size;
+        }
+        Ok(
+            read_records(&mut reader, count, self.record_size, &mut total_work)?
+                .into_iter()
+                .unzip(),
+        )
+    }
+
+    /// Lee el hash de todos los headers guardados, por lotes de HEADERS_READ_BATCH y sin parsear los headers,
+    /// y devuelve la posicion de cada uno.
+    pub fn load_positions(&self) -> Result<HashMap<Hash256, usize>, CustomError> {
+        let mut reader = BufReader::new(self.open_file()?);
+        reader.seek(SeekFrom::Start(self.position(0)))?;
+        let mut positions = HashMap::with_capacity(self.count);
+        let mut read = 0;
+        while read < self.count {
+            let batch_size = HEADERS_READ_BATCH.min(self.count - read);
+            let mut buffer = vec![0_u8; batch_size * self.record_size];
+            reader.read_exact(&mut buffer)?;
+            for record in buffer.chunks(self.record_size) {
+                positions.insert(Hash256::from_slice(&record[80..112])?, read);
+                read += 1;
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Convierte al formato actual un archivo guardado antes de el, agregando el encabezado
+    /// y el trabajo acumulado de cada header.
+    /// Escribe el archivo nuevo en un temporal y lo renombra sobre el anterior, para no perder headers si se interrumpe.
+    fn upgrade(&mut self) -> Result<(), CustomError> {
+        if self.record_size == HEADER_RECORD_SIZE {
+            return Ok(());
+        }
+        let temp_path = format!("{}.tmp", self.path);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writer.write_all(HEADERS_MAGIC)?;
+        writer.write_all(&HEADERS_VERSION.to_le_bytes())?;
+
+        let mut reader = BufReader::new(self.open_file()?);
+        reader.seek(SeekFrom::Start(self.position(0)))?;
+        let mut total_work = ChainWork::ZERO;
+        let mut upgraded = 0;
+        while upgraded < self.count {
+            let batch_size = HEADERS_READ_BATCH.min(self.count - upgraded);
+            for (header, work) in
+                read_records(&mut reader, batch_size, self.record_size, &mut total_work)?
+            {
+                writer.write_all(&serialize_record(&header, &work))?;
+            }
+            upgraded += batch_size;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(temp_path, &self.path)?;
+
+        self.offset = HEADERS_PREFIX_SIZE;
+        self.record_size = HEADER_RECORD_SIZE;
+        Ok(())
+    }
+
+    /// Devuelve la posicion en el archivo del header en la posicion index.
+    fn position(&self, index: usize) -> u64 {
+        (self.offset + index * self.record_size) as u64
+    }
+
+    fn open_file(&self) -> Result<File, CustomError> {
+        Ok(File::open(&self.path)?)
+    }
+}
+
+/// Serializa un header junto al trabajo acumulado por la cadena hasta el.
+fn serialize_record(header: &BlockHeader, chain_work: &ChainWork) -> Vec<u8> {
+    let mut record = header.serialize_for_backup();
+    record.extend(chain_work.to_be_bytes());
+    record
+}
+
+/// Lee count headers del archivo junto al trabajo acumulado hasta cada uno.
+/// Los registros sin trabajo acumulado, de versiones anteriores del formato, lo calculan sumando el de su header
+/// a total_work, el trabajo acumulado hasta el registro anterior. total_work queda con el del ultimo registro leido.
+fn read_records(
+    reader: &mut impl Read,
+    count: usize,
+    record_size: usize,
+    total_work: &mut ChainWork,
+) -> Result<Vec<(BlockHeader, ChainWork)>, CustomError> {
+    let mut buffer = vec![0_u8; count * record_size];
+    reader.read_exact(&mut buffer)?;
+
+    let mut records = Vec::with_capacity(count);
+    for record in buffer.chunks(record_size) {
+        let header = BlockHeader::parse_from_backup(record[..LEGACY_RECORD_SIZE].to_vec())?;
+        *total_work = match <&[u8; 32]>::try_from(&record[LEGACY_RECORD_SIZE..]) {
+            Ok(work) => ChainWork::from_be_bytes(work),
+            Err(_) => *total_work + ChainWork::of(std::slice::from_ref(&header)),
+        };
+        records.push((header, *total_work));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_file;
+
+    use super::*;
+
+    fn test_headers() -> (Vec<BlockHeader>, Vec<ChainWork>) {
+        let store = HeadersStore::open("tests/test_headers.bin".to_string()).unwrap();
+        store.read_range_with_chain_work(0, store.len()).unwrap()
+    }
+
+    #[test]
+    fn open_existing_store() {
+        let store = HeadersStore::open("tests/test_headers.bin".to_string()).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.read_range(0, store.len()).unwrap().len(), 2);
+
+        assert!(HeadersStore::open("tests/test_headers_error.bin".to_string()).is_err());
+    }
+
+    #[test]
+    fn read_headers_by_position() {
+        let store = HeadersStore::open("tests/test_headers.bin".to_string()).unwrap();
+        let headers = store.read_range(0, store.len()).unwrap();
+
+        assert_eq!(store.get(1).unwrap().unwrap().hash, headers[1].hash);
+        assert!(store.get(2).unwrap().is_none());
+
+        let range = store.read_range(1, 10).unwrap();
+        assert_eq!(range.len(), 1);
+        assert_eq!(range[0].hash, headers[1].hash);
+    }
+
+    #[test]
+    fn read_chain_work_from_the_middle_of_the_file() {
+        let (headers, chain_work) = test_headers();
+        let store = HeadersStore::open("tests/test_headers.bin".to_string()).unwrap();
+
+        // el archivo no guarda el trabajo acumulado, se calcula desde el primer header
+        let (range, range_work) = store.read_range_with_chain_work(1, 10).unwrap();
+        assert_eq!(range.len(), 1);
+        assert_eq!(range[0].hash, headers[1].hash);
+        assert_eq!(range_work, vec![ChainWork::of(&headers)]);
+        assert_eq!(range_work[0], chain_work[1]);
+    }
+
+    #[test]
+    fn load_positions_by_hash() {
+        let (headers, _) = test_headers();
+        let store = HeadersStore::open("tests/test_headers.bin".to_string()).unwrap();
+
+        let positions = store.load_positions().unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions.get(headers[0].hash()), Some(&0));
+        assert_eq!(positions.get(headers[1].hash()), Some(&1));
+    }
+
+    #[test]
+    fn find_headers_in_a_chain() {
+        let (headers, _) = test_headers();
+        let mut chain = headers.clone();
+        chain.extend(vec![headers[0].clone(); HEADERS_READ_BATCH]);
+
+        assert_eq!(chain.find_position(headers[1].hash()).unwrap(), Some(1));
+        assert_eq!(chain.find_position(&Hash256::ZERO).unwrap(), None);
+        assert_eq!(chain.get(1).unwrap().unwrap().hash, headers[1].hash);
+        assert!(chain.get(chain.len()).unwrap().is_none());
+
+        // el ultimo header con timestamp anterior esta en el lote anterior al ultimo
+        let timestamp = headers[1].timestamp;
+        chain[0].timestamp = timestamp + 1;
+        let chain_len = chain.len();
+        for header in &mut chain[2..] {
+            header.timestamp = timestamp + 1;
+        }
+        assert_eq!(chain.index_from_timestamp(timestamp).unwrap(), 1);
+        assert_eq!(chain.index_from_timestamp(0).unwrap(), 0);
+        assert_eq!(chain.read_range(chain_len - 1, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn append_headers() {
+        let path = "tests/test_headers_store_append.bin".to_string();
+        let (headers, chain_work) = test_headers();
+
+        let mut store = HeadersStore::open(path.clone()).unwrap();
+        assert!(store.is_empty());
+        store.append(&headers[..1], &chain_work[..1]).unwrap();
+        store.append(&headers[1..], &chain_work[1..]).unwrap();
+        assert_eq!(store.len(), 2);
+
+        let store = HeadersStore::open(path.clone()).unwrap();
+        let restored = store.read_range(0, store.len()).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored[0].hash, headers[0].hash);
+        assert_eq!(restored[1].hash, headers[1].hash);
+        assert_eq!(
+            store.read_range_with_chain_work(0, store.len()).unwrap().1,
+            chain_work
+        );
+
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncate_headers() {
+        let path = "tests/test_headers_store_truncate.bin".to_string();
+        let (headers, chain_work) = test_headers();
+
+        let mut store = HeadersStore::open(path.clone()).unwrap();
+        store.append(&headers, &chain_work).unwrap();
+        store.truncate(1).unwrap();
+        assert_eq!(store.len(), 1);
+
+        let store = HeadersStore::open(path.clone()).unwrap();
+        let restored = store.read_range(0, store.len()).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].hash, headers[0].hash);
+
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn repairs_interrupted_appends() {
+        let path = "tests/test_headers_store_repair.bin".to_string();
+        let (headers, chain_work) = test_headers();
+
+        let mut store = HeadersStore::open(path.clone()).unwrap();
+        store.append(&headers, &chain_work).unwrap();
+        let mut file = open_new_file(path.clone(), true).unwrap();
+        let mut record = headers[1].serialize_for_backup();
+        record[0] ^= 1;
+        file.write_all(&record).unwrap();
+        file.write_all(&record[..50]).unwrap();
+
+        let store = HeadersStore::open(path.clone()).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store.read_range(0, store.len()).unwrap()[1].hash,
+            headers[1].hash
+        );
+        assert_eq!(
+            fs::metadata(&path).unwrap().len() as usize,
+            HEADERS_PREFIX_SIZE + 2 * HEADER_RECORD_SIZE
+        );
+
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn upgrades_files_without_prefix() {
+        let path = "tests/test_headers_store_upgrade.bin".to_string();
+        fs::copy("tests/test_headers.bin", &path).unwrap();
+        let (headers, chain_work) = test_headers();
+
+        let mut store = HeadersStore::open(path.clone()).unwrap();
+        store.truncate(1).unwrap();
+        store.append(&headers[1..], &chain_work[1..]).unwrap();
+
+        let store = HeadersStore::open(path.clone()).unwrap();
+        assert_eq!(store.offset, HEADERS_PREFIX_SIZE);
+        assert_eq!(store.record_size, HEADER_RECORD_SIZE);
+        let (restored, restored_work) = store.read_range_with_chain_work(0, store.len()).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored[1].hash, headers[1].hash);
+        assert_eq!(restored_work, chain_work);
+        assert_eq!(restored_work[0], ChainWork::of(&headers[..1]));
+        assert_eq!(restored_work[1], ChainWork::of(&headers));
+
+        remove_file(path).unwrap();
+    }
+}
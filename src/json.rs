@@ -0,0 +1,335 @@
+use std::fmt;
+
+use crate::error::CustomError;
+
+/// JsonValue es un valor JSON (RFC 8259).
+/// Los objetos mantienen el orden de sus claves, tanto al parsearse como al serializarse.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Parsea un valor JSON a partir de un texto.
+    /// Devuelve CustomError si el texto no es un JSON valido o tiene contenido luego del valor.
+    pub fn parse(text: &str) -> Result<Self, CustomError> {
+        let mut parser = JsonParser {
+            chars: text.chars().collect(),
+            position: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.chars.len() {
+            return Err(invalid_json());
+        }
+        Ok(value)
+    }
+
+    /// Crea un objeto a partir de pares clave valor.
+    pub fn object(entries: Vec<(&str, JsonValue)>) -> Self {
+        JsonValue::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Devuelve el valor de una clave si es un objeto que la contiene.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        let JsonValue::Object(entries) = self else { return None };
+        entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Devuelve el string si es un string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Devuelve el booleano si es un booleano.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Devuelve el numero si es un numero.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Devuelve los elementos si es un array.
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for JsonValue {
+    /// Serializa el valor en JSON compacto, sin espacios.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(value) => write!(f, "{value}"),
+            JsonValue::Number(value) if value.is_finite() => write!(f, "{value}"),
+            JsonValue::Number(_) => write!(f, "null"),
+            JsonValue::String(value) => write_string(f, value),
+            JsonValue::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            JsonValue::Object(entries) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for char in value.chars() {
+        match char {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            char if (char as u32) < 0x20 => write!(f, "\\u{:04x}", char as u32)?,
+            char => write!(f, "{char}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn invalid_json() -> CustomError {
+    CustomError::Validation(String::from("Invalid JSON"))
+}
+
+/// Parser recursivo de JSON que recorre el texto caracter a caracter.
+struct JsonParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl JsonParser {
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Result<char, CustomError> {
+        let char = self.peek().ok_or_else(invalid_json)?;
+        self.position += 1;
+        Ok(char)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), CustomError> {
+        for char in expected.chars() {
+            if self.next()? != char {
+                return Err(invalid_json());
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, CustomError> {
+        self.skip_whitespace();
+        match self.peek().ok_or_else(invalid_json)? {
+            'n' => self.expect("null").map(|_| JsonValue::Null),
+            't' => self.expect("true").map(|_| JsonValue::Bool(true)),
+            'f' => self.expect("false").map(|_| JsonValue::Bool(false)),
+            '"' => self.parse_string().map(JsonValue::String),
+            '[' => self.parse_array(),
+            '{' => self.parse_object(),
+            '-' | '0'..='9' => self.parse_number(),
+            _ => Err(invalid_json()),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, CustomError> {
+        let start = self.position;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+            self.position += 1;
+        }
+        let number: String = self.chars[start..self.position].iter().collect();
+        number
+            .parse::<f64>()
+            .map(JsonValue::Number)
+            .map_err(|_| invalid_json())
+    }
+
+    fn parse_string(&mut self) -> Result<String, CustomError> {
+        self.expect("\"")?;
+        let mut string = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(string),
+                '\\' => match self.next()? {
+                    '"' => string.push('"'),
+                    '\\' => string.push('\\'),
+                    '/' => string.push('/'),
+                    'b' => string.push('\u{8}'),
+                    'f' => string.push('\u{c}'),
+                    'n' => string.push('\n'),
+                    'r' => string.push('\r'),
+                    't' => string.push('\t'),
+                    'u' => string.push(self.parse_unicode_escape()?),
+                    _ => return Err(invalid_json()),
+                },
+                char => string.push(char),
+            }
+        }
+    }
+
+    /// Lee los 4 digitos hexadecimales de un \u, combinando los pares surrogate de UTF-16.
+    fn parse_unicode_escape(&mut self) -> Result<char, CustomError> {
+        let high = self.parse_hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(invalid_json);
+        }
+        self.expect("\\u")?;
+        let low = self.parse_hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(invalid_json());
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).ok_or_else(invalid_json)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, CustomError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = self.next()?.to_digit(16).ok_or_else(invalid_json)?;
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, CustomError> {
+        self.expect("[")?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(JsonValue::Array(values)),
+                _ => return Err(invalid_json()),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, CustomError> {
+        self.expect("{")?;
+        let mut entries = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            entries.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(JsonValue::Object(entries)),
+                _ => return Err(invalid_json()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_json() {
+        let value = JsonValue::parse(
+            r#" {"method": "sendtoaddress", "params": ["tb1q", 0.5, true, null], "id": -1e1, "memo": "\"aé😀\n"} "#,
+        )
+        .unwrap();
+        assert_eq!(value.get("method").unwrap().as_str(), Some("sendtoaddress"));
+        let params = value.get("params").unwrap().as_array().unwrap();
+        assert_eq!(params[1].as_f64(), Some(0.5));
+        assert_eq!(params[2], JsonValue::Bool(true));
+        assert_eq!(params[3], JsonValue::Null);
+        assert_eq!(value.get("id").unwrap().as_f64(), Some(-10.0));
+        assert_eq!(value.get("memo").unwrap().as_str(), Some("\"aé😀\n"));
+    }
+
+    #[test]
+    fn parse_invalid_json() {
+        assert!(JsonValue::parse("").is_err());
+        assert!(JsonValue::parse("{\"a\":1,}").is_err());
+        assert!(JsonValue::parse("[1 2]").is_err());
+        assert!(JsonValue::parse("\"abc").is_err());
+        assert!(JsonValue::parse("{} {}").is_err());
+    }
+
+    #[test]
+    fn serialize_json() {
+        let value = JsonValue::object(vec![
+            ("result", JsonValue::Number(0.00012345)),
+            ("count", JsonValue::Number(5.0)),
+            ("error", JsonValue::Null),
+            (
+                "list",
+                JsonValue::Array(vec![
+                    JsonValue::String(String::from("a\"b\n")),
+                    JsonValue::Bool(false),
+                ]),
+            ),
+        ]);
+        let text = value.to_string();
+        assert_eq!(
+            text,
+            r#"{"result":0.00012345,"count":5,"error":null,"list":["a\"b\n",false]}"#
+        );
+        assert_eq!(JsonValue::parse(&text).unwrap(), value);
+    }
+}
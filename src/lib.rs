@@ -1,15 +1,45 @@
+pub mod address;
+pub mod atomic_file;
+pub mod base64;
+pub mod block_locator;
+pub mod block_parser;
+pub mod block_store;
+pub mod chain_validator;
+pub mod codec;
 pub mod config;
+pub mod discovery;
+pub mod download_checkpoint;
+pub mod encoding;
 pub mod error;
+pub mod exchange_rate;
+pub mod fee_estimator;
 pub mod gui;
+pub mod headers_store;
+pub mod json;
 pub mod logger;
 pub mod loops;
+pub mod merkle_proof;
 pub mod message;
 pub mod messages;
+pub mod metrics;
+pub mod mock_peer;
+pub mod network;
 pub mod node;
 pub mod node_state;
+pub mod notifications;
 pub mod parser;
+pub mod payment_uri;
 pub mod peer;
+pub mod proxy;
+pub mod psbt;
+pub mod rpc;
+pub mod shutdown;
+pub mod signer;
+pub mod signet;
 pub mod states;
 pub mod structs;
+pub mod transaction_builder;
 pub mod utils;
+pub mod utxo_set;
 pub mod wallet;
+pub mod wallet_store;
@@ -1,81 +1,308 @@
-use std::fs;
+use std::fs::{self, File};
 use std::io::Write;
+use std::panic::Location;
 use std::path::Path;
+use std::str::FromStr;
 use std::{
     fs::OpenOptions,
     sync::mpsc::{self, Sender},
     thread,
 };
 
-use chrono::Local;
+use chrono::{DateTime, Local};
 use gtk::glib;
 
-use crate::error::CustomError;
+use crate::error::{CustomError, StorageError};
 use crate::gui::init::GUIEvents;
 
+/// Formato de la fecha y hora con la que se registra cada log.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Tamaño por defecto en bytes a partir del cual se rota el archivo de logs.
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+/// Cantidad por defecto de archivos de logs rotados que se conservan.
+const DEFAULT_MAX_FILES: usize = 5;
+
+/// LogLevel es la importancia de un log, ordenada de menor a mayor.
+/// Solo se registran los logs con nivel mayor o igual al nivel configurado en el logger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl LogLevel {
+    /// Devuelve el nombre del nivel como se muestra en los logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warning => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = CustomError;
+
+    /// Lee un nivel a partir de su nombre: debug, info, warn o error.
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.to_lowercase().as_str() {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" | "warning" => Ok(LogLevel::Warning),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(CustomError::Validation(format!(
+                "Invalid log level {level}"
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-/// Log es el tipo de dato que se envia al logger.
-/// Puede ser un Message o un Error.
-/// Los Message reciben un String y esos seran los logs que se guarden en el archivo, impriman en consola y se muestren en la interfaz.
+/// Log es el contenido de un log, que se envia al logger dentro de un LogEntry.
+/// Los Debug, Message y Warning reciben un String y esos seran los logs que se guarden en el archivo, impriman en consola y se muestren en la interfaz,
+/// con nivel Debug, Info y Warning respectivamente.
 /// Los Error reciben un CustomError y haran los mismo que los Message, y ademas muestran una ventana popup con el error.
+/// SetLevel cambia el nivel minimo de los logs que se registran a partir de ese momento.
+/// Terminate termina el thread del logger.
 pub enum Log {
+    Debug(String),
     Message(String),
+    Warning(String),
     Error(CustomError),
+    SetLevel(LogLevel),
     Terminate,
 }
 
+impl Log {
+    /// Devuelve el nivel del log, o None para Terminate.
+    /// El cambio de nivel se registra como un log de nivel Info.
+    pub fn level(&self) -> Option<LogLevel> {
+        match self {
+            Log::Debug(_) => Some(LogLevel::Debug),
+            Log::Message(_) | Log::SetLevel(_) => Some(LogLevel::Info),
+            Log::Warning(_) => Some(LogLevel::Warning),
+            Log::Error(_) => Some(LogLevel::Error),
+            Log::Terminate => None,
+        }
+    }
+
+    /// Devuelve el texto del log, sin fecha ni nivel.
+    pub fn text(&self) -> String {
+        match self {
+            Log::Debug(string) | Log::Message(string) | Log::Warning(string) => string.clone(),
+            Log::Error(error) => error.to_string(),
+            Log::SetLevel(level) => format!("Log level set to {}", level.as_str()),
+            Log::Terminate => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// LogEntry es el tipo de dato que se envia al logger por el channel, y que el logger reenvia a la interfaz.
+/// Los elementos son:
+/// - timestamp: Fecha y hora en la que se genero el log.
+/// - source: Modulo desde el que se envio el log, por ejemplo loops::peer_stream_loop.
+/// - log: Contenido del log, del que se obtiene su nivel.
+pub struct LogEntry {
+    pub timestamp: DateTime<Local>,
+    pub source: String,
+    pub log: Log,
+}
+
+impl LogEntry {
+    /// Crea un LogEntry con la fecha y hora actual y el modulo desde el que se llama a la funcion.
+    #[track_caller]
+    pub fn new(log: Log) -> Self {
+        Self {
+            timestamp: Local::now(),
+            source: module_of(Location::caller().file()),
+            log,
+        }
+    }
+
+    /// Devuelve el nivel del log, o None para Terminate.
+    pub fn level(&self) -> Option<LogLevel> {
+        self.log.level()
+    }
+
+    /// Devuelve la linea con la que se registra el log: fecha, nivel, modulo y texto.
+    pub fn line(&self) -> String {
+        format!(
+            "[{}] [{}] [{}] {}",
+            self.timestamp.format(TIMESTAMP_FORMAT),
+            self.level().map(|level| level.as_str()).unwrap_or_default(),
+            self.source,
+            self.log.text()
+        )
+    }
+}
+
+/// Devuelve el modulo al que pertenece un archivo del crate, por ejemplo loops::peer_stream_loop para
+/// src/loops/peer_stream_loop.rs, aunque el path sea absoluto. Los archivos fuera de src, como los tests de
+/// integracion, conservan su path.
+fn module_of(file: &str) -> String {
+    let file = file.replace('\\', "/");
+    let path = match file.rfind("/src/") {
+        Some(index) => &file[index + "/src/".len()..],
+        None => match file.strip_prefix("src/") {
+            Some(path) => path,
+            None => return file,
+        },
+    };
+    let path = path.strip_suffix(".rs").unwrap_or(path);
+    let path = path.strip_suffix("/mod").unwrap_or(path);
+    path.replace('/', "::")
+}
+
+/// LoggerOptions es la configuracion del logger.
+/// Los elementos son:
+/// - level: Nivel minimo de los logs que se registran.
+/// - max_file_size: Tamaño en bytes a partir del cual se rota el archivo de logs.
+/// - max_files: Cantidad de archivos rotados que se conservan, el archivo con sufijo .1 es el mas reciente.
+#[derive(Debug, Clone)]
+pub struct LoggerOptions {
+    pub level: LogLevel,
+    pub max_file_size: u64,
+    pub max_files: usize,
+}
+
+impl Default for LoggerOptions {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+}
+
+/// LogFile es el archivo donde se guardan los logs, que se rota al superar el tamaño maximo.
+/// Los elementos son:
+/// - path: Ubicacion del archivo de logs.
+/// - file: Archivo de logs abierto.
+/// - size: Tamaño actual del archivo en bytes.
+/// - max_file_size: Tamaño a partir del cual se rota el archivo.
+/// - max_files: Cantidad de archivos rotados que se conservan.
+struct LogFile {
+    path: String,
+    file: File,
+    size: u64,
+    max_file_size: u64,
+    max_files: usize,
+}
+
+impl LogFile {
+    /// Crea el archivo de logs en la ubicacion recibida. Si ya existe, lo borra.
+    fn new(path: &String, options: &LoggerOptions) -> Result<Self, CustomError> {
+        if Path::new(path).exists() {
+            fs::remove_file(path)
+                .map_err(|_| CustomError::Storage(StorageError::CannotRemoveFile))?;
+        }
+        Ok(Self {
+            path: path.clone(),
+            file: open_log_file(path)?,
+            size: 0,
+            max_file_size: options.max_file_size,
+            max_files: options.max_files,
+        })
+    }
+
+    /// Escribe una linea en el archivo, rotandolo antes si con ella supera el tamaño maximo.
+    fn write_line(&mut self, line: &str) -> Result<(), CustomError> {
+        let line_size = line.len() as u64 + 1;
+        if self.size > 0 && self.size + line_size > self.max_file_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line_size;
+        Ok(())
+    }
+
+    /// Renombra el archivo actual con el sufijo .1, desplaza los archivos rotados anteriores
+    /// y borra el mas antiguo si se supera max_files. Luego crea un archivo nuevo.
+    fn rotate(&mut self) -> Result<(), CustomError> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)
+                .map_err(|_| CustomError::Storage(StorageError::CannotRemoveFile))?;
+        } else {
+            let oldest = format!("{}.{}", self.path, self.max_files);
+            if Path::new(&oldest).exists() {
+                fs::remove_file(&oldest)
+                    .map_err(|_| CustomError::Storage(StorageError::CannotRemoveFile))?;
+            }
+            for index in (1..self.max_files).rev() {
+                let rotated = format!("{}.{}", self.path, index);
+                if Path::new(&rotated).exists() {
+                    fs::rename(&rotated, format!("{}.{}", self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+        self.file = open_log_file(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_log_file(path: &String) -> Result<File, CustomError> {
+    Ok(OpenOptions::new()
+        .create_new(true)
+        .append(true)
+        .open(path)?)
+}
+
 /// Logger es una estructura que contiene los elementos necesarios para manejar los logs.
 /// Los elementos son:
 /// - tx: Sender para enviar logs al logger.
 pub struct Logger {
-    pub tx: Sender<Log>,
+    pub tx: Sender<LogEntry>,
     pub thread: thread::JoinHandle<Result<(), CustomError>>,
 }
 
 impl Logger {
+    /// Inicializa el logger con las opciones por defecto.
+    /// Ver with_options.
+    pub fn new(
+        filename: &String,
+        gui_sender: glib::Sender<GUIEvents>,
+    ) -> Result<Self, CustomError> {
+        Self::with_options(filename, LoggerOptions::default(), gui_sender)
+    }
+
     /// Inicializa el logger.
     /// Si el archivo donde se guardan los logs existe, lo borra.
-    /// Crea el archivo de logs en la ubicacion recibida
+    /// Crea el archivo de logs en la ubicacion recibida, que se rota segun las opciones recibidas.
     /// Inicializa el thread que escucha los Logs recibidos por un channel que crea.
-    /// Los logs se manejan como se comenta en el enum Log.
-    pub fn new(
+    /// Los logs con nivel menor al configurado se descartan, el resto se manejan como se comenta en el enum Log.
+    /// Cada log se registra con la fecha y hora y el modulo de su LogEntry.
+    pub fn with_options(
         filename: &String,
+        options: LoggerOptions,
         gui_sender: glib::Sender<GUIEvents>,
     ) -> Result<Self, CustomError> {
-        let (tx, rx) = mpsc::channel::<Log>();
-
-        if Path::new(filename).exists() {
-            fs::remove_file(filename).map_err(|_| CustomError::CannotRemoveFile)?;
-        }
-
-        let mut file = OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .append(true)
-            .open(filename)?;
+        let (tx, rx) = mpsc::channel::<LogEntry>();
+        let mut file = LogFile::new(filename, &options)?;
+        let mut level = options.level;
 
         let thread = thread::spawn(move || -> Result<(), CustomError> {
             while let Ok(message) = rx.recv() {
-                match message {
-                    Log::Message(ref string) => {
-                        let current_time = Local::now();
-                        let formatted_time = current_time.format("%Y-%m-%d %H:%M:%S");
-                        println!("[{}] {}", formatted_time, string);
-                        writeln!(file, "[{}] {}", formatted_time, string)?;
-                        if let Err(error) = gui_sender.send(GUIEvents::Log(message)) {
-                            println!("Error sending log message to gui: {}", error);
-                        }
-                    }
-                    Log::Error(ref error) => {
-                        let current_time = Local::now();
-                        let formatted_time = current_time.format("%Y-%m-%d %H:%M:%S");
-                        println!("[{}] [ERROR] {}", formatted_time, error);
-                        writeln!(file, "[{}] [ERROR] {}", formatted_time, error)?;
-                        if let Err(error) = gui_sender.send(GUIEvents::Log(message)) {
-                            println!("Error sending log error to gui: {}", error);
-                        }
-                    }
-                    Log::Terminate => break,
+                let Some(message_level) = message.level() else { break };
+                if let Log::SetLevel(new_level) = message.log {
+                    level = new_level;
+                }
+                if message_level < level {
+                    continue;
+                }
+
+                let line = message.line();
+                println!("{}", line);
+                file.write_line(&line)?;
+                if let Err(error) = gui_sender.send(GUIEvents::Log(message)) {
+                    println!("Error sending log to gui: {}", error);
                 }
             }
             Ok(())
@@ -85,17 +312,19 @@ impl Logger {
     }
 
     /// Devuelve el sender para enviar logs al logger.
-    pub fn get_sender(&self) -> Sender<Log> {
+    pub fn get_sender(&self) -> Sender<LogEntry> {
         self.tx.clone()
     }
 }
 
 /// Funcion auxiliar para enviar logs al logger.
+/// El log se envia en un LogEntry con la fecha y hora actual y el modulo desde el que se llama a la funcion.
 /// Si no se puede enviar el log, se imprime el error y se imprime el mensaje original.
-pub fn send_log(logger_sender: &Sender<Log>, message: Log) {
-    if let Err(error) = logger_sender.send(message.clone()) {
+#[track_caller]
+pub fn send_log(logger_sender: &Sender<LogEntry>, message: Log) {
+    if let Err(error) = logger_sender.send(LogEntry::new(message)) {
         println!("Error sending log message: {}", error);
-        println!("Original message: {:?}", message);
+        println!("Original message: {}", error.0.line());
     }
 }
 
@@ -114,17 +343,13 @@ mod tests {
         let logger = Logger::new(&String::from("test1.txt"), tx).unwrap();
         let sender = logger.get_sender();
         let timestamp_string_1 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        sender
-            .send(Log::Message(String::from("Sender test 1")))
-            .unwrap();
+        send_log(&sender, Log::Message(String::from("Sender test 1")));
         let timestamp_string_2 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        sender
-            .send(Log::Message(String::from("Sender test 2")))
-            .unwrap();
+        send_log(&sender, Log::Message(String::from("Sender test 2")));
         thread::sleep(time::Duration::from_millis(100));
 
         let result = format!(
-            "[{}] Sender test 1\n[{}] Sender test 2\n",
+            "[{}] [INFO] [logger] Sender test 1\n[{}] [INFO] [logger] Sender test 2\n",
             timestamp_string_1, timestamp_string_2
         );
         assert_eq!(fs::read_to_string("test1.txt").unwrap(), result);
@@ -138,17 +363,19 @@ mod tests {
         let logger = Logger::new(&String::from("test2.txt"), tx).unwrap();
         let sender = logger.get_sender();
         let timestamp_string_1 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        sender
-            .send(Log::Error(CustomError::CannotRemoveFile))
-            .unwrap();
+        send_log(
+            &sender,
+            Log::Error(CustomError::Storage(StorageError::CannotRemoveFile)),
+        );
         let timestamp_string_2 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        sender
-            .send(Log::Error(CustomError::CannotRemoveFile))
-            .unwrap();
+        send_log(
+            &sender,
+            Log::Error(CustomError::Storage(StorageError::CannotRemoveFile)),
+        );
         thread::sleep(time::Duration::from_millis(100));
 
         let result = format!(
-            "[{}] [ERROR] Error: cannot remove file\n[{}] [ERROR] Error: cannot remove file\n",
+            "[{}] [ERROR] [logger] Error: cannot remove file\n[{}] [ERROR] [logger] Error: cannot remove file\n",
             timestamp_string_1, timestamp_string_2
         );
         assert_eq!(fs::read_to_string("test2.txt").unwrap(), result);
@@ -163,17 +390,13 @@ mod tests {
         let sender1 = logger.get_sender();
         let sender2 = logger.get_sender();
         let timestamp_string_1 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        sender1
-            .send(Log::Message(String::from("Sender test 1")))
-            .unwrap();
+        send_log(&sender1, Log::Message(String::from("Sender test 1")));
         let timestamp_string_2 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        sender2
-            .send(Log::Message(String::from("Sender test 2")))
-            .unwrap();
+        send_log(&sender2, Log::Message(String::from("Sender test 2")));
         thread::sleep(time::Duration::from_millis(100));
 
         let result = format!(
-            "[{}] Sender test 1\n[{}] Sender test 2\n",
+            "[{}] [INFO] [logger] Sender test 1\n[{}] [INFO] [logger] Sender test 2\n",
             timestamp_string_1, timestamp_string_2
         );
         assert_eq!(fs::read_to_string("test3.txt").unwrap(), result);
@@ -188,28 +411,105 @@ mod tests {
         let sender1 = logger.get_sender();
         let sender2 = logger.get_sender();
         let timestamp_string_1 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        thread::spawn(move || {
-            sender1
-                .send(Log::Message(String::from("Sender test 1")))
-                .unwrap()
-        })
-        .join()
-        .unwrap();
+        thread::spawn(move || send_log(&sender1, Log::Message(String::from("Sender test 1"))))
+            .join()
+            .unwrap();
         let timestamp_string_2 = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        thread::spawn(move || {
-            sender2
-                .send(Log::Message(String::from("Sender test 2")))
-                .unwrap()
-        })
-        .join()
-        .unwrap();
+        thread::spawn(move || send_log(&sender2, Log::Message(String::from("Sender test 2"))))
+            .join()
+            .unwrap();
         thread::sleep(time::Duration::from_millis(100));
 
         let result = format!(
-            "[{}] Sender test 1\n[{}] Sender test 2\n",
+            "[{}] [INFO] [logger] Sender test 1\n[{}] [INFO] [logger] Sender test 2\n",
             timestamp_string_1, timestamp_string_2
         );
         assert_eq!(fs::read_to_string("test4.txt").unwrap(), result);
         fs::remove_file("test4.txt").unwrap();
     }
+
+    #[test]
+    fn logs_below_level_are_discarded() {
+        let (tx, _rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+        let options = LoggerOptions {
+            level: LogLevel::Warning,
+            ..LoggerOptions::default()
+        };
+        let logger = Logger::with_options(&String::from("test5.txt"), options, tx).unwrap();
+        let sender = logger.get_sender();
+        send_log(&sender, Log::Debug(String::from("debug 1")));
+        send_log(&sender, Log::Message(String::from("info 1")));
+        send_log(&sender, Log::Warning(String::from("warn 1")));
+        send_log(&sender, Log::SetLevel(LogLevel::Debug));
+        send_log(&sender, Log::Debug(String::from("debug 2")));
+        thread::sleep(time::Duration::from_millis(100));
+
+        let content = fs::read_to_string("test5.txt").unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("[WARN] [logger] warn 1"));
+        assert!(lines[1].ends_with("[INFO] [logger] Log level set to DEBUG"));
+        assert!(lines[2].ends_with("[DEBUG] [logger] debug 2"));
+        fs::remove_file("test5.txt").unwrap();
+    }
+
+    #[test]
+    fn log_file_gets_rotated() {
+        let (tx, _rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+        let options = LoggerOptions {
+            level: LogLevel::Info,
+            max_file_size: 64,
+            max_files: 2,
+        };
+        let logger = Logger::with_options(&String::from("test6.txt"), options, tx).unwrap();
+        let sender = logger.get_sender();
+        for i in 0..4 {
+            send_log(&sender, Log::Message(format!("Rotation test {i}")));
+        }
+        thread::sleep(time::Duration::from_millis(100));
+
+        // cada linea ocupa mas de la mitad del tamaño maximo, por lo que cada archivo tiene una sola
+        assert!(fs::read_to_string("test6.txt")
+            .unwrap()
+            .ends_with("Rotation test 3\n"));
+        assert!(fs::read_to_string("test6.txt.1")
+            .unwrap()
+            .ends_with("Rotation test 2\n"));
+        assert!(fs::read_to_string("test6.txt.2")
+            .unwrap()
+            .ends_with("Rotation test 1\n"));
+        assert!(!Path::new("test6.txt.3").exists());
+        for file in ["test6.txt", "test6.txt.1", "test6.txt.2"] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn log_source_is_the_module_of_the_caller() {
+        let (sender, receiver) = mpsc::channel();
+        send_log(&sender, Log::Message(String::from("source test")));
+        let entry = receiver.recv().unwrap();
+        assert_eq!(entry.source, "logger");
+        assert!(entry.line().ends_with("[INFO] [logger] source test"));
+
+        assert_eq!(
+            module_of("src/loops/peer_stream_loop.rs"),
+            "loops::peer_stream_loop"
+        );
+        assert_eq!(module_of("src/states/mod.rs"), "states");
+        assert_eq!(module_of("/home/user/btc-wallet/src/node.rs"), "node");
+        assert_eq!(
+            module_of("tests/integration/main.rs"),
+            "tests/integration/main.rs"
+        );
+    }
+
+    #[test]
+    fn log_level_from_str() {
+        assert_eq!(LogLevel::from_str("debug").unwrap(), LogLevel::Debug);
+        assert_eq!(LogLevel::from_str("WARN").unwrap(), LogLevel::Warning);
+        assert!(LogLevel::from_str("verbose").is_err());
+    }
 }
@@ -0,0 +1,64 @@
+use std::{
+    net::SocketAddrV6,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+    shutdown::ShutdownSignal,
+};
+
+/// block_download_loop es una funcion que genera un loop que se encarga de supervisar la descarga de bloques.
+/// Reasigna a otros peers los bloques pedidos a peers que dejaron de entregarlos y reparte los bloques encolados
+/// entre los peers con lugar para nuevos pedidos. Tambien vuelve a pedir las transacciones cuyo pedido fallo
+/// y descarta los compact blocks cuyas transacciones faltantes no llegaron.
+/// Los getdata se envian luego de liberar el lock del NodeState.
+/// Los elementos son:
+/// - node_state_ref: Referencia al estado del nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+/// - shutdown: ShutdownSignal, el loop termina cuando se solicita el cierre del nodo.
+pub fn block_download_loop(
+    node_state_ref: Arc<Mutex<NodeState>>,
+    logger_sender: mpsc::Sender<LogEntry>,
+    shutdown: ShutdownSignal,
+) -> thread::JoinHandle<Result<(), CustomError>> {
+    thread::spawn(move || -> Result<(), CustomError> {
+        while !shutdown.wait(Duration::from_secs(5)) {
+            let mut node_state = node_state_ref.lock()?;
+
+            for address in node_state.check_stalled_peers()? {
+                send_log(
+                    &logger_sender,
+                    Log::Warning(format!(
+                        "Peer {} stalled the block download, reassigning its blocks...",
+                        address
+                    )),
+                );
+            }
+
+            node_state.evict_stale_partial_blocks();
+
+            let requests = node_state.take_data_requests()?;
+            drop(node_state);
+
+            let failed: Vec<SocketAddrV6> = requests
+                .into_iter()
+                .filter_map(|request| {
+                    let address = request.address;
+                    request.send().err().map(|_| address)
+                })
+                .collect();
+            if !failed.is_empty() {
+                let mut node_state = node_state_ref.lock()?;
+                for address in failed {
+                    node_state.request_failed(address);
+                }
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,60 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    error::CustomError,
+    exchange_rate::RateProvider,
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+    shutdown::ShutdownSignal,
+};
+
+/// ExchangeRateLoop es el loop que se encarga de mantener actualizado el precio de bitcoin en monedas fiat,
+/// que la interfaz grafica muestra junto a los montos en BTC.
+/// Si no se puede obtener el precio se mantiene el ultimo obtenido y se vuelve a intentar en el siguiente intervalo.
+/// Los elementos son:
+/// - logger_sender: Sender para enviar logs al logger
+/// - node_state_ref: Referencia al estado del nodo
+/// - provider: RateProvider del que se obtiene el precio
+/// - interval: Tiempo en segundos entre cada actualizacion del precio
+/// - shutdown: ShutdownSignal, el loop termina cuando se solicita el cierre del nodo
+pub struct ExchangeRateLoop {
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub node_state_ref: Arc<Mutex<NodeState>>,
+    pub provider: Box<dyn RateProvider>,
+    pub interval: u64,
+    pub shutdown: ShutdownSignal,
+}
+
+impl ExchangeRateLoop {
+    /// Inicializa el loop en un thread.
+    pub fn spawn(self) -> JoinHandle<Result<(), CustomError>> {
+        thread::spawn(move || -> Result<(), CustomError> { self.event_loop() })
+    }
+
+    fn event_loop(&self) -> Result<(), CustomError> {
+        loop {
+            // el pedido se hace sin tomar el lock del estado del nodo
+            match self.provider.get_rates() {
+                Ok(exchange_rates) => self
+                    .node_state_ref
+                    .lock()?
+                    .set_exchange_rates(exchange_rates)?,
+                Err(error) => send_log(
+                    &self.logger_sender,
+                    Log::Warning(format!(
+                        "Cannot update the exchange rates from {}: {}",
+                        self.provider.name(),
+                        error
+                    )),
+                ),
+            }
+            if self.shutdown.wait(Duration::from_secs(self.interval)) {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+    shutdown::ShutdownSignal,
+    states::{filters_state::FiltersRequest, sync_progress_state::SyncMilestone},
+};
+
+use super::node_action_loop::NodeAction;
+
+/// filter_sync_loop es una funcion que genera un loop que se encarga de descargar los filtros compactos (BIP 157/158).
+/// Una vez sincronizados los headers, pide por lotes los cfheaders y cfilters de los bloques anteriores al IBD
+/// a un peer que sirva filtros. Las respuestas se procesan en el NodeActionLoop, que solo pide los bloques que coinciden con las wallets.
+/// Los elementos son:
+/// - node_state_ref: Referencia al estado del nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+/// - node_action_sender: Sender para avisar al nodo que termino el escaneo de los filtros.
+/// - shutdown: ShutdownSignal, el loop termina cuando se solicita el cierre del nodo.
+pub fn filter_sync_loop(
+    node_state_ref: Arc<Mutex<NodeState>>,
+    logger_sender: mpsc::Sender<LogEntry>,
+    node_action_sender: mpsc::Sender<NodeAction>,
+    shutdown: ShutdownSignal,
+) -> thread::JoinHandle<Result<(), CustomError>> {
+    thread::spawn(move || -> Result<(), CustomError> {
+        while !shutdown.wait(Duration::from_secs(5)) {
+            let mut node_state = node_state_ref.lock()?;
+
+            if !node_state.start_filter_sync() {
+                drop(node_state);
+                continue;
+            }
+
+            if node_state.is_filter_sync_finished() {
+                node_action_sender
+                    .send(NodeAction::SyncMilestone(SyncMilestone::FiltersScanned))?;
+                break;
+            }
+
+            let Some(request) = node_state.next_filters_request()? else { continue };
+            let Some(peer) = node_state.get_filters_peer() else {
+                send_log(
+                    &logger_sender,
+                    Log::Message("No peers serving compact filters".to_string()),
+                );
+                continue;
+            };
+
+            let address = peer.address;
+            let sent = match request {
+                FiltersRequest::Headers(get_cfheaders) => peer.send(get_cfheaders),
+                FiltersRequest::Filters(get_cfilters) => peer.send(get_cfilters),
+            };
+            if sent.is_err() {
+                node_state.remove_peer(address);
+            }
+        }
+        Ok(())
+    })
+}
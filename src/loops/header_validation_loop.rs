@@ -0,0 +1,181 @@
+use std::{
+    net::SocketAddrV6,
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    error::{ChainError, CustomError},
+    logger::{send_log, Log, LogEntry},
+    message::{Command, Message},
+    messages::headers::Headers,
+    states::peer_score_state::Misbehavior,
+};
+
+use super::node_action_loop::NodeAction;
+
+/// Cantidad de threads que validan headers, uno por cada peer del que se descargan headers en simultaneo.
+pub const HEADER_VALIDATION_WORKERS: usize = 3;
+
+/// HeaderBatch es un mensaje headers recibido de un peer que todavia no se valido.
+/// Los elementos son:
+/// - address: Direccion del peer que envio el mensaje.
+/// - payload: Payload del mensaje.
+pub struct HeaderBatch {
+    pub address: SocketAddrV6,
+    pub payload: Vec<u8>,
+}
+
+/// HeaderValidationLoop es un pool de threads que parsea los mensajes headers y valida la proof of work de cada header,
+/// para que un lote de 2000 headers no demore al NetworkLoop en leer los mensajes de los demas peers.
+/// Cada peer se asigna a un thread al conectarse, por lo que sus lotes se validan en el orden en que llegaron.
+/// Los headers validos se envian al nodo con NodeAction::NewHeaders. Si el mensaje no es valido, se penaliza al peer
+/// y se piden los headers a otro.
+/// Los elementos son:
+/// - batch_senders: Senders para enviar lotes a cada thread.
+/// - workers: Threads del pool.
+/// - next_worker: Indice del thread al que se asigna el proximo peer.
+pub struct HeaderValidationLoop {
+    batch_senders: Vec<mpsc::Sender<HeaderBatch>>,
+    workers: Vec<JoinHandle<()>>,
+    next_worker: usize,
+}
+
+impl HeaderValidationLoop {
+    /// Inicializa los threads del pool.
+    pub fn spawn(
+        workers: usize,
+        node_action_sender: mpsc::Sender<NodeAction>,
+        logger_sender: mpsc::Sender<LogEntry>,
+    ) -> Self {
+        let mut batch_senders = vec![];
+        let mut handles = vec![];
+        for _ in 0..workers.max(1) {
+            let (batch_sender, batch_receiver) = mpsc::channel();
+            let node_action_sender = node_action_sender.clone();
+            let logger_sender = logger_sender.clone();
+            batch_senders.push(batch_sender);
+            handles.push(thread::spawn(move || {
+                validate_batches(batch_receiver, node_action_sender, logger_sender)
+            }));
+        }
+        Self {
+            batch_senders,
+            workers: handles,
+            next_worker: 0,
+        }
+    }
+
+    /// Devuelve el Sender del thread que valida los lotes de un peer nuevo, repartiendo los peers entre los threads.
+    pub fn batch_sender(&mut self) -> mpsc::Sender<HeaderBatch> {
+        let batch_sender = self.batch_senders[self.next_worker % self.batch_senders.len()].clone();
+        self.next_worker = self.next_worker.wrapping_add(1);
+        batch_sender
+    }
+
+    /// Espera a que los threads terminen de validar los lotes pendientes.
+    /// Solo termina una vez que se liberaron los Sender entregados a los peers.
+    /// Devuelve CustomError si algun thread termino con un panic.
+    pub fn stop(self) -> Result<(), CustomError> {
+        drop(self.batch_senders);
+        for worker in self.workers {
+            worker.join().map_err(|_| CustomError::UnknownError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Valida los lotes recibidos hasta que se liberan todos sus Sender o el NodeActionLoop termina.
+fn validate_batches(
+    batch_receiver: mpsc::Receiver<HeaderBatch>,
+    node_action_sender: mpsc::Sender<NodeAction>,
+    logger_sender: mpsc::Sender<LogEntry>,
+) {
+    for batch in batch_receiver {
+        let actions = match Headers::parse(&mut batch.payload.as_slice()) {
+            Ok(headers) => vec![NodeAction::NewHeaders(batch.address, headers)],
+            Err(error) => {
+                let error = error.with_peer(batch.address, Some(Command::Headers));
+                let misbehavior = match error.root() {
+                    CustomError::Chain(ChainError::HeaderInvalidPoW) => Misbehavior::InvalidHeaders,
+                    _ => Misbehavior::ProtocolViolation,
+                };
+                send_log(
+                    &logger_sender,
+                    Log::Warning(format!("Invalid headers: {}", error)),
+                );
+                vec![
+                    NodeAction::PeerMisbehaving(batch.address, misbehavior),
+                    NodeAction::GetHeadersError,
+                ]
+            }
+        };
+        for action in actions {
+            if node_action_sender.send(action).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv6Addr, time::Duration};
+
+    use crate::structs::{block_header::BlockHeader, hash256::Hash256};
+
+    use super::*;
+
+    /// Devuelve un mensaje headers con un header minado con la dificultad minima de regtest, o uno que no la cumple.
+    fn headers_payload(valid: bool) -> Vec<u8> {
+        let mut nonce = 0;
+        loop {
+            let header = BlockHeader {
+                version: 1,
+                prev_block_hash: Hash256::ZERO,
+                merkle_root: Hash256::ZERO,
+                timestamp: 1677449562,
+                bits: 0x207fffff,
+                nonce,
+                hash: Hash256::ZERO,
+                broadcasted: false,
+                block_downloaded: false,
+            };
+            nonce += 1;
+            if BlockHeader::parse(header.serialize()).is_ok() == valid {
+                let mut headers = Headers::new();
+                headers.headers.push(header);
+                return headers.serialize();
+            }
+        }
+    }
+
+    #[test]
+    fn validates_the_batches_of_each_peer_in_order() {
+        let (node_action_sender, node_actions) = mpsc::channel();
+        let (logger_sender, _logs) = mpsc::channel();
+        let mut validation = HeaderValidationLoop::spawn(2, node_action_sender, logger_sender);
+        let address = SocketAddrV6::new(Ipv6Addr::LOCALHOST, 18333, 0, 0);
+
+        let batch_sender = validation.batch_sender();
+        for payload in [headers_payload(true), headers_payload(false)] {
+            batch_sender.send(HeaderBatch { address, payload }).unwrap();
+        }
+        drop(batch_sender);
+
+        let timeout = Duration::from_secs(5);
+        assert!(matches!(
+            node_actions.recv_timeout(timeout).unwrap(),
+            NodeAction::NewHeaders(peer, headers) if peer == address && headers.headers.len() == 1
+        ));
+        assert!(matches!(
+            node_actions.recv_timeout(timeout).unwrap(),
+            NodeAction::PeerMisbehaving(peer, Misbehavior::InvalidHeaders) if peer == address
+        ));
+        assert!(matches!(
+            node_actions.recv_timeout(timeout).unwrap(),
+            NodeAction::GetHeadersError
+        ));
+        validation.stop().unwrap();
+    }
+}
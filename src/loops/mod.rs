@@ -1,5 +1,10 @@
+pub mod block_download_loop;
+pub mod exchange_rate_loop;
+pub mod filter_sync_loop;
+pub mod header_validation_loop;
+pub mod network_loop;
 pub mod node_action_loop;
 pub mod peer_action_loop;
+pub mod peer_discovery_loop;
 pub mod peer_stream_loop;
-pub mod pending_blocks_loop;
 pub mod tcp_listener_loop;
@@ -0,0 +1,800 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, ErrorKind, Read, Write},
+    net,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use mio::{event::Event, net::TcpStream, Events, Interest, Poll, Token, Waker};
+
+use crate::{
+    block_parser::BlockStreamParser,
+    error::{CustomError, NetworkError, ParseError},
+    logger::{send_log, Log, LogEntry},
+    message::{Command, MessageHeader},
+    states::{
+        peer_info_state::{PeerInfo, PeerInfoRegistry},
+        peer_score_state::Misbehavior,
+    },
+};
+
+use super::{
+    header_validation_loop::{HeaderValidationLoop, HEADER_VALIDATION_WORKERS},
+    node_action_loop::NodeAction,
+    peer_action_loop::{PeerAction, PeerActionLoop},
+    peer_stream_loop::PeerStreamLoop,
+};
+
+/// Token reservado para despertar al loop desde otros threads.
+const WAKER: Token = Token(0);
+/// Tiempo maximo que se espera un evento antes de atender las acciones pendientes y el keep alive de los peers.
+const TICK: Duration = Duration::from_millis(100);
+/// Tamaño maximo del payload de un mensaje, los peers que anuncian uno mayor violan el protocolo.
+const MAX_PAYLOAD_SIZE: usize = 32 * 1024 * 1024;
+/// Tamaño del header de los mensajes.
+const HEADER_SIZE: usize = 24;
+/// Cantidad maxima de bloques recibidos que pueden esperar a que los procese el NodeActionLoop.
+/// Al alcanzarla se deja de leer de los peers, que dejan de enviar cuando se llena el buffer TCP.
+pub const MAX_PENDING_BLOCKS: usize = 16;
+
+/// NetworkCommand es una enumeracion de los pedidos que recibe el NetworkLoop de otros threads.
+/// - Register: Comienza a atender la conexion con un peer que ya completo el handshake, con la informacion obtenida en el.
+/// - Write: Envia bytes a un peer.
+/// - Close: Cierra la conexion con un peer.
+pub enum NetworkCommand {
+    Register(PeerWriter, PeerInfo, net::TcpStream),
+    Write(Token, Vec<u8>),
+    Close(Token),
+}
+
+/// BlockBacklog cuenta los bloques enviados al NodeActionLoop que todavia no termino de procesar.
+/// El channel de NodeAction no tiene limite, por lo que sin este contador los peers rapidos podrian llenar la memoria
+/// con bloques parseados durante la descarga inicial mientras el NodeState los procesa.
+#[derive(Clone, Default)]
+pub struct BlockBacklog(Arc<AtomicUsize>);
+
+impl BlockBacklog {
+    /// Crea el contador sin bloques pendientes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registra un bloque enviado al NodeActionLoop.
+    pub fn push(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra un bloque que el NodeActionLoop termino de procesar.
+    pub fn pop(&self) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            });
+    }
+
+    /// Devuelve true si hay MAX_PENDING_BLOCKS bloques o mas esperando a que los procese el NodeActionLoop.
+    pub fn is_full(&self) -> bool {
+        self.0.load(Ordering::Relaxed) >= MAX_PENDING_BLOCKS
+    }
+}
+
+/// NetworkHandle permite a los demas threads registrar conexiones en el NetworkLoop.
+/// Los elementos son:
+/// - command_sender: Sender para enviar comandos al NetworkLoop.
+/// - waker: Waker para despertar al NetworkLoop luego de enviarle un comando.
+/// - next_token: Proximo token a asignar a una conexion.
+#[derive(Clone)]
+pub struct NetworkHandle {
+    command_sender: mpsc::Sender<NetworkCommand>,
+    waker: Arc<Waker>,
+    next_token: Arc<AtomicUsize>,
+}
+
+impl NetworkHandle {
+    /// Crea un PeerWriter para una conexion nueva.
+    /// Los mensajes escritos se envian al peer una vez que se registra su stream.
+    pub fn writer(&self) -> PeerWriter {
+        PeerWriter {
+            token: Token(self.next_token.fetch_add(1, Ordering::Relaxed)),
+            closed: Arc::new(AtomicBool::new(false)),
+            handle: self.clone(),
+        }
+    }
+
+    /// Registra el stream de un peer que ya completo el handshake para que lo atienda el NetworkLoop.
+    /// El NetworkLoop avisa al nodo que el peer se conecto y la altura de su cadena informada en el handshake.
+    /// Devuelve CustomError si no se puede pasar el stream a modo no bloqueante o el NetworkLoop termino.
+    pub fn register(
+        &self,
+        writer: &PeerWriter,
+        info: PeerInfo,
+        stream: net::TcpStream,
+    ) -> Result<(), CustomError> {
+        stream.set_read_timeout(None)?;
+        stream.set_nonblocking(true)?;
+        self.send(NetworkCommand::Register(writer.clone(), info, stream))
+    }
+
+    fn send(&self, command: NetworkCommand) -> Result<(), CustomError> {
+        self.command_sender
+            .send(command)
+            .map_err(|_| CustomError::Network(NetworkError::CannotSendToStream))?;
+        self.waker.wake()?;
+        Ok(())
+    }
+}
+
+/// PeerWriter es el extremo de escritura de la conexion con un peer.
+/// Los bytes escritos se encolan en el NetworkLoop, que los envia cuando el stream lo permite.
+/// Los elementos son:
+/// - token: Token que identifica la conexion en el NetworkLoop.
+/// - closed: Indica si el NetworkLoop cerro la conexion.
+/// - handle: NetworkHandle del NetworkLoop que atiende la conexion.
+#[derive(Clone)]
+pub struct PeerWriter {
+    token: Token,
+    closed: Arc<AtomicBool>,
+    handle: NetworkHandle,
+}
+
+impl PeerWriter {
+    /// Cierra la conexion con el peer.
+    pub fn shutdown(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        let _ = self.handle.send(NetworkCommand::Close(self.token));
+    }
+}
+
+impl Write for PeerWriter {
+    /// Encola los bytes para enviarlos al peer.
+    /// Devuelve un error si la conexion esta cerrada.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed.load(Ordering::Relaxed) {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+        self.handle
+            .send(NetworkCommand::Write(self.token, buf.to_vec()))
+            .map_err(|_| io::Error::from(ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Connection es una conexion con un peer atendida por el NetworkLoop.
+/// Los elementos son:
+/// - stream: Stream no bloqueante del peer.
+/// - closed: Indica a los PeerWriter del peer que la conexion se cerro.
+/// - read_buffer: Bytes recibidos que todavia no forman un mensaje completo.
+/// - block_parser: Parser del bloque que se esta recibiendo, que se procesa a medida que llega en lugar de esperar el payload completo.
+/// - write_buffer: Bytes a enviar que el stream todavia no acepto.
+/// - paused: Indica si se dejo de leer el stream por haber demasiados bloques pendientes, aunque podia tener datos.
+/// - block_backlog: BlockBacklog donde se registran los bloques recibidos que se envian al nodo.
+/// - stream_handler: Maneja los mensajes recibidos del peer.
+/// - action_handler: Maneja las acciones a enviar al peer.
+struct Connection {
+    stream: TcpStream,
+    closed: Arc<AtomicBool>,
+    read_buffer: Vec<u8>,
+    block_parser: Option<BlockStreamParser>,
+    write_buffer: Vec<u8>,
+    paused: bool,
+    block_backlog: BlockBacklog,
+    stream_handler: PeerStreamLoop,
+    action_handler: PeerActionLoop,
+}
+
+impl Connection {
+    /// Lee todo lo disponible en el stream y procesa los mensajes completos luego de cada lectura,
+    /// para que los bloques grandes no se acumulen enteros en memoria.
+    /// Si el NodeActionLoop tiene demasiados bloques pendientes deja de leer y marca la conexion como pausada,
+    /// ya que el stream no vuelve a avisar que tiene datos hasta que se lea todo lo disponible.
+    /// Devuelve CustomError si el peer cerro la conexion o envio un mensaje invalido.
+    fn read(&mut self) -> Result<(), CustomError> {
+        let mut buffer = [0; 64 * 1024];
+        self.paused = false;
+        loop {
+            if self.block_backlog.is_full() {
+                self.paused = true;
+                break;
+            }
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(CustomError::Network(NetworkError::CannotReadStream)),
+                Ok(size) => {
+                    self.stream_handler.record_received(size)?;
+                    self.read_buffer.extend_from_slice(&buffer[..size]);
+                    self.handle_messages()?;
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return Err(CustomError::Network(NetworkError::CannotReadStream)),
+            }
+        }
+        self.handle_messages()
+    }
+
+    /// Procesa los mensajes completos del buffer de lectura.
+    /// Los bloques se parsean a medida que llegan sus transacciones, sin copiar el payload completo.
+    /// Los errores llevan el peer y el comando del mensaje que los causo.
+    fn handle_messages(&mut self) -> Result<(), CustomError> {
+        let address = self.stream_handler.address;
+        loop {
+            if let Some(block_parser) = self.block_parser.as_mut() {
+                let consumed = block_parser
+                    .feed(&self.read_buffer)
+                    .map_err(|error| error.with_peer(address, Some(Command::Block)))?;
+                self.read_buffer.drain(..consumed);
+                if !block_parser.is_complete() {
+                    break;
+                }
+                if let Some(block_parser) = self.block_parser.take() {
+                    // se registra antes de enviarlo, para que el NodeActionLoop no lo descuente antes de contarlo
+                    self.block_backlog.push();
+                    let block = block_parser.finish();
+                    if let Err(error) =
+                        block.and_then(|block| self.stream_handler.handle_parsed_block(block))
+                    {
+                        self.block_backlog.pop();
+                        return Err(error.with_peer(address, Some(Command::Block)));
+                    }
+                }
+                continue;
+            }
+            if self.read_buffer.len() < HEADER_SIZE {
+                break;
+            }
+            let header_buffer: [u8; HEADER_SIZE] = self.read_buffer[..HEADER_SIZE]
+                .try_into()
+                .map_err(|_| CustomError::Network(NetworkError::CannotReadMessageHeader))?;
+            let header = MessageHeader::parse(header_buffer)?;
+            let payload_size = header.payload_size as usize;
+            if payload_size > MAX_PAYLOAD_SIZE {
+                return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid)
+                    .with_peer(address, Some(header.command)));
+            }
+            if header.command == Command::Block {
+                self.stream_handler
+                    .record_message_received(header.command, HEADER_SIZE + payload_size)?;
+                self.read_buffer.drain(..HEADER_SIZE);
+                self.block_parser = Some(BlockStreamParser::new(payload_size, header.checksum));
+                continue;
+            }
+            if self.read_buffer.len() < HEADER_SIZE + payload_size {
+                break;
+            }
+
+            self.stream_handler
+                .record_message_received(header.command, HEADER_SIZE + payload_size)?;
+            let payload = &self.read_buffer[HEADER_SIZE..HEADER_SIZE + payload_size];
+            header
+                .verify_checksum(payload)
+                .and_then(|_| self.stream_handler.handle_message(&header, payload))
+                .map_err(|error| error.with_peer(address, Some(header.command)))?;
+            self.read_buffer.drain(..HEADER_SIZE + payload_size);
+        }
+        Ok(())
+    }
+
+    /// Agrega un mensaje serializado al buffer de escritura, registra su comando y envia lo que el stream acepte.
+    fn queue(&mut self, message: Vec<u8>) -> Result<(), CustomError> {
+        if let Some(header_buffer) = message.get(..HEADER_SIZE) {
+            let header_buffer: [u8; HEADER_SIZE] = header_buffer
+                .try_into()
+                .map_err(|_| CustomError::Network(NetworkError::CannotReadMessageHeader))?;
+            let header = MessageHeader::parse(header_buffer)?;
+            self.stream_handler
+                .record_message_sent(header.command, message.len())?;
+        }
+        self.write_buffer.extend(message);
+        self.write()
+    }
+
+    /// Envia todo lo que el stream acepte sin bloquear.
+    fn write(&mut self) -> Result<(), CustomError> {
+        while !self.write_buffer.is_empty() {
+            match self.stream.write(&self.write_buffer) {
+                Ok(0) => return Err(CustomError::Network(NetworkError::CannotSendToStream)),
+                Ok(size) => {
+                    self.stream_handler.record_sent(size)?;
+                    self.write_buffer.drain(..size);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return Err(CustomError::Network(NetworkError::CannotSendToStream)),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &Event) -> Result<(), CustomError> {
+        if event.is_readable() || event.is_read_closed() || event.is_error() {
+            self.read()?;
+        }
+        if event.is_writable() {
+            self.write()?;
+        }
+        Ok(())
+    }
+}
+
+/// NetworkLoop atiende las conexiones con todos los peers desde un unico thread, sin bloquearse en ninguna.
+/// Lee los mensajes de los peers y se los pasa a su PeerStreamLoop, y reparte los PeerAction recibidos
+/// entre los peers conectados.
+/// Los elementos son:
+/// - poll: Poll en el que se registran los streams de los peers.
+/// - command_receiver: Receiver para recibir comandos de los NetworkHandle.
+/// - peer_action_receiver: Receiver para recibir acciones a realizar por los peers.
+/// - node_action_sender: Sender para enviar acciones al nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+/// - peer_info_ref: Referencia al registro con la informacion de los peers conectados.
+/// - header_validation: HeaderValidationLoop que valida los headers recibidos fuera de este thread.
+/// - connections: Conexiones registradas, por token.
+/// - pending_actions: Acciones recibidas cuando no habia peers conectados.
+/// - next_peer: Indice del proximo peer al que se le asigna una accion.
+/// - block_backlog: BlockBacklog con los bloques que el NodeActionLoop todavia no proceso.
+pub struct NetworkLoop {
+    poll: Poll,
+    command_receiver: mpsc::Receiver<NetworkCommand>,
+    peer_action_receiver: mpsc::Receiver<PeerAction>,
+    node_action_sender: mpsc::Sender<NodeAction>,
+    logger_sender: mpsc::Sender<LogEntry>,
+    peer_info_ref: Arc<Mutex<PeerInfoRegistry>>,
+    header_validation: HeaderValidationLoop,
+    connections: HashMap<Token, Connection>,
+    pending_actions: VecDeque<PeerAction>,
+    next_peer: usize,
+    block_backlog: BlockBacklog,
+}
+
+impl NetworkLoop {
+    /// Crea el loop y el NetworkHandle para registrar conexiones en el.
+    pub fn new(
+        peer_action_receiver: mpsc::Receiver<PeerAction>,
+        node_action_sender: mpsc::Sender<NodeAction>,
+        logger_sender: mpsc::Sender<LogEntry>,
+        peer_info_ref: Arc<Mutex<PeerInfoRegistry>>,
+    ) -> Result<(Self, NetworkHandle), CustomError> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        let (command_sender, command_receiver) = mpsc::channel();
+
+        let handle = NetworkHandle {
+            command_sender,
+            waker,
+            next_token: Arc::new(AtomicUsize::new(WAKER.0 + 1)),
+        };
+        let header_validation = HeaderValidationLoop::spawn(
+            HEADER_VALIDATION_WORKERS,
+            node_action_sender.clone(),
+            logger_sender.clone(),
+        );
+        let network_loop = Self {
+            poll,
+            command_receiver,
+            peer_action_receiver,
+            node_action_sender,
+            logger_sender,
+            peer_info_ref,
+            header_validation,
+            connections: HashMap::new(),
+            pending_actions: VecDeque::new(),
+            next_peer: 0,
+            block_backlog: BlockBacklog::new(),
+        };
+        Ok((network_loop, handle))
+    }
+
+    /// Devuelve el BlockBacklog del loop, el NodeActionLoop debe registrar en el cada bloque que termina de procesar.
+    pub fn block_backlog(&self) -> BlockBacklog {
+        self.block_backlog.clone()
+    }
+
+    /// Inicializa el loop de eventos en un thread.
+    /// Termina al recibir PeerAction::Terminate, una vez que se validaron los headers pendientes.
+    pub fn spawn(mut self) -> JoinHandle<Result<(), CustomError>> {
+        thread::spawn(move || -> Result<(), CustomError> {
+            self.event_loop()?;
+            self.header_validation.stop()
+        })
+    }
+
+    fn event_loop(&mut self) -> Result<(), CustomError> {
+        let mut events = Events::with_capacity(1024);
+        loop {
+            if let Err(error) = self.poll.poll(&mut events, Some(TICK)) {
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error.into());
+            }
+
+            for event in events.iter() {
+                let token = event.token();
+                let Some(connection) = self.connections.get_mut(&token) else { continue };
+                if let Err(error) = connection.handle_event(event) {
+                    self.close(token, Some(error))?;
+                }
+            }
+
+            self.resume_reading()?;
+            self.handle_commands()?;
+            if !self.handle_peer_actions()? {
+                break;
+            }
+            self.keep_alive()?;
+        }
+
+        for token in self.tokens() {
+            self.close(token, None)?;
+        }
+        Ok(())
+    }
+
+    fn handle_commands(&mut self) -> Result<(), CustomError> {
+        while let Ok(command) = self.command_receiver.try_recv() {
+            match command {
+                NetworkCommand::Register(writer, info, stream) => {
+                    self.register(writer, info, stream)?
+                }
+                NetworkCommand::Write(token, bytes) => {
+                    let Some(connection) = self.connections.get_mut(&token) else { continue };
+                    if let Err(error) = connection.queue(bytes) {
+                        self.close(token, Some(error))?;
+                    }
+                }
+                NetworkCommand::Close(token) => self.close(token, None)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn register(
+        &mut self,
+        writer: PeerWriter,
+        info: PeerInfo,
+        stream: net::TcpStream,
+    ) -> Result<(), CustomError> {
+        let token = writer.token;
+        let (address, version, start_height) = (info.address, info.version, info.start_height);
+        let mut stream = TcpStream::from_std(stream);
+        self.poll.registry().register(
+            &mut stream,
+            token,
+            Interest::READABLE | Interest::WRITABLE,
+        )?;
+
+        let mut connection = Connection {
+            stream,
+            closed: writer.closed.clone(),
+            read_buffer: vec![],
+            block_parser: None,
+            write_buffer: vec![],
+            paused: false,
+            block_backlog: self.block_backlog.clone(),
+            stream_handler: PeerStreamLoop::new(
+                address,
+                version,
+                writer.clone(),
+                self.logger_sender.clone(),
+                self.node_action_sender.clone(),
+                self.header_validation.batch_sender(),
+                self.peer_info_ref.clone(),
+            ),
+            action_handler: PeerActionLoop::new(
+                address,
+                version,
+                writer,
+                self.logger_sender.clone(),
+                self.node_action_sender.clone(),
+            ),
+        };
+
+        self.peer_info_ref.lock()?.insert(info);
+        self.node_action_sender
+            .send(NodeAction::PeerConnected(address))?;
+        self.node_action_sender
+            .send(NodeAction::PeerHeight(address, start_height))?;
+
+        // el peer pudo haber enviado mensajes entre el handshake y el registro
+        let response = connection.read();
+        self.connections.insert(token, connection);
+        if let Err(error) = response {
+            self.close(token, Some(error))?;
+        }
+        Ok(())
+    }
+
+    /// Reparte las acciones recibidas entre los peers conectados, de a uno por vez.
+    /// Devuelve false si se recibio PeerAction::Terminate.
+    fn handle_peer_actions(&mut self) -> Result<bool, CustomError> {
+        while let Ok(action) = self.peer_action_receiver.try_recv() {
+            if let PeerAction::Terminate = action {
+                return Ok(false);
+            }
+            self.pending_actions.push_back(action);
+        }
+
+        while !self.connections.is_empty() {
+            let Some(action) = self.pending_actions.pop_front() else { break };
+            let tokens = self.tokens();
+            let token = tokens[self.next_peer % tokens.len()];
+            self.next_peer = self.next_peer.wrapping_add(1);
+
+            let Some(connection) = self.connections.get_mut(&token) else { continue };
+            if let Err(error) = connection.action_handler.handle(action) {
+                self.close(token, Some(error))?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Vuelve a leer de las conexiones pausadas una vez que el NodeActionLoop proceso los bloques pendientes.
+    fn resume_reading(&mut self) -> Result<(), CustomError> {
+        for token in self.tokens() {
+            if self.block_backlog.is_full() {
+                break;
+            }
+            let Some(connection) = self.connections.get_mut(&token) else { continue };
+            if !connection.paused {
+                continue;
+            }
+            if let Err(error) = connection.read() {
+                self.close(token, Some(error))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn keep_alive(&mut self) -> Result<(), CustomError> {
+        for token in self.tokens() {
+            let Some(connection) = self.connections.get_mut(&token) else { continue };
+            if let Err(error) = connection.stream_handler.keep_alive() {
+                self.close(token, Some(error))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deja de atender la conexion con un peer.
+    /// Si se cierra por un error, se avisa al nodo para que elimine al peer. Si no, se le avisa que el peer se desconecto.
+    fn close(&mut self, token: Token, error: Option<CustomError>) -> Result<(), CustomError> {
+        let Some(mut connection) = self.connections.remove(&token) else { return Ok(()) };
+        connection.closed.store(true, Ordering::Relaxed);
+        let _ = self.poll.registry().deregister(&mut connection.stream);
+        let _ = connection.stream.shutdown(net::Shutdown::Both);
+
+        let address = connection.stream_handler.address;
+        self.peer_info_ref.lock()?.remove(&address);
+        let Some(error) = error.map(|error| error.with_peer(address, None)) else {
+            // al cerrar el nodo el NodeActionLoop puede haber terminado antes que el NetworkLoop
+            let _ = self
+                .node_action_sender
+                .send(NodeAction::PeerDisconnected(address));
+            return Ok(());
+        };
+        send_log(
+            &self.logger_sender,
+            Log::Warning(format!("Closing connection after an error: {error}")),
+        );
+        // un mensaje que no se puede parsear o que llega dañado es una violacion del protocolo
+        if let CustomError::Parse(ParseError::SerializedBufferIsInvalid)
+        | CustomError::Network(NetworkError::InvalidChecksum) = error.root()
+        {
+            self.node_action_sender.send(NodeAction::PeerMisbehaving(
+                address,
+                Misbehavior::ProtocolViolation,
+            ))?;
+        }
+        self.node_action_sender
+            .send(NodeAction::PeerError(address))?;
+        Ok(())
+    }
+
+    /// Devuelve los tokens de las conexiones en orden, para repartir las acciones siempre igual.
+    fn tokens(&self) -> Vec<Token> {
+        let mut tokens: Vec<Token> = self.connections.keys().copied().collect();
+        tokens.sort();
+        tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddrV6, TcpListener};
+
+    use crate::{
+        message::Message,
+        messages::{
+            get_addr::GetAddr,
+            ping_pong::{Ping, Pong},
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn network_loop_serves_registered_peer() {
+        let (peer_action_sender, peer_action_receiver) = mpsc::channel();
+        let (node_action_sender, node_action_receiver) = mpsc::channel();
+        let (logger_sender, _logger_receiver) = mpsc::channel();
+        let peer_info_ref = PeerInfoRegistry::new();
+        let (network_loop, network) = NetworkLoop::new(
+            peer_action_receiver,
+            node_action_sender,
+            logger_sender,
+            peer_info_ref.clone(),
+        )
+        .unwrap();
+        let network_thread = network_loop.spawn();
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut remote, _) = listener.accept().unwrap();
+        remote
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let address = SocketAddrV6::new(Ipv4Addr::LOCALHOST.to_ipv6_mapped(), 18333, 0, 0);
+        let mut writer = network.writer();
+        let info = PeerInfo::new(address, "/Satoshi:25.0.0/".to_string(), 70015, 800);
+        network.register(&writer, info, stream).unwrap();
+
+        // se avisa al nodo que el peer se conecto y la altura de su cadena
+        let node_action = node_action_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(node_action, NodeAction::PeerConnected(peer) if peer == address));
+        let node_action = node_action_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(node_action, NodeAction::PeerHeight(peer, 800) if peer == address));
+
+        // el loop responde los ping del peer
+        Ping { nonce: 7 }.send(&mut remote).unwrap();
+        let header = MessageHeader::read(&mut remote).unwrap();
+        assert_eq!(header.command, Command::Pong);
+        let pong = Pong::read(&mut remote, &header).unwrap();
+        assert_eq!(pong.nonce, 7);
+
+        // los mensajes escritos en el PeerWriter llegan al peer
+        GetAddr::new().send(&mut writer).unwrap();
+        let header = MessageHeader::read(&mut remote).unwrap();
+        assert_eq!(header.command, Command::GetAddr);
+
+        // se registra el trafico con el peer, el getaddr puede no haberse contado todavia
+        let peers = peer_info_ref.lock().unwrap().get_all();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].user_agent, "/Satoshi:25.0.0/");
+        assert_eq!(peers[0].bytes_received, 32);
+        assert!(peers[0].bytes_sent >= 32);
+
+        // y el de cada tipo de mensaje, que se cuenta antes de escribirlo en el stream
+        let totals = peer_info_ref.lock().unwrap().get_totals();
+        assert_eq!(totals.by_command["ping"].messages_received, 1);
+        assert_eq!(totals.by_command["ping"].bytes_received, 32);
+        assert_eq!(totals.by_command["pong"].messages_sent, 1);
+        assert_eq!(totals.by_command["getaddr"].bytes_sent, 24);
+
+        // si el peer cierra la conexion se avisa al nodo
+        drop(remote);
+        let node_action = node_action_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(node_action, NodeAction::PeerError(peer) if peer == address));
+        assert!(GetAddr::new().send(&mut writer).is_err());
+        assert!(peer_info_ref.lock().unwrap().get_all().is_empty());
+        assert_eq!(
+            peer_info_ref.lock().unwrap().get_totals().bytes_received,
+            32
+        );
+
+        peer_action_sender.send(PeerAction::Terminate).unwrap();
+        assert!(network_thread.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn network_loop_penalizes_messages_with_invalid_checksum() {
+        let (peer_action_sender, peer_action_receiver) = mpsc::channel();
+        let (node_action_sender, node_action_receiver) = mpsc::channel();
+        let (logger_sender, _logger_receiver) = mpsc::channel();
+        let (network_loop, network) = NetworkLoop::new(
+            peer_action_receiver,
+            node_action_sender,
+            logger_sender,
+            PeerInfoRegistry::new(),
+        )
+        .unwrap();
+        let network_thread = network_loop.spawn();
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut remote, _) = listener.accept().unwrap();
+
+        let address = SocketAddrV6::new(Ipv4Addr::LOCALHOST.to_ipv6_mapped(), 18334, 0, 0);
+        let writer = network.writer();
+        let info = PeerInfo::new(address, "/Satoshi:25.0.0/".to_string(), 70015, 800);
+        network.register(&writer, info, stream).unwrap();
+        // se descartan los avisos de conexion y altura del peer
+        for _ in 0..2 {
+            node_action_receiver
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap();
+        }
+
+        let ping = Ping { nonce: 7 };
+        let mut message = MessageHeader::new(&ping).serialize().to_vec();
+        let mut payload = ping.serialize();
+        payload[0] ^= 1;
+        message.extend(payload);
+        remote.write_all(&message).unwrap();
+
+        let node_action = node_action_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(
+            node_action,
+            NodeAction::PeerMisbehaving(peer, Misbehavior::ProtocolViolation) if peer == address
+        ));
+        let node_action = node_action_receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(node_action, NodeAction::PeerError(peer) if peer == address));
+
+        peer_action_sender.send(PeerAction::Terminate).unwrap();
+        assert!(network_thread.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn network_loop_pauses_reading_while_blocks_are_pending() {
+        let (peer_action_sender, peer_action_receiver) = mpsc::channel();
+        let (node_action_sender, _node_action_receiver) = mpsc::channel();
+        let (logger_sender, _logger_receiver) = mpsc::channel();
+        let (network_loop, network) = NetworkLoop::new(
+            peer_action_receiver,
+            node_action_sender,
+            logger_sender,
+            PeerInfoRegistry::new(),
+        )
+        .unwrap();
+        let block_backlog = network_loop.block_backlog();
+        for _ in 0..MAX_PENDING_BLOCKS {
+            block_backlog.push();
+        }
+        assert!(block_backlog.is_full());
+        let network_thread = network_loop.spawn();
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut remote, _) = listener.accept().unwrap();
+        let address = SocketAddrV6::new(Ipv4Addr::LOCALHOST.to_ipv6_mapped(), 18335, 0, 0);
+        let writer = network.writer();
+        let info = PeerInfo::new(address, "/Satoshi:25.0.0/".to_string(), 70015, 800);
+        network.register(&writer, info, stream).unwrap();
+
+        // con el nodo atrasado el ping queda sin leer
+        Ping { nonce: 7 }.send(&mut remote).unwrap();
+        remote
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        assert!(MessageHeader::read(&mut remote).is_err());
+
+        // al procesar un bloque se vuelve a leer y se responde
+        block_backlog.pop();
+        remote
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let header = MessageHeader::read(&mut remote).unwrap();
+        assert_eq!(header.command, Command::Pong);
+
+        peer_action_sender.send(PeerAction::Terminate).unwrap();
+        assert!(network_thread.join().unwrap().is_ok());
+    }
+}
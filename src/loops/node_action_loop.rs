@@ -1,5 +1,4 @@
 use std::{
-    collections::HashMap,
     net::SocketAddrV6,
     sync::{mpsc, Arc, Mutex},
 };
@@ -7,52 +6,127 @@ use std::{
 use gtk::glib;
 
 use crate::{
-    error::CustomError,
+    error::{ChainError, CustomError, ParseError, WalletError},
+    fee_estimator::FeePriority,
     gui::init::GUIEvents,
-    logger::{send_log, Log},
-    message::Message,
+    logger::{send_log, Log, LogEntry},
+    message::{Command, Message},
     messages::{
-        block::Block, get_data::GetData, get_headers::GetHeaders, headers::Headers, inv::Inv,
-        not_found::NotFound, transaction::Transaction,
+        block::Block,
+        block_txn::BlockTxn,
+        cfheaders::CFHeaders,
+        cfilter::CFilter,
+        compact_block::{CompactBlock, PartialBlock},
+        get_block_txn::GetBlockTxn,
+        get_data::GetData,
+        get_headers::GetHeaders,
+        headers::Headers,
+        inv::Inv,
+        merkle_block::FilteredBlock,
+        not_found::NotFound,
+        reject::Reject,
+        transaction::Transaction,
     },
+    network::Network,
     node_state::NodeState,
+    peer::Peer,
+    signer::{connect_hardware_wallet, sign_transaction},
+    signet::validate_block_signature,
+    states::{
+        filters_state::FiltersRequest,
+        peer_score_state::Misbehavior,
+        sync_progress_state::{SyncMilestone, SyncProgress},
+        tx_broadcasts_state::{select_broadcast_peers, BroadcastStatus},
+    },
     structs::{
         block_header::{hash_as_string, BlockHeader},
+        hash256::Hash256,
         inventory::{Inventory, InventoryType},
+        network_address::NetworkAddress,
     },
+    transaction_builder::Payment,
 };
 
-use super::peer_action_loop::PeerAction;
+use super::{network_loop::BlockBacklog, peer_action_loop::PeerAction};
 
 /// NodeAction es una enumeracion de las acciones que puede realizar el nodo.
 /// Las acciones son:
+/// - PeerConnected: Un peer completo el handshake y su conexion empezo a atenderse.
+/// - PeerDisconnected: Se cerro la conexion con un peer sin que haya fallado, por ejemplo al banearlo.
+/// - PeerHeight: Se conocio la altura de la cadena de un peer.
 /// - PeerError: Peer tiene comportamiento no esperado.
-/// - NewHeaders: Recibe nuevos headers.
+/// - PeerMisbehaving: Peer cometio una falta, si alcanza el puntaje para ser baneado se desconecta.
+/// - NewHeaders: Recibe nuevos headers de un peer.
 /// - GetHeadersError: Error al solicitar headers.
 /// - Block: Recibe un bloque.
 /// - GetDataError: Error al solicitar data.
+/// - NotFound: Un peer no tiene los inventories que se le pidieron.
 /// - PendingTransaction: Recibe una transaccion.
-/// - MakeTransaction: Solicitar una transaccion.
+/// - MakeTransaction: Solicitar la transaccion de un pago, que gasta los outpoints elegidos en el coin control o, si no hay ninguno, las UTXO que se seleccionen.
+///   La transaccion se guarda con la etiqueta recibida, salvo que este vacia.
+/// - BumpFee: Solicitar el reemplazo de una pending tx propia por otra con mas fee (BIP 125).
+/// - Cpfp: Solicitar una transaccion que gaste los outputs de una pending tx con mas fee, para acelerar su confirmacion (CPFP).
+/// - Rescan: Volver a escanear las wallets en los bloques posteriores a la altura recibida.
+/// - SweepKey: Solicitar una transaccion que barra los fondos de una private key en formato WIF hacia la wallet activa.
+/// - SendTransaction: Difundir una transaccion propia ya creada y agregarla al mempool.
 /// - SendHeaders: Habilita el envio directo de headers a un peer.
+/// - SendCmpct: Un peer anuncio la version de compact blocks que soporta.
+/// - FeeFilter: Un peer pidio no recibir transacciones con un fee rate menor al indicado, en satoshis por kilobyte virtual.
 /// - GetHeaders: Solicitud de headers de parte de un peer.
 /// - GetData: Solicitud de data de parte de un peer.
+/// - MerkleBlock: Recibe un merkle block de un peer con las transacciones que coinciden con el bloom filter.
+/// - CompactBlock: Recibe un compact block de un peer.
+/// - GetBlockTxn: Solicitud de transacciones de un compact block de parte de un peer.
+/// - BlockTxn: Recibe las transacciones faltantes de un compact block.
+/// - CFHeaders: Recibe filter headers de un peer.
+/// - CFilter: Recibe un filtro compacto de un peer.
+/// - BlocksAnnounced: Un peer anuncio bloques con un inv, si alguno no esta en la cadena de headers se le piden los headers nuevos.
+/// - TxAnnounced: Un peer anuncio transacciones con un inv, se le piden las que no estan en el mempool.
+/// - TxRejected: Un peer rechazo una transaccion.
+/// - TxSeenInMempool: Un peer envio una transaccion de su mempool.
+/// - Addresses: Un peer anuncio direcciones de otros nodos.
+/// - GetSyncProgress: Solicitar el progreso de la descarga inicial de headers y bloques, que se responde por el sender recibido.
+/// - SyncMilestone: Se alcanzo una etapa de la sincronizacion fuera del NodeActionLoop, por ejemplo el escaneo de los filtros compactos.
 /// - Terminate: Termina el nodo.
 pub enum NodeAction {
+    PeerConnected(SocketAddrV6),
+    PeerDisconnected(SocketAddrV6),
+    PeerHeight(SocketAddrV6, usize),
     PeerError(SocketAddrV6),
-    NewHeaders(Headers),
+    PeerMisbehaving(SocketAddrV6, Misbehavior),
+    NewHeaders(SocketAddrV6, Headers),
     GetHeadersError,
-    Block((Vec<u8>, Block)),
+    Block((Hash256, Block)),
     GetDataError(Vec<Inventory>),
+    NotFound(SocketAddrV6, Vec<Inventory>),
     PendingTransaction(Transaction),
-    MakeTransaction((HashMap<String, u64>, u64)),
+    MakeTransaction((Payment, String)),
+    BumpFee(Hash256),
+    Cpfp(Hash256),
+    Rescan(usize),
+    SweepKey(String),
+    SendTransaction(Transaction),
     SendHeaders(SocketAddrV6),
+    SendCmpct(SocketAddrV6, u64),
+    FeeFilter(SocketAddrV6, u64),
     GetHeaders(SocketAddrV6, GetHeaders),
     GetData(SocketAddrV6, GetData),
+    MerkleBlock(SocketAddrV6, FilteredBlock),
+    CompactBlock(SocketAddrV6, CompactBlock),
+    GetBlockTxn(SocketAddrV6, GetBlockTxn),
+    BlockTxn(BlockTxn),
+    CFHeaders(SocketAddrV6, CFHeaders),
+    CFilter(SocketAddrV6, CFilter),
+    BlocksAnnounced(SocketAddrV6, Vec<Hash256>),
+    TxAnnounced(SocketAddrV6, Vec<Hash256>),
+    TxRejected(SocketAddrV6, Reject),
+    TxSeenInMempool(SocketAddrV6, Hash256),
+    Addresses(SocketAddrV6, Vec<NetworkAddress>),
+    GetSyncProgress(mpsc::Sender<SyncProgress>),
+    SyncMilestone(SyncMilestone),
     Terminate,
 }
 
-const START_DATE_IBD: u32 = 1681095630;
-
 /// NodeActionLoop es una estructura que contiene los elementos necesarios para manejar los mensajes recibidos por el nodo.
 /// Genera el loop de eventos alrededor de los NodeAction recibidoe por node_action_receiver.
 /// Los elementos son:
@@ -61,12 +135,14 @@ const START_DATE_IBD: u32 = 1681095630;
 /// - peer_action_sender: Sender para enviar acciones al los peers.
 /// - logger_sender: Sender para enviar logs al logger.
 /// - node_state_ref: Referencia al estado del nodo.
+/// - block_backlog: BlockBacklog donde se registran los bloques procesados, para que el NetworkLoop vuelva a leer de los peers.
 pub struct NodeActionLoop {
     gui_sender: glib::Sender<GUIEvents>,
     node_action_receiver: mpsc::Receiver<NodeAction>,
     peer_action_sender: mpsc::Sender<PeerAction>,
-    logger_sender: mpsc::Sender<Log>,
+    logger_sender: mpsc::Sender<LogEntry>,
     node_state_ref: Arc<Mutex<NodeState>>,
+    block_backlog: BlockBacklog,
 }
 
 impl NodeActionLoop {
@@ -75,8 +151,9 @@ impl NodeActionLoop {
         gui_sender: glib::Sender<GUIEvents>,
         node_action_receiver: mpsc::Receiver<NodeAction>,
         peer_action_sender: mpsc::Sender<PeerAction>,
-        logger_sender: mpsc::Sender<Log>,
+        logger_sender: mpsc::Sender<LogEntry>,
         node_state_ref: Arc<Mutex<NodeState>>,
+        block_backlog: BlockBacklog,
     ) {
         let mut node_thread = Self {
             gui_sender,
@@ -84,6 +161,7 @@ impl NodeActionLoop {
             peer_action_sender,
             logger_sender,
             node_state_ref,
+            block_backlog,
         };
         node_thread.event_loop();
     }
@@ -91,137 +169,676 @@ impl NodeActionLoop {
     fn event_loop(&mut self) {
         while let Ok(message) = self.node_action_receiver.recv() {
             let response = match message {
+                NodeAction::PeerConnected(address) => self.handle_peer_connected(address),
+                NodeAction::PeerDisconnected(address) => self.handle_peer_disconnected(address),
+                NodeAction::PeerHeight(address, height) => self.handle_peer_height(address, height),
                 NodeAction::PeerError(address) => self.handle_peer_error(address),
-                NodeAction::Block((block_hash, block)) => self.handle_block(block_hash, block),
-                NodeAction::NewHeaders(new_headers) => self.handle_new_headers(new_headers),
+                NodeAction::PeerMisbehaving(address, misbehavior) => {
+                    self.handle_peer_misbehaving(address, misbehavior)
+                }
+                NodeAction::Block((block_hash, block)) => {
+                    let response = self.handle_block(block_hash, block);
+                    self.block_backlog.pop();
+                    response
+                }
+                NodeAction::NewHeaders(address, new_headers) => {
+                    self.handle_new_headers(address, new_headers)
+                }
                 NodeAction::GetHeadersError => self.handle_get_headers_error(),
                 NodeAction::GetDataError(inventory) => self.handle_get_data_error(inventory),
-                NodeAction::MakeTransaction((outputs, fee)) => {
-                    self.handle_make_transaction(outputs, fee)
+                NodeAction::NotFound(address, inventory) => {
+                    self.handle_not_found(address, inventory)
+                }
+                NodeAction::MakeTransaction((payment, label)) => {
+                    self.handle_make_transaction(payment, label)
+                }
+                NodeAction::BumpFee(tx_hash) => self.handle_bump_fee(tx_hash),
+                NodeAction::Cpfp(tx_hash) => self.handle_cpfp(tx_hash),
+                NodeAction::Rescan(height) => self.handle_rescan(height),
+                NodeAction::SweepKey(wif) => self.handle_sweep_key(wif),
+                NodeAction::SendTransaction(transaction) => {
+                    self.handle_send_transaction(transaction)
                 }
                 NodeAction::PendingTransaction(transaction) => {
                     self.handle_pending_transaction(transaction)
                 }
                 NodeAction::SendHeaders(address) => self.handle_send_headers(address),
+                NodeAction::SendCmpct(address, version) => self.handle_send_cmpct(address, version),
+                NodeAction::FeeFilter(address, fee_rate) => {
+                    self.handle_fee_filter(address, fee_rate)
+                }
                 NodeAction::GetHeaders(address, getheaders) => {
                     self.handle_get_headers(address, getheaders)
                 }
                 NodeAction::GetData(address, getdata) => self.handle_get_data(address, getdata),
+                NodeAction::MerkleBlock(address, filtered_block) => {
+                    self.handle_merkle_block(address, filtered_block)
+                }
+                NodeAction::CompactBlock(address, compact_block) => {
+                    self.handle_compact_block(address, compact_block)
+                }
+                NodeAction::GetBlockTxn(address, get_block_txn) => {
+                    self.handle_get_block_txn(address, get_block_txn)
+                }
+                NodeAction::BlockTxn(block_txn) => self.handle_block_txn(block_txn),
+                NodeAction::CFHeaders(address, cfheaders) => {
+                    self.handle_cfheaders(address, cfheaders)
+                }
+                NodeAction::CFilter(address, cfilter) => self.handle_cfilter(address, cfilter),
+                NodeAction::BlocksAnnounced(address, block_hashes) => {
+                    self.handle_blocks_announced(address, block_hashes)
+                }
+                NodeAction::TxAnnounced(address, tx_hashes) => {
+                    self.handle_tx_announced(address, tx_hashes)
+                }
+                NodeAction::TxRejected(address, reject) => self.handle_tx_rejected(address, reject),
+                NodeAction::TxSeenInMempool(address, tx_hash) => {
+                    self.handle_tx_seen_in_mempool(address, tx_hash)
+                }
+                NodeAction::Addresses(address, addresses) => {
+                    self.handle_addresses(address, addresses)
+                }
+                NodeAction::GetSyncProgress(sender) => self.handle_get_sync_progress(sender),
+                NodeAction::SyncMilestone(milestone) => self.handle_sync_milestone(milestone),
                 NodeAction::Terminate => break,
             };
 
             if let Err(error) = response {
                 send_log(
                     &self.logger_sender,
-                    Log::Message(format!("Error on NodeActionLoop: {error}")),
+                    Log::Warning(format!("Error on NodeActionLoop: {error}")),
                 );
             }
         }
     }
 
+    fn handle_peer_connected(&mut self, address: SocketAddrV6) -> Result<(), CustomError> {
+        send_log(
+            &self.logger_sender,
+            Log::Debug(format!("Peer {} connected", address)),
+        );
+        self.gui_sender.send(GUIEvents::PeersUpdated)?;
+        Ok(())
+    }
+
+    fn handle_peer_disconnected(&mut self, address: SocketAddrV6) -> Result<(), CustomError> {
+        send_log(
+            &self.logger_sender,
+            Log::Debug(format!("Peer {} disconnected", address)),
+        );
+        self.remove_peer(address)
+    }
+
+    fn handle_peer_height(
+        &mut self,
+        address: SocketAddrV6,
+        height: usize,
+    ) -> Result<(), CustomError> {
+        // el peer puede no estar en la lista todavia, en ese caso su altura se toma del handshake al agregarlo
+        if self
+            .node_state_ref
+            .lock()?
+            .update_peer_height(address, height)
+        {
+            self.gui_sender.send(GUIEvents::PeersUpdated)?;
+        }
+        Ok(())
+    }
+
     fn handle_peer_error(&mut self, address: SocketAddrV6) -> Result<(), CustomError> {
-        let mut node_state = self.node_state_ref.lock()?;
         send_log(
             &self.logger_sender,
             Log::Message(format!("Deleting Peer {} from list...", address)),
         );
+        self.remove_peer(address)
+    }
+
+    fn remove_peer(&mut self, address: SocketAddrV6) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
         node_state.remove_peer(address);
+
+        // los bloques pedidos al peer se reparten entre los demas
+        node_state.request_pending_blocks()?;
+        drop(node_state);
+
+        self.gui_sender.send(GUIEvents::PeersUpdated)?;
         Ok(())
     }
 
-    fn handle_make_transaction(
+    fn handle_peer_misbehaving(
         &mut self,
-        outputs: HashMap<String, u64>,
-        fee: u64,
+        address: SocketAddrV6,
+        misbehavior: Misbehavior,
     ) -> Result<(), CustomError> {
         let mut node_state = self.node_state_ref.lock()?;
-        let transaction = match node_state.make_transaction(outputs, fee) {
+        if !node_state.peer_misbehaving(address, misbehavior)? {
+            return Ok(());
+        }
+        send_log(
+            &self.logger_sender,
+            Log::Warning(format!(
+                "Peer {} banned for {}",
+                address,
+                misbehavior.description()
+            )),
+        );
+        node_state.request_pending_blocks()
+    }
+
+    fn handle_make_transaction(
+        &mut self,
+        payment: Payment,
+        label: String,
+    ) -> Result<(), CustomError> {
+        let signs_with_hardware_wallet = self
+            .node_state_ref
+            .lock()?
+            .active_wallet_signs_with_hardware_wallet();
+        let result = match signs_with_hardware_wallet {
+            true => self.make_transaction_with_hardware_wallet(payment),
+            false => self.node_state_ref.lock()?.make_transaction(payment),
+        };
+        let result = result.and_then(|transaction| {
+            self.node_state_ref
+                .lock()?
+                .set_tx_label(&transaction.hash(), &label)?;
+            Ok(transaction)
+        });
+        let transaction = match result {
             Ok(transaction) => transaction,
             Err(error) => {
                 send_log(&self.logger_sender, Log::Error(error));
                 return Ok(());
             }
         };
+
+        self.broadcast_transaction(transaction.clone())?;
+
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.append_pending_tx(transaction)?;
+        self.gui_sender.send(GUIEvents::TransactionSent)?;
+
+        Ok(())
+    }
+
+    /// Crea la transaccion de la wallet activa y la firma en la hardware wallet conectada.
+    /// No mantiene el lock de NodeState mientras el usuario confirma la transaccion en el dispositivo.
+    fn make_transaction_with_hardware_wallet(
+        &self,
+        payment: Payment,
+    ) -> Result<Transaction, CustomError> {
+        let node_state = self.node_state_ref.lock()?;
+        let psbt = node_state.create_unsigned_transaction(payment)?;
+        let Some(wallet) = node_state.get_active_wallet().cloned() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
         drop(node_state);
 
-        self.broadcast(transaction.clone())?;
+        let gui_sender = self.gui_sender.clone();
+        let mut signer =
+            connect_hardware_wallet(Box::new(move || request_hardware_wallet_pin(&gui_sender)))?;
+        send_log(
+            &self.logger_sender,
+            Log::Message(format!("Confirm the transaction on the {}", signer.name())),
+        );
+        sign_transaction(signer.as_mut(), psbt, &wallet)
+    }
+
+    fn handle_send_transaction(&mut self, transaction: Transaction) -> Result<(), CustomError> {
+        self.broadcast_transaction(transaction.clone())?;
+
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.append_pending_tx(transaction)?;
+        Ok(())
+    }
 
+    fn handle_rescan(&mut self, height: usize) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        if let Err(error) = node_state.rescan_from(height) {
+            send_log(&self.logger_sender, Log::Error(error));
+        }
+        Ok(())
+    }
+
+    fn handle_sweep_key(&mut self, wif: String) -> Result<(), CustomError> {
+        let node_state = self.node_state_ref.lock()?;
+        let fee_rate = node_state.get_fee_rate(FeePriority::Medium);
+        let transaction = match node_state.sweep_key(&wif, fee_rate) {
+            Ok(transaction) => transaction,
+            Err(error) => {
+                send_log(&self.logger_sender, Log::Error(error));
+                return Ok(());
+            }
+        };
+        drop(node_state);
+
+        self.broadcast_transaction(transaction.clone())?;
         send_log(
             &self.logger_sender,
-            Log::Message("Transaction broadcasted!".to_string()),
+            Log::Message(format!("Key swept by transaction {}", transaction.hash())),
         );
 
         let mut node_state = self.node_state_ref.lock()?;
         node_state.append_pending_tx(transaction)?;
-        self.gui_sender.send(GUIEvents::TransactionSent)?;
 
         Ok(())
     }
 
-    fn handle_get_data_error(&mut self, inventory: Vec<Inventory>) -> Result<(), CustomError> {
+    fn handle_bump_fee(&mut self, tx_hash: Hash256) -> Result<(), CustomError> {
+        let node_state = self.node_state_ref.lock()?;
+        let transaction = match node_state.bump_fee(&tx_hash) {
+            Ok(transaction) => transaction,
+            Err(error) => {
+                send_log(&self.logger_sender, Log::Error(error));
+                return Ok(());
+            }
+        };
+        drop(node_state);
+
+        self.broadcast_transaction(transaction.clone())?;
         send_log(
             &self.logger_sender,
-            Log::Message("Error requesting data,trying with another peer...".to_string()),
+            Log::Message(format!(
+                "Transaction {} replaced by {}",
+                tx_hash,
+                transaction.hash()
+            )),
         );
 
-        self.peer_action_sender
-            .send(PeerAction::GetData(inventory))?;
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.replace_pending_tx(transaction)?;
+
         Ok(())
     }
 
-    fn handle_get_headers_error(&mut self) -> Result<(), CustomError> {
+    fn handle_cpfp(&mut self, tx_hash: Hash256) -> Result<(), CustomError> {
         let node_state = self.node_state_ref.lock()?;
-        let last_header = node_state.get_last_header_hash();
+        let transaction = match node_state.cpfp(&tx_hash) {
+            Ok(transaction) => transaction,
+            Err(error) => {
+                send_log(&self.logger_sender, Log::Error(error));
+                return Ok(());
+            }
+        };
         drop(node_state);
 
+        self.broadcast_transaction(transaction.clone())?;
         send_log(
             &self.logger_sender,
-            Log::Message("Error requesting headers,trying with another peer...".to_string()),
+            Log::Message(format!(
+                "Transaction {} accelerated by {}",
+                tx_hash,
+                transaction.hash()
+            )),
         );
 
-        self.peer_action_sender
-            .send(PeerAction::GetHeaders(last_header))?;
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.append_pending_tx(transaction)?;
+
         Ok(())
     }
 
-    fn handle_new_headers(&mut self, new_headers: Headers) -> Result<(), CustomError> {
+    /// Envia una transaccion propia a una parte de los peers y registra su propagacion,
+    /// que confirman los anuncios de los peers a los que no se envio.
+    fn broadcast_transaction(&mut self, transaction: Transaction) -> Result<(), CustomError> {
+        let tx_hash = transaction.hash();
+        let mut node_state = self.node_state_ref.lock()?;
+        let fee_rate = node_state.transaction_fee_rate(&transaction);
+        // los peers que no quieren recibir transacciones (relay en false) o que filtran su fee rate (BIP 133)
+        // no cuentan para el broadcast
+        let relays: Vec<SocketAddrV6> = node_state
+            .get_peers()
+            .iter()
+            .filter(|peer| peer.relay && peer.accepts_fee_rate(fee_rate))
+            .map(|peer| peer.address)
+            .collect();
+        drop(node_state);
+
+        let peers = relays.len();
+        let sent_to = select_broadcast_peers(relays);
+        self.broadcast_to(transaction, |peer| sent_to.contains(&peer.address))?;
+
         let mut node_state = self.node_state_ref.lock()?;
-        node_state.append_headers(&new_headers)?;
+        let status = node_state.append_tx_broadcast(tx_hash, sent_to, peers);
         drop(node_state);
 
-        let headers_after_timestamp = &new_headers
-            .headers
+        self.log_broadcast_status(&tx_hash, status);
+        Ok(())
+    }
+
+    fn handle_blocks_announced(
+        &mut self,
+        address: SocketAddrV6,
+        block_hashes: Vec<Hash256>,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        // durante la sincronizacion inicial los headers ya se estan pidiendo
+        if !node_state.is_synced() {
+            return Ok(());
+        }
+        let unknown_blocks = block_hashes
             .iter()
-            .filter(|header| header.timestamp > START_DATE_IBD)
-            .collect::<Vec<_>>();
-        let chunks: Vec<&[&BlockHeader]> = headers_after_timestamp.chunks(5).collect();
-        for chunk in chunks {
-            self.request_block(chunk)?;
+            .filter(|block_hash| node_state.get_block_height(block_hash).is_none())
+            .count();
+        if unknown_blocks == 0 {
+            return Ok(());
         }
+        let version = match node_state.get_peer(&address) {
+            Some(peer) => peer.version,
+            None => return Ok(()),
+        };
+
+        // los headers que responda el peer se agregan en handle_new_headers, que pide sus bloques
+        let get_headers = GetHeaders::new(version, node_state.get_block_locator()?, Hash256::ZERO);
+        send_message(&mut node_state, address, get_headers)?;
+        drop(node_state);
+        send_log(
+            &self.logger_sender,
+            Log::Message(format!(
+                "Peer {address} announced {unknown_blocks} new blocks, requesting headers..."
+            )),
+        );
+        Ok(())
+    }
 
+    fn handle_tx_announced(
+        &mut self,
+        address: SocketAddrV6,
+        tx_hashes: Vec<Hash256>,
+    ) -> Result<(), CustomError> {
         let mut node_state = self.node_state_ref.lock()?;
-        node_state.verify_sync()?;
+        let mut statuses = vec![];
+        for hash in &tx_hashes {
+            // los peers con wtxidrelay anuncian nuestras transacciones por su wtxid
+            let tx_hash = node_state.get_pending_txid(hash).unwrap_or(*hash);
+            if let Some(status) = node_state.tx_announced(&tx_hash, address) {
+                statuses.push((tx_hash, status));
+            }
+        }
+
+        let inventory_type = match node_state.get_peer(&address) {
+            Some(peer) if peer.wtxid_relay => InventoryType::Wtx,
+            _ => InventoryType::Tx,
+        };
+        let inventories: Vec<Inventory> = node_state
+            .request_transactions(tx_hashes)?
+            .into_iter()
+            .map(|tx_hash| Inventory::new(inventory_type.clone(), tx_hash))
+            .collect();
+        node_state.request_inventories(address, inventories)?;
+        drop(node_state);
 
+        for (tx_hash, status) in statuses {
+            self.log_broadcast_status(&tx_hash, status);
+        }
+        Ok(())
+    }
+
+    fn handle_tx_rejected(
+        &mut self,
+        address: SocketAddrV6,
+        reject: Reject,
+    ) -> Result<(), CustomError> {
+        let Some(tx_hash) = reject.rejected_tx_hash() else { return Ok(()) };
+        let mut node_state = self.node_state_ref.lock()?;
+        let status = node_state.tx_rejected(&tx_hash, address, reject.reason);
+        drop(node_state);
+
+        if let Some(status) = status {
+            self.log_broadcast_status(&tx_hash, status);
+        }
+        Ok(())
+    }
+
+    fn handle_addresses(
+        &mut self,
+        address: SocketAddrV6,
+        addresses: Vec<NetworkAddress>,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        let new_addresses = node_state.append_addresses(addresses)?;
+        drop(node_state);
+
+        if new_addresses > 0 {
+            send_log(
+                &self.logger_sender,
+                Log::Message(format!(
+                    "Received {new_addresses} new peer addresses from {address}"
+                )),
+            );
+        }
         Ok(())
     }
 
-    fn request_block(&mut self, headers: &[&BlockHeader]) -> Result<(), CustomError> {
+    fn handle_tx_seen_in_mempool(
+        &mut self,
+        address: SocketAddrV6,
+        tx_hash: Hash256,
+    ) -> Result<(), CustomError> {
         let mut node_state = self.node_state_ref.lock()?;
+        let status = node_state.tx_announced(&tx_hash, address);
+        drop(node_state);
 
-        let mut inventories = vec![];
-        for header in headers {
-            node_state.append_pending_block(header.hash().clone())?;
-            inventories.push(Inventory::new(InventoryType::Block, header.hash().clone()));
+        if let Some(status) = status {
+            self.log_broadcast_status(&tx_hash, status);
         }
+        Ok(())
+    }
 
+    fn handle_sync_milestone(&mut self, milestone: SyncMilestone) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        // el UTXO espera a que termine el escaneo de filtros para incluir los bloques que coincidieron
+        if milestone == SyncMilestone::FiltersScanned {
+            node_state.verify_sync()?;
+        }
+        let reached = node_state.reach_sync_milestone(milestone);
         drop(node_state);
 
+        if reached {
+            self.notify_sync_milestone(milestone)?;
+        }
+        Ok(())
+    }
+
+    /// Avisa las etapas de la sincronizacion que se alcanzaron al procesar headers o bloques.
+    fn notify_sync_milestones(&mut self) -> Result<(), CustomError> {
+        let milestones = self.node_state_ref.lock()?.new_sync_milestones();
+        for milestone in milestones {
+            self.notify_sync_milestone(milestone)?;
+        }
+        Ok(())
+    }
+
+    fn notify_sync_milestone(&mut self, milestone: SyncMilestone) -> Result<(), CustomError> {
+        send_log(
+            &self.logger_sender,
+            Log::Message(milestone.description().to_string()),
+        );
+        self.gui_sender.send(GUIEvents::SyncMilestone(milestone))?;
+        Ok(())
+    }
+
+    fn handle_get_sync_progress(
+        &mut self,
+        sender: mpsc::Sender<SyncProgress>,
+    ) -> Result<(), CustomError> {
+        let progress = self.node_state_ref.lock()?.get_sync_progress();
+        // quien pidio el progreso puede haber dejado de esperarlo
+        let _ = sender.send(progress);
+        Ok(())
+    }
+
+    fn log_broadcast_status(&self, tx_hash: &Hash256, status: BroadcastStatus) {
+        let message = match status {
+            BroadcastStatus::Sent(sent_to) => {
+                format!("Transaction {tx_hash} broadcasted to {sent_to} peers")
+            }
+            BroadcastStatus::Announced(announced_by, sent_to) => {
+                format!("Transaction {tx_hash} announced by {announced_by} of {sent_to} peers")
+            }
+            BroadcastStatus::Rejected(address, reason) => {
+                format!("Transaction {tx_hash} rejected by {address}: {reason}")
+            }
+        };
+        send_log(&self.logger_sender, Log::Message(message));
+    }
+
+    fn handle_get_data_error(&mut self, inventory: Vec<Inventory>) -> Result<(), CustomError> {
+        send_log(
+            &self.logger_sender,
+            Log::Warning("Error requesting data,trying with another peer...".to_string()),
+        );
+
+        let (blocks, inventory): (Vec<Inventory>, Vec<Inventory>) =
+            inventory.into_iter().partition(|inventory| {
+                inventory.inventory_type == InventoryType::Block
+                    || inventory.inventory_type == InventoryType::CompactBlock
+            });
+
+        if !blocks.is_empty() {
+            let mut node_state = self.node_state_ref.lock()?;
+            node_state.retry_pending_blocks(blocks);
+            node_state.request_pending_blocks()?;
+        }
+        if !inventory.is_empty() {
+            self.node_state_ref.lock()?.retry_inventories(&inventory)?;
+        }
+        Ok(())
+    }
+
+    /// Los bloques que el peer no tiene se reasignan enseguida a otro peer,
+    /// el resto de los inventories se vuelve a pedir a otro peer luego del backoff del InventoryScheduler.
+    fn handle_not_found(
+        &mut self,
+        address: SocketAddrV6,
+        inventory: Vec<Inventory>,
+    ) -> Result<(), CustomError> {
+        send_log(
+            &self.logger_sender,
+            Log::Debug(format!(
+                "Peer {address} did not find {} requested items",
+                inventory.len()
+            )),
+        );
+
+        let (blocks, inventory): (Vec<Inventory>, Vec<Inventory>) =
+            inventory.into_iter().partition(|inventory| {
+                inventory.inventory_type == InventoryType::Block
+                    || inventory.inventory_type == InventoryType::CompactBlock
+            });
+
+        let mut node_state = self.node_state_ref.lock()?;
+        if !blocks.is_empty() {
+            node_state.retry_pending_blocks(blocks);
+            node_state.request_pending_blocks()?;
+        }
+        if !inventory.is_empty() {
+            node_state.inventories_not_found(address, &inventory)?;
+        }
+        Ok(())
+    }
+
+    fn handle_get_headers_error(&mut self) -> Result<(), CustomError> {
+        let node_state = self.node_state_ref.lock()?;
+        let block_locator = node_state.get_block_locator()?;
+        drop(node_state);
+
+        send_log(
+            &self.logger_sender,
+            Log::Warning("Error requesting headers,trying with another peer...".to_string()),
+        );
+
         self.peer_action_sender
-            .send(PeerAction::GetData(inventories))?;
+            .send(PeerAction::GetHeaders(block_locator))?;
         Ok(())
     }
 
-    fn handle_block(&mut self, block_hash: Vec<u8>, block: Block) -> Result<(), CustomError> {
+    fn handle_new_headers(
+        &mut self,
+        address: SocketAddrV6,
+        new_headers: Headers,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        let appended_headers = match node_state.append_headers(address, &new_headers) {
+            Ok(appended_headers) => appended_headers,
+            // los headers pertenecen a un fork desconocido, se piden los headers desde el ultimo en comun
+            Err(CustomError::Chain(ChainError::BlockChainBroken)) if node_state.is_synced() => {
+                let block_locator = node_state.get_block_locator()?;
+                drop(node_state);
+                send_log(
+                    &self.logger_sender,
+                    Log::Message(
+                        "Received headers from an unknown fork, requesting headers...".to_string(),
+                    ),
+                );
+                self.peer_action_sender
+                    .send(PeerAction::GetHeaders(block_locator))?;
+                return Ok(());
+            }
+            // los headers no cumplen las reglas de consenso, se banea al peer y se piden a otro
+            Err(
+                error @ (CustomError::Chain(ChainError::HeaderInvalidPoW)
+                | CustomError::Chain(ChainError::HeaderInvalidDifficulty)
+                | CustomError::Chain(ChainError::HeaderInvalidTimestamp)
+                | CustomError::Chain(ChainError::HeaderInvalidVersion)
+                | CustomError::Chain(ChainError::HeaderCheckpointMismatch)),
+            ) => {
+                drop(node_state);
+                self.handle_peer_misbehaving(address, Misbehavior::InvalidHeaders)?;
+                self.handle_get_headers_error()?;
+                return Err(error.with_peer(address, Some(Command::Headers)));
+            }
+            // el peer sirve una cadena distinta con menos trabajo, se lo penaliza y se conserva la actual
+            Err(CustomError::Chain(ChainError::HeaderLowWorkChain)) => {
+                drop(node_state);
+                send_log(
+                    &self.logger_sender,
+                    Log::Warning(format!(
+                        "Peer {} serves a fork with less work than the current chain",
+                        address
+                    )),
+                );
+                return self.handle_peer_misbehaving(address, Misbehavior::LowWorkChain);
+            }
+            Err(error) => return Err(error),
+        };
+        drop(node_state);
+
+        let headers_after_timestamp = &appended_headers
+            .iter()
+            .filter(|header| header.timestamp > Network::current().ibd_start_timestamp())
+            .collect::<Vec<_>>();
+        self.request_blocks(headers_after_timestamp)?;
+
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.verify_sync()?;
+        // el peer tiene al menos los headers que envio
+        let height = node_state.get_best_height();
+        drop(node_state);
+
+        if !appended_headers.is_empty() {
+            self.handle_peer_height(address, height)?;
+        }
+        self.notify_sync_milestones()
+    }
+
+    /// Encola los bloques en el BlockDownloader, que los reparte entre los peers.
+    fn request_blocks(&mut self, headers: &[&BlockHeader]) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+
+        // una vez sincronizados, las transacciones de los bloques nuevos suelen estar en las pending txs
+        let inventory_type = match node_state.is_synced() {
+            true => InventoryType::CompactBlock,
+            false => InventoryType::Block,
+        };
+
+        let inventories = headers
+            .iter()
+            .map(|header| Inventory::new(inventory_type.clone(), *header.hash()))
+            .collect();
+        node_state.append_pending_blocks(inventories)?;
+        node_state.request_pending_blocks()
+    }
+
+    fn handle_block(&mut self, block_hash: Hash256, block: Block) -> Result<(), CustomError> {
         let mut node_state = self.node_state_ref.lock()?;
         if !node_state.is_block_pending(&block_hash)? {
             drop(node_state);
@@ -229,17 +846,202 @@ impl NodeActionLoop {
         }
 
         // check if the node was synced before receiving the block
-        let is_synced = node_state.is_synced();
+        // los bloques que se vuelven a descargar para escanear las wallets no son nuevos
+        let is_synced = node_state.is_synced() && !node_state.is_block_processed(&block_hash);
 
         node_state.append_block(block_hash, &block)?;
+        // el peer que entrego el bloque tiene lugar para un nuevo pedido
+        node_state.request_pending_blocks()?;
         drop(node_state);
+        self.notify_sync_milestones()?;
 
-        if is_synced {
+        // los bloques anteriores al IBD descargados por los filtros compactos no se anuncian
+        if is_synced && block.header.timestamp > Network::current().ibd_start_timestamp() {
             self.broadcast_new_header(block.header)?;
         }
         Ok(())
     }
 
+    fn handle_merkle_block(
+        &mut self,
+        address: SocketAddrV6,
+        filtered_block: FilteredBlock,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        let in_chain = node_state.append_filtered_block(&filtered_block)?;
+        drop(node_state);
+
+        let message = match in_chain {
+            true => format!(
+                "Merkle block {} confirmed {} transactions",
+                filtered_block.hash(),
+                filtered_block.transactions.len()
+            ),
+            false => format!(
+                "Merkle block {} from peer {} is not part of the chain",
+                filtered_block.hash(),
+                address
+            ),
+        };
+        send_log(&self.logger_sender, Log::Debug(message));
+        Ok(())
+    }
+
+    fn handle_compact_block(
+        &mut self,
+        address: SocketAddrV6,
+        compact_block: CompactBlock,
+    ) -> Result<(), CustomError> {
+        let block_hash = *compact_block.header.hash();
+        let mut node_state = self.node_state_ref.lock()?;
+        if !node_state.is_block_pending(&block_hash)? {
+            drop(node_state);
+            return Ok(());
+        }
+
+        let mempool = node_state.get_all_pending_txs();
+        let partial_block = match compact_block.reconstruct(&mempool) {
+            Ok(partial_block) => partial_block,
+            Err(_) => {
+                drop(node_state);
+                return self.request_full_block(block_hash);
+            }
+        };
+
+        let missing_indexes = partial_block.missing_indexes();
+        if !missing_indexes.is_empty() {
+            node_state.append_partial_block(partial_block);
+            let message = GetBlockTxn::new(block_hash, missing_indexes);
+            return send_message(&mut node_state, address, message);
+        }
+        drop(node_state);
+
+        self.complete_partial_block(partial_block)
+    }
+
+    fn handle_block_txn(&mut self, block_txn: BlockTxn) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        let Some(mut partial_block) = node_state.remove_partial_block(&block_txn.block_hash) else { return Ok(()) };
+        drop(node_state);
+
+        if partial_block.fill(block_txn).is_err() {
+            return self.request_full_block(*partial_block.hash());
+        }
+        self.complete_partial_block(partial_block)
+    }
+
+    fn complete_partial_block(&mut self, partial_block: PartialBlock) -> Result<(), CustomError> {
+        let block_hash = *partial_block.hash();
+        let block = match partial_block.into_block() {
+            Ok(block) => block,
+            Err(_) => return self.request_full_block(block_hash),
+        };
+
+        // un short id puede colisionar, en ese caso el merkle root no coincide
+        if block.create_merkle_root().is_err() {
+            send_log(
+                &self.logger_sender,
+                Log::Message(format!(
+                    "Could not reconstruct compact block {}, requesting full block...",
+                    block_hash
+                )),
+            );
+            return self.request_full_block(block_hash);
+        }
+        if let Err(error) = validate_block_signature(&block) {
+            send_log(
+                &self.logger_sender,
+                Log::Warning(format!(
+                    "Error validating the block {}: {}",
+                    block_hash, error
+                )),
+            );
+            return Ok(());
+        }
+
+        self.handle_block(block_hash, block)
+    }
+
+    fn request_full_block(&mut self, block_hash: Hash256) -> Result<(), CustomError> {
+        let inventory = Inventory::new(InventoryType::Block, block_hash);
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.retry_pending_blocks(vec![inventory]);
+        node_state.request_pending_blocks()
+    }
+
+    fn handle_get_block_txn(
+        &mut self,
+        address: SocketAddrV6,
+        get_block_txn: GetBlockTxn,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        let block = match node_state.get_block(hash_as_string(&get_block_txn.block_hash)) {
+            Ok(block) => block,
+            Err(_) => {
+                let inventory = Inventory::new(InventoryType::Block, get_block_txn.block_hash);
+                let not_found = NotFound::new(vec![inventory]);
+                return send_message(&mut node_state, address, not_found);
+            }
+        };
+
+        let mut transactions = vec![];
+        for index in get_block_txn.indexes {
+            match block.transactions.get(index as usize) {
+                Some(tx) => transactions.push(tx.clone()),
+                None => return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid)),
+            }
+        }
+        let message = BlockTxn::new(get_block_txn.block_hash, transactions);
+        send_message(&mut node_state, address, message)
+    }
+
+    fn handle_cfheaders(
+        &mut self,
+        address: SocketAddrV6,
+        cfheaders: CFHeaders,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        match node_state.append_filter_headers(&cfheaders)? {
+            Some(FiltersRequest::Headers(get_cfheaders)) => {
+                send_message(&mut node_state, address, get_cfheaders)
+            }
+            Some(FiltersRequest::Filters(get_cfilters)) => {
+                send_message(&mut node_state, address, get_cfilters)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn handle_cfilter(
+        &mut self,
+        address: SocketAddrV6,
+        cfilter: CFilter,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        if node_state.check_block_filter(&cfilter)? {
+            send_log(
+                &self.logger_sender,
+                Log::Message(format!(
+                    "Compact filter matched block {}, requesting it...",
+                    cfilter.block_hash
+                )),
+            );
+            let inventory = Inventory::new(InventoryType::Block, cfilter.block_hash);
+            node_state.append_pending_blocks(vec![inventory])?;
+            node_state.request_pending_blocks()?;
+        }
+
+        match node_state.next_filters_request()? {
+            Some(FiltersRequest::Headers(get_cfheaders)) => {
+                send_message(&mut node_state, address, get_cfheaders)
+            }
+            Some(FiltersRequest::Filters(get_cfilters)) => {
+                send_message(&mut node_state, address, get_cfilters)
+            }
+            None => Ok(()),
+        }
+    }
+
     fn handle_pending_transaction(&mut self, transaction: Transaction) -> Result<(), CustomError> {
         let mut node_state = self.node_state_ref.lock()?;
         if !node_state.is_synced() {
@@ -248,10 +1050,11 @@ impl NodeActionLoop {
         }
 
         let is_pending_new = node_state.append_pending_tx(transaction.clone())?;
+        let fee_rate = node_state.transaction_fee_rate(&transaction);
         drop(node_state);
 
         if is_pending_new {
-            self.broadcast(transaction)?;
+            self.broadcast_to(transaction, |peer| peer.accepts_fee_rate(fee_rate))?;
         }
         Ok(())
     }
@@ -262,6 +1065,26 @@ impl NodeActionLoop {
         Ok(())
     }
 
+    fn handle_send_cmpct(
+        &mut self,
+        address: SocketAddrV6,
+        version: u64,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.peer_send_cmpct(address, version);
+        Ok(())
+    }
+
+    fn handle_fee_filter(
+        &mut self,
+        address: SocketAddrV6,
+        fee_rate: u64,
+    ) -> Result<(), CustomError> {
+        let mut node_state = self.node_state_ref.lock()?;
+        node_state.peer_fee_filter(address, fee_rate);
+        Ok(())
+    }
+
     fn handle_get_headers(
         &mut self,
         address: SocketAddrV6,
@@ -269,7 +1092,15 @@ impl NodeActionLoop {
     ) -> Result<(), CustomError> {
         let mut node_state = self.node_state_ref.lock()?;
         node_state.peer_requested_headers(address);
-        let headers = node_state.get_headers(getheaders);
+        let headers = node_state.get_headers(getheaders)?;
+        send_log(
+            &self.logger_sender,
+            Log::Debug(format!(
+                "Sending {} headers to peer {}",
+                headers.len(),
+                address
+            )),
+        );
 
         let message = Headers { headers };
         send_message(&mut node_state, address, message)
@@ -284,7 +1115,7 @@ impl NodeActionLoop {
         for inventory in getdata.get_inventories() {
             match inventory.inventory_type {
                 InventoryType::Block => {
-                    match node_state.get_block(hash_as_string(inventory.hash.clone())) {
+                    match node_state.get_block(hash_as_string(&inventory.hash)) {
                         Ok(block) => send_message(&mut node_state, address, block)?,
                         Err(_) => {
                             let not_found = NotFound::new(vec![inventory.clone()]);
@@ -292,7 +1123,20 @@ impl NodeActionLoop {
                         }
                     }
                 }
-                InventoryType::Tx => {
+                InventoryType::CompactBlock => {
+                    match node_state.get_block(hash_as_string(&inventory.hash)) {
+                        Ok(block) => {
+                            let nonce = chrono::Utc::now().timestamp_millis() as u64;
+                            let compact_block = CompactBlock::from_block(&block, nonce);
+                            send_message(&mut node_state, address, compact_block)?
+                        }
+                        Err(_) => {
+                            let not_found = NotFound::new(vec![inventory.clone()]);
+                            send_message(&mut node_state, address, not_found)?;
+                        }
+                    }
+                }
+                InventoryType::Tx | InventoryType::Wtx => {
                     match node_state.get_pending_tx(&inventory.hash) {
                         Some(tx) => send_message(&mut node_state, address, tx)?,
                         None => {
@@ -310,12 +1154,16 @@ impl NodeActionLoop {
         Ok(())
     }
 
-    fn broadcast(&mut self, message: impl Message) -> Result<(), CustomError> {
+    /// Envia un mensaje a los peers que cumplen el filtro, eliminando los que fallan.
+    fn broadcast_to<F>(&mut self, message: impl Message, filter: F) -> Result<(), CustomError>
+    where
+        F: Fn(&Peer) -> bool,
+    {
         let mut node_state = self.node_state_ref.lock()?;
 
         let peers = node_state.get_peers();
         let mut peers_to_remove = vec![];
-        for peer in peers {
+        for peer in peers.iter_mut().filter(|peer| filter(peer)) {
             if message.send(&mut peer.stream).is_err() {
                 peers_to_remove.push(peer.address);
             }
@@ -327,7 +1175,7 @@ impl NodeActionLoop {
                 &self.logger_sender,
                 Log::Message(format!(
                     "Error sending message {} to peer {}",
-                    message.get_command(),
+                    message.command(),
                     address,
                 )),
             );
@@ -355,7 +1203,7 @@ impl NodeActionLoop {
             } else {
                 let mut inventories = vec![];
                 for header in &headers_to_send {
-                    inventories.push(Inventory::new(InventoryType::Block, header.hash().clone()));
+                    inventories.push(Inventory::new(InventoryType::Block, *header.hash()));
                 }
                 let inv_msg = Inv::new(inventories);
                 inv_msg.send(&mut peer.stream)
@@ -385,3 +1233,13 @@ fn send_message(
     }
     Ok(())
 }
+
+/// Le pide a la interfaz grafica el PIN de la hardware wallet y espera a que el usuario lo ingrese.
+/// Devuelve None si el usuario cancela o no se pudo mostrar el pedido.
+fn request_hardware_wallet_pin(gui_sender: &glib::Sender<GUIEvents>) -> Option<String> {
+    let (pin_sender, pin_receiver) = mpsc::channel();
+    gui_sender
+        .send(GUIEvents::PinMatrixRequest(pin_sender))
+        .ok()?;
+    pin_receiver.recv().ok().flatten()
+}
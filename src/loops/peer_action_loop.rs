@@ -1,112 +1,96 @@
-use std::{
-    net::{SocketAddrV6, TcpStream},
-    sync::{mpsc, Arc, Mutex},
-    thread::{self, JoinHandle},
-};
+use std::{io::Write, net::SocketAddrV6, sync::mpsc};
 
 use crate::{
     error::CustomError,
-    logger::{send_log, Log},
+    logger::{send_log, Log, LogEntry},
     message::Message,
-    messages::{get_data::GetData, transaction::Transaction},
+    messages::{
+        get_data::GetData, send_cmpct::COMPACT_BLOCKS_PROTOCOL_VERSION, transaction::Transaction,
+    },
     peer::request_headers,
-    structs::inventory::Inventory,
+    structs::{
+        hash256::Hash256,
+        inventory::{Inventory, InventoryType},
+    },
 };
 
-use super::node_action_loop::NodeAction;
+use super::{network_loop::PeerWriter, node_action_loop::NodeAction};
 
 /// PeerAction es una enumeracion de las acciones que puede realizar un peer.
 /// Las acciones son:
 /// - GetHeaders: Solicita headers al peer.
 /// - GetData: Solicita data al peer.
 /// - SendTransaction: Envia una transaccion al peer.
-/// - Terminate: Termina la conexion con el peer.
+/// - Terminate: Termina el NetworkLoop, cerrando las conexiones con todos los peers.
 pub enum PeerAction {
-    GetHeaders(Option<Vec<u8>>),
+    GetHeaders(Vec<Hash256>),
     GetData(Vec<Inventory>),
     SendTransaction(Transaction),
     Terminate,
 }
 
 /// PeerActionLoop es una estructura que contiene los elementos necesarios para manejar los las acciones a enviar al peer asociado.
-/// Genera el loop de eventos alrededor de los PeerAction recibido por peer_action_receiver.
+/// No tiene un thread propio: el NetworkLoop le asigna los PeerAction recibidos por peer_action_receiver.
+/// Los mensajes se escriben en el PeerWriter de la conexion, o en cualquier otro stream, por ejemplo el de un MockPeer en las pruebas.
 /// Los elementos son:
 /// - address: Direccion del peer.
 /// - version: Version del nodo.
 /// - stream: Stream del peer.
 /// - logger_sender: Sender para enviar logs al logger.
-/// - peer_action_receiver: Receiver para recibir acciones del peer.
 /// - node_action_sender: Sender para enviar acciones al nodo.
-pub struct PeerActionLoop {
+pub struct PeerActionLoop<W: Write = PeerWriter> {
     pub address: SocketAddrV6,
     pub version: i32,
-    pub stream: TcpStream,
-    pub logger_sender: mpsc::Sender<Log>,
-    pub peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
+    pub stream: W,
+    pub logger_sender: mpsc::Sender<LogEntry>,
     pub node_action_sender: mpsc::Sender<NodeAction>,
 }
 
-impl PeerActionLoop {
-    /// Inicializa el loop de eventos en un thread.
-    pub fn spawn(
+impl<W: Write> PeerActionLoop<W> {
+    /// Crea el manejador de acciones del peer.
+    pub fn new(
         address: SocketAddrV6,
         version: i32,
-        stream: TcpStream,
-        logger_sender: mpsc::Sender<Log>,
-        peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
+        stream: W,
+        logger_sender: mpsc::Sender<LogEntry>,
         node_action_sender: mpsc::Sender<NodeAction>,
-    ) -> JoinHandle<Result<(), CustomError>> {
-        thread::spawn(move || -> Result<(), CustomError> {
-            let mut peer_action_thread = Self {
-                address,
-                peer_action_receiver,
-                version,
-                stream,
-                logger_sender,
-                node_action_sender,
-            };
-            peer_action_thread.event_loop()
-        })
+    ) -> Self {
+        Self {
+            address,
+            version,
+            stream,
+            logger_sender,
+            node_action_sender,
+        }
     }
 
-    fn event_loop(&mut self) -> Result<(), CustomError> {
-        loop {
-            let peer_message = self
-                .peer_action_receiver
-                .lock()
-                .map_err(|_| CustomError::CannotLockGuard)?
-                .recv()?;
-            let response = match peer_message {
-                PeerAction::GetHeaders(last_header) => self.handle_getheaders(last_header),
-                PeerAction::GetData(inventories) => self.handle_getdata(inventories),
-                PeerAction::SendTransaction(transaction) => {
-                    self.handle_send_transaction(&transaction)
-                }
-                PeerAction::Terminate => break,
-            };
-
-            if let Err(error) = response {
-                send_log(
-                    &self.logger_sender,
-                    Log::Message(format!("Error on PeerActionLoop: {error}")),
-                );
-                self.node_action_sender
-                    .send(NodeAction::PeerError(self.address))?;
-                break;
-            }
+    /// Realiza una accion con el peer.
+    /// Devuelve CustomError si no se pudo enviar el mensaje, en ese caso el NetworkLoop cierra la conexion.
+    pub fn handle(&mut self, peer_action: PeerAction) -> Result<(), CustomError> {
+        match peer_action {
+            PeerAction::GetHeaders(block_locator) => self.handle_getheaders(block_locator),
+            PeerAction::GetData(inventories) => self.handle_getdata(inventories),
+            PeerAction::SendTransaction(transaction) => self.handle_send_transaction(&transaction),
+            PeerAction::Terminate => Ok(()),
         }
-        Ok(())
     }
 
     fn handle_send_transaction(&mut self, transaction: &Transaction) -> Result<(), CustomError> {
         transaction.send(&mut self.stream)?;
         send_log(
             &self.logger_sender,
-            Log::Message("Sending transaction".to_string()),
+            Log::Debug("Sending transaction".to_string()),
         );
         Ok(())
     }
-    fn handle_getdata(&mut self, inventories: Vec<Inventory>) -> Result<(), CustomError> {
+    fn handle_getdata(&mut self, mut inventories: Vec<Inventory>) -> Result<(), CustomError> {
+        if self.version < COMPACT_BLOCKS_PROTOCOL_VERSION {
+            for inventory in inventories.iter_mut() {
+                if inventory.inventory_type == InventoryType::CompactBlock {
+                    inventory.inventory_type = InventoryType::Block;
+                }
+            }
+        }
         let inventories_clone = inventories.clone();
         let request = GetData::new(inventories).send(&mut self.stream);
         if let Err(error) = request {
@@ -117,9 +101,9 @@ impl PeerActionLoop {
         Ok(())
     }
 
-    fn handle_getheaders(&mut self, last_header: Option<Vec<u8>>) -> Result<(), CustomError> {
+    fn handle_getheaders(&mut self, block_locator: Vec<Hash256>) -> Result<(), CustomError> {
         request_headers(
-            last_header,
+            block_locator,
             self.version,
             &mut self.stream,
             &self.logger_sender,
@@ -127,3 +111,74 @@ impl PeerActionLoop {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use crate::mock_peer::{DuplexStream, MockPeer};
+
+    use super::*;
+
+    fn simulation(
+        version: i32,
+    ) -> (
+        PeerActionLoop<DuplexStream>,
+        MockPeer,
+        mpsc::Receiver<NodeAction>,
+    ) {
+        let address = SocketAddrV6::new(Ipv6Addr::LOCALHOST, 18333, 0, 0);
+        let (peer, node_stream) = MockPeer::new(address);
+        let (logger_sender, _) = mpsc::channel();
+        let (node_action_sender, node_actions) = mpsc::channel();
+        let action_loop = PeerActionLoop::new(
+            address,
+            version,
+            node_stream,
+            logger_sender,
+            node_action_sender,
+        );
+        (action_loop, peer, node_actions)
+    }
+
+    #[test]
+    fn requests_blocks_in_the_format_the_peer_supports() {
+        let inventories = vec![
+            Inventory::new(InventoryType::CompactBlock, Hash256::new([1; 32])),
+            Inventory::new(InventoryType::Block, Hash256::new([2; 32])),
+        ];
+
+        let (mut action_loop, mut peer, _) = simulation(COMPACT_BLOCKS_PROTOCOL_VERSION);
+        action_loop
+            .handle(PeerAction::GetData(inventories.clone()))
+            .unwrap();
+        let getdata = peer.expect::<GetData>().unwrap();
+        assert_eq!(getdata.get_inventories(), &inventories);
+
+        // los peers sin compact blocks reciben el pedido del bloque completo
+        let (mut action_loop, mut peer, _) = simulation(COMPACT_BLOCKS_PROTOCOL_VERSION - 1);
+        action_loop
+            .handle(PeerAction::GetData(inventories.clone()))
+            .unwrap();
+        let getdata = peer.expect::<GetData>().unwrap();
+        assert!(getdata
+            .get_inventories()
+            .iter()
+            .all(|inventory| inventory.inventory_type == InventoryType::Block));
+    }
+
+    #[test]
+    fn returns_the_blocks_requested_to_a_disconnected_peer() {
+        let (mut action_loop, peer, node_actions) = simulation(70016);
+        drop(peer);
+
+        let inventories = vec![Inventory::new(InventoryType::Block, Hash256::new([1; 32]))];
+        assert!(action_loop
+            .handle(PeerAction::GetData(inventories.clone()))
+            .is_err());
+        assert!(matches!(
+            node_actions.try_recv().unwrap(),
+            NodeAction::GetDataError(returned) if returned == inventories
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    net::SocketAddrV6,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+    peer::Peer,
+    shutdown::ShutdownSignal,
+    utils::get_address_v6,
+};
+
+use super::network_loop::NetworkHandle;
+
+/// Tiempo en segundos entre cada verificacion de la cantidad de peers conectados.
+const PEER_DISCOVERY_INTERVAL: u64 = 30;
+
+/// PeerDiscoveryLoop es el loop que se encarga de mantener la cantidad de peers conectados.
+/// Cuando se pierden conexiones, se conecta a nuevos peers a partir de las direcciones conocidas por AddrMan,
+/// y registra el resultado de cada intento de conexion.
+/// Los elementos son:
+/// - logger_sender: Sender para enviar logs al logger
+/// - node_state_ref: Referencia al estado del nodo
+/// - address: Dirección del nodo
+/// - services: Servicios que ofrece el nodo
+/// - version: Versión del protocolo que maneja el nodo
+/// - npeers: Cantidad de peers a los que el nodo intenta mantenerse conectado
+/// - network: NetworkHandle para registrar las conexiones en el NetworkLoop
+/// - shutdown: ShutdownSignal, el loop termina cuando se solicita el cierre del nodo
+pub struct PeerDiscoveryLoop {
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub node_state_ref: Arc<Mutex<NodeState>>,
+    pub address: SocketAddrV6,
+    pub services: u64,
+    pub version: i32,
+    pub npeers: u8,
+    pub network: NetworkHandle,
+    pub shutdown: ShutdownSignal,
+}
+
+impl PeerDiscoveryLoop {
+    /// Inicializa el loop en un thread.
+    pub fn spawn(self) -> JoinHandle<Result<(), CustomError>> {
+        thread::spawn(move || -> Result<(), CustomError> { self.event_loop() })
+    }
+
+    fn event_loop(&self) -> Result<(), CustomError> {
+        while !self
+            .shutdown
+            .wait(Duration::from_secs(PEER_DISCOVERY_INTERVAL))
+        {
+            let node_state = self.node_state_ref.lock()?;
+            // los peers entrantes no cuentan, pueden no enviarnos bloques
+            let connected = node_state.get_outbound_peers_count();
+            let missing_peers = (self.npeers as usize).saturating_sub(connected);
+            if missing_peers == 0 {
+                continue;
+            }
+            let candidates = node_state.get_peer_candidates(missing_peers);
+            drop(node_state);
+
+            if candidates.is_empty() {
+                continue;
+            }
+            send_log(
+                &self.logger_sender,
+                Log::Message(format!(
+                    "Connected to {connected} of {} peers, trying {} known addresses...",
+                    self.npeers,
+                    candidates.len()
+                )),
+            );
+
+            for address in candidates {
+                if self.shutdown.is_requested() {
+                    break;
+                }
+                // el handshake se hace sin tomar el lock del estado del nodo
+                let peer = Peer::call(
+                    address,
+                    self.address,
+                    self.services,
+                    self.version,
+                    &self.network,
+                    self.logger_sender.clone(),
+                );
+
+                let mut node_state = self.node_state_ref.lock()?;
+                match peer {
+                    Ok(peer) => {
+                        node_state.peer_connected(peer.address, peer.services)?;
+                        node_state.append_peers(vec![peer]);
+                    }
+                    Err(error) => {
+                        send_log(
+                            &self.logger_sender,
+                            Log::Warning(format!("Error connecting to peer: {}", error)),
+                        );
+                        node_state.peer_connection_failed(get_address_v6(address))?;
+                    }
+                }
+                drop(node_state);
+            }
+        }
+        Ok(())
+    }
+}
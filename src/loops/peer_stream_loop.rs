@@ -1,218 +1,757 @@
 use std::{
-    io::Read,
-    net::{SocketAddrV6, TcpStream},
-    sync::mpsc,
-    thread::{self, JoinHandle},
+    io::{Read, Write},
+    net::SocketAddrV6,
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    error::CustomError,
-    logger::{send_log, Log},
-    message::{Message, MessageHeader},
+    error::{CustomError, NetworkError},
+    logger::{send_log, Log, LogEntry},
+    message::{Command, Message, MessageHeader},
     messages::{
+        addr::{Addr, AddrV2},
         block::Block,
+        block_txn::BlockTxn,
+        cfheaders::CFHeaders,
+        cfilter::CFilter,
+        compact_block::CompactBlock,
+        fee_filter::FeeFilter,
+        get_block_txn::GetBlockTxn,
         get_data::GetData,
         get_headers::GetHeaders,
         headers::Headers,
         inv::Inv,
+        merkle_block::{FilteredBlock, MerkleBlock},
         ping_pong::{Ping, Pong},
+        reject::Reject,
+        send_cmpct::SendCmpct,
         send_headers::SendHeaders,
         transaction::Transaction,
     },
     peer::request_headers,
+    signet::validate_block_signature,
+    states::{
+        headers_state::MAX_HEADERS_PER_MESSAGE, peer_info_state::PeerInfoRegistry,
+        peer_score_state::Misbehavior,
+    },
     structs::{
-        block_header::BlockHeader,
+        hash256::Hash256,
         inventory::{Inventory, InventoryType},
     },
 };
 
-use super::node_action_loop::NodeAction;
+use super::{
+    header_validation_loop::HeaderBatch, network_loop::PeerWriter, node_action_loop::NodeAction,
+};
+
+/// Tiempo que se espera entre los ping enviados al peer.
+const PING_INTERVAL: Duration = Duration::from_secs(2 * 60);
+/// Tiempo maximo que se espera el pong del peer, si no responde se lo desconecta.
+const PONG_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 /// PeerStreamLoop es una estructura que contiene los elementos necesarios para manejar los mensajes recibidos del peer asociado.
-/// Genera el loop de eventos alrededor de los mensajes recibidos por el TcpStream.
+/// No tiene un thread propio: el NetworkLoop lee los mensajes del stream y se los pasa a handle_message.
+/// Las respuestas se escriben en el PeerWriter de la conexion, o en cualquier otro stream, por ejemplo el de un MockPeer en las pruebas.
 /// Los elementos son:
 /// - address: Direccion del peer.
 /// - stream: Stream del peer.
 /// - node_action_sender: Sender para enviar acciones al nodo.
+/// - header_batch_sender: Sender para enviar los mensajes headers a validar al HeaderValidationLoop.
 /// - version: Version del nodo.
 /// - logger_sender: Sender para enviar logs al logger.
-pub struct PeerStreamLoop {
+/// - last_ping: Momento en que se envio el ultimo ping al peer.
+/// - ping_nonce: Nonce del ping enviado que el peer todavia no respondio.
+/// - peer_info_ref: Referencia al registro de peers, donde se guarda la latencia y el trafico del peer.
+/// - filtered_block: Ultimo merkle block recibido, a la espera de los mensajes tx con sus transacciones.
+pub struct PeerStreamLoop<W: Write = PeerWriter> {
     pub address: SocketAddrV6,
-    pub stream: TcpStream,
+    pub stream: W,
     pub node_action_sender: mpsc::Sender<NodeAction>,
+    pub header_batch_sender: mpsc::Sender<HeaderBatch>,
     pub version: i32,
-    pub logger_sender: mpsc::Sender<Log>,
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub last_ping: Instant,
+    pub ping_nonce: Option<u64>,
+    pub peer_info_ref: Arc<Mutex<PeerInfoRegistry>>,
+    pub filtered_block: Option<FilteredBlock>,
 }
 
-impl PeerStreamLoop {
-    #[must_use]
-    /// Inicializa el loop de eventos en un thread.
-    pub fn spawn(
-        version: i32,
+impl<W: Write> PeerStreamLoop<W> {
+    /// Crea el manejador de los mensajes del peer.
+    pub fn new(
         address: SocketAddrV6,
-        stream: TcpStream,
-        logger_sender: mpsc::Sender<Log>,
+        version: i32,
+        stream: W,
+        logger_sender: mpsc::Sender<LogEntry>,
         node_action_sender: mpsc::Sender<NodeAction>,
-    ) -> JoinHandle<Result<(), CustomError>> {
-        thread::spawn(move || -> Result<(), CustomError> {
-            let mut peer_action_thread = Self {
-                address,
-                stream,
-                node_action_sender,
-                version,
-                logger_sender,
-            };
-            peer_action_thread.event_loop()
-        })
-    }
-
-    fn event_loop(&mut self) -> Result<(), CustomError> {
-        loop {
-            let response_header = MessageHeader::read(&mut self.stream)?;
-
-            let response = match response_header.command.as_str() {
-                "headers" => self.handle_headers(&response_header),
-                "block" => self.handle_block(&response_header),
-                "ping" => self.handle_ping(&response_header),
-                "inv" => self.handle_inv(&response_header),
-                "tx" => self.handle_tx(&response_header),
-                "notfound" => self.handle_notfound(&response_header),
-                "sendheaders" => self.handle_sendheaders(&response_header),
-                "getheaders" => self.handle_getheaders(&response_header),
-                "getdata" => self.handle_getdata(&response_header),
-                _ => self.ignore_message(&response_header),
-            };
+        header_batch_sender: mpsc::Sender<HeaderBatch>,
+        peer_info_ref: Arc<Mutex<PeerInfoRegistry>>,
+    ) -> Self {
+        Self {
+            address,
+            stream,
+            node_action_sender,
+            header_batch_sender,
+            version,
+            logger_sender,
+            last_ping: Instant::now(),
+            ping_nonce: None,
+            peer_info_ref,
+            filtered_block: None,
+        }
+    }
 
-            if let Err(error) = response {
-                send_log(
-                    &self.logger_sender,
-                    Log::Message(format!("Error on PeerStreamLoop: {error}")),
-                );
-                self.node_action_sender
-                    .send(NodeAction::PeerError(self.address))?;
-                break;
+    /// Registra bytes recibidos del peer.
+    pub fn record_received(&self, bytes: usize) -> Result<(), CustomError> {
+        self.peer_info_ref
+            .lock()?
+            .record_received(&self.address, bytes);
+        Ok(())
+    }
+
+    /// Registra bytes enviados al peer.
+    pub fn record_sent(&self, bytes: usize) -> Result<(), CustomError> {
+        self.peer_info_ref.lock()?.record_sent(&self.address, bytes);
+        Ok(())
+    }
+
+    /// Registra un mensaje recibido del peer con su comando y su tamaño, incluyendo el header.
+    pub fn record_message_received(
+        &self,
+        command: Command,
+        bytes: usize,
+    ) -> Result<(), CustomError> {
+        self.peer_info_ref
+            .lock()?
+            .record_message_received(command.as_str(), bytes);
+        Ok(())
+    }
+
+    /// Registra un mensaje enviado al peer con su comando y su tamaño, incluyendo el header.
+    pub fn record_message_sent(&self, command: Command, bytes: usize) -> Result<(), CustomError> {
+        self.peer_info_ref
+            .lock()?
+            .record_message_sent(command.as_str(), bytes);
+        Ok(())
+    }
+
+    /// Procesa un mensaje recibido del peer a partir de su header y su payload, que se parsea sin copiarlo.
+    /// Solo los headers se copian, ya que se validan en otro thread.
+    /// Devuelve CustomError si el mensaje no es valido, en ese caso el NetworkLoop cierra la conexion.
+    pub fn handle_message(
+        &mut self,
+        response_header: &MessageHeader,
+        mut payload: &[u8],
+    ) -> Result<(), CustomError> {
+        // las transacciones de un merkle block llegan inmediatamente despues de el
+        if response_header.command != Command::Tx {
+            self.complete_filtered_block()?;
+        }
+        match response_header.command {
+            Command::Headers => self.handle_headers(payload.to_vec()),
+            Command::Block => self.handle_block(&mut payload),
+            Command::MerkleBlock => self.handle_merkleblock(&mut payload),
+            Command::Ping => self.handle_ping(&mut payload),
+            Command::Inv => self.handle_inv(&mut payload),
+            Command::Tx => self.handle_tx(&mut payload),
+            Command::NotFound => self.handle_notfound(&mut payload),
+            Command::SendHeaders => self.handle_sendheaders(&mut payload),
+            Command::FeeFilter => self.handle_feefilter(&mut payload),
+            Command::GetHeaders => self.handle_getheaders(&mut payload),
+            Command::GetData => self.handle_getdata(&mut payload),
+            Command::SendCmpct => self.handle_sendcmpct(&mut payload),
+            Command::CmpctBlock => self.handle_cmpctblock(&mut payload),
+            Command::GetBlockTxn => self.handle_getblocktxn(&mut payload),
+            Command::BlockTxn => self.handle_blocktxn(&mut payload),
+            Command::CFHeaders => self.handle_cfheaders(&mut payload),
+            Command::CFilter => self.handle_cfilter(&mut payload),
+            Command::Reject => self.handle_reject(&mut payload),
+            Command::Addr => self.handle_addr(&mut payload),
+            Command::AddrV2 => self.handle_addrv2(&mut payload),
+            Command::Pong => self.handle_pong(&mut payload),
+            _ => self.ignore_message(response_header),
+        }
+    }
+
+    /// Envia un ping al peer cada PING_INTERVAL.
+    /// Devuelve CustomError si el peer no respondio el ultimo ping dentro de PONG_TIMEOUT.
+    pub fn keep_alive(&mut self) -> Result<(), CustomError> {
+        if self.ping_nonce.is_some() {
+            if self.last_ping.elapsed() > PONG_TIMEOUT {
+                return Err(CustomError::Network(NetworkError::PeerTimedOut));
             }
+            return Ok(());
         }
+        if self.last_ping.elapsed() < PING_INTERVAL {
+            return Ok(());
+        }
+
+        let nonce = chrono::Utc::now().timestamp_millis() as u64;
+        Ping { nonce }.send(&mut self.stream)?;
+        self.last_ping = Instant::now();
+        self.ping_nonce = Some(nonce);
         Ok(())
     }
 
-    fn handle_headers(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let response = match Headers::read(&mut self.stream, response_header.payload_size) {
-            Ok(response) => response,
+    /// Envia los headers recibidos al HeaderValidationLoop, que valida su proof of work en otro thread
+    /// para que el NetworkLoop siga leyendo los mensajes de los peers.
+    fn handle_headers(&mut self, payload: Vec<u8>) -> Result<(), CustomError> {
+        // si el peer envio la cantidad maxima de headers se piden los siguientes, con un block locator
+        // de los recibidos por si el peer cambia de rama mientras tanto
+        let block_locator = match Headers::count(&payload) {
+            Ok(MAX_HEADERS_PER_MESSAGE) => Headers::block_locator(&payload).map(Some),
+            Ok(_) => Ok(None),
+            Err(error) => Err(error),
+        };
+        let block_locator = match block_locator {
+            Ok(block_locator) => block_locator,
             Err(error) => {
                 self.node_action_sender.send(NodeAction::GetHeadersError)?;
                 return Err(error);
             }
         };
 
-        if response.headers.len() == 2000 {
-            let last_header = response.headers.last().map(BlockHeader::hash).cloned();
+        if let Some(block_locator) = block_locator {
             request_headers(
-                last_header,
+                block_locator,
                 self.version,
                 &mut self.stream,
                 &self.logger_sender,
                 &self.node_action_sender,
             )?;
         }
-        self.node_action_sender
-            .send(NodeAction::NewHeaders(response))?;
+        self.header_batch_sender.send(HeaderBatch {
+            address: self.address,
+            payload,
+        })?;
         Ok(())
     }
 
-    fn handle_block(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let block = Block::read(&mut self.stream, response_header.payload_size)?;
-        if let Err(error) = block.create_merkle_root() {
-            let inventory = Inventory::new(InventoryType::Block, block.header.hash().clone());
+    fn handle_block(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        self.handle_parsed_block(Block::parse(payload)?)
+    }
+
+    /// Procesa un bloque recibido del peer que ya fue parseado, por ejemplo por el BlockStreamParser del NetworkLoop.
+    /// Devuelve CustomError si el merkle root del bloque no es valido, o si en signet su firma no resuelve el challenge.
+    pub fn handle_parsed_block(&mut self, block: Block) -> Result<(), CustomError> {
+        if let Err(error) = block
+            .create_merkle_root()
+            .and_then(|_| validate_block_signature(&block))
+        {
+            let inventory = Inventory::new(InventoryType::Block, *block.header.hash());
 
             self.node_action_sender
                 .send(NodeAction::GetDataError(vec![inventory]))?;
+            self.node_action_sender.send(NodeAction::PeerMisbehaving(
+                self.address,
+                Misbehavior::InvalidBlock,
+            ))?;
 
             send_log(
                 &self.logger_sender,
-                Log::Message(format!(
-                    "Error validating the merkle root in the block: {:?}",
-                    block.header.hash()
+                Log::Warning(format!(
+                    "Error validating the block {:?}: {}",
+                    block.header.hash(),
+                    error
                 )),
             );
             return Err(error);
         };
 
         self.node_action_sender
-            .send(NodeAction::Block((block.header.hash().clone(), block)))?;
+            .send(NodeAction::Block((*block.header.hash(), block)))?;
 
         Ok(())
     }
 
-    fn handle_ping(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let ping = Ping::read(&mut self.stream, response_header.payload_size)?;
+    fn handle_ping(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let ping = Ping::parse(payload)?;
         let pong = Pong { nonce: ping.nonce };
         pong.send(&mut self.stream)?;
         Ok(())
     }
 
-    fn handle_inv(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let inv = Inv::read(&mut self.stream, response_header.payload_size)?;
+    fn handle_pong(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let pong = Pong::parse(payload)?;
+        if self.ping_nonce == Some(pong.nonce) {
+            self.ping_nonce = None;
+            let latency = self.last_ping.elapsed();
+            self.peer_info_ref
+                .lock()?
+                .record_ping(&self.address, latency);
+            send_log(
+                &self.logger_sender,
+                Log::Debug(format!(
+                    "Pong from {} in {}ms",
+                    self.address,
+                    latency.as_millis()
+                )),
+            );
+        }
+        Ok(())
+    }
+
+    fn handle_inv(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let inv = Inv::parse(payload)?;
 
-        for inventory in inv.inventories {
-            if inventory.inventory_type == InventoryType::Tx {
-                let message = GetData::new(vec![inventory]);
-                message.send(&mut self.stream)?;
+        // los bloques nuevos se anuncian por inv a los peers que no pidieron sendheaders
+        let block_hashes: Vec<Hash256> = inv
+            .inventories
+            .iter()
+            .filter(|inventory| {
+                matches!(
+                    inventory.inventory_type,
+                    InventoryType::Block | InventoryType::WitnessBlock
+                )
+            })
+            .map(|inventory| inventory.hash)
+            .collect();
+        if !block_hashes.is_empty() {
+            self.node_action_sender
+                .send(NodeAction::BlocksAnnounced(self.address, block_hashes))?;
+        }
+
+        // las transacciones que no estan en el mempool se piden desde el NodeActionLoop
+        // los peers con wtxidrelay las anuncian por su wtxid
+        let tx_hashes: Vec<Hash256> = inv
+            .inventories
+            .into_iter()
+            .filter(|inventory| {
+                matches!(
+                    inventory.inventory_type,
+                    InventoryType::Tx | InventoryType::Wtx
+                )
+            })
+            .map(|inventory| inventory.hash)
+            .collect();
+        if !tx_hashes.is_empty() {
+            self.node_action_sender
+                .send(NodeAction::TxAnnounced(self.address, tx_hashes))?;
+        }
+        Ok(())
+    }
+
+    fn handle_reject(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let reject = Reject::parse(payload)?;
+        if reject.rejected_tx_hash().is_some() {
+            self.node_action_sender
+                .send(NodeAction::TxRejected(self.address, reject))?;
+        }
+        Ok(())
+    }
+
+    /// Verifica el merkle tree parcial del merkle block y espera las transacciones que coinciden con el filtro.
+    /// Devuelve CustomError si el merkle tree parcial no corresponde al merkle root del header.
+    fn handle_merkleblock(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let merkle_block = MerkleBlock::parse(payload)?;
+        let block_hash = *merkle_block.header.hash();
+        let filtered_block = match FilteredBlock::new(merkle_block) {
+            Ok(filtered_block) => filtered_block,
+            Err(error) => {
+                self.node_action_sender.send(NodeAction::PeerMisbehaving(
+                    self.address,
+                    Misbehavior::InvalidBlock,
+                ))?;
+                send_log(
+                    &self.logger_sender,
+                    Log::Warning(format!(
+                        "Error validating the merkle block {}: {}",
+                        block_hash, error
+                    )),
+                );
+                return Err(error);
             }
+        };
+
+        self.filtered_block = Some(filtered_block);
+        if self
+            .filtered_block
+            .as_ref()
+            .is_some_and(FilteredBlock::is_complete)
+        {
+            self.complete_filtered_block()?;
+        }
+        Ok(())
+    }
+
+    /// Envia al nodo el merkle block pendiente con las transacciones recibidas hasta el momento.
+    fn complete_filtered_block(&mut self) -> Result<(), CustomError> {
+        if let Some(filtered_block) = self.filtered_block.take() {
+            self.node_action_sender
+                .send(NodeAction::MerkleBlock(self.address, filtered_block))?;
         }
         Ok(())
     }
 
-    fn handle_tx(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let tx = Transaction::read(&mut self.stream, response_header.payload_size)?;
+    fn handle_tx(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let tx = Transaction::parse(payload)?;
+        // las transacciones de un merkle block ya estan confirmadas, no forman parte del mempool del peer
+        if let Some(filtered_block) = self.filtered_block.as_mut() {
+            if filtered_block.expects(&tx.hash()) {
+                filtered_block.append_transaction(tx);
+                if filtered_block.is_complete() {
+                    self.complete_filtered_block()?;
+                }
+                return Ok(());
+            }
+            self.complete_filtered_block()?;
+        }
+        // el peer envia transacciones que estan en su mempool
+        self.node_action_sender
+            .send(NodeAction::TxSeenInMempool(self.address, tx.hash()))?;
         self.node_action_sender
             .send(NodeAction::PendingTransaction(tx))?;
         Ok(())
     }
 
-    fn handle_notfound(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let notfound = GetData::read(&mut self.stream, response_header.payload_size)?;
+    fn handle_notfound(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let notfound = GetData::parse(payload)?;
         let inventories = notfound.get_inventories().clone();
         self.node_action_sender
-            .send(NodeAction::GetDataError(inventories))?;
+            .send(NodeAction::NotFound(self.address, inventories))?;
 
         Ok(())
     }
 
-    fn handle_sendheaders(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let _ = SendHeaders::read(&mut self.stream, response_header.payload_size)?;
+    fn handle_sendheaders(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let _ = SendHeaders::parse(payload)?;
         self.node_action_sender
             .send(NodeAction::SendHeaders(self.address))?;
         Ok(())
     }
 
-    fn handle_getheaders(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let getheaders = GetHeaders::read(&mut self.stream, response_header.payload_size)?;
+    fn handle_feefilter(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let fee_filter = FeeFilter::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::FeeFilter(self.address, fee_filter.fee_rate))?;
+        Ok(())
+    }
+
+    fn handle_getheaders(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let getheaders = GetHeaders::parse(payload)?;
         self.node_action_sender
             .send(NodeAction::GetHeaders(self.address, getheaders))?;
         Ok(())
     }
 
-    fn handle_getdata(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let getdata = GetData::read(&mut self.stream, response_header.payload_size)?;
+    fn handle_getdata(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let getdata = GetData::parse(payload)?;
         self.node_action_sender
             .send(NodeAction::GetData(self.address, getdata))?;
         Ok(())
     }
 
+    fn handle_sendcmpct(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        // No pedimos anuncios por compact blocks, solo los usamos al solicitar bloques
+        let send_cmpct = SendCmpct::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::SendCmpct(self.address, send_cmpct.version))?;
+        Ok(())
+    }
+
+    fn handle_cmpctblock(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let compact_block = CompactBlock::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::CompactBlock(self.address, compact_block))?;
+        Ok(())
+    }
+
+    fn handle_getblocktxn(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let get_block_txn = GetBlockTxn::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::GetBlockTxn(self.address, get_block_txn))?;
+        Ok(())
+    }
+
+    fn handle_blocktxn(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let block_txn = BlockTxn::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::BlockTxn(block_txn))?;
+        Ok(())
+    }
+
+    fn handle_cfheaders(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let cfheaders = CFHeaders::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::CFHeaders(self.address, cfheaders))?;
+        Ok(())
+    }
+
+    fn handle_cfilter(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let cfilter = CFilter::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::CFilter(self.address, cfilter))?;
+        Ok(())
+    }
+
+    fn handle_addr(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let addr = Addr::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::Addresses(self.address, addr.addresses))?;
+        Ok(())
+    }
+
+    fn handle_addrv2(&mut self, payload: &mut impl Read) -> Result<(), CustomError> {
+        let addr = AddrV2::parse(payload)?;
+        self.node_action_sender
+            .send(NodeAction::Addresses(self.address, addr.addresses))?;
+        Ok(())
+    }
+
     fn ignore_message(&mut self, response_header: &MessageHeader) -> Result<(), CustomError> {
-        let cmd = response_header.command.as_str();
-        if cmd != "alert" && cmd != "addr" {
+        if response_header.command != Command::Alert {
             send_log(
                 &self.logger_sender,
-                Log::Message(format!(
+                Log::Debug(format!(
                     "Received unknown command: {:?}",
-                    response_header.command
+                    response_header.command.as_str()
                 )),
             );
         }
-        let mut buffer: Vec<u8> = vec![0; response_header.payload_size as usize];
-        self.stream.read_exact(&mut buffer)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, net::Ipv6Addr, time::Duration};
+
+    use crate::{
+        loops::header_validation_loop::HeaderValidationLoop,
+        mock_peer::{deliver, DuplexStream, MockPeer},
+        structs::block_header::BlockHeader,
+    };
+
+    use super::*;
+
+    /// Simulation conecta un PeerStreamLoop a un MockPeer, con los receivers de las acciones y los logs que genera.
+    /// Los headers recibidos se validan en el HeaderValidationLoop, por lo que sus acciones llegan desde otro thread.
+    struct Simulation {
+        stream_loop: PeerStreamLoop<DuplexStream>,
+        peer: MockPeer,
+        node_actions: mpsc::Receiver<NodeAction>,
+        _header_validation: HeaderValidationLoop,
+        _logs: mpsc::Receiver<LogEntry>,
+    }
+
+    fn simulation() -> Simulation {
+        let address = SocketAddrV6::new(Ipv6Addr::LOCALHOST, 18333, 0, 0);
+        let (peer, node_stream) = MockPeer::new(address);
+        let (logger_sender, logs) = mpsc::channel();
+        let (node_action_sender, node_actions) = mpsc::channel();
+        let mut header_validation =
+            HeaderValidationLoop::spawn(1, node_action_sender.clone(), logger_sender.clone());
+        let stream_loop = PeerStreamLoop::new(
+            address,
+            70016,
+            node_stream,
+            logger_sender,
+            node_action_sender,
+            header_validation.batch_sender(),
+            PeerInfoRegistry::new(),
+        );
+        Simulation {
+            stream_loop,
+            peer,
+            node_actions,
+            _header_validation: header_validation,
+            _logs: logs,
+        }
+    }
+
+    /// Devuelve headers encadenados entre si, minados con la dificultad minima de regtest.
+    fn chained_headers(count: usize) -> Headers {
+        let mut headers = Headers::new();
+        let mut prev_block_hash = Hash256::ZERO;
+        let mut nonce = 0;
+        while headers.headers.len() < count {
+            let header = BlockHeader {
+                version: 1,
+                prev_block_hash,
+                merkle_root: Hash256::ZERO,
+                timestamp: 1677449562,
+                bits: 0x207fffff,
+                nonce,
+                hash: Hash256::ZERO,
+                broadcasted: false,
+                block_downloaded: false,
+            };
+            nonce += 1;
+            // los nonce cuyo hash no cumple la proof of work se descartan
+            let Ok(header) = BlockHeader::parse(header.serialize()) else { continue };
+            prev_block_hash = *header.hash();
+            headers.headers.push(header);
+        }
+        headers
+    }
+
+    fn test_block() -> Block {
+        Block::parse(&mut fs::read("tests/blocks/test_block.bin").unwrap().as_slice()).unwrap()
+    }
+
+    #[test]
+    fn requests_the_next_headers_after_a_full_batch() {
+        let mut simulation = simulation();
+        let headers = chained_headers(MAX_HEADERS_PER_MESSAGE);
+        let last_hash = *headers.headers.last().unwrap().hash();
+
+        headers.send(&mut simulation.peer).unwrap();
+        assert_eq!(deliver(&mut simulation.stream_loop).unwrap(), 1);
+
+        let getheaders = simulation.peer.expect::<GetHeaders>().unwrap();
+        assert_eq!(getheaders.block_locator_hashes[0], last_hash);
+        assert_eq!(getheaders.hash_stop, Hash256::ZERO);
+        let node_action = simulation
+            .node_actions
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(
+            node_action,
+            NodeAction::NewHeaders(address, headers)
+                if address == simulation.peer.address && headers.headers.len() == MAX_HEADERS_PER_MESSAGE
+        ));
+
+        // con un lote incompleto el peer ya no tiene mas headers para enviar
+        chained_headers(10).send(&mut simulation.peer).unwrap();
+        deliver(&mut simulation.stream_loop).unwrap();
+        assert!(simulation.peer.received_commands().is_empty());
+        assert!(matches!(
+            simulation.node_actions.recv_timeout(Duration::from_secs(5)).unwrap(),
+            NodeAction::NewHeaders(_, headers) if headers.headers.len() == 10
+        ));
+    }
+
+    #[test]
+    fn answers_pings_and_records_the_latency_of_pongs() {
+        let mut simulation = simulation();
+        Ping { nonce: 7 }.send(&mut simulation.peer).unwrap();
+        deliver(&mut simulation.stream_loop).unwrap();
+        assert_eq!(simulation.peer.expect::<Pong>().unwrap().nonce, 7);
+
+        simulation.stream_loop.ping_nonce = Some(9);
+        Pong { nonce: 8 }.send(&mut simulation.peer).unwrap();
+        deliver(&mut simulation.stream_loop).unwrap();
+        assert_eq!(simulation.stream_loop.ping_nonce, Some(9));
+
+        Pong { nonce: 9 }.send(&mut simulation.peer).unwrap();
+        deliver(&mut simulation.stream_loop).unwrap();
+        assert_eq!(simulation.stream_loop.ping_nonce, None);
+    }
+
+    #[test]
+    fn forwards_the_relay_preferences_of_the_peer() {
+        let mut simulation = simulation();
+        SendHeaders::new().send(&mut simulation.peer).unwrap();
+        FeeFilter::new(5).send(&mut simulation.peer).unwrap();
+        SendCmpct::new(false, 2).send(&mut simulation.peer).unwrap();
+        assert_eq!(deliver(&mut simulation.stream_loop).unwrap(), 3);
+
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::SendHeaders(address) if address == simulation.peer.address
+        ));
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::FeeFilter(address, 5000) if address == simulation.peer.address
+        ));
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::SendCmpct(address, 2) if address == simulation.peer.address
+        ));
+    }
+
+    #[test]
+    fn forwards_announced_blocks_and_transactions_separately() {
+        let mut simulation = simulation();
+        let block_hash = Hash256::from([1; 32]);
+        let tx_hash = Hash256::from([2; 32]);
+        Inv::new(vec![
+            Inventory::new(InventoryType::Tx, tx_hash),
+            Inventory::new(InventoryType::Block, block_hash),
+        ])
+        .send(&mut simulation.peer)
+        .unwrap();
+        deliver(&mut simulation.stream_loop).unwrap();
+
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::BlocksAnnounced(address, hashes)
+                if address == simulation.peer.address && hashes == vec![block_hash]
+        ));
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::TxAnnounced(_, hashes) if hashes == vec![tx_hash]
+        ));
+        assert!(simulation.node_actions.try_recv().is_err());
+    }
+
+    #[test]
+    fn forwards_valid_blocks_and_penalizes_invalid_ones() {
+        let mut simulation = simulation();
+        let block = test_block();
+        let block_hash = *block.header.hash();
+
+        block.send(&mut simulation.peer).unwrap();
+        deliver(&mut simulation.stream_loop).unwrap();
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::Block((hash, _)) if hash == block_hash
+        ));
+
+        // un bloque cuyas transacciones no coinciden con el merkle root se vuelve a pedir a otro peer
+        let mut block = test_block();
+        block.transactions.pop();
+        block.send(&mut simulation.peer).unwrap();
+        assert!(deliver(&mut simulation.stream_loop).is_err());
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::GetDataError(inventories) if inventories[0].hash == block_hash
+        ));
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::PeerMisbehaving(_, Misbehavior::InvalidBlock)
+        ));
+    }
+
+    #[test]
+    fn forwards_merkle_blocks_with_their_transactions() {
+        let mut simulation = simulation();
+        let block = test_block();
+        let matched = vec![block.transactions[6].hash(), block.transactions[19].hash()];
+
+        MerkleBlock::from_block(&block, &matched)
+            .send(&mut simulation.peer)
+            .unwrap();
+        block.transactions[6].send(&mut simulation.peer).unwrap();
+        block.transactions[19].send(&mut simulation.peer).unwrap();
+        // una transaccion fuera del merkle block es del mempool del peer
+        block.transactions[1].send(&mut simulation.peer).unwrap();
+        assert_eq!(deliver(&mut simulation.stream_loop).unwrap(), 4);
+
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::MerkleBlock(_, filtered_block)
+                if filtered_block.transactions.len() == 2 && filtered_block.hash() == block.header.hash()
+        ));
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::TxSeenInMempool(_, hash) if hash == block.transactions[1].hash()
+        ));
+    }
+
+    #[test]
+    fn penalizes_invalid_merkle_blocks() {
+        let mut simulation = simulation();
+        let block = test_block();
+        let mut merkle_block = MerkleBlock::from_block(&block, &[block.transactions[1].hash()]);
+        merkle_block.hashes[0] = Hash256::ZERO;
+
+        merkle_block.send(&mut simulation.peer).unwrap();
+        assert!(deliver(&mut simulation.stream_loop).is_err());
+        assert!(matches!(
+            simulation.node_actions.try_recv().unwrap(),
+            NodeAction::PeerMisbehaving(_, Misbehavior::InvalidBlock)
+        ));
+    }
+}
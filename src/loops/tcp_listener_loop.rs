@@ -1,4 +1,5 @@
 use std::{
+    io,
     net::{SocketAddrV6, TcpListener},
     sync::{mpsc, Arc, Mutex},
     thread::{self, JoinHandle},
@@ -6,88 +7,113 @@ use std::{
 
 use crate::{
     error::CustomError,
-    logger::{send_log, Log},
+    logger::{send_log, Log, LogEntry},
     node_state::NodeState,
     peer::Peer,
+    shutdown::{ShutdownSignal, SHUTDOWN_POLL_INTERVAL},
+    utils::get_address_v6,
 };
 
-use super::{node_action_loop::NodeAction, peer_action_loop::PeerAction};
+use super::network_loop::NetworkHandle;
+
+/// Cantidad maxima de peers entrantes por defecto.
+pub const DEFAULT_MAX_INBOUND: usize = 8;
 
 /// TcpListenerLoop es el loop de eventos que se encarga de escuchar conexiones entrantes.
 /// Cada vez que se recibe una conexión, inicializa un nuevo Peer y contesta el handshake.
-/// Luego, agrega el nuevo Peer a la lista de peers del nodo
+/// Luego, agrega el nuevo Peer a la lista de peers del nodo, que atiende sus getheaders y getdata
+/// con los headers y bloques guardados.
+/// Las conexiones de peers baneados o que superan max_inbound se cierran sin handshake,
+/// y un handshake fallido solo descarta esa conexion.
 /// Los elementos son:
 /// - logger_sender: Sender para enviar logs al logger
 /// - node_state_ref: Referencia al estado del nodo
 /// - address: Dirección del nodo
 /// - services: Servicios que ofrece el nodo
 /// - version: Versión del protocolo que maneja el nodo
-/// - peer_action_receiver: Receiver para recibir acciones de los peers
-/// - node_action_sender: Sender para enviar acciones al nodo
+/// - max_inbound: Cantidad maxima de peers entrantes conectados a la vez
+/// - network: NetworkHandle para registrar las conexiones en el NetworkLoop
+/// - shutdown: ShutdownSignal, el loop termina cuando se solicita el cierre del nodo
 pub struct TcpListenerLoop {
-    logger_sender: mpsc::Sender<Log>,
-    node_state_ref: Arc<Mutex<NodeState>>,
-    address: SocketAddrV6,
-    services: u64,
-    version: i32,
-    peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
-    node_action_sender: mpsc::Sender<NodeAction>,
+    pub logger_sender: mpsc::Sender<LogEntry>,
+    pub node_state_ref: Arc<Mutex<NodeState>>,
+    pub address: SocketAddrV6,
+    pub services: u64,
+    pub version: i32,
+    pub max_inbound: usize,
+    pub network: NetworkHandle,
+    pub shutdown: ShutdownSignal,
 }
 
 impl TcpListenerLoop {
-    #[must_use]
     /// Inicializa el loop de eventos en un thread.
-    pub fn spawn(
-        logger_sender: mpsc::Sender<Log>,
-        node_state_ref: Arc<Mutex<NodeState>>,
-        address: SocketAddrV6,
-        services: u64,
-        version: i32,
-        peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
-        node_action_sender: mpsc::Sender<NodeAction>,
-    ) -> JoinHandle<Result<(), CustomError>> {
-        thread::spawn(move || -> Result<(), CustomError> {
-            let mut thread = Self {
-                logger_sender,
-                node_state_ref,
-                address,
-                services,
-                version,
-                peer_action_receiver,
-                node_action_sender,
-            };
-            thread.event_loop()
-        })
+    pub fn spawn(self) -> JoinHandle<Result<(), CustomError>> {
+        thread::spawn(move || -> Result<(), CustomError> { self.event_loop() })
     }
 
-    fn event_loop(&mut self) -> Result<(), CustomError> {
+    fn event_loop(&self) -> Result<(), CustomError> {
         let listener = TcpListener::bind(self.address)?;
+        // el listener no bloquea, para revisar periodicamente si se solicito el cierre del nodo
+        listener.set_nonblocking(true)?;
         send_log(
             &self.logger_sender,
             Log::Message(String::from("Server started...")),
         );
 
-        for stream in listener.incoming() {
-            let stream = stream?;
+        while !self.shutdown.is_requested() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            stream.set_nonblocking(false)?;
             let peer_address = stream.peer_addr()?;
             send_log(
                 &self.logger_sender,
-                Log::Message(format!("New connection: {:?}", peer_address)),
+                Log::Debug(format!("New connection: {:?}", peer_address)),
             );
+            let node_state = self.node_state_ref.lock()?;
+            let banned = node_state.is_banned(&get_address_v6(peer_address));
+            let inbound = node_state.get_inbound_peers_count();
+            drop(node_state);
+            if banned {
+                send_log(
+                    &self.logger_sender,
+                    Log::Debug(format!(
+                        "Rejected connection from banned peer {}",
+                        peer_address
+                    )),
+                );
+                continue;
+            }
+            if inbound >= self.max_inbound {
+                send_log(
+                    &self.logger_sender,
+                    Log::Debug(format!(
+                        "Rejected connection from {}, already connected to {} inbound peers",
+                        peer_address, inbound
+                    )),
+                );
+                continue;
+            }
 
-            let new_peer = Peer::answer(
+            match Peer::answer(
                 stream,
                 self.address,
                 self.services,
                 self.version,
-                self.peer_action_receiver.clone(),
+                &self.network,
                 self.logger_sender.clone(),
-                self.node_action_sender.clone(),
-            )?;
-
-            let mut node_state = self.node_state_ref.lock()?;
-            node_state.append_peers(vec![new_peer]);
-            drop(node_state);
+            ) {
+                Ok(new_peer) => self.node_state_ref.lock()?.append_peers(vec![new_peer]),
+                Err(error) => send_log(
+                    &self.logger_sender,
+                    Log::Warning(format!("Error answering handshake: {}", error)),
+                ),
+            }
         }
 
         Ok(())
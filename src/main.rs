@@ -1,14 +1,22 @@
 use bitcoin::{
-    config::Config,
-    gui::init::GUI,
-    logger::{send_log, Log, Logger},
-    loops::node_action_loop::NodeAction,
+    config::{Config, Settings},
+    gui::init::{GUIEvents, GUI},
+    logger::{send_log, Log, LogEntry, Logger},
+    metrics::MetricsServer,
+    network::Network,
     node::Node,
     node_state::NodeState,
-    utils::get_addresses,
+    proxy,
+    rpc::{RpcAuth, RpcServer},
 };
 use gtk::glib::{self, Priority};
-use std::{env, path::Path};
+use std::{
+    env,
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    sync::mpsc,
+    thread,
+};
 
 const CANT_ARGS: usize = 2;
 
@@ -24,17 +32,27 @@ fn main() {
         return;
     }
 
-    let config = match Config::from_file(args[1].as_str()) {
+    let mut config = match Config::from_file(args[1].as_str()) {
         Ok(config) => config,
         Err(error) => {
             println!("ERROR: {error}");
             return;
         }
     };
+    if let Err(error) = config.apply_args(&args[CANT_ARGS..]) {
+        println!("ERROR: {error}");
+        return;
+    }
+    Network::select(config.network);
+    proxy::select(config.proxy);
 
     let (gui_sender, gui_receiver) = glib::MainContext::channel(Priority::default());
 
-    let logger = match Logger::new(&config.log_file, gui_sender.clone()) {
+    let logger = match Logger::with_options(
+        &config.log_file,
+        config.log_options.clone(),
+        gui_sender.clone(),
+    ) {
         Ok(logger) => logger,
         Err(error) => {
             println!("ERROR: {error}");
@@ -66,8 +84,7 @@ fn main() {
 
     let node_action_sender = node.node_action_sender.clone();
 
-    let addresses = get_addresses(config.seed.clone(), config.port);
-    let addresses = match addresses {
+    let addresses = match config.peer_addresses() {
         Ok(addresses) => addresses,
         Err(error) => {
             send_log(&logger_sender, Log::Error(error));
@@ -75,37 +92,95 @@ fn main() {
         }
     };
 
-    let node_thread = node.spawn(addresses, gui_sender);
+    let node_handle = node.spawn(addresses, gui_sender);
 
-    let gui = GUI::start(
-        gui_receiver,
-        node_state_ref,
-        logger_sender.clone(),
-        node_action_sender.clone(),
-    );
+    let (stop_sender, stop_receiver) = mpsc::channel();
+    let mut rpc_auth = None;
+    if let Some(rpc_port) = config.rpc_port {
+        match RpcAuth::new(
+            &config.store_path,
+            config.rpc_user.clone(),
+            config.rpc_password.clone(),
+        ) {
+            Ok(auth) => {
+                rpc_auth = Some(auth.clone());
+                let _rpc_thread = RpcServer::spawn(
+                    SocketAddr::from((Ipv4Addr::LOCALHOST, rpc_port)),
+                    auth,
+                    node_state_ref.clone(),
+                    node_action_sender.clone(),
+                    logger_sender.clone(),
+                    stop_sender,
+                );
+            }
+            Err(error) => send_log(&logger_sender, Log::Error(error)),
+        }
+    }
+    if let Some(metrics_port) = config.metrics_port {
+        let _metrics_thread = MetricsServer::spawn(
+            SocketAddr::from((Ipv4Addr::LOCALHOST, metrics_port)),
+            node_state_ref.clone(),
+            logger_sender.clone(),
+        );
+    }
 
-    if let Err(error) = gui {
-        send_log(
-            &logger_sender,
-            Log::Message(format!("Error starting GUI: {}", error)),
+    if config.headless {
+        run_headless(gui_receiver, stop_receiver);
+    } else {
+        // con interfaz grafica el nodo se detiene al cerrarla, el metodo stop devuelve un error
+        drop(stop_receiver);
+        let gui = GUI::start(
+            gui_receiver,
+            node_state_ref,
+            logger_sender.clone(),
+            node_action_sender.clone(),
+            config.log_options.level,
+            Settings::from_config(&config),
+            args[1].clone(),
         );
-    };
 
-    if node_action_sender.send(NodeAction::Terminate).is_ok() {
-        if let Err(error) = node_thread.join() {
+        if let Err(error) = gui {
             send_log(
                 &logger_sender,
-                Log::Message(format!("Error closing node thread: {:?}", error)),
+                Log::Warning(format!("Error starting GUI: {}", error)),
             );
         };
     }
 
-    if logger.tx.send(Log::Terminate).is_ok() {
+    if let Err(error) = node_handle.stop() {
+        send_log(
+            &logger_sender,
+            Log::Warning(format!("Error closing node thread: {:?}", error)),
+        );
+    }
+
+    if let Some(Err(error)) = rpc_auth.map(|auth| auth.remove_cookie()) {
+        send_log(
+            &logger_sender,
+            Log::Warning(format!("Error removing the RPC cookie: {}", error)),
+        );
+    }
+
+    if logger.tx.send(LogEntry::new(Log::Terminate)).is_ok() {
         if let Err(error) = logger.thread.join() {
             send_log(
                 &logger_sender,
-                Log::Message(format!("Error closing logger thread: {:?}", error)),
+                Log::Warning(format!("Error closing logger thread: {:?}", error)),
             );
         };
     }
 }
+
+/// Corre el nodo sin interfaz grafica hasta que se solicite detenerlo con el metodo stop del servidor RPC.
+/// Los eventos de la interfaz grafica se descartan.
+fn run_headless(gui_receiver: glib::Receiver<GUIEvents>, stop_receiver: mpsc::Receiver<()>) {
+    let main_loop = glib::MainLoop::new(None, false);
+    gui_receiver.attach(None, |_| glib::Continue(true));
+
+    let main_loop_ref = main_loop.clone();
+    thread::spawn(move || {
+        let _ = stop_receiver.recv();
+        main_loop_ref.quit();
+    });
+    main_loop.run();
+}
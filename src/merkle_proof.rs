@@ -0,0 +1,268 @@
+use crate::{
+    error::{ChainError, CustomError, WalletError},
+    messages::block::{merge_hashes, Block},
+    structs::{block_header::BlockHeader, hash256::Hash256},
+};
+
+/// MerkleProof es un merkle tree parcial (BIP 37), como el que se recibe en un mensaje merkleblock.
+/// Prueba la inclusion de algunas transacciones de un bloque contra el merkle root de su header,
+/// por lo que permite confirmar una transaccion (SPV) sin descargar el bloque completo.
+/// Los elementos son:
+/// - total_transactions: Cantidad total de transacciones del bloque.
+/// - hashes: Hashes del merkle tree parcial, recorrido en profundidad.
+/// - flags: Bits que indican como recorrer el merkle tree parcial.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub total_transactions: u32,
+    pub hashes: Vec<Hash256>,
+    pub flags: Vec<u8>,
+}
+
+impl MerkleProof {
+    /// Genera el merkle tree parcial a partir de los hashes de todas las transacciones del bloque,
+    /// incluyendo las transacciones marcadas en matches.
+    pub fn new(tx_hashes: &[Hash256], matches: &[bool]) -> Self {
+        let mut tree = PartialMerkleTree {
+            tx_hashes,
+            matches,
+            bits: vec![],
+            hashes: vec![],
+        };
+        if !tx_hashes.is_empty() {
+            tree.build(tree_height(tx_hashes.len()), 0);
+        }
+
+        let mut flags = vec![0_u8; tree.bits.len().div_ceil(8)];
+        for (i, bit) in tree.bits.iter().enumerate() {
+            flags[i / 8] |= (*bit as u8) << (i % 8);
+        }
+
+        Self {
+            total_transactions: tx_hashes.len() as u32,
+            hashes: tree.hashes,
+            flags,
+        }
+    }
+
+    /// Genera la prueba de inclusion de una transaccion de un bloque.
+    /// Devuelve CustomError si la transaccion no esta en el bloque.
+    pub fn from_block(block: &Block, tx_hash: &Hash256) -> Result<Self, CustomError> {
+        let tx_hashes: Vec<Hash256> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        let matches: Vec<bool> = tx_hashes.iter().map(|hash| hash == tx_hash).collect();
+        if !matches.contains(&true) {
+            return Err(CustomError::Wallet(WalletError::TransactionNotFound));
+        }
+        Ok(Self::new(&tx_hashes, &matches))
+    }
+
+    /// Recorre el merkle tree parcial y devuelve los hashes de las transacciones incluidas.
+    /// Devuelve CustomError si el merkle root calculado no coincide con el recibido,
+    /// o si el merkle tree parcial esta mal formado.
+    pub fn matched_transactions(&self, merkle_root: &Hash256) -> Result<Vec<Hash256>, CustomError> {
+        if self.total_transactions == 0 || self.hashes.len() > self.total_transactions as usize {
+            return Err(CustomError::Chain(ChainError::InvalidMerkleRoot));
+        }
+
+        let mut traversal = MerkleTraversal {
+            proof: self,
+            bits_used: 0,
+            hashes_used: 0,
+            matched: vec![],
+        };
+        let height = tree_height(self.total_transactions as usize);
+        let calculated_root = traversal.traverse(height, 0)?;
+
+        if calculated_root != *merkle_root
+            || traversal.hashes_used != self.hashes.len()
+            || traversal.bits_used.div_ceil(8) != self.flags.len()
+        {
+            return Err(CustomError::Chain(ChainError::InvalidMerkleRoot));
+        }
+        Ok(traversal.matched)
+    }
+
+    /// Verifica que la transaccion este incluida en el bloque del header.
+    /// Devuelve CustomError si la prueba no corresponde al header o no incluye a la transaccion.
+    pub fn verify(&self, header: &BlockHeader, tx_hash: &Hash256) -> Result<(), CustomError> {
+        if !self
+            .matched_transactions(&header.merkle_root)?
+            .contains(tx_hash)
+        {
+            return Err(CustomError::Wallet(WalletError::TransactionNotFound));
+        }
+        Ok(())
+    }
+
+    fn flag_bit(&self, index: usize) -> Option<bool> {
+        self.flags
+            .get(index / 8)
+            .map(|byte| (byte >> (index % 8)) & 1 == 1)
+    }
+}
+
+/// Cantidad de nodos del merkle tree en una altura dada.
+fn tree_width(total_transactions: usize, height: u32) -> usize {
+    (total_transactions + (1 << height) - 1) >> height
+}
+
+/// Altura del merkle tree para una cantidad de transacciones.
+fn tree_height(total_transactions: usize) -> u32 {
+    let mut height = 0;
+    while tree_width(total_transactions, height) > 1 {
+        height += 1;
+    }
+    height
+}
+
+/// Estado auxiliar para construir un merkle tree parcial.
+struct PartialMerkleTree<'a> {
+    tx_hashes: &'a [Hash256],
+    matches: &'a [bool],
+    bits: Vec<bool>,
+    hashes: Vec<Hash256>,
+}
+
+impl PartialMerkleTree<'_> {
+    fn calculate_hash(&self, height: u32, pos: usize) -> Hash256 {
+        if height == 0 {
+            return self.tx_hashes[pos];
+        }
+        let left = self.calculate_hash(height - 1, pos * 2);
+        let right = match pos * 2 + 1 < tree_width(self.tx_hashes.len(), height - 1) {
+            true => self.calculate_hash(height - 1, pos * 2 + 1),
+            false => left,
+        };
+        merge_hashes(&left, &right)
+    }
+
+    fn build(&mut self, height: u32, pos: usize) {
+        let start = pos << height;
+        let end = ((pos + 1) << height).min(self.tx_hashes.len());
+        let parent_of_match = self.matches[start..end].iter().any(|matched| *matched);
+        self.bits.push(parent_of_match);
+
+        if height == 0 || !parent_of_match {
+            let hash = self.calculate_hash(height, pos);
+            self.hashes.push(hash);
+            return;
+        }
+        self.build(height - 1, pos * 2);
+        if pos * 2 + 1 < tree_width(self.tx_hashes.len(), height - 1) {
+            self.build(height - 1, pos * 2 + 1);
+        }
+    }
+}
+
+/// Estado auxiliar para recorrer un merkle tree parcial.
+struct MerkleTraversal<'a> {
+    proof: &'a MerkleProof,
+    bits_used: usize,
+    hashes_used: usize,
+    matched: Vec<Hash256>,
+}
+
+impl MerkleTraversal<'_> {
+    fn traverse(&mut self, height: u32, pos: usize) -> Result<Hash256, CustomError> {
+        let parent_of_match = self
+            .proof
+            .flag_bit(self.bits_used)
+            .ok_or(CustomError::Chain(ChainError::InvalidMerkleRoot))?;
+        self.bits_used += 1;
+
+        if height == 0 || !parent_of_match {
+            let hash = self
+                .proof
+                .hashes
+                .get(self.hashes_used)
+                .copied()
+                .ok_or(CustomError::Chain(ChainError::InvalidMerkleRoot))?;
+            self.hashes_used += 1;
+            if height == 0 && parent_of_match {
+                self.matched.push(hash);
+            }
+            return Ok(hash);
+        }
+
+        let left = self.traverse(height - 1, pos * 2)?;
+        let total_transactions = self.proof.total_transactions as usize;
+        let right = if pos * 2 + 1 < tree_width(total_transactions, height - 1) {
+            let right = self.traverse(height - 1, pos * 2 + 1)?;
+            // evita la vulnerabilidad CVE-2012-2459 de hashes duplicados
+            if right == left {
+                return Err(CustomError::Chain(ChainError::InvalidMerkleRoot));
+            }
+            right
+        } else {
+            left
+        };
+        Ok(merge_hashes(&left, &right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::{message::Message, utils::open_new_file};
+
+    use super::*;
+
+    fn test_block() -> Block {
+        let mut file = open_new_file("tests/blocks/test_block.bin".to_string(), true).unwrap();
+        let mut buffer = vec![];
+        file.read_to_end(&mut buffer).unwrap();
+        Block::parse(&mut buffer.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn verify_every_transaction_of_block() {
+        let block = test_block();
+        for tx in &block.transactions {
+            let proof = MerkleProof::from_block(&block, &tx.hash()).unwrap();
+            assert!(proof.verify(&block.header, &tx.hash()).is_ok());
+        }
+    }
+
+    #[test]
+    fn verify_fails_for_other_transaction() {
+        let block = test_block();
+        let proof = MerkleProof::from_block(&block, &block.transactions[4].hash()).unwrap();
+        assert!(proof
+            .verify(&block.header, &block.transactions[5].hash())
+            .is_err());
+    }
+
+    #[test]
+    fn verify_fails_for_other_header() {
+        let block = test_block();
+        let tx_hash = block.transactions[2].hash();
+        let proof = MerkleProof::from_block(&block, &tx_hash).unwrap();
+
+        let mut header = block.header.clone();
+        header.merkle_root = Hash256::ZERO;
+        assert!(proof.verify(&header, &tx_hash).is_err());
+    }
+
+    #[test]
+    fn proof_of_transaction_not_in_block() {
+        let block = test_block();
+        assert!(MerkleProof::from_block(&block, &Hash256::ZERO).is_err());
+    }
+
+    #[test]
+    fn proof_with_unused_hashes_is_invalid() {
+        let block = test_block();
+        let tx_hash = block.transactions[0].hash();
+        let mut proof = MerkleProof::from_block(&block, &tx_hash).unwrap();
+        proof.hashes.push(Hash256::ZERO);
+        assert!(proof.verify(&block.header, &tx_hash).is_err());
+    }
+
+    #[test]
+    fn proof_of_single_transaction_block() {
+        let tx_hash = Hash256::new([7; 32]);
+        let proof = MerkleProof::new(&[tx_hash], &[true]);
+        assert_eq!(proof.hashes, vec![tx_hash]);
+        assert_eq!(proof.matched_transactions(&tx_hash).unwrap(), vec![tx_hash]);
+    }
+}
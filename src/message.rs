@@ -1,43 +1,217 @@
-use crate::error::CustomError;
+use crate::error::{CustomError, NetworkError};
+use crate::network::Network;
 use bitcoin_hashes::sha256;
 use bitcoin_hashes::Hash;
+use bitcoin_hashes::HashEngine;
 
+use std::fmt;
+use std::io;
 use std::io::Read;
+use std::io::Take;
 use std::io::Write;
-use std::net::TcpStream;
+
+/// Tamaño del header de los mensajes.
+const HEADER_SIZE: usize = 24;
+/// Tamaño del campo comando del header.
+const COMMAND_SIZE: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Representa el comando que identifica el tipo de un mensaje en su header.
+/// Los comandos que el nodo no conoce se conservan como Unknown con los 12 bytes recibidos,
+/// para poder registrarlos y descartar el mensaje.
+pub enum Command {
+    Addr,
+    AddrV2,
+    Alert,
+    Block,
+    BlockTxn,
+    CFHeaders,
+    CFilter,
+    CmpctBlock,
+    FeeFilter,
+    FilterAdd,
+    FilterClear,
+    FilterLoad,
+    GetAddr,
+    GetBlockTxn,
+    GetCFHeaders,
+    GetCFilters,
+    GetData,
+    GetHeaders,
+    Headers,
+    Inv,
+    MerkleBlock,
+    NotFound,
+    Ping,
+    Pong,
+    Reject,
+    SendAddrV2,
+    SendCmpct,
+    SendHeaders,
+    Tx,
+    VerAck,
+    Version,
+    WtxidRelay,
+    Unknown([u8; COMMAND_SIZE]),
+}
+
+impl Command {
+    /// Devuelve el nombre del comando, como se envia en el header sin los bytes nulos del final.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Command::Addr => "addr",
+            Command::AddrV2 => "addrv2",
+            Command::Alert => "alert",
+            Command::Block => "block",
+            Command::BlockTxn => "blocktxn",
+            Command::CFHeaders => "cfheaders",
+            Command::CFilter => "cfilter",
+            Command::CmpctBlock => "cmpctblock",
+            Command::FeeFilter => "feefilter",
+            Command::FilterAdd => "filteradd",
+            Command::FilterClear => "filterclear",
+            Command::FilterLoad => "filterload",
+            Command::GetAddr => "getaddr",
+            Command::GetBlockTxn => "getblocktxn",
+            Command::GetCFHeaders => "getcfheaders",
+            Command::GetCFilters => "getcfilters",
+            Command::GetData => "getdata",
+            Command::GetHeaders => "getheaders",
+            Command::Headers => "headers",
+            Command::Inv => "inv",
+            Command::MerkleBlock => "merkleblock",
+            Command::NotFound => "notfound",
+            Command::Ping => "ping",
+            Command::Pong => "pong",
+            Command::Reject => "reject",
+            Command::SendAddrV2 => "sendaddrv2",
+            Command::SendCmpct => "sendcmpct",
+            Command::SendHeaders => "sendheaders",
+            Command::Tx => "tx",
+            Command::VerAck => "verack",
+            Command::Version => "version",
+            Command::WtxidRelay => "wtxidrelay",
+            Command::Unknown(bytes) => {
+                let size = bytes
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .unwrap_or(COMMAND_SIZE);
+                // los bytes se validan al parsear el comando
+                std::str::from_utf8(&bytes[..size]).unwrap_or_default()
+            }
+        }
+    }
+
+    /// Serializa el comando en los 12 bytes del header, completando con bytes nulos.
+    pub fn serialize(&self) -> [u8; COMMAND_SIZE] {
+        let mut bytes = [0; COMMAND_SIZE];
+        let name = self.as_str().as_bytes();
+        bytes[..name.len()].copy_from_slice(name);
+        bytes
+    }
+
+    /// Parsea el comando de los 12 bytes del header.
+    /// Devuelve CustomError si el comando no es ASCII o tiene bytes distintos de 0 despues del primer byte nulo.
+    pub fn parse(bytes: [u8; COMMAND_SIZE]) -> Result<Self, CustomError> {
+        let size = bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(COMMAND_SIZE);
+        if !bytes[..size].is_ascii() || bytes[size..].iter().any(|byte| *byte != 0) {
+            return Err(CustomError::Network(NetworkError::CannotReadMessageHeader));
+        }
+        let command = match &bytes[..size] {
+            b"addr" => Command::Addr,
+            b"addrv2" => Command::AddrV2,
+            b"alert" => Command::Alert,
+            b"block" => Command::Block,
+            b"blocktxn" => Command::BlockTxn,
+            b"cfheaders" => Command::CFHeaders,
+            b"cfilter" => Command::CFilter,
+            b"cmpctblock" => Command::CmpctBlock,
+            b"feefilter" => Command::FeeFilter,
+            b"filteradd" => Command::FilterAdd,
+            b"filterclear" => Command::FilterClear,
+            b"filterload" => Command::FilterLoad,
+            b"getaddr" => Command::GetAddr,
+            b"getblocktxn" => Command::GetBlockTxn,
+            b"getcfheaders" => Command::GetCFHeaders,
+            b"getcfilters" => Command::GetCFilters,
+            b"getdata" => Command::GetData,
+            b"getheaders" => Command::GetHeaders,
+            b"headers" => Command::Headers,
+            b"inv" => Command::Inv,
+            b"merkleblock" => Command::MerkleBlock,
+            b"notfound" => Command::NotFound,
+            b"ping" => Command::Ping,
+            b"pong" => Command::Pong,
+            b"reject" => Command::Reject,
+            b"sendaddrv2" => Command::SendAddrV2,
+            b"sendcmpct" => Command::SendCmpct,
+            b"sendheaders" => Command::SendHeaders,
+            b"tx" => Command::Tx,
+            b"verack" => Command::VerAck,
+            b"version" => Command::Version,
+            b"wtxidrelay" => Command::WtxidRelay,
+            _ => Command::Unknown(bytes),
+        };
+        Ok(command)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
 
 /// Este trait representa un mensaje del protocolo.
-/// Todos los mensajes deben implementar este trait, por lo que todos deben poder:
-/// - Serializarse.
-/// - Parsearse.
-/// - Obtener su comando.
+/// Todos los mensajes deben implementar este trait, por lo que todos deben:
+/// - Tener un comando, que los identifica en el header.
+/// - Poder escribir su payload serializado en un Write.
+/// - Poder parsearse a partir de un reader de su payload.
 /// Esto se realiza de forma distinta para cada mensaje, por lo que se implementa de forma individual en cada uno.
 /// A parte de esto, todos los mensajes deben poder enviarse a un stream y leerse de un stream.
 /// Para ello, se implementan los métodos send y read que al ser el procedimiento igual en todos los mensajes, no requieren implementación individual.
-pub trait Message {
-    fn serialize(&self) -> Vec<u8>;
-    fn get_command(&self) -> String;
-    fn parse(buffer: Vec<u8>) -> Result<Self, CustomError>
-    where
-        Self: Sized;
+pub trait Message: Sized {
+    /// Comando del mensaje.
+    const COMMAND: Command;
+
+    /// Escribe el payload serializado del mensaje en el writer, sin armar buffers intermedios.
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    /// Parsea el mensaje extrayendo sus campos del reader a medida que los necesita, sin copiar antes el payload completo.
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError>;
+
+    /// Devuelve el comando del mensaje.
+    fn command(&self) -> Command {
+        Self::COMMAND
+    }
+
+    /// Devuelve el payload serializado del mensaje.
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        // escribir en un Vec no puede fallar
+        let _ = self.serialize_into(&mut buffer);
+        buffer
+    }
 
     /// Envía el mensaje a un stream.
+    /// El payload se serializa directamente a continuacion del espacio reservado para el header,
+    /// que se completa al final con el tamaño y el checksum, por lo que se arma un unico buffer por mensaje.
+    /// El header y el payload se escriben juntos, para que no se intercalen con otros mensajes enviados al mismo peer.
     /// Devuelve CustomError si:
-    /// - No se puede escribir en el stream el header del mensaje.
-    /// - No se puede escribir en el stream el payload del mensaje.
+    /// - No se puede escribir en el stream el mensaje.
     /// - No se puede hacer flush del stream.
-    fn send(&self, stream: &mut TcpStream) -> Result<(), CustomError>
-    where
-        Self: Sized,
-    {
-        let header = MessageHeader::new(self);
-
-        stream
-            .write(&header.serialize())
+    fn send<W: Write>(&self, stream: &mut W) -> Result<(), CustomError> {
+        let mut message = vec![0; HEADER_SIZE];
+        self.serialize_into(&mut message)
             .map_err(|_| CustomError::CannotSendMessageToChannel)?;
+        let header = MessageHeader::for_payload(Self::COMMAND, &message[HEADER_SIZE..]);
+        message[..HEADER_SIZE].copy_from_slice(&header.serialize());
 
         stream
-            .write(&self.serialize())
+            .write_all(&message)
             .map_err(|_| CustomError::CannotSendMessageToChannel)?;
 
         stream
@@ -47,20 +221,59 @@ pub trait Message {
         Ok(())
     }
 
-    /// Lee un mensaje de un stream y lo parsea.
+    /// Lee de un stream el payload del mensaje cuyo header se recibio y lo parsea a medida que lo lee.
+    /// El checksum se calcula sobre los bytes leidos, y el payload se termina de leer aunque el parseo falle,
+    /// para que el stream quede al comienzo del siguiente mensaje.
     /// Devuelve CustomError si:
     /// - No se puede leer del stream
-    fn read(stream: &mut TcpStream, message_size: u32) -> Result<Self, CustomError>
-    where
-        Self: Sized,
-    {
-        let mut payload_buffer = vec![0; message_size as usize];
+    /// - El checksum del payload no coincide con el del header.
+    /// - El payload no es valido para el mensaje.
+    fn read<R: Read>(stream: &mut R, header: &MessageHeader) -> Result<Self, CustomError> {
+        let mut payload = PayloadReader::new(stream, header.payload_size);
+        let message = Self::parse(&mut payload);
+        if payload.finish()? != header.checksum {
+            return Err(CustomError::Network(NetworkError::InvalidChecksum));
+        }
+        message
+    }
+}
 
-        stream
-            .read_exact(&mut payload_buffer)
-            .map_err(|_| CustomError::CannotReadStream)?;
+/// PayloadReader lee el payload de un mensaje de un stream, sin pasarse del tamaño indicado en su header,
+/// y calcula su checksum a medida que se leen los bytes.
+/// Los elementos son:
+/// - stream: Stream limitado al tamaño del payload.
+/// - engine: Estado del sha256 de los bytes leidos.
+struct PayloadReader<'a, R: Read> {
+    stream: Take<&'a mut R>,
+    engine: sha256::HashEngine,
+}
 
-        Self::parse(payload_buffer)
+impl<'a, R: Read> PayloadReader<'a, R> {
+    fn new(stream: &'a mut R, payload_size: u32) -> Self {
+        Self {
+            stream: stream.take(payload_size as u64),
+            engine: sha256::HashEngine::default(),
+        }
+    }
+
+    /// Lee lo que quede del payload y devuelve su checksum.
+    /// Devuelve CustomError si no se puede leer del stream o se cierra antes de terminar el payload.
+    fn finish(mut self) -> Result<[u8; 4], CustomError> {
+        io::copy(&mut self, &mut io::sink())
+            .map_err(|_| CustomError::Network(NetworkError::CannotReadStream))?;
+        if self.stream.limit() > 0 {
+            return Err(CustomError::Network(NetworkError::CannotReadStream));
+        }
+        let hash = sha256::Hash::hash(sha256::Hash::from_engine(self.engine).as_byte_array());
+        Ok([hash[0], hash[1], hash[2], hash[3]])
+    }
+}
+
+impl<R: Read> Read for PayloadReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.stream.read(buf)?;
+        self.engine.input(&buf[..size]);
+        Ok(size)
     }
 }
 
@@ -72,34 +285,33 @@ fn get_checksum(payload: &[u8]) -> [u8; 4] {
     [hash[0], hash[1], hash[2], hash[3]]
 }
 
-/// El magic number es un número que se usa para identificar la red, en nuestro caso, la testnet.
-const MAGIC: u32 = 0x0b110907;
 #[derive(Debug)]
 /// Representa el header de un mensaje.
 /// El header contiene:
-/// - Un magic number que identifica la red.
+/// - Un magic number que identifica la red, ver Network::magic.
 /// - Un comando que identifica el tipo de mensaje.
 /// - El tamaño del payload.
 /// - El checksum del payload.
 pub struct MessageHeader {
     magic: u32,
-    pub command: String,
+    pub command: Command,
     pub payload_size: u32,
-    checksum: [u8; 4],
+    pub checksum: [u8; 4],
 }
 
 impl MessageHeader {
     /// Crea un nuevo header a partir de un mensaje.
-    pub fn new(message: &dyn Message) -> Self {
-        let payload = message.serialize();
-        let payload_size = payload.len() as u32;
-        let checksum = get_checksum(&payload);
+    pub fn new<M: Message>(message: &M) -> Self {
+        Self::for_payload(M::COMMAND, &message.serialize())
+    }
 
+    /// Crea el header de un mensaje a partir de su comando y su payload serializado.
+    pub fn for_payload(command: Command, payload: &[u8]) -> Self {
         MessageHeader {
-            magic: MAGIC,
-            command: message.get_command(),
-            payload_size,
-            checksum,
+            magic: Network::current().magic(),
+            command,
+            payload_size: payload.len() as u32,
+            checksum: get_checksum(payload),
         }
     }
 
@@ -109,14 +321,11 @@ impl MessageHeader {
     /// - Command: 12 bytes.
     /// - Payload size: 4 bytes.
     /// - Checksum: 4 bytes.
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut header = vec![0; 24];
-
-        let mut command = self.command.as_bytes().to_vec();
-        command.resize(12, 0);
+    pub fn serialize(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
 
         header[0..4].copy_from_slice(&self.magic.to_be_bytes());
-        header[4..16].copy_from_slice(&command);
+        header[4..16].copy_from_slice(&self.command.serialize());
         header[16..20].copy_from_slice(&self.payload_size.to_le_bytes());
         header[20..24].copy_from_slice(&self.checksum);
         header
@@ -125,19 +334,16 @@ impl MessageHeader {
     /// Parsea un header a partir de un buffer de 24 bytes.
     /// Devuelve CustomError si:
     /// - El buffer no tiene 24 bytes.
-    /// - El comando no se puede parsear a String.
-    pub fn parse(buffer: [u8; 24]) -> Result<Self, CustomError> {
-        if buffer.len() != 24 {
-            return Err(CustomError::CannotReadMessageHeader);
-        }
+    /// - El magic number no es el de la red seleccionada.
+    /// - El comando no es valido.
+    pub fn parse(buffer: [u8; HEADER_SIZE]) -> Result<Self, CustomError> {
         let magic = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
-        let mut command = match String::from_utf8(buffer[4..16].to_vec()) {
-            Ok(command) => command,
-            Err(_) => {
-                return Err(CustomError::CannotReadMessageHeader);
-            }
-        };
-        command = command.replace('\0', "");
+        if magic != Network::current().magic() {
+            return Err(CustomError::Network(NetworkError::CannotReadMessageHeader));
+        }
+        let mut command = [0; COMMAND_SIZE];
+        command.copy_from_slice(&buffer[4..16]);
+        let command = Command::parse(command)?;
         let payload_size = u32::from_le_bytes([buffer[16], buffer[17], buffer[18], buffer[19]]);
         let checksum = [buffer[20], buffer[21], buffer[22], buffer[23]];
 
@@ -149,15 +355,24 @@ impl MessageHeader {
         })
     }
 
+    /// Verifica que el checksum del header coincida con el del payload recibido.
+    /// Devuelve NetworkError::InvalidChecksum si no coincide, es decir si el payload llego dañado.
+    pub fn verify_checksum(&self, payload: &[u8]) -> Result<(), CustomError> {
+        if get_checksum(payload) != self.checksum {
+            return Err(CustomError::Network(NetworkError::InvalidChecksum));
+        }
+        Ok(())
+    }
+
     /// Lee un header de un stream y lo parsea.
     /// Devuelve CustomError si:
     /// - No se puede leer del stream.
-    pub fn read(stream: &mut TcpStream) -> Result<Self, CustomError> {
-        let mut header_buffer = [0; 24];
+    pub fn read<R: Read>(stream: &mut R) -> Result<Self, CustomError> {
+        let mut header_buffer = [0; HEADER_SIZE];
 
         stream
             .read_exact(&mut header_buffer)
-            .map_err(|_| CustomError::CannotReadMessageHeader)?;
+            .map_err(|_| CustomError::Network(NetworkError::CannotReadMessageHeader))?;
 
         let header = Self::parse(header_buffer)?;
 
@@ -169,7 +384,10 @@ impl MessageHeader {
 mod tests {
     use std::net::{Ipv6Addr, SocketAddrV6};
 
-    use crate::messages::version::Version;
+    use crate::messages::{
+        ping_pong::{Ping, Pong},
+        version::Version,
+    };
 
     use super::*;
 
@@ -199,10 +417,109 @@ mod tests {
 
         let header = MessageHeader::parse(header).unwrap();
 
-        assert_eq!(header.magic, MAGIC);
-        assert_eq!(header.command, "version");
+        assert_eq!(header.magic, Network::Testnet.magic());
+        assert_eq!(header.command, Command::Version);
         assert_eq!(header.payload_size, (85 as u32));
         assert_eq!(header.checksum.len(), 4);
         assert_eq!(header.checksum, [75, 114, 249, 186]);
     }
+
+    #[test]
+    fn verify_payload_checksum() {
+        let ping = Ping { nonce: 7 };
+        let header = MessageHeader::new(&ping);
+        let mut payload = ping.serialize();
+        assert!(header.verify_checksum(&payload).is_ok());
+
+        payload[0] ^= 1;
+        assert!(matches!(
+            header.verify_checksum(&payload),
+            Err(CustomError::Network(NetworkError::InvalidChecksum))
+        ));
+    }
+
+    #[test]
+    fn commands_are_serialized_padded_with_zeros() {
+        let bytes = Command::GetHeaders.serialize();
+        assert_eq!(&bytes, b"getheaders\0\0");
+        assert_eq!(Command::parse(bytes).unwrap(), Command::GetHeaders);
+        assert_eq!(Command::Version.to_string(), "version");
+    }
+
+    #[test]
+    fn unknown_commands_are_kept() {
+        let command = Command::parse(*b"getblocks\0\0\0").unwrap();
+        assert_eq!(command, Command::Unknown(*b"getblocks\0\0\0"));
+        assert_eq!(command.as_str(), "getblocks");
+        assert_eq!(command.serialize(), *b"getblocks\0\0\0");
+    }
+
+    #[test]
+    fn invalid_commands_are_rejected() {
+        assert!(Command::parse(*b"ping\0\0\0\0\0\0\0x").is_err());
+        assert!(Command::parse([0xff; 12]).is_err());
+    }
+
+    #[test]
+    fn sent_messages_start_with_their_header() {
+        let ping = Ping { nonce: 7 };
+        let mut stream = vec![];
+        ping.send(&mut stream).unwrap();
+
+        let header = MessageHeader::parse(stream[..24].try_into().unwrap()).unwrap();
+        assert_eq!(header.command, Command::Ping);
+        assert_eq!(header.payload_size, 8);
+        assert_eq!(stream[..24], MessageHeader::new(&ping).serialize());
+        assert_eq!(stream[24..], ping.serialize());
+    }
+
+    #[test]
+    fn read_consumes_the_whole_payload_of_invalid_messages() {
+        let mut stream = vec![];
+        Ping { nonce: 7 }.send(&mut stream).unwrap();
+        stream[HEADER_SIZE] ^= 1;
+        Pong { nonce: 8 }.send(&mut stream).unwrap();
+        // un ping con un byte de mas no es valido
+        let header = MessageHeader::for_payload(Command::Ping, &[0; 9]);
+        stream.extend(header.serialize());
+        stream.extend([0; 9]);
+        Ping { nonce: 9 }.send(&mut stream).unwrap();
+
+        let mut reader = stream.as_slice();
+        let header = MessageHeader::read(&mut reader).unwrap();
+        assert!(matches!(
+            Ping::read(&mut reader, &header),
+            Err(CustomError::Network(NetworkError::InvalidChecksum))
+        ));
+        let header = MessageHeader::read(&mut reader).unwrap();
+        assert_eq!(Pong::read(&mut reader, &header).unwrap().nonce, 8);
+        let header = MessageHeader::read(&mut reader).unwrap();
+        assert!(Ping::read(&mut reader, &header).is_err());
+        let header = MessageHeader::read(&mut reader).unwrap();
+        assert_eq!(Ping::read(&mut reader, &header).unwrap().nonce, 9);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn read_fails_if_the_stream_ends_before_the_payload() {
+        let mut stream = vec![];
+        Ping { nonce: 7 }.send(&mut stream).unwrap();
+        stream.pop();
+
+        let mut reader = stream.as_slice();
+        let header = MessageHeader::read(&mut reader).unwrap();
+        assert!(matches!(
+            Ping::read(&mut reader, &header),
+            Err(CustomError::Network(NetworkError::CannotReadStream))
+        ));
+    }
+
+    #[test]
+    fn test_message_header_from_another_network() {
+        let header = [
+            0xf9, 0xbe, 0xb4, 0xd9, 118, 101, 114, 115, 105, 111, 110, 0, 0, 0, 0, 0, 85, 0, 0, 0,
+            75, 114, 249, 186,
+        ];
+        assert!(MessageHeader::parse(header).is_err());
+    }
 }
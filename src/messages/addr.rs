@@ -0,0 +1,173 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::network_address::NetworkAddress,
+};
+
+/// Cantidad maxima de direcciones en un mensaje addr o addrv2.
+pub const MAX_ADDR_ADDRESSES: usize = 1000;
+
+#[derive(Debug, PartialEq)]
+/// Addr es el mensaje con el que un peer anuncia direcciones de otros nodos de la red.
+/// Los elementos son:
+/// - addresses: Direcciones anunciadas.
+pub struct Addr {
+    pub addresses: Vec<NetworkAddress>,
+}
+
+impl Addr {
+    /// Crea un nuevo mensaje addr con las direcciones recibidas.
+    pub fn new(addresses: Vec<NetworkAddress>) -> Self {
+        Self { addresses }
+    }
+}
+
+/// Implementa el trait Message para el mensaje addr.
+/// Permite serializar, parsear y obtener el comando
+impl Message for Addr {
+    const COMMAND: Command = Command::Addr;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.addresses.len().write_varint(writer)?;
+        for address in &self.addresses {
+            writer.write_all(&address.serialize())?;
+        }
+        Ok(())
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let count = parser.extract_varint()? as usize;
+        if count > MAX_ADDR_ADDRESSES {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+
+        let mut addresses = vec![];
+        for _ in 0..count {
+            addresses.push(NetworkAddress::parse(&mut parser)?);
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self { addresses })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// AddrV2 es el mensaje addr extendido a otras redes (BIP 155).
+/// Solo se conservan las direcciones IPv4 e IPv6, las de otras redes se descartan al parsear.
+/// Los elementos son:
+/// - addresses: Direcciones anunciadas.
+pub struct AddrV2 {
+    pub addresses: Vec<NetworkAddress>,
+}
+
+impl AddrV2 {
+    /// Crea un nuevo mensaje addrv2 con las direcciones recibidas.
+    pub fn new(addresses: Vec<NetworkAddress>) -> Self {
+        Self { addresses }
+    }
+}
+
+/// Implementa el trait Message para el mensaje addrv2.
+/// Permite serializar, parsear y obtener el comando
+impl Message for AddrV2 {
+    const COMMAND: Command = Command::AddrV2;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.addresses.len().write_varint(writer)?;
+        for address in &self.addresses {
+            writer.write_all(&address.serialize_v2())?;
+        }
+        Ok(())
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let count = parser.extract_varint()? as usize;
+        if count > MAX_ADDR_ADDRESSES {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+
+        let mut addresses = vec![];
+        for _ in 0..count {
+            if let Some(address) = NetworkAddress::parse_v2(&mut parser)? {
+                addresses.push(address);
+            }
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self { addresses })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddrV6};
+
+    use super::*;
+
+    fn addresses() -> Vec<NetworkAddress> {
+        (1..=3)
+            .map(|host| NetworkAddress {
+                timestamp: 1680000000,
+                services: 1,
+                address: SocketAddrV6::new(
+                    Ipv4Addr::new(10, 0, 0, host).to_ipv6_mapped(),
+                    18333,
+                    0,
+                    0,
+                ),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn addr_serialize_and_parse() {
+        let addr = Addr::new(addresses());
+        let serialized = addr.serialize();
+        assert_eq!(serialized.len(), 1 + 3 * 30);
+        assert_eq!(Addr::parse(&mut serialized.as_slice()).unwrap(), addr);
+    }
+
+    #[test]
+    fn addrv2_serialize_and_parse() {
+        let addr = AddrV2::new(addresses());
+        assert_eq!(
+            AddrV2::parse(&mut addr.serialize().as_slice()).unwrap(),
+            addr
+        );
+    }
+
+    #[test]
+    fn addrv2_skips_unsupported_networks() {
+        let mut buffer = AddrV2::new(addresses()).serialize();
+        buffer[0] = 4;
+        // direccion I2P de 32 bytes
+        buffer.extend([0, 0xc4, 0x22, 0x64, 0x01, 0x05, 32]);
+        buffer.extend([7; 32]);
+        buffer.extend([0, 0]);
+
+        assert_eq!(
+            AddrV2::parse(&mut buffer.as_slice()).unwrap().addresses,
+            addresses()
+        );
+    }
+
+    #[test]
+    fn parse_invalid_addr() {
+        let mut buffer = Addr::new(addresses()).serialize();
+        buffer.pop();
+        assert!(Addr::parse(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_addr() {
+        assert_eq!(Addr::new(vec![]).command(), Command::Addr);
+        assert_eq!(AddrV2::new(vec![]).command(), Command::AddrV2);
+    }
+}
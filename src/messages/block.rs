@@ -1,21 +1,22 @@
 use std::{
     fs::remove_file,
-    io::{Read, Write},
+    io::{self, BufReader, Read, Write},
     vec,
 };
 
-use bitcoin_hashes::{sha256, Hash};
-
 use super::transaction::Transaction;
 
 use crate::{
-    error::CustomError,
-    message::Message,
-    parser::{BufferParser, VarIntSerialize},
-    structs::block_header::BlockHeader,
-    utils::open_new_file,
+    error::{ChainError, CustomError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::{block_header::BlockHeader, hash256::Hash256},
+    utils::{open_new_file, parallel_map},
 };
 
+/// Prefijo del script del output del coinbase que contiene el witness commitment (BIP 141).
+pub const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
 #[derive(Debug)]
 
 /// Esta estructura es la que se encarga de almacenar los bloques, esto lo hace con un BlockHeader y en un vector de 'transactions' por cada uno
@@ -36,10 +37,8 @@ impl Block {
     /// Esta funcion se encargar de restaurar un bloque, recibe un path al archivo que contiene al bloque, lo lee y lo parsea
     /// Devuelve CustomError si no puede abrir o leer el archivo
     pub fn restore(path: String) -> Result<Self, CustomError> {
-        let mut block_file = open_new_file(path.clone(), true)?;
-        let mut block_buffer = Vec::new();
-        block_file.read_to_end(&mut block_buffer)?;
-        let block = match Self::parse(block_buffer) {
+        let block_file = open_new_file(path.clone(), true)?;
+        let block = match Self::parse(&mut BufReader::new(&block_file)) {
             Ok(block) => Ok(block),
             Err(e) => {
                 remove_file(path)?;
@@ -58,42 +57,80 @@ impl Block {
     }
 
     /// Esta funcion se encarga de crear el merkle tree del bloque, recorre las transacciones del bloque y calcula el hash de cada una, luego que el merkle tree es generado a partir de los hashes de las transacciones, se lo devuelve.
-    fn create_merkle_tree(&self) -> Vec<Vec<Vec<u8>>> {
-        let mut hashes = vec![];
-        for transaction in &self.transactions {
-            hashes.push(transaction.hash());
-        }
+    fn create_merkle_tree(&self) -> Vec<Vec<Hash256>> {
+        let hashes = parallel_map(&self.transactions, Transaction::hash);
+        generate_merkle_tree(hashes)
+    }
 
-        let mut merkle_tree = vec![hashes.clone()];
-        generate_merkle_tree(hashes, &mut merkle_tree);
-        merkle_tree
+    /// Calcula el txid y el wtxid de cada transaccion, repartiendo las transacciones entre varios threads.
+    /// Las transacciones sin witness se serializan una sola vez, ya que su wtxid coincide con el txid.
+    fn transaction_ids(&self) -> Vec<(Hash256, Hash256)> {
+        parallel_map(&self.transactions, |transaction| {
+            let txid = transaction.hash();
+            match transaction.has_witness() {
+                true => (txid, transaction.wtxid()),
+                false => (txid, txid),
+            }
+        })
     }
 
     /// Esta funcion se encarga de validar la proof of inclusion del bloque, creando el merkle tree y comparando el merkle root del BlockHeader con el merkle root calculado
+    /// Si el bloque tiene transacciones con witness, tambien valida el witness commitment del coinbase.
     /// Devuelve CustomError si el merkle root del BlockHeader no coincide con el merkle root calculado, significando que el bloque no es valido
     pub fn create_merkle_root(&self) -> Result<(), CustomError> {
-        let merkle_tree = self.create_merkle_tree();
+        let (txids, wtxids): (Vec<Hash256>, Vec<Hash256>) =
+            self.transaction_ids().into_iter().unzip();
+
+        match merkle_root(txids) {
+            Some(merkle_root) if merkle_root == self.header.merkle_root => {}
+            _ => return Err(CustomError::Chain(ChainError::InvalidMerkleRoot)),
+        }
+        self.validate_witness_commitment(wtxids)
+    }
 
-        let merkle_root = match merkle_tree.last() {
-            Some(root_level) => root_level[0].to_vec(),
-            None => return Err(CustomError::InvalidMerkleRoot),
+    /// Esta funcion se encarga de validar el witness commitment del bloque (BIP 141), recibe los wtxid de las transacciones del bloque.
+    /// El commitment es el hash del merkle root de los wtxid, en el que el del coinbase se reemplaza por ceros,
+    /// concatenado con el witness reserved value que el coinbase lleva en su witness.
+    /// Si ninguna transaccion tiene witness no hay nada que validar.
+    /// Devuelve CustomError si el commitment falta o no coincide con el calculado.
+    fn validate_witness_commitment(&self, mut wtxids: Vec<Hash256>) -> Result<(), CustomError> {
+        if !self.transactions.iter().any(|tx| tx.has_witness()) {
+            return Ok(());
+        }
+        let Some(coinbase) = self.transactions.first() else { return Err(CustomError::Chain(ChainError::InvalidWitnessCommitment)) };
+        let Some(commitment) = coinbase
+            .outputs
+            .iter()
+            .rev()
+            .map(|output| &output.script_pubkey)
+            .find(|script| script.len() >= 38 && script.starts_with(&WITNESS_COMMITMENT_HEADER))
+        else {
+            return Err(CustomError::Chain(ChainError::InvalidWitnessCommitment));
+        };
+        let reserved_value = match coinbase.inputs.first() {
+            Some(input) if input.witness.len() == 1 => Hash256::from_slice(&input.witness[0])
+                .map_err(|_| CustomError::Chain(ChainError::InvalidWitnessCommitment))?,
+            _ => return Err(CustomError::Chain(ChainError::InvalidWitnessCommitment)),
         };
 
-        if merkle_root != self.header.merkle_root {
-            return Err(CustomError::InvalidMerkleRoot);
+        wtxids[0] = Hash256::ZERO;
+        let Some(witness_root) = merkle_root(wtxids) else { return Err(CustomError::Chain(ChainError::InvalidWitnessCommitment)) };
+
+        if merge_hashes(&witness_root, &reserved_value).as_bytes()[..] != commitment[6..38] {
+            return Err(CustomError::Chain(ChainError::InvalidWitnessCommitment));
         }
         Ok(())
     }
 
     /// Esta funcion se encarga de encontrar el indice de una transaccion dado un bloque y el hash de la transaccion
     /// Devuelve CustomError si no puede encontrar la transaccion en el bloque
-    fn find_transaction_index(&self, transaction_hash: &Vec<u8>) -> Result<usize, CustomError> {
+    fn find_transaction_index(&self, transaction_hash: &Hash256) -> Result<usize, CustomError> {
         for i in 0..self.transactions.len() {
             if self.transactions[i].hash() == *transaction_hash {
                 return Ok(i);
             }
         }
-        Err(CustomError::InvalidMerkleRoot)
+        Err(CustomError::Chain(ChainError::InvalidMerkleRoot))
     }
 
     /// Esta funcion se encarga de generar el merkle path de una transaccion, recibe el hash de la transaccion y el bloque a la que esta pertence para generar el merkle path
@@ -101,8 +138,8 @@ impl Block {
     /// Devuelve CustomError si no puede encontrar la transaccion en el bloque
     pub fn generate_merkle_path(
         &self,
-        transaction_hash: Vec<u8>,
-    ) -> Result<(Vec<u8>, Vec<Vec<u8>>), CustomError> {
+        transaction_hash: Hash256,
+    ) -> Result<(Vec<u8>, Vec<Hash256>), CustomError> {
         let merkle_tree = self.create_merkle_tree();
         let mut hash_index = self.find_transaction_index(&transaction_hash)?;
         let mut mp_flags: Vec<u8> = vec![1];
@@ -115,11 +152,11 @@ impl Block {
             if hash_index % 2 == 0 {
                 mp_flags.insert(0, 1);
                 mp_flags.push(0);
-                mp_hashes.push(level[hash_index + 1].clone());
+                mp_hashes.push(level[hash_index + 1]);
             } else {
                 mp_flags.insert(0, 0);
                 mp_flags.insert(0, 1);
-                mp_hashes.insert(0, level[hash_index - 1].clone());
+                mp_hashes.insert(0, level[hash_index - 1]);
             }
             hash_index /= 2;
         }
@@ -133,55 +170,60 @@ impl Block {
 }
 
 /// Esta funcion se encarga de mergear dos hashes, recibe dos hashes y los mergea en un solo hash
-fn merge_hashes(mut left: Vec<u8>, mut right: Vec<u8>) -> Vec<u8> {
-    left.append(&mut right);
-    let hash = sha256::Hash::hash(sha256::Hash::hash(left.as_slice()).as_byte_array())
-        .as_byte_array()
-        .to_vec();
-    hash
+pub fn merge_hashes(left: &Hash256, right: &Hash256) -> Hash256 {
+    let mut buffer = [0; 64];
+    buffer[..32].copy_from_slice(left.as_bytes());
+    buffer[32..].copy_from_slice(right.as_bytes());
+    Hash256::hash(&buffer)
 }
 
-/// Esta funcion se encarga de generar el merkle tree, recibe un vector de hashes y un vector de vectores de vectores de bytes, y va generando el merkle tree recursivamente por niveles
-fn generate_merkle_tree(hashes: Vec<Vec<u8>>, merkle_tree: &mut Vec<Vec<Vec<u8>>>) {
-    if hashes.len() == 1 {
-        return;
+/// Calcula el merkle root de los hashes recibidos, en orden. Devuelve None si no hay hashes.
+/// A diferencia de generate_merkle_tree, solo conserva el nivel actual del arbol.
+pub fn merkle_root(hashes: Vec<Hash256>) -> Option<Hash256> {
+    let mut level = hashes;
+    while level.len() > 1 {
+        level = merkle_level(&level);
     }
+    level.first().copied()
+}
 
-    let mut level = vec![];
-    for i in (0..hashes.len()).step_by(2) {
-        let current_hash = &hashes[i];
-        if i + 1 >= hashes.len() {
-            level.push(merge_hashes(current_hash.clone(), current_hash.clone()));
-            break;
-        }
-        let next_hash = &hashes[i + 1];
-        level.push(merge_hashes(current_hash.clone(), next_hash.clone()));
+/// Esta funcion se encarga de generar el merkle tree, recibe un vector de hashes y devuelve todos los niveles del arbol,
+/// desde los hashes recibidos hasta el merkle root.
+fn generate_merkle_tree(hashes: Vec<Hash256>) -> Vec<Vec<Hash256>> {
+    let mut merkle_tree = vec![hashes];
+    while let Some(level) = merkle_tree.last().filter(|level| level.len() > 1) {
+        let next_level = merkle_level(level);
+        merkle_tree.push(next_level);
     }
+    merkle_tree
+}
 
-    merkle_tree.push(level.clone());
-    generate_merkle_tree(level, merkle_tree);
+/// Calcula el siguiente nivel del merkle tree mergeando los hashes de a pares, repartiendo los pares entre varios threads.
+/// Si la cantidad de hashes es impar, el ultimo se mergea consigo mismo.
+fn merkle_level(hashes: &[Hash256]) -> Vec<Hash256> {
+    let pairs: Vec<&[Hash256]> = hashes.chunks(2).collect();
+    parallel_map(&pairs, |pair| {
+        merge_hashes(&pair[0], pair.get(1).unwrap_or(&pair[0]))
+    })
 }
 
 /// Implementa el trait Message para bloque
 /// Permite serializar, parsear y obtener el comando
 impl Message for Block {
-    fn serialize(&self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = vec![];
-        buffer.extend(self.header.serialize());
-        buffer.extend(self.transactions.len().to_varint_bytes());
+    const COMMAND: Command = Command::Block;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.header.serialize())?;
+        self.transactions.len().write_varint(writer)?;
         for transaction in &self.transactions {
-            buffer.extend(transaction.serialize());
+            transaction.serialize_into(writer)?;
         }
-        buffer
-    }
-
-    fn get_command(&self) -> String {
-        String::from("block")
+        Ok(())
     }
 
-    fn parse(buffer: Vec<u8>) -> Result<Self, crate::error::CustomError> {
-        let mut parser = BufferParser::new(buffer);
-        let header = BlockHeader::parse(parser.extract_buffer(80)?.to_vec())?;
+    fn parse(reader: &mut impl Read) -> Result<Self, crate::error::CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let header = BlockHeader::parse(parser.extract_bytes(80)?)?;
         let tx_count = parser.extract_varint()? as usize;
         let mut transactions = vec![];
         for _ in 0..tx_count {
@@ -201,21 +243,91 @@ impl Message for Block {
 mod tests {
     use std::io::Read;
 
-    use crate::utils::open_new_file;
+    use bitcoin_hashes::{sha256, Hash};
+
+    use crate::{
+        structs::{outpoint::OutPoint, tx_input::TransactionInput, tx_output::TransactionOutput},
+        utils::open_new_file,
+    };
 
     use super::*;
 
+    fn witness_block(reserved_value: Vec<u8>) -> Block {
+        let input = |witness: Vec<Vec<u8>>| TransactionInput {
+            previous_output: OutPoint {
+                hash: Hash256::ZERO,
+                index: 0xffffffff,
+            },
+            script_sig: vec![1, 2, 3],
+            sequence: 0xffffffff,
+            witness,
+        };
+        let segwit_tx = Transaction {
+            version: 2,
+            inputs: vec![input(vec![vec![1; 71], vec![2; 33]])],
+            outputs: vec![TransactionOutput {
+                value: 1000,
+                script_pubkey: vec![0; 22],
+            }],
+            lock_time: 0,
+        };
+
+        let witness_root = merge_hashes(&Hash256::ZERO, &segwit_tx.wtxid());
+        let mut commitment = WITNESS_COMMITMENT_HEADER.to_vec();
+        commitment.extend(merge_hashes(&witness_root, &Hash256::ZERO).as_bytes());
+        let coinbase = Transaction {
+            version: 2,
+            inputs: vec![input(vec![reserved_value])],
+            outputs: vec![TransactionOutput {
+                value: 0,
+                script_pubkey: commitment,
+            }],
+            lock_time: 0,
+        };
+
+        let header = BlockHeader {
+            version: 0,
+            prev_block_hash: Hash256::ZERO,
+            merkle_root: Hash256::ZERO,
+            timestamp: 0,
+            bits: 0,
+            nonce: 0,
+            hash: Hash256::ZERO,
+            broadcasted: false,
+            block_downloaded: false,
+        };
+        let mut block = Block::new(header, vec![coinbase, segwit_tx]);
+        block.header.merkle_root = block.create_merkle_tree().last().unwrap()[0];
+        block
+    }
+
     #[test]
     fn merge_hashes_test() {
-        let left_hash: Vec<u8> = vec![1, 2, 3];
-        let right_hash: Vec<u8> = vec![4, 5, 6];
-        let result = merge_hashes(left_hash, right_hash);
-        let hash = sha256::Hash::hash(
-            sha256::Hash::hash(vec![1, 2, 3, 4, 5, 6].as_slice()).as_byte_array(),
-        )
-        .as_byte_array()
-        .to_vec();
-        assert_eq!(result, hash);
+        let left_hash = Hash256::new([1; 32]);
+        let right_hash = Hash256::new([2; 32]);
+        let result = merge_hashes(&left_hash, &right_hash);
+        let mut merged = vec![1; 32];
+        merged.extend([2; 32]);
+        let hash = sha256::Hash::hash(sha256::Hash::hash(merged.as_slice()).as_byte_array());
+        assert_eq!(result.as_bytes(), hash.as_byte_array());
+    }
+
+    #[test]
+    fn merkle_root_of_many_hashes() {
+        let hashes: Vec<Hash256> = (0..1001_u32)
+            .map(|i| Hash256::hash(&i.to_le_bytes()))
+            .collect();
+        let mut level = hashes.clone();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| merge_hashes(&pair[0], pair.last().unwrap()))
+                .collect();
+        }
+
+        assert_eq!(merkle_root(hashes.clone()), Some(level[0]));
+        assert_eq!(generate_merkle_tree(hashes).last().unwrap(), &level);
+        assert_eq!(merkle_root(vec![]), None);
     }
 
     #[test]
@@ -223,7 +335,7 @@ mod tests {
         let mut file = open_new_file("tests/blocks/test_block.bin".to_string(), true).unwrap();
         let mut buffer = vec![];
         file.read_to_end(&mut buffer).unwrap();
-        let block = Block::parse(buffer).unwrap();
+        let block = Block::parse(&mut buffer.as_slice()).unwrap();
 
         let merkle_tree = block.create_merkle_tree();
         assert_eq!(merkle_tree.last().unwrap()[0], block.header.merkle_root);
@@ -243,29 +355,27 @@ mod tests {
         let mut file = open_new_file("tests/blocks/test_block.bin".to_string(), true).unwrap();
         let mut buffer = vec![];
         file.read_to_end(&mut buffer).unwrap();
-        let block = Block::parse(buffer).unwrap();
+        let block = Block::parse(&mut buffer.as_slice()).unwrap();
 
         let merkle_tree = block.create_merkle_tree();
         let transactions_hashes = merkle_tree.get(0).unwrap();
-        let (flags, hashes) = block
-            .generate_merkle_path(transactions_hashes[6].clone())
-            .unwrap();
+        let (flags, hashes) = block.generate_merkle_path(transactions_hashes[6]).unwrap();
 
         assert_eq!(flags, vec![1, 1, 1, 0, 1, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
         assert_eq!(hashes.len(), 6);
 
-        let merging = merge_hashes(hashes[2].clone(), hashes[3].clone());
-        let merging = merge_hashes(hashes[1].clone(), merging);
-        let merging = merge_hashes(hashes[0].clone(), merging);
-        let merging = merge_hashes(merging, hashes[4].clone());
-        let merging = merge_hashes(merging, hashes[5].clone());
+        let merging = merge_hashes(&hashes[2], &hashes[3]);
+        let merging = merge_hashes(&hashes[1], &merging);
+        let merging = merge_hashes(&hashes[0], &merging);
+        let merging = merge_hashes(&merging, &hashes[4]);
+        let merging = merge_hashes(&merging, &hashes[5]);
 
         assert_eq!(hashes.len(), 6);
         assert_eq!(merging, block.header.merkle_root);
     }
 
     #[test]
-    fn get_command_block_test() {
+    fn command_block_test() {
         let buffer = vec![
             1, 0, 0, 0, 5, 159, 141, 74, 195, 4, 19, 253, 127, 1, 148, 149, 222, 143, 237, 24, 27,
             124, 186, 34, 123, 241, 216, 166, 203, 239, 86, 108, 0, 0, 0, 0, 233, 233, 109, 115,
@@ -275,6 +385,22 @@ mod tests {
         ];
         let block_header = BlockHeader::parse(buffer).unwrap();
         let block = Block::new(block_header, vec![]);
-        assert_eq!(block.get_command(), "block");
+        assert_eq!(block.command(), Command::Block);
+    }
+
+    #[test]
+    fn witness_commitment() {
+        let block = witness_block(vec![0; 32]);
+        assert!(block.create_merkle_root().is_ok());
+
+        let parsed = Transaction::parse(&mut block.transactions[1].serialize().as_slice()).unwrap();
+        assert!(parsed.has_witness());
+        assert_eq!(parsed.wtxid(), block.transactions[1].wtxid());
+
+        let block = witness_block(vec![1; 32]);
+        assert!(matches!(
+            block.create_merkle_root(),
+            Err(CustomError::Chain(ChainError::InvalidWitnessCommitment))
+        ));
     }
 }
@@ -0,0 +1,99 @@
+use std::io::{self, Read, Write};
+
+use super::transaction::Transaction;
+
+use crate::{
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::hash256::Hash256,
+};
+
+#[derive(Debug)]
+/// BlockTxn es la respuesta a un getblocktxn, con las transacciones pedidas de un compact block (BIP 152).
+/// Los campos son:
+/// - block_hash: Hash del bloque.
+/// - transactions: Transacciones pedidas, en el mismo orden que los indices del getblocktxn.
+pub struct BlockTxn {
+    pub block_hash: Hash256,
+    pub transactions: Vec<Transaction>,
+}
+
+impl BlockTxn {
+    /// Crea un nuevo mensaje blocktxn.
+    pub fn new(block_hash: Hash256, transactions: Vec<Transaction>) -> Self {
+        Self {
+            block_hash,
+            transactions,
+        }
+    }
+}
+
+/// Implementa el trait Message para el mensaje blocktxn.
+/// Permite serializar, parsear y obtener el comando
+impl Message for BlockTxn {
+    const COMMAND: Command = Command::BlockTxn;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.block_hash.as_bytes())?;
+        self.transactions.len().write_varint(writer)?;
+        for tx in &self.transactions {
+            tx.serialize_into(writer)?;
+        }
+        Ok(())
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let block_hash = parser.extract_hash32()?.into();
+        let count = parser.extract_varint()? as usize;
+        let mut transactions = vec![];
+        for _ in 0..count {
+            transactions.push(Transaction::parse_from_parser(&mut parser)?);
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self {
+            block_hash,
+            transactions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::{messages::block::Block, utils::open_new_file};
+
+    use super::*;
+
+    #[test]
+    fn block_txn_serialize_and_parse() {
+        let mut file = open_new_file("tests/blocks/test_block.bin".to_string(), true).unwrap();
+        let mut buffer = vec![];
+        file.read_to_end(&mut buffer).unwrap();
+        let block = Block::parse(&mut buffer.as_slice()).unwrap();
+
+        let block_txn = BlockTxn::new(*block.header.hash(), block.transactions[1..4].to_vec());
+        let parsed = BlockTxn::parse(&mut block_txn.serialize().as_slice()).unwrap();
+
+        assert_eq!(parsed.block_hash, block_txn.block_hash);
+        assert_eq!(parsed.transactions.len(), 3);
+        assert_eq!(parsed.transactions[2].hash(), block.transactions[3].hash());
+    }
+
+    #[test]
+    fn parse_invalid_block_txn() {
+        let mut buffer = vec![0; 32];
+        buffer.push(1);
+        assert!(BlockTxn::parse(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_block_txn() {
+        let block_txn = BlockTxn::new(Hash256::ZERO, vec![]);
+        assert_eq!(block_txn.command(), Command::BlockTxn);
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::{block_filter::filter_header, hash256::Hash256},
+};
+
+/// Cantidad maxima de filter hashes en un mensaje cfheaders (BIP 157).
+const MAX_CFHEADERS: usize = 2000;
+
+#[derive(Debug, PartialEq)]
+/// CFHeaders es la respuesta a un getcfheaders (BIP 157).
+/// Los elementos son:
+/// - filter_type: Tipo de filtro.
+/// - stop_hash: Hash del ultimo bloque del rango.
+/// - previous_filter_header: Filter header del bloque anterior al rango.
+/// - filter_hashes: Hashes de los filtros de cada bloque del rango.
+pub struct CFHeaders {
+    pub filter_type: u8,
+    pub stop_hash: Hash256,
+    pub previous_filter_header: Vec<u8>,
+    pub filter_hashes: Vec<Vec<u8>>,
+}
+
+impl CFHeaders {
+    /// Crea un nuevo mensaje cfheaders.
+    pub fn new(
+        filter_type: u8,
+        stop_hash: Hash256,
+        previous_filter_header: Vec<u8>,
+        filter_hashes: Vec<Vec<u8>>,
+    ) -> Self {
+        Self {
+            filter_type,
+            stop_hash,
+            previous_filter_header,
+            filter_hashes,
+        }
+    }
+
+    /// Devuelve los filter headers de cada bloque del rango, encadenados a partir del previous_filter_header.
+    pub fn filter_headers(&self) -> Vec<Vec<u8>> {
+        let mut previous = self.previous_filter_header.clone();
+        let mut headers = vec![];
+        for filter_hash in &self.filter_hashes {
+            previous = filter_header(filter_hash, &previous);
+            headers.push(previous.clone());
+        }
+        headers
+    }
+}
+
+/// Implementa el trait Message para el mensaje cfheaders.
+/// Permite serializar, parsear y obtener el comando
+impl Message for CFHeaders {
+    const COMMAND: Command = Command::CFHeaders;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&[self.filter_type])?;
+        writer.write_all(self.stop_hash.as_bytes())?;
+        writer.write_all(&self.previous_filter_header)?;
+        self.filter_hashes.len().write_varint(writer)?;
+        for filter_hash in &self.filter_hashes {
+            writer.write_all(filter_hash)?;
+        }
+        Ok(())
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let filter_type = parser.extract_u8()?;
+        let stop_hash = parser.extract_hash32()?.into();
+        let previous_filter_header = parser.extract_bytes(32)?;
+
+        let count = parser.extract_varint()? as usize;
+        if count > MAX_CFHEADERS {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        let mut filter_hashes = vec![];
+        for _ in 0..count {
+            filter_hashes.push(parser.extract_bytes(32)?);
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+
+        Ok(Self {
+            filter_type,
+            stop_hash,
+            previous_filter_header,
+            filter_hashes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cfheaders_serialize_and_parse() {
+        let cfheaders = CFHeaders::new(
+            0,
+            Hash256::new([1; 32]),
+            vec![0; 32],
+            vec![vec![2; 32], vec![3; 32]],
+        );
+        let serialized = cfheaders.serialize();
+        assert_eq!(serialized.len(), 1 + 32 + 32 + 1 + 64);
+        assert_eq!(
+            CFHeaders::parse(&mut serialized.as_slice()).unwrap(),
+            cfheaders
+        );
+    }
+
+    #[test]
+    fn cfheaders_chain() {
+        let cfheaders = CFHeaders::new(
+            0,
+            Hash256::new([1; 32]),
+            vec![0; 32],
+            vec![vec![2; 32], vec![3; 32]],
+        );
+        let headers = cfheaders.filter_headers();
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0], filter_header(&[2; 32], &[0; 32]));
+        assert_eq!(headers[1], filter_header(&[3; 32], &headers[0]));
+    }
+
+    #[test]
+    fn parse_invalid_cfheaders() {
+        let mut buffer = vec![0; 65];
+        buffer.extend(vec![2, 1, 2, 3]);
+        assert!(CFHeaders::parse(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_cfheaders() {
+        let cfheaders = CFHeaders::new(0, Hash256::ZERO, vec![0; 32], vec![]);
+        assert_eq!(cfheaders.command(), Command::CFHeaders);
+    }
+}
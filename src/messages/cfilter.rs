@@ -0,0 +1,72 @@
+use crate::{
+    error::CustomError,
+    impl_bitcoin_codec,
+    message::Command,
+    structs::{
+        block_filter::{filter_hash, BlockFilter},
+        hash256::Hash256,
+    },
+};
+
+#[derive(Debug, PartialEq)]
+/// CFilter es la respuesta a un getcfilters, con el filtro compacto de un bloque (BIP 157).
+/// Los elementos son:
+/// - filter_type: Tipo de filtro.
+/// - block_hash: Hash del bloque al que pertenece el filtro.
+/// - filter: Filtro serializado.
+pub struct CFilter {
+    pub filter_type: u8,
+    pub block_hash: Hash256,
+    pub filter: Vec<u8>,
+}
+
+impl CFilter {
+    /// Crea un nuevo mensaje cfilter.
+    pub fn new(filter_type: u8, block_hash: Hash256, filter: Vec<u8>) -> Self {
+        Self {
+            filter_type,
+            block_hash,
+            filter,
+        }
+    }
+
+    /// Devuelve el hash del filtro, para validarlo contra los filter headers.
+    pub fn filter_hash(&self) -> Vec<u8> {
+        filter_hash(&self.filter)
+    }
+
+    /// Decodifica el filtro recibido.
+    pub fn block_filter(&self) -> Result<BlockFilter, CustomError> {
+        BlockFilter::parse(self.filter.clone())
+    }
+}
+
+impl_bitcoin_codec!(CFilter, Command::CFilter, filter_type, block_hash, filter);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn cfilter_serialize_and_parse() {
+        let cfilter = CFilter::new(0, Hash256::new([3; 32]), vec![0x01, 0x9d, 0xfc, 0xa8]);
+        let serialized = cfilter.serialize();
+        assert_eq!(serialized.len(), 1 + 32 + 1 + 4);
+        assert_eq!(CFilter::parse(&mut serialized.as_slice()).unwrap(), cfilter);
+    }
+
+    #[test]
+    fn parse_invalid_cfilter() {
+        let mut buffer = vec![0; 33];
+        buffer.extend(vec![4, 1, 2]);
+        assert!(CFilter::parse(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_cfilter() {
+        let cfilter = CFilter::new(0, Hash256::ZERO, vec![0]);
+        assert_eq!(cfilter.command(), Command::CFilter);
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use bitcoin_hashes::{sha256, siphash24, Hash};
+
+use super::{block::Block, block_txn::BlockTxn, transaction::Transaction};
+
+use crate::{
+    error::{CustomError, ParseError, WalletError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::{block_header::BlockHeader, hash256::Hash256},
+};
+
+const SHORT_ID_BYTES: usize = 6;
+
+#[derive(Debug, Clone)]
+/// PrefilledTransaction es una transaccion que se envia completa dentro de un compact block.
+/// Los campos son:
+/// - index: Posicion absoluta de la transaccion dentro del bloque.
+/// - tx: La transaccion.
+pub struct PrefilledTransaction {
+    pub index: u64,
+    pub tx: Transaction,
+}
+
+#[derive(Debug, Clone)]
+/// CompactBlock representa el mensaje 'cmpctblock' de BIP 152.
+/// En lugar de enviar todas las transacciones del bloque, se envian identificadores cortos
+/// que permiten reconstruir el bloque a partir de las transacciones pendientes que ya conocemos.
+/// Los campos son:
+/// - header: Header del bloque.
+/// - nonce: Nonce utilizado para calcular las claves de los short ids.
+/// - short_ids: Identificadores cortos (6 bytes) de las transacciones no incluidas.
+/// - prefilled_txs: Transacciones enviadas completas (al menos la coinbase).
+pub struct CompactBlock {
+    pub header: BlockHeader,
+    pub nonce: u64,
+    pub short_ids: Vec<u64>,
+    pub prefilled_txs: Vec<PrefilledTransaction>,
+}
+
+#[derive(Debug)]
+/// PartialBlock es un bloque reconstruido a partir de un compact block al que le pueden faltar transacciones.
+/// Los campos son:
+/// - header: Header del bloque.
+/// - transactions: Transacciones del bloque, None en las posiciones que todavia faltan.
+pub struct PartialBlock {
+    pub header: BlockHeader,
+    pub transactions: Vec<Option<Transaction>>,
+}
+
+impl CompactBlock {
+    /// Genera un compact block a partir de un bloque completo, enviando unicamente la coinbase completa.
+    pub fn from_block(block: &Block, nonce: u64) -> Self {
+        let mut compact_block = Self {
+            header: block.header.clone(),
+            nonce,
+            short_ids: vec![],
+            prefilled_txs: vec![],
+        };
+        let (k0, k1) = compact_block.short_id_keys();
+
+        for (index, tx) in block.transactions.iter().enumerate() {
+            if index == 0 {
+                compact_block.prefilled_txs.push(PrefilledTransaction {
+                    index: 0,
+                    tx: tx.clone(),
+                });
+            } else {
+                compact_block.short_ids.push(short_id(k0, k1, &tx.wtxid()));
+            }
+        }
+        compact_block
+    }
+
+    /// Calcula las claves de siphash a partir del sha256 del header y el nonce.
+    fn short_id_keys(&self) -> (u64, u64) {
+        let mut buffer = self.header.serialize();
+        buffer.extend(self.nonce.to_le_bytes());
+        let hash = sha256::Hash::hash(&buffer).to_byte_array();
+
+        let k0 = u64::from_le_bytes([
+            hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7],
+        ]);
+        let k1 = u64::from_le_bytes([
+            hash[8], hash[9], hash[10], hash[11], hash[12], hash[13], hash[14], hash[15],
+        ]);
+        (k0, k1)
+    }
+
+    /// Devuelve la cantidad total de transacciones del bloque.
+    pub fn tx_count(&self) -> usize {
+        self.short_ids.len() + self.prefilled_txs.len()
+    }
+
+    /// Reconstruye el bloque a partir de las transacciones pendientes recibidas.
+    /// Las transacciones que no se encuentren quedan como None en el PartialBlock.
+    /// Devuelve CustomError si los indices de las transacciones prefilled son invalidos.
+    pub fn reconstruct(&self, mempool: &[Transaction]) -> Result<PartialBlock, CustomError> {
+        let mut transactions: Vec<Option<Transaction>> = vec![None; self.tx_count()];
+
+        for prefilled in &self.prefilled_txs {
+            match transactions.get_mut(prefilled.index as usize) {
+                Some(slot) => *slot = Some(prefilled.tx.clone()),
+                None => return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid)),
+            }
+        }
+
+        let (k0, k1) = self.short_id_keys();
+        let mut mempool_ids: HashMap<u64, &Transaction> = HashMap::new();
+        for tx in mempool {
+            mempool_ids.insert(short_id(k0, k1, &tx.wtxid()), tx);
+        }
+
+        let mut short_ids = self.short_ids.iter();
+        for slot in transactions.iter_mut().filter(|slot| slot.is_none()) {
+            if let Some(id) = short_ids.next() {
+                *slot = mempool_ids.get(id).map(|tx| (*tx).clone());
+            }
+        }
+
+        Ok(PartialBlock {
+            header: self.header.clone(),
+            transactions,
+        })
+    }
+}
+
+impl PartialBlock {
+    /// Devuelve el hash del bloque.
+    pub fn hash(&self) -> &Hash256 {
+        self.header.hash()
+    }
+
+    /// Devuelve los indices de las transacciones que faltan para completar el bloque.
+    pub fn missing_indexes(&self) -> Vec<u64> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| tx.is_none())
+            .map(|(index, _)| index as u64)
+            .collect()
+    }
+
+    /// Completa las transacciones faltantes con las recibidas en un mensaje blocktxn.
+    /// Devuelve CustomError si la cantidad de transacciones recibidas no coincide con las faltantes.
+    pub fn fill(&mut self, block_txn: BlockTxn) -> Result<(), CustomError> {
+        let missing = self.missing_indexes();
+        if missing.len() != block_txn.transactions.len() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        for (index, tx) in missing.into_iter().zip(block_txn.transactions) {
+            self.transactions[index as usize] = Some(tx);
+        }
+        Ok(())
+    }
+
+    /// Convierte el bloque parcial en un bloque completo.
+    /// Devuelve CustomError si todavia faltan transacciones.
+    pub fn into_block(self) -> Result<Block, CustomError> {
+        let mut transactions = vec![];
+        for tx in self.transactions {
+            match tx {
+                Some(tx) => transactions.push(tx),
+                None => return Err(CustomError::Wallet(WalletError::TransactionNotFound)),
+            }
+        }
+        Ok(Block::new(self.header, transactions))
+    }
+}
+
+/// Calcula el short id de una transaccion: siphash-2-4 del wtxid truncado a 6 bytes (version 2 de BIP 152).
+fn short_id(k0: u64, k1: u64, wtxid: &Hash256) -> u64 {
+    siphash24::Hash::hash_to_u64_with_keys(k0, k1, wtxid.as_bytes()) & 0x0000_ffff_ffff_ffff
+}
+
+/// Codifica indices absolutos de forma diferencial, como lo pide BIP 152.
+/// Devuelve CustomError si los indices no son estrictamente crecientes, ya que la diferencia seria negativa.
+pub fn encode_differential_indexes(indexes: &[u64]) -> Result<Vec<u64>, CustomError> {
+    let mut differential = vec![];
+    let mut last: Option<u64> = None;
+    for index in indexes {
+        match last {
+            Some(last) => differential.push(
+                index
+                    .checked_sub(last)
+                    .and_then(|diff| diff.checked_sub(1))
+                    .ok_or(CustomError::Validation(String::from(
+                        "Differential indexes must be strictly increasing",
+                    )))?,
+            ),
+            None => differential.push(*index),
+        }
+        last = Some(*index);
+    }
+    Ok(differential)
+}
+
+/// Decodifica indices codificados de forma diferencial a indices absolutos.
+/// Devuelve CustomError si algun indice desborda.
+pub fn decode_differential_indexes(differential: &[u64]) -> Result<Vec<u64>, CustomError> {
+    let mut indexes = vec![];
+    let mut last: Option<u64> = None;
+    for diff in differential {
+        let index = match last {
+            Some(last) => last
+                .checked_add(*diff)
+                .and_then(|index| index.checked_add(1))
+                .ok_or(CustomError::Parse(ParseError::SerializedBufferIsInvalid))?,
+            None => *diff,
+        };
+        indexes.push(index);
+        last = Some(index);
+    }
+    Ok(indexes)
+}
+
+/// Implementa el trait Message para el mensaje cmpctblock.
+/// Permite serializar, parsear y obtener el comando
+impl Message for CompactBlock {
+    const COMMAND: Command = Command::CmpctBlock;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.header.serialize())?;
+        writer.write_all(&self.nonce.to_le_bytes())?;
+
+        self.short_ids.len().write_varint(writer)?;
+        for short_id in &self.short_ids {
+            writer.write_all(&short_id.to_le_bytes()[..SHORT_ID_BYTES])?;
+        }
+
+        let indexes: Vec<u64> = self.prefilled_txs.iter().map(|p| p.index).collect();
+        let differential = encode_differential_indexes(&indexes)
+            .map_err(|_| io::Error::from(ErrorKind::InvalidInput))?;
+        self.prefilled_txs.len().write_varint(writer)?;
+        for (prefilled, diff) in self.prefilled_txs.iter().zip(differential) {
+            (diff as usize).write_varint(writer)?;
+            prefilled.tx.serialize_into(writer)?;
+        }
+        Ok(())
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let header = BlockHeader::parse(parser.extract_bytes(80)?)?;
+        let nonce = parser.extract_u64()?;
+
+        let short_ids_count = parser.extract_varint()? as usize;
+        let mut short_ids = vec![];
+        for _ in 0..short_ids_count {
+            let mut bytes = [0_u8; 8];
+            bytes[..SHORT_ID_BYTES].copy_from_slice(&parser.extract_array::<SHORT_ID_BYTES>()?);
+            short_ids.push(u64::from_le_bytes(bytes));
+        }
+
+        let prefilled_count = parser.extract_varint()? as usize;
+        let mut differential = vec![];
+        let mut txs = vec![];
+        for _ in 0..prefilled_count {
+            differential.push(parser.extract_varint()?);
+            txs.push(Transaction::parse_from_parser(&mut parser)?);
+        }
+        let indexes = decode_differential_indexes(&differential)?;
+        let prefilled_txs = indexes
+            .into_iter()
+            .zip(txs)
+            .map(|(index, tx)| PrefilledTransaction { index, tx })
+            .collect();
+
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+
+        Ok(Self {
+            header,
+            nonce,
+            short_ids,
+            prefilled_txs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::utils::open_new_file;
+
+    use super::*;
+
+    fn test_block() -> Block {
+        let mut file = open_new_file("tests/blocks/test_block.bin".to_string(), true).unwrap();
+        let mut buffer = vec![];
+        file.read_to_end(&mut buffer).unwrap();
+        Block::parse(&mut buffer.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn differential_indexes() {
+        let indexes = vec![0, 1, 5, 6, 20];
+        let differential = encode_differential_indexes(&indexes).unwrap();
+        assert_eq!(differential, vec![0, 0, 3, 0, 13]);
+        assert_eq!(decode_differential_indexes(&differential).unwrap(), indexes);
+    }
+
+    #[test]
+    fn unsorted_or_repeated_indexes_cannot_be_encoded() {
+        assert!(matches!(
+            encode_differential_indexes(&[0, 5, 3]),
+            Err(CustomError::Validation(_))
+        ));
+        assert!(matches!(
+            encode_differential_indexes(&[2, 2]),
+            Err(CustomError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn compact_block_serialize_and_parse() {
+        let block = test_block();
+        let compact_block = CompactBlock::from_block(&block, 1234);
+        let parsed = CompactBlock::parse(&mut compact_block.serialize().as_slice()).unwrap();
+
+        assert_eq!(parsed.nonce, 1234);
+        assert_eq!(parsed.short_ids, compact_block.short_ids);
+        assert_eq!(parsed.prefilled_txs.len(), 1);
+        assert_eq!(parsed.tx_count(), block.transactions.len());
+    }
+
+    #[test]
+    fn reconstruct_with_full_mempool() {
+        let block = test_block();
+        let compact_block = CompactBlock::from_block(&block, 42);
+        let mempool: Vec<Transaction> = block.transactions.iter().skip(1).cloned().collect();
+
+        let partial_block = compact_block.reconstruct(&mempool).unwrap();
+        assert!(partial_block.missing_indexes().is_empty());
+
+        let reconstructed = partial_block.into_block().unwrap();
+        assert!(reconstructed.create_merkle_root().is_ok());
+    }
+
+    #[test]
+    fn reconstruct_with_missing_transactions() {
+        let block = test_block();
+        let compact_block = CompactBlock::from_block(&block, 42);
+        let mempool: Vec<Transaction> = block.transactions.iter().skip(3).cloned().collect();
+
+        let mut partial_block = compact_block.reconstruct(&mempool).unwrap();
+        assert_eq!(partial_block.missing_indexes(), vec![1, 2]);
+
+        let block_txn = BlockTxn::new(
+            *block.header.hash(),
+            vec![block.transactions[1].clone(), block.transactions[2].clone()],
+        );
+        partial_block.fill(block_txn).unwrap();
+
+        let reconstructed = partial_block.into_block().unwrap();
+        assert!(reconstructed.create_merkle_root().is_ok());
+    }
+
+    #[test]
+    fn short_ids_are_computed_from_the_wtxid() {
+        let block = test_block();
+        let compact_block = CompactBlock::from_block(&block, 42);
+        let (k0, k1) = compact_block.short_id_keys();
+
+        let expected: Vec<u64> = block
+            .transactions
+            .iter()
+            .skip(1)
+            .map(|tx| short_id(k0, k1, &tx.wtxid()))
+            .collect();
+        assert_eq!(compact_block.short_ids, expected);
+    }
+
+    #[test]
+    fn incomplete_partial_block_cannot_be_converted() {
+        let block = test_block();
+        let compact_block = CompactBlock::from_block(&block, 42);
+        let partial_block = compact_block.reconstruct(&[]).unwrap();
+        assert!(partial_block.into_block().is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{impl_bitcoin_codec, message::Command};
+
+/// Version de protocolo a partir de la cual los peers soportan el mensaje feefilter (BIP 133).
+pub const FEE_FILTER_PROTOCOL_VERSION: i32 = 70013;
+
+#[derive(Debug, PartialEq)]
+/// FeeFilter es el mensaje con el que se le pide a un peer que no anuncie ni envie transacciones
+/// cuyo fee rate sea menor al indicado (BIP 133).
+/// Los campos son:
+/// - fee_rate: Fee rate minimo en satoshis por kilobyte virtual.
+pub struct FeeFilter {
+    pub fee_rate: u64,
+}
+
+impl FeeFilter {
+    /// Crea un nuevo mensaje feefilter a partir de un fee rate en satoshis por byte virtual.
+    pub fn new(satoshis_per_byte: u64) -> Self {
+        Self {
+            fee_rate: satoshis_per_byte * 1000,
+        }
+    }
+}
+
+impl_bitcoin_codec!(FeeFilter, Command::FeeFilter, fee_rate);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn fee_filter_serialize_and_parse() {
+        let fee_filter = FeeFilter::new(1);
+        let serialized = fee_filter.serialize();
+        assert_eq!(serialized, vec![0xe8, 0x03, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            FeeFilter::parse(&mut serialized.as_slice()).unwrap(),
+            fee_filter
+        );
+    }
+
+    #[test]
+    fn parse_invalid_fee_filter() {
+        assert!(FeeFilter::parse(&mut vec![0xe8, 0x03].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_fee_filter() {
+        assert_eq!(FeeFilter::new(1).command(), Command::FeeFilter);
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+};
+
+/// Tamaño maximo de un elemento agregado al filtro (BIP 37).
+const MAX_FILTER_ADD_SIZE: usize = 520;
+
+#[derive(Debug, PartialEq)]
+/// FilterAdd es el mensaje que agrega un elemento al bloom filter cargado en el peer (BIP 37).
+pub struct FilterAdd {
+    pub data: Vec<u8>,
+}
+
+impl FilterAdd {
+    /// Crea un nuevo mensaje filteradd.
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+}
+
+/// Implementa el trait Message para el mensaje filteradd.
+/// Permite serializar, parsear y obtener el comando
+impl Message for FilterAdd {
+    const COMMAND: Command = Command::FilterAdd;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.data.len().write_varint(writer)?;
+        writer.write_all(&self.data)
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let size = parser.extract_varint()? as usize;
+        if size > MAX_FILTER_ADD_SIZE {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        let data = parser.extract_bytes(size)?;
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self { data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_add_serialize_and_parse() {
+        let filter_add = FilterAdd::new(vec![1, 2, 3]);
+        let serialized = filter_add.serialize();
+        assert_eq!(serialized, vec![3, 1, 2, 3]);
+        assert_eq!(
+            FilterAdd::parse(&mut serialized.as_slice()).unwrap(),
+            filter_add
+        );
+    }
+
+    #[test]
+    fn parse_invalid_filter_add() {
+        assert!(FilterAdd::parse(&mut vec![3, 1, 2].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_filter_add() {
+        let filter_add = FilterAdd::new(vec![]);
+        assert_eq!(filter_add.command(), Command::FilterAdd);
+    }
+}
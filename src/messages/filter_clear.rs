@@ -0,0 +1,43 @@
+use crate::{impl_bitcoin_codec, message::Command};
+
+#[derive(Debug)]
+/// FilterClear es un mensaje vacio que elimina el bloom filter cargado en el peer (BIP 37).
+/// A partir de ese momento el peer vuelve a enviarnos todas las transacciones.
+pub struct FilterClear {}
+
+impl FilterClear {
+    /// Crea un nuevo mensaje filterclear.
+    pub fn new() -> Self {
+        FilterClear {}
+    }
+}
+
+impl Default for FilterClear {
+    fn default() -> Self {
+        FilterClear::new()
+    }
+}
+
+impl_bitcoin_codec!(FilterClear, Command::FilterClear);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn serialize_filter_clear() {
+        assert_eq!(FilterClear::new().serialize(), vec![]);
+    }
+
+    #[test]
+    fn parse_invalid_filter_clear() {
+        assert!(FilterClear::parse(&mut vec![0x00].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_filter_clear() {
+        assert_eq!(FilterClear::new().command(), Command::FilterClear);
+    }
+}
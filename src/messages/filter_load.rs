@@ -0,0 +1,69 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser},
+    structs::bloom_filter::BloomFilter,
+};
+
+#[derive(Debug, PartialEq)]
+/// FilterLoad es el mensaje que carga un bloom filter en el peer (BIP 37).
+/// A partir de ese momento el peer solo nos envia las transacciones que coinciden con el filtro.
+pub struct FilterLoad {
+    pub filter: BloomFilter,
+}
+
+impl FilterLoad {
+    /// Crea un nuevo mensaje filterload.
+    pub fn new(filter: BloomFilter) -> Self {
+        Self { filter }
+    }
+}
+
+/// Implementa el trait Message para el mensaje filterload.
+/// Permite serializar, parsear y obtener el comando
+impl Message for FilterLoad {
+    const COMMAND: Command = Command::FilterLoad;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.filter.serialize())
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let filter = BloomFilter::parse(&mut parser)?;
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self { filter })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::bloom_filter::BLOOM_UPDATE_ALL;
+
+    use super::*;
+
+    #[test]
+    fn filter_load_serialize_and_parse() {
+        let mut filter = BloomFilter::new(2, 0.0001, 5, BLOOM_UPDATE_ALL);
+        filter.insert(&[0xAA; 20]);
+        let filter_load = FilterLoad::new(filter);
+
+        let parsed = FilterLoad::parse(&mut filter_load.serialize().as_slice()).unwrap();
+        assert_eq!(parsed, filter_load);
+    }
+
+    #[test]
+    fn parse_invalid_filter_load() {
+        assert!(FilterLoad::parse(&mut vec![0x02, 0x00].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_filter_load() {
+        let filter_load = FilterLoad::new(BloomFilter::new(1, 0.01, 0, BLOOM_UPDATE_ALL));
+        assert_eq!(filter_load.command(), Command::FilterLoad);
+    }
+}
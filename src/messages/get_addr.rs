@@ -0,0 +1,43 @@
+use crate::{impl_bitcoin_codec, message::Command};
+
+#[derive(Debug)]
+/// GetAddr es un mensaje vacio con el que se le piden a un peer direcciones de otros nodos de la red.
+/// El peer responde con uno o varios mensajes addr o addrv2.
+pub struct GetAddr {}
+
+impl GetAddr {
+    /// Crea un nuevo mensaje getaddr.
+    pub fn new() -> Self {
+        GetAddr {}
+    }
+}
+
+impl Default for GetAddr {
+    fn default() -> Self {
+        GetAddr::new()
+    }
+}
+
+impl_bitcoin_codec!(GetAddr, Command::GetAddr);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn serialize_get_addr() {
+        assert_eq!(GetAddr::new().serialize(), vec![]);
+    }
+
+    #[test]
+    fn parse_invalid_get_addr() {
+        assert!(GetAddr::parse(&mut vec![0x00].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_get_addr() {
+        assert_eq!(GetAddr::new().command(), Command::GetAddr);
+    }
+}
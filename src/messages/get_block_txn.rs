@@ -0,0 +1,106 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use super::compact_block::{decode_differential_indexes, encode_differential_indexes};
+
+use crate::{
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::hash256::Hash256,
+};
+
+#[derive(Debug, PartialEq)]
+/// GetBlockTxn es el mensaje que se envia para pedir las transacciones que faltan
+/// para reconstruir un compact block (BIP 152).
+/// Los campos son:
+/// - block_hash: Hash del bloque.
+/// - indexes: Indices absolutos de las transacciones pedidas dentro del bloque.
+pub struct GetBlockTxn {
+    pub block_hash: Hash256,
+    pub indexes: Vec<u64>,
+}
+
+impl GetBlockTxn {
+    /// Crea un nuevo mensaje getblocktxn.
+    /// Los indices se ordenan y se eliminan los repetidos, ya que se codifican de forma diferencial.
+    pub fn new(block_hash: Hash256, mut indexes: Vec<u64>) -> Self {
+        indexes.sort_unstable();
+        indexes.dedup();
+        Self {
+            block_hash,
+            indexes,
+        }
+    }
+}
+
+/// Implementa el trait Message para el mensaje getblocktxn.
+/// Permite serializar, parsear y obtener el comando
+impl Message for GetBlockTxn {
+    const COMMAND: Command = Command::GetBlockTxn;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.block_hash.as_bytes())?;
+        self.indexes.len().write_varint(writer)?;
+        let differential = encode_differential_indexes(&self.indexes)
+            .map_err(|_| io::Error::from(ErrorKind::InvalidInput))?;
+        for diff in differential {
+            (diff as usize).write_varint(writer)?;
+        }
+        Ok(())
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let block_hash = parser.extract_hash32()?.into();
+        let count = parser.extract_varint()? as usize;
+        let mut differential = vec![];
+        for _ in 0..count {
+            differential.push(parser.extract_varint()?);
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self {
+            block_hash,
+            indexes: decode_differential_indexes(&differential)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_block_txn_serialize_and_parse() {
+        let get_block_txn = GetBlockTxn::new(Hash256::new([7; 32]), vec![1, 2, 10]);
+        let serialized = get_block_txn.serialize();
+
+        let mut expected = vec![7; 32];
+        expected.extend(vec![3, 1, 0, 7]);
+        assert_eq!(serialized, expected);
+
+        let parsed = GetBlockTxn::parse(&mut serialized.as_slice()).unwrap();
+        assert_eq!(parsed, get_block_txn);
+    }
+
+    #[test]
+    fn get_block_txn_with_unsorted_indexes() {
+        let get_block_txn = GetBlockTxn::new(Hash256::new([7; 32]), vec![10, 1, 2, 10]);
+        assert_eq!(get_block_txn.indexes, vec![1, 2, 10]);
+
+        let parsed = GetBlockTxn::parse(&mut get_block_txn.serialize().as_slice()).unwrap();
+        assert_eq!(parsed, get_block_txn);
+    }
+
+    #[test]
+    fn parse_invalid_get_block_txn() {
+        assert!(GetBlockTxn::parse(&mut vec![1; 20].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_get_block_txn() {
+        let get_block_txn = GetBlockTxn::new(Hash256::ZERO, vec![]);
+        assert_eq!(get_block_txn.command(), Command::GetBlockTxn);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{impl_bitcoin_codec, message::Command, structs::hash256::Hash256};
+
+#[derive(Debug, PartialEq)]
+/// GetCFilters es el mensaje que solicita los filtros compactos de un rango de bloques (BIP 157).
+/// Los elementos son:
+/// - filter_type: Tipo de filtro solicitado.
+/// - start_height: Altura del primer bloque del rango.
+/// - stop_hash: Hash del ultimo bloque del rango.
+pub struct GetCFilters {
+    pub filter_type: u8,
+    pub start_height: u32,
+    pub stop_hash: Hash256,
+}
+
+impl GetCFilters {
+    /// Crea un nuevo mensaje getcfilters.
+    pub fn new(filter_type: u8, start_height: u32, stop_hash: Hash256) -> Self {
+        Self {
+            filter_type,
+            start_height,
+            stop_hash,
+        }
+    }
+}
+
+impl_bitcoin_codec!(
+    GetCFilters,
+    Command::GetCFilters,
+    filter_type,
+    start_height,
+    stop_hash
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn get_cfilters_serialize_and_parse() {
+        let get_cfilters = GetCFilters::new(0, 1000, Hash256::new([5; 32]));
+        let serialized = get_cfilters.serialize();
+        assert_eq!(serialized[0..5], [0, 0xe8, 0x03, 0, 0]);
+        assert_eq!(
+            GetCFilters::parse(&mut serialized.as_slice()).unwrap(),
+            get_cfilters
+        );
+    }
+
+    #[test]
+    fn parse_invalid_get_cfilters() {
+        assert!(GetCFilters::parse(&mut vec![0; 36].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_get_cfilters() {
+        let get_cfilters = GetCFilters::new(0, 0, Hash256::ZERO);
+        assert_eq!(get_cfilters.command(), Command::GetCFilters);
+    }
+}
@@ -1,7 +1,15 @@
-use crate::{message::Message, structs::inventory::Inventory};
+use std::io::{self, Read, Write};
+
+use crate::{
+    message::{Command, Message},
+    structs::inventory::Inventory,
+};
 
 use super::inv::Inv;
 
+/// Cantidad maxima de inventarios en un mensaje 'getdata'.
+pub const MAX_GETDATA_SIZE: usize = 50000;
+
 /// Esta estructura representa al mensaje 'getdata' de Bitcoin, el cual se utiliza para pedirle a un nodo que nos envie un inventario de bloques o transacciones
 pub struct GetData {
     inv: Inv,
@@ -14,6 +22,15 @@ impl GetData {
         Self { inv }
     }
 
+    /// Esta funcion se encarga de crear los mensajes 'getdata' necesarios para pedir todos los inventarios,
+    /// con hasta MAX_GETDATA_SIZE inventarios cada uno
+    pub fn batches(inventories: Vec<Inventory>) -> Vec<Self> {
+        inventories
+            .chunks(MAX_GETDATA_SIZE)
+            .map(|chunk| Self::new(chunk.to_vec()))
+            .collect()
+    }
+
     /// Esta funcion se encarga de devolver el vector de inventarios del mensaje 'getdata'
     pub fn get_inventories(&self) -> &Vec<Inventory> {
         &self.inv.inventories
@@ -23,17 +40,15 @@ impl GetData {
 /// Implementa el trait Message para el mensaje 'getdata'
 /// Permite serializar, parsear y obtener el comando
 impl Message for GetData {
-    fn serialize(&self) -> Vec<u8> {
-        self.inv.serialize()
-    }
+    const COMMAND: Command = Command::GetData;
 
-    fn get_command(&self) -> String {
-        String::from("getdata")
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.inv.serialize_into(writer)
     }
 
-    fn parse(buffer: Vec<u8>) -> Result<Self, crate::error::CustomError> {
+    fn parse(reader: &mut impl Read) -> Result<Self, crate::error::CustomError> {
         Ok(Self {
-            inv: Inv::parse(buffer)?,
+            inv: Inv::parse(reader)?,
         })
     }
 }
@@ -42,22 +57,27 @@ impl Message for GetData {
 
 mod tests {
 
-    use crate::structs::inventory::{Inventory, InventoryType};
+    use crate::structs::{
+        hash256::Hash256,
+        inventory::{Inventory, InventoryType},
+    };
 
     use super::*;
 
     #[test]
     fn serialize_get_data() {
+        let mut hash = [0; 32];
+        hash[30] = 0xab;
+        hash[31] = 0xcd;
         let inv = Inv::new(vec![Inventory::new(
             InventoryType::Block,
-            vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xab, 0xcd],
+            Hash256::new(hash),
         )]);
         let get_data = GetData { inv };
         let serialized_get_data = get_data.serialize();
-        assert_eq!(
-            serialized_get_data,
-            vec![1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xab, 0xcd,]
-        );
+        let mut expected = vec![1, 2, 0, 0, 0];
+        expected.extend(hash);
+        assert_eq!(serialized_get_data, expected);
     }
 
     #[test]
@@ -66,22 +86,34 @@ mod tests {
             1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xab, 0xcd, 4, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0,
             0, 0, 0xef, 0xaa, 3, 12, 7, 0, 7, 8,
         ];
-        let get_data = GetData::parse(buffer).unwrap();
+        let get_data = GetData::parse(&mut buffer.as_slice()).unwrap();
         let inventories = get_data.get_inventories();
         assert_eq!(inventories.len(), 1);
         assert_eq!(inventories[0].inventory_type, InventoryType::Block);
         assert_eq!(
             inventories[0].hash,
-            vec![
+            Hash256::new([
                 0, 0, 0, 0, 0, 0, 0, 0, 0xab, 0xcd, 4, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0xef,
                 0xaa, 3, 12, 7, 0, 7, 8,
-            ]
+            ])
         );
     }
 
     #[test]
-    fn get_command_get_data() {
+    fn get_data_batches() {
+        let inventories: Vec<Inventory> = (0..MAX_GETDATA_SIZE + 1)
+            .map(|i| Inventory::new(InventoryType::Tx, Hash256::hash(&(i as u32).to_le_bytes())))
+            .collect();
+        let batches = GetData::batches(inventories);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].get_inventories().len(), MAX_GETDATA_SIZE);
+        assert_eq!(batches[1].get_inventories().len(), 1);
+        assert!(GetData::batches(vec![]).is_empty());
+    }
+
+    #[test]
+    fn command_get_data() {
         let get_data = GetData::new(vec![]);
-        assert_eq!(get_data.get_command(), "getdata");
+        assert_eq!(get_data.command(), Command::GetData);
     }
 }
@@ -1,7 +1,10 @@
+use std::io::{self, Read, Write};
+
 use crate::{
-    error::CustomError,
-    message::Message,
-    parser::{BufferParser, VarIntSerialize},
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::hash256::Hash256,
 };
 
 #[derive(PartialEq, Debug)]
@@ -9,13 +12,13 @@ use crate::{
 /// Esta estructura representa el mensaje 'getheaders' de Bitcoin, el cual se utiliza para pedirle a un nodo que nos envie los headers de los bloques que tiene a partir de un bloque especifico.
 pub struct GetHeaders {
     pub version: i32,
-    pub block_locator_hashes: Vec<Vec<u8>>,
-    pub hash_stop: Vec<u8>,
+    pub block_locator_hashes: Vec<Hash256>,
+    pub hash_stop: Hash256,
 }
 
 impl GetHeaders {
     /// Esta funcion se encarga de crear un nuevo mensaje 'getheaders' con la version, el block locator hashes y el hash stop que se reciben por parametro
-    pub fn new(version: i32, block_locator_hashes: Vec<Vec<u8>>, hash_stop: Vec<u8>) -> Self {
+    pub fn new(version: i32, block_locator_hashes: Vec<Hash256>, hash_stop: Hash256) -> Self {
         GetHeaders {
             version,
             block_locator_hashes,
@@ -27,41 +30,32 @@ impl GetHeaders {
 /// Implementa el trait Message para el mensaje 'getheaders'.
 /// Permite serializar, parsear y obtener el comando
 impl Message for GetHeaders {
-    fn get_command(&self) -> String {
-        String::from("getheaders")
-    }
+    const COMMAND: Command = Command::GetHeaders;
 
-    fn serialize(&self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = vec![];
-        buffer.extend(&self.version.to_le_bytes());
-        buffer.extend(self.block_locator_hashes.len().to_varint_bytes());
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.version.to_le_bytes())?;
+        self.block_locator_hashes.len().write_varint(writer)?;
         for hash in &self.block_locator_hashes {
-            buffer.extend(hash);
+            writer.write_all(hash.as_bytes())?;
         }
-        buffer.extend(&self.hash_stop);
-        buffer
+        writer.write_all(self.hash_stop.as_bytes())
     }
 
-    fn parse(buffer: Vec<u8>) -> Result<Self, CustomError> {
-        let mut parser = BufferParser::new(buffer);
-
-        if parser.len() < 37 {
-            return Err(CustomError::SerializedBufferIsInvalid);
-        }
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
         let version = parser.extract_i32()?;
         let hash_count = parser.extract_varint()?;
 
-        let mut block_locator_hashes: Vec<Vec<u8>> = vec![];
-
-        while parser.len() > 32 {
-            let hash = parser.extract_buffer(32)?.to_vec();
+        let mut block_locator_hashes: Vec<Hash256> = vec![];
+        for _ in 0..hash_count {
+            let hash = parser.extract_hash32()?.into();
             block_locator_hashes.push(hash);
         }
 
-        let hash_stop = parser.extract_buffer(32)?.to_vec();
+        let hash_stop = parser.extract_hash32()?.into();
 
-        if !parser.is_empty() || block_locator_hashes.len() != hash_count as usize {
-            return Err(CustomError::SerializedBufferIsInvalid);
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
         }
 
         Ok(GetHeaders {
@@ -75,17 +69,19 @@ impl Message for GetHeaders {
 #[cfg(test)]
 mod tests {
 
-    use crate::peer::GENESIS;
+    use crate::network::Network;
 
     use super::*;
 
     #[test]
     fn get_headers_serialize() {
-        let mut empty_stop_hash: Vec<u8> = vec![];
-        empty_stop_hash.resize(32, 0);
-        let get_headers = GetHeaders::new(70015, [GENESIS.to_vec()].to_vec(), empty_stop_hash);
+        let get_headers = GetHeaders::new(
+            70015,
+            [Network::Testnet.genesis_hash()].to_vec(),
+            Hash256::ZERO,
+        );
         let serialized_getheaders = get_headers.serialize();
-        let parsed_getheaders = GetHeaders::parse(serialized_getheaders).unwrap();
+        let parsed_getheaders = GetHeaders::parse(&mut serialized_getheaders.as_slice()).unwrap();
         assert_eq!(get_headers, parsed_getheaders);
     }
 
@@ -98,17 +94,17 @@ mod tests {
             0,
         ];
 
-        let parsed_getheaders = GetHeaders::parse(serialized_getheaders).unwrap();
+        let parsed_getheaders = GetHeaders::parse(&mut serialized_getheaders.as_slice()).unwrap();
         assert_eq!(parsed_getheaders.version, 70015);
         assert_eq!(parsed_getheaders.block_locator_hashes.len(), 1);
         assert_eq!(
             parsed_getheaders.block_locator_hashes[0],
-            [
+            Hash256::new([
                 155, 77, 153, 101, 77, 212, 76, 182, 137, 41, 103, 109, 128, 43, 106, 32, 200, 118,
                 162, 103, 247, 127, 103, 118, 167, 48, 41, 155, 158, 132, 88, 193
-            ]
+            ])
         );
-        assert_eq!(parsed_getheaders.hash_stop, vec![0; 32]);
+        assert_eq!(parsed_getheaders.hash_stop, Hash256::ZERO);
     }
 
     #[test]
@@ -120,7 +116,7 @@ mod tests {
             0, 1, 5, 5, 5, 4,
         ];
 
-        let parsed_getheaders = GetHeaders::parse(serialized_getheaders);
+        let parsed_getheaders = GetHeaders::parse(&mut serialized_getheaders.as_slice());
         assert!(parsed_getheaders.is_err());
     }
 
@@ -131,16 +127,16 @@ mod tests {
             0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
 
-        let parsed_getheaders = GetHeaders::parse(serialized_getheaders).unwrap();
+        let parsed_getheaders = GetHeaders::parse(&mut serialized_getheaders.as_slice()).unwrap();
         assert_eq!(parsed_getheaders.version, 70015);
         assert_eq!(parsed_getheaders.block_locator_hashes.len(), 0);
-        assert_eq!(parsed_getheaders.hash_stop, vec![0; 32]);
+        assert_eq!(parsed_getheaders.hash_stop, Hash256::ZERO);
     }
 
     #[test]
     fn parse_invalid_getheaders_with_short_buffer() {
         let invalid_getheaders = vec![0; 36];
-        let parsed_getheaders = GetHeaders::parse(invalid_getheaders);
+        let parsed_getheaders = GetHeaders::parse(&mut invalid_getheaders.as_slice());
         assert!(parsed_getheaders.is_err());
     }
 
@@ -150,13 +146,13 @@ mod tests {
             127, 17, 1, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
-        let parsed_getheaders = GetHeaders::parse(invalid_getheaders);
+        let parsed_getheaders = GetHeaders::parse(&mut invalid_getheaders.as_slice());
         assert!(parsed_getheaders.is_err());
     }
 
     #[test]
-    fn get_command_getheaders() {
-        let getheaders = GetHeaders::new(70012, vec![], vec![]);
-        assert_eq!(getheaders.get_command(), "getheaders");
+    fn command_getheaders() {
+        let getheaders = GetHeaders::new(70012, vec![], Hash256::ZERO);
+        assert_eq!(getheaders.command(), Command::GetHeaders);
     }
 }
@@ -1,10 +1,17 @@
+use std::io::{self, Read, Write};
+
 use crate::{
-    error::CustomError,
-    message::Message,
-    parser::{BufferParser, VarIntSerialize},
-    structs::block_header::BlockHeader,
+    block_locator::locator_indexes,
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    network::Network,
+    parser::{BufferParser, Parser, StreamParser, VarIntSerialize},
+    structs::{block_header::BlockHeader, hash256::Hash256},
 };
 
+/// Tamaño de cada header en el mensaje: los 80 bytes del header y la cantidad de transacciones, que siempre es 0.
+const HEADER_SIZE: usize = 81;
+
 #[derive(Debug, Clone)]
 
 ///Esta estructura es la que se encarga de almacenar los headers de los bloques, esto lo hace en un vector de 'BlockHeaders'
@@ -17,6 +24,37 @@ impl Headers {
     pub fn new() -> Self {
         Headers { headers: vec![] }
     }
+
+    /// Devuelve la cantidad de headers de un mensaje serializado, sin parsearlos.
+    /// Devuelve CustomError si:
+    /// - El varint inicial no esta serializado con la menor cantidad de bytes posible.
+    /// - El largo del buffer no coincide con la cantidad de headers.
+    pub fn count(buffer: &[u8]) -> Result<usize, CustomError> {
+        let mut parser = BufferParser::new(buffer);
+
+        let header_count = extract_header_count(&mut parser)?;
+        if header_count.checked_mul(HEADER_SIZE) != Some(parser.len()) {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(header_count)
+    }
+
+    /// Construye el block locator de los headers de un mensaje serializado, parseando solo los headers que incluye.
+    /// Permite pedir los headers siguientes sin esperar a que se validen todos los recibidos.
+    /// Devuelve CustomError si el mensaje no es valido o alguno de esos headers no cumple la proof of work.
+    pub fn block_locator(buffer: &[u8]) -> Result<Vec<Hash256>, CustomError> {
+        let header_count = Self::count(buffer)?;
+        let first_header = buffer.len() - header_count * HEADER_SIZE;
+
+        let mut locator = vec![];
+        for index in locator_indexes(header_count) {
+            let start = first_header + index * HEADER_SIZE;
+            let header = BlockHeader::parse(buffer[start..start + 80].to_vec())?;
+            locator.push(*header.hash());
+        }
+        locator.push(Network::current().genesis_hash());
+        Ok(locator)
+    }
 }
 
 impl Default for Headers {
@@ -28,66 +66,133 @@ impl Default for Headers {
 /// Implementa el trair Message para Headers
 /// Permite serializar, parsear y obtener el comando
 impl Message for Headers {
-    fn get_command(&self) -> String {
-        String::from("headers")
-    }
+    const COMMAND: Command = Command::Headers;
 
-    fn serialize(&self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = vec![];
-        buffer.extend(self.headers.len().to_varint_bytes());
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.headers.len().write_varint(writer)?;
         for header in &self.headers {
-            buffer.extend(&header.serialize());
-            buffer.extend(0_u8.to_le_bytes());
+            writer.write_all(&header.serialize())?;
+            writer.write_all(&0_u8.to_le_bytes())?;
         }
-        buffer
+        Ok(())
     }
 
-    fn parse(buffer: Vec<u8>) -> Result<Self, CustomError> {
-        let mut parser = BufferParser::new(buffer);
-
-        let header_count = parser.extract_varint()?;
-        if parser.len() % 81 != 0 {
-            return Err(CustomError::SerializedBufferIsInvalid);
-        }
+    /// Parsea la cantidad de headers indicada por el varint inicial.
+    /// Devuelve CustomError si:
+    /// - El varint no esta serializado con la menor cantidad de bytes posible.
+    /// - El largo del buffer no coincide con la cantidad de headers.
+    /// - Algun header no es valido o su cantidad de transacciones no es 0.
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let header_count = extract_header_count(&mut parser)?;
 
         let mut headers = vec![];
-        while parser.len() >= 81 {
-            headers.push(BlockHeader::parse(parser.extract_buffer(80)?.to_vec())?);
-            parser.extract_buffer(1)?;
+        for _ in 0..header_count {
+            headers.push(BlockHeader::parse(parser.extract_bytes(80)?)?);
+            // los headers se envian como bloques sin transacciones
+            if parser.extract_u8()? != 0 {
+                return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+            }
         }
-
-        if header_count != headers.len() as u64 {
-            return Err(CustomError::SerializedBufferIsInvalid);
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
         }
 
         Ok(Headers { headers })
     }
 }
 
+/// Extrae la cantidad de headers del varint inicial del mensaje.
+/// Devuelve CustomError si el varint no esta serializado con la menor cantidad de bytes posible,
+/// es decir si su primer byte no es el de la serializacion de la cantidad.
+fn extract_header_count(parser: &mut impl Parser) -> Result<usize, CustomError> {
+    let prefix = parser.extract_u8()?;
+    let header_count = match prefix {
+        0xFF => parser.extract_u64()? as usize,
+        0xFE => parser.extract_u32()? as usize,
+        0xFD => parser.extract_u16()? as usize,
+        count => count as usize,
+    };
+    if header_count.to_varint_bytes()[0] != prefix {
+        return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+    }
+    Ok(header_count)
+}
+
 #[cfg(test)]
 
 mod tests {
 
+    use crate::block_locator::block_locator;
+
     use super::*;
 
-    #[test]
-    fn parse_and_serialize_headers() {
-        let buffer = vec![
+    fn valid_buffer() -> Vec<u8> {
+        vec![
             1, 0, 0, 128, 32, 169, 255, 173, 21, 40, 44, 123, 115, 129, 193, 143, 57, 71, 116, 199,
             75, 244, 113, 169, 45, 227, 42, 180, 111, 0, 0, 0, 0, 0, 0, 0, 0, 109, 105, 250, 106,
             92, 126, 17, 171, 97, 243, 124, 194, 172, 252, 249, 166, 202, 8, 231, 136, 21, 107,
             106, 136, 64, 241, 195, 82, 179, 236, 159, 63, 155, 22, 96, 100, 105, 90, 32, 25, 11,
             42, 241, 166, 0,
-        ];
+        ]
+    }
 
+    #[test]
+    fn parse_and_serialize_headers() {
+        let buffer = valid_buffer();
         let buffer_clone = buffer.clone();
 
-        let headers = Headers::parse(buffer).unwrap();
+        let headers = Headers::parse(&mut buffer.as_slice()).unwrap();
         let serialized_headers = headers.serialize();
 
         assert_eq!(buffer_clone, serialized_headers);
     }
 
+    #[test]
+    fn parse_honors_the_declared_count() {
+        let headers = Headers::parse(&mut valid_buffer().as_slice()).unwrap();
+
+        // dos headers declarados y uno enviado
+        let mut buffer = headers.serialize();
+        buffer[0] = 2;
+        assert!(Headers::parse(&mut buffer.as_slice()).is_err());
+
+        // un header declarado y dos enviados
+        let mut buffer = headers.serialize();
+        buffer.extend(&headers.serialize()[1..]);
+        assert!(Headers::parse(&mut buffer.as_slice()).is_err());
+
+        // la cantidad de transacciones del header no es 0
+        let mut buffer = headers.serialize();
+        buffer[81] = 1;
+        assert!(Headers::parse(&mut buffer.as_slice()).is_err());
+
+        // varint no canonico, su serializacion no seria igual al buffer
+        let mut buffer = vec![0xfd, 1, 0];
+        buffer.extend(&headers.serialize()[1..]);
+        assert!(Headers::parse(&mut buffer.as_slice()).is_err());
+
+        assert!(Headers::parse(&mut vec![0].as_slice())
+            .unwrap()
+            .headers
+            .is_empty());
+    }
+
+    #[test]
+    fn block_locator_of_a_serialized_message() {
+        let headers = Headers::parse(&mut valid_buffer().as_slice()).unwrap();
+        let mut repeated = Headers::new();
+        repeated.headers = vec![headers.headers[0].clone(); 30];
+        let buffer = repeated.serialize();
+
+        assert_eq!(Headers::count(&buffer).unwrap(), 30);
+        assert_eq!(
+            Headers::block_locator(&buffer).unwrap(),
+            block_locator(&repeated.headers)
+        );
+        assert!(Headers::count(&buffer[..buffer.len() - 1]).is_err());
+    }
+
     #[test]
     fn invalid_header() {
         let buffer = vec![
@@ -96,7 +201,7 @@ mod tests {
             92, 126, 17, 171, 9,
         ];
 
-        let headers = Headers::parse(buffer);
+        let headers = Headers::parse(&mut buffer.as_slice());
 
         assert!(headers.is_err());
     }
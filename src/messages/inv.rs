@@ -1,5 +1,9 @@
+use std::io::{self, Read, Write};
+
 use crate::{
-    error::CustomError, message::Message, parser::BufferParser, parser::VarIntSerialize,
+    error::{CustomError, ParseError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
     structs::inventory::Inventory,
 };
 
@@ -19,31 +23,27 @@ impl Inv {
 /// Implementa el trait Message para el mensaje inv.
 /// Permite serializar, parsear y obtener el comando
 impl Message for Inv {
-    fn serialize(&self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = vec![];
-        buffer.extend(self.inventories.len().to_varint_bytes());
+    const COMMAND: Command = Command::Inv;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.inventories.len().write_varint(writer)?;
         for inventory in &self.inventories {
-            buffer.extend(inventory.serialize());
+            writer.write_all(&inventory.serialize())?;
         }
-        buffer
+        Ok(())
     }
 
-    fn get_command(&self) -> String {
-        String::from("inv")
-    }
-
-    fn parse(buffer: Vec<u8>) -> Result<Self, crate::error::CustomError> {
-        let mut parser = BufferParser::new(buffer);
+    fn parse(reader: &mut impl Read) -> Result<Self, crate::error::CustomError> {
+        let mut parser = StreamParser::new(reader);
 
         let count = parser.extract_varint()? as usize;
 
-        if parser.len() % 36 != 0 {
-            return Err(CustomError::SerializedBufferIsInvalid);
-        }
-
         let mut inventories = vec![];
         for _i in 0..count {
-            inventories.push(Inventory::parse(parser.extract_buffer(36)?.to_vec())?);
+            inventories.push(Inventory::parse(parser.extract_bytes(36)?)?);
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
         }
         Ok(Self { inventories })
     }
@@ -53,7 +53,7 @@ impl Message for Inv {
 
 mod tests {
 
-    use crate::structs::inventory::InventoryType;
+    use crate::structs::{hash256::Hash256, inventory::InventoryType};
 
     use super::*;
 
@@ -61,15 +61,14 @@ mod tests {
     fn inv_serialize_and_parse() {
         let inventory = Inventory::new(
             InventoryType::Block,
-            [
+            Hash256::new([
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
-            ]
-            .to_vec(),
+            ]),
         );
         let inv = Inv::new(vec![inventory]);
         let buffer = inv.serialize();
-        let parsed_inv = Inv::parse(buffer).unwrap();
+        let parsed_inv = Inv::parse(&mut buffer.as_slice()).unwrap();
         assert_eq!(inv, parsed_inv);
     }
 
@@ -77,22 +76,21 @@ mod tests {
     fn inv_invalid_buffer() {
         let inventory = Inventory {
             inventory_type: InventoryType::Block,
-            hash: [
+            hash: Hash256::new([
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
-                124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220, 100, 200, 129,
-                233, 45, 56, 82, 56, 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 46,
-            ]
-            .to_vec(),
+                124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
+            ]),
         };
         let inv = Inv::new(vec![inventory]);
-        let buffer = inv.serialize();
-        let parsed_inv = Inv::parse(buffer);
+        let mut buffer = inv.serialize();
+        buffer.extend([100, 200, 129, 233, 45, 56, 82, 56]);
+        let parsed_inv = Inv::parse(&mut buffer.as_slice());
         assert!(parsed_inv.is_err());
     }
 
     #[test]
-    fn get_command_inv() {
+    fn command_inv() {
         let inv = Inv::new(vec![]);
-        assert_eq!(inv.get_command(), "inv");
+        assert_eq!(inv.command(), Command::Inv);
     }
 }
@@ -0,0 +1,237 @@
+use std::io::{self, Read, Write};
+
+use super::{block::Block, transaction::Transaction};
+
+use crate::{
+    error::{CustomError, ParseError},
+    merkle_proof::MerkleProof,
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::{block_header::BlockHeader, hash256::Hash256},
+};
+
+#[derive(Debug)]
+/// MerkleBlock es la respuesta a un getdata de tipo FilteredBlock (BIP 37).
+/// Contiene el header del bloque y un merkle tree parcial que prueba la inclusion de las transacciones que coinciden con el filtro.
+/// Los elementos son:
+/// - header: Header del bloque.
+/// - total_transactions: Cantidad total de transacciones del bloque.
+/// - hashes: Hashes del merkle tree parcial, recorrido en profundidad.
+/// - flags: Bits que indican como recorrer el merkle tree parcial.
+pub struct MerkleBlock {
+    pub header: BlockHeader,
+    pub total_transactions: u32,
+    pub hashes: Vec<Hash256>,
+    pub flags: Vec<u8>,
+}
+
+impl MerkleBlock {
+    /// Genera un merkle block a partir de un bloque y los hashes de las transacciones que coinciden con el filtro.
+    pub fn from_block(block: &Block, matched_tx_hashes: &[Hash256]) -> Self {
+        let tx_hashes: Vec<Hash256> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        let matches: Vec<bool> = tx_hashes
+            .iter()
+            .map(|hash| matched_tx_hashes.contains(hash))
+            .collect();
+
+        let proof = MerkleProof::new(&tx_hashes, &matches);
+        Self {
+            header: block.header.clone(),
+            total_transactions: proof.total_transactions,
+            hashes: proof.hashes,
+            flags: proof.flags,
+        }
+    }
+
+    /// Devuelve el merkle tree parcial del merkle block.
+    pub fn merkle_proof(&self) -> MerkleProof {
+        MerkleProof {
+            total_transactions: self.total_transactions,
+            hashes: self.hashes.clone(),
+            flags: self.flags.clone(),
+        }
+    }
+
+    /// Recorre el merkle tree parcial y devuelve los hashes de las transacciones incluidas.
+    /// Devuelve CustomError si el merkle root calculado no coincide con el del header.
+    pub fn matched_transactions(&self) -> Result<Vec<Hash256>, CustomError> {
+        self.merkle_proof()
+            .matched_transactions(&self.header.merkle_root)
+    }
+}
+
+/// FilteredBlock es un merkle block junto con las transacciones que coinciden con el filtro,
+/// que el peer envia en mensajes tx inmediatamente despues del merkleblock (BIP 37).
+/// Los elementos son:
+/// - merkle_block: MerkleBlock recibido.
+/// - matched: Hashes de las transacciones que coinciden con el filtro, segun el merkle tree parcial.
+/// - transactions: Transacciones del merkle block recibidas hasta el momento.
+#[derive(Debug)]
+pub struct FilteredBlock {
+    pub merkle_block: MerkleBlock,
+    pub matched: Vec<Hash256>,
+    pub transactions: Vec<Transaction>,
+}
+
+impl FilteredBlock {
+    /// Crea el bloque filtrado a partir de un merkle block, a la espera de sus transacciones.
+    /// Devuelve CustomError si el merkle tree parcial no corresponde al merkle root del header.
+    pub fn new(merkle_block: MerkleBlock) -> Result<Self, CustomError> {
+        let matched = merkle_block.matched_transactions()?;
+        Ok(Self {
+            merkle_block,
+            matched,
+            transactions: vec![],
+        })
+    }
+
+    /// Devuelve true si la transaccion coincide con el filtro y todavia no se recibio.
+    pub fn expects(&self, tx_hash: &Hash256) -> bool {
+        self.matched.contains(tx_hash) && !self.transactions.iter().any(|tx| tx.hash() == *tx_hash)
+    }
+
+    /// Agrega una transaccion del merkle block.
+    pub fn append_transaction(&mut self, transaction: Transaction) {
+        self.transactions.push(transaction);
+    }
+
+    /// Devuelve true si ya se recibieron todas las transacciones que coinciden con el filtro.
+    pub fn is_complete(&self) -> bool {
+        self.transactions.len() == self.matched.len()
+    }
+
+    /// Devuelve el hash del bloque.
+    pub fn hash(&self) -> &Hash256 {
+        self.merkle_block.header.hash()
+    }
+}
+
+/// Implementa el trait Message para el mensaje merkleblock.
+/// Permite serializar, parsear y obtener el comando
+impl Message for MerkleBlock {
+    const COMMAND: Command = Command::MerkleBlock;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.header.serialize())?;
+        writer.write_all(&self.total_transactions.to_le_bytes())?;
+        self.hashes.len().write_varint(writer)?;
+        for hash in &self.hashes {
+            writer.write_all(hash.as_bytes())?;
+        }
+        self.flags.len().write_varint(writer)?;
+        writer.write_all(&self.flags)
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let header = BlockHeader::parse(parser.extract_bytes(80)?)?;
+        let total_transactions = parser.extract_u32()?;
+
+        let hash_count = parser.extract_varint()? as usize;
+        let mut hashes = vec![];
+        for _ in 0..hash_count {
+            hashes.push(parser.extract_hash32()?.into());
+        }
+
+        let flag_bytes = parser.extract_varint()? as usize;
+        let flags = parser.extract_bytes(flag_bytes)?;
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+
+        Ok(Self {
+            header,
+            total_transactions,
+            hashes,
+            flags,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::utils::open_new_file;
+
+    use super::*;
+
+    fn test_block() -> Block {
+        let mut file = open_new_file("tests/blocks/test_block.bin".to_string(), true).unwrap();
+        let mut buffer = vec![];
+        file.read_to_end(&mut buffer).unwrap();
+        Block::parse(&mut buffer.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn merkle_block_matches_transactions() {
+        let block = test_block();
+        let matched = vec![block.transactions[6].hash(), block.transactions[19].hash()];
+
+        let merkle_block = MerkleBlock::from_block(&block, &matched);
+        assert_eq!(merkle_block.matched_transactions().unwrap(), matched);
+    }
+
+    #[test]
+    fn merkle_block_without_matches() {
+        let block = test_block();
+        let merkle_block = MerkleBlock::from_block(&block, &[]);
+
+        assert_eq!(merkle_block.hashes.len(), 1);
+        assert!(merkle_block.matched_transactions().unwrap().is_empty());
+    }
+
+    #[test]
+    fn merkle_block_serialize_and_parse() {
+        let block = test_block();
+        let matched = vec![block.transactions[3].hash()];
+        let merkle_block = MerkleBlock::from_block(&block, &matched);
+
+        let parsed = MerkleBlock::parse(&mut merkle_block.serialize().as_slice()).unwrap();
+        assert_eq!(parsed.total_transactions, 20);
+        assert_eq!(parsed.hashes, merkle_block.hashes);
+        assert_eq!(parsed.flags, merkle_block.flags);
+        assert_eq!(parsed.matched_transactions().unwrap(), matched);
+    }
+
+    #[test]
+    fn merkle_block_with_invalid_hash() {
+        let block = test_block();
+        let mut merkle_block = MerkleBlock::from_block(&block, &[block.transactions[1].hash()]);
+        merkle_block.hashes[0] = Hash256::ZERO;
+        assert!(merkle_block.matched_transactions().is_err());
+    }
+
+    #[test]
+    fn filtered_block_collects_the_matched_transactions() {
+        let block = test_block();
+        let matched = vec![block.transactions[6].hash(), block.transactions[19].hash()];
+        let merkle_block = MerkleBlock::from_block(&block, &matched);
+
+        let mut filtered_block = FilteredBlock::new(merkle_block).unwrap();
+        assert!(!filtered_block.expects(&block.transactions[1].hash()));
+        assert!(filtered_block.expects(&matched[0]));
+
+        filtered_block.append_transaction(block.transactions[6].clone());
+        assert!(!filtered_block.expects(&matched[0]));
+        assert!(!filtered_block.is_complete());
+
+        filtered_block.append_transaction(block.transactions[19].clone());
+        assert!(filtered_block.is_complete());
+        assert_eq!(filtered_block.hash(), block.header.hash());
+    }
+
+    #[test]
+    fn filtered_block_with_invalid_merkle_tree() {
+        let block = test_block();
+        let mut merkle_block = MerkleBlock::from_block(&block, &[block.transactions[1].hash()]);
+        merkle_block.hashes[0] = Hash256::ZERO;
+        assert!(FilteredBlock::new(merkle_block).is_err());
+    }
+
+    #[test]
+    fn command_merkle_block() {
+        let merkle_block = MerkleBlock::from_block(&test_block(), &[]);
+        assert_eq!(merkle_block.command(), Command::MerkleBlock);
+    }
+}
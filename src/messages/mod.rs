@@ -1,11 +1,29 @@
+pub mod addr;
 pub mod block;
+pub mod block_txn;
+pub mod cfheaders;
+pub mod cfilter;
+pub mod compact_block;
+pub mod fee_filter;
+pub mod filter_add;
+pub mod filter_clear;
+pub mod filter_load;
+pub mod get_addr;
+pub mod get_block_txn;
+pub mod get_cfheaders;
+pub mod get_cfilters;
 pub mod get_data;
 pub mod get_headers;
 pub mod headers;
 pub mod inv;
+pub mod merkle_block;
 pub mod not_found;
 pub mod ping_pong;
+pub mod reject;
+pub mod send_addr_v2;
+pub mod send_cmpct;
 pub mod send_headers;
 pub mod transaction;
 pub mod ver_ack;
 pub mod version;
+pub mod wtxid_relay;
@@ -1,4 +1,9 @@
-use crate::{message::Message, structs::inventory::Inventory};
+use std::io::{self, Read, Write};
+
+use crate::{
+    message::{Command, Message},
+    structs::inventory::Inventory,
+};
 
 use super::inv::Inv;
 
@@ -23,17 +28,15 @@ impl NotFound {
 /// Implementa el trait Message para el mensaje 'notfound'
 /// Permite serializar, parsear y obtener el comando
 impl Message for NotFound {
-    fn serialize(&self) -> Vec<u8> {
-        self.inv.serialize()
-    }
+    const COMMAND: Command = Command::NotFound;
 
-    fn get_command(&self) -> String {
-        String::from("notfound")
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.inv.serialize_into(writer)
     }
 
-    fn parse(buffer: Vec<u8>) -> Result<Self, crate::error::CustomError> {
+    fn parse(reader: &mut impl Read) -> Result<Self, crate::error::CustomError> {
         Ok(Self {
-            inv: Inv::parse(buffer)?,
+            inv: Inv::parse(reader)?,
         })
     }
 }
@@ -42,22 +45,27 @@ impl Message for NotFound {
 
 mod tests {
 
-    use crate::structs::inventory::{Inventory, InventoryType};
+    use crate::structs::{
+        hash256::Hash256,
+        inventory::{Inventory, InventoryType},
+    };
 
     use super::*;
 
     #[test]
     fn serialize_not_found() {
+        let mut hash = [0; 32];
+        hash[30] = 0xab;
+        hash[31] = 0xcd;
         let inv = Inv::new(vec![Inventory::new(
             InventoryType::Block,
-            vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xab, 0xcd],
+            Hash256::new(hash),
         )]);
         let not_found = NotFound { inv };
         let serialized_not_found = not_found.serialize();
-        assert_eq!(
-            serialized_not_found,
-            vec![1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xab, 0xcd,]
-        );
+        let mut expected = vec![1, 2, 0, 0, 0];
+        expected.extend(hash);
+        assert_eq!(serialized_not_found, expected);
     }
 
     #[test]
@@ -66,22 +74,22 @@ mod tests {
             1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xab, 0xcd, 4, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0,
             0, 0, 0xef, 0xaa, 3, 12, 7, 0, 7, 8,
         ];
-        let not_found = NotFound::parse(buffer).unwrap();
+        let not_found = NotFound::parse(&mut buffer.as_slice()).unwrap();
         let inventories = not_found.get_inventories();
         assert_eq!(inventories.len(), 1);
         assert_eq!(inventories[0].inventory_type, InventoryType::Block);
         assert_eq!(
             inventories[0].hash,
-            vec![
+            Hash256::new([
                 0, 0, 0, 0, 0, 0, 0, 0, 0xab, 0xcd, 4, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0xef,
                 0xaa, 3, 12, 7, 0, 7, 8,
-            ]
+            ])
         );
     }
 
     #[test]
-    fn get_command_not_found() {
+    fn command_not_found() {
         let not_found = NotFound::new(vec![]);
-        assert_eq!(not_found.get_command(), "notfound");
+        assert_eq!(not_found.command(), Command::NotFound);
     }
 }
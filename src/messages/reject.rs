@@ -0,0 +1,113 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    error::CustomError,
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
+    structs::hash256::Hash256,
+};
+
+/// Codigo de rechazo por una transaccion invalida.
+pub const REJECT_INVALID: u8 = 0x10;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Reject es el mensaje que envia un peer al rechazar un mensaje recibido (BIP 61).
+/// Los elementos son:
+/// - message: Comando del mensaje rechazado.
+/// - ccode: Codigo del motivo del rechazo.
+/// - reason: Descripcion del motivo del rechazo.
+/// - data: Datos extra, para tx y block es el hash del elemento rechazado.
+pub struct Reject {
+    pub message: String,
+    pub ccode: u8,
+    pub reason: String,
+    pub data: Vec<u8>,
+}
+
+impl Reject {
+    /// Crea un nuevo mensaje reject.
+    pub fn new(message: String, ccode: u8, reason: String, data: Vec<u8>) -> Self {
+        Self {
+            message,
+            ccode,
+            reason,
+            data,
+        }
+    }
+
+    /// Devuelve el hash de la transaccion rechazada, si el mensaje rechazado es un tx.
+    pub fn rejected_tx_hash(&self) -> Option<Hash256> {
+        if self.message != "tx" {
+            return None;
+        }
+        Hash256::from_slice(&self.data).ok()
+    }
+}
+
+/// Implementa el trait Message para el mensaje reject.
+/// Permite serializar, parsear y obtener el comando
+impl Message for Reject {
+    const COMMAND: Command = Command::Reject;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.message.len().write_varint(writer)?;
+        writer.write_all(self.message.as_bytes())?;
+        writer.write_all(&[self.ccode])?;
+        self.reason.len().write_varint(writer)?;
+        writer.write_all(self.reason.as_bytes())?;
+        writer.write_all(&self.data)
+    }
+
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError> {
+        let mut parser = StreamParser::new(reader);
+        let message_len = parser.extract_varint()? as usize;
+        let message = parser.extract_string(message_len)?;
+        let ccode = parser.extract_u8()?;
+        let reason_len = parser.extract_varint()? as usize;
+        let reason = parser.extract_string(reason_len)?;
+        let data = parser.extract_remaining()?;
+
+        Ok(Self {
+            message,
+            ccode,
+            reason,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_serialize_and_parse() {
+        let reject = Reject::new(
+            String::from("tx"),
+            REJECT_INVALID,
+            String::from("bad-txns-inputs-missingorspent"),
+            vec![7; 32],
+        );
+        let parsed = Reject::parse(&mut reject.serialize().as_slice()).unwrap();
+        assert_eq!(parsed, reject);
+        assert_eq!(parsed.rejected_tx_hash(), Some(Hash256::new([7; 32])));
+    }
+
+    #[test]
+    fn reject_of_other_message() {
+        let reject = Reject::new(
+            String::from("version"),
+            0x11,
+            String::from("obsolete"),
+            vec![],
+        );
+        let parsed = Reject::parse(&mut reject.serialize().as_slice()).unwrap();
+        assert_eq!(parsed.rejected_tx_hash(), None);
+    }
+
+    #[test]
+    fn command_reject() {
+        let reject = Reject::new(String::from("tx"), REJECT_INVALID, String::new(), vec![]);
+        assert_eq!(reject.command(), Command::Reject);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{impl_bitcoin_codec, message::Command};
+
+#[derive(Debug)]
+/// SendAddrV2 es un mensaje vacio que se envia durante el handshake, antes del verack (BIP 155).
+/// Indica al peer que preferimos recibir las direcciones con mensajes addrv2 en lugar de addr.
+pub struct SendAddrV2 {}
+
+impl SendAddrV2 {
+    /// Crea un nuevo mensaje sendaddrv2.
+    pub fn new() -> Self {
+        SendAddrV2 {}
+    }
+}
+
+impl Default for SendAddrV2 {
+    fn default() -> Self {
+        SendAddrV2::new()
+    }
+}
+
+impl_bitcoin_codec!(SendAddrV2, Command::SendAddrV2);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn serialize_send_addr_v2() {
+        assert_eq!(SendAddrV2::new().serialize(), vec![]);
+    }
+
+    #[test]
+    fn parse_invalid_send_addr_v2() {
+        assert!(SendAddrV2::parse(&mut vec![0x00].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_send_addr_v2() {
+        assert_eq!(SendAddrV2::new().command(), Command::SendAddrV2);
+    }
+}
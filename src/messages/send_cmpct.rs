@@ -0,0 +1,52 @@
+use crate::{impl_bitcoin_codec, message::Command};
+
+/// Version de compact blocks soportada (BIP 152), la 2 calcula los short ids a partir del wtxid.
+pub const COMPACT_BLOCKS_VERSION: u64 = 2;
+/// Version de protocolo a partir de la cual los peers soportan compact blocks (BIP 152).
+pub const COMPACT_BLOCKS_PROTOCOL_VERSION: i32 = 70014;
+
+#[derive(Debug, PartialEq)]
+/// SendCmpct es el mensaje que se envia tras el handshake para indicar que se soportan compact blocks (BIP 152).
+/// Los campos son:
+/// - announce: Si es true, se le pide al peer que anuncie los bloques nuevos directamente con cmpctblock.
+/// - version: Version de compact blocks soportada.
+pub struct SendCmpct {
+    pub announce: bool,
+    pub version: u64,
+}
+
+impl SendCmpct {
+    /// Crea un nuevo mensaje sendcmpct.
+    pub fn new(announce: bool, version: u64) -> Self {
+        Self { announce, version }
+    }
+}
+
+impl_bitcoin_codec!(SendCmpct, Command::SendCmpct, announce, version);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn send_cmpct_serialize_and_parse() {
+        let send_cmpct = SendCmpct::new(false, COMPACT_BLOCKS_VERSION);
+        let serialized = send_cmpct.serialize();
+        assert_eq!(serialized, vec![0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        let parsed = SendCmpct::parse(&mut serialized.as_slice()).unwrap();
+        assert_eq!(parsed, send_cmpct);
+    }
+
+    #[test]
+    fn parse_invalid_send_cmpct() {
+        assert!(SendCmpct::parse(&mut vec![1, 1, 0].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_send_cmpct() {
+        let send_cmpct = SendCmpct::new(true, COMPACT_BLOCKS_VERSION);
+        assert_eq!(send_cmpct.command(), Command::SendCmpct);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{error::CustomError, message::Message};
+use crate::{impl_bitcoin_codec, message::Command};
+
+/// Version de protocolo a partir de la cual los peers soportan el mensaje sendheaders (BIP 130).
+pub const SEND_HEADERS_PROTOCOL_VERSION: i32 = 70012;
 
 #[derive(Debug)]
 /// SendHeaders es un mensaje vacio que se envia tras intercambiar los mensajes de version.
@@ -18,27 +21,12 @@ impl Default for SendHeaders {
     }
 }
 
-/// Implementa el trait Message para el mensaje de verificación de conexión.
-/// Permite serializar, parsear y obtener el comando
-impl Message for SendHeaders {
-    fn get_command(&self) -> String {
-        String::from("sendheaders")
-    }
-
-    fn serialize(&self) -> Vec<u8> {
-        vec![]
-    }
-
-    fn parse(buffer: Vec<u8>) -> Result<Self, crate::error::CustomError> {
-        if !buffer.is_empty() {
-            return Err(CustomError::SerializedBufferIsInvalid);
-        }
-        Ok(SendHeaders {})
-    }
-}
+impl_bitcoin_codec!(SendHeaders, Command::SendHeaders);
 
 #[cfg(test)]
 mod tests {
+    use crate::message::Message;
+
     use super::*;
 
     #[test]
@@ -52,20 +40,20 @@ mod tests {
     fn parse_send_headers() {
         let send_headers = SendHeaders::new();
         let serialize_send_headers = send_headers.serialize();
-        let parsed_send_headers = SendHeaders::parse(serialize_send_headers);
+        let parsed_send_headers = SendHeaders::parse(&mut serialize_send_headers.as_slice());
         assert_eq!(parsed_send_headers.is_ok(), true);
     }
 
     #[test]
     fn parse_invalid_send_headers() {
         let buffer_too_long = vec![0x00];
-        let parsed_send_headers = SendHeaders::parse(buffer_too_long);
+        let parsed_send_headers = SendHeaders::parse(&mut buffer_too_long.as_slice());
         assert_eq!(parsed_send_headers.is_err(), true);
     }
 
     #[test]
-    fn get_command_send_headers() {
+    fn command_send_headers() {
         let send_headers = SendHeaders::new();
-        assert_eq!(send_headers.get_command(), String::from("sendheaders"));
+        assert_eq!(send_headers.command(), Command::SendHeaders);
     }
 }
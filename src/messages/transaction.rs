@@ -1,21 +1,33 @@
-use std::collections::HashMap;
+use std::io::{self, Read, Write};
 
-use bitcoin_hashes::{sha256, sha256d, Hash};
-use secp256k1::Secp256k1;
+use bitcoin_hashes::{sha256d, Hash};
+use secp256k1::{ecdsa::Signature, PublicKey, Secp256k1};
 
 use crate::{
-    error::CustomError,
-    message::Message,
-    parser::{BufferParser, VarIntSerialize},
+    error::{CustomError, ParseError, WalletError},
+    message::{Command, Message},
+    parser::{Parser, StreamParser, VarIntSerialize},
     states::utxo_state::UTXO,
     structs::{
-        movement::Movement, outpoint::OutPoint, tx_input::TransactionInput,
+        hash256::Hash256,
+        movement::Movement,
+        outpoint::OutPoint,
+        script::{classify::ScriptType, Script, ScriptElement, OP_0},
+        tx_input::TransactionInput,
         tx_output::TransactionOutput,
     },
-    wallet::{get_script_pubkey, Wallet},
+    wallet::{get_address, get_script_pubkey, Wallet},
 };
 
-const SIGHASH_ALL: u32 = 1;
+/// Sighash type que firma todos los inputs y outputs de la transaccion, el unico que se usa para firmar.
+pub const SIGHASH_ALL: u32 = 1;
+/// Marker y flag que indican que la transaccion se serializa con witness (BIP 144).
+const SEGWIT_MARKER: u8 = 0x00;
+const SEGWIT_FLAG: u8 = 0x01;
+/// Sequence maximo de un input para que la transaccion indique que puede ser reemplazada (BIP 125).
+pub const MAX_BIP125_RBF_SEQUENCE: u32 = 0xfffffffd;
+/// Sequence de un input que no permite reemplazar la transaccion.
+const FINAL_SEQUENCE: u32 = 0xffffffff;
 
 #[derive(Debug, Clone)]
 
@@ -33,17 +45,82 @@ pub struct Transaction {
 }
 
 impl Transaction {
-    /// Esta funcion se encarga de hashear una transacción.
-    pub fn hash(&self) -> Vec<u8> {
-        sha256::Hash::hash(sha256::Hash::hash(self.serialize().as_slice()).as_byte_array())
-            .as_byte_array()
-            .to_vec()
+    /// Esta funcion se encarga de hashear una transacción, devolviendo su txid.
+    /// El txid se calcula sobre la serializacion sin witness.
+    pub fn hash(&self) -> Hash256 {
+        Hash256::hash(&self.serialize_without_witness())
+    }
+
+    /// Esta funcion se encarga de calcular el wtxid de una transacción, que es el hash de su serializacion con witness.
+    /// Si la transaccion no tiene witness, coincide con el txid.
+    pub fn wtxid(&self) -> Hash256 {
+        Hash256::hash(&self.serialize())
+    }
+
+    /// Devuelve el tamaño virtual de la transaccion (BIP 141): su peso dividido 4, redondeado hacia arriba.
+    /// El peso cuenta 4 unidades por byte de la serializacion sin witness y 1 por byte de witness.
+    pub fn vsize(&self) -> u64 {
+        let base_size = self.serialize_without_witness().len() as u64;
+        let total_size = self.serialize().len() as u64;
+        (base_size * 3 + total_size).div_ceil(4)
+    }
+
+    /// Devuelve true si alguno de los inputs de la transaccion tiene witness.
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Devuelve true si la transaccion indica que puede ser reemplazada por otra que pague mas fee (BIP 125),
+    /// es decir si alguno de sus inputs tiene sequence menor o igual a MAX_BIP125_RBF_SEQUENCE.
+    pub fn signals_rbf(&self) -> bool {
+        self.inputs
+            .iter()
+            .any(|input| input.sequence <= MAX_BIP125_RBF_SEQUENCE)
+    }
+
+    /// Esta funcion se encarga de serializar una transacción con el formato previo a SegWit, sin marker, flag ni witness.
+    pub fn serialize_without_witness(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = vec![];
+        // escribir en un Vec no puede fallar
+        let _ = self.serialize_without_witness_into(&mut buffer);
+        buffer
+    }
+
+    fn serialize_without_witness_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.version.to_le_bytes())?;
+        self.serialize_inputs_and_outputs_into(writer)?;
+        writer.write_all(&self.lock_time.to_le_bytes())
+    }
+
+    fn serialize_inputs_and_outputs_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.inputs.len().write_varint(writer)?;
+        for input in &self.inputs {
+            writer.write_all(&input.serialize())?;
+        }
+        self.outputs.len().write_varint(writer)?;
+        for output in &self.outputs {
+            writer.write_all(&output.serialize())?;
+        }
+        Ok(())
     }
 
     /// Esta funcion se encarga de parsear una transacción a partir de un parser.
-    pub fn parse_from_parser(parser: &mut BufferParser) -> Result<Self, CustomError> {
+    /// Acepta tanto el formato previo a SegWit como el formato con witness (BIP 144), en el que luego de la version
+    /// vienen el marker y el flag, y luego de los outputs el witness de cada input.
+    /// Devuelve CustomError si:
+    /// - Falla alguna de las extracciones del parser
+    /// - El flag de la serializacion con witness no es valido, o la transaccion indica witness pero no lo tiene
+    pub fn parse_from_parser(parser: &mut impl Parser) -> Result<Self, CustomError> {
         let version = parser.extract_u32()?;
-        let tx_in_count = parser.extract_varint()? as usize;
+        let mut tx_in_count = parser.extract_varint()? as usize;
+        // una transaccion sin inputs no es valida, por lo que un 0 en su lugar es el marker
+        let segwit = tx_in_count == SEGWIT_MARKER as usize;
+        if segwit {
+            if parser.extract_u8()? != SEGWIT_FLAG {
+                return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+            }
+            tx_in_count = parser.extract_varint()? as usize;
+        }
         let mut inputs = vec![];
         for _ in 0..tx_in_count {
             inputs.push(TransactionInput::parse(parser)?);
@@ -53,6 +130,14 @@ impl Transaction {
         for _ in 0..tx_out_count {
             outputs.push(TransactionOutput::parse(parser)?);
         }
+        if segwit {
+            for input in inputs.iter_mut() {
+                input.parse_witness(parser)?;
+            }
+            if !inputs.iter().any(|input| !input.witness.is_empty()) {
+                return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+            }
+        }
 
         let lock_time = parser.extract_u32()?;
         Ok(Self {
@@ -64,39 +149,66 @@ impl Transaction {
     }
 
     /// Esta funcion se encarga de obtener un movement de una transacción.(ver structs/movement.rs)
-    /// Recibe por parametro el hash del public key de la wallet en la cual se quiere ver si se realizo un movimiento, el estado de UTXO, y la transaccion en la que se realizo el movimiento.
-    /// Devuelve un Option<Movement> que puede ser None si no se realizo ningun movimiento para la wallet indicada por el public key hash en la transacción, o Some(Movement) si se realizo un movimiento para la wallet determinada por la public key hash.
+    /// Recibe por parametro los hashes de las public keys de la wallet en la cual se quiere ver si se realizo un movimiento, el estado de UTXO, y la transaccion en la que se realizo el movimiento.
+    /// Devuelve un Option<Movement> que puede ser None si no se realizo ningun movimiento para la wallet indicada por los public key hashes en la transacción, o Some(Movement) si se realizo un movimiento para la wallet determinada por los public key hashes.
+    /// El fee del movimiento solo se calcula si la wallet gasta alguno de los inputs y se conocen los outputs que gastan todos ellos.
     pub fn get_movement(
         &self,
-        public_key_hash: &Vec<u8>,
+        public_key_hashes: &[Vec<u8>],
         utxo: &UTXO,
     ) -> Result<Option<Movement>, CustomError> {
         let mut value: i64 = 0;
+        let mut spends = false;
+        let mut inputs_value = Some(0);
         for input in &self.inputs {
-            if let Some(utxo_value) = utxo.tx_set.get(&input.previous_output) {
-                if utxo_value.tx_out.is_sent_to_key(public_key_hash)? {
-                    value -= utxo_value.tx_out.value as i64;
+            match utxo.tx_set.get(&input.previous_output) {
+                Some(utxo_value) => {
+                    if utxo_value.tx_out.is_sent_to_any_key(public_key_hashes)? {
+                        value -= utxo_value.tx_out.value as i64;
+                        spends = true;
+                    }
+                    inputs_value = inputs_value.map(|total| total + utxo_value.tx_out.value);
                 }
+                None => inputs_value = None,
             }
         }
         for output in &self.outputs {
-            if output.is_sent_to_key(public_key_hash)? {
+            if output.is_sent_to_any_key(public_key_hashes)? {
                 value += output.value as i64;
             }
         }
-        if value != 0 {
-            Ok(Some(Movement {
-                tx_hash: self.hash(),
-                value,
-                block_hash: None,
-            }))
-        } else {
-            Ok(None)
+        if value == 0 {
+            return Ok(None);
+        }
+
+        // si la wallet envia fondos la otra parte es el primer destinatario ajeno, sino la direccion propia que recibe
+        let mut address = None;
+        let mut output_type = None;
+        for output in &self.outputs {
+            if output.is_sent_to_any_key(public_key_hashes)? == (value > 0) {
+                address = get_address(&output.script_pubkey).ok();
+                output_type = Some(output.script_type().output_type());
+                break;
+            }
         }
+        let outputs_value: u64 = self.outputs.iter().map(|output| output.value).sum();
+        let fee = match spends {
+            true => inputs_value.and_then(|inputs_value| inputs_value.checked_sub(outputs_value)),
+            false => None,
+        };
+        Ok(Some(Movement {
+            tx_hash: self.hash(),
+            value,
+            block_hash: None,
+            address,
+            output_type,
+            fee,
+        }))
     }
 
     /// Esta funcion se encarga de crear una transacción.
-    /// Recibe por parametro la wallet de la cual se quiere enviar la transacción, un vector de OutPoint que contiene los outpoints de las transacciones que se quieren gastar, y un HashMap que contiene los public key hash de las wallets a las cuales se quiere enviar dinero y la cantidad de dinero que se quiere enviar a cada una.
+    /// Recibe por parametro la wallet de la cual se quiere enviar la transacción, un vector con los outpoints de las transacciones que se quieren gastar junto a sus outputs, y un vector con las direcciones a las cuales se quiere enviar dinero y la cantidad de dinero que se quiere enviar a cada una, en el orden de los outputs.
+    /// Si replaceable es true los inputs indican que la transacción puede ser reemplazada (BIP 125).
     /// Crea la transacción y la manda a firmar con la wallet de la cual se quiere enviar la transacción.
     /// Finalmente devuelve la transacción firmada.
     /// Devuelve CustomError si:
@@ -104,20 +216,46 @@ impl Transaction {
     /// - No se pudo firmar la transacción.
     pub fn create(
         sender_wallet: &Wallet,
-        inputs_outpoints: Vec<OutPoint>,
-        outputs: HashMap<String, u64>,
+        inputs_outpoints: Vec<(OutPoint, TransactionOutput)>,
+        outputs: Vec<(String, u64)>,
+        replaceable: bool,
     ) -> Result<Self, CustomError> {
+        let (mut transaction, previous_outputs) =
+            Self::create_unsigned(inputs_outpoints, outputs, replaceable)?;
+
+        transaction.get_script_sigs(sender_wallet, &previous_outputs)?;
+
+        Ok(transaction)
+    }
+
+    /// Esta funcion se encarga de crear una transacción sin firmar, con los script sigs y los witness vacios.
+    /// Recibe los mismos parametros que create, salvo la wallet.
+    /// Devuelve la transacción junto a los outputs que gasta cada input, necesarios para firmarla.
+    /// Devuelve CustomError si no se puede obtener el script pubkey de alguna de las direcciones.
+    pub fn create_unsigned(
+        inputs_outpoints: Vec<(OutPoint, TransactionOutput)>,
+        outputs: Vec<(String, u64)>,
+        replaceable: bool,
+    ) -> Result<(Self, Vec<TransactionOutput>), CustomError> {
+        let sequence = if replaceable {
+            MAX_BIP125_RBF_SEQUENCE
+        } else {
+            FINAL_SEQUENCE
+        };
         let mut transaction = Transaction {
             version: 1,
             inputs: vec![],
             outputs: vec![],
             lock_time: 0,
         };
-        for outpoint in inputs_outpoints {
+        let mut previous_outputs = vec![];
+        for (outpoint, previous_output) in inputs_outpoints {
+            previous_outputs.push(previous_output);
             let input = TransactionInput {
                 previous_output: outpoint,
                 script_sig: vec![],
-                sequence: 0xffffffff,
+                sequence,
+                witness: vec![],
             };
             transaction.inputs.push(input);
         }
@@ -130,96 +268,278 @@ impl Transaction {
             transaction.outputs.push(output);
         }
 
-        transaction.get_script_sigs(sender_wallet)?;
-
-        Ok(transaction)
+        Ok((transaction, previous_outputs))
     }
 
     /// Esta funcion se encarga de mandar a firmar una transacción.
-    /// Recibe por parametro la wallet con la cual se quiere firmar.
+    /// Recibe por parametro la wallet con la cual se quiere firmar y los outputs que gasta cada input.
+    /// Los inputs que gastan outputs P2WPKH se firman segun BIP 143 y la firma se guarda en su witness,
+    /// el resto se firma con el formato previo a SegWit y la firma se guarda en su script sig,
+    /// junto con la public key salvo que el output sea P2PK.
     /// Devuelve CustomError si:
-    /// - No se puede obtener la script pub key de la wallet.
-    /// - No se puede obtener el hash del private key de la wallet.
+    /// - La cantidad de outputs no coincide con la de inputs.
+    /// - No se puede obtener la private key de la wallet que gasta alguno de los outputs.
     /// - No se pudo firmar la transacción.
-    fn get_script_sigs(&mut self, wallet: &Wallet) -> Result<(), CustomError> {
+    pub fn get_script_sigs(
+        &mut self,
+        wallet: &Wallet,
+        previous_outputs: &[TransactionOutput],
+    ) -> Result<(), CustomError> {
+        if previous_outputs.len() != self.inputs.len() {
+            return Err(CustomError::Wallet(WalletError::CannotSignTx));
+        }
         let mut script_sigs = vec![];
-        let script_pubkey = wallet.get_script_pubkey()?;
-        let privkey_hash = wallet.get_privkey_hash()?;
-
-        for i in 0..self.inputs.len() {
-            self.inputs[i].script_sig = script_pubkey.clone();
-            let serialized_unsigned_tx = self.serialize();
-            let script_sig = sign(serialized_unsigned_tx, &privkey_hash)?;
-            script_sigs.push(script_sig);
-            self.inputs[i].script_sig = vec![];
+        let mut witnesses = vec![];
+
+        for (i, previous_output) in previous_outputs.iter().enumerate() {
+            let script_pubkey = previous_output.script_pubkey.clone();
+            let privkey_hash = wallet.get_signing_key(&script_pubkey)?;
+            if previous_output.is_p2wpkh() {
+                let sighash = self.segwit_sighash(i, &script_pubkey[2..], previous_output.value);
+                let (signature, public_key) = sign(&sighash, &privkey_hash)?;
+                script_sigs.push(vec![]);
+                witnesses.push(vec![signature, public_key]);
+                continue;
+            }
+
+            let sighash = self.legacy_sighash(i, &script_pubkey);
+            let (signature, public_key) = sign(&sighash, &privkey_hash)?;
+            // los outputs P2PK ya tienen la public key en el script pubkey, solo se agrega la firma
+            match previous_output.is_p2pk() {
+                true => {
+                    script_sigs.push(Script::new(vec![ScriptElement::Push(signature)]).serialize())
+                }
+                false => script_sigs.push(get_script_sig(signature, public_key)),
+            }
+            witnesses.push(vec![]);
         }
 
-        for (index, script_sig) in script_sigs.iter().enumerate() {
-            self.inputs[index].script_sig = script_sig.clone();
+        for (index, (script_sig, witness)) in script_sigs.into_iter().zip(witnesses).enumerate() {
+            self.inputs[index].script_sig = script_sig;
+            self.inputs[index].witness = witness;
         }
 
         Ok(())
     }
-}
 
-/// Implementa el trait Message para la estructura Transaction.
-/// Permite serializar, parsear y obtener el comando
-impl Message for Transaction {
-    fn serialize(&self) -> Vec<u8> {
-        let mut buffer: Vec<u8> = vec![];
-        buffer.extend(self.version.to_le_bytes());
-        buffer.extend(self.inputs.len().to_varint_bytes());
+    /// Esta funcion se encarga de agregar la firma de una de las claves de un multisig al input que gasta
+    /// un output P2SH con ese redeem script, para co-firmar una transaccion junto a las otras claves.
+    /// Conserva las firmas validas que ya tiene el input, ordenadas como sus public keys en el redeem script,
+    /// hasta la cantidad de firmas requeridas.
+    /// Devuelve true si el input quedo con todas las firmas requeridas.
+    /// Devuelve CustomError si el input no existe, si el redeem script no es un multisig o si la clave no es de ninguna de sus public keys.
+    pub fn sign_multisig_input(
+        &mut self,
+        index: usize,
+        redeem_script: &Script,
+        privkey: &[u8],
+    ) -> Result<bool, CustomError> {
+        let Some((required, pubkeys)) = redeem_script.multisig_keys() else { return Err(CustomError::Wallet(WalletError::CannotSignTx)) };
+        let Some(input) = self.inputs.get(index) else { return Err(CustomError::Wallet(WalletError::CannotSignTx)) };
+        let redeem_script = redeem_script.serialize();
+        let sighash = self.legacy_sighash(index, &redeem_script);
+
+        // el script sig de un input multisig es OP_0, las firmas y el redeem script
+        let mut signatures = vec![];
+        if let [_, pushed_signatures @ .., _] =
+            Script::parse(&input.script_sig)?.elements.as_slice()
+        {
+            for element in pushed_signatures {
+                let ScriptElement::Push(signature) = element else { continue };
+                if let Some(key_index) = find_signing_key(&sighash, signature, &pubkeys) {
+                    signatures.push((key_index, signature.clone()));
+                }
+            }
+        }
+
+        let (signature, public_key) = sign(&sighash, privkey)?;
+        let Some(key_index) = pubkeys.iter().position(|pubkey| *pubkey == public_key) else { return Err(CustomError::Wallet(WalletError::CannotSignTx)) };
+        if !signatures.iter().any(|(index, _)| *index == key_index) {
+            signatures.push((key_index, signature));
+        }
+        signatures.sort_by_key(|(key_index, _)| *key_index);
+        signatures.truncate(required as usize);
+        let complete = signatures.len() == required as usize;
+
+        let mut script_sig = vec![ScriptElement::Op(OP_0)];
+        script_sig.extend(
+            signatures
+                .into_iter()
+                .map(|(_, signature)| ScriptElement::Push(signature)),
+        );
+        script_sig.push(ScriptElement::Push(redeem_script));
+        self.inputs[index].script_sig = Script::new(script_sig).serialize();
+        Ok(complete)
+    }
+
+    /// Esta funcion se encarga de calcular el hash a firmar de un input segun el tipo del output que gasta.
+    /// Los inputs que gastan outputs P2WPKH se firman segun BIP 143, los P2PK y P2PKH con el formato previo a SegWit,
+    /// y los P2SH con el formato previo a SegWit sobre su redeem script.
+    /// Devuelve CustomError si el input no existe, si el output es P2SH y no se recibe el redeem script,
+    /// o si es de otro tipo.
+    pub fn input_sighash(
+        &self,
+        index: usize,
+        previous_output: &TransactionOutput,
+        redeem_script: Option<&[u8]>,
+    ) -> Result<[u8; 32], CustomError> {
+        if index >= self.inputs.len() {
+            return Err(CustomError::Wallet(WalletError::CannotSignTx));
+        }
+        match (previous_output.script_type(), redeem_script) {
+            (ScriptType::P2WPKH(pubkey_hash), _) => {
+                Ok(self.segwit_sighash(index, &pubkey_hash, previous_output.value))
+            }
+            (ScriptType::P2PK(_) | ScriptType::P2PKH(_), _) => {
+                Ok(self.legacy_sighash(index, &previous_output.script_pubkey))
+            }
+            (ScriptType::P2SH(_), Some(redeem_script)) => {
+                Ok(self.legacy_sighash(index, redeem_script))
+            }
+            _ => Err(CustomError::Wallet(WalletError::CannotSignTx)),
+        }
+    }
+
+    /// Esta funcion se encarga de calcular el hash a firmar de un input con el formato previo a SegWit.
+    /// Recibe el indice del input y el script que se firma en su lugar: el script pubkey que gasta, o el redeem script si es P2SH.
+    /// Los script sigs del resto de los inputs se firman vacios.
+    pub fn legacy_sighash(&self, index: usize, script_code: &[u8]) -> [u8; 32] {
+        let mut unsigned_tx = self.clone();
+        for (i, input) in unsigned_tx.inputs.iter_mut().enumerate() {
+            input.script_sig = match i == index {
+                true => script_code.to_vec(),
+                false => vec![],
+            };
+        }
+        let mut serialized_unsigned_tx = unsigned_tx.serialize_without_witness();
+        serialized_unsigned_tx.extend(SIGHASH_ALL.to_le_bytes());
+        sha256d::Hash::hash(&serialized_unsigned_tx).to_byte_array()
+    }
+
+    /// Esta funcion se encarga de calcular el hash a firmar de un input que gasta un output P2WPKH (BIP 143).
+    /// Recibe el indice del input, el hash de la public key del output que gasta y su valor.
+    fn segwit_sighash(&self, index: usize, pubkey_hash: &[u8], value: u64) -> [u8; 32] {
+        let mut prevouts = vec![];
+        let mut sequences = vec![];
         for input in &self.inputs {
-            buffer.extend(input.serialize());
+            prevouts.extend(input.previous_output.serialize());
+            sequences.extend(input.sequence.to_le_bytes());
         }
-        buffer.extend(self.outputs.len().to_varint_bytes());
+        let mut outputs = vec![];
         for output in &self.outputs {
-            buffer.extend(output.serialize());
+            outputs.extend(output.serialize());
         }
+
+        // el script code de un input P2WPKH es el script P2PKH de su hash
+        let mut script_code = vec![0x76, 0xa9, 0x14];
+        script_code.extend(pubkey_hash);
+        script_code.extend([0x88, 0xac]);
+
+        let input = &self.inputs[index];
+        let mut buffer = vec![];
+        buffer.extend(self.version.to_le_bytes());
+        buffer.extend(sha256d::Hash::hash(&prevouts).to_byte_array());
+        buffer.extend(sha256d::Hash::hash(&sequences).to_byte_array());
+        buffer.extend(input.previous_output.serialize());
+        buffer.extend(script_code.len().to_varint_bytes());
+        buffer.extend(script_code);
+        buffer.extend(value.to_le_bytes());
+        buffer.extend(input.sequence.to_le_bytes());
+        buffer.extend(sha256d::Hash::hash(&outputs).to_byte_array());
         buffer.extend(self.lock_time.to_le_bytes());
-        buffer
+        buffer.extend(SIGHASH_ALL.to_le_bytes());
+        sha256d::Hash::hash(&buffer).to_byte_array()
     }
+}
 
-    fn get_command(&self) -> String {
-        String::from("tx")
+/// Implementa el trait Message para la estructura Transaction.
+/// Permite serializar, parsear y obtener el comando
+/// Si la transaccion tiene witness se serializa con el formato de BIP 144.
+impl Message for Transaction {
+    const COMMAND: Command = Command::Tx;
+
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if !self.has_witness() {
+            return self.serialize_without_witness_into(writer);
+        }
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&[SEGWIT_MARKER, SEGWIT_FLAG])?;
+        self.serialize_inputs_and_outputs_into(writer)?;
+        for input in &self.inputs {
+            writer.write_all(&input.serialize_witness())?;
+        }
+        writer.write_all(&self.lock_time.to_le_bytes())
     }
 
-    fn parse(buffer: Vec<u8>) -> Result<Self, crate::error::CustomError> {
-        let mut parser = BufferParser::new(buffer);
+    fn parse(reader: &mut impl Read) -> Result<Self, crate::error::CustomError> {
+        let mut parser = StreamParser::new(reader);
         Transaction::parse_from_parser(&mut parser)
     }
 }
 
 /// Esta funcion se encarga de firmar una transacción.
-/// Recibe un buffer que contiene la transacción a firmar y el hash del private key de la wallet con la cual se quiere firmar la transacción.
-fn sign(mut buffer: Vec<u8>, privkey: &[u8]) -> Result<Vec<u8>, CustomError> {
-    buffer.extend(SIGHASH_ALL.to_le_bytes());
-
-    let z = sha256d::Hash::hash(&buffer);
-
+/// Recibe el hash a firmar y el hash del private key de la wallet con la cual se quiere firmar la transacción.
+/// Devuelve la firma seguida del sighash type y la public key comprimida de la wallet.
+pub fn sign(sighash: &[u8; 32], privkey: &[u8]) -> Result<(Vec<u8>, Vec<u8>), CustomError> {
     let secp = Secp256k1::new();
-    let msg = secp256k1::Message::from_slice(&z.to_byte_array())
-        .map_err(|_| CustomError::CannotSignTx)?;
+    let msg = secp256k1::Message::from_slice(sighash)
+        .map_err(|_| CustomError::Wallet(WalletError::CannotSignTx))?;
 
-    let key = secp256k1::SecretKey::from_slice(privkey).map_err(|_| CustomError::CannotSignTx)?;
+    let key = secp256k1::SecretKey::from_slice(privkey)
+        .map_err(|_| CustomError::Wallet(WalletError::CannotSignTx))?;
     let publickey = secp256k1::PublicKey::from_secret_key(&secp, &key).serialize();
 
-    let signature = secp.sign_ecdsa(&msg, &key).serialize_der();
+    let mut signature = secp.sign_ecdsa(&msg, &key).serialize_der().to_vec();
+    signature.push(SIGHASH_ALL as u8);
 
+    Ok((signature, publickey.to_vec()))
+}
+
+/// Esta funcion se encarga de buscar cual de las public keys recibidas genero una firma del sighash.
+/// La firma incluye al final el sighash type.
+/// Devuelve el indice de la public key, o None si la firma no es valida para ninguna.
+pub fn find_signing_key(
+    sighash: &[u8; 32],
+    signature: &[u8],
+    pubkeys: &[Vec<u8>],
+) -> Option<usize> {
+    let secp = Secp256k1::verification_only();
+    let msg = secp256k1::Message::from_slice(sighash).ok()?;
+    let (_, der_signature) = signature.split_last()?;
+    let signature = Signature::from_der(der_signature).ok()?;
+    pubkeys.iter().position(|pubkey| {
+        PublicKey::from_slice(pubkey)
+            .map(|pubkey| secp.verify_ecdsa(&msg, &signature, &pubkey).is_ok())
+            .unwrap_or(false)
+    })
+}
+
+/// Esta funcion se encarga de armar el script sig de un input P2PKH a partir de la firma y la public key.
+fn get_script_sig(signature: Vec<u8>, publickey: Vec<u8>) -> Vec<u8> {
     let mut script_sig = vec![];
 
-    script_sig.extend((signature.len() + 1).to_varint_bytes());
-    script_sig.extend(signature.to_vec());
-    script_sig.extend((0x1_u8).to_le_bytes());
+    script_sig.extend(signature.len().to_varint_bytes());
+    script_sig.extend(signature);
     script_sig.extend(publickey.len().to_varint_bytes());
     script_sig.extend(publickey);
 
-    Ok(script_sig)
+    script_sig
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::BufferParser;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // transaccion P2WPKH firmada del ejemplo de BIP 143
+    const SEGWIT_TX: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
 
     #[test]
     fn tx_parse_and_serialize() {
@@ -244,23 +564,24 @@ mod tests {
             0x04, 0x84, 0xCF, 0xAB, 0x12, 0x48, 0x5E, 0xFD, 0xA0, 0xB7, 0x8B, 0x4E, 0xCC, 0x52,
             0x88, 0xAC, 0x00, 0x00, 0x00, 0x00,
         ];
-        let mut parser = BufferParser::new(buffer.clone());
+        let mut parser = BufferParser::new(&buffer);
         let tx = Transaction::parse_from_parser(&mut parser).unwrap();
         assert_eq!(tx.version, 1);
         assert_eq!(tx.inputs.len(), 1);
         let outpoint = tx.inputs.get(0).unwrap().previous_output.clone();
         assert_eq!(
             outpoint.hash,
-            vec![
+            Hash256::new([
                 0x6D, 0xBD, 0xDB, 0x08, 0x5B, 0x1D, 0x8A, 0xF7, 0x51, 0x84, 0xF0, 0xBC, 0x01, 0xFA,
                 0xD5, 0x8D, 0x12, 0x66, 0xE9, 0xB6, 0x3B, 0x50, 0x88, 0x19, 0x90, 0xE4, 0xB4, 0x0D,
                 0x6A, 0xEE, 0x36, 0x29,
-            ]
+            ])
         );
         let script_sig = tx.inputs.get(0).unwrap().script_sig.clone();
         assert_eq!(script_sig.len(), 139);
         let sequence = tx.inputs.get(0).unwrap().sequence;
         assert_eq!(sequence, 0xFFFFFFFF);
+        assert!(!tx.signals_rbf());
         assert_eq!(tx.outputs.len(), 2);
         let output = tx.outputs.get(0).unwrap();
         assert_eq!(output.value, 5000000);
@@ -306,8 +627,186 @@ mod tests {
             0x04, 0x84, 0xCF, 0xAB, 0x12, 0x48, 0x5E, 0xFD, 0xA0, 0xB7, 0x8B, 0x4E, 0xCC, 0x52,
             0x88, 0xAC, 0x00, 0x00, 0x00, 0x00,
         ];
-        let mut parser = BufferParser::new(buffer);
+        let mut parser = BufferParser::new(&buffer);
         let mut tx = Transaction::parse_from_parser(&mut parser).unwrap();
-        assert!(tx.get_script_sigs(&wallet).is_ok());
+        let previous_outputs = vec![TransactionOutput {
+            value: 0,
+            script_pubkey: wallet.get_script_pubkey().unwrap(),
+        }];
+        assert!(tx.get_script_sigs(&wallet, &previous_outputs).is_ok());
+    }
+
+    #[test]
+    fn segwit_tx_parse_and_serialize() {
+        let buffer = from_hex(SEGWIT_TX);
+        let tx = Transaction::parse(&mut buffer.as_slice()).unwrap();
+        assert!(tx.has_witness());
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.outputs.len(), 2);
+        assert!(tx.inputs[0].witness.is_empty());
+        assert_eq!(tx.inputs[1].witness.len(), 2);
+        assert_eq!(tx.inputs[1].witness[1].len(), 33);
+        assert_eq!(tx.lock_time, 0x11);
+
+        assert_eq!(tx.serialize(), buffer);
+        let stripped = tx.serialize_without_witness();
+        assert_eq!(
+            Transaction::parse(&mut stripped.as_slice()).unwrap().hash(),
+            tx.hash()
+        );
+        assert!(!Transaction::parse(&mut stripped.as_slice())
+            .unwrap()
+            .has_witness());
+
+        assert_eq!(stripped.len(), 233);
+        assert_eq!(tx.vsize(), 261);
+    }
+
+    #[test]
+    fn segwit_tx_txid_and_wtxid() {
+        let tx = Transaction::parse(&mut from_hex(SEGWIT_TX).as_slice()).unwrap();
+        assert_eq!(
+            tx.hash().to_string(),
+            "e8151a2af31c368a35053ddd4bdb285a8595c769a3ad83e0fa02314a602d4609"
+        );
+        assert_eq!(
+            tx.wtxid().to_string(),
+            "c36c38370907df2324d9ce9d149d191192f338b37665a82e78e76a12c909b762"
+        );
+    }
+
+    #[test]
+    fn movement_address_is_the_receiving_address() {
+        let tx = Transaction::parse(&mut from_hex(SEGWIT_TX).as_slice()).unwrap();
+        let utxo = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        let pubkey_hash = from_hex("3bde42dbee7e4dbe6a21b2d50ce2f0167faa8159");
+
+        let movement = tx.get_movement(&[pubkey_hash], &utxo).unwrap().unwrap();
+        assert_eq!(movement.value, tx.outputs[1].value as i64);
+        assert_eq!(movement.fee, None);
+        assert_eq!(
+            movement.address,
+            Some(get_address(&tx.outputs[1].script_pubkey).unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_invalid_segwit_flag() {
+        let mut buffer = from_hex(SEGWIT_TX);
+        buffer[5] = 0x02;
+        assert!(Transaction::parse(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn segwit_sighash() {
+        // el hash a firmar no depende de los script sigs ni del witness de los inputs
+        let tx = Transaction::parse(&mut from_hex(SEGWIT_TX).as_slice()).unwrap();
+
+        let sighash = tx.segwit_sighash(
+            1,
+            &from_hex("1d0f172a0ecb48aee1be1f2687d2963ae33f71a1"),
+            600000000,
+        );
+        assert_eq!(
+            sighash.to_vec(),
+            from_hex("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670")
+        );
+    }
+
+    #[test]
+    fn sign_p2wpkh_tx() {
+        let wallet = Wallet {
+            name: String::from("test"),
+            pubkey: String::from("mscatccDgq7azndWHFTzvEuZuywCsUvTRu"),
+            privkey: Some(String::from(
+                "cNpwEsaVLhju18SJowLtdCNaJtvMvqL4jtFLm2FXw7vZjg4sRWvH",
+            )),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        };
+        let previous_output = TransactionOutput {
+            value: 10000,
+            script_pubkey: get_script_pubkey(wallet.get_bech32_address().unwrap()).unwrap(),
+        };
+        let outpoint = OutPoint {
+            hash: Hash256::new([1; 32]),
+            index: 0,
+        };
+        let outputs = vec![(String::from("mhzZUxRkPzNpCsQHemTakuJa5xhCajxyVm"), 9000)];
+        let tx =
+            Transaction::create(&wallet, vec![(outpoint, previous_output)], outputs, true).unwrap();
+
+        assert!(tx.inputs[0].script_sig.is_empty());
+        assert_eq!(tx.inputs[0].witness.len(), 2);
+        assert_eq!(tx.inputs[0].witness[1].len(), 33);
+        assert_eq!(*tx.inputs[0].witness[0].last().unwrap(), SIGHASH_ALL as u8);
+        assert!(tx.vsize() < tx.serialize().len() as u64);
+        assert_ne!(tx.hash(), tx.wtxid());
+        assert!(tx.signals_rbf());
+    }
+
+    #[test]
+    fn cosign_multisig_input() {
+        let secp = Secp256k1::new();
+        let privkeys: Vec<Vec<u8>> = (1..=3).map(|i| vec![i; 32]).collect();
+        let pubkeys: Vec<Vec<u8>> = privkeys
+            .iter()
+            .map(|privkey| {
+                let key = secp256k1::SecretKey::from_slice(privkey).unwrap();
+                PublicKey::from_secret_key(&secp, &key).serialize().to_vec()
+            })
+            .collect();
+        let redeem_script = Script::multisig(2, &pubkeys).unwrap();
+        let mut tx = Transaction {
+            version: 1,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: Hash256::new([1; 32]),
+                    index: 0,
+                },
+                script_sig: vec![],
+                sequence: FINAL_SEQUENCE,
+                witness: vec![],
+            }],
+            outputs: vec![TransactionOutput {
+                value: 9000,
+                script_pubkey: get_script_pubkey(String::from(
+                    "mhzZUxRkPzNpCsQHemTakuJa5xhCajxyVm",
+                ))
+                .unwrap(),
+            }],
+            lock_time: 0,
+        };
+        let txid = tx.hash();
+
+        assert!(!tx
+            .sign_multisig_input(0, &redeem_script, &privkeys[2])
+            .unwrap());
+        // volver a firmar con la misma clave no agrega otra firma
+        assert!(!tx
+            .sign_multisig_input(0, &redeem_script, &privkeys[2])
+            .unwrap());
+        assert!(tx
+            .sign_multisig_input(0, &redeem_script, &privkeys[0])
+            .unwrap());
+        assert_ne!(tx.hash(), txid);
+
+        // las firmas quedan en el orden de las public keys: OP_0 <firma 1> <firma 3> <redeem script>
+        let script_sig = Script::parse(&tx.inputs[0].script_sig).unwrap();
+        let sighash = tx.legacy_sighash(0, &redeem_script.serialize());
+        let [ScriptElement::Op(OP_0), ScriptElement::Push(first), ScriptElement::Push(second), ScriptElement::Push(redeem)] =
+            script_sig.elements.as_slice()
+        else {
+            panic!("invalid multisig script sig");
+        };
+        assert_eq!(find_signing_key(&sighash, first, &pubkeys), Some(0));
+        assert_eq!(find_signing_key(&sighash, second, &pubkeys), Some(2));
+        assert_eq!(*redeem, redeem_script.serialize());
+
+        assert!(tx
+            .sign_multisig_input(0, &Script::p2pkh(&[0; 20]), &privkeys[0])
+            .is_err());
+        assert!(tx.sign_multisig_input(0, &redeem_script, &[4; 32]).is_err());
     }
 }
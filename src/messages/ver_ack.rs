@@ -1,4 +1,4 @@
-use crate::{error::CustomError, message::Message};
+use crate::{impl_bitcoin_codec, message::Command};
 
 #[derive(Debug)]
 /// VerAck es un mensaje vacio que se envia tras intercambiar los mensajes de version.
@@ -18,27 +18,12 @@ impl Default for VerAck {
     }
 }
 
-/// Implementa el trait Message para el mensaje de verificación de conexión.
-/// Permite serializar, parsear y obtener el comando
-impl Message for VerAck {
-    fn get_command(&self) -> String {
-        String::from("verack")
-    }
-
-    fn serialize(&self) -> Vec<u8> {
-        vec![]
-    }
-
-    fn parse(buffer: Vec<u8>) -> Result<Self, crate::error::CustomError> {
-        if !buffer.is_empty() {
-            return Err(CustomError::SerializedBufferIsInvalid);
-        }
-        Ok(VerAck {})
-    }
-}
+impl_bitcoin_codec!(VerAck, Command::VerAck);
 
 #[cfg(test)]
 mod tests {
+    use crate::message::Message;
+
     use super::*;
 
     #[test]
@@ -63,21 +48,20 @@ mod tests {
     fn parse_verack() {
         let verack = VerAck::new();
         let serialized_verack = verack.serialize();
-        let parsed_verack = VerAck::parse(serialized_verack);
+        let parsed_verack = VerAck::parse(&mut serialized_verack.as_slice());
         assert_eq!(parsed_verack.is_ok(), true);
     }
 
     #[test]
     fn parse_invalid_verack() {
         let buffer_too_long = vec![0x00];
-        let parsed_verack = VerAck::parse(buffer_too_long);
+        let parsed_verack = VerAck::parse(&mut buffer_too_long.as_slice());
         assert_eq!(parsed_verack.is_err(), true);
     }
 
     #[test]
-    fn get_command_verack() {
+    fn command_verack() {
         let verack = VerAck::new();
-        let command = verack.get_command();
-        assert_eq!(command, String::from("verack"));
+        assert_eq!(verack.command(), Command::VerAck);
     }
 }
@@ -1,8 +1,11 @@
-use std::net::SocketAddrV6;
+use std::{
+    io::{self, Read, Write},
+    net::SocketAddrV6,
+};
 
 use crate::error::CustomError;
-use crate::message::Message;
-use crate::parser::BufferParser;
+use crate::message::{Command, Message};
+use crate::parser::{Parser, StreamParser};
 
 #[derive(PartialEq, Debug)]
 /// Crea una estructura para el mensaje de versión con los campos necesarios de acuerdo con el protocolo de Bitcoin.
@@ -18,6 +21,7 @@ use crate::parser::BufferParser;
 /// - user_agent: que indica el software que utiliza el nodo que envía el mensaje, puede ser vacío.
 /// - user_agent_length: que indica la longitud del campo user_agent. Si es 0, el campo user_agent no se incluye.
 /// - start_height: que indica el tamaño de la blockchain del nodo que envía el mensaje.
+/// - relay: que indica si el nodo que envía el mensaje quiere recibir anuncios de transacciones (BIP 37).
 pub struct Version {
     pub version: i32,
     pub services: u64,
@@ -30,6 +34,7 @@ pub struct Version {
     pub user_agent: String,
     pub user_agent_length: u8,
     pub start_height: i32,
+    pub relay: bool,
 }
 
 impl Version {
@@ -37,6 +42,7 @@ impl Version {
     /// El campo user_agent se inicializa con un string vacío y el campo user_agent_length con 0.
     /// El campo nonce se inicializa con 0.
     /// El campo start_height se inicializa con 0.
+    /// El campo relay se inicializa con true.
     pub fn new(
         receiver_address: SocketAddrV6,
         sender_address: SocketAddrV6,
@@ -55,6 +61,7 @@ impl Version {
             user_agent: String::from(""),
             user_agent_length: 0x00,
             start_height: 0x00,
+            relay: true,
         }
     }
 }
@@ -62,43 +69,30 @@ impl Version {
 /// Implementa el trait Message para el mensaje de versión.
 /// Permite serializar, parsear y obtener el comando
 impl Message for Version {
-    fn get_command(&self) -> String {
-        String::from("version")
-    }
+    const COMMAND: Command = Command::Version;
 
-    fn serialize(&self) -> Vec<u8> {
-        let mut buffer = vec![];
-        buffer.extend_from_slice(&self.version.to_le_bytes());
-        buffer.extend_from_slice(&self.services.to_le_bytes());
-        buffer.extend_from_slice(&self.timestamp.to_le_bytes());
-        buffer.extend_from_slice(&self.receiver_services.to_le_bytes());
-        let ipv6_buffer = self.receiver_address.ip().octets();
-        for byte in ipv6_buffer {
-            buffer.extend_from_slice(&[byte]);
-        }
-        buffer.extend_from_slice(&self.receiver_address.port().to_be_bytes());
-        buffer.extend_from_slice(&self.sender_services.to_le_bytes());
-        let ipv6_buffer = self.sender_address.ip().octets();
-        for byte in ipv6_buffer {
-            buffer.extend_from_slice(&[byte]);
-        }
-        buffer.extend_from_slice(&self.sender_address.port().to_be_bytes());
-        buffer.extend_from_slice(&self.nonce.to_le_bytes());
-        buffer.extend_from_slice(&self.user_agent_length.to_le_bytes());
-        buffer.extend_from_slice(self.user_agent.as_bytes());
-        buffer.extend_from_slice(&self.start_height.to_le_bytes());
-
-        buffer
+    fn serialize_into<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&self.services.to_le_bytes())?;
+        writer.write_all(&self.timestamp.to_le_bytes())?;
+        writer.write_all(&self.receiver_services.to_le_bytes())?;
+        writer.write_all(&self.receiver_address.ip().octets())?;
+        writer.write_all(&self.receiver_address.port().to_be_bytes())?;
+        writer.write_all(&self.sender_services.to_le_bytes())?;
+        writer.write_all(&self.sender_address.ip().octets())?;
+        writer.write_all(&self.sender_address.port().to_be_bytes())?;
+        writer.write_all(&self.nonce.to_le_bytes())?;
+        writer.write_all(&self.user_agent_length.to_le_bytes())?;
+        writer.write_all(self.user_agent.as_bytes())?;
+        writer.write_all(&self.start_height.to_le_bytes())?;
+        writer.write_all(&[self.relay as u8])
     }
 
-    fn parse(buffer: Vec<u8>) -> Result<Self, CustomError>
+    fn parse(reader: &mut impl Read) -> Result<Self, CustomError>
     where
         Self: Sized,
     {
-        let mut parser = BufferParser::new(buffer);
-        if parser.len() < 85 {
-            return Err(CustomError::SerializedBufferIsInvalid);
-        }
+        let mut parser = StreamParser::new(reader);
 
         let version = parser.extract_i32()?;
         let services = parser.extract_u64()?;
@@ -111,6 +105,11 @@ impl Message for Version {
         let user_agent_length = parser.extract_u8()?;
         let user_agent = parser.extract_string(user_agent_length as usize)?;
         let start_height = parser.extract_i32()?;
+        // el campo relay es opcional, si no se envia el peer espera recibir anuncios de transacciones
+        let relay = parser
+            .extract_remaining()?
+            .first()
+            .is_none_or(|relay| *relay != 0);
 
         Ok(Version {
             version,
@@ -124,6 +123,7 @@ impl Message for Version {
             user_agent,
             user_agent_length,
             start_height,
+            relay,
         })
     }
 }
@@ -141,11 +141,25 @@ mod tests {
         let receiver_address = SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1), 1234, 0, 0);
         let version: Version = Version::new(receiver_address, sender_address, 7000, 0x00);
         let buffer = version.serialize();
-        let parsed_version = Version::parse(buffer)?;
+        let parsed_version = Version::parse(&mut buffer.as_slice())?;
         assert_eq!(version, parsed_version);
         Ok(())
     }
 
+    #[test]
+    fn parse_version_relay() -> Result<(), CustomError> {
+        let address = SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1), 18333, 0, 0);
+        let mut version = Version::new(address, address, 70016, 0x01);
+        version.relay = false;
+        let mut buffer = version.serialize();
+        assert!(!Version::parse(&mut buffer.as_slice())?.relay);
+
+        // sin el campo relay se asume que el peer quiere recibir transacciones
+        buffer.pop();
+        assert!(Version::parse(&mut buffer.as_slice())?.relay);
+        Ok(())
+    }
+
     #[test]
     fn parse_invalid_version() {
         let buffer_too_short = vec![
@@ -153,7 +167,7 @@ mod tests {
             0, 0, 40, 0, 0, 64, 0, 27, 8, 11, 68, 134, 135, 118, 52, 198, 86, 32, 213, 227, 9, 4,
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
-        let parsed_version = Version::parse(buffer_too_short);
+        let parsed_version = Version::parse(&mut buffer_too_short.as_slice());
         assert_eq!(parsed_version.is_err(), true);
     }
 }
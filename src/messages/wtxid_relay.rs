@@ -0,0 +1,46 @@
+use crate::{impl_bitcoin_codec, message::Command};
+
+/// Version de protocolo a partir de la cual los peers soportan el anuncio de transacciones por wtxid (BIP 339).
+pub const WTXID_RELAY_PROTOCOL_VERSION: i32 = 70016;
+
+#[derive(Debug)]
+/// WtxidRelay es un mensaje vacio que se envia durante el handshake, antes del verack (BIP 339).
+/// Indica al peer que preferimos que anuncie las transacciones por su wtxid en lugar de su txid.
+pub struct WtxidRelay {}
+
+impl WtxidRelay {
+    /// Crea un nuevo mensaje wtxidrelay.
+    pub fn new() -> Self {
+        WtxidRelay {}
+    }
+}
+
+impl Default for WtxidRelay {
+    fn default() -> Self {
+        WtxidRelay::new()
+    }
+}
+
+impl_bitcoin_codec!(WtxidRelay, Command::WtxidRelay);
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Message;
+
+    use super::*;
+
+    #[test]
+    fn serialize_wtxid_relay() {
+        assert_eq!(WtxidRelay::new().serialize(), vec![]);
+    }
+
+    #[test]
+    fn parse_invalid_wtxid_relay() {
+        assert!(WtxidRelay::parse(&mut vec![0x00].as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_wtxid_relay() {
+        assert_eq!(WtxidRelay::new().command(), Command::WtxidRelay);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    error::{CustomError, NetworkError},
+    logger::{send_log, Log, LogEntry},
+    node_state::NodeState,
+    states::peer_info_state::{MessageStats, NetTotals},
+};
+
+/// Prefijo de los nombres de todas las metricas.
+const METRIC_PREFIX: &str = "btc_node";
+/// Ruta en la que se exportan las metricas.
+const METRICS_PATH: &str = "/metrics";
+/// Content type del formato de texto de Prometheus.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// Tiempo maximo en segundos para leer un request.
+const READ_TIMEOUT: u64 = 5;
+/// Cantidad maxima de lineas de headers que se leen de un request.
+const MAX_HEADER_LINES: usize = 100;
+
+/// NodeMetrics son los valores del estado del nodo que se exportan, tomados en un mismo momento.
+/// Los elementos son:
+/// - outbound_peers: Cantidad de peers a los que se conecto el nodo.
+/// - inbound_peers: Cantidad de peers que se conectaron al nodo.
+/// - header_height: Height del ultimo header de la cadena.
+/// - block_height: Height del ultimo bloque procesado.
+/// - peer_height: Mayor height de la cadena informada por los peers.
+/// - sync_progress: Fraccion completada de la etapa actual de la sincronizacion, entre 0 y 1.
+/// - synced: Si el nodo termino de sincronizarse.
+/// - mempool_size: Cantidad de transacciones en el mempool.
+/// - net_totals: Trafico del nodo desde que se inicio, por comando.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NodeMetrics {
+    pub outbound_peers: usize,
+    pub inbound_peers: usize,
+    pub header_height: usize,
+    pub block_height: usize,
+    pub peer_height: usize,
+    pub sync_progress: f64,
+    pub synced: bool,
+    pub mempool_size: usize,
+    pub net_totals: NetTotals,
+}
+
+impl NodeMetrics {
+    /// Toma los valores del estado del nodo.
+    pub fn from_node_state(node_state: &NodeState) -> Result<Self, CustomError> {
+        let sync_progress = node_state.get_sync_progress();
+        Ok(Self {
+            outbound_peers: node_state.get_outbound_peers_count(),
+            inbound_peers: node_state.get_inbound_peers_count(),
+            header_height: node_state.get_best_height(),
+            block_height: node_state.get_last_block_height(),
+            peer_height: sync_progress.peer_height,
+            sync_progress: sync_progress.fraction(),
+            synced: sync_progress.synced,
+            mempool_size: node_state.get_mempool_size(),
+            net_totals: node_state.net_totals()?,
+        })
+    }
+
+    /// Serializa las metricas en el formato de texto de Prometheus.
+    /// El trafico se exporta como contadores, de los que Prometheus calcula la tasa de mensajes con rate().
+    pub fn to_prometheus(&self) -> String {
+        let mut output = String::new();
+        write_metric(
+            &mut output,
+            "peers",
+            "gauge",
+            "Connected peers by direction.",
+            &[
+                (
+                    labels(&[("direction", "outbound")]),
+                    self.outbound_peers as f64,
+                ),
+                (
+                    labels(&[("direction", "inbound")]),
+                    self.inbound_peers as f64,
+                ),
+            ],
+        );
+        write_metric(
+            &mut output,
+            "header_height",
+            "gauge",
+            "Height of the best header.",
+            &[(String::new(), self.header_height as f64)],
+        );
+        write_metric(
+            &mut output,
+            "block_height",
+            "gauge",
+            "Height of the last processed block.",
+            &[(String::new(), self.block_height as f64)],
+        );
+        write_metric(
+            &mut output,
+            "peer_height",
+            "gauge",
+            "Highest chain height reported by the peers.",
+            &[(String::new(), self.peer_height as f64)],
+        );
+        write_metric(
+            &mut output,
+            "sync_progress",
+            "gauge",
+            "Completed fraction of the current sync stage, from 0 to 1.",
+            &[(String::new(), self.sync_progress)],
+        );
+        write_metric(
+            &mut output,
+            "synced",
+            "gauge",
+            "Whether the node finished syncing.",
+            &[(String::new(), if self.synced { 1.0 } else { 0.0 })],
+        );
+        write_metric(
+            &mut output,
+            "mempool_transactions",
+            "gauge",
+            "Transactions in the mempool.",
+            &[(String::new(), self.mempool_size as f64)],
+        );
+
+        let by_command = |value: fn(&MessageStats) -> u64| -> Vec<(String, f64)> {
+            self.net_totals
+                .by_command
+                .iter()
+                .map(|(command, stats)| (labels(&[("command", command)]), value(stats) as f64))
+                .collect()
+        };
+        write_metric(
+            &mut output,
+            "messages_received_total",
+            "counter",
+            "Messages received from peers by command.",
+            &by_command(|stats| stats.messages_received),
+        );
+        write_metric(
+            &mut output,
+            "messages_sent_total",
+            "counter",
+            "Messages sent to peers by command.",
+            &by_command(|stats| stats.messages_sent),
+        );
+        write_metric(
+            &mut output,
+            "bytes_received_total",
+            "counter",
+            "Bytes received from peers by command, including the message header.",
+            &by_command(|stats| stats.bytes_received),
+        );
+        write_metric(
+            &mut output,
+            "bytes_sent_total",
+            "counter",
+            "Bytes sent to peers by command, including the message header.",
+            &by_command(|stats| stats.bytes_sent),
+        );
+        output
+    }
+}
+
+/// MetricsServer es el loop de eventos del servidor que exporta las metricas del nodo para Prometheus.
+/// Escucha requests HTTP GET en una direccion local y atiende una conexion a la vez.
+/// Los elementos son:
+/// - address: Direccion en la que escucha el servidor.
+/// - node_state_ref: Referencia al estado del nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+pub struct MetricsServer {
+    address: SocketAddr,
+    node_state_ref: Arc<Mutex<NodeState>>,
+    logger_sender: mpsc::Sender<LogEntry>,
+}
+
+impl MetricsServer {
+    #[must_use]
+    /// Inicializa el servidor en un thread.
+    pub fn spawn(
+        address: SocketAddr,
+        node_state_ref: Arc<Mutex<NodeState>>,
+        logger_sender: mpsc::Sender<LogEntry>,
+    ) -> JoinHandle<Result<(), CustomError>> {
+        thread::spawn(move || -> Result<(), CustomError> {
+            let server = Self {
+                address,
+                node_state_ref,
+                logger_sender,
+            };
+            server.event_loop()
+        })
+    }
+
+    fn event_loop(&self) -> Result<(), CustomError> {
+        let listener = TcpListener::bind(self.address)
+            .map_err(|_| CustomError::Network(NetworkError::CannotStartMetricsServer))?;
+        send_log(
+            &self.logger_sender,
+            Log::Message(format!(
+                "Metrics server listening on {}{}",
+                self.address, METRICS_PATH
+            )),
+        );
+
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            if let Err(error) = self.handle_connection(stream) {
+                send_log(
+                    &self.logger_sender,
+                    Log::Warning(format!("Error handling metrics request: {}", error)),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Lee un request HTTP y responde con las metricas si pide METRICS_PATH, o con 404 si no.
+    fn handle_connection(&self, mut stream: TcpStream) -> Result<(), CustomError> {
+        stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT)))?;
+        let path = read_request_path(&stream)?;
+        let (status, body) = match path.as_str() {
+            METRICS_PATH => {
+                let metrics = NodeMetrics::from_node_state(&*self.node_state_ref.lock()?)?;
+                ("200 OK", metrics.to_prometheus())
+            }
+            _ => ("404 Not Found", String::from("Not found\n")),
+        };
+
+        let http_response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            CONTENT_TYPE,
+            body.len(),
+            body
+        );
+        stream
+            .write_all(http_response.as_bytes())
+            .map_err(|_| CustomError::Network(NetworkError::CannotSendToStream))?;
+        Ok(())
+    }
+}
+
+/// Lee la linea inicial y los headers de un request HTTP y devuelve la ruta pedida, sin la query.
+fn read_request_path(stream: impl Read) -> Result<String, CustomError> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|_| CustomError::Network(NetworkError::CannotReadStream))?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(_method), Some(target)) = (parts.next(), parts.next()) else { return Err(CustomError::Network(NetworkError::CannotReadStream)) };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    for _ in 0..MAX_HEADER_LINES {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|_| CustomError::Network(NetworkError::CannotReadStream))?;
+        if read == 0 || line.trim_end().is_empty() {
+            return Ok(path);
+        }
+    }
+    Err(CustomError::Network(NetworkError::CannotReadStream))
+}
+
+/// Agrega una metrica con su descripcion, su tipo y sus muestras, cada una con sus labels ya formateados.
+fn write_metric(
+    output: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(String, f64)],
+) {
+    output.push_str(&format!("# HELP {}_{} {}\n", METRIC_PREFIX, name, help));
+    output.push_str(&format!("# TYPE {}_{} {}\n", METRIC_PREFIX, name, kind));
+    for (labels, value) in samples {
+        output.push_str(&format!("{}_{}{} {}\n", METRIC_PREFIX, name, labels, value));
+    }
+}
+
+/// Formatea los labels de una muestra, escapando sus valores.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_metrics() {
+        let mut net_totals = NetTotals::default();
+        net_totals.by_command.insert(
+            String::from("inv"),
+            MessageStats {
+                messages_sent: 2,
+                bytes_sent: 122,
+                messages_received: 5,
+                bytes_received: 305,
+            },
+        );
+        let metrics = NodeMetrics {
+            outbound_peers: 8,
+            inbound_peers: 1,
+            header_height: 2500,
+            block_height: 2400,
+            peer_height: 2600,
+            sync_progress: 0.5,
+            synced: false,
+            mempool_size: 3,
+            net_totals,
+        };
+        let output = metrics.to_prometheus();
+
+        for line in [
+            "# HELP btc_node_peers Connected peers by direction.",
+            "# TYPE btc_node_peers gauge",
+            "btc_node_peers{direction=\"outbound\"} 8",
+            "btc_node_peers{direction=\"inbound\"} 1",
+            "btc_node_header_height 2500",
+            "btc_node_block_height 2400",
+            "btc_node_peer_height 2600",
+            "btc_node_sync_progress 0.5",
+            "btc_node_synced 0",
+            "btc_node_mempool_transactions 3",
+            "# TYPE btc_node_messages_received_total counter",
+            "btc_node_messages_received_total{command=\"inv\"} 5",
+            "btc_node_messages_sent_total{command=\"inv\"} 2",
+            "btc_node_bytes_received_total{command=\"inv\"} 305",
+            "btc_node_bytes_sent_total{command=\"inv\"} 122",
+        ] {
+            assert!(output.lines().any(|l| l == line), "missing line {}", line);
+        }
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(
+            labels(&[("command", "a\"b\\c\n")]),
+            "{command=\"a\\\"b\\\\c\\n\"}"
+        );
+    }
+
+    #[test]
+    fn read_request_paths() {
+        let request = "GET /metrics?format=text HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert_eq!(read_request_path(request.as_bytes()).unwrap(), "/metrics");
+
+        let request = "GET / HTTP/1.1\r\n\r\n";
+        assert_eq!(read_request_path(request.as_bytes()).unwrap(), "/");
+
+        assert!(read_request_path("\r\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddrV6,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::{CustomError, NetworkError},
+    loops::peer_stream_loop::PeerStreamLoop,
+    message::{Command, Message, MessageHeader},
+};
+
+/// Tamaño del header de los mensajes.
+const HEADER_SIZE: usize = 24;
+
+/// DuplexStream es un extremo de una conexion en memoria, creada con duplex.
+/// Los bytes escritos en un extremo se leen en el otro en el mismo orden.
+/// La lectura no bloquea: si no hay bytes disponibles devuelve ErrorKind::WouldBlock, como un stream no bloqueante,
+/// por lo que el resultado de las pruebas no depende de los tiempos de ningun thread.
+/// Una vez liberado el otro extremo, la lectura devuelve 0 bytes y la escritura falla, como en un stream cerrado.
+/// Los elementos son:
+/// - incoming: Bytes escritos por el otro extremo que todavia no se leyeron.
+/// - outgoing: Bytes escritos por este extremo que el otro todavia no leyo.
+pub struct DuplexStream {
+    incoming: Arc<Mutex<VecDeque<u8>>>,
+    outgoing: Arc<Mutex<VecDeque<u8>>>,
+}
+
+/// Crea los dos extremos de una conexion en memoria.
+pub fn duplex() -> (DuplexStream, DuplexStream) {
+    let first = Arc::new(Mutex::new(VecDeque::new()));
+    let second = Arc::new(Mutex::new(VecDeque::new()));
+    (
+        DuplexStream {
+            incoming: first.clone(),
+            outgoing: second.clone(),
+        },
+        DuplexStream {
+            incoming: second,
+            outgoing: first,
+        },
+    )
+}
+
+impl DuplexStream {
+    /// Lee el proximo mensaje que envio el otro extremo, con su header y su payload.
+    /// Si el mensaje todavia no llego completo no consume ningun byte.
+    /// Devuelve CustomError si:
+    /// - No hay un mensaje completo para leer.
+    /// - El header no es valido o el checksum no coincide con el del payload.
+    pub fn read_message(&mut self) -> Result<(MessageHeader, Vec<u8>), CustomError> {
+        let mut incoming = self.incoming.lock()?;
+        if incoming.len() < HEADER_SIZE {
+            return Err(CustomError::Network(NetworkError::CannotReadMessageHeader));
+        }
+        let mut header_buffer = [0; HEADER_SIZE];
+        for (byte, incoming_byte) in header_buffer.iter_mut().zip(incoming.iter()) {
+            *byte = *incoming_byte;
+        }
+        let header = MessageHeader::parse(header_buffer)?;
+        let message_size = HEADER_SIZE + header.payload_size as usize;
+        if incoming.len() < message_size {
+            return Err(CustomError::Network(NetworkError::CannotReadStream));
+        }
+
+        let payload: Vec<u8> = incoming.drain(..message_size).skip(HEADER_SIZE).collect();
+        header.verify_checksum(&payload)?;
+        Ok((header, payload))
+    }
+
+    /// Devuelve true si el otro extremo no envio bytes que todavia no se leyeron.
+    pub fn is_empty(&self) -> Result<bool, CustomError> {
+        Ok(self.incoming.lock()?.is_empty())
+    }
+
+    fn is_closed(&self) -> bool {
+        // cada buffer lo comparten los dos extremos, si queda una sola referencia el otro se libero
+        Arc::strong_count(&self.outgoing) == 1
+    }
+}
+
+impl Read for DuplexStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut incoming = self
+            .incoming
+            .lock()
+            .map_err(|_| io::Error::from(ErrorKind::Other))?;
+        if incoming.is_empty() {
+            if self.is_closed() {
+                return Ok(0);
+            }
+            return Err(ErrorKind::WouldBlock.into());
+        }
+
+        let size = buf.len().min(incoming.len());
+        for (byte, incoming_byte) in buf.iter_mut().zip(incoming.drain(..size)) {
+            *byte = incoming_byte;
+        }
+        Ok(size)
+    }
+}
+
+impl Write for DuplexStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.is_closed() {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+        self.outgoing
+            .lock()
+            .map_err(|_| io::Error::from(ErrorKind::Other))?
+            .extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// MockPeer es un peer simulado en memoria, para probar el manejo de los mensajes del protocolo de forma deterministica
+/// y sin acceso a la red. El nodo usa el otro extremo de la conexion como el stream del peer, por ejemplo en un
+/// PeerStreamLoop o un PeerActionLoop: lee de el los mensajes que envia el MockPeer y escribe en el sus respuestas.
+/// Implementa Read y Write, por lo que los mensajes se le envian con Message::send y se leen con Message::read.
+/// Los elementos son:
+/// - address: Direccion del peer simulado.
+/// - stream: Extremo de la conexion del peer simulado.
+pub struct MockPeer {
+    pub address: SocketAddrV6,
+    stream: DuplexStream,
+}
+
+impl MockPeer {
+    /// Crea el peer simulado y devuelve el extremo de la conexion que usa el nodo.
+    pub fn new(address: SocketAddrV6) -> (Self, DuplexStream) {
+        let (stream, node_stream) = duplex();
+        (Self { address, stream }, node_stream)
+    }
+
+    /// Lee el proximo mensaje que envio el nodo, con su header y su payload.
+    /// Devuelve CustomError si el nodo no envio un mensaje completo o el mensaje no es valido.
+    pub fn receive(&mut self) -> Result<(MessageHeader, Vec<u8>), CustomError> {
+        self.stream.read_message()
+    }
+
+    /// Lee el proximo mensaje que envio el nodo y lo parsea, verificando que tenga el comando del mensaje esperado.
+    /// Devuelve CustomError si el nodo no envio un mensaje, o si envio uno con otro comando o que no se puede parsear.
+    pub fn expect<M: Message>(&mut self) -> Result<M, CustomError> {
+        let (header, payload) = self.receive()?;
+        if header.command != M::COMMAND {
+            return Err(CustomError::Validation(format!(
+                "Expected {} message, received {}",
+                M::COMMAND,
+                header.command
+            )));
+        }
+        M::parse(&mut payload.as_slice())
+    }
+
+    /// Lee todos los mensajes que envio el nodo y devuelve sus comandos, en el orden en que se enviaron.
+    pub fn received_commands(&mut self) -> Vec<Command> {
+        let mut commands = vec![];
+        while let Ok((header, _)) = self.receive() {
+            commands.push(header.command);
+        }
+        commands
+    }
+}
+
+impl Read for MockPeer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for MockPeer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Procesa en el PeerStreamLoop todos los mensajes que le envio el MockPeer, como lo hace el NetworkLoop con los de un peer real.
+/// Devuelve la cantidad de mensajes procesados.
+/// Devuelve CustomError si algun mensaje llego dañado o el PeerStreamLoop lo rechaza,
+/// en ese caso el NetworkLoop cerraria la conexion.
+pub fn deliver(stream_loop: &mut PeerStreamLoop<DuplexStream>) -> Result<usize, CustomError> {
+    let mut delivered = 0;
+    loop {
+        let (header, payload) = match stream_loop.stream.read_message() {
+            Ok(message) => message,
+            Err(
+                CustomError::Network(NetworkError::CannotReadMessageHeader)
+                | CustomError::Network(NetworkError::CannotReadStream),
+            ) => break,
+            Err(error) => return Err(error),
+        };
+        stream_loop.handle_message(&header, &payload)?;
+        delivered += 1;
+    }
+    Ok(delivered)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use crate::messages::{
+        get_addr::GetAddr,
+        ping_pong::{Ping, Pong},
+    };
+
+    use super::*;
+
+    fn address() -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv6Addr::LOCALHOST, 18333, 0, 0)
+    }
+
+    #[test]
+    fn messages_are_read_on_the_other_end() {
+        let (mut peer, mut node_stream) = MockPeer::new(address());
+
+        // sin mensajes la lectura no bloquea
+        let mut buffer = [0; 8];
+        assert_eq!(
+            node_stream.read(&mut buffer).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert!(node_stream.read_message().is_err());
+
+        Ping { nonce: 7 }.send(&mut peer).unwrap();
+        let header = MessageHeader::read(&mut node_stream).unwrap();
+        assert_eq!(header.command, Command::Ping);
+        assert_eq!(Ping::read(&mut node_stream, &header).unwrap().nonce, 7);
+        assert!(node_stream.is_empty().unwrap());
+
+        Pong { nonce: 7 }.send(&mut node_stream).unwrap();
+        GetAddr::new().send(&mut node_stream).unwrap();
+        assert_eq!(peer.expect::<Pong>().unwrap().nonce, 7);
+        assert_eq!(peer.received_commands(), vec![Command::GetAddr]);
+    }
+
+    #[test]
+    fn incomplete_messages_are_not_consumed() {
+        let (mut peer, mut node_stream) = MockPeer::new(address());
+        let ping = Ping { nonce: 7 };
+        let mut message = MessageHeader::new(&ping).serialize().to_vec();
+        message.extend(ping.serialize());
+
+        peer.write_all(&message[..30]).unwrap();
+        assert!(node_stream.read_message().is_err());
+        peer.write_all(&message[30..]).unwrap();
+        let (header, payload) = node_stream.read_message().unwrap();
+        assert_eq!(header.command, Command::Ping);
+        assert_eq!(payload, ping.serialize());
+    }
+
+    #[test]
+    fn expect_rejects_other_commands() {
+        let (mut peer, mut node_stream) = MockPeer::new(address());
+        GetAddr::new().send(&mut node_stream).unwrap();
+        assert!(matches!(
+            peer.expect::<Pong>(),
+            Err(CustomError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn dropping_an_end_closes_the_connection() {
+        let (mut peer, node_stream) = MockPeer::new(address());
+        drop(node_stream);
+
+        let mut buffer = [0; 8];
+        assert_eq!(peer.read(&mut buffer).unwrap(), 0);
+        assert!(Ping { nonce: 7 }.send(&mut peer).is_err());
+    }
+}
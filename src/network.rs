@@ -0,0 +1,418 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{error::CustomError, structs::hash256::Hash256, utils::hex_to_bytes};
+
+/// Challenge de la signet por defecto en hexadecimal: un multisig 1-of-2 de las claves de sus firmantes (BIP 325).
+const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+/// Red activa del proceso, se selecciona una unica vez al iniciar el nodo a partir del config.
+static ACTIVE_NETWORK: AtomicU8 = AtomicU8::new(Network::Testnet as u8);
+
+/// Network es la red de Bitcoin a la que se conecta el nodo.
+/// Cada red tiene sus propios parametros: magic number de los mensajes, puerto por defecto,
+/// bloque genesis, semillas DNS y reglas de dificultad.
+/// - Mainnet: Red principal.
+/// - Testnet: Red de pruebas testnet3.
+/// - Regtest: Red local de pruebas, los bloques se minan a demanda.
+/// - Signet: Red de pruebas en la que los bloques se firman por una autoridad (BIP 325).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+    Signet,
+}
+
+impl Network {
+    /// Selecciona la red con la que trabaja el proceso.
+    /// Debe llamarse antes de conectarse a los peers o cargar los headers.
+    pub fn select(network: Network) {
+        ACTIVE_NETWORK.store(network as u8, Ordering::Relaxed);
+    }
+
+    /// Devuelve la red seleccionada, por defecto testnet.
+    pub fn current() -> Network {
+        match ACTIVE_NETWORK.load(Ordering::Relaxed) {
+            0 => Network::Mainnet,
+            2 => Network::Regtest,
+            3 => Network::Signet,
+            _ => Network::Testnet,
+        }
+    }
+
+    /// Devuelve el nombre de la red, como se escribe en el archivo de configuracion.
+    pub fn name(&self) -> &str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+            Network::Signet => "signet",
+        }
+    }
+
+    /// Devuelve el magic number que encabeza los mensajes de la red.
+    pub fn magic(&self) -> u32 {
+        match self {
+            Network::Mainnet => 0xf9beb4d9,
+            Network::Testnet => 0x0b110907,
+            Network::Regtest => 0xfabfb5da,
+            Network::Signet => 0x0a03cf40,
+        }
+    }
+
+    /// Devuelve el puerto en el que escuchan por defecto los nodos de la red.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Network::Mainnet => 8333,
+            Network::Testnet => 18333,
+            Network::Regtest => 18444,
+            Network::Signet => 38333,
+        }
+    }
+
+    /// Devuelve el coin type del path BIP 44 de las wallets de la red (SLIP 44):
+    /// 0 en mainnet y 1 en todas las redes de prueba.
+    pub fn coin_type(&self) -> u32 {
+        match self {
+            Network::Mainnet => 0,
+            _ => 1,
+        }
+    }
+
+    /// Devuelve el human readable part de las direcciones bech32 de la red (BIP 173).
+    /// Signet comparte el de testnet.
+    pub fn bech32_hrp(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet | Network::Signet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+
+    /// Devuelve el prefijo de las direcciones P2PKH en base58 de la red, que comparten todas las redes de prueba.
+    pub fn p2pkh_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            _ => 0x6f,
+        }
+    }
+
+    /// Devuelve el prefijo de las direcciones P2SH en base58 de la red, que comparten todas las redes de prueba.
+    pub fn p2sh_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            _ => 0xc4,
+        }
+    }
+
+    /// Devuelve el prefijo base58 de las private keys en formato WIF de la red.
+    pub fn wif_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x80,
+            _ => 0xef,
+        }
+    }
+
+    /// Devuelve el timestamp a partir del cual se descargan los bloques completos para armar el UTXO set.
+    /// Los bloques anteriores solo se validan por sus headers, ya que las wallets no pueden tener fondos en ellos.
+    /// En regtest la cadena es local y corta, por lo que se descarga completa.
+    pub fn ibd_start_timestamp(&self) -> u32 {
+        match self {
+            Network::Regtest => self.genesis_timestamp(),
+            _ => 1681095630,
+        }
+    }
+
+    /// Devuelve el hash del bloque genesis de la red, en little endian.
+    pub fn genesis_hash(&self) -> Hash256 {
+        match self {
+            Network::Mainnet => Hash256::new([
+                111, 226, 140, 10, 182, 241, 179, 114, 193, 166, 162, 70, 174, 99, 247, 79, 147,
+                30, 131, 101, 225, 90, 8, 156, 104, 214, 25, 0, 0, 0, 0, 0,
+            ]),
+            Network::Testnet => Hash256::new([
+                67, 73, 127, 215, 248, 38, 149, 113, 8, 244, 163, 15, 217, 206, 195, 174, 186, 121,
+                151, 32, 132, 233, 14, 173, 1, 234, 51, 9, 0, 0, 0, 0,
+            ]),
+            Network::Regtest => Hash256::new([
+                6, 34, 110, 70, 17, 26, 11, 89, 202, 175, 18, 96, 67, 235, 91, 191, 40, 195, 79,
+                58, 94, 51, 42, 31, 199, 178, 183, 60, 241, 136, 145, 15,
+            ]),
+            Network::Signet => Hash256::new([
+                246, 30, 238, 59, 99, 163, 128, 164, 119, 160, 99, 175, 50, 178, 187, 201, 124,
+                159, 249, 240, 31, 44, 66, 37, 233, 115, 152, 129, 8, 0, 0, 0,
+            ]),
+        }
+    }
+
+    /// Devuelve el timestamp del bloque genesis de la red.
+    pub fn genesis_timestamp(&self) -> u32 {
+        match self {
+            Network::Mainnet => 1231006505,
+            Network::Testnet | Network::Regtest => 1296688602,
+            Network::Signet => 1598918400,
+        }
+    }
+
+    /// Devuelve la dificultad minima de la red (pow limit) en formato compacto.
+    pub fn pow_limit_bits(&self) -> u32 {
+        match self {
+            Network::Mainnet | Network::Testnet => 0x1d00ffff,
+            Network::Regtest => 0x207fffff,
+            Network::Signet => 0x1e0377ae,
+        }
+    }
+
+    /// Devuelve el script que deben resolver las firmas de los bloques si la red es signet, o None si no lo es.
+    pub fn signet_challenge(&self) -> Option<Vec<u8>> {
+        match self {
+            Network::Signet => hex_to_bytes(DEFAULT_SIGNET_CHALLENGE),
+            _ => None,
+        }
+    }
+
+    /// Devuelve true si la red permite bloques de dificultad minima cuando pasaron
+    /// mas de 20 minutos desde el bloque anterior.
+    pub fn allows_min_difficulty_blocks(&self) -> bool {
+        matches!(self, Network::Testnet | Network::Regtest)
+    }
+
+    /// Devuelve true si la red ajusta la dificultad cada 2016 bloques.
+    pub fn retargets_difficulty(&self) -> bool {
+        !matches!(self, Network::Regtest)
+    }
+
+    /// Devuelve las heights desde las que la red exige cada version minima de los headers:
+    /// 2 desde BIP 34, 3 desde BIP 66 y 4 desde BIP 65. Son las mismas que usa Bitcoin Core.
+    pub fn version_activation_heights(&self) -> [(usize, i32); 3] {
+        match self {
+            Network::Mainnet => [(227931, 2), (363725, 3), (388381, 4)],
+            Network::Testnet => [(21111, 2), (330776, 3), (581885, 4)],
+            Network::Regtest | Network::Signet => [(1, 2), (1, 3), (1, 4)],
+        }
+    }
+
+    /// Devuelve la version minima que debe tener el header de la height recibida.
+    /// Las versiones que señalizan soft forks con version bits (BIP 9) son mayores a 4, por lo que se aceptan.
+    pub fn min_block_version(&self, height: usize) -> i32 {
+        self.version_activation_heights()
+            .iter()
+            .filter(|(activation_height, _)| height >= *activation_height)
+            .map(|(_, version)| *version)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Devuelve los checkpoints de la red: height y hash del header en hexadecimal, en el orden en que
+    /// lo muestran los exploradores. Son los mismos que usa Bitcoin Core.
+    /// Los headers de esas heights deben tener ese hash, y los anteriores al ultimo checkpoint no requieren
+    /// validar la dificultad ni el timestamp, ver chain_validator.
+    pub fn checkpoints(&self) -> &[(usize, &str)] {
+        match self {
+            Network::Mainnet => &[
+                (
+                    11111,
+                    "0000000069e244f73d78e8fd29ba2fd2ed618bd6fa2ee92559f542fdb26e7c1d",
+                ),
+                (
+                    33333,
+                    "000000002dd5588a74784eaa7ab0507a18ad16a236e7b1ce69f00d7ddfb5d0a6",
+                ),
+                (
+                    74000,
+                    "0000000000573993a3c9e41ce34471c079dcf5f52a0e824a81e7f953b8661a20",
+                ),
+                (
+                    105000,
+                    "00000000000291ce28027faea320c8d2b054b2e0fe44a773f3eefb151d6bdc97",
+                ),
+                (
+                    134444,
+                    "00000000000005b12ffd4cd315cd34ffd4a594f430ac814c91184a0d42d2b0fe",
+                ),
+                (
+                    168000,
+                    "000000000000099e61ea72015e79632f216fe6cb33d7899acb35b75c8303b763",
+                ),
+                (
+                    193000,
+                    "000000000000059f452a5f7340de6682a977387c17010ff6e6c3bd83ca8b1317",
+                ),
+                (
+                    210000,
+                    "000000000000048b95347e83192f69cf0366076336c639f9b7228e9ba171342e",
+                ),
+                (
+                    216116,
+                    "00000000000001b4f4b433e81ee46494af945cf96014816a4e2370f11b23df4e",
+                ),
+                (
+                    225430,
+                    "00000000000001c108384350f74090433e7fcf79a606b8e797f065b130575932",
+                ),
+                (
+                    250000,
+                    "000000000000003887df1f29024b06fc2200b55f8af8f35453d7be294df2d214",
+                ),
+                (
+                    279000,
+                    "0000000000000001ae8c72a0b0c301f67e3afca10e819efa9041e458e9bd7e40",
+                ),
+                (
+                    295000,
+                    "00000000000000004d9b4ef50f0f9d686fd69db2e03af35a100370c64632a983",
+                ),
+            ],
+            Network::Testnet => &[(
+                546,
+                "000000002a936ca763904c3c35fce2f3556c559c0214345d31b1bcebf76acb70",
+            )],
+            Network::Regtest | Network::Signet => &[],
+        }
+    }
+
+    /// Devuelve las semillas DNS de las que se obtienen direcciones de peers de la red.
+    /// Regtest no tiene semillas, los peers se deben configurar explicitamente.
+    pub fn dns_seeds(&self) -> &[&str] {
+        match self {
+            Network::Mainnet => &[
+                "seed.bitcoin.sipa.be",
+                "dnsseed.bluematt.me",
+                "seed.bitcoin.jonasschnelli.ch",
+                "seed.btc.petertodd.net",
+                "seed.bitcoin.sprovoost.nl",
+            ],
+            Network::Testnet => &[
+                "testnet-seed.bitcoin.jonasschnelli.ch",
+                "seed.tbtc.petertodd.net",
+                "seed.testnet.bitcoin.sprovoost.nl",
+                "testnet-seed.bluematt.me",
+            ],
+            Network::Regtest => &[],
+            Network::Signet => &["seed.signet.bitcoin.sprovoost.nl"],
+        }
+    }
+
+    /// Devuelve peers fijos de la red con los servicios que ofrecian, a los que se recurre si ninguna semilla DNS responde.
+    /// Es una foto de nodos que estaban activos, algunos pueden ya no estarlo.
+    pub fn fixed_seeds(&self) -> &[(&str, u64)] {
+        match self {
+            Network::Mainnet => &[
+                ("1.65.195.98:8333", 0x409),
+                ("2.59.236.56:8333", 0x409),
+                ("2.83.114.20:8333", 0x409),
+                ("2.248.194.16:8333", 0x409),
+                ("5.2.154.6:8333", 0x409),
+                ("5.101.140.30:8333", 0x408),
+            ],
+            Network::Testnet => &[("5.19.5.127:18333", 0x409), ("13.250.27.195:18333", 0x409)],
+            Network::Regtest | Network::Signet => &[],
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = CustomError;
+
+    /// Lee una red a partir de su nombre: mainnet, testnet, regtest o signet.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "mainnet" | "main" => Ok(Network::Mainnet),
+            "testnet" | "testnet3" | "test" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            "signet" => Ok(Network::Signet),
+            _ => Err(CustomError::Validation(format!("Invalid network {name}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::block_header::BlockHeader;
+
+    use super::*;
+
+    /// Merkle root de los bloques genesis, comun a todas las redes.
+    const GENESIS_MERKLE_ROOT: [u8; 32] = [
+        59, 163, 237, 253, 122, 123, 18, 178, 122, 199, 44, 62, 103, 118, 143, 97, 127, 200, 27,
+        195, 136, 138, 81, 50, 58, 159, 184, 170, 75, 30, 94, 74,
+    ];
+
+    fn genesis_header(network: Network, nonce: u32) -> BlockHeader {
+        let mut buffer = 1_i32.to_le_bytes().to_vec();
+        buffer.extend([0; 32]);
+        buffer.extend(GENESIS_MERKLE_ROOT);
+        buffer.extend(network.genesis_timestamp().to_le_bytes());
+        buffer.extend(network.pow_limit_bits().to_le_bytes());
+        buffer.extend(nonce.to_le_bytes());
+        BlockHeader::parse(buffer).unwrap()
+    }
+
+    #[test]
+    fn network_from_str() {
+        assert_eq!(Network::from_str("mainnet").unwrap(), Network::Mainnet);
+        assert_eq!(Network::from_str("testnet3").unwrap(), Network::Testnet);
+        assert_eq!(Network::from_str("REGTEST").unwrap(), Network::Regtest);
+        assert_eq!(Network::from_str("signet").unwrap(), Network::Signet);
+        assert!(Network::from_str("litecoin").is_err());
+    }
+
+    #[test]
+    fn address_parameters_of_each_network() {
+        assert_eq!(Network::Mainnet.coin_type(), 0);
+        assert_eq!(Network::Signet.coin_type(), 1);
+        assert_eq!(Network::Mainnet.bech32_hrp(), "bc");
+        assert_eq!(Network::Signet.bech32_hrp(), "tb");
+        assert_eq!(Network::Regtest.bech32_hrp(), "bcrt");
+        assert_eq!(Network::Mainnet.p2pkh_prefix(), 0x00);
+        assert_eq!(Network::Regtest.p2sh_prefix(), 0xc4);
+        assert_eq!(Network::Mainnet.wif_prefix(), 0x80);
+        assert_eq!(Network::Signet.wif_prefix(), 0xef);
+    }
+
+    #[test]
+    fn genesis_blocks() {
+        let networks = [
+            (Network::Mainnet, 2083236893),
+            (Network::Testnet, 414098458),
+            (Network::Regtest, 2),
+            (Network::Signet, 52613770),
+        ];
+        for (network, nonce) in networks {
+            let header = genesis_header(network, nonce);
+            assert_eq!(*header.hash(), network.genesis_hash());
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_sorted_hashes() {
+        for network in [Network::Mainnet, Network::Testnet] {
+            let checkpoints = network.checkpoints();
+            assert!(checkpoints.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert!(checkpoints
+                .iter()
+                .all(|(_, hash)| hash.len() == 64 && hash.starts_with("0000000")));
+        }
+    }
+
+    #[test]
+    fn min_block_version_by_height() {
+        assert_eq!(Network::Mainnet.min_block_version(227930), 1);
+        assert_eq!(Network::Mainnet.min_block_version(227931), 2);
+        assert_eq!(Network::Mainnet.min_block_version(363725), 3);
+        assert_eq!(Network::Mainnet.min_block_version(800000), 4);
+        assert_eq!(Network::Testnet.min_block_version(21111), 2);
+        assert_eq!(Network::Regtest.min_block_version(0), 1);
+        assert_eq!(Network::Regtest.min_block_version(1), 4);
+    }
+
+    #[test]
+    fn default_network_is_testnet() {
+        assert_eq!(Network::current(), Network::Testnet);
+        assert_eq!(Network::current().magic(), 0x0b110907);
+        assert_eq!(Network::current().default_port(), 18333);
+    }
+}
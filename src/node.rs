@@ -10,45 +10,67 @@ use gtk::glib;
 use crate::{
     config::Config,
     error::CustomError,
+    exchange_rate::HttpRateProvider,
     gui::init::GUIEvents,
-    logger::{send_log, Log, Logger},
+    logger::{send_log, Log, LogEntry, Logger},
     loops::{
+        block_download_loop::block_download_loop,
+        exchange_rate_loop::ExchangeRateLoop,
+        filter_sync_loop::filter_sync_loop,
+        network_loop::{BlockBacklog, NetworkHandle, NetworkLoop},
         node_action_loop::{NodeAction, NodeActionLoop},
         peer_action_loop::PeerAction,
-        pending_blocks_loop::pending_blocks_loop,
+        peer_discovery_loop::PeerDiscoveryLoop,
         tcp_listener_loop::TcpListenerLoop,
     },
     node_state::NodeState,
     peer::{request_headers, Peer},
+    shutdown::ShutdownCoordinator,
+    utils::get_address_v6,
 };
 
+/// Cantidad de peers de los que se descargan los headers en simultaneo durante la sincronizacion inicial.
+const HEADER_SYNC_PEERS: usize = 3;
+
 /// Node es la estructura que representa nuestro nodo.
 /// Los elementos son:
 /// - address: Direccion del nodo.
 /// - services: Servicios que ofrece el nodo.
 /// - version: Version del nodo.
 /// - client_only: Indica si el nodo es completo o solo cliente.
+/// - max_inbound: Cantidad maxima de peers entrantes, si el nodo no es solo cliente.
 /// - logger_sender: Sender para enviar logs al logger.
 /// - peer_action_sender: Sender para enviar acciones al los peers.
-/// - peer_action_receiver: Receiver para recibir acciones del peer.
 /// - node_action_sender: Sender para enviar acciones al nodo.
 /// - node_action_receiver: Receiver para recibir acciones del nodo.
-/// - tcp_listener_thread: Thread del loop para atender conexiones entrantes a este nodo.
+/// - network: NetworkHandle para registrar las conexiones con los peers en el NetworkLoop.
+/// - network_loop: NetworkLoop que atiende a los peers, hasta que se inicializa su thread.
+/// - block_backlog: BlockBacklog del NetworkLoop, en el que el NodeActionLoop registra los bloques procesados.
+/// - shutdown: ShutdownCoordinator con los threads de los loops del nodo, para detenerlos al cerrarlo.
 /// - node_state_ref: Referencia al estado del nodo.
 /// - npeers: Cantidad de peers.
+/// - filters_start_height: Altura desde la que se escanean filtros compactos, si esta habilitado.
+/// - exchange_rate_url: Url de la API del precio de bitcoin en monedas fiat, si esta habilitado.
+/// - exchange_rate_interval: Tiempo en segundos entre cada actualizacion del precio.
 pub struct Node {
     pub address: SocketAddrV6,
     pub services: u64,
     pub version: i32,
     client_only: bool,
-    logger_sender: mpsc::Sender<Log>,
+    max_inbound: usize,
+    logger_sender: mpsc::Sender<LogEntry>,
     peer_action_sender: mpsc::Sender<PeerAction>,
-    peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
     pub node_action_sender: mpsc::Sender<NodeAction>,
     node_action_receiver: Option<mpsc::Receiver<NodeAction>>,
-    tcp_listener_thread: Option<thread::JoinHandle<Result<(), CustomError>>>,
+    network: NetworkHandle,
+    network_loop: Option<NetworkLoop>,
+    block_backlog: BlockBacklog,
+    shutdown: ShutdownCoordinator,
     node_state_ref: Arc<Mutex<NodeState>>,
     npeers: u8,
+    filters_start_height: Option<usize>,
+    exchange_rate_url: Option<String>,
+    exchange_rate_interval: u64,
 }
 
 impl Node {
@@ -60,42 +82,78 @@ impl Node {
         node_state_ref: Arc<Mutex<NodeState>>,
     ) -> Result<Self, CustomError> {
         let logger_sender = logger.get_sender();
-        let (peer_action_sender, receiver) = mpsc::channel();
-        let peer_action_receiver = Arc::new(Mutex::new(receiver));
+        let (peer_action_sender, peer_action_receiver) = mpsc::channel();
         let (node_action_sender, node_action_receiver) = mpsc::channel();
+        let peer_info_ref = node_state_ref.lock()?.get_peer_info_registry();
+        let (network_loop, network) = NetworkLoop::new(
+            peer_action_receiver,
+            node_action_sender.clone(),
+            logger_sender.clone(),
+            peer_info_ref,
+        )?;
+        let block_backlog = network_loop.block_backlog();
 
         let node = Self {
-            address: SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), config.port, 0, 0),
+            address: SocketAddrV6::new(
+                Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),
+                config.get_port(),
+                0,
+                0,
+            ),
             services: 0x00,
             version: config.protocol_version,
             client_only: config.client_only,
+            max_inbound: config.max_inbound,
+            shutdown: ShutdownCoordinator::new(logger_sender.clone()),
             logger_sender,
             peer_action_sender,
-            peer_action_receiver,
             node_action_sender,
             node_action_receiver: Some(node_action_receiver),
-            tcp_listener_thread: None,
+            network,
+            network_loop: Some(network_loop),
+            block_backlog,
             npeers: config.npeers,
+            filters_start_height: config.filters_start_height,
+            exchange_rate_url: config.exchange_rate_url.clone(),
+            exchange_rate_interval: config.exchange_rate_interval,
             node_state_ref,
         };
+        let mut node_state = node.node_state_ref.lock()?;
+        node_state.set_ban_time(config.ban_time);
+        node_state.set_prune_mode(config.prune);
+        node_state.set_max_fee(config.max_fee);
+        node_state.set_spend_min_confirmations(config.spend_min_confirmations);
+        node_state.set_desktop_notifications(config.desktop_notifications);
+        node_state.set_filters_start_height(config.filters_start_height);
+        drop(node_state);
 
         Ok(node)
     }
 
     /// Inicializa el nodo en un thread.
-    /// Comienza el thread de pending_blocks_loop.
+    /// Comienza el thread de network_loop.
+    /// Comienza el thread de block_download_loop.
     /// Comienza el thread de tcp_listener_loop.
+    /// Comienza el thread de filter_sync_loop si esta habilitado.
+    /// Comienza el thread de peer_discovery_loop.
+    /// Comienza el thread de exchange_rate_loop si esta habilitado.
     /// Comienza la descarga de headers.
     /// Comienza el thread de node_action_loop.
+    /// Devuelve el NodeHandle con el que se detiene el nodo.
     pub fn spawn(
         mut self,
         addresses: IntoIter<SocketAddr>,
         gui_sender: glib::Sender<GUIEvents>,
-    ) -> JoinHandle<Result<(), CustomError>> {
-        self.initialize_pending_blocks_loop();
+    ) -> NodeHandle {
+        self.initialize_network_loop();
+        self.initialize_block_download_loop();
         self.initialize_tcp_listener_loop();
+        self.initialize_filter_sync_loop();
+        self.initialize_peer_discovery_loop();
+        self.initialize_exchange_rate_loop();
 
-        thread::spawn(move || -> Result<(), CustomError> {
+        let node_action_sender = self.node_action_sender.clone();
+        let thread = thread::spawn(move || -> Result<(), CustomError> {
             if let Err(error) = self.connect(addresses, self.npeers) {
                 send_log(&self.logger_sender, Log::Error(error));
             }
@@ -106,26 +164,41 @@ impl Node {
                 send_log(&self.logger_sender, Log::Error(error));
             }
             Ok(())
-        })
+        });
+        NodeHandle {
+            node_action_sender,
+            thread,
+        }
     }
 
+    /// Se conecta primero a las direcciones conocidas de ejecuciones anteriores y luego a las de la semilla DNS.
     fn connect(
         &mut self,
         addresses: IntoIter<SocketAddr>,
         mut number_of_peers: u8,
     ) -> Result<(), CustomError> {
+        let node_state = self.node_state_ref.lock()?;
+        let mut candidates = node_state.get_peer_candidates(number_of_peers as usize);
+        for address in addresses {
+            if !candidates.contains(&address) && !node_state.is_banned(&get_address_v6(address)) {
+                candidates.push(address);
+            }
+        }
+        drop(node_state);
+
         send_log(
             &self.logger_sender,
             Log::Message(format!(
                 "Handshaking with {} nodes ({} available)",
                 number_of_peers,
-                addresses.len()
+                candidates.len()
             )),
         );
 
         let mut peers = vec![];
+        let mut failed = vec![];
 
-        for address in addresses {
+        for address in candidates {
             if number_of_peers == 0 {
                 break;
             }
@@ -135,9 +208,8 @@ impl Node {
                 self.address,
                 self.services,
                 self.version,
-                self.peer_action_receiver.clone(),
+                &self.network,
                 self.logger_sender.clone(),
-                self.node_action_sender.clone(),
             ) {
                 Ok(peer) => {
                     peers.push(peer);
@@ -146,58 +218,121 @@ impl Node {
                 Err(error) => {
                     send_log(
                         &self.logger_sender,
-                        Log::Message(format!("Error connecting to peer: {:?}", error)),
+                        Log::Warning(format!("Error connecting to peer: {}", error)),
                     );
+                    failed.push(get_address_v6(address));
                 }
             };
         }
 
         let mut node_state = self.node_state_ref.lock()?;
+        for peer in &peers {
+            node_state.peer_connected(peer.address, peer.services)?;
+        }
+        for address in failed {
+            node_state.peer_connection_failed(address)?;
+        }
         node_state.append_peers(peers);
         Ok(())
     }
 
-    fn initialize_pending_blocks_loop(&self) {
-        pending_blocks_loop(
+    fn initialize_network_loop(&mut self) {
+        if let Some(network_loop) = self.network_loop.take() {
+            self.shutdown.register("network loop", network_loop.spawn());
+        }
+    }
+
+    fn initialize_block_download_loop(&mut self) {
+        let thread = block_download_loop(
             self.node_state_ref.clone(),
-            self.peer_action_sender.clone(),
             self.logger_sender.clone(),
+            self.shutdown.signal(),
         );
+        self.shutdown.register("block download loop", thread);
     }
 
-    fn initialize_tcp_listener_loop(&mut self) {
-        if !self.client_only {
-            self.tcp_listener_thread = Some(TcpListenerLoop::spawn(
-                self.logger_sender.clone(),
+    fn initialize_filter_sync_loop(&mut self) {
+        if self.filters_start_height.is_some() {
+            let thread = filter_sync_loop(
                 self.node_state_ref.clone(),
-                self.address,
-                self.services,
-                self.version,
-                self.peer_action_receiver.clone(),
+                self.logger_sender.clone(),
                 self.node_action_sender.clone(),
-            ));
+                self.shutdown.signal(),
+            );
+            self.shutdown.register("filter sync loop", thread);
+        }
+    }
+
+    fn initialize_peer_discovery_loop(&mut self) {
+        let peer_discovery_loop = PeerDiscoveryLoop {
+            logger_sender: self.logger_sender.clone(),
+            node_state_ref: self.node_state_ref.clone(),
+            address: self.address,
+            services: self.services,
+            version: self.version,
+            npeers: self.npeers,
+            network: self.network.clone(),
+            shutdown: self.shutdown.signal(),
+        };
+        self.shutdown
+            .register("peer discovery loop", peer_discovery_loop.spawn());
+    }
+
+    fn initialize_exchange_rate_loop(&mut self) {
+        if let Some(url) = &self.exchange_rate_url {
+            let exchange_rate_loop = ExchangeRateLoop {
+                logger_sender: self.logger_sender.clone(),
+                node_state_ref: self.node_state_ref.clone(),
+                provider: Box::new(HttpRateProvider::new(url.clone())),
+                interval: self.exchange_rate_interval,
+                shutdown: self.shutdown.signal(),
+            };
+            self.shutdown
+                .register("exchange rate loop", exchange_rate_loop.spawn());
+        }
+    }
+
+    fn initialize_tcp_listener_loop(&mut self) {
+        if !self.client_only {
+            let tcp_listener_loop = TcpListenerLoop {
+                logger_sender: self.logger_sender.clone(),
+                node_state_ref: self.node_state_ref.clone(),
+                address: self.address,
+                services: self.services,
+                version: self.version,
+                max_inbound: self.max_inbound,
+                network: self.network.clone(),
+                shutdown: self.shutdown.signal(),
+            };
+            self.shutdown
+                .register("tcp listener loop", tcp_listener_loop.spawn());
         }
     }
 
     fn initialize_ibd(&self) -> Result<(), CustomError> {
         let mut node_state = self.node_state_ref.lock()?;
-        let last_header = node_state.get_last_header_hash();
-        let fastest_peer = node_state.get_fastest_peer();
+        let block_locator = node_state.get_block_locator()?;
+        let fastest_peers = node_state.get_fastest_peers(HEADER_SYNC_PEERS);
 
-        if let Some(fastest_peer) = fastest_peer {
-            request_headers(
-                last_header,
-                self.version,
-                &mut fastest_peer.stream,
-                &self.logger_sender,
-                &self.node_action_sender,
-            )?;
+        // cada peer envia su cadena completa, para compararlas y quedarse con la de mas trabajo
+        if !fastest_peers.is_empty() {
+            let mut addresses = vec![];
+            for peer in fastest_peers {
+                request_headers(
+                    block_locator.clone(),
+                    self.version,
+                    &mut peer.stream,
+                    &self.logger_sender,
+                    &self.node_action_sender,
+                )?;
+                addresses.push(peer.address.ip().to_string());
+            }
 
             send_log(
                 &self.logger_sender,
                 Log::Message(format!(
-                    "Starting headers download with fastest peer: {}",
-                    fastest_peer.address.ip()
+                    "Starting headers download with fastest peers: {}",
+                    addresses.join(", ")
                 )),
             );
 
@@ -206,7 +341,7 @@ impl Node {
 
         drop(node_state);
         self.peer_action_sender
-            .send(PeerAction::GetHeaders(last_header))?;
+            .send(PeerAction::GetHeaders(block_locator))?;
         Ok(())
     }
 
@@ -221,6 +356,7 @@ impl Node {
                 self.peer_action_sender.clone(),
                 self.logger_sender.clone(),
                 self.node_state_ref.clone(),
+                self.block_backlog.clone(),
             );
             return Ok(());
         }
@@ -228,22 +364,48 @@ impl Node {
     }
 }
 
+/// NodeHandle es el nodo iniciado con Node::spawn, permite detenerlo y esperar a que termine.
+/// Los elementos son:
+/// - node_action_sender: Sender para enviar acciones al nodo.
+/// - thread: Thread del nodo.
+pub struct NodeHandle {
+    pub node_action_sender: mpsc::Sender<NodeAction>,
+    thread: JoinHandle<Result<(), CustomError>>,
+}
+
+impl NodeHandle {
+    /// Detiene el nodo y espera a que termine su thread.
+    /// Al volver ya terminaron todos los loops del nodo y su estado esta guardado en disco,
+    /// por lo que se puede volver a iniciar un nodo con el mismo store.
+    /// Devuelve CustomError si el nodo ya se habia detenido o su thread termino con un error.
+    pub fn stop(self) -> Result<(), CustomError> {
+        self.node_action_sender.send(NodeAction::Terminate)?;
+        self.thread.join().map_err(|_| CustomError::UnknownError)?
+    }
+}
+
 impl Drop for Node {
-    /// Espera a que los threads de PeerActions terminen.
-    /// Estos junto al NodeActionLoop son los únicos threads que modifican el estado del nodo, por lo que hace falta esperarlos.
+    /// Cierra el nodo una vez que termino el NodeActionLoop.
+    /// Detiene el NetworkLoop y los demas loops y espera a que terminen, ya que todos modifican el estado del nodo.
+    /// Recien entonces guarda el estado en disco, para que ningun archivo quede escrito a medias al terminar el programa.
     fn drop(&mut self) {
-        if let Ok(mut node_state) = self.node_state_ref.lock() {
-            let peers = node_state.get_peers();
-            for _ in peers.iter() {
-                self.peer_action_sender.send(PeerAction::Terminate).unwrap();
-            }
-            for peer in peers {
-                if let Some(thread) = peer.peer_action_thread.take() {
-                    if let Err(error) = thread.join() {
-                        println!("Error joining thread: {:?}", error);
-                    }
-                }
-            }
+        send_log(
+            &self.logger_sender,
+            Log::Message(String::from("Shutting down node...")),
+        );
+        let _ = self.peer_action_sender.send(PeerAction::Terminate);
+        self.shutdown.shutdown();
+
+        let result = self
+            .node_state_ref
+            .lock()
+            .map_err(CustomError::from)
+            .and_then(|mut node_state| node_state.flush());
+        if let Err(error) = result {
+            send_log(
+                &self.logger_sender,
+                Log::Warning(format!("Error saving node state: {}", error)),
+            );
         }
     }
 }
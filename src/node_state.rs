@@ -1,56 +1,152 @@
 use std::{
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
     fs,
-    net::SocketAddrV6,
+    net::{SocketAddr, SocketAddrV6},
     path::Path,
     sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use gtk::glib::Sender;
 
 use crate::{
-    error::CustomError,
+    block_store::{BlockStore, PruneMode},
+    chain_validator::{
+        last_checkpoint_height, validate_headers_from, ChainWork, DIFFICULTY_ADJUSTMENT_INTERVAL,
+    },
+    error::{ChainError, CustomError, WalletError},
+    exchange_rate::ExchangeRates,
+    fee_estimator::{FeeEstimator, FeePriority, MIN_FEE_RATE},
     gui::init::GUIEvents,
-    logger::{send_log, Log},
-    messages::{block::Block, get_headers::GetHeaders, headers::Headers, transaction::Transaction},
-    peer::Peer,
+    headers_store::HeaderChain,
+    logger::{send_log, Log, LogEntry},
+    merkle_proof::MerkleProof,
+    messages::{
+        block::Block, cfheaders::CFHeaders, cfilter::CFilter, compact_block::PartialBlock,
+        filter_load::FilterLoad, get_headers::GetHeaders, headers::Headers,
+        merkle_block::FilteredBlock, send_cmpct::COMPACT_BLOCKS_VERSION, transaction::Transaction,
+    },
+    network::Network,
+    notifications::PaymentNotifier,
+    payment_uri::PaymentUri,
+    peer::{DataRequest, Peer, NODE_BLOOM, NODE_NETWORK},
+    psbt::Psbt,
     states::{
+        addr_man_state::AddrMan,
+        address_book_state::{AddressBook, Contact},
+        block_downloader_state::BlockDownloader,
         blocks_state::BlocksState,
-        headers_state::HeadersState,
+        filters_state::{FiltersRequest, FiltersState, NODE_COMPACT_FILTERS},
+        header_sync_state::{HeaderBranch, HeaderSync},
+        headers_state::{HeadersState, MAX_HEADERS_PER_MESSAGE},
+        history_state::{History, HistoryEntry},
+        inventory_scheduler_state::InventoryScheduler,
+        locked_utxo_state::LockedUTXO,
+        mempool_state::Mempool,
+        peer_info_state::{NetTotals, PeerInfo, PeerInfoRegistry},
+        peer_score_state::{Misbehavior, PeerScore},
         pending_blocks_state::PendingBlocks,
-        pending_txs_state::PendingTxs,
+        sync_progress_state::{SyncMilestone, SyncProgress, SyncProgressTracker},
+        tx_broadcasts_state::{BroadcastStatus, TxBroadcasts},
         utxo_state::{UTXOValue, UTXO},
         wallets_state::WalletsState,
     },
-    structs::{block_header::BlockHeader, movement::Movement, outpoint::OutPoint},
-    wallet::Wallet,
+    structs::{
+        balances::{Balances, COINBASE_MATURITY},
+        block_filter::genesis_filter_header,
+        block_header::{hash_as_string, BlockHeader},
+        bloom_filter::{BloomFilter, BLOOM_UPDATE_ALL},
+        hash256::Hash256,
+        inventory::{Inventory, InventoryType},
+        network_address::NetworkAddress,
+        outpoint::OutPoint,
+        script::Script,
+        transaction_summary::TransactionSummary,
+        tx_output::TransactionOutput,
+    },
+    transaction_builder::{
+        estimate_vsize, Fee, Payment, TransactionBuilder, DEFAULT_MAX_FEE,
+        DEFAULT_SPEND_MIN_CONFIRMATIONS,
+    },
+    utxo_set::UTXOSet,
+    wallet::{get_address, Wallet},
 };
 
+/// Tasa de falsos positivos del bloom filter enviado a los peers.
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.0001;
+/// Tiempo maximo que se espera las transacciones faltantes de un compact block antes de descartarlo.
+const PARTIAL_BLOCK_TIMEOUT: Duration = Duration::from_secs(20);
+
 /// NodeState es una estructura que contiene el estado del nodo.
 /// Los elementos son (para mas informacion de cada una de estas estructuras ver su documentacion en la carpeta states):
 /// - logger_sender: Sender para enviar logs al logger.
 /// - gui_sender: Sender para enviar eventos a la interfaz grafica.
 /// - headers: HeadersState.
+/// - header_sync: HeaderSync, ramas de headers de los peers que se apartan de la cadena del nodo.
 /// - peers: Vector de peers conectados al nodo.
 /// - wallets: WalletsState.
 /// - blocks: BlocksState.
 /// - utxo: UTXO.
-/// - pending_txs: PendingTxs.
+/// - utxo_set: UTXOSet, UTXO y balance de cada wallet.
+/// - mempool: Mempool, transacciones sin confirmar recibidas de los peers o enviadas por el nodo.
+/// - partial_blocks: Bloques recibidos como compact blocks a los que les faltan transacciones, con el momento en que se recibieron.
+/// - filters: FiltersState, solo se inicializa si se habilita la descarga de filtros compactos.
+/// - filters_start_height: Altura desde la que se escanean filtros compactos, None si no esta habilitado.
+/// - tx_broadcasts: TxBroadcasts, estado de propagacion de las transacciones enviadas.
+/// - block_downloader: BlockDownloader, reparte la descarga de bloques entre los peers.
+/// - inventory_scheduler: InventoryScheduler, pedidos de transacciones a los peers y sus reintentos.
+/// - addr_man: AddrMan, direcciones de nodos conocidas para conectarse.
+/// - peer_score: PeerScore, faltas de los peers conectados y direcciones baneadas.
+/// - peer_info: PeerInfoRegistry, informacion de los peers conectados que actualiza el NetworkLoop.
+/// - fee_estimator: FeeEstimator, estima el fee de las transacciones a partir de los bloques recientes.
+/// - history: History, historial de transacciones de cada wallet con la height de sus bloques.
+/// - history_blocks: Height y timestamp de los bloques con movimientos de las wallets, para no leerlos de los headers en cada actualizacion del historial.
+/// - address_book: AddressBook, libreta de direcciones con los contactos a los que se envian fondos.
+/// - locked_utxo: LockedUTXO, UTXO que el usuario reservo y que no se seleccionan para financiar transacciones.
+/// - max_fee: Fee total maximo en satoshis de las transacciones que crea la wallet.
+/// - spend_min_confirmations: Confirmaciones minimas de las UTXO que se seleccionan para financiar las transacciones.
+/// - exchange_rates: Ultimo precio de bitcoin en monedas fiat, None si todavia no se obtuvo.
+/// - payment_notifier: PaymentNotifier, genera las notificaciones de los pagos recibidos por las wallets.
+/// - desktop_notifications: Indica si las notificaciones tambien se muestran en el escritorio.
+/// - sync_progress: SyncProgressTracker, progreso de la descarga inicial de headers y bloques.
 pub struct NodeState {
-    logger_sender: mpsc::Sender<Log>,
+    logger_sender: mpsc::Sender<LogEntry>,
     gui_sender: Sender<GUIEvents>,
     headers: HeadersState,
+    header_sync: HeaderSync,
     peers: Vec<Peer>,
     wallets: WalletsState,
     blocks: BlocksState,
     utxo: UTXO,
-    pending_txs: PendingTxs,
+    utxo_set: UTXOSet,
+    mempool: Mempool,
+    partial_blocks: HashMap<Hash256, (PartialBlock, Instant)>,
+    filters: Option<FiltersState>,
+    filters_start_height: Option<usize>,
+    tx_broadcasts: TxBroadcasts,
+    block_downloader: BlockDownloader,
+    inventory_scheduler: InventoryScheduler,
+    addr_man: AddrMan,
+    peer_score: PeerScore,
+    peer_info: Arc<Mutex<PeerInfoRegistry>>,
+    fee_estimator: FeeEstimator,
+    history: History,
+    history_blocks: HashMap<Hash256, (usize, u32)>,
+    address_book: AddressBook,
+    locked_utxo: LockedUTXO,
+    max_fee: u64,
+    spend_min_confirmations: usize,
+    exchange_rates: Option<ExchangeRates>,
+    payment_notifier: PaymentNotifier,
+    desktop_notifications: bool,
+    sync_progress: SyncProgressTracker,
 }
 
 impl NodeState {
     /// Inicializa el estado del nodo. Inicializa todas sus estructuras indicando donde se encuentra el archivo donde se guardan.
     pub fn new(
-        logger_sender: mpsc::Sender<Log>,
+        logger_sender: mpsc::Sender<LogEntry>,
         gui_sender: Sender<GUIEvents>,
         store_path: &String,
     ) -> Result<Arc<Mutex<Self>>, CustomError> {
@@ -62,38 +158,98 @@ impl NodeState {
 
         let headers =
             HeadersState::new(format!("{}/headers.bin", store_path), logger_sender.clone())?;
-        let pending_blocks_ref = PendingBlocks::new(store_path, headers.get_all());
+        let block_store = BlockStore::open(store_path.clone())?;
+        let pending_blocks_ref =
+            PendingBlocks::new(&headers, block_store.prune_height(), |block_hash| {
+                block_store.is_downloaded(block_hash)
+            })?;
+
+        // los bloques que quedaron pendientes en una ejecucion anterior se vuelven a descargar
+        let mut block_downloader = BlockDownloader::new();
+        let lost_blocks = pending_blocks_ref.lock()?.get_blocks();
+        block_downloader.enqueue(
+            lost_blocks
+                .into_iter()
+                .map(|block_hash| Inventory::new(InventoryType::Block, block_hash))
+                .collect(),
+        );
 
         let node_state_ref = Arc::new(Mutex::new(Self {
             logger_sender: logger_sender.clone(),
             gui_sender,
             headers,
+            header_sync: HeaderSync::new(),
             peers: vec![],
             wallets: WalletsState::new(format!("{}/wallets.bin", store_path))?,
-            blocks: BlocksState::new(store_path.clone(), logger_sender, pending_blocks_ref),
+            blocks: BlocksState::new(block_store, logger_sender, pending_blocks_ref),
             utxo: UTXO::new(store_path.clone(), "/utxo.bin".to_string())?,
-            pending_txs: PendingTxs::new(),
+            utxo_set: UTXOSet::new(format!("{}/utxo_set.bin", store_path))?,
+            mempool: Mempool::new(),
+            partial_blocks: HashMap::new(),
+            filters: None,
+            filters_start_height: None,
+            tx_broadcasts: TxBroadcasts::new(),
+            block_downloader,
+            inventory_scheduler: InventoryScheduler::new(),
+            addr_man: AddrMan::new(format!("{}/peers.bin", store_path))?,
+            peer_score: PeerScore::new(format!("{}/banned.bin", store_path))?,
+            peer_info: PeerInfoRegistry::new(),
+            fee_estimator: FeeEstimator::new(),
+            history: History::with_labels(format!("{}/labels.bin", store_path))?,
+            history_blocks: HashMap::new(),
+            address_book: AddressBook::new(format!("{}/contacts.bin", store_path))?,
+            locked_utxo: LockedUTXO::new(format!("{}/locked_utxo.bin", store_path))?,
+            max_fee: DEFAULT_MAX_FEE,
+            spend_min_confirmations: DEFAULT_SPEND_MIN_CONFIRMATIONS,
+            exchange_rates: None,
+            payment_notifier: PaymentNotifier::new(),
+            desktop_notifications: true,
+            sync_progress: SyncProgressTracker::new(),
         }));
+        node_state_ref.lock()?.update_history()?;
 
         Ok(node_state_ref)
     }
 
-    /// Agrega un bloque nuevo, lo guarda en su archivo y actualiza los pending_blocks, wallets, pending_txs y utxo.
-    /// Tambien verifica si ahora el nodo esta actualizado con la red
-    pub fn append_block(&mut self, block_hash: Vec<u8>, block: &Block) -> Result<(), CustomError> {
+    /// Agrega un bloque nuevo, lo guarda en su archivo y actualiza los pending_blocks, wallets, mempool y utxo.
+    /// Tambien verifica si ahora el nodo esta actualizado con la red.
+    /// Durante la sincronizacion el historial solo se actualiza con los bloques que modifican las wallets,
+    /// y se notifica a la interfaz grafica una vez sincronizado el nodo.
+    pub fn append_block(&mut self, block_hash: Hash256, block: &Block) -> Result<(), CustomError> {
         self.blocks
             .append_block(&block_hash, block, self.headers.total_headers_to_download())?;
         self.headers.set_downloaded(&block_hash);
+        self.block_downloader.received(&block_hash)?;
+        self.partial_blocks.remove(&block_hash);
 
         self.verify_sync()?;
 
-        self.update_wallets(block)?;
+        // los bloques que se vuelven a descargar para escanear las wallets ya forman parte del UTXO
+        let processed = self.is_block_processed(&block_hash);
+        let wallets_updated = self.update_wallets(block)?;
         self.update_pending_tx(block)?;
 
-        if self.is_synced() {
+        // los bloques anteriores al IBD solo se descargan al escanear filtros y se agregan al UTXO al generarlo
+        if self.is_synced()
+            && !processed
+            && block.header.timestamp > Network::current().ibd_start_timestamp()
+        {
+            self.fee_estimator.record_block(block, &self.utxo);
             self.utxo.update_from_block(block, true)?;
+            self.utxo_set
+                .update_from_block(block, self.wallets.get_all())?;
+            self.prune_blocks()?;
         }
+        // el UTXO procesa los bloques en orden, por lo que las wallets ya procesaron todos los bloques hasta su altura
+        self.wallets
+            .set_processed_height(self.get_last_block_height());
 
+        if self.is_synced() {
+            self.update_history()?;
+            self.gui_sender.send(GUIEvents::NewBlock)?;
+        } else if wallets_updated {
+            self.update_history()?;
+        }
         Ok(())
     }
 
@@ -102,6 +258,24 @@ impl NodeState {
         self.blocks.get_block(block_string_hash)
     }
 
+    /// Actualiza el modo de poda de los bloques guardados en disco
+    pub fn set_prune_mode(&mut self, prune_mode: PruneMode) {
+        self.blocks.set_prune_mode(prune_mode);
+    }
+
+    /// Elimina del disco los bloques que no se conservan segun el modo de poda.
+    /// Se conservan los bloques con transacciones de alguna wallet, para poder generar sus merkle proofs.
+    fn prune_blocks(&mut self) -> Result<(), CustomError> {
+        let wallet_blocks: HashSet<Hash256> = self
+            .wallets
+            .get_all()
+            .iter()
+            .flat_map(|wallet| wallet.history.iter())
+            .filter_map(|movement| movement.block_hash)
+            .collect();
+        self.blocks.prune(&self.headers, &wallet_blocks)
+    }
+
     /********************     PEERS     ********************/
 
     /// Devuelve referencia a los peers del nodo
@@ -109,14 +283,57 @@ impl NodeState {
         &mut self.peers
     }
 
+    /// Devuelve la cantidad de peers conectados
+    pub fn get_peers_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Devuelve la cantidad de peers conectados a los que llamamos nosotros
+    pub fn get_outbound_peers_count(&self) -> usize {
+        self.peers.iter().filter(|peer| !peer.inbound).count()
+    }
+
+    /// Devuelve la cantidad de peers que se conectaron a nosotros
+    pub fn get_inbound_peers_count(&self) -> usize {
+        self.peers.iter().filter(|peer| peer.inbound).count()
+    }
+
+    /// Devuelve las direcciones de los peers que pueden enviar todos los bloques de la cadena
+    /// Los peers entrantes pueden ser clientes que no los guardan
+    fn get_block_peers(&self) -> Vec<SocketAddrV6> {
+        self.peers
+            .iter()
+            .filter(|peer| peer.services & NODE_NETWORK != 0)
+            .map(|peer| peer.address)
+            .collect()
+    }
+
     /// Devuelve referencia a un peer en particular
     pub fn get_peer(&mut self, address: &SocketAddrV6) -> Option<&mut Peer> {
         self.peers.iter_mut().find(|p| &p.address == address)
     }
 
     /// Agrega varios peers nuevos al nodo
+    /// Si hay wallets cargadas, le envia el bloom filter a cada peer que lo soporta para recibir solo las transacciones relevantes
     pub fn append_peers(&mut self, peers: Vec<Peer>) {
-        self.peers.extend(peers);
+        let bloom_filter = self.get_bloom_filter();
+        for mut peer in peers {
+            let supports_bloom = peer.services & NODE_BLOOM != 0;
+            if let Some(filter) = bloom_filter.as_ref().filter(|_| supports_bloom) {
+                if peer.send(FilterLoad::new(filter.clone())).is_err() {
+                    send_log(
+                        &self.logger_sender,
+                        Log::Message(format!(
+                            "Error loading bloom filter on peer {}",
+                            peer.address
+                        )),
+                    );
+                    continue;
+                }
+            }
+            self.peers.push(peer);
+        }
+        self.update_headers_progress();
     }
 
     /// Elimina del nodo a un peer en particular
@@ -126,6 +343,65 @@ impl NodeState {
         if let Some(index) = index {
             self.peers.remove(index);
         }
+        self.block_downloader.remove_peer(address);
+        self.inventory_scheduler.remove_peer(address);
+        self.header_sync.remove_peer(&address);
+    }
+
+    /// Actualiza la mayor altura conocida de la cadena de un peer.
+    /// Devuelve true si la altura del peer aumento.
+    pub fn update_peer_height(&mut self, address: SocketAddrV6, height: usize) -> bool {
+        let Some(peer) = self.get_peer(&address) else { return false };
+        if height <= peer.best_height {
+            return false;
+        }
+        peer.best_height = height;
+        self.update_headers_progress();
+        true
+    }
+
+    /// Registra una falta de un peer.
+    /// Si el peer alcanza el puntaje para ser baneado, se cierra su conexion y se elimina del nodo.
+    /// Devuelve true si el peer se baneo.
+    pub fn peer_misbehaving(
+        &mut self,
+        address: SocketAddrV6,
+        misbehavior: Misbehavior,
+    ) -> Result<bool, CustomError> {
+        if !self.peer_score.misbehaving(address, misbehavior)? {
+            return Ok(false);
+        }
+        if let Some(peer) = self.get_peer(&address) {
+            // al cerrar la conexion el NetworkLoop deja de atender al peer
+            peer.stream.shutdown();
+        }
+        self.remove_peer(address);
+        Ok(true)
+    }
+
+    /// Devuelve la referencia al registro con la informacion de los peers conectados, para que lo actualice el NetworkLoop
+    pub fn get_peer_info_registry(&self) -> Arc<Mutex<PeerInfoRegistry>> {
+        self.peer_info.clone()
+    }
+
+    /// Devuelve la informacion de los peers conectados, ordenada por direccion
+    pub fn get_peers_info(&self) -> Result<Vec<PeerInfo>, CustomError> {
+        Ok(self.peer_info.lock()?.get_all())
+    }
+
+    /// Devuelve el trafico total del nodo y de cada tipo de mensaje desde que se inicio
+    pub fn net_totals(&self) -> Result<NetTotals, CustomError> {
+        Ok(self.peer_info.lock()?.get_totals())
+    }
+
+    /// Devuelve true si la direccion esta baneada.
+    pub fn is_banned(&self, address: &SocketAddrV6) -> bool {
+        self.peer_score.is_banned(address)
+    }
+
+    /// Actualiza el tiempo en segundos que se banea a los peers.
+    pub fn set_ban_time(&mut self, ban_time: u64) {
+        self.peer_score.set_ban_time(ban_time);
     }
 
     /// Registra que un peer solicito el envio directo de headers
@@ -136,6 +412,25 @@ impl NodeState {
         }
     }
 
+    /// Habilita los compact blocks con un peer si anuncio la version que soportamos (BIP 152).
+    /// Los anuncios de otras versiones no deshabilitan una version 2 ya anunciada.
+    pub fn peer_send_cmpct(&mut self, address: SocketAddrV6, version: u64) {
+        let peer = self.peers.iter_mut().find(|p| p.address == address);
+        if let Some(peer) = peer {
+            if version == COMPACT_BLOCKS_VERSION {
+                peer.compact_blocks = true;
+            }
+        }
+    }
+
+    /// Registra el fee rate minimo de las transacciones que un peer quiere recibir (BIP 133)
+    pub fn peer_fee_filter(&mut self, address: SocketAddrV6, fee_rate: u64) {
+        let peer = self.peers.iter_mut().find(|p| p.address == address);
+        if let Some(peer) = peer {
+            peer.fee_filter = fee_rate;
+        }
+    }
+
     /// Registra que un peer solicito headers
     pub fn peer_requested_headers(&mut self, address: SocketAddrV6) {
         let peer = self.peers.iter_mut().find(|p| p.address == address);
@@ -144,50 +439,328 @@ impl NodeState {
         }
     }
 
-    /// Obtiene el peer con el que haya realizado el handshake mas rapido
-    pub fn get_fastest_peer(&mut self) -> Option<&mut Peer> {
-        self.peers
+    /// Obtiene hasta count peers, los que hayan realizado el handshake mas rapido entre los que pueden enviar todos los bloques
+    pub fn get_fastest_peers(&mut self, count: usize) -> Vec<&mut Peer> {
+        let mut peers: Vec<&mut Peer> = self
+            .peers
             .iter_mut()
-            .min_by(|a, b| a.benchmark.cmp(&b.benchmark))
+            .filter(|peer| peer.services & NODE_NETWORK != 0)
+            .collect();
+        peers.sort_by_key(|peer| peer.benchmark);
+        peers.truncate(count);
+        peers
+    }
+
+    /********************     ADDRESSES     ********************/
+
+    /// Agrega a AddrMan las direcciones anunciadas por un peer.
+    /// Devuelve la cantidad de direcciones nuevas.
+    pub fn append_addresses(
+        &mut self,
+        addresses: Vec<NetworkAddress>,
+    ) -> Result<usize, CustomError> {
+        self.addr_man.add(addresses)
+    }
+
+    /// Devuelve hasta count direcciones conocidas a las que conectarse, sin incluir los peers conectados
+    /// ni las direcciones baneadas.
+    pub fn get_peer_candidates(&self, count: usize) -> Vec<SocketAddr> {
+        let mut exclude: Vec<SocketAddrV6> = self.peers.iter().map(|peer| peer.address).collect();
+        exclude.extend(
+            self.addr_man
+                .addresses()
+                .filter(|address| self.is_banned(address)),
+        );
+        self.addr_man.candidates(count, &exclude)
+    }
+
+    /// Registra en AddrMan una conexion exitosa con un peer
+    pub fn peer_connected(
+        &mut self,
+        address: SocketAddrV6,
+        services: u64,
+    ) -> Result<(), CustomError> {
+        self.addr_man.connected(address, services)
+    }
+
+    /// Registra en AddrMan un intento fallido de conexion con un peer
+    pub fn peer_connection_failed(&mut self, address: SocketAddrV6) -> Result<(), CustomError> {
+        self.addr_man.failed(address)
     }
 
     /********************     HEADERS     ********************/
 
     /// devuelve el hash del ultimo header guardado
-    pub fn get_last_header_hash(&self) -> Option<Vec<u8>> {
+    pub fn get_last_header_hash(&self) -> Option<Hash256> {
         self.headers.get_last_header_hash()
     }
 
-    /// agrega un header nuevo en HeadersState
-    pub fn append_headers(&mut self, headers: &Headers) -> Result<(), CustomError> {
+    /// agrega los headers nuevos que envio un peer en HeadersState
+    /// Los headers se validan contra la cadena actual antes de agregarse, ver chain_validator.
+    /// Si los headers forman un fork con mas trabajo acumulado que la cadena actual, comparando con el trabajo guardado
+    /// de cada header y sumando el de todos los mensajes que envio el peer de la rama, se reorganiza la cadena
+    /// y se reemplazan los headers posteriores al fork. Si tienen menos trabajo pero el peer todavia no termino
+    /// de enviar su cadena, la rama se guarda en HeaderSync y se vuelve a comparar con sus siguientes headers.
+    /// Devuelve los headers agregados, o ChainError::HeaderLowWorkChain si el peer termino de enviar
+    /// una rama con menos trabajo que la cadena actual.
+    pub fn append_headers(
+        &mut self,
+        address: SocketAddrV6,
+        headers: &Headers,
+    ) -> Result<Vec<BlockHeader>, CustomError> {
+        let Some(first_header) = headers.headers.first() else { return Ok(vec![]) };
+        let branch = match self
+            .header_sync
+            .take_branch(&address, &first_header.prev_block_hash)
+        {
+            Some(branch) if self.is_branch_of(&branch)? => Some(branch),
+            _ => None,
+        };
+        let is_new_branch = branch.is_none();
+        let (mut fork_len, received, branch_work) = match branch {
+            Some(mut branch) => {
+                branch.extend(&headers.headers);
+                (branch.fork_len, branch.headers, Some(branch.work))
+            }
+            None => match first_header.prev_block_hash == Network::current().genesis_hash() {
+                true => (0, headers.headers.clone(), None),
+                false => match self
+                    .headers
+                    .find_header_position(&first_header.prev_block_hash)
+                {
+                    Some(position) => (position + 1, headers.headers.clone(), None),
+                    None => return Err(CustomError::Chain(ChainError::BlockChainBroken)),
+                },
+            },
+        };
+
+        // los headers que ya forman parte de la cadena se ignoran
+        let known_headers = received
+            .iter()
+            .zip(&self.headers.read_range(fork_len, received.len())?)
+            .take_while(|(header, chain_header)| header.hash() == chain_header.hash())
+            .count();
+        fork_len += known_headers;
+        let new_headers = &received[known_headers..];
+        if new_headers.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // solo se necesitan los ultimos headers anteriores al fork para validar la dificultad
+        let tail_start = fork_len.saturating_sub(DIFFICULTY_ADJUSTMENT_INTERVAL);
+        let chain_tail = self.headers.read_range(tail_start, fork_len - tail_start)?;
+        validate_headers_from(tail_start, &chain_tail, new_headers)?;
+        if fork_len < self.headers.len() {
+            if fork_len < last_checkpoint_height() {
+                return Err(CustomError::Chain(ChainError::HeaderCheckpointMismatch));
+            }
+            // el trabajo de una rama guardada se acumula con cada mensaje del peer, sin volver a recorrer sus headers
+            let new_work = match branch_work {
+                Some(work) if known_headers == 0 => work,
+                _ => ChainWork::of(new_headers),
+            };
+            let branch_work = self.headers.chain_work_at(fork_len)? + new_work;
+            match branch_work.cmp(&self.headers.chain_work()) {
+                Ordering::Greater => send_log(
+                    &self.logger_sender,
+                    Log::Message(format!(
+                        "Peer {} serves a chain with more work, switching to it...",
+                        address
+                    )),
+                ),
+                // el peer envia mas headers, la rama puede superar el trabajo de la cadena actual
+                _ if headers.headers.len() == MAX_HEADERS_PER_MESSAGE => {
+                    if is_new_branch {
+                        send_log(
+                            &self.logger_sender,
+                            Log::Message(format!(
+                                "Peer {} serves a fork at height {}, comparing its work with the current chain...",
+                                address, fork_len
+                            )),
+                        );
+                    }
+                    self.header_sync.keep_branch(
+                        address,
+                        HeaderBranch {
+                            fork_len,
+                            headers: new_headers.to_vec(),
+                            work: new_work,
+                        },
+                    );
+                    return Ok(vec![]);
+                }
+                // dos bloques de la misma altura pueden competir hasta que se extienda uno
+                Ordering::Equal => {
+                    send_log(
+                        &self.logger_sender,
+                        Log::Message(format!(
+                            "Ignoring fork at height {} with the same work as the current chain",
+                            fork_len
+                        )),
+                    );
+                    return Ok(vec![]);
+                }
+                Ordering::Less => return Err(CustomError::Chain(ChainError::HeaderLowWorkChain)),
+            }
+            self.reorganize(fork_len)?;
+        }
+        let headers = new_headers.to_vec();
+
         let mut new_headers = vec![];
 
-        for header in headers.headers.iter() {
-            let mut header = header.clone();
+        for mut header in headers {
             if !self.is_synced() {
                 header.broadcasted = true;
             }
             new_headers.push(header);
         }
 
-        self.headers.append_headers(new_headers)?;
+        self.headers.append_headers(new_headers.clone())?;
+        self.history.set_best_height(self.headers.len());
+        self.update_headers_progress();
         self.gui_sender.send(GUIEvents::NewHeaders)?;
 
+        Ok(new_headers)
+    }
+
+    /// Devuelve true si la rama todavia se aparta de la cadena de headers en su fork, es decir que la cadena
+    /// no se reorganizo por debajo del fork desde que se guardo.
+    fn is_branch_of(&self, branch: &HeaderBranch) -> Result<bool, CustomError> {
+        let Some(first_header) = branch.headers.first() else { return Ok(false) };
+        let fork_hash = match branch.fork_len {
+            0 => Network::current().genesis_hash(),
+            fork_len => match self.headers.get(fork_len - 1)? {
+                Some(fork_header) => *fork_header.hash(),
+                None => return Ok(false),
+            },
+        };
+        Ok(first_header.prev_block_hash == fork_hash)
+    }
+
+    /// Devuelve el trabajo acumulado por la cadena de headers, con el que se elige la cadena ante un fork.
+    pub fn chain_work(&self) -> ChainWork {
+        self.headers.chain_work()
+    }
+
+    /// Devuelve el block locator de la cadena de headers, para solicitar headers a los peers.
+    pub fn get_block_locator(&self) -> Result<Vec<Hash256>, CustomError> {
+        self.headers.get_block_locator()
+    }
+
+    /// Reorganiza la cadena desconectando los bloques posteriores a la altura fork_len:
+    /// descarta sus headers, deshace sus cambios en el UTXO y en el historial de las wallets,
+    /// y devuelve sus transacciones al mempool.
+    /// Si el UTXO no se puede deshacer, se vuelve a generar una vez descargados los bloques de la nueva cadena.
+    fn reorganize(&mut self, fork_len: usize) -> Result<(), CustomError> {
+        let removed_headers = self.headers.rollback(fork_len)?;
+        self.history_blocks
+            .retain(|_, (height, _)| *height <= fork_len);
+        self.blocks.rollback(fork_len)?;
+        let removed_hashes: Vec<Hash256> = removed_headers
+            .iter()
+            .map(|header| *header.hash())
+            .collect();
+        send_log(
+            &self.logger_sender,
+            Log::Message(format!(
+                "Chain reorganization at height {}, {} blocks disconnected",
+                fork_len,
+                removed_headers.len()
+            )),
+        );
+
+        let mut pending_blocks = self.blocks.pending_blocks_ref.lock()?;
+        for block_hash in &removed_hashes {
+            pending_blocks.remove_block(block_hash)?;
+        }
+        drop(pending_blocks);
+        self.block_downloader.cancel(&removed_hashes);
+
+        // bloques desconectados que ya se habian descargado, del mas reciente al mas antiguo
+        let removed_blocks: Vec<Block> = removed_headers
+            .iter()
+            .rev()
+            .filter_map(|header| self.blocks.get_block(header.hash_as_string()).ok())
+            .collect();
+
+        if self.utxo.is_synced() {
+            match self.undo_utxo(&removed_blocks) {
+                Ok(()) => self.utxo_set.sync(self.wallets.get_all(), &self.utxo)?,
+                Err(error) => {
+                    send_log(
+                        &self.logger_sender,
+                        Log::Message(format!(
+                            "Error rolling back UTXO ({error}), it will be generated again"
+                        )),
+                    );
+                    self.utxo.reset()?;
+                    self.blocks.reset_sync();
+                }
+            }
+        }
+
+        if self.wallets.rollback(&removed_hashes)? {
+            self.gui_sender.send(GUIEvents::WalletsUpdated)?;
+        }
+
+        // la coinbase de un bloque desconectado deja de ser valida
+        let mut pending_txs_updated = false;
+        for block in &removed_blocks {
+            for transaction in block.transactions.iter().skip(1) {
+                pending_txs_updated |= self.mempool.append_pending_tx(transaction.clone())?;
+            }
+        }
+        if pending_txs_updated {
+            self.gui_sender.send(GUIEvents::NewPendingTx)?;
+        }
+        self.update_history()
+    }
+
+    /// Deshace en el UTXO los bloques desconectados que habia procesado, del mas reciente al mas antiguo.
+    fn undo_utxo(&mut self, removed_blocks: &[Block]) -> Result<(), CustomError> {
+        let last_block_position = removed_blocks
+            .iter()
+            .position(|block| Some(block.header.hash()) == self.utxo.last_block_hash());
+        let Some(last_block_position) = last_block_position else { return Ok(()) };
+
+        for block in &removed_blocks[last_block_position..] {
+            self.utxo.undo_block(block)?;
+        }
         Ok(())
     }
 
+    /// Devuelve la height del ultimo header de la cadena
+    pub fn get_best_height(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Devuelve la height de un bloque de la cadena, o None si no forma parte de ella
+    pub fn get_block_height(&self, block_hash: &Hash256) -> Option<usize> {
+        // la cadena de headers no incluye al genesis
+        self.headers
+            .find_header_position(block_hash)
+            .map(|position| position + 1)
+    }
+
+    /// Devuelve la cantidad de confirmaciones de un bloque, 0 si no esta en la cadena
+    pub fn get_confirmations(&self, block_hash: &Hash256) -> usize {
+        self.get_block_height(block_hash)
+            .map(|height| self.get_best_height().saturating_sub(height) + 1)
+            .unwrap_or(0)
+    }
+
     /// Devuelve los ultimos count headers del HeaderState
     pub fn get_last_headers(&self, count: usize) -> Vec<(usize, BlockHeader)> {
         self.headers.get_last_headers(count)
     }
 
     /// Devuelve los headers que se le solicitan mediante el mensaje GetHeaders del protocolo btc
-    pub fn get_headers(&self, get_headers: GetHeaders) -> Vec<BlockHeader> {
+    pub fn get_headers(&self, get_headers: GetHeaders) -> Result<Vec<BlockHeader>, CustomError> {
         self.headers.get_headers(get_headers)
     }
 
     /// Devuelve los headers listos para enviar a medida que se descargan sus bloques, siguiendo el orden de la blockchain.
-    pub fn get_headers_to_send(&mut self, block_hash: &Vec<u8>) -> Vec<BlockHeader> {
+    pub fn get_headers_to_send(&mut self, block_hash: &Hash256) -> Vec<BlockHeader> {
         self.headers.get_headers_to_send(block_hash)
     }
 
@@ -202,16 +775,20 @@ impl NodeState {
     /// Si el nodo esta sincronizado, envia un evento a la interfaz grafica para indicar que el nodo esta listo para usarse
     /// Si el nodo no esta sincronizado, verifica si los headers estan sincronizados
     /// Si los headers estan sincronizados, verifica si los bloques estan sincronizados
-    /// Si los bloques estan sincronizados, genera el UTXO
+    /// Si los bloques estan sincronizados y termino el escaneo de filtros compactos, genera el UTXO
+    /// incluyendo los bloques anteriores al IBD que coincidieron con los filtros
+    /// Actualiza el progreso de la sincronizacion con los bloques descargados
     ///
     pub fn verify_sync(&mut self) -> Result<(), CustomError> {
         if self.headers.is_synced() {
             self.blocks.verify_sync()?;
         }
 
-        if self.blocks.is_synced() && !self.utxo.is_synced() {
+        if self.blocks.is_synced() && !self.utxo.is_synced() && !self.is_filter_scan_pending()? {
+            let matched_blocks = self.get_filter_matched_blocks();
             self.utxo
-                .generate(self.headers.get_all(), &mut self.logger_sender)?;
+                .generate(&self.headers, &matched_blocks, &mut self.logger_sender)?;
+            self.utxo_set.sync(self.wallets.get_all(), &self.utxo)?;
         }
 
         if self.is_synced() {
@@ -219,7 +796,75 @@ impl NodeState {
                 .send(GUIEvents::NodeStateReady)
                 .map_err(|_| CustomError::CannotInitGUI)?;
         }
+        self.update_blocks_progress()?;
+        self.sync_progress.set_synced(self.is_synced());
+
+        Ok(())
+    }
+
+    /// Devuelve las etapas de la sincronizacion alcanzadas desde la ultima vez que se consultaron
+    pub fn new_sync_milestones(&mut self) -> Vec<SyncMilestone> {
+        let milestones = [
+            (self.headers.is_synced(), SyncMilestone::HeadersSynced),
+            (self.blocks.is_synced(), SyncMilestone::BlocksSynced),
+            (self.is_synced(), SyncMilestone::Synced),
+        ];
+        milestones
+            .into_iter()
+            .filter(|(reached, milestone)| *reached && self.sync_progress.reach(*milestone))
+            .map(|(_, milestone)| milestone)
+            .collect()
+    }
+
+    /// Registra una etapa de la sincronizacion alcanzada fuera del node state, por ejemplo el escaneo de los filtros compactos
+    /// Devuelve true si es la primera vez que se alcanza
+    pub fn reach_sync_milestone(&mut self, milestone: SyncMilestone) -> bool {
+        self.sync_progress.reach(milestone)
+    }
+
+    /// Devuelve el progreso de la descarga inicial de headers y bloques
+    pub fn get_sync_progress(&self) -> SyncProgress {
+        self.sync_progress.get()
+    }
 
+    /// Actualiza el progreso con los headers descargados y la altura de la cadena de los peers conectados
+    fn update_headers_progress(&mut self) {
+        let peer_height = self
+            .peers
+            .iter()
+            .map(|peer| peer.best_height)
+            .max()
+            .unwrap_or(0);
+        self.sync_progress
+            .update_headers(Instant::now(), self.get_best_height(), peer_height);
+    }
+
+    /// Actualiza el progreso con los bloques descargados desde la fecha de inicio del IBD
+    fn update_blocks_progress(&mut self) -> Result<(), CustomError> {
+        let blocks_to_download = self.headers.total_headers_to_download();
+        let pending_blocks = self.blocks.pending_blocks_count()?;
+        self.sync_progress.update_blocks(
+            Instant::now(),
+            blocks_to_download.saturating_sub(pending_blocks),
+            blocks_to_download,
+        );
+        Ok(())
+    }
+
+    /********************     SHUTDOWN     ********************/
+
+    /// Guarda en disco los headers, las UTXO, las wallets y los bloques descargados antes de cerrar el nodo
+    /// Debe llamarse una vez que terminaron los threads que modifican el estado, para que ninguno escriba despues
+    pub fn flush(&mut self) -> Result<(), CustomError> {
+        self.headers.flush()?;
+        self.utxo.flush()?;
+        self.utxo_set.flush()?;
+        self.wallets.flush()?;
+        self.blocks.flush()?;
+        send_log(
+            &self.logger_sender,
+            Log::Message(String::from("Node state saved")),
+        );
         Ok(())
     }
 
@@ -231,14 +876,182 @@ impl NodeState {
     }
 
     /// Agrega una wallet nueva a WalletState
+    /// Si la private key es un mnemonico BIP 39 se crea una wallet HD y la public key se ignora.
+    /// Si la private key esta vacia se crea una wallet watch-only a partir de la public key, que puede ser una xpub.
     pub fn append_wallet(
         &mut self,
         name: String,
         public_key: String,
         private_key: String,
     ) -> Result<(), CustomError> {
-        let new_wallet = Wallet::new(name, public_key, private_key, &self.utxo)?;
-        self.wallets.append(new_wallet)
+        let new_wallet = match private_key.split_whitespace().count() {
+            0 => Wallet::watch_only(name, public_key, &self.utxo)?,
+            1 => Wallet::new(name, public_key, private_key, &self.utxo)?,
+            _ => Wallet::from_mnemonic(name, &private_key, "", &self.utxo)?,
+        };
+        self.add_wallet(new_wallet)
+    }
+
+    /// Agrega una wallet nueva a partir de una private key en formato WIF, cuya public key es su direccion P2PKH.
+    /// Como la clave puede haber recibido fondos antes de importarla, una vez sincronizado el nodo
+    /// se vuelven a escanear los bloques posteriores al IBD para generar su historial.
+    /// Devuelve la cantidad de bloques que se vuelven a descargar para el escaneo.
+    pub fn import_key(&mut self, name: String, wif: &str) -> Result<usize, CustomError> {
+        let new_wallet = Wallet::from_wif(name, wif, &self.utxo)?;
+        self.add_wallet(new_wallet)?;
+        self.rescan_from_ibd()
+    }
+
+    /// Firma un mensaje con la clave de la wallet activa
+    /// Devuelve la direccion de la wallet y la firma en base64
+    pub fn sign_message(&self, message: &str) -> Result<(String, String), CustomError> {
+        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        let signature = active_wallet.sign_message(message)?;
+        Ok((active_wallet.pubkey.clone(), signature))
+    }
+
+    /// Guarda en path una copia de seguridad de la wallet activa encriptada con la passphrase,
+    /// junto a las etiquetas de sus transacciones
+    pub fn export_active_wallet_backup(
+        &self,
+        path: &str,
+        passphrase: &str,
+    ) -> Result<(), CustomError> {
+        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        let labels = self
+            .history
+            .get_entries(&active_wallet.pubkey)
+            .iter()
+            .filter_map(|entry| Some((entry.tx_hash, entry.label.clone()?)))
+            .collect();
+        active_wallet.export_backup(path, passphrase, labels)
+    }
+
+    /// Agrega la wallet guardada en una copia de seguridad y restaura las etiquetas de sus transacciones.
+    /// Como en import_key, una vez sincronizado el nodo se vuelven a escanear los bloques posteriores al IBD.
+    /// Devuelve la cantidad de bloques que se vuelven a descargar para el escaneo.
+    pub fn restore_wallet_backup(
+        &mut self,
+        path: &str,
+        passphrase: &str,
+    ) -> Result<usize, CustomError> {
+        let (new_wallet, labels) = Wallet::restore_backup(path, passphrase, &self.utxo)?;
+        self.add_wallet(new_wallet)?;
+        for (tx_hash, label) in labels {
+            self.history.set_label(&tx_hash, &label)?;
+        }
+        self.gui_sender.send(GUIEvents::TxLabelsUpdated)?;
+        self.rescan_from_ibd()
+    }
+
+    fn rescan_from_ibd(&mut self) -> Result<usize, CustomError> {
+        // si el nodo no esta sincronizado, los bloques que faltan se escanean al descargarlos
+        if !self.is_synced() {
+            return Ok(0);
+        }
+        let ibd_height = self.headers.ibd_start_index() + 1;
+        self.rescan_from(ibd_height)
+    }
+
+    fn add_wallet(&mut self, new_wallet: Wallet) -> Result<(), CustomError> {
+        // si el UTXO todavia no se genero, las UTXO de la wallet se cargan al sincronizar
+        if self.utxo.is_synced() {
+            self.utxo_set.append_wallet(&new_wallet, &self.utxo)?;
+        }
+        self.wallets.append(new_wallet)?;
+        self.update_history()?;
+        self.load_bloom_filter();
+        Ok(())
+    }
+
+    /// Agrega a la wallet activa un multisig m-of-n del que participa alguna de sus claves
+    /// Actualiza sus UTXO para incluir los outputs P2SH del multisig
+    /// Devuelve la direccion P2SH del multisig
+    pub fn add_multisig(
+        &mut self,
+        required: u8,
+        pubkeys: &[Vec<u8>],
+    ) -> Result<String, CustomError> {
+        let address = self.wallets.add_multisig_to_active(required, pubkeys)?;
+        if let (true, Some(active_wallet)) = (self.utxo.is_synced(), self.wallets.get_active()) {
+            self.utxo_set.append_wallet(active_wallet, &self.utxo)?;
+        }
+        self.load_bloom_filter();
+        self.gui_sender.send(GUIEvents::WalletsUpdated)?;
+        Ok(address)
+    }
+
+    /// Devuelve true si las wallets estan encriptadas y todavia no se ingreso la passphrase
+    pub fn wallets_locked(&self) -> bool {
+        self.wallets.is_locked()
+    }
+
+    /// Desencripta las wallets con la passphrase recibida
+    /// Vuelve a escanear los bloques que se procesaron mientras las wallets estaban bloqueadas
+    pub fn unlock_wallets(&mut self, passphrase: String) -> Result<(), CustomError> {
+        let processed_height = self.wallets.unlock(passphrase)?;
+        if self.utxo.is_synced() {
+            self.utxo_set.sync(self.wallets.get_all(), &self.utxo)?;
+        }
+        let last_block_height = self.get_last_block_height();
+        if let Some(height) = processed_height.filter(|height| *height < last_block_height) {
+            self.rescan_from(height)?;
+        }
+        self.update_history()?;
+        self.load_bloom_filter();
+        Ok(())
+    }
+
+    /// Encripta el archivo de wallets con la passphrase recibida
+    pub fn set_wallets_passphrase(&mut self, passphrase: String) -> Result<(), CustomError> {
+        self.wallets.set_passphrase(passphrase)
+    }
+
+    /// Genera el bloom filter con los datos de los script pubkeys de todas las wallets,
+    /// para que los peers encuentren sus outputs P2PKH, P2WPKH y P2SH.
+    /// Devuelve None si no hay wallets cargadas.
+    pub fn get_bloom_filter(&self) -> Option<BloomFilter> {
+        let script_pubkeys: Vec<Script> = self
+            .wallets
+            .get_all()
+            .iter()
+            .filter_map(|wallet| wallet.get_script_pubkeys().ok())
+            .flatten()
+            .filter_map(|script_pubkey| Script::parse(&script_pubkey).ok())
+            .collect();
+        if script_pubkeys.is_empty() {
+            return None;
+        }
+
+        let n_tweak = chrono::Utc::now().timestamp() as u32;
+        let mut filter = BloomFilter::new(
+            script_pubkeys.len(),
+            BLOOM_FALSE_POSITIVE_RATE,
+            n_tweak,
+            BLOOM_UPDATE_ALL,
+        );
+        for script_pubkey in &script_pubkeys {
+            filter.insert_script_pubkey(script_pubkey);
+        }
+        Some(filter)
+    }
+
+    /// Envia el bloom filter actualizado a los peers que lo soportan
+    fn load_bloom_filter(&mut self) {
+        let Some(filter) = self.get_bloom_filter() else { return };
+
+        let mut peers_to_remove = vec![];
+        for peer in self.peers.iter_mut() {
+            if peer.services & NODE_BLOOM == 0 {
+                continue;
+            }
+            if peer.send(FilterLoad::new(filter.clone())).is_err() {
+                peers_to_remove.push(peer.address);
+            }
+        }
+        for address in peers_to_remove {
+            self.remove_peer(address);
+        }
     }
 
     /// Devuelve la wallet activa de WalletState
@@ -246,57 +1059,185 @@ impl NodeState {
         self.wallets.get_active()
     }
 
-    /// Cambia la wallet activa de WalletState
+    /// Cambia la wallet activa de WalletState.
+    /// No escanea, ya que el balance, UTXO e historial de todas las wallets se mantienen actualizados.
     pub fn change_wallet(&mut self, public_key: String) -> Result<(), CustomError> {
         self.wallets.set_active(&public_key)?;
         self.gui_sender.send(GUIEvents::WalletChanged)?;
         Ok(())
     }
 
+    /// Devuelve la direccion en la que la wallet activa recibe fondos
+    pub fn get_active_wallet_receive_address(&self) -> Result<String, CustomError> {
+        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        Ok(active_wallet.get_receive_address())
+    }
+
+    /// Devuelve la URI de pago BIP 21 de la direccion en la que la wallet activa recibe fondos,
+    /// con el monto en satoshis y el nombre opcionales
+    pub fn get_active_wallet_payment_uri(
+        &self,
+        amount: Option<u64>,
+        label: Option<String>,
+    ) -> Result<PaymentUri, CustomError> {
+        let address = self.get_active_wallet_receive_address()?;
+        Ok(PaymentUri::new(address, amount, label))
+    }
+
+    /// Vuelve a escanear las wallets en los bloques posteriores a la altura recibida,
+    /// por ejemplo al importar una clave antigua o restaurar una wallet desde su mnemonico.
+    /// Descarta los movimientos de las wallets en esos bloques y los vuelve a generar con los bloques guardados en disco.
+    /// Los bloques que ya se podaron o que nunca se descargaron se vuelven a pedir y se escanean al recibirlos.
+    /// Devuelve la cantidad de bloques que se vuelven a descargar.
+    /// Devuelve CustomError si la altura es mayor a la de la cadena.
+    pub fn rescan_from(&mut self, height: usize) -> Result<usize, CustomError> {
+        if height > self.get_best_height() {
+            return Err(CustomError::Validation(format!(
+                "Cannot rescan from height {height}, the chain has {} blocks",
+                self.get_best_height()
+            )));
+        }
+        // la altura de un bloque es su posicion en la cadena de headers mas uno
+        let block_hashes: Vec<Hash256> = self
+            .headers
+            .read_range(height, self.get_best_height() - height)?
+            .iter()
+            .map(|header| *header.hash())
+            .collect();
+        self.wallets.rollback(&block_hashes)?;
+
+        let mut missing_blocks = vec![];
+        for block_hash in block_hashes {
+            match self.blocks.get_block(hash_as_string(&block_hash)) {
+                Ok(block) => {
+                    self.wallets.update(&block, &self.utxo)?;
+                }
+                Err(_) => missing_blocks.push(Inventory::new(InventoryType::Block, block_hash)),
+            }
+        }
+        let missing_count = missing_blocks.len();
+        if !missing_blocks.is_empty() {
+            self.append_pending_blocks(missing_blocks)?;
+            self.request_pending_blocks()?;
+        }
+
+        send_log(
+            &self.logger_sender,
+            Log::Message(format!(
+                "Rescanning wallets from height {height}, {missing_count} blocks to download"
+            )),
+        );
+        self.update_history()?;
+        self.gui_sender.send(GUIEvents::WalletsUpdated)?;
+        Ok(missing_count)
+    }
+
     /// Actualiza las wallets de WalletState
-    pub fn update_wallets(&mut self, block: &Block) -> Result<(), CustomError> {
+    pub fn update_wallets(&mut self, block: &Block) -> Result<bool, CustomError> {
         let wallets_updated = self.wallets.update(block, &self.utxo)?;
         if wallets_updated {
             self.gui_sender
                 .send(GUIEvents::WalletsUpdated)
                 .map_err(|_| CustomError::CannotInitGUI)?;
         }
-        Ok(())
+        Ok(wallets_updated)
     }
 
     /********************     UTXO     ********************/
 
-    /// Devuelve el balance de la wallet activa
-    pub fn get_active_wallet_balance(&self) -> Result<u64, CustomError> {
-        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::WalletNotFound) };
-        self.utxo.wallet_balance(active_wallet)
+    /// Devuelve la height del ultimo bloque que proceso el UTXO, 0 si todavia no proceso ninguno.
+    pub fn get_last_block_height(&self) -> usize {
+        self.utxo
+            .last_block_hash()
+            .and_then(|block_hash| self.get_block_height(block_hash))
+            .unwrap_or(0)
+    }
+
+    /// Devuelve true si el UTXO ya proceso el bloque, es decir si no es posterior al ultimo bloque que proceso.
+    pub fn is_block_processed(&self, block_hash: &Hash256) -> bool {
+        let Some(last_block_hash) = self.utxo.last_block_hash() else { return false };
+        match (
+            self.get_block_height(block_hash),
+            self.get_block_height(last_block_hash),
+        ) {
+            (Some(height), Some(last_height)) => height <= last_height,
+            _ => false,
+        }
+    }
+
+    /// Devuelve el balance de la wallet activa desglosado en confirmado, pendiente e inmaduro
+    pub fn get_active_wallet_balances(&self) -> Result<Balances, CustomError> {
+        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        let outputs: Vec<(u64, Option<usize>)> = self
+            .utxo_set
+            .wallet_utxo(active_wallet)
+            .iter()
+            .map(|(out_point, value)| {
+                let coinbase_confirmations = self.get_coinbase_confirmations(out_point, value);
+                (value.tx_out.value, coinbase_confirmations)
+            })
+            .collect();
+        Ok(Balances::new(
+            &outputs,
+            self.history.get_pending(&active_wallet.pubkey),
+        ))
+    }
+
+    /// Devuelve las confirmaciones de un output si es de una coinbase que todavia no puede gastarse.
+    /// Los bloques recientes nunca se podan, por lo que el de una coinbase inmadura siempre esta guardado.
+    fn get_coinbase_confirmations(&self, out_point: &OutPoint, value: &UTXOValue) -> Option<usize> {
+        let confirmations = self.get_confirmations(&value.block_hash);
+        if confirmations == 0 || confirmations >= COINBASE_MATURITY {
+            return None;
+        }
+        let block = self
+            .blocks
+            .get_block(hash_as_string(&value.block_hash))
+            .ok()?;
+        let coinbase = block.transactions.first()?;
+        (coinbase.hash() == out_point.hash).then_some(confirmations)
     }
 
     /// Devuelve el UTXO de la wallet activa
     pub fn get_active_wallet_utxo(&self) -> Result<Vec<(OutPoint, UTXOValue)>, CustomError> {
-        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::WalletNotFound) };
-        self.utxo.generate_wallet_utxo(active_wallet)
+        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        Ok(self.utxo_set.wallet_utxo(active_wallet))
     }
 
-    /********************     PENDING TXs     ********************/
+    /********************     MEMPOOL     ********************/
 
-    /// Actualiza las pending txs de PendingTxs
+    /// Elimina del Mempool las transacciones confirmadas en el bloque y las que entran en conflicto con ellas
     pub fn update_pending_tx(&mut self, block: &Block) -> Result<(), CustomError> {
-        self.pending_txs.update_pending_tx(block)
+        let tx_hashes: Vec<Hash256> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        self.tx_broadcasts.remove(&tx_hashes);
+        self.mempool.update_pending_tx(block)
     }
 
-    /// Devuelve las pending txs de la wallet activa
-    pub fn get_active_wallet_pending_txs(&self) -> Result<Vec<Movement>, CustomError> {
-        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::WalletNotFound) };
+    /// Devuelve las pending txs de la wallet activa, una por transaccion y sin las que ya se confirmaron
+    pub fn get_active_wallet_pending_txs(&self) -> Result<Vec<HistoryEntry>, CustomError> {
+        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        Ok(self
+            .history
+            .get_pending_entries(&active_wallet.pubkey)
+            .to_vec())
+    }
 
-        self.pending_txs.from_wallet(active_wallet, &self.utxo)
+    /// Recibe las transacciones anunciadas por un peer y devuelve las que no estan en el Mempool y hay que pedirle
+    pub fn request_transactions(
+        &mut self,
+        tx_hashes: Vec<Hash256>,
+    ) -> Result<Vec<Hash256>, CustomError> {
+        self.mempool.request(tx_hashes)
     }
 
-    /// Agrega una pending tx nueva a PendingTxs
+    /// Agrega una pending tx nueva al Mempool
     pub fn append_pending_tx(&mut self, transaction: Transaction) -> Result<bool, CustomError> {
-        let updated = self.pending_txs.append_pending_tx(transaction);
+        self.inventory_scheduler.received(&transaction.hash());
+        self.inventory_scheduler.received(&transaction.wtxid());
+        let updated = self.mempool.append_pending_tx(transaction)?;
 
         if updated {
+            self.update_history()?;
             self.gui_sender
                 .send(GUIEvents::NewPendingTx)
                 .map_err(|_| CustomError::CannotInitGUI)?;
@@ -309,30 +1250,289 @@ impl NodeState {
         Ok(updated)
     }
 
-    /// Devuelve una pending tx de PendingTxs en base a su hash
-    pub fn get_pending_tx(&self, tx_hash: &Vec<u8>) -> Option<Transaction> {
-        self.pending_txs.get_pending_tx(tx_hash)
+    /// Devuelve una pending tx del Mempool en base a su hash
+    pub fn get_pending_tx(&self, tx_hash: &Hash256) -> Option<Transaction> {
+        self.mempool.get_pending_tx(tx_hash)
+    }
+
+    /// Devuelve el hash de una pending tx a partir de su hash o su wtxid
+    pub fn get_pending_txid(&self, hash: &Hash256) -> Option<Hash256> {
+        self.mempool.get_txid(hash)
+    }
+
+    /// Devuelve el fee rate de una transaccion en satoshis por kilobyte virtual, como lo expresa el mensaje feefilter.
+    /// Devuelve None si no se conoce alguno de los outputs que gasta.
+    pub fn transaction_fee_rate(&self, transaction: &Transaction) -> Option<u64> {
+        let fee = self.mempool.fee(transaction, &self.utxo)?;
+        Some(fee * 1000 / transaction.vsize())
+    }
+
+    /// Devuelve todas las pending txs del Mempool
+    pub fn get_all_pending_txs(&self) -> Vec<Transaction> {
+        self.mempool.get_all()
+    }
+
+    /// Devuelve la cantidad de pending txs del Mempool
+    pub fn get_mempool_size(&self) -> usize {
+        self.mempool.len()
+    }
+
+    /********************     HISTORY     ********************/
+
+    /// Reconstruye el historial de transacciones de todas las wallets con sus movimientos y sus pending txs,
+    /// y notifica los pagos recibidos que aparecen en el mempool o se confirman, salvo durante la sincronizacion
+    fn update_history(&mut self) -> Result<(), CustomError> {
+        self.history.set_best_height(self.headers.len());
+        let synced = self.is_synced();
+        // la height de un bloque es su posicion en la cadena de headers mas uno, ya que no incluye al genesis
+        for block_hash in self
+            .wallets
+            .get_all()
+            .iter()
+            .flat_map(|wallet| wallet.history.iter())
+            .filter_map(|movement| movement.block_hash)
+        {
+            if self.history_blocks.contains_key(&block_hash) {
+                continue;
+            }
+            let Some(position) = self.headers.find_header_position(&block_hash) else { continue };
+            let Some(header) = self.headers.get(position)? else { continue };
+            self.history_blocks
+                .insert(block_hash, (position + 1, header.timestamp));
+        }
+        let history_blocks = &self.history_blocks;
+        for wallet in self.wallets.get_all() {
+            let pending = self.mempool.from_wallet(wallet, &self.utxo)?;
+            self.history
+                .update(&wallet.pubkey, &wallet.history, &pending, |block_hash| {
+                    history_blocks.get(block_hash).copied()
+                });
+
+            let notifications = self.payment_notifier.update(
+                &wallet.pubkey,
+                &wallet.name,
+                self.history.get_entries(&wallet.pubkey),
+                synced,
+            );
+            for notification in notifications {
+                send_log(
+                    &self.logger_sender,
+                    Log::Message(format!("{}: {}", notification.title, notification.body)),
+                );
+                if self.desktop_notifications {
+                    notification.send_to_desktop();
+                }
+                self.gui_sender
+                    .send(GUIEvents::PaymentNotification(notification))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Devuelve el historial de transacciones de la wallet activa junto a la height del ultimo header,
+    /// para calcular sus confirmaciones
+    pub fn get_active_wallet_history(&self) -> (Vec<HistoryEntry>, usize) {
+        let entries = match self.wallets.get_active() {
+            Some(active_wallet) => self.history.get_entries(&active_wallet.pubkey).to_vec(),
+            None => vec![],
+        };
+        (entries, self.history.get_best_height())
+    }
+
+    /// Exporta el historial de transacciones de la wallet activa a un archivo CSV
+    pub fn export_active_wallet_history(&self, path: String) -> Result<(), CustomError> {
+        let Some(active_wallet) = self.wallets.get_active() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        self.history.export_csv(&active_wallet.pubkey, path)
+    }
+
+    /// Le pone una etiqueta a una transaccion de las wallets, reemplazando la que tenia
+    /// Una etiqueta vacia elimina la que tenia la transaccion
+    pub fn set_tx_label(&mut self, tx_hash: &Hash256, label: &str) -> Result<(), CustomError> {
+        self.history.set_label(tx_hash, label)?;
+        self.gui_sender.send(GUIEvents::TxLabelsUpdated)?;
+        Ok(())
+    }
+
+    /********************     ADDRESS BOOK     ********************/
+
+    /// Devuelve los contactos de la libreta de direcciones, ordenados por nombre
+    pub fn get_contacts(&self) -> Vec<Contact> {
+        self.address_book.get_all().clone()
+    }
+
+    /// Devuelve el contacto con el nombre recibido
+    pub fn get_contact(&self, label: &str) -> Option<Contact> {
+        self.address_book.get(label).cloned()
+    }
+
+    /// Devuelve el nombre del contacto de una direccion, si esta en la libreta de direcciones
+    pub fn get_contact_label(&self, address: &str) -> Option<String> {
+        self.address_book.get_label(address).cloned()
+    }
+
+    /// Agrega un contacto a la libreta de direcciones
+    pub fn add_contact(&mut self, label: String, address: String) -> Result<(), CustomError> {
+        self.address_book.add(label, address)?;
+        self.gui_sender.send(GUIEvents::ContactsUpdated)?;
+        Ok(())
+    }
+
+    /// Reemplaza el nombre y la direccion de un contacto de la libreta de direcciones
+    pub fn update_contact(
+        &mut self,
+        label: &str,
+        new_label: String,
+        new_address: String,
+    ) -> Result<(), CustomError> {
+        self.address_book.update(label, new_label, new_address)?;
+        self.gui_sender.send(GUIEvents::ContactsUpdated)?;
+        Ok(())
+    }
+
+    /// Elimina un contacto de la libreta de direcciones
+    pub fn remove_contact(&mut self, label: &str) -> Result<Contact, CustomError> {
+        let contact = self.address_book.remove(label)?;
+        self.gui_sender.send(GUIEvents::ContactsUpdated)?;
+        Ok(contact)
+    }
+
+    /********************     EXCHANGE RATES     ********************/
+
+    /// Actualiza el precio de bitcoin en monedas fiat
+    pub fn set_exchange_rates(&mut self, exchange_rates: ExchangeRates) -> Result<(), CustomError> {
+        self.exchange_rates = Some(exchange_rates);
+        self.gui_sender.send(GUIEvents::ExchangeRatesUpdated)?;
+        Ok(())
+    }
+
+    /// Devuelve el ultimo precio de bitcoin en monedas fiat, si ya se obtuvo
+    pub fn get_exchange_rates(&self) -> Option<ExchangeRates> {
+        self.exchange_rates
+    }
+
+    /********************     MERKLE PROOFS     ********************/
+
+    /// Genera la prueba de inclusion de una transaccion en un bloque guardado
+    pub fn get_merkle_proof(
+        &self,
+        block_hash: &Hash256,
+        tx_hash: &Hash256,
+    ) -> Result<MerkleProof, CustomError> {
+        let block = self.get_block(hash_as_string(block_hash))?;
+        MerkleProof::from_block(&block, tx_hash)
+    }
+
+    /// Verifica la prueba de inclusion de una transaccion contra el merkle root del header del bloque en la cadena
+    /// y devuelve la cantidad de confirmaciones de la transaccion.
+    /// Solo necesita el header del bloque, por lo que sirve tambien para pruebas recibidas en un merkleblock.
+    pub fn verify_merkle_proof(
+        &self,
+        block_hash: &Hash256,
+        proof: &MerkleProof,
+        tx_hash: &Hash256,
+    ) -> Result<usize, CustomError> {
+        let Some(position) = self.headers.find_header_position(block_hash) else {
+            return Err(CustomError::Validation(String::from(
+                "Block of merkle proof is not part of the chain",
+            )));
+        };
+        let Some(header) = self.headers.get(position)? else {
+            return Err(CustomError::Validation(String::from(
+                "Block of merkle proof is not part of the chain",
+            )));
+        };
+        proof.verify(&header, tx_hash)?;
+        // la height de un bloque es su posicion en la cadena de headers mas uno, ya que no incluye al genesis
+        Ok(self.get_best_height() - position)
+    }
+
+    /// Registra las transacciones de un merkle block (BIP 37), confirmadas en un bloque de la cadena:
+    /// se quitan del mempool junto con las que gastan sus mismos outputs y deja de seguirse su propagacion.
+    /// Devuelve false si el bloque no es parte de la cadena.
+    pub fn append_filtered_block(
+        &mut self,
+        filtered_block: &FilteredBlock,
+    ) -> Result<bool, CustomError> {
+        if self
+            .headers
+            .find_header_position(filtered_block.hash())
+            .is_none()
+        {
+            return Ok(false);
+        }
+
+        self.tx_broadcasts.remove(&filtered_block.matched);
+        self.mempool.remove_confirmed(&filtered_block.transactions);
+        self.update_history()?;
+        Ok(true)
     }
 
     /********************     PENDING BLOCKS     ********************/
 
-    /// Agrega un pending block nuevo a PendingBlocks
-    pub fn append_pending_block(&mut self, header_hash: Vec<u8>) -> Result<(), CustomError> {
+    /// Agrega los bloques a PendingBlocks y los encola en el BlockDownloader para ser pedidos
+    pub fn append_pending_blocks(
+        &mut self,
+        inventories: Vec<Inventory>,
+    ) -> Result<(), CustomError> {
         let mut pending_blocks = self.blocks.pending_blocks_ref.lock()?;
-        pending_blocks.append_block(header_hash)?;
+        for inventory in &inventories {
+            pending_blocks.append_block(inventory.hash)?;
+        }
         drop(pending_blocks);
 
+        self.block_downloader.enqueue(inventories);
         Ok(())
     }
 
-    // /// Devuelve los pending blocks de PendingBlocks
-    pub fn get_stale_requests(&self) -> Result<Vec<Vec<u8>>, CustomError> {
-        let mut pending_blocks = self.blocks.pending_blocks_ref.lock()?;
-        pending_blocks.get_stale_requests()
+    /// Vuelve a encolar bloques que un peer no pudo entregar, para pedirlos a otro peer
+    pub fn retry_pending_blocks(&mut self, inventories: Vec<Inventory>) {
+        self.block_downloader.retry(inventories);
+    }
+
+    /// Reasigna los bloques pedidos a peers que dejaron de entregarlos y devuelve esos peers
+    pub fn check_stalled_peers(&mut self) -> Result<Vec<SocketAddrV6>, CustomError> {
+        self.block_downloader.check_timeouts()
+    }
+
+    /// Reparte los bloques encolados entre los peers y les envia los getdata
+    pub fn request_pending_blocks(&mut self) -> Result<(), CustomError> {
+        for request in self.assign_pending_blocks()? {
+            self.send_request(request);
+        }
+        Ok(())
+    }
+
+    /// Reparte los bloques encolados y las transacciones a reintentar entre los peers y devuelve los pedidos,
+    /// para enviarlos luego de liberar el lock del NodeState
+    pub fn take_data_requests(&mut self) -> Result<Vec<DataRequest>, CustomError> {
+        let mut requests = self.assign_pending_blocks()?;
+        requests.extend(self.assign_pending_inventories()?);
+        Ok(requests)
+    }
+
+    /// Arma los pedidos de los bloques encolados que se asignaron a cada peer
+    /// Los peers que no anunciaron compact blocks version 2 reciben el pedido del bloque completo
+    fn assign_pending_blocks(&mut self) -> Result<Vec<DataRequest>, CustomError> {
+        let addresses = self.get_block_peers();
+        let assignments = self.block_downloader.assign(&addresses)?;
+
+        let mut requests = vec![];
+        for (address, mut inventories) in assignments {
+            let Some(peer) = self.get_peer(&address) else { continue };
+            if !peer.compact_blocks {
+                for inventory in inventories.iter_mut() {
+                    if inventory.inventory_type == InventoryType::CompactBlock {
+                        inventory.inventory_type = InventoryType::Block;
+                    }
+                }
+            }
+            requests.push(peer.request_data(inventories));
+        }
+        Ok(requests)
     }
 
     // /// Devuelve true si el bloque esta en PendingBlocks
-    pub fn is_block_pending(&self, block_hash: &Vec<u8>) -> Result<bool, CustomError> {
+    pub fn is_block_pending(&self, block_hash: &Hash256) -> Result<bool, CustomError> {
         let pending_blocks = self.blocks.pending_blocks_ref.lock()?;
         Ok(pending_blocks.is_block_pending(block_hash))
     }
@@ -343,65 +1543,595 @@ impl NodeState {
         Ok(pending_blocks.is_empty())
     }
 
+    /// Guarda un bloque parcial, a la espera de las transacciones faltantes
+    pub fn append_partial_block(&mut self, partial_block: PartialBlock) {
+        self.partial_blocks
+            .insert(*partial_block.hash(), (partial_block, Instant::now()));
+    }
+
+    /// Quita y devuelve un bloque parcial en base a su hash
+    pub fn remove_partial_block(&mut self, block_hash: &Hash256) -> Option<PartialBlock> {
+        self.partial_blocks
+            .remove(block_hash)
+            .map(|(partial_block, _)| partial_block)
+    }
+
+    /// Descarta los bloques parciales cuyas transacciones faltantes no llegaron durante PARTIAL_BLOCK_TIMEOUT.
+    /// El BlockDownloader vuelve a pedir esos bloques cuando vence su pedido.
+    pub fn evict_stale_partial_blocks(&mut self) {
+        self.partial_blocks
+            .retain(|_, (_, received_at)| received_at.elapsed() < PARTIAL_BLOCK_TIMEOUT);
+    }
+
+    /********************     INVENTORY REQUESTS     ********************/
+
+    /// Pide a un peer las transacciones que anuncio, salvo las que ya estan pedidas a otro peer
+    pub fn request_inventories(
+        &mut self,
+        address: SocketAddrV6,
+        inventories: Vec<Inventory>,
+    ) -> Result<(), CustomError> {
+        let inventories = self.inventory_scheduler.request(address, inventories)?;
+        if !inventories.is_empty() {
+            self.send_getdata(address, inventories);
+        }
+        Ok(())
+    }
+
+    /// Registra los inventories que un peer respondio con notfound, para pedirlos a otro peer
+    pub fn inventories_not_found(
+        &mut self,
+        address: SocketAddrV6,
+        inventories: &[Inventory],
+    ) -> Result<(), CustomError> {
+        self.inventory_scheduler.not_found(address, inventories)
+    }
+
+    /// Registra los inventories que no se pudieron pedir, para pedirlos a otro peer
+    pub fn retry_inventories(&mut self, inventories: &[Inventory]) -> Result<(), CustomError> {
+        self.inventory_scheduler.failed(inventories)
+    }
+
+    /// Vuelve a pedir a otros peers los inventories cuyo pedido fallo y ya cumplieron su backoff
+    pub fn request_pending_inventories(&mut self) -> Result<(), CustomError> {
+        for request in self.assign_pending_inventories()? {
+            self.send_request(request);
+        }
+        Ok(())
+    }
+
+    /// Arma los pedidos de los inventories a reintentar que se asignaron a cada peer
+    fn assign_pending_inventories(&mut self) -> Result<Vec<DataRequest>, CustomError> {
+        let addresses: Vec<SocketAddrV6> = self.peers.iter().map(|peer| peer.address).collect();
+        let assignments = self.inventory_scheduler.assign(&addresses)?;
+        Ok(assignments
+            .into_iter()
+            .filter_map(|(address, inventories)| {
+                let peer = self.peers.iter().find(|peer| peer.address == address)?;
+                Some(peer.request_data(inventories))
+            })
+            .collect())
+    }
+
+    /// Registra que no se pudo enviar un pedido a un peer: se elimina al peer y sus pedidos se reasignan
+    pub fn request_failed(&mut self, address: SocketAddrV6) {
+        send_log(
+            &self.logger_sender,
+            Log::Warning(format!("Error requesting data to peer {}", address)),
+        );
+        self.remove_peer(address);
+    }
+
+    /// Envia los inventories a pedir a un peer en mensajes getdata de hasta MAX_GETDATA_SIZE inventories
+    fn send_getdata(&mut self, address: SocketAddrV6, inventories: Vec<Inventory>) {
+        let Some(peer) = self.get_peer(&address) else { return };
+        let request = peer.request_data(inventories);
+        self.send_request(request);
+    }
+
+    /// Envia un pedido a un peer, si falla el envio se elimina al peer y sus pedidos se reasignan
+    fn send_request(&mut self, request: DataRequest) {
+        let address = request.address;
+        if request.send().is_err() {
+            self.request_failed(address);
+        }
+    }
+
+    /********************     COMPACT FILTERS     ********************/
+
+    /// Habilita la descarga de filtros compactos desde start_height.
+    /// El UTXO no se genera hasta que termine, para incluir los bloques que coincidan con las wallets.
+    pub fn set_filters_start_height(&mut self, start_height: Option<usize>) {
+        self.filters_start_height = start_height;
+    }
+
+    /// Inicializa la descarga de filtros compactos desde filters_start_height hasta el ultimo bloque anterior al IBD.
+    /// Los filter headers se validan desde el genesis, cuyo filter header se calcula localmente.
+    /// Devuelve false si la descarga no esta habilitada o los headers todavia no estan sincronizados.
+    pub fn start_filter_sync(&mut self) -> bool {
+        if self.filters.is_some() {
+            return true;
+        }
+        let Some(start_height) = self.filters_start_height else { return false };
+        if !self.headers.is_synced() {
+            return false;
+        }
+        let stop_height = self.headers.ibd_start_index() + 1;
+        let checkpoint = (0, genesis_filter_header(Network::current()));
+        self.filters = Some(FiltersState::new(start_height, stop_height, checkpoint));
+        true
+    }
+
+    /// Devuelve true si ya se descargaron todos los filtros compactos.
+    pub fn is_filter_sync_finished(&self) -> bool {
+        match &self.filters {
+            Some(filters) => filters.is_finished(),
+            None => false,
+        }
+    }
+
+    /// Devuelve el proximo mensaje a enviar para avanzar con la descarga de filtros.
+    pub fn next_filters_request(&mut self) -> Result<Option<FiltersRequest>, CustomError> {
+        let Some(filters) = &mut self.filters else { return Ok(None) };
+        filters.next_request(&self.headers)
+    }
+
+    /// Obtiene el peer mas rapido que sirva filtros compactos
+    pub fn get_filters_peer(&mut self) -> Option<&mut Peer> {
+        self.peers
+            .iter_mut()
+            .filter(|peer| peer.services & NODE_COMPACT_FILTERS != 0)
+            .min_by(|a, b| a.benchmark.cmp(&b.benchmark))
+    }
+
+    /// Registra los filter headers recibidos y devuelve el proximo pedido de la descarga de filtros
+    pub fn append_filter_headers(
+        &mut self,
+        cfheaders: &CFHeaders,
+    ) -> Result<Option<FiltersRequest>, CustomError> {
+        let Some(filters) = &mut self.filters else { return Ok(None) };
+        filters.append_filter_headers(cfheaders, &self.headers)
+    }
+
+    /// Valida un filtro compacto recibido y devuelve true si coincide con algun script de las wallets
+    pub fn check_block_filter(&mut self, cfilter: &CFilter) -> Result<bool, CustomError> {
+        let Some(filters) = &mut self.filters else { return Ok(false) };
+        if !filters.check_filter(cfilter)? {
+            return Ok(false);
+        }
+
+        let scripts: Vec<Vec<u8>> = self
+            .wallets
+            .get_all()
+            .iter()
+            .filter_map(|wallet| wallet.get_script_pubkeys().ok())
+            .flatten()
+            .collect();
+        let matched = cfilter
+            .block_filter()?
+            .match_any(&cfilter.block_hash, &scripts)?;
+        if matched {
+            filters.append_matched_block(cfilter.block_hash);
+        }
+        Ok(matched)
+    }
+
+    /// Devuelve true si la descarga de filtros compactos esta habilitada y todavia no termino,
+    /// o si faltan descargar bloques que coincidieron con los filtros.
+    fn is_filter_scan_pending(&self) -> Result<bool, CustomError> {
+        if self.filters_start_height.is_none() {
+            return Ok(false);
+        }
+        Ok(!self.is_filter_sync_finished() || !self.is_pending_blocks_empty()?)
+    }
+
+    /// Devuelve los bloques que coincidieron con los filtros compactos, ordenados por altura.
+    fn get_filter_matched_blocks(&self) -> Vec<Hash256> {
+        let Some(filters) = &self.filters else { return vec![] };
+        let mut matched_blocks: Vec<(usize, Hash256)> = filters
+            .matched_blocks()
+            .iter()
+            .filter_map(|block_hash| Some((self.get_block_height(block_hash)?, *block_hash)))
+            .collect();
+        matched_blocks.sort();
+        matched_blocks
+            .into_iter()
+            .map(|(_, block_hash)| block_hash)
+            .collect()
+    }
+
     /********************     TRANSACTIONS     ********************/
 
+    /// Devuelve un TransactionBuilder con la active wallet de WalletsState, sus UTXO y el fee maximo configurado
+    /// Las UTXO bloqueadas o con menos de spend_min_confirmations confirmaciones no se seleccionan
+    /// Si no hay una wallet activa, devuelve un error
+    pub fn transaction_builder(&self) -> Result<TransactionBuilder<'_>, CustomError> {
+        let Some(active_wallet) = self.get_active_wallet() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        let utxo = self.get_active_wallet_utxo()?;
+        let excluded: Vec<OutPoint> = utxo
+            .iter()
+            .filter(|(out_point, value)| {
+                self.locked_utxo.is_locked(out_point)
+                    || self.get_confirmations(&value.block_hash) < self.spend_min_confirmations
+            })
+            .map(|(out_point, _)| out_point.clone())
+            .collect();
+        Ok(TransactionBuilder::new(active_wallet, utxo)
+            .max_fee(self.max_fee)
+            .exclude_inputs(excluded))
+    }
+
+    /// Habilita o deshabilita las notificaciones de escritorio de los pagos recibidos
+    pub fn set_desktop_notifications(&mut self, desktop_notifications: bool) {
+        self.desktop_notifications = desktop_notifications;
+    }
+
+    /// Actualiza el fee total maximo en satoshis de las transacciones que crea la wallet
+    pub fn set_max_fee(&mut self, max_fee: u64) {
+        self.max_fee = max_fee;
+    }
+
+    /// Actualiza las confirmaciones minimas de las UTXO que se seleccionan para financiar las transacciones
+    pub fn set_spend_min_confirmations(&mut self, spend_min_confirmations: usize) {
+        self.spend_min_confirmations = spend_min_confirmations;
+    }
+
+    /// Bloquea una UTXO para que no se seleccione para financiar transacciones hasta que se desbloquee
+    /// El bloqueo se mantiene al reiniciar el nodo
+    pub fn lock_utxo(&mut self, out_point: OutPoint) -> Result<(), CustomError> {
+        self.locked_utxo.lock(out_point)?;
+        Ok(())
+    }
+
+    /// Desbloquea una UTXO para que se pueda volver a seleccionar para financiar transacciones
+    pub fn unlock_utxo(&mut self, out_point: &OutPoint) -> Result<(), CustomError> {
+        self.locked_utxo.unlock(out_point)?;
+        Ok(())
+    }
+
+    /// Devuelve true si la UTXO esta bloqueada
+    pub fn is_utxo_locked(&self, out_point: &OutPoint) -> bool {
+        self.locked_utxo.is_locked(out_point)
+    }
+
+    /// Devuelve true si la active wallet de WalletsState es una wallet HD watch-only, importada de la public key
+    /// extendida de una hardware wallet, que firma sus transacciones en el dispositivo
+    pub fn active_wallet_signs_with_hardware_wallet(&self) -> bool {
+        self.get_active_wallet()
+            .is_some_and(|wallet| wallet.is_watch_only() && wallet.hd_account.is_some())
+    }
+
     /// Realiza una transaccion nueva para la active wallet de WalletsState
-    /// con los outputs y el fee recibidos por parametro
+    /// con los outputs, el fee y los datos OP_RETURN del pago recibido por parametro
+    /// Gasta solo los outpoints del pago (coin control), o selecciona las UTXO de la wallet si no tiene ninguno
     /// Devuelve la transaccion creada
     /// Si no hay una wallet activa, devuelve un error
     /// Si no hay suficientes fondos, devuelve un error
-    pub fn make_transaction(
-        &mut self,
-        mut outputs: HashMap<String, u64>,
-        fee: u64,
-    ) -> Result<Transaction, CustomError> {
-        let Some(active_wallet) = self.get_active_wallet() else { return Err(CustomError::WalletNotFound) };
+    pub fn make_transaction(&mut self, payment: Payment) -> Result<Transaction, CustomError> {
+        self.funded_transaction_builder(payment)?.build()
+    }
 
-        let total_value = self.calculate_total_value(fee, &outputs)?;
-        let mut active_wallet_utxo = self.get_active_wallet_utxo()?;
+    /// Devuelve el detalle de la transaccion que crearia make_transaction con el pago recibido, sin crearla,
+    /// para que el usuario la revise antes de enviarla
+    /// Si no hay una wallet activa, devuelve un error
+    /// Si no hay suficientes fondos, devuelve un error
+    pub fn get_transaction_summary(
+        &self,
+        payment: Payment,
+    ) -> Result<TransactionSummary, CustomError> {
+        self.funded_transaction_builder(payment)?.summary()
+    }
 
-        active_wallet_utxo.sort_by(|a, b| b.1.tx_out.value.cmp(&a.1.tx_out.value));
-        let (inputs, total_input_value) = calculate_inputs(&active_wallet_utxo, total_value);
+    /// Devuelve el monto maximo que se puede enviar a la direccion recibida con los demas outputs, el fee,
+    /// los datos OP_RETURN y los outpoints del pago, gastando todas las UTXO que se pueden seleccionar sin cambio
+    /// Si no hay una wallet activa, devuelve un error
+    /// Si el balance no alcanza para los demas outputs y el fee, devuelve un error
+    pub fn get_max_amount(&self, payment: Payment, address: String) -> Result<u64, CustomError> {
+        self.funded_transaction_builder(payment)?
+            .send_max(address)
+            .max_amount()
+    }
 
-        let change = total_input_value - total_value;
-        if change > 0 {
-            outputs.insert(active_wallet.pubkey.clone(), change);
+    /// Crea la transaccion como make_transaction, pero sin firmar en un PSBT (BIP 174),
+    /// para firmarla en una hardware wallet
+    /// A los inputs se les agrega la transaccion que gastan si su bloque esta guardado
+    /// Si no hay una wallet activa, devuelve un error
+    /// Si no hay suficientes fondos, devuelve un error
+    pub fn create_unsigned_transaction(&self, payment: Payment) -> Result<Psbt, CustomError> {
+        let mut psbt = self.funded_transaction_builder(payment)?.build_psbt()?;
+        self.add_previous_txs(&mut psbt)?;
+        Ok(psbt)
+    }
+
+    /// Devuelve un TransactionBuilder para la active wallet de WalletsState con los outputs, el fee y los datos del pago,
+    /// que gasta solo los outpoints del pago si tiene alguno
+    fn funded_transaction_builder(
+        &self,
+        payment: Payment,
+    ) -> Result<TransactionBuilder<'_>, CustomError> {
+        let mut builder = self.transaction_builder()?.outputs(payment.outputs);
+        if !payment.inputs.is_empty() {
+            builder = builder.with_inputs(payment.inputs);
+        }
+        if let Some(data) = payment.data {
+            builder = builder.op_return(data);
+        }
+        if let Some(address) = payment.subtract_fee_from {
+            builder = builder.subtract_fee_from(address);
+        }
+        Ok(match payment.fee {
+            Fee::Fixed(fee) => builder.fee(fee),
+            Fee::Rate(satoshis_per_byte) => builder.fee_rate(satoshis_per_byte),
+        })
+    }
+
+    /// Crea un PSBT (BIP 174) sin firmar para la active wallet de WalletsState
+    /// con los outputs y el fee recibidos por parametro, para firmarlo fuera de la wallet
+    /// Gasta los outpoints recibidos, y si no alcanzan se agregan otras UTXO de la wallet
+    /// A los inputs que no son SegWit se les agrega la transaccion que gastan si su bloque esta guardado
+    /// Si no hay una wallet activa, devuelve un error
+    /// Si no hay suficientes fondos, devuelve un error
+    pub fn create_psbt(
+        &self,
+        outputs: Vec<(String, u64)>,
+        fee: Fee,
+        required_inputs: Vec<OutPoint>,
+    ) -> Result<Psbt, CustomError> {
+        let builder = self
+            .transaction_builder()?
+            .outputs(outputs)
+            .must_spend(required_inputs);
+        let mut psbt = match fee {
+            Fee::Fixed(fee) => builder.fee(fee),
+            Fee::Rate(satoshis_per_byte) => builder.fee_rate(satoshis_per_byte),
+        }
+        .build_psbt()?;
+        self.add_previous_txs(&mut psbt)?;
+        Ok(psbt)
+    }
+
+    /// Agrega a los inputs del PSBT que gastan UTXO de la active wallet la transaccion que gastan,
+    /// si su bloque esta guardado
+    fn add_previous_txs(&self, psbt: &mut Psbt) -> Result<(), CustomError> {
+        let wallet_utxo = self.get_active_wallet_utxo()?;
+        for index in 0..psbt.inputs.len() {
+            let out_point = &psbt.unsigned_tx.inputs[index].previous_output;
+            let Some((_, value)) = wallet_utxo
+                .iter()
+                .find(|(utxo_out_point, _)| utxo_out_point == out_point)
+            else {
+                continue;
+            };
+            let Ok(block) = self.get_block(hash_as_string(&value.block_hash)) else { continue };
+            if let Some(previous_tx) = block
+                .transactions
+                .into_iter()
+                .find(|tx| tx.hash() == out_point.hash)
+            {
+                psbt.add_previous_tx(index, previous_tx)?;
+            }
         }
+        Ok(())
+    }
 
-        Transaction::create(active_wallet, inputs, outputs)
+    /// Firma con la wallet activa los inputs del PSBT que gastan sus outputs, incluidos los de sus multisigs,
+    /// y finaliza los inputs que quedan con todas las firmas necesarias
+    /// Devuelve el PSBT y true si todos sus inputs quedaron finalizados
+    /// Devuelve un error si no hay una wallet activa o si es watch-only
+    pub fn process_psbt(&self, mut psbt: Psbt) -> Result<(Psbt, bool), CustomError> {
+        let Some(active_wallet) = self.get_active_wallet() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        psbt.sign(active_wallet)?;
+        let complete = psbt.finalize();
+        Ok((psbt, complete))
     }
 
-    fn calculate_total_value(
+    /// Co-firma con la wallet activa los inputs de la transaccion que gastan outputs P2SH de sus multisigs
+    /// Devuelve la transaccion firmada y true si esos inputs quedaron con todas las firmas requeridas
+    /// Devuelve un error si no hay una wallet activa o si ningun input gasta un output de sus multisigs
+    pub fn cosign_transaction(
         &self,
-        fee: u64,
-        outputs: &HashMap<String, u64>,
-    ) -> Result<u64, CustomError> {
-        let mut total_value = fee;
-        for output in outputs.values() {
-            total_value += output;
+        mut transaction: Transaction,
+    ) -> Result<(Transaction, bool), CustomError> {
+        let Some(active_wallet) = self.get_active_wallet() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        let wallet_utxo = self.get_active_wallet_utxo()?;
+
+        let mut signed_inputs = 0;
+        let mut complete = true;
+        for index in 0..transaction.inputs.len() {
+            let previous_output = transaction.inputs[index].previous_output.clone();
+            let Some(redeem_script) = wallet_utxo
+                .iter()
+                .find(|(out_point, _)| *out_point == previous_output)
+                .and_then(|(_, value)| active_wallet.find_multisig(&value.tx_out.script_pubkey))
+            else {
+                continue;
+            };
+            let privkey = active_wallet.get_multisig_signing_key(redeem_script)?;
+            complete &= transaction.sign_multisig_input(index, redeem_script, &privkey)?;
+            signed_inputs += 1;
         }
-        let wallet_balance = self.get_active_wallet_balance()?;
-        if total_value > wallet_balance {
-            return Err(CustomError::InsufficientFunds);
+        if signed_inputs == 0 {
+            return Err(CustomError::Wallet(WalletError::CannotSignTx));
         }
-        Ok(total_value)
+        Ok((transaction, complete))
+    }
+
+    /// Crea una transaccion que reemplaza a una pending tx propia pagando mas fee (BIP 125)
+    /// Gasta los mismos inputs y envia los mismos valores a los outputs que no son de la wallet activa,
+    /// con el fee de prioridad alta y al menos el fee original mas el fee minimo de relay del tamaño del reemplazo
+    /// Si hace falta se agregan otras UTXO de la wallet para cubrir el nuevo fee
+    /// Devuelve un error si la transaccion no esta pendiente, no indica RBF o gasta outputs que no son de la wallet
+    pub fn bump_fee(&self, tx_hash: &Hash256) -> Result<Transaction, CustomError> {
+        let Some(original) = self.get_pending_tx(tx_hash) else { return Err(CustomError::Wallet(WalletError::TransactionNotFound)) };
+        let Some(active_wallet) = self.get_active_wallet() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        if !original.signals_rbf() {
+            return Err(CustomError::Wallet(WalletError::CannotBumpFee));
+        }
+
+        let wallet_utxo = self.get_active_wallet_utxo()?;
+        let mut inputs_value = 0;
+        for input in &original.inputs {
+            let Some((_, value)) = wallet_utxo
+                .iter()
+                .find(|(out_point, _)| *out_point == input.previous_output)
+            else {
+                return Err(CustomError::Wallet(WalletError::CannotBumpFee));
+            };
+            inputs_value += value.tx_out.value;
+        }
+        let outputs_value: u64 = original.outputs.iter().map(|output| output.value).sum();
+        let Some(original_fee) = inputs_value.checked_sub(outputs_value) else { return Err(CustomError::Wallet(WalletError::CannotBumpFee)) };
+
+        let wallet_scripts = active_wallet.get_script_pubkeys()?;
+        let mut builder = self.transaction_builder()?.must_spend(
+            original
+                .inputs
+                .iter()
+                .map(|input| input.previous_output.clone())
+                .collect(),
+        );
+        for output in &original.outputs {
+            if !wallet_scripts.contains(&output.script_pubkey) {
+                builder = builder.add_output(get_address(&output.script_pubkey)?, output.value);
+            }
+        }
+        builder
+            .fee_rate(self.get_fee_rate(FeePriority::High))
+            .replaces(original_fee)
+            .build()
     }
-}
 
-fn calculate_inputs(
-    active_wallet_utxo: &[(OutPoint, UTXOValue)],
-    total_value: u64,
-) -> (Vec<OutPoint>, u64) {
-    let mut inputs = vec![];
-    let mut total_input_value = 0;
-    for (out_point, tx_out) in active_wallet_utxo.iter() {
-        inputs.push(out_point.clone());
-        total_input_value += tx_out.tx_out.value;
-        if total_input_value >= total_value {
-            break;
+    /// Crea una transaccion hija que gasta los outputs de la wallet activa de una pending tx y los envia a su
+    /// direccion de cambio pagando mas fee, para que los mineros incluyan ambas y la pending tx se confirme antes (CPFP)
+    /// El fee de la hija cubre el fee de prioridad alta de las dos transacciones, descontando el que paga la pending tx
+    /// Devuelve un error si la transaccion no esta pendiente, si no tiene outputs de la wallet activa sin gastar
+    /// o si esos outputs no alcanzan para pagar el fee
+    pub fn cpfp(&self, tx_hash: &Hash256) -> Result<Transaction, CustomError> {
+        let Some(parent) = self.get_pending_tx(tx_hash) else { return Err(CustomError::Wallet(WalletError::TransactionNotFound)) };
+        let Some(active_wallet) = self.get_active_wallet() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+
+        let parent_hash = parent.hash();
+        let outputs: Vec<(OutPoint, TransactionOutput)> = self
+            .mempool
+            .unspent_outputs(&active_wallet.get_script_pubkeys()?)
+            .into_iter()
+            .filter(|(out_point, output)| out_point.hash == parent_hash && !output.is_p2sh())
+            .collect();
+        if outputs.is_empty() {
+            return Err(CustomError::Wallet(WalletError::CannotCpfp));
         }
+
+        let segwit_inputs = outputs
+            .iter()
+            .filter(|(_, output)| output.is_p2wpkh())
+            .count();
+        let child_vsize = estimate_vsize(outputs.len() - segwit_inputs, segwit_inputs, 1);
+        // si no se conocen los outputs que gasta la pending tx, la hija paga el fee de las dos
+        let parent_fee = self.mempool.fee(&parent, &self.utxo).unwrap_or(0);
+        let package_fee = self.get_fee_rate(FeePriority::High) * (parent.vsize() + child_vsize);
+        let fee = package_fee
+            .saturating_sub(parent_fee)
+            .max(MIN_FEE_RATE * child_vsize);
+
+        let value: u64 = outputs.iter().map(|(_, output)| output.value).sum();
+        let Some(child_value) = value.checked_sub(fee) else { return Err(CustomError::Wallet(WalletError::InsufficientFunds)) };
+        let utxo = outputs
+            .into_iter()
+            .map(|(out_point, tx_out)| {
+                // los outputs sin confirmar no tienen bloque
+                let value = UTXOValue {
+                    tx_out,
+                    block_hash: Hash256::ZERO,
+                    block_timestamp: 0,
+                };
+                (out_point, value)
+            })
+            .collect();
+        TransactionBuilder::new(active_wallet, utxo)
+            .add_output(active_wallet.get_change_address(), child_value)
+            .fee(fee)
+            .max_fee(self.max_fee)
+            .build()
+    }
+
+    /// Crea una transaccion que barre los fondos de una private key en formato WIF (sweep),
+    /// gastando todas sus UTXO confirmadas hacia la direccion de recepcion de la wallet activa.
+    /// A diferencia de import_key, la clave no se agrega a las wallets.
+    /// Devuelve CustomError si:
+    /// - No hay una wallet activa.
+    /// - La clave no es una private key WIF valida.
+    /// - La clave no tiene fondos suficientes para pagar el fee con el fee rate recibido, en satoshis por byte.
+    pub fn sweep_key(&self, wif: &str, fee_rate: u64) -> Result<Transaction, CustomError> {
+        let Some(active_wallet) = self.get_active_wallet() else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        let key_wallet = Wallet::from_wif(String::from("sweep"), wif, &self.utxo)?;
+        let utxo: Vec<(OutPoint, UTXOValue)> = self
+            .utxo
+            .generate_wallet_utxo(&key_wallet)?
+            .into_iter()
+            .filter(|(_, value)| !value.tx_out.is_p2sh())
+            .collect();
+        TransactionBuilder::new(&key_wallet, utxo)
+            .send_max(active_wallet.get_receive_address())
+            .fee_rate(fee_rate)
+            .max_fee(self.max_fee)
+            .build()
+    }
+
+    /// Reemplaza en el Mempool las pending txs que gastan los mismos outputs que la transaccion recibida
+    pub fn replace_pending_tx(&mut self, transaction: Transaction) -> Result<bool, CustomError> {
+        let replaced = self.mempool.replace_pending_tx(transaction)?;
+
+        if replaced {
+            self.update_history()?;
+            self.gui_sender
+                .send(GUIEvents::NewPendingTx)
+                .map_err(|_| CustomError::CannotInitGUI)?;
+            send_log(
+                &self.logger_sender,
+                Log::Message("Pending transaction replaced".to_string()),
+            );
+        }
+
+        Ok(replaced)
+    }
+
+    /// Devuelve el fee estimado en satoshis por byte virtual para la prioridad recibida,
+    /// segun los fees pagados en los bloques recientes
+    pub fn get_fee_rate(&self, priority: FeePriority) -> u64 {
+        self.fee_estimator.estimate(priority)
+    }
+
+    /// Registra una transaccion propia enviada a los peers de sent_to, de un total de peers que la pueden recibir,
+    /// para seguir su propagacion
+    pub fn append_tx_broadcast(
+        &mut self,
+        tx_hash: Hash256,
+        sent_to: Vec<SocketAddrV6>,
+        peers: usize,
+    ) -> BroadcastStatus {
+        self.tx_broadcasts.append(tx_hash, sent_to, peers)
+    }
+
+    /// Registra el anuncio de una transaccion propia por parte de un peer
+    /// Devuelve None si la transaccion no es propia o el peer ya la habia anunciado
+    pub fn tx_announced(
+        &mut self,
+        tx_hash: &Hash256,
+        address: SocketAddrV6,
+    ) -> Option<BroadcastStatus> {
+        self.tx_broadcasts.announced(tx_hash, address)
+    }
+
+    /// Registra el rechazo de una transaccion propia por parte de un peer
+    /// Devuelve None si la transaccion no es propia o el peer ya la habia rechazado
+    pub fn tx_rejected(
+        &mut self,
+        tx_hash: &Hash256,
+        address: SocketAddrV6,
+        reason: String,
+    ) -> Option<BroadcastStatus> {
+        self.tx_broadcasts.rejected(tx_hash, address, reason)
     }
-    (inputs, total_input_value)
 }
 
 fn create_store_dir(path: &String) -> Result<(), CustomError> {
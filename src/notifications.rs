@@ -0,0 +1,171 @@
+use std::{collections::HashSet, process::Command, thread};
+
+use crate::{
+    states::history_state::{Direction, HistoryEntry},
+    structs::hash256::Hash256,
+};
+
+/// Programa con el que se envian las notificaciones de escritorio, incluido en libnotify.
+const NOTIFY_SEND: &str = "notify-send";
+/// Nombre de la aplicacion en las notificaciones de escritorio.
+const APP_NAME: &str = "Rust-eze";
+/// Cantidad de satoshis en un bitcoin.
+const SATOSHIS_PER_BITCOIN: f64 = 100_000_000.0;
+
+/// Notification es un aviso para el usuario, que se muestra como notificacion de escritorio y en la interfaz grafica.
+/// Los elementos son:
+/// - title: Titulo de la notificacion.
+/// - body: Texto de la notificacion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+impl Notification {
+    /// Muestra la notificacion en el escritorio con notify-send, en otro thread para no esperar al programa.
+    /// Si notify-send no esta instalado la notificacion no se muestra.
+    pub fn send_to_desktop(&self) {
+        let title = self.title.clone();
+        let body = self.body.clone();
+        thread::spawn(move || {
+            let _ = Command::new(NOTIFY_SEND)
+                .args(["--app-name", APP_NAME, &title, &body])
+                .status();
+        });
+    }
+}
+
+/// PaymentNotifier genera las notificaciones de los pagos que reciben las wallets:
+/// una cuando la transaccion aparece en el mempool y otra cuando se confirma.
+/// Recuerda las transacciones notificadas para no repetir las notificaciones cada vez que se actualiza el historial.
+/// Los elementos son:
+/// - known_wallets: Public keys de las wallets cuyo historial ya se registro.
+/// - seen: Transacciones pendientes de cada wallet ya registradas, por la public key de la wallet y el hash de la transaccion.
+/// - confirmed: Transacciones confirmadas de cada wallet ya registradas.
+#[derive(Debug)]
+pub struct PaymentNotifier {
+    known_wallets: HashSet<String>,
+    seen: HashSet<(String, Hash256)>,
+    confirmed: HashSet<(String, Hash256)>,
+}
+
+impl Default for PaymentNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PaymentNotifier {
+    /// Inicializa el notifier sin transacciones registradas.
+    pub fn new() -> Self {
+        Self {
+            known_wallets: HashSet::new(),
+            seen: HashSet::new(),
+            confirmed: HashSet::new(),
+        }
+    }
+
+    /// Registra las transacciones del historial de una wallet y devuelve las notificaciones de los pagos recibidos
+    /// que no estaban registrados.
+    /// La primera vez que se registra una wallet, o si notify es false (por ejemplo durante la descarga inicial de bloques),
+    /// solo se registran las transacciones, para no notificar pagos viejos.
+    pub fn update(
+        &mut self,
+        wallet_pubkey: &str,
+        wallet_name: &str,
+        entries: &[HistoryEntry],
+        notify: bool,
+    ) -> Vec<Notification> {
+        let first_update = self.known_wallets.insert(wallet_pubkey.to_string());
+        let notify = notify && !first_update;
+        let mut notifications = vec![];
+        for entry in entries {
+            let key = (wallet_pubkey.to_string(), entry.tx_hash);
+            let (registered, title) = match entry.block_height {
+                None => (&mut self.seen, "Incoming payment"),
+                Some(_) => (&mut self.confirmed, "Payment confirmed"),
+            };
+            if !registered.insert(key) {
+                continue;
+            }
+            if notify && entry.direction == Direction::Received {
+                notifications.push(Notification {
+                    title: title.to_string(),
+                    body: format!(
+                        "{:.8} BTC received in {}",
+                        entry.amount as f64 / SATOSHIS_PER_BITCOIN,
+                        wallet_name
+                    ),
+                });
+            }
+        }
+        notifications
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tx_hash: u8, direction: Direction, block_height: Option<usize>) -> HistoryEntry {
+        HistoryEntry {
+            tx_hash: Hash256::new([tx_hash; 32]),
+            direction,
+            amount: 150_000,
+            block_hash: block_height.map(|_| Hash256::new([1; 32])),
+            block_height,
+            timestamp: None,
+            address: None,
+            output_type: None,
+            label: None,
+            fee: None,
+        }
+    }
+
+    #[test]
+    fn notifies_incoming_payments_when_seen_and_confirmed() {
+        let mut notifier = PaymentNotifier::new();
+        let old = entry(1, Direction::Received, Some(10));
+        assert!(notifier
+            .update("pubkey", "wallet", std::slice::from_ref(&old), true)
+            .is_empty());
+
+        let pending = entry(2, Direction::Received, None);
+        let sent = entry(3, Direction::Sent, None);
+        let notifications = notifier.update(
+            "pubkey",
+            "wallet",
+            &[pending.clone(), sent, old.clone()],
+            true,
+        );
+        assert_eq!(
+            notifications,
+            vec![Notification {
+                title: String::from("Incoming payment"),
+                body: String::from("0.00150000 BTC received in wallet"),
+            }]
+        );
+        assert!(notifier
+            .update("pubkey", "wallet", &[pending, old.clone()], true)
+            .is_empty());
+
+        let confirmed = entry(2, Direction::Received, Some(11));
+        let notifications = notifier.update("pubkey", "wallet", &[confirmed, old], true);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].title, "Payment confirmed");
+    }
+
+    #[test]
+    fn does_not_notify_while_syncing() {
+        let mut notifier = PaymentNotifier::new();
+        notifier.update("pubkey", "wallet", &[], true);
+        let confirmed = entry(1, Direction::Received, Some(10));
+        assert!(notifier
+            .update("pubkey", "wallet", std::slice::from_ref(&confirmed), false)
+            .is_empty());
+        assert!(notifier
+            .update("pubkey", "wallet", &[confirmed], true)
+            .is_empty());
+    }
+}
@@ -1,160 +1,211 @@
-use std::net::{Ipv6Addr, SocketAddrV6};
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv6Addr, SocketAddrV6},
+};
 
-use crate::error::CustomError;
+use crate::error::{CustomError, ParseError};
 
 /// BufferParser es una estructura que contiene los elementos necesarios para parsear un buffer.
+/// No copia el buffer sino que lo recorre prestado, por lo que los buffers extraidos apuntan al buffer original.
+/// Todas las extracciones verifican que queden bytes suficientes, y si no devuelven error.
 /// Los elementos son:
 /// - buffer: Buffer a parsear.
 /// - pos: Posicion actual del buffer.
-pub struct BufferParser {
-    buffer: Vec<u8>,
+pub struct BufferParser<'a> {
+    buffer: &'a [u8],
     pos: usize,
 }
 
-impl BufferParser {
+impl<'a> BufferParser<'a> {
     #[must_use]
     /// Inicializa el parser de un buffer en la posicion 0.
-    pub fn new(buffer: Vec<u8>) -> Self {
+    pub fn new(buffer: &'a [u8]) -> Self {
         Self { buffer, pos: 0 }
     }
 
     /// Devuelve el largo restante del buffer.
-    pub fn len(&mut self) -> usize {
+    pub fn len(&self) -> usize {
         self.buffer.len() - self.pos
     }
 
     /// Devuelve true si fue recorrido completamente
-    pub fn is_empty(&mut self) -> bool {
-        self.buffer.len() - self.pos == 0
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Devuelve la parte del buffer que todavia no fue recorrida, sin avanzar.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.buffer[self.pos..]
+    }
+
+    /// Devuelve los siguientes size bytes del buffer sin avanzar.
+    pub fn peek(&self, size: usize) -> Result<&'a [u8], CustomError> {
+        self.pos
+            .checked_add(size)
+            .and_then(|end| self.buffer.get(self.pos..end))
+            .ok_or(CustomError::Parse(ParseError::SerializedBufferIsInvalid))
     }
 
     /// Extrae un buffer de tamaño size del buffer.
-    pub fn extract_buffer(&mut self, size: usize) -> Result<&[u8], CustomError> {
-        let buffer = match self.buffer.get(self.pos..(self.pos + size)) {
-            Some(buffer) => Ok(buffer),
-            None => return Err(CustomError::SerializedBufferIsInvalid),
-        };
+    pub fn extract_buffer(&mut self, size: usize) -> Result<&'a [u8], CustomError> {
+        let buffer = self.peek(size)?;
         self.pos += size;
-        buffer
+        Ok(buffer)
     }
+}
 
-    /// extrae un u8 del buffer
-    pub fn extract_u8(&mut self) -> Result<u8, CustomError> {
-        let slice: [u8; 1] = self
-            .extract_buffer(1)?
+impl Parser for BufferParser<'_> {
+    fn extract_array<const N: usize>(&mut self) -> Result<[u8; N], CustomError> {
+        self.extract_buffer(N)?
             .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
+            .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))
+    }
 
-        Ok(u8::from_le_bytes(slice))
+    fn extract_bytes(&mut self, size: usize) -> Result<Vec<u8>, CustomError> {
+        Ok(self.extract_buffer(size)?.to_vec())
     }
 
-    /// extrae un u16 del buffer
-    pub fn extract_u16(&mut self) -> Result<u16, CustomError> {
-        let slice: [u8; 2] = self
-            .extract_buffer(2)?
-            .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
+    fn extract_remaining(&mut self) -> Result<Vec<u8>, CustomError> {
+        self.extract_bytes(self.len())
+    }
 
-        Ok(u16::from_le_bytes(slice))
+    fn is_empty(&mut self) -> bool {
+        self.len() == 0
     }
+}
 
-    /// extrae un u32 del buffer
-    pub fn extract_u32(&mut self) -> Result<u32, CustomError> {
-        let slice: [u8; 4] = self
-            .extract_buffer(4)?
-            .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
+/// StreamParser extrae los campos directamente de un reader, sin copiar antes todo su contenido a un buffer.
+/// Los buffers de largo variable se leen a medida que llegan, por lo que un largo invalido no reserva memoria de mas.
+/// Los elementos son:
+/// - reader: Reader del que se extraen los bytes.
+pub struct StreamParser<R: Read> {
+    reader: R,
+}
 
-        Ok(u32::from_le_bytes(slice))
+impl<R: Read> StreamParser<R> {
+    #[must_use]
+    /// Inicializa el parser de un reader.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
     }
+}
 
-    /// extrae un u64 del buffer
-    pub fn extract_u64(&mut self) -> Result<u64, CustomError> {
-        let slice: [u8; 8] = self
-            .extract_buffer(8)?
-            .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
+impl<R: Read> Parser for StreamParser<R> {
+    fn extract_array<const N: usize>(&mut self) -> Result<[u8; N], CustomError> {
+        let mut array = [0; N];
+        self.reader
+            .read_exact(&mut array)
+            .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+        Ok(array)
+    }
 
-        Ok(u64::from_le_bytes(slice))
+    fn extract_bytes(&mut self, size: usize) -> Result<Vec<u8>, CustomError> {
+        let mut buffer = vec![];
+        self.reader
+            .by_ref()
+            .take(size as u64)
+            .read_to_end(&mut buffer)
+            .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+        if buffer.len() != size {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(buffer)
     }
 
-    /// extrae un i8 del buffer
-    pub fn extract_i8(&mut self) -> Result<i8, CustomError> {
-        let slice: [u8; 1] = self
-            .extract_buffer(1)?
-            .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
+    fn extract_remaining(&mut self) -> Result<Vec<u8>, CustomError> {
+        let mut buffer = vec![];
+        self.reader
+            .read_to_end(&mut buffer)
+            .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+        Ok(buffer)
+    }
 
-        Ok(i8::from_le_bytes(slice))
+    /// Para saber si el reader termino intenta leer un byte, que se descarta si lo habia.
+    fn is_empty(&mut self) -> bool {
+        matches!(self.reader.read(&mut [0]), Ok(0))
     }
+}
 
-    /// extrae un i16 del buffer
-    pub fn extract_i16(&mut self) -> Result<i16, CustomError> {
-        let slice: [u8; 2] = self
-            .extract_buffer(2)?
-            .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
+/// Este trait representa una fuente de la que se extraen campos serializados, ya sea un buffer (BufferParser)
+/// o un reader (StreamParser), para que el parseo no dependa de donde vienen los bytes.
+/// Cada fuente implementa la extraccion de arrays y buffers y el chequeo de fin, el resto de las extracciones se arman a partir de ellas.
+/// Todas las extracciones devuelven error si no quedan bytes suficientes.
+pub trait Parser {
+    /// Extrae un array de tamaño fijo N.
+    fn extract_array<const N: usize>(&mut self) -> Result<[u8; N], CustomError>;
 
-        Ok(i16::from_le_bytes(slice))
+    /// Extrae una copia de los siguientes size bytes.
+    fn extract_bytes(&mut self, size: usize) -> Result<Vec<u8>, CustomError>;
+
+    /// Extrae una copia de todos los bytes que quedan.
+    fn extract_remaining(&mut self) -> Result<Vec<u8>, CustomError>;
+
+    /// Devuelve true si no quedan bytes por extraer.
+    fn is_empty(&mut self) -> bool;
+
+    /// Extrae un hash de 32 bytes.
+    fn extract_hash32(&mut self) -> Result<[u8; 32], CustomError> {
+        self.extract_array()
     }
 
-    /// extrae un i32 del buffer
-    pub fn extract_i32(&mut self) -> Result<i32, CustomError> {
-        let slice: [u8; 4] = self
-            .extract_buffer(4)?
-            .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
+    /// extrae un u8
+    fn extract_u8(&mut self) -> Result<u8, CustomError> {
+        Ok(u8::from_le_bytes(self.extract_array()?))
+    }
 
-        Ok(i32::from_le_bytes(slice))
+    /// extrae un u16
+    fn extract_u16(&mut self) -> Result<u16, CustomError> {
+        Ok(u16::from_le_bytes(self.extract_array()?))
     }
 
-    /// extrae un i64 del buffer
-    pub fn extract_i64(&mut self) -> Result<i64, CustomError> {
-        let slice: [u8; 8] = self
-            .extract_buffer(8)?
-            .try_into()
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
-
-        Ok(i64::from_le_bytes(slice))
-    }
-
-    /// extrae un varint del buffer
-    pub fn extract_varint(&mut self) -> Result<u64, CustomError> {
-        let first_byte = self.extract_u8()?;
-        let slice = match first_byte {
-            0xFF_u8 => {
-                let slice: [u8; 8] = self
-                    .extract_buffer(8)?
-                    .try_into()
-                    .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
-
-                u64::from_le_bytes([
-                    slice[0], slice[1], slice[2], slice[3], slice[4], slice[5], slice[6], slice[7],
-                ])
-            }
-            0xFE_u8 => {
-                let slice: [u8; 4] = self
-                    .extract_buffer(4)?
-                    .try_into()
-                    .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
-
-                u64::from_le_bytes([slice[0], slice[1], slice[2], slice[3], 0, 0, 0, 0])
-            }
-            0xFD_u8 => {
-                let slice: [u8; 2] = self
-                    .extract_buffer(2)?
-                    .try_into()
-                    .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
-
-                u64::from_le_bytes([slice[0], slice[1], 0, 0, 0, 0, 0, 0])
-            }
-            _ => u64::from_le_bytes([first_byte, 0, 0, 0, 0, 0, 0, 0]),
+    /// extrae un u32
+    fn extract_u32(&mut self) -> Result<u32, CustomError> {
+        Ok(u32::from_le_bytes(self.extract_array()?))
+    }
+
+    /// extrae un u64
+    fn extract_u64(&mut self) -> Result<u64, CustomError> {
+        Ok(u64::from_le_bytes(self.extract_array()?))
+    }
+
+    /// extrae un i8
+    fn extract_i8(&mut self) -> Result<i8, CustomError> {
+        Ok(i8::from_le_bytes(self.extract_array()?))
+    }
+
+    /// extrae un i16
+    fn extract_i16(&mut self) -> Result<i16, CustomError> {
+        Ok(i16::from_le_bytes(self.extract_array()?))
+    }
+
+    /// extrae un i32
+    fn extract_i32(&mut self) -> Result<i32, CustomError> {
+        Ok(i32::from_le_bytes(self.extract_array()?))
+    }
+
+    /// extrae un i64
+    fn extract_i64(&mut self) -> Result<i64, CustomError> {
+        Ok(i64::from_le_bytes(self.extract_array()?))
+    }
+
+    /// extrae un varint
+    fn extract_varint(&mut self) -> Result<u64, CustomError> {
+        let value = match self.extract_u8()? {
+            0xFF_u8 => self.extract_u64()?,
+            0xFE_u8 => self.extract_u32()? as u64,
+            0xFD_u8 => self.extract_u16()? as u64,
+            first_byte => first_byte as u64,
         };
-        Ok(slice)
+        Ok(value)
     }
 
-    /// extrae una direccion del buffer
-    pub fn extract_address(&mut self) -> Result<SocketAddrV6, CustomError> {
+    /// Extrae un compact size, es el nombre que recibe el varint en el protocolo de Bitcoin.
+    fn extract_compact_size(&mut self) -> Result<u64, CustomError> {
+        self.extract_varint()
+    }
+
+    /// extrae una direccion
+    fn extract_address(&mut self) -> Result<SocketAddrV6, CustomError> {
         let ipv6 = Ipv6Addr::new(
             u16::from_be_bytes([self.extract_u8()?, self.extract_u8()?]),
             u16::from_be_bytes([self.extract_u8()?, self.extract_u8()?]),
@@ -170,37 +221,42 @@ impl BufferParser {
         Ok(socket)
     }
 
-    /// extrae un string del buffer del tamaño recibido
-    pub fn extract_string(&mut self, size: usize) -> Result<String, CustomError> {
-        let buffer = self.extract_buffer(size)?;
-        let string = String::from_utf8(buffer.to_vec())
-            .map_err(|_| CustomError::SerializedBufferIsInvalid)?;
-        Ok(string)
+    /// extrae un string del tamaño recibido
+    fn extract_string(&mut self, size: usize) -> Result<String, CustomError> {
+        String::from_utf8(self.extract_bytes(size)?)
+            .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))
     }
 }
 
 pub trait VarIntSerialize {
     fn to_varint_bytes(&self) -> Vec<u8>;
+
+    /// Escribe el varint en el writer con la menor cantidad de bytes posible.
+    fn write_varint<W: Write>(&self, writer: &mut W) -> io::Result<()>;
 }
 
 impl VarIntSerialize for usize {
     fn to_varint_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        // escribir en un Vec no puede fallar
+        let _ = self.write_varint(&mut buffer);
+        buffer
+    }
+
+    fn write_varint<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         if *self < 0xFD {
-            return (*self as u8).to_le_bytes().to_vec();
+            return writer.write_all(&[*self as u8]);
         }
         if *self <= 0xFFFF {
-            let mut buffer = [0xFD_u8].to_vec();
-            buffer.append(&mut (*self as u16).to_le_bytes().to_vec());
-            return buffer;
+            writer.write_all(&[0xFD_u8])?;
+            return writer.write_all(&(*self as u16).to_le_bytes());
         }
         if *self <= 0xFFFFFFFF {
-            let mut buffer = [0xFE_u8].to_vec();
-            buffer.append(&mut (*self as u32).to_le_bytes().to_vec());
-            return buffer;
+            writer.write_all(&[0xFE_u8])?;
+            return writer.write_all(&(*self as u32).to_le_bytes());
         }
-        let mut buffer = [0xFF_u8].to_vec();
-        buffer.append(&mut self.to_le_bytes().to_vec());
-        buffer
+        writer.write_all(&[0xFF_u8])?;
+        writer.write_all(&(*self as u64).to_le_bytes())
     }
 }
 
@@ -212,14 +268,14 @@ mod tests {
     #[test]
     fn test_parser_utils() {
         let buffer = vec![];
-        let mut parser = BufferParser::new(buffer);
+        let parser = BufferParser::new(&buffer);
         assert_eq!(parser.len(), 0);
         assert_eq!(parser.is_empty(), true);
     }
 
     #[test]
     fn extract_u8() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03]);
         assert_eq!(buffer.extract_u8().unwrap(), 0x01);
         assert_eq!(buffer.extract_u8().unwrap(), 0x02);
         assert_eq!(buffer.extract_u8().unwrap(), 0x03);
@@ -227,26 +283,26 @@ mod tests {
 
     #[test]
     fn extract_u16() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03, 0x04]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03, 0x04]);
         assert_eq!(buffer.extract_u16().unwrap(), 0x0201);
         assert_eq!(buffer.extract_u16().unwrap(), 0x0403);
     }
 
     #[test]
     fn extract_u32() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03, 0x04]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03, 0x04]);
         assert_eq!(buffer.extract_u32().unwrap(), 0x04030201);
     }
 
     #[test]
     fn extract_u64() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
         assert_eq!(buffer.extract_u64().unwrap(), 0x0807060504030201);
     }
 
     #[test]
     fn extract_i8() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03]);
         assert_eq!(buffer.extract_i8().unwrap(), 0x01);
         assert_eq!(buffer.extract_i8().unwrap(), 0x02);
         assert_eq!(buffer.extract_i8().unwrap(), 0x03);
@@ -254,26 +310,26 @@ mod tests {
 
     #[test]
     fn extract_i16() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03, 0x04]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03, 0x04]);
         assert_eq!(buffer.extract_i16().unwrap(), 0x0201);
         assert_eq!(buffer.extract_i16().unwrap(), 0x0403);
     }
 
     #[test]
     fn extract_i32() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03, 0x04]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03, 0x04]);
         assert_eq!(buffer.extract_i32().unwrap(), 0x04030201);
     }
 
     #[test]
     fn extract_i64() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
         assert_eq!(buffer.extract_u64().unwrap(), 0x0807060504030201);
     }
 
     #[test]
     fn extract_invalid_returns_error() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03]);
         assert!(buffer.extract_u8().is_ok());
         assert!(buffer.extract_u8().is_ok());
         assert!(buffer.extract_u8().is_ok());
@@ -282,29 +338,28 @@ mod tests {
 
     #[test]
     fn extract_larger_than_buffer_returns_error() {
-        let mut buffer = BufferParser::new(vec![0x01, 0x02, 0x03]);
+        let mut buffer = BufferParser::new(&[0x01, 0x02, 0x03]);
         assert!(buffer.extract_u32().is_err());
     }
 
     #[test]
     fn extract_varint() {
-        let mut buffer = BufferParser::new(vec![0x03]);
+        let mut buffer = BufferParser::new(&[0x03]);
         assert_eq!(buffer.extract_varint().unwrap(), 0x03);
 
-        let mut buffer = BufferParser::new(vec![0xFD, 0x03, 0x02]);
+        let mut buffer = BufferParser::new(&[0xFD, 0x03, 0x02]);
         assert_eq!(buffer.extract_varint().unwrap(), 0x0203);
 
-        let mut buffer = BufferParser::new(vec![0xFE, 0x03, 0x02, 0x01, 0x00]);
+        let mut buffer = BufferParser::new(&[0xFE, 0x03, 0x02, 0x01, 0x00]);
         assert_eq!(buffer.extract_varint().unwrap(), 0x010203);
 
-        let mut buffer =
-            BufferParser::new(vec![0xFF, 0x03, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let mut buffer = BufferParser::new(&[0xFF, 0x03, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
         assert_eq!(buffer.extract_varint().unwrap(), 0x00000000010203);
     }
 
     #[test]
     fn extract_address() {
-        let mut buffer = BufferParser::new(vec![
+        let mut buffer = BufferParser::new(&[
             0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x10, 0x11, 0x12, 0x13, 0x14,
             0x15, 0x16, 0x17, 0x18,
         ]);
@@ -319,10 +374,94 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extract_varint_as_compact_size() {
+        let mut buffer = BufferParser::new(&[0xFD, 0x03, 0x02, 0xFE, 0x01]);
+        assert_eq!(buffer.extract_compact_size().unwrap(), 0x0203);
+        assert!(buffer.extract_compact_size().is_err());
+    }
+
+    #[test]
+    fn peek_does_not_advance() {
+        let buffer = [0x01, 0x02, 0x03];
+        let mut parser = BufferParser::new(&buffer);
+        assert_eq!(parser.peek(2).unwrap(), &[0x01, 0x02]);
+        assert_eq!(parser.extract_u8().unwrap(), 0x01);
+        assert_eq!(parser.remaining(), &[0x02, 0x03]);
+        assert!(parser.peek(3).is_err());
+        assert!(parser.peek(usize::MAX).is_err());
+        assert_eq!(parser.len(), 2);
+    }
+
+    #[test]
+    fn extract_fixed_size_arrays() {
+        let buffer: Vec<u8> = (0..40).collect();
+        let mut parser = BufferParser::new(&buffer);
+        let hash = parser.extract_hash32().unwrap();
+        assert_eq!(hash[..], buffer[..32]);
+        assert_eq!(parser.extract_array::<4>().unwrap(), [32, 33, 34, 35]);
+
+        // si no hay bytes suficientes no avanza
+        assert!(parser.extract_hash32().is_err());
+        assert_eq!(parser.len(), 4);
+    }
+
+    #[test]
+    fn extracted_buffers_outlive_the_parser() {
+        let buffer = vec![0x01, 0x02, 0x03];
+        let extracted = {
+            let mut parser = BufferParser::new(&buffer);
+            parser.extract_buffer(2).unwrap()
+        };
+        assert_eq!(extracted, &[0x01, 0x02]);
+    }
+
+    #[test]
+    fn stream_parser_extracts_from_a_reader() {
+        let buffer = [0x01, 0x02, 0x03, 0x04, 0xFD, 0x03, 0x02, 0x05, 0x06];
+        let mut reader = &buffer[..];
+        let mut parser = StreamParser::new(&mut reader);
+        assert_eq!(parser.extract_u16().unwrap(), 0x0201);
+        assert_eq!(parser.extract_array::<2>().unwrap(), [0x03, 0x04]);
+        assert_eq!(parser.extract_varint().unwrap(), 0x0203);
+        assert!(!parser.is_empty());
+        assert!(parser.extract_bytes(usize::MAX).is_err());
+        assert!(parser.is_empty());
+    }
+
+    #[test]
+    fn stream_parser_extracts_the_remaining_bytes() {
+        let buffer = [0x01, 0x02, 0x03];
+        let mut parser = StreamParser::new(&buffer[..]);
+        assert_eq!(parser.extract_u8().unwrap(), 0x01);
+        assert_eq!(parser.extract_remaining().unwrap(), vec![0x02, 0x03]);
+        assert!(parser.is_empty());
+        assert!(parser.extract_u8().is_err());
+    }
+
     #[test]
     fn serialize_varint() {
         let number: usize = 0x03;
         let bytes = number.to_varint_bytes();
         assert_eq!(bytes, vec![0x03]);
     }
+
+    #[test]
+    fn write_varint_with_the_smallest_size() {
+        for (number, expected) in [
+            (0xFC_usize, vec![0xFC]),
+            (0xFD, vec![0xFD, 0xFD, 0x00]),
+            (0x10000, vec![0xFE, 0x00, 0x00, 0x01, 0x00]),
+            (
+                0x100000000,
+                vec![0xFF, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+            ),
+        ] {
+            let mut buffer = vec![];
+            number.write_varint(&mut buffer).unwrap();
+            assert_eq!(buffer, expected);
+            let mut parser = BufferParser::new(&buffer);
+            assert_eq!(parser.extract_varint().unwrap(), number as u64);
+        }
+    }
 }
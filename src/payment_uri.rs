@@ -0,0 +1,299 @@
+use std::{fmt, str::FromStr};
+
+use qrcode::{Color, QrCode};
+
+use crate::{
+    address::Address,
+    error::{CustomError, ParseError, WalletError},
+};
+
+/// Esquema de las URIs de pago (BIP 21).
+const URI_SCHEME: &str = "bitcoin:";
+/// Cantidad de satoshis en un bitcoin, el monto de la URI se expresa en bitcoins.
+const SATOSHIS_PER_BITCOIN: u64 = 100_000_000;
+/// Cantidad maxima de decimales del monto, un satoshi es 0.00000001 bitcoins.
+const AMOUNT_DECIMALS: usize = 8;
+/// Prefijo de los parametros que el receptor debe entender para poder pagar (BIP 21).
+const REQUIRED_PARAM_PREFIX: &str = "req-";
+
+/// PaymentUri es una URI de pago BIP 21, por ejemplo bitcoin:tb1q...?amount=0.01&label=Alice.
+/// Los elementos son:
+/// - address: Direccion a la que se pide el pago.
+/// - amount: Monto pedido en satoshis, None si no se indica.
+/// - label: Nombre del destinatario, None si no se indica.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaymentUri {
+    pub address: String,
+    pub amount: Option<u64>,
+    pub label: Option<String>,
+}
+
+impl PaymentUri {
+    /// Genera la URI de pago de una direccion, con el monto y el nombre opcionales.
+    pub fn new(address: String, amount: Option<u64>, label: Option<String>) -> Self {
+        Self {
+            address,
+            amount,
+            label: label.filter(|label| !label.is_empty()),
+        }
+    }
+
+    /// Genera el codigo QR de la URI y devuelve sus modulos por fila, true si el modulo es oscuro.
+    /// No incluye el margen alrededor del codigo.
+    /// Devuelve CustomError si la URI es demasiado larga para un codigo QR.
+    pub fn qr_code(&self) -> Result<Vec<Vec<bool>>, CustomError> {
+        let code = QrCode::new(self.to_string().as_bytes())
+            .map_err(|_| CustomError::Wallet(WalletError::CannotGenerateQrCode))?;
+        let width = code.width();
+        let modules: Vec<bool> = code
+            .to_colors()
+            .into_iter()
+            .map(|color| color == Color::Dark)
+            .collect();
+        Ok(modules.chunks(width).map(<[bool]>::to_vec).collect())
+    }
+
+    /// Devuelve true si el texto empieza con el esquema de las URIs de pago, sin distinguir mayusculas.
+    pub fn has_scheme(text: &str) -> bool {
+        text.get(..URI_SCHEME.len())
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case(URI_SCHEME))
+    }
+}
+
+impl FromStr for PaymentUri {
+    type Err = CustomError;
+
+    /// Parsea una URI de pago BIP 21.
+    /// Se ignoran los parametros desconocidos, salvo los que empiezan con req- que deben entenderse para pagar.
+    /// Devuelve CustomError si la URI, la direccion o el monto no son validos, si hay parametros repetidos,
+    /// o si tiene un parametro requerido que no se soporta.
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        if !Self::has_scheme(uri) {
+            return Err(CustomError::Parse(ParseError::InvalidPaymentUri));
+        }
+        let (address, query) = match uri[URI_SCHEME.len()..].split_once('?') {
+            Some((address, query)) => (address, Some(query)),
+            None => (&uri[URI_SCHEME.len()..], None),
+        };
+        if Address::from_str(address).is_err() {
+            return Err(CustomError::Parse(ParseError::InvalidPaymentUri));
+        }
+
+        let mut amount = None;
+        let mut label = None;
+        for param in query
+            .unwrap_or("")
+            .split('&')
+            .filter(|param| !param.is_empty())
+        {
+            let Some((key, value)) = param.split_once('=') else { return Err(CustomError::Parse(ParseError::InvalidPaymentUri)) };
+            match key {
+                "amount" if amount.is_none() => amount = Some(parse_amount(value)?),
+                "label" if label.is_none() => label = Some(percent_decode(value)?),
+                "amount" | "label" => {
+                    return Err(CustomError::Parse(ParseError::InvalidPaymentUri))
+                }
+                _ if key.starts_with(REQUIRED_PARAM_PREFIX) => {
+                    return Err(CustomError::Parse(ParseError::PaymentUriUnsupportedParam))
+                }
+                _ => continue,
+            }
+        }
+        Ok(Self::new(address.to_string(), amount, label))
+    }
+}
+
+impl fmt::Display for PaymentUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut params = vec![];
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", format_amount(amount)));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", percent_encode(label)));
+        }
+
+        write!(f, "{}{}", URI_SCHEME, self.address)?;
+        if !params.is_empty() {
+            write!(f, "?{}", params.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Expresa un monto en satoshis en bitcoins, sin ceros decimales de mas.
+fn format_amount(amount: u64) -> String {
+    let bitcoins = amount / SATOSHIS_PER_BITCOIN;
+    let satoshis = amount % SATOSHIS_PER_BITCOIN;
+    if satoshis == 0 {
+        return bitcoins.to_string();
+    }
+    let decimals = format!("{:08}", satoshis);
+    format!("{}.{}", bitcoins, decimals.trim_end_matches('0'))
+}
+
+/// Convierte un monto en bitcoins a satoshis.
+/// Devuelve CustomError si no es un numero decimal positivo con hasta AMOUNT_DECIMALS decimales.
+fn parse_amount(amount: &str) -> Result<u64, CustomError> {
+    let (bitcoins, decimals) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_digits = |digits: &str| digits.bytes().all(|byte| byte.is_ascii_digit());
+    if bitcoins.is_empty()
+        || !is_digits(bitcoins)
+        || !is_digits(decimals)
+        || decimals.len() > AMOUNT_DECIMALS
+    {
+        return Err(CustomError::Parse(ParseError::InvalidPaymentUri));
+    }
+    let decimals = format!("{:0<width$}", decimals, width = AMOUNT_DECIMALS);
+
+    let bitcoins = bitcoins
+        .parse::<u64>()
+        .map_err(|_| CustomError::Parse(ParseError::InvalidPaymentUri))?;
+    let satoshis = decimals
+        .parse::<u64>()
+        .map_err(|_| CustomError::Parse(ParseError::InvalidPaymentUri))?;
+    match bitcoins
+        .checked_mul(SATOSHIS_PER_BITCOIN)
+        .and_then(|amount| amount.checked_add(satoshis))
+    {
+        Some(0) | None => Err(CustomError::Parse(ParseError::InvalidPaymentUri)),
+        Some(amount) => Ok(amount),
+    }
+}
+
+/// Decodifica un valor de la URI con percent-encoding (RFC 3986).
+/// Devuelve CustomError si tiene un escape invalido o si el resultado no es UTF-8.
+fn percent_decode(value: &str) -> Result<String, CustomError> {
+    let bytes = value.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let byte = value
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or(CustomError::Parse(ParseError::InvalidPaymentUri))?;
+        decoded.push(byte);
+        i += 3;
+    }
+    String::from_utf8(decoded).map_err(|_| CustomError::Parse(ParseError::InvalidPaymentUri))
+}
+
+/// Codifica un valor de la URI con percent-encoding (RFC 3986), dejando solo los caracteres no reservados.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+    #[test]
+    fn uri_without_params() {
+        let uri = PaymentUri::new(String::from(ADDRESS), None, Some(String::new()));
+        assert_eq!(uri.to_string(), format!("bitcoin:{}", ADDRESS));
+    }
+
+    #[test]
+    fn uri_with_amount_and_label() {
+        let uri = PaymentUri::new(
+            String::from(ADDRESS),
+            Some(1_000_000),
+            Some(String::from("Luke Jr & co")),
+        );
+        assert_eq!(
+            uri.to_string(),
+            format!("bitcoin:{}?amount=0.01&label=Luke%20Jr%20%26%20co", ADDRESS)
+        );
+    }
+
+    #[test]
+    fn amount_format() {
+        assert_eq!(format_amount(0), "0");
+        assert_eq!(format_amount(100_000_000), "1");
+        assert_eq!(format_amount(2_050_000_000), "20.5");
+        assert_eq!(format_amount(1), "0.00000001");
+    }
+
+    #[test]
+    fn parse_uri() {
+        let uri = format!(
+            "BITCOIN:{}?amount=20.3&label=Luke%20Jr&message=Donation",
+            ADDRESS
+        );
+        let uri = uri.parse::<PaymentUri>().unwrap();
+        assert_eq!(uri.address, ADDRESS);
+        assert_eq!(uri.amount, Some(2_030_000_000));
+        assert_eq!(uri.label, Some(String::from("Luke Jr")));
+
+        let uri = PaymentUri::new(
+            String::from(ADDRESS),
+            Some(1),
+            Some(String::from("Café & co")),
+        );
+        assert_eq!(uri.to_string().parse::<PaymentUri>().unwrap(), uri);
+    }
+
+    #[test]
+    fn invalid_uris_are_rejected() {
+        let invalid_uris = [
+            format!("bitcoin;{}", ADDRESS),
+            String::from("bitcoin:invalid?amount=1"),
+            format!("bitcoin:{}?amount=-1", ADDRESS),
+            format!("bitcoin:{}?amount=1,5", ADDRESS),
+            format!("bitcoin:{}?amount=0.000000001", ADDRESS),
+            format!("bitcoin:{}?amount=1&amount=2", ADDRESS),
+            format!("bitcoin:{}?label=%E", ADDRESS),
+            format!("bitcoin:{}?label", ADDRESS),
+        ];
+        for uri in invalid_uris {
+            assert!(matches!(
+                uri.parse::<PaymentUri>(),
+                Err(CustomError::Parse(ParseError::InvalidPaymentUri))
+            ));
+        }
+        assert!(matches!(
+            format!("bitcoin:{}?req-somethingyoudontunderstand=50", ADDRESS).parse::<PaymentUri>(),
+            Err(CustomError::Parse(ParseError::PaymentUriUnsupportedParam))
+        ));
+    }
+
+    #[test]
+    fn amount_parse() {
+        assert_eq!(parse_amount("1").unwrap(), 100_000_000);
+        assert_eq!(parse_amount("0.01").unwrap(), 1_000_000);
+        assert_eq!(parse_amount("0.00000001").unwrap(), 1);
+        assert_eq!(parse_amount("20.").unwrap(), 2_000_000_000);
+        assert!(parse_amount("0").is_err());
+        assert!(parse_amount(".5").is_err());
+        assert!(parse_amount("1e3").is_err());
+        assert!(parse_amount("184467440738").is_err());
+    }
+
+    #[test]
+    fn qr_code_of_uri() {
+        let uri = PaymentUri::new(String::from(ADDRESS), Some(1_000_000), None);
+        let modules = uri.qr_code().unwrap();
+
+        // los codigos QR tienen 17 + 4 * version modulos por lado
+        assert_eq!((modules.len() - 17) % 4, 0);
+        assert!(modules.iter().all(|row| row.len() == modules.len()));
+        // la esquina superior izquierda es un patron de posicion, con su borde oscuro
+        assert!(modules[0][..7].iter().all(|module| *module));
+        assert!(!modules[1][1]);
+    }
+}
@@ -1,238 +1,383 @@
 use std::{
+    io::{Read, Write},
     net::{SocketAddr, SocketAddrV6, TcpStream},
-    sync::{mpsc, Arc, Mutex},
-    thread,
+    sync::mpsc,
+    time::Duration,
 };
 
 use chrono::Local;
 
 use crate::{
-    error::CustomError,
-    logger::{send_log, Log},
+    error::{CustomError, NetworkError},
+    fee_estimator::MIN_FEE_RATE,
+    logger::{send_log, Log, LogEntry},
     loops::{
+        network_loop::{NetworkHandle, PeerWriter},
         node_action_loop::NodeAction,
-        peer_action_loop::{PeerAction, PeerActionLoop},
-        peer_stream_loop::PeerStreamLoop,
     },
-    message::{Message, MessageHeader},
+    message::{Command, Message, MessageHeader},
     messages::{
-        get_headers::GetHeaders, send_headers::SendHeaders, ver_ack::VerAck, version::Version,
+        fee_filter::{FeeFilter, FEE_FILTER_PROTOCOL_VERSION},
+        get_addr::GetAddr,
+        get_data::GetData,
+        get_headers::GetHeaders,
+        send_addr_v2::SendAddrV2,
+        send_cmpct::{SendCmpct, COMPACT_BLOCKS_PROTOCOL_VERSION, COMPACT_BLOCKS_VERSION},
+        send_headers::{SendHeaders, SEND_HEADERS_PROTOCOL_VERSION},
+        ver_ack::VerAck,
+        version::Version,
+        wtxid_relay::{WtxidRelay, WTXID_RELAY_PROTOCOL_VERSION},
     },
+    network::Network,
+    states::peer_info_state::PeerInfo,
+    structs::{hash256::Hash256, inventory::Inventory},
     utils::{get_address_v6, open_stream},
 };
 
-/// GENESIS es el hash del bloque genesis de la blockchain de Bitcoin.
-pub const GENESIS: [u8; 32] = [
-    67, 73, 127, 215, 248, 38, 149, 113, 8, 244, 163, 15, 217, 206, 195, 174, 186, 121, 151, 32,
-    132, 233, 14, 173, 1, 234, 51, 9, 0, 0, 0, 0,
-];
+/// Version de protocolo minima que deben soportar los peers, anterior a ella no existen los mensajes pong ni filterload.
+const MIN_PEER_PROTOCOL_VERSION: i32 = 70001;
+/// Servicio de los peers que pueden enviar todos los bloques de la cadena.
+pub const NODE_NETWORK: u64 = 1;
+/// Servicio de los peers que aceptan bloom filters y responden con merkle blocks (BIP 111).
+pub const NODE_BLOOM: u64 = 4;
+/// Tiempo maximo que se espera cada mensaje del handshake, luego el NetworkLoop atiende el stream sin bloquearse.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Peer es una representacion de los Peers a los que nos conectamos, contiene los elementos necesarios para manejar la conexion con el peer.
-/// Cada peer tiene dos threads asociados:
-/// - peer_action_thread: Thread que escucha las acciones a realizar por el peer.
-/// - peer_stream_thread: Thread que escucha el stream del peer.
+/// El handshake se realiza de forma bloqueante, luego el stream se registra en el NetworkLoop,
+/// que atiende a todos los peers desde un unico thread.
 ///
 /// Los elementos son:
 /// - address: Direccion del peer.
 /// - services: Servicios del peer.
-/// - version: Version del peer.
+/// - version: Version de protocolo negociada con el peer, la menor entre la nuestra y la suya.
+/// - relay: Booleano que indica si el peer quiere recibir transacciones (BIP 37).
+/// - wtxid_relay: Booleano que indica si el peer anuncia y espera que le pidan las transacciones por su wtxid (BIP 339).
 /// - send_headers: Booleano que indica si el peer soporta el envio de headers.
+/// - compact_blocks: Booleano que indica si el peer anuncio con sendcmpct que soporta compact blocks version 2 (BIP 152).
+/// - fee_filter: Fee rate minimo en satoshis por kilobyte virtual de las transacciones que el peer quiere recibir (BIP 133).
 /// - requested_headers: Booleano que indica si el peer ya nos solicito headers.
-/// - stream: Stream del peer.
+/// - user_agent: User agent que informo el peer en el handshake.
+/// - start_height: Altura de la cadena del peer al momento del handshake.
+/// - best_height: Mayor altura conocida de la cadena del peer, la del handshake o la de los headers que anuncio despues.
+/// - inbound: Booleano que indica si el peer se conecto a nosotros, en lugar de llamarlo nosotros.
+/// - stream: Extremo de escritura de la conexion con el peer.
 /// - benchmark: Velocidad de handshake con el peer, utilizado para elegir el mejor peer.
-/// - peer_action_thread: Thread que escucha las acciones a realizar por el peer.
-/// - peer_stream_thread: Thread que escucha el stream del peer.
 ///
 pub struct Peer {
     pub address: SocketAddrV6,
     pub services: u64,
     pub version: i32,
+    pub relay: bool,
+    pub wtxid_relay: bool,
     pub send_headers: bool,
+    pub compact_blocks: bool,
+    pub fee_filter: u64,
     pub requested_headers: bool,
-    pub stream: TcpStream,
+    pub user_agent: String,
+    pub start_height: usize,
+    pub best_height: usize,
+    pub inbound: bool,
+    pub stream: PeerWriter,
     pub benchmark: i64,
-    pub peer_action_thread: Option<thread::JoinHandle<Result<(), CustomError>>>,
-    pub peer_stream_thread: Option<thread::JoinHandle<Result<(), CustomError>>>,
 }
 
 impl Peer {
     /// Llama a un peer de la red de Bitcoin y crea un Peer.
-    /// Realiza el handshake con el peer y crea los threads asociados.
+    /// Realiza el handshake con el peer y registra su stream en el NetworkLoop.
     pub fn call(
         address: SocketAddr,
         sender_address: SocketAddrV6,
         services: u64,
         version: i32,
-        peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
-        logger_sender: mpsc::Sender<Log>,
-        node_action_sender: mpsc::Sender<NodeAction>,
+        network: &NetworkHandle,
+        logger_sender: mpsc::Sender<LogEntry>,
     ) -> Result<Self, CustomError> {
-        let stream = open_stream(address)?;
+        let mut stream =
+            open_stream(address).map_err(|error| error.with_peer(get_address_v6(address), None))?;
 
         let mut peer = Self {
             address: get_address_v6(address),
-            peer_action_thread: None,
-            peer_stream_thread: None,
             services,
             version,
-            stream,
+            stream: network.writer(),
             benchmark: 99999,
+            relay: true,
+            wtxid_relay: false,
             send_headers: false,
+            compact_blocks: false,
+            fee_filter: 0,
             requested_headers: false,
+            user_agent: String::new(),
+            start_height: 0,
+            best_height: 0,
+            inbound: false,
         };
 
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
         let timestamp_before_handshake = Local::now().timestamp_millis();
-        peer.call_handshake(sender_address)?;
+        peer.call_handshake(&mut stream, sender_address)
+            .map_err(|error| error.with_peer(peer.address, None))?;
         let timestamp_after_handshake = Local::now().timestamp_millis();
         peer.benchmark = timestamp_after_handshake - timestamp_before_handshake;
 
         send_log(
             &logger_sender,
-            Log::Message(format!(
+            Log::Debug(format!(
                 "Successful handshake with {} in {}ms",
                 peer.address.ip(),
                 peer.benchmark
             )),
         );
 
-        peer.spawn_threads(peer_action_receiver, node_action_sender, logger_sender)?;
+        network.register(&peer.stream, peer.info(), stream)?;
         Ok(peer)
     }
 
     /// Atiende el llamado de un peer de la red de Bitcoin y crea un Peer.
-    /// Realiza el handshake con el peer y crea los threads asociados.
+    /// Realiza el handshake con el peer y registra su stream en el NetworkLoop.
     pub fn answer(
-        stream: TcpStream,
+        mut stream: TcpStream,
         sender_address: SocketAddrV6,
         services: u64,
         version: i32,
-        peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
-        logger_sender: mpsc::Sender<Log>,
-        node_action_sender: mpsc::Sender<NodeAction>,
+        network: &NetworkHandle,
+        logger_sender: mpsc::Sender<LogEntry>,
     ) -> Result<Self, CustomError> {
         let mut peer = Self {
             address: get_address_v6(stream.peer_addr()?),
-            peer_action_thread: None,
-            peer_stream_thread: None,
             services,
             version,
-            stream,
+            stream: network.writer(),
             benchmark: 99999,
+            relay: true,
+            wtxid_relay: false,
             send_headers: false,
+            compact_blocks: false,
+            fee_filter: 0,
             requested_headers: false,
+            user_agent: String::new(),
+            start_height: 0,
+            best_height: 0,
+            inbound: true,
         };
 
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
         let timestamp_before_handshake = Local::now().timestamp_millis();
-        peer.answer_handshake(sender_address)?;
+        peer.answer_handshake(&mut stream, sender_address)
+            .map_err(|error| error.with_peer(peer.address, None))?;
         let timestamp_after_handshake = Local::now().timestamp_millis();
         peer.benchmark = timestamp_after_handshake - timestamp_before_handshake;
 
         send_log(
             &logger_sender,
-            Log::Message(format!(
+            Log::Debug(format!(
                 "Successful handshake with {} in {}ms",
                 peer.address.ip(),
                 peer.benchmark
             )),
         );
 
-        peer.spawn_threads(peer_action_receiver, node_action_sender, logger_sender)?;
+        network.register(&peer.stream, peer.info(), stream)?;
         Ok(peer)
     }
 
+    /// Devuelve la informacion del peer que se muestra al usuario, sin trafico registrado.
+    pub fn info(&self) -> PeerInfo {
+        PeerInfo::new(
+            self.address,
+            self.user_agent.clone(),
+            self.version,
+            self.start_height,
+        )
+    }
+
     /// Realiza el handshake de Node con el Peer, cuando el Node es el que llama al Peer.
-    fn call_handshake(&mut self, sender_address: SocketAddrV6) -> Result<(), CustomError> {
-        Version::new(self.address, sender_address, self.version, self.services)
-            .send(&mut self.stream)?;
-
-        let response_header = MessageHeader::read(&mut self.stream)?;
-        let version_response = Version::read(&mut self.stream, response_header.payload_size)
-            .map_err(|_| CustomError::CannotHandshakeNode)?;
-        self.version = version_response.version;
-        self.services = version_response.services;
+    fn call_handshake(
+        &mut self,
+        stream: &mut TcpStream,
+        sender_address: SocketAddrV6,
+    ) -> Result<(), CustomError> {
+        Version::new(self.address, sender_address, self.version, self.services).send(stream)?;
+
+        let response_header = MessageHeader::read(stream)?;
+        let version_response = Version::read(stream, &response_header)
+            .map_err(|_| CustomError::Network(NetworkError::CannotHandshakeNode))?;
+        self.negotiate(version_response, true)?;
 
-        let response_header = MessageHeader::read(&mut self.stream)?;
-        VerAck::read(&mut self.stream, response_header.payload_size)
-            .map_err(|_| CustomError::CannotHandshakeNode)?;
+        let peer_wtxid_relay = self.read_verack(stream)?;
 
-        VerAck::new().send(&mut self.stream)?;
-        SendHeaders::new().send(&mut self.stream)?;
+        SendAddrV2::new().send(stream)?;
+        self.send_wtxid_relay(stream, peer_wtxid_relay)?;
+        VerAck::new().send(stream)?;
+        self.send_features(stream)?;
+        GetAddr::new().send(stream)?;
 
         Ok(())
     }
 
     /// Realiza el handshake de Node con el Peer, cuando el Peer es el que llama al Node.
-    fn answer_handshake(&mut self, sender_address: SocketAddrV6) -> Result<(), CustomError> {
-        let response_header = MessageHeader::read(&mut self.stream)?;
-        let version_response = Version::read(&mut self.stream, response_header.payload_size)
-            .map_err(|_| CustomError::CannotHandshakeNode)?;
-
-        Version::new(self.address, sender_address, self.version, self.services)
-            .send(&mut self.stream)?;
-        self.version = version_response.version;
-        self.services = version_response.services;
+    fn answer_handshake(
+        &mut self,
+        stream: &mut TcpStream,
+        sender_address: SocketAddrV6,
+    ) -> Result<(), CustomError> {
+        let response_header = MessageHeader::read(stream)?;
+        let version_response = Version::read(stream, &response_header)
+            .map_err(|_| CustomError::Network(NetworkError::CannotHandshakeNode))?;
+
+        Version::new(self.address, sender_address, self.version, self.services).send(stream)?;
+        self.negotiate(version_response, false)?;
 
-        VerAck::new().send(&mut self.stream)?;
+        SendAddrV2::new().send(stream)?;
+        self.send_wtxid_relay(stream, true)?;
+        VerAck::new().send(stream)?;
 
-        let response_header = MessageHeader::read(&mut self.stream)?;
-        VerAck::read(&mut self.stream, response_header.payload_size)
-            .map_err(|_| CustomError::CannotHandshakeNode)?;
-        SendHeaders::new().send(&mut self.stream)?;
+        let peer_wtxid_relay = self.read_verack(stream)?;
+        self.wtxid_relay &= peer_wtxid_relay;
+        self.send_features(stream)?;
+
+        Ok(())
+    }
 
+    /// Registra la version, los servicios, el relay y el user agent del peer, negociando la version de protocolo.
+    /// Devuelve NetworkError::PeerNotSupported si la version del peer es menor a MIN_PEER_PROTOCOL_VERSION,
+    /// o si es un peer al que llamamos y no puede enviarnos todos los bloques de la cadena.
+    fn negotiate(&mut self, version_response: Version, outbound: bool) -> Result<(), CustomError> {
+        if version_response.version < MIN_PEER_PROTOCOL_VERSION
+            || (outbound && version_response.services & NODE_NETWORK == 0)
+        {
+            return Err(CustomError::Network(NetworkError::PeerNotSupported));
+        }
+        self.version = self.version.min(version_response.version);
+        self.services = version_response.services;
+        self.relay = version_response.relay;
+        self.user_agent = version_response.user_agent;
+        self.start_height = version_response.start_height.max(0) as usize;
+        self.best_height = self.start_height;
         Ok(())
     }
 
-    fn spawn_threads(
+    /// Envia el mensaje wtxidrelay si la version negociada lo soporta, debe enviarse antes del verack.
+    /// Si el peer tambien lo envio, las transacciones se anuncian por su wtxid.
+    fn send_wtxid_relay(
         &mut self,
-        peer_action_receiver: Arc<Mutex<mpsc::Receiver<PeerAction>>>,
-        node_action_sender: mpsc::Sender<NodeAction>,
-        logger_sender: mpsc::Sender<Log>,
+        stream: &mut TcpStream,
+        peer_wtxid_relay: bool,
     ) -> Result<(), CustomError> {
-        //thread que escucha al nodo
-        self.peer_action_thread = Some(PeerActionLoop::spawn(
-            self.address,
-            self.version,
-            self.stream.try_clone()?,
-            logger_sender.clone(),
-            peer_action_receiver,
-            node_action_sender.clone(),
-        ));
-
-        //Thread que escucha el stream
-        self.peer_stream_thread = Some(PeerStreamLoop::spawn(
-            self.version,
-            self.address,
-            self.stream.try_clone()?,
-            logger_sender,
-            node_action_sender,
-        ));
+        if self.version < WTXID_RELAY_PROTOCOL_VERSION {
+            return Ok(());
+        }
+        WtxidRelay::new().send(stream)?;
+        self.wtxid_relay = peer_wtxid_relay;
+        Ok(())
+    }
+
+    /// Envia los mensajes de negociacion posteriores al verack que soporta la version negociada:
+    /// sendheaders para recibir los headers nuevos directamente, feefilter para no recibir transacciones
+    /// con un fee menor al que retransmiten los nodos y sendcmpct para recibir compact blocks.
+    fn send_features(&mut self, stream: &mut TcpStream) -> Result<(), CustomError> {
+        if self.version >= SEND_HEADERS_PROTOCOL_VERSION {
+            SendHeaders::new().send(stream)?;
+        }
+        if self.version >= FEE_FILTER_PROTOCOL_VERSION {
+            FeeFilter::new(MIN_FEE_RATE).send(stream)?;
+        }
+        if self.version >= COMPACT_BLOCKS_PROTOCOL_VERSION {
+            SendCmpct::new(false, COMPACT_BLOCKS_VERSION).send(stream)?;
+        }
         Ok(())
     }
 
+    /// Lee los mensajes del peer hasta recibir su verack.
+    /// Devuelve true si el peer envio wtxidrelay antes del verack.
+    /// Los demas mensajes de negociacion que el peer envia antes del verack (como sendaddrv2) se descartan.
+    fn read_verack(&mut self, stream: &mut TcpStream) -> Result<bool, CustomError> {
+        let mut wtxid_relay = false;
+        loop {
+            let response_header = MessageHeader::read(stream)?;
+            match response_header.command {
+                Command::VerAck => {
+                    VerAck::read(stream, &response_header)
+                        .map_err(|_| CustomError::Network(NetworkError::CannotHandshakeNode))?;
+                    return Ok(wtxid_relay);
+                }
+                Command::WtxidRelay => {
+                    WtxidRelay::read(stream, &response_header)
+                        .map_err(|_| CustomError::Network(NetworkError::CannotHandshakeNode))?;
+                    wtxid_relay = true;
+                }
+                _ => {
+                    let mut buffer = vec![0; response_header.payload_size as usize];
+                    stream.read_exact(&mut buffer)?;
+                    response_header.verify_checksum(&buffer)?;
+                }
+            }
+        }
+    }
+
     /// Envia un mensaje al peer.
     pub fn send(&mut self, message: impl Message) -> Result<(), CustomError> {
         message.send(&mut self.stream)
     }
+
+    /// Arma el pedido de los inventories al peer, en mensajes getdata de hasta MAX_GETDATA_SIZE inventories.
+    pub fn request_data(&self, inventories: Vec<Inventory>) -> DataRequest {
+        DataRequest {
+            address: self.address,
+            stream: self.stream.clone(),
+            messages: GetData::batches(inventories),
+        }
+    }
+
+    /// Devuelve true si el peer quiere recibir transacciones con el fee rate recibido, en satoshis por kilobyte virtual.
+    /// Las transacciones cuyo fee no se conoce se le envian igual.
+    pub fn accepts_fee_rate(&self, fee_rate: Option<u64>) -> bool {
+        fee_rate.is_none_or(|fee_rate| fee_rate >= self.fee_filter)
+    }
+}
+
+/// DataRequest es un pedido de datos a un peer que se arma con el NodeState bloqueado y se envia luego de liberarlo.
+/// Los elementos son:
+/// - address: Direccion del peer.
+/// - stream: Extremo de escritura de la conexion con el peer.
+/// - messages: Mensajes getdata a enviar.
+pub struct DataRequest {
+    pub address: SocketAddrV6,
+    stream: PeerWriter,
+    messages: Vec<GetData>,
+}
+
+impl DataRequest {
+    /// Envia los mensajes getdata al peer.
+    /// Devuelve CustomError si la conexion con el peer esta cerrada.
+    pub fn send(mut self) -> Result<(), CustomError> {
+        for message in self.messages {
+            message.send(&mut self.stream)?;
+        }
+        Ok(())
+    }
 }
 
-/// Se encarga de solicitar a un peer los headers siguientes a su ultimo header.
+/// Se encarga de solicitar a un peer los headers siguientes al ultimo header en comun del block locator.
+/// Si el block locator esta vacio, se solicitan los headers desde el genesis.
 pub fn request_headers(
-    last_header: Option<Vec<u8>>,
+    block_locator: Vec<Hash256>,
     version: i32,
-    stream: &mut TcpStream,
-    logger_sender: &mpsc::Sender<Log>,
+    stream: &mut impl Write,
+    logger_sender: &mpsc::Sender<LogEntry>,
     node_action_sender: &mpsc::Sender<NodeAction>,
 ) -> Result<(), CustomError> {
-    let block_header_hashes = match last_header {
-        Some(header) => [header].to_vec(),
-        None => [GENESIS.to_vec()].to_vec(),
+    let block_header_hashes = match block_locator.is_empty() {
+        true => [Network::current().genesis_hash()].to_vec(),
+        false => block_locator,
     };
 
-    let request = GetHeaders::new(version, block_header_hashes, vec![0; 32]).send(stream);
+    let request = GetHeaders::new(version, block_header_hashes, Hash256::ZERO).send(stream);
     if request.is_err() {
         send_log(
             logger_sender,
-            Log::Message("Error requesting headers".to_string()),
+            Log::Warning("Error requesting headers".to_string()),
         );
         node_action_sender.send(NodeAction::GetHeadersError)?;
     }
@@ -0,0 +1,167 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::error::{CustomError, NetworkError};
+
+/// Version del protocolo SOCKS.
+const SOCKS_VERSION: u8 = 5;
+/// Metodo de autenticacion sin usuario ni contraseña.
+const NO_AUTHENTICATION: u8 = 0;
+/// Comando para abrir una conexion TCP.
+const CONNECT_COMMAND: u8 = 1;
+/// Tipo de direccion IPv4.
+const ADDRESS_IPV4: u8 = 1;
+/// Tipo de direccion IPv6.
+const ADDRESS_IPV6: u8 = 4;
+
+/// Proxy SOCKS5 por el que se abren las conexiones salientes, None si se conecta directamente.
+static ACTIVE_PROXY: Mutex<Option<SocketAddr>> = Mutex::new(None);
+
+/// Selecciona el proxy SOCKS5 por el que se abren las conexiones con los peers a partir de ese momento,
+/// o None para conectarse directamente.
+pub fn select(proxy: Option<SocketAddr>) {
+    if let Ok(mut active_proxy) = ACTIVE_PROXY.lock() {
+        *active_proxy = proxy;
+    }
+}
+
+/// Devuelve el proxy seleccionado, por defecto None.
+pub fn current() -> Option<SocketAddr> {
+    ACTIVE_PROXY.lock().map(|proxy| *proxy).unwrap_or(None)
+}
+
+/// Abre una conexion con la direccion recibida a traves del proxy SOCKS5 (RFC 1928), sin autenticacion.
+/// El timeout se aplica a la conexion con el proxy y a cada lectura y escritura del handshake.
+/// Devuelve CustomError si no se puede conectar con el proxy o si rechaza la conexion.
+pub fn connect(
+    proxy: SocketAddr,
+    address: SocketAddr,
+    timeout: Duration,
+) -> Result<TcpStream, CustomError> {
+    let mut stream = TcpStream::connect_timeout(&proxy, timeout)
+        .map_err(|_| CustomError::Network(NetworkError::CannotConnectToNode))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    // se ofrece un unico metodo de autenticacion
+    stream.write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])?;
+    let mut method = [0; 2];
+    stream.read_exact(&mut method)?;
+    if method != [SOCKS_VERSION, NO_AUTHENTICATION] {
+        return Err(CustomError::Network(NetworkError::CannotConnectToNode));
+    }
+
+    stream.write_all(&connect_request(address))?;
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != SOCKS_VERSION || reply[1] != 0 {
+        return Err(CustomError::Network(NetworkError::CannotConnectToNode));
+    }
+    // la direccion con la que el proxy se conecto no se usa
+    let bound_address_size = match reply[3] {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        _ => return Err(CustomError::Network(NetworkError::CannotConnectToNode)),
+    };
+    let mut bound_address = vec![0; bound_address_size + 2];
+    stream.read_exact(&mut bound_address)?;
+
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok(stream)
+}
+
+/// Devuelve el pedido de conexion a la direccion recibida.
+fn connect_request(address: SocketAddr) -> Vec<u8> {
+    let mut request = vec![SOCKS_VERSION, CONNECT_COMMAND, 0];
+    match address {
+        SocketAddr::V4(address) => {
+            request.push(ADDRESS_IPV4);
+            request.extend(address.ip().octets());
+        }
+        SocketAddr::V6(address) => {
+            request.push(ADDRESS_IPV6);
+            request.extend(address.ip().octets());
+        }
+    }
+    request.extend(address.port().to_be_bytes());
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    /// Pedido de conexion y datos que recibio el proxy de prueba despues del handshake.
+    type ProxyReceived = thread::JoinHandle<(Vec<u8>, Vec<u8>)>;
+
+    /// Atiende una conexion como un proxy SOCKS5 que responde con el codigo recibido,
+    /// y devuelve el pedido de conexion y lo que se escribio despues del handshake.
+    fn socks5_proxy(reply_code: u8) -> (SocketAddr, ProxyReceived) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            stream
+                .write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])
+                .unwrap();
+
+            let mut request = vec![0; 10];
+            stream.read_exact(&mut request).unwrap();
+            stream
+                .write_all(&[SOCKS_VERSION, reply_code, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+                .unwrap();
+
+            // si el cliente cierra la conexion sin leer toda la respuesta, se cierra con un reset
+            let mut payload = vec![];
+            let _ = stream.read_to_end(&mut payload);
+            (request, payload)
+        });
+        (proxy, handle)
+    }
+
+    #[test]
+    fn connects_through_the_proxy() {
+        let (proxy, handle) = socks5_proxy(0);
+        let address = SocketAddr::from(([10, 0, 0, 1], 18333));
+
+        let mut stream = connect(proxy, address, Duration::from_secs(5)).unwrap();
+        stream.write_all(b"version").unwrap();
+        drop(stream);
+
+        let (request, payload) = handle.join().unwrap();
+        assert_eq!(
+            request,
+            vec![
+                SOCKS_VERSION,
+                CONNECT_COMMAND,
+                0,
+                ADDRESS_IPV4,
+                10,
+                0,
+                0,
+                1,
+                0x47,
+                0x9d
+            ]
+        );
+        assert_eq!(payload, b"version");
+    }
+
+    #[test]
+    fn connection_refused_by_the_proxy_is_an_error() {
+        let (proxy, handle) = socks5_proxy(5);
+        let address = SocketAddr::from(([10, 0, 0, 1], 18333));
+
+        assert!(connect(proxy, address, Duration::from_secs(5)).is_err());
+        handle.join().unwrap();
+    }
+}
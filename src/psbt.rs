@@ -0,0 +1,811 @@
+use std::{fmt, str::FromStr};
+
+use bitcoin_hashes::{hash160, Hash};
+
+use crate::{
+    base64,
+    error::{CustomError, ParseError, WalletError},
+    messages::transaction::{find_signing_key, sign, Transaction, SIGHASH_ALL},
+    parser::{BufferParser, Parser, VarIntSerialize},
+    structs::{
+        script::{classify::ScriptType, Script, ScriptElement, OP_0},
+        tx_output::TransactionOutput,
+    },
+    wallet::Wallet,
+};
+
+/// Bytes magicos con los que empieza un PSBT serializado: "psbt" seguido de 0xff.
+const PSBT_MAGIC: [u8; 5] = [0x70, 0x73, 0x62, 0x74, 0xff];
+/// Separador que termina cada mapa de claves y valores, una clave vacia.
+const SEPARATOR: u8 = 0x00;
+
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const GLOBAL_VERSION: u8 = 0xfb;
+const IN_NON_WITNESS_UTXO: u8 = 0x00;
+const IN_WITNESS_UTXO: u8 = 0x01;
+const IN_PARTIAL_SIG: u8 = 0x02;
+const IN_SIGHASH_TYPE: u8 = 0x03;
+const IN_REDEEM_SCRIPT: u8 = 0x04;
+const IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+/// Par de clave y valor de un mapa del PSBT. La clave empieza con su tipo.
+type KeyValue = (Vec<u8>, Vec<u8>);
+/// Script sig y witness finales de un input, None si no lo usa.
+type FinalScripts = (Option<Vec<u8>>, Option<Vec<Vec<u8>>>);
+
+/// PsbtInput son los datos de un input del PSBT necesarios para firmarlo y finalizarlo.
+/// Los elementos son:
+/// - non_witness_utxo: Transaccion completa que crea el output que gasta el input, para los inputs que no son SegWit.
+/// - witness_utxo: Output que gasta el input.
+/// - partial_sigs: Firmas del input y la public key de cada una.
+/// - sighash_type: Sighash type con el que se debe firmar, None si no se indica.
+/// - redeem_script: Redeem script del output P2SH que gasta el input.
+/// - final_script_sig: Script sig del input finalizado.
+/// - final_script_witness: Witness del input finalizado.
+/// - unknown: Pares de clave y valor de tipos que no se interpretan, se conservan al serializar.
+#[derive(Debug, Clone, Default)]
+pub struct PsbtInput {
+    pub non_witness_utxo: Option<Transaction>,
+    pub witness_utxo: Option<TransactionOutput>,
+    pub partial_sigs: Vec<KeyValue>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<Vec<u8>>,
+    pub final_script_sig: Option<Vec<u8>>,
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    pub unknown: Vec<KeyValue>,
+}
+
+impl PsbtInput {
+    /// Devuelve true si el input ya tiene su script sig o su witness final.
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        if let Some(tx) = &self.non_witness_utxo {
+            write_pair(
+                &mut buffer,
+                &[IN_NON_WITNESS_UTXO],
+                &tx.serialize_without_witness(),
+            );
+        }
+        if let Some(output) = &self.witness_utxo {
+            write_pair(&mut buffer, &[IN_WITNESS_UTXO], &output.serialize());
+        }
+        for (pubkey, signature) in &self.partial_sigs {
+            let mut key = vec![IN_PARTIAL_SIG];
+            key.extend(pubkey);
+            write_pair(&mut buffer, &key, signature);
+        }
+        if let Some(sighash_type) = self.sighash_type {
+            write_pair(&mut buffer, &[IN_SIGHASH_TYPE], &sighash_type.to_le_bytes());
+        }
+        if let Some(redeem_script) = &self.redeem_script {
+            write_pair(&mut buffer, &[IN_REDEEM_SCRIPT], redeem_script);
+        }
+        if let Some(script_sig) = &self.final_script_sig {
+            write_pair(&mut buffer, &[IN_FINAL_SCRIPTSIG], script_sig);
+        }
+        if let Some(witness) = &self.final_script_witness {
+            let mut value = witness.len().to_varint_bytes();
+            for item in witness {
+                value.extend(item.len().to_varint_bytes());
+                value.extend(item);
+            }
+            write_pair(&mut buffer, &[IN_FINAL_SCRIPTWITNESS], &value);
+        }
+        for (key, value) in &self.unknown {
+            write_pair(&mut buffer, key, value);
+        }
+        buffer.push(SEPARATOR);
+        buffer
+    }
+
+    fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        let mut input = Self::default();
+        for (key, value) in parse_map(parser)? {
+            match (key[0], key.len()) {
+                (IN_NON_WITNESS_UTXO, 1) => {
+                    input.non_witness_utxo =
+                        Some(parse_exact(&value, Transaction::parse_from_parser)?)
+                }
+                (IN_WITNESS_UTXO, 1) => {
+                    input.witness_utxo = Some(parse_exact(&value, TransactionOutput::parse)?)
+                }
+                (IN_PARTIAL_SIG, 34 | 66) => input.partial_sigs.push((key[1..].to_vec(), value)),
+                (IN_SIGHASH_TYPE, 1) => {
+                    input.sighash_type = Some(parse_exact(&value, BufferParser::extract_u32)?)
+                }
+                (IN_REDEEM_SCRIPT, 1) => input.redeem_script = Some(value),
+                (IN_FINAL_SCRIPTSIG, 1) => input.final_script_sig = Some(value),
+                (IN_FINAL_SCRIPTWITNESS, 1) => {
+                    input.final_script_witness = Some(parse_exact(&value, parse_witness)?)
+                }
+                (
+                    IN_NON_WITNESS_UTXO
+                    | IN_WITNESS_UTXO
+                    | IN_PARTIAL_SIG
+                    | IN_SIGHASH_TYPE
+                    | IN_REDEEM_SCRIPT
+                    | IN_FINAL_SCRIPTSIG
+                    | IN_FINAL_SCRIPTWITNESS,
+                    _,
+                ) => return Err(CustomError::Parse(ParseError::InvalidPsbt)),
+                _ => input.unknown.push((key, value)),
+            }
+        }
+        Ok(input)
+    }
+
+    /// Agrega los datos del otro input que este input no tiene.
+    fn combine(&mut self, other: PsbtInput) {
+        self.non_witness_utxo = self.non_witness_utxo.take().or(other.non_witness_utxo);
+        self.witness_utxo = self.witness_utxo.take().or(other.witness_utxo);
+        self.sighash_type = self.sighash_type.or(other.sighash_type);
+        self.redeem_script = self.redeem_script.take().or(other.redeem_script);
+        self.final_script_sig = self.final_script_sig.take().or(other.final_script_sig);
+        self.final_script_witness = self
+            .final_script_witness
+            .take()
+            .or(other.final_script_witness);
+        combine_pairs(&mut self.partial_sigs, other.partial_sigs);
+        combine_pairs(&mut self.unknown, other.unknown);
+    }
+}
+
+/// PsbtOutput son los datos de un output del PSBT. No se interpreta ninguno, se conservan al serializar.
+/// Los elementos son:
+/// - unknown: Pares de clave y valor del output.
+#[derive(Debug, Clone, Default)]
+pub struct PsbtOutput {
+    pub unknown: Vec<KeyValue>,
+}
+
+/// Psbt es una transaccion parcialmente firmada (BIP 174), que se puede pasar entre las partes que deben firmarla,
+/// por ejemplo los cosigners de un multisig o una hardware wallet, antes de finalizarla y enviarla.
+/// Los elementos son:
+/// - unsigned_tx: Transaccion sin firmar, con los script sigs y los witness vacios.
+/// - inputs: Datos de cada input de la transaccion.
+/// - outputs: Datos de cada output de la transaccion.
+/// - unknown: Pares de clave y valor globales que no se interpretan, se conservan al serializar.
+#[derive(Debug, Clone)]
+pub struct Psbt {
+    pub unsigned_tx: Transaction,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+    pub unknown: Vec<KeyValue>,
+}
+
+impl Psbt {
+    /// Crea el PSBT de una transaccion sin firmar, a partir de los outputs que gasta cada input.
+    /// Cada output gastado se guarda como witness utxo; para los inputs que no son SegWit se puede agregar
+    /// luego la transaccion completa con add_previous_tx.
+    /// Devuelve CustomError si la transaccion tiene firmas o si la cantidad de outputs gastados no coincide con la de inputs.
+    pub fn new(
+        unsigned_tx: Transaction,
+        previous_outputs: Vec<TransactionOutput>,
+    ) -> Result<Self, CustomError> {
+        if previous_outputs.len() != unsigned_tx.inputs.len() || !is_unsigned(&unsigned_tx) {
+            return Err(CustomError::Parse(ParseError::InvalidPsbt));
+        }
+        let inputs = previous_outputs
+            .into_iter()
+            .map(|previous_output| PsbtInput {
+                witness_utxo: Some(previous_output),
+                ..Default::default()
+            })
+            .collect();
+        Ok(Self {
+            outputs: vec![PsbtOutput::default(); unsigned_tx.outputs.len()],
+            unsigned_tx,
+            inputs,
+            unknown: vec![],
+        })
+    }
+
+    /// Agrega la transaccion completa que crea el output que gasta un input.
+    /// Si el output no es SegWit se descarta su witness utxo, que solo corresponde a los inputs SegWit.
+    /// Devuelve CustomError si el input no existe o si la transaccion no es la que gasta.
+    pub fn add_previous_tx(
+        &mut self,
+        index: usize,
+        previous_tx: Transaction,
+    ) -> Result<(), CustomError> {
+        let Some(input) = self.unsigned_tx.inputs.get(index) else { return Err(CustomError::Parse(ParseError::InvalidPsbt)) };
+        let out_point = &input.previous_output;
+        let Some(previous_output) = previous_tx.outputs.get(out_point.index as usize) else { return Err(CustomError::Parse(ParseError::InvalidPsbt)) };
+        if previous_tx.hash() != out_point.hash {
+            return Err(CustomError::Parse(ParseError::InvalidPsbt));
+        }
+        if !is_segwit(previous_output) {
+            self.inputs[index].witness_utxo = None;
+        }
+        self.inputs[index].non_witness_utxo = Some(previous_tx);
+        Ok(())
+    }
+
+    /// Devuelve el output que gasta un input, a partir de su transaccion completa o de su witness utxo.
+    pub fn previous_output(&self, index: usize) -> Option<TransactionOutput> {
+        let input = self.inputs.get(index)?;
+        let out_point = &self.unsigned_tx.inputs.get(index)?.previous_output;
+        input
+            .non_witness_utxo
+            .as_ref()
+            .and_then(|tx| tx.outputs.get(out_point.index as usize).cloned())
+            .or_else(|| input.witness_utxo.clone())
+    }
+
+    /// Devuelve el fee de la transaccion, o None si no se conocen todos los outputs que gasta.
+    pub fn fee(&self) -> Option<u64> {
+        let mut inputs_value: u64 = 0;
+        for index in 0..self.inputs.len() {
+            inputs_value = inputs_value.checked_add(self.previous_output(index)?.value)?;
+        }
+        let outputs_value = self
+            .unsigned_tx
+            .outputs
+            .iter()
+            .map(|output| output.value)
+            .sum();
+        inputs_value.checked_sub(outputs_value)
+    }
+
+    /// Firma con la wallet los inputs que gastan sus outputs y todavia no estan finalizados,
+    /// incluidos los outputs P2SH de sus multisigs, y agrega las firmas a las firmas parciales.
+    /// Devuelve la cantidad de inputs firmados.
+    /// Devuelve CustomError si la wallet es watch-only o si no se pudo firmar algun input.
+    pub fn sign(&mut self, wallet: &Wallet) -> Result<usize, CustomError> {
+        if wallet.is_watch_only() {
+            return Err(CustomError::Wallet(WalletError::WatchOnlyWallet));
+        }
+        let pubkey_hashes = wallet.get_pubkey_hashes()?;
+        let mut signed_inputs = 0;
+        for index in 0..self.inputs.len() {
+            let Some(previous_output) = self.previous_output(index) else { continue };
+            let input = &self.inputs[index];
+            if input.is_finalized()
+                || input
+                    .sighash_type
+                    .is_some_and(|sighash_type| sighash_type != SIGHASH_ALL)
+                || !previous_output.is_sent_to_any_key(&pubkey_hashes)?
+            {
+                continue;
+            }
+
+            let (privkey, redeem_script) =
+                match wallet.find_multisig(&previous_output.script_pubkey) {
+                    Some(script) => (
+                        wallet.get_multisig_signing_key(script)?,
+                        Some(script.serialize()),
+                    ),
+                    None => (
+                        wallet.get_signing_key(&previous_output.script_pubkey)?,
+                        None,
+                    ),
+                };
+            let sighash = self.unsigned_tx.input_sighash(
+                index,
+                &previous_output,
+                redeem_script.as_deref(),
+            )?;
+            let (signature, pubkey) = sign(&sighash, &privkey)?;
+
+            let input = &mut self.inputs[index];
+            input.partial_sigs.retain(|(key, _)| *key != pubkey);
+            input.partial_sigs.push((pubkey, signature));
+            if redeem_script.is_some() {
+                input.redeem_script = redeem_script;
+            }
+            signed_inputs += 1;
+        }
+        Ok(signed_inputs)
+    }
+
+    /// Combina las firmas y los datos de otro PSBT de la misma transaccion, por ejemplo el que firmo otro cosigner.
+    /// Devuelve CustomError si los PSBTs son de transacciones distintas.
+    pub fn combine(&mut self, other: Psbt) -> Result<(), CustomError> {
+        if self.unsigned_tx.hash() != other.unsigned_tx.hash() {
+            return Err(CustomError::Validation(String::from(
+                "PSBTs are for different transactions",
+            )));
+        }
+        for (input, other_input) in self.inputs.iter_mut().zip(other.inputs) {
+            input.combine(other_input);
+        }
+        for (output, other_output) in self.outputs.iter_mut().zip(other.outputs) {
+            combine_pairs(&mut output.unknown, other_output.unknown);
+        }
+        combine_pairs(&mut self.unknown, other.unknown);
+        Ok(())
+    }
+
+    /// Finaliza los inputs que tienen todas las firmas validas que necesitan, armando su script sig o su witness
+    /// segun el tipo de output que gastan: P2PK, P2PKH, P2WPKH o un multisig P2SH.
+    /// Las firmas parciales y el redeem script de los inputs finalizados se descartan.
+    /// Devuelve true si todos los inputs quedaron finalizados.
+    pub fn finalize(&mut self) -> bool {
+        for index in 0..self.inputs.len() {
+            if self.inputs[index].is_finalized() {
+                continue;
+            }
+            let Some((script_sig, witness)) = self.finalize_input(index) else { continue };
+            let input = &mut self.inputs[index];
+            input.final_script_sig = script_sig;
+            input.final_script_witness = witness;
+            input.partial_sigs.clear();
+            input.sighash_type = None;
+            input.redeem_script = None;
+        }
+        self.inputs.iter().all(PsbtInput::is_finalized)
+    }
+
+    /// Devuelve la transaccion firmada, lista para enviar, con los script sigs y witness finales de cada input.
+    /// Devuelve CustomError si algun input no esta finalizado.
+    pub fn extract(&self) -> Result<Transaction, CustomError> {
+        if !self.inputs.iter().all(PsbtInput::is_finalized) {
+            return Err(CustomError::Wallet(WalletError::IncompletePsbt));
+        }
+        let mut transaction = self.unsigned_tx.clone();
+        for (tx_input, input) in transaction.inputs.iter_mut().zip(&self.inputs) {
+            tx_input.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            tx_input.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+        Ok(transaction)
+    }
+
+    /// Serializa el PSBT (BIP 174).
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = PSBT_MAGIC.to_vec();
+        write_pair(
+            &mut buffer,
+            &[GLOBAL_UNSIGNED_TX],
+            &self.unsigned_tx.serialize_without_witness(),
+        );
+        for (key, value) in &self.unknown {
+            write_pair(&mut buffer, key, value);
+        }
+        buffer.push(SEPARATOR);
+        for input in &self.inputs {
+            buffer.extend(input.serialize());
+        }
+        for output in &self.outputs {
+            for (key, value) in &output.unknown {
+                write_pair(&mut buffer, key, value);
+            }
+            buffer.push(SEPARATOR);
+        }
+        buffer
+    }
+
+    /// Parsea un PSBT serializado (BIP 174), version 0.
+    /// Devuelve CustomError si no empieza con los bytes magicos, si falta la transaccion sin firmar o tiene firmas,
+    /// si alguna clave esta repetida o tiene un valor invalido, o si la cantidad de mapas no coincide con la de
+    /// inputs y outputs de la transaccion.
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, CustomError> {
+        let mut parser = BufferParser::new(&bytes);
+        if parser.extract_buffer(PSBT_MAGIC.len()).ok() != Some(&PSBT_MAGIC[..]) {
+            return Err(CustomError::Parse(ParseError::InvalidPsbt));
+        }
+
+        let mut unsigned_tx = None;
+        let mut unknown = vec![];
+        for (key, value) in parse_map(&mut parser)? {
+            match (key[0], key.len()) {
+                (GLOBAL_UNSIGNED_TX, 1) => {
+                    unsigned_tx = Some(parse_exact(&value, Transaction::parse_from_parser)?)
+                }
+                (GLOBAL_VERSION, 1) => {
+                    // solo se soporta la version 0
+                    if parse_exact(&value, BufferParser::extract_u32)? != 0 {
+                        return Err(CustomError::Parse(ParseError::InvalidPsbt));
+                    }
+                    unknown.push((key, value));
+                }
+                (GLOBAL_UNSIGNED_TX | GLOBAL_VERSION, _) => {
+                    return Err(CustomError::Parse(ParseError::InvalidPsbt))
+                }
+                _ => unknown.push((key, value)),
+            }
+        }
+        let Some(unsigned_tx) = unsigned_tx.filter(is_unsigned) else { return Err(CustomError::Parse(ParseError::InvalidPsbt)) };
+
+        let mut inputs = vec![];
+        for tx_input in &unsigned_tx.inputs {
+            let input = PsbtInput::parse(&mut parser)?;
+            if let Some(previous_tx) = &input.non_witness_utxo {
+                if previous_tx.hash() != tx_input.previous_output.hash {
+                    return Err(CustomError::Parse(ParseError::InvalidPsbt));
+                }
+            }
+            inputs.push(input);
+        }
+        let mut outputs = vec![];
+        for _ in &unsigned_tx.outputs {
+            outputs.push(PsbtOutput {
+                unknown: parse_map(&mut parser)?,
+            });
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::InvalidPsbt));
+        }
+        Ok(Self {
+            unsigned_tx,
+            inputs,
+            outputs,
+            unknown,
+        })
+    }
+
+    /// Arma el script sig y el witness final de un input, o devuelve None si no tiene las firmas que necesita.
+    fn finalize_input(&self, index: usize) -> Option<FinalScripts> {
+        let previous_output = self.previous_output(index)?;
+        let input = &self.inputs[index];
+        let sighash = self
+            .unsigned_tx
+            .input_sighash(index, &previous_output, input.redeem_script.as_deref())
+            .ok()?;
+        // firma valida de una clave cuyo hash160 es el recibido, junto a esa clave
+        let signature_for_hash = |hash: &[u8]| {
+            input.partial_sigs.iter().find(|(pubkey, signature)| {
+                hash160::Hash::hash(pubkey).as_byte_array()[..] == hash[..]
+                    && find_signing_key(&sighash, signature, std::slice::from_ref(pubkey)).is_some()
+            })
+        };
+
+        match previous_output.script_type() {
+            ScriptType::P2PK(pubkey) => {
+                let (_, signature) = input.partial_sigs.iter().find(|(_, signature)| {
+                    find_signing_key(&sighash, signature, std::slice::from_ref(&pubkey)).is_some()
+                })?;
+                let script_sig = Script::new(vec![ScriptElement::Push(signature.clone())]);
+                Some((Some(script_sig.serialize()), None))
+            }
+            ScriptType::P2PKH(pubkey_hash) => {
+                let (pubkey, signature) = signature_for_hash(&pubkey_hash)?;
+                let script_sig = Script::new(vec![
+                    ScriptElement::Push(signature.clone()),
+                    ScriptElement::Push(pubkey.clone()),
+                ]);
+                Some((Some(script_sig.serialize()), None))
+            }
+            ScriptType::P2WPKH(pubkey_hash) => {
+                let (pubkey, signature) = signature_for_hash(&pubkey_hash)?;
+                Some((None, Some(vec![signature.clone(), pubkey.clone()])))
+            }
+            ScriptType::P2SH(script_hash) => {
+                let redeem_script = input.redeem_script.as_ref()?;
+                if hash160::Hash::hash(redeem_script).as_byte_array()[..] != script_hash[..] {
+                    return None;
+                }
+                let (required, pubkeys) = Script::parse(redeem_script).ok()?.multisig_keys()?;
+                let mut signatures: Vec<(usize, Vec<u8>)> = vec![];
+                for (_, signature) in &input.partial_sigs {
+                    let Some(key_index) = find_signing_key(&sighash, signature, &pubkeys) else { continue };
+                    if !signatures.iter().any(|(index, _)| *index == key_index) {
+                        signatures.push((key_index, signature.clone()));
+                    }
+                }
+                if signatures.len() < required as usize {
+                    return None;
+                }
+                signatures.sort_by_key(|(key_index, _)| *key_index);
+                signatures.truncate(required as usize);
+
+                let mut script_sig = vec![ScriptElement::Op(OP_0)];
+                script_sig.extend(
+                    signatures
+                        .into_iter()
+                        .map(|(_, signature)| ScriptElement::Push(signature)),
+                );
+                script_sig.push(ScriptElement::Push(redeem_script.clone()));
+                Some((Some(Script::new(script_sig).serialize()), None))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Psbt {
+    /// Muestra el PSBT serializado en base64, el formato con el que se intercambia.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", base64::encode(&self.serialize()))
+    }
+}
+
+impl FromStr for Psbt {
+    type Err = CustomError;
+
+    /// Parsea un PSBT serializado en base64.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let bytes =
+            base64::decode(text.trim()).map_err(|_| CustomError::Parse(ParseError::InvalidPsbt))?;
+        Self::parse(bytes)
+    }
+}
+
+/// Devuelve true si ningun input de la transaccion tiene script sig ni witness.
+fn is_unsigned(transaction: &Transaction) -> bool {
+    transaction
+        .inputs
+        .iter()
+        .all(|input| input.script_sig.is_empty() && input.witness.is_empty())
+}
+
+/// Devuelve true si el output es SegWit nativo, en cuyo caso alcanza con el witness utxo para firmarlo.
+fn is_segwit(output: &TransactionOutput) -> bool {
+    matches!(
+        output.script_type(),
+        ScriptType::P2WPKH(_) | ScriptType::P2WSH(_) | ScriptType::P2TR(_)
+    )
+}
+
+/// Serializa un par de clave y valor, cada uno precedido por su largo.
+fn write_pair(buffer: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    buffer.extend(key.len().to_varint_bytes());
+    buffer.extend(key);
+    buffer.extend(value.len().to_varint_bytes());
+    buffer.extend(value);
+}
+
+/// Parsea los pares de clave y valor de un mapa hasta su separador.
+/// Devuelve CustomError si falta el separador o si alguna clave esta repetida.
+fn parse_map(parser: &mut BufferParser) -> Result<Vec<KeyValue>, CustomError> {
+    let invalid = |_| CustomError::Parse(ParseError::InvalidPsbt);
+    let mut pairs: Vec<KeyValue> = vec![];
+    loop {
+        let key_len = parser.extract_varint().map_err(invalid)? as usize;
+        if key_len == 0 {
+            return Ok(pairs);
+        }
+        let key = parser.extract_buffer(key_len).map_err(invalid)?.to_vec();
+        let value_len = parser.extract_varint().map_err(invalid)? as usize;
+        let value = parser.extract_buffer(value_len).map_err(invalid)?.to_vec();
+        if pairs.iter().any(|(pair_key, _)| *pair_key == key) {
+            return Err(CustomError::Parse(ParseError::InvalidPsbt));
+        }
+        pairs.push((key, value));
+    }
+}
+
+/// Parsea un valor con la funcion recibida, verificando que no sobren bytes.
+fn parse_exact<'a, T, F>(value: &'a [u8], parse: F) -> Result<T, CustomError>
+where
+    F: FnOnce(&mut BufferParser<'a>) -> Result<T, CustomError>,
+{
+    let mut parser = BufferParser::new(value);
+    let parsed = parse(&mut parser).map_err(|_| CustomError::Parse(ParseError::InvalidPsbt))?;
+    match parser.is_empty() {
+        true => Ok(parsed),
+        false => Err(CustomError::Parse(ParseError::InvalidPsbt)),
+    }
+}
+
+/// Parsea el stack de un witness: la cantidad de elementos y cada elemento precedido por su largo.
+fn parse_witness(parser: &mut BufferParser) -> Result<Vec<Vec<u8>>, CustomError> {
+    let item_count = parser.extract_varint()? as usize;
+    let mut witness = vec![];
+    for _ in 0..item_count {
+        let item_len = parser.extract_varint()? as usize;
+        witness.push(parser.extract_buffer(item_len)?.to_vec());
+    }
+    Ok(witness)
+}
+
+/// Agrega los pares del otro mapa cuyas claves no estan en el mapa.
+fn combine_pairs(pairs: &mut Vec<KeyValue>, other: Vec<KeyValue>) {
+    for (key, value) in other {
+        if !pairs.iter().any(|(pair_key, _)| *pair_key == key) {
+            pairs.push((key, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::{
+        states::utxo_state::UTXOValue,
+        structs::{hash256::Hash256, outpoint::OutPoint, tx_input::TransactionInput},
+        transaction_builder::TransactionBuilder,
+        wallet::get_script_pubkey,
+    };
+
+    const SENDER: &str = "mscatccDgq7azndWHFTzvEuZuywCsUvTRu";
+    const RECEIVER: &str = "mhzZUxRkPzNpCsQHemTakuJa5xhCajxyVm";
+
+    fn wallet() -> Wallet {
+        Wallet {
+            name: String::from("test"),
+            pubkey: String::from(SENDER),
+            privkey: Some(String::from(
+                "cNpwEsaVLhju18SJowLtdCNaJtvMvqL4jtFLm2FXw7vZjg4sRWvH",
+            )),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        }
+    }
+
+    /// UTXO de la wallet: una P2PKH y una P2WPKH.
+    fn utxo(wallet: &Wallet) -> Vec<(OutPoint, UTXOValue)> {
+        let addresses = [String::from(SENDER), wallet.get_bech32_address().unwrap()];
+        addresses
+            .into_iter()
+            .enumerate()
+            .map(|(i, address)| {
+                (
+                    OutPoint {
+                        hash: Hash256::new([i as u8; 32]),
+                        index: 0,
+                    },
+                    UTXOValue {
+                        tx_out: TransactionOutput {
+                            value: 10000,
+                            script_pubkey: get_script_pubkey(address).unwrap(),
+                        },
+                        block_hash: Hash256::new([0; 32]),
+                        block_timestamp: 0,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn builder(wallet: &Wallet) -> TransactionBuilder<'_> {
+        TransactionBuilder::new(wallet, utxo(wallet))
+            .add_output(String::from(RECEIVER), 19500)
+            .fee(500)
+    }
+
+    #[test]
+    fn psbt_serialization() {
+        let wallet = wallet();
+        let mut psbt = builder(&wallet).build_psbt().unwrap();
+        psbt.unknown.push((vec![0xfc, 1, 2], vec![3]));
+        psbt.outputs[0].unknown.push((vec![0x02, 4], vec![5, 6]));
+
+        let serialized = psbt.serialize();
+        assert_eq!(serialized[..5], PSBT_MAGIC);
+        let parsed = Psbt::parse(serialized.clone()).unwrap();
+        assert_eq!(parsed.serialize(), serialized);
+        assert_eq!(parsed.unknown, psbt.unknown);
+        assert_eq!(parsed.inputs.len(), 2);
+        assert_eq!(parsed.fee(), Some(500));
+
+        let base64 = psbt.to_string();
+        assert!(base64.starts_with("cHNidP8"));
+        assert_eq!(base64.parse::<Psbt>().unwrap().serialize(), serialized);
+    }
+
+    #[test]
+    fn invalid_psbts_are_rejected() {
+        let wallet = wallet();
+        let psbt = builder(&wallet).build_psbt().unwrap();
+        let serialized = psbt.serialize();
+
+        let mut bad_magic = serialized.clone();
+        bad_magic[4] = 0x00;
+        let mut missing_output = serialized.clone();
+        missing_output.pop();
+        let mut trailing_bytes = serialized.clone();
+        trailing_bytes.push(0x00);
+        let mut duplicated_key = PSBT_MAGIC.to_vec();
+        for _ in 0..2 {
+            write_pair(
+                &mut duplicated_key,
+                &[GLOBAL_UNSIGNED_TX],
+                &psbt.unsigned_tx.serialize_without_witness(),
+            );
+        }
+
+        for bytes in [bad_magic, missing_output, trailing_bytes, duplicated_key] {
+            assert!(matches!(
+                Psbt::parse(bytes),
+                Err(CustomError::Parse(ParseError::InvalidPsbt))
+            ));
+        }
+        assert!("not a psbt".parse::<Psbt>().is_err());
+
+        let signed_tx = builder(&wallet).build().unwrap();
+        assert!(Psbt::new(signed_tx, vec![]).is_err());
+    }
+
+    #[test]
+    fn sign_finalize_and_extract() {
+        let wallet = wallet();
+        let mut psbt = builder(&wallet).build_psbt().unwrap();
+        assert!(!psbt.finalize());
+        assert!(matches!(
+            psbt.extract(),
+            Err(CustomError::Wallet(WalletError::IncompletePsbt))
+        ));
+
+        // el PSBT pasa por su serializacion, como si lo firmara otra aplicacion
+        let mut psbt = Psbt::parse(psbt.serialize()).unwrap();
+        assert_eq!(psbt.sign(&wallet).unwrap(), 2);
+        assert!(psbt.finalize());
+        assert!(psbt
+            .inputs
+            .iter()
+            .all(|input| input.partial_sigs.is_empty()));
+
+        // las firmas son deterministicas (RFC 6979), la transaccion es la misma que firma el builder
+        let transaction = psbt.extract().unwrap();
+        let signed_tx = builder(&wallet).build().unwrap();
+        assert_eq!(transaction.wtxid(), signed_tx.wtxid());
+        assert!(transaction.has_witness());
+    }
+
+    #[test]
+    fn combine_multisig_signatures() {
+        let secp = Secp256k1::new();
+        let privkeys: Vec<Vec<u8>> = (1..=3).map(|i| vec![i; 32]).collect();
+        let pubkeys: Vec<Vec<u8>> = privkeys
+            .iter()
+            .map(|privkey| {
+                let key = SecretKey::from_slice(privkey).unwrap();
+                PublicKey::from_secret_key(&secp, &key).serialize().to_vec()
+            })
+            .collect();
+        let redeem_script = Script::multisig(2, &pubkeys).unwrap();
+        let previous_output = TransactionOutput {
+            value: 10000,
+            script_pubkey: Script::p2sh(&redeem_script.hash160()).serialize(),
+        };
+        let unsigned_tx = Transaction {
+            version: 1,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: Hash256::new([1; 32]),
+                    index: 0,
+                },
+                script_sig: vec![],
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            outputs: vec![TransactionOutput {
+                value: 9000,
+                script_pubkey: get_script_pubkey(String::from(RECEIVER)).unwrap(),
+            }],
+            lock_time: 0,
+        };
+        let psbt = Psbt::new(unsigned_tx.clone(), vec![previous_output.clone()]).unwrap();
+
+        // cada cosigner firma su copia del PSBT
+        let sighash = unsigned_tx
+            .input_sighash(0, &previous_output, Some(&redeem_script.serialize()))
+            .unwrap();
+        let mut cosigners = vec![];
+        for privkey in [&privkeys[2], &privkeys[0]] {
+            let mut cosigner_psbt = psbt.clone();
+            let (signature, pubkey) = sign(&sighash, privkey).unwrap();
+            cosigner_psbt.inputs[0]
+                .partial_sigs
+                .push((pubkey, signature));
+            cosigner_psbt.inputs[0].redeem_script = Some(redeem_script.serialize());
+            cosigners.push(cosigner_psbt);
+        }
+        let mut combined = cosigners.remove(0);
+        assert!(!combined.clone().finalize());
+        combined.combine(cosigners.remove(0)).unwrap();
+        assert_eq!(combined.inputs[0].partial_sigs.len(), 2);
+        assert!(combined.finalize());
+
+        let mut cosigned_tx = unsigned_tx;
+        cosigned_tx
+            .sign_multisig_input(0, &redeem_script, &privkeys[2])
+            .unwrap();
+        cosigned_tx
+            .sign_multisig_input(0, &redeem_script, &privkeys[0])
+            .unwrap();
+        assert_eq!(combined.extract().unwrap().hash(), cosigned_tx.hash());
+
+        let mut other_tx = psbt.clone();
+        other_tx.unsigned_tx.lock_time = 1;
+        assert!(combined.combine(other_tx).is_err());
+    }
+}
@@ -0,0 +1,1042 @@
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+
+use crate::{
+    base64,
+    error::{CustomError, NetworkError, ParseError, WalletError},
+    fee_estimator::FeePriority,
+    json::JsonValue,
+    logger::{send_log, Log, LogEntry},
+    loops::node_action_loop::NodeAction,
+    message::Message,
+    messages::transaction::Transaction,
+    network::Network,
+    node_state::NodeState,
+    psbt::Psbt,
+    states::history_state::Direction,
+    structs::{hash256::Hash256, outpoint::OutPoint, transaction_details::TransactionDetails},
+    transaction_builder::{Fee, Payment},
+    utils::{bytes_to_hex, hex_to_bytes},
+    wallet::get_address,
+};
+
+/// Cantidad de satoshis en un bitcoin, los montos de la API se expresan en bitcoins.
+const SATOSHIS_PER_BITCOIN: f64 = 100_000_000.0;
+/// Tiempo maximo en segundos para leer un request.
+const READ_TIMEOUT: u64 = 5;
+/// Tamaño maximo en bytes del body de un request.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+/// Archivo del directorio de datos con las credenciales generadas al iniciar el servidor.
+const COOKIE_FILE: &str = ".cookie";
+/// Usuario de las credenciales del archivo cookie, el mismo que usa Bitcoin Core.
+const COOKIE_USER: &str = "__cookie__";
+/// Tamaño en bytes de la password aleatoria del archivo cookie.
+const COOKIE_PASSWORD_SIZE: usize = 32;
+/// Tiempo en milisegundos que se demora la respuesta a un request con credenciales invalidas,
+/// para que no se puedan probar passwords por fuerza bruta.
+const UNAUTHORIZED_DELAY: u64 = 250;
+
+/// Codigos de error de JSON-RPC, con los mismos valores que usa Bitcoin Core.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const MISC_ERROR: i64 = -1;
+const WALLET_ERROR: i64 = -4;
+const INVALID_ADDRESS_OR_KEY: i64 = -5;
+const DESERIALIZATION_ERROR: i64 = -22;
+
+/// RpcError es un error de un request JSON-RPC.
+/// Los elementos son:
+/// - code: Codigo del error.
+/// - message: Descripcion del error.
+#[derive(Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<CustomError> for RpcError {
+    fn from(error: CustomError) -> Self {
+        // el contexto del peer no cambia el codigo ni el mensaje que recibe el cliente
+        let code = match error.root() {
+            CustomError::Wallet(WalletError::WalletNotFound)
+            | CustomError::Wallet(WalletError::InsufficientFunds)
+            | CustomError::Wallet(WalletError::WatchOnlyWallet) => WALLET_ERROR,
+            CustomError::Validation(_) => INVALID_ADDRESS_OR_KEY,
+            CustomError::Parse(ParseError::InvalidPsbt) => DESERIALIZATION_ERROR,
+            _ => MISC_ERROR,
+        };
+        match error.root() {
+            CustomError::Validation(message) => Self::new(code, message),
+            error => Self::new(code, error.description()),
+        }
+    }
+}
+
+impl<T> From<PoisonError<T>> for RpcError {
+    fn from(error: PoisonError<T>) -> Self {
+        CustomError::from(error).into()
+    }
+}
+
+/// RpcAuth son las credenciales que deben enviar los clientes del servidor en el header Authorization (HTTP Basic).
+/// Si no se configuraron un usuario y una password, se genera una password aleatoria en cada inicio y se guarda
+/// como "__cookie__:password" en el archivo .cookie del directorio de datos, como en Bitcoin Core.
+/// Los elementos son:
+/// - credentials: Usuario y password esperados, en el formato "usuario:password".
+/// - cookie_path: Archivo cookie con las credenciales, si se generaron al iniciar el servidor.
+#[derive(Debug, Clone)]
+pub struct RpcAuth {
+    credentials: String,
+    cookie_path: Option<PathBuf>,
+}
+
+impl RpcAuth {
+    /// Inicializa las credenciales con el usuario y la password configurados, o genera el archivo cookie
+    /// en el directorio de datos si no se configuraron. El archivo solo lo puede leer el usuario que corre el nodo.
+    /// Devuelve CustomError si se configuro solo el usuario o solo la password, o si no se pudo escribir el archivo.
+    pub fn new(
+        data_dir: &str,
+        user: Option<String>,
+        password: Option<String>,
+    ) -> Result<Self, CustomError> {
+        match (user, password) {
+            (Some(user), Some(password)) => Ok(Self {
+                credentials: format!("{}:{}", user, password),
+                cookie_path: None,
+            }),
+            (None, None) => {
+                let mut password = [0; COOKIE_PASSWORD_SIZE];
+                OsRng.fill_bytes(&mut password);
+                let credentials = format!("{}:{}", COOKIE_USER, bytes_to_hex(&password));
+                let cookie_path = Path::new(data_dir).join(COOKIE_FILE);
+                write_cookie(&cookie_path, &credentials)?;
+                Ok(Self {
+                    credentials,
+                    cookie_path: Some(cookie_path),
+                })
+            }
+            _ => Err(CustomError::Validation(String::from(
+                "RPC user and password must be configured together",
+            ))),
+        }
+    }
+
+    /// Devuelve el archivo cookie con las credenciales, si se genero.
+    pub fn cookie_path(&self) -> Option<&Path> {
+        self.cookie_path.as_deref()
+    }
+
+    /// Devuelve true si el valor del header Authorization tiene las credenciales del servidor.
+    pub fn is_authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(encoded) = authorization.and_then(|value| value.trim().strip_prefix("Basic "))
+        else {
+            return false;
+        };
+        let Ok(credentials) = base64::decode(encoded.trim()) else { return false };
+        constant_time_eq(&credentials, self.credentials.as_bytes())
+    }
+
+    /// Elimina el archivo cookie, para que no queden credenciales validas cuando el servidor se detiene.
+    pub fn remove_cookie(&self) -> Result<(), CustomError> {
+        if let Some(cookie_path) = &self.cookie_path {
+            fs::remove_file(cookie_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Escribe las credenciales en el archivo cookie, reemplazando el de una ejecucion anterior.
+/// En unix el archivo se crea con permisos de lectura y escritura solo para su dueño.
+fn write_cookie(path: &Path, credentials: &str) -> Result<(), CustomError> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(path)?.write_all(credentials.as_bytes())?;
+    Ok(())
+}
+
+/// Compara las credenciales recibidas con las esperadas en un tiempo que no depende de en que byte difieren.
+fn constant_time_eq(received: &[u8], expected: &[u8]) -> bool {
+    received.len() == expected.len()
+        && received
+            .iter()
+            .zip(expected)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// RpcRequest es un request JSON-RPC 1.0 o 2.0.
+/// Los elementos son:
+/// - id: Identificador del request, se devuelve en la respuesta.
+/// - method: Nombre del metodo a ejecutar.
+/// - params: Parametros del metodo, en orden.
+#[derive(Debug, PartialEq)]
+pub struct RpcRequest {
+    pub id: JsonValue,
+    pub method: String,
+    pub params: Vec<JsonValue>,
+}
+
+impl RpcRequest {
+    /// Parsea un request a partir del body recibido.
+    /// Devuelve RpcError si el body no es JSON o no es un request valido.
+    pub fn parse(body: &str) -> Result<Self, RpcError> {
+        let request =
+            JsonValue::parse(body).map_err(|_| RpcError::new(PARSE_ERROR, "Parse error"))?;
+        let Some(method) = request.get("method").and_then(JsonValue::as_str) else { return Err(RpcError::new(INVALID_REQUEST, "Method must be a string")) };
+        let params = match request.get("params") {
+            None | Some(JsonValue::Null) => vec![],
+            Some(JsonValue::Array(params)) => params.clone(),
+            Some(_) => {
+                return Err(RpcError::new(INVALID_REQUEST, "Params must be an array"));
+            }
+        };
+        Ok(Self {
+            id: request.get("id").cloned().unwrap_or(JsonValue::Null),
+            method: method.to_string(),
+            params,
+        })
+    }
+}
+
+/// Serializa la respuesta a un request, con el resultado o el error.
+pub fn response(id: JsonValue, result: Result<JsonValue, RpcError>) -> String {
+    let (result, error) = match result {
+        Ok(result) => (result, JsonValue::Null),
+        Err(error) => (
+            JsonValue::Null,
+            JsonValue::object(vec![
+                ("code", JsonValue::Number(error.code as f64)),
+                ("message", JsonValue::String(error.message)),
+            ]),
+        ),
+    };
+    JsonValue::object(vec![("result", result), ("error", error), ("id", id)]).to_string()
+}
+
+/// RpcServer es el loop de eventos del servidor JSON-RPC, que permite operar la wallet activa sin interfaz grafica.
+/// Escucha requests HTTP POST en una direccion local y atiende una conexion a la vez.
+/// Solo ejecuta los requests con las credenciales de auth, al resto les responde 401 Unauthorized.
+/// Los metodos soportados son getbalance, listunspent, sendtoaddress, gettransaction, getblockcount, getblockchaininfo,
+/// getnettotals y stop.
+/// Los elementos son:
+/// - address: Direccion en la que escucha el servidor.
+/// - auth: Credenciales que deben enviar los clientes.
+/// - node_state_ref: Referencia al estado del nodo.
+/// - node_action_sender: Sender para enviar acciones al nodo.
+/// - logger_sender: Sender para enviar logs al logger.
+/// - stop_sender: Sender para avisar que se solicito detener el nodo.
+pub struct RpcServer {
+    address: SocketAddr,
+    auth: RpcAuth,
+    node_state_ref: Arc<Mutex<NodeState>>,
+    node_action_sender: mpsc::Sender<NodeAction>,
+    logger_sender: mpsc::Sender<LogEntry>,
+    stop_sender: mpsc::Sender<()>,
+}
+
+impl RpcServer {
+    #[must_use]
+    /// Inicializa el servidor en un thread.
+    pub fn spawn(
+        address: SocketAddr,
+        auth: RpcAuth,
+        node_state_ref: Arc<Mutex<NodeState>>,
+        node_action_sender: mpsc::Sender<NodeAction>,
+        logger_sender: mpsc::Sender<LogEntry>,
+        stop_sender: mpsc::Sender<()>,
+    ) -> JoinHandle<Result<(), CustomError>> {
+        thread::spawn(move || -> Result<(), CustomError> {
+            let mut server = Self {
+                address,
+                auth,
+                node_state_ref,
+                node_action_sender,
+                logger_sender,
+                stop_sender,
+            };
+            server.event_loop()
+        })
+    }
+
+    fn event_loop(&mut self) -> Result<(), CustomError> {
+        let listener = TcpListener::bind(self.address)
+            .map_err(|_| CustomError::Network(NetworkError::CannotStartRpcServer))?;
+        send_log(
+            &self.logger_sender,
+            Log::Message(format!("RPC server listening on {}", self.address)),
+        );
+
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            if let Err(error) = self.handle_connection(stream) {
+                send_log(
+                    &self.logger_sender,
+                    Log::Warning(format!("Error handling RPC request: {}", error)),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Lee un request HTTP, lo ejecuta si tiene las credenciales del servidor y escribe la respuesta.
+    fn handle_connection(&mut self, mut stream: TcpStream) -> Result<(), CustomError> {
+        stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT)))?;
+        let request = read_http_request(&stream)?;
+        let http_response = match self.auth.is_authorized(request.authorization.as_deref()) {
+            true => {
+                let response = self.handle_request(&request.body);
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+            }
+            false => {
+                send_log(
+                    &self.logger_sender,
+                    Log::Warning(String::from("RPC request with invalid credentials")),
+                );
+                thread::sleep(Duration::from_millis(UNAUTHORIZED_DELAY));
+                String::from(
+                    "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"jsonrpc\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+            }
+        };
+        stream
+            .write_all(http_response.as_bytes())
+            .map_err(|_| CustomError::Network(NetworkError::CannotSendToStream))?;
+        Ok(())
+    }
+
+    /// Ejecuta un request JSON-RPC y devuelve la respuesta serializada.
+    fn handle_request(&mut self, body: &str) -> String {
+        let request = match RpcRequest::parse(body) {
+            Ok(request) => request,
+            Err(error) => return response(JsonValue::Null, Err(error)),
+        };
+        send_log(
+            &self.logger_sender,
+            Log::Debug(format!("RPC request: {}", request.method)),
+        );
+        let result = self.call(&request.method, &request.params);
+        response(request.id, result)
+    }
+
+    fn call(&mut self, method: &str, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        match method {
+            "getbalance" => self.get_balance(),
+            "listunspent" => self.list_unspent(),
+            "sendtoaddress" => self.send_to_address(params),
+            "gettransaction" => self.get_transaction(params),
+            "getpaymenturi" => self.get_payment_uri(params),
+            "addmultisigaddress" => self.add_multisig_address(params),
+            "cosignrawtransaction" => self.cosign_raw_transaction(params),
+            "sendrawtransaction" => self.send_raw_transaction(params),
+            "decoderawtransaction" => self.decode_raw_transaction(params),
+            "walletcreatefundedpsbt" => self.wallet_create_funded_psbt(params),
+            "walletprocesspsbt" => self.wallet_process_psbt(params),
+            "combinepsbt" => self.combine_psbt(params),
+            "finalizepsbt" => self.finalize_psbt(params),
+            "getblockcount" => self.get_block_count(),
+            "getblockchaininfo" => self.get_blockchain_info(),
+            "getnettotals" => self.get_net_totals(),
+            "stop" => self.stop(),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+        }
+    }
+
+    /// Devuelve el balance confirmado de la wallet activa.
+    fn get_balance(&self) -> Result<JsonValue, RpcError> {
+        let node_state = self.node_state_ref.lock()?;
+        let balances = node_state.get_active_wallet_balances()?;
+        Ok(bitcoins(balances.confirmed as i64))
+    }
+
+    /// Devuelve los outputs sin gastar de la wallet activa.
+    fn list_unspent(&self) -> Result<JsonValue, RpcError> {
+        let node_state = self.node_state_ref.lock()?;
+        let best_height = node_state.get_best_height();
+        let utxo = node_state.get_active_wallet_utxo()?;
+
+        let unspent = utxo
+            .into_iter()
+            .map(|(out_point, value)| {
+                let confirmations = node_state
+                    .get_block_height(&value.block_hash)
+                    .map(|height| best_height.saturating_sub(height) + 1)
+                    .unwrap_or(0);
+                JsonValue::object(vec![
+                    ("txid", JsonValue::String(out_point.hash.to_string())),
+                    ("vout", JsonValue::Number(out_point.index as f64)),
+                    (
+                        "address",
+                        get_address(&value.tx_out.script_pubkey)
+                            .map(JsonValue::String)
+                            .unwrap_or(JsonValue::Null),
+                    ),
+                    (
+                        "scriptPubKey",
+                        JsonValue::String(bytes_to_hex(&value.tx_out.script_pubkey)),
+                    ),
+                    ("amount", bitcoins(value.tx_out.value as i64)),
+                    ("confirmations", JsonValue::Number(confirmations as f64)),
+                ])
+            })
+            .collect();
+        Ok(JsonValue::Array(unspent))
+    }
+
+    /// Envia un monto en bitcoins a una direccion desde la wallet activa, con el fee de prioridad media.
+    /// El comentario opcional se guarda como etiqueta de la transaccion.
+    /// Como en Bitcoin Core, el quinto parametro subtractfeefromamount descuenta el fee del monto enviado.
+    /// Devuelve el txid de la transaccion creada.
+    fn send_to_address(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let (Some(address), Some(amount)) = (
+            params.first().and_then(JsonValue::as_str),
+            params.get(1).and_then(JsonValue::as_f64),
+        ) else {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                "Expected address and amount params",
+            ));
+        };
+        let satoshis = (amount * SATOSHIS_PER_BITCOIN).round();
+        if satoshis <= 0.0 {
+            return Err(RpcError::new(INVALID_PARAMS, "Amount must be positive"));
+        }
+
+        let comment = params
+            .get(2)
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default();
+        let subtract_fee_from_amount = params
+            .get(4)
+            .and_then(JsonValue::as_bool)
+            .unwrap_or_default();
+
+        let mut node_state = self.node_state_ref.lock()?;
+        let fee_rate = node_state.get_fee_rate(FeePriority::Medium);
+        let outputs = vec![(address.to_string(), satoshis as u64)];
+        let mut payment = Payment::new(outputs, Fee::Rate(fee_rate));
+        if subtract_fee_from_amount {
+            payment.subtract_fee_from = Some(address.to_string());
+        }
+        let transaction = node_state.make_transaction(payment)?;
+        node_state.set_tx_label(&transaction.hash(), comment)?;
+        drop(node_state);
+
+        let txid = transaction.hash().to_string();
+        self.node_action_sender
+            .send(NodeAction::SendTransaction(transaction))
+            .map_err(CustomError::from)?;
+        Ok(JsonValue::String(txid))
+    }
+
+    /// Devuelve una transaccion del historial de la wallet activa a partir de su txid.
+    fn get_transaction(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let Some(txid) = params.first().and_then(JsonValue::as_str) else { return Err(RpcError::new(INVALID_PARAMS, "Expected txid param")) };
+        let not_found = || {
+            RpcError::new(
+                INVALID_ADDRESS_OR_KEY,
+                "Invalid or non-wallet transaction id",
+            )
+        };
+        let tx_hash = txid.parse::<Hash256>().map_err(|_| not_found())?;
+
+        let node_state = self.node_state_ref.lock()?;
+        let (entries, best_height) = node_state.get_active_wallet_history();
+        drop(node_state);
+        let entry = entries
+            .into_iter()
+            .find(|entry| entry.tx_hash == tx_hash)
+            .ok_or_else(not_found)?;
+
+        let amount = match entry.direction {
+            Direction::Received => entry.amount as i64,
+            Direction::Sent => -(entry.amount as i64),
+        };
+        let mut fields = vec![
+            ("txid", JsonValue::String(txid.to_lowercase())),
+            ("amount", bitcoins(amount)),
+            (
+                "confirmations",
+                JsonValue::Number(entry.confirmations(best_height) as f64),
+            ),
+        ];
+        if let (Some(block_hash), Some(block_height)) = (&entry.block_hash, entry.block_height) {
+            fields.push(("blockhash", JsonValue::String(block_hash.to_string())));
+            fields.push(("blockheight", JsonValue::Number(block_height as f64)));
+        }
+        if let Some(label) = entry.label {
+            fields.push(("comment", JsonValue::String(label)));
+        }
+        Ok(JsonValue::object(fields))
+    }
+
+    /// Devuelve la URI de pago BIP 21 de la direccion en la que la wallet activa recibe fondos,
+    /// con el monto en bitcoins y el nombre opcionales.
+    fn get_payment_uri(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let amount = match params.first() {
+            None | Some(JsonValue::Null) => None,
+            Some(amount) => {
+                let Some(amount) = amount.as_f64().filter(|amount| *amount > 0.0) else { return Err(RpcError::new(INVALID_PARAMS, "Amount must be positive")) };
+                Some((amount * SATOSHIS_PER_BITCOIN).round() as u64)
+            }
+        };
+        let label = params
+            .get(1)
+            .and_then(JsonValue::as_str)
+            .map(str::to_string);
+
+        let node_state = self.node_state_ref.lock()?;
+        let uri = node_state.get_active_wallet_payment_uri(amount, label)?;
+        Ok(JsonValue::String(uri.to_string()))
+    }
+
+    /// Agrega a la wallet activa un multisig m-of-n a partir de la cantidad de firmas requeridas
+    /// y las public keys en hexadecimal. Devuelve su direccion P2SH.
+    fn add_multisig_address(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let (Some(required), Some(keys)) = (
+            params.first().and_then(JsonValue::as_f64),
+            params.get(1).and_then(JsonValue::as_array),
+        ) else {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                "Expected nrequired and keys params",
+            ));
+        };
+        let pubkeys = keys
+            .iter()
+            .map(|key| key.as_str().and_then(hex_to_bytes))
+            .collect::<Option<Vec<Vec<u8>>>>()
+            .ok_or_else(|| RpcError::new(INVALID_ADDRESS_OR_KEY, "Invalid public key"))?;
+        if required.fract() != 0.0 || !(0.0..=u8::MAX as f64).contains(&required) {
+            return Err(RpcError::new(INVALID_PARAMS, "Invalid nrequired"));
+        }
+
+        let mut node_state = self.node_state_ref.lock()?;
+        let address = node_state.add_multisig(required as u8, &pubkeys)?;
+        Ok(JsonValue::String(address))
+    }
+
+    /// Co-firma una transaccion en hexadecimal con las claves de los multisigs de la wallet activa.
+    /// Devuelve la transaccion firmada y si sus inputs multisig quedaron con todas las firmas requeridas.
+    fn cosign_raw_transaction(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let transaction = raw_transaction_param(params)?;
+        let node_state = self.node_state_ref.lock()?;
+        let (transaction, complete) = node_state.cosign_transaction(transaction)?;
+        Ok(JsonValue::object(vec![
+            (
+                "hex",
+                JsonValue::String(bytes_to_hex(&transaction.serialize())),
+            ),
+            ("complete", JsonValue::Bool(complete)),
+        ]))
+    }
+
+    /// Envia a los peers una transaccion firmada en hexadecimal. Devuelve su txid.
+    fn send_raw_transaction(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let transaction = raw_transaction_param(params)?;
+        let txid = transaction.hash().to_string();
+        self.node_action_sender
+            .send(NodeAction::SendTransaction(transaction))
+            .map_err(CustomError::from)?;
+        Ok(JsonValue::String(txid))
+    }
+
+    /// Decodifica una transaccion en hexadecimal, sin difundirla.
+    /// Devuelve su txid, tamaño, inputs y outputs con el tipo y la direccion de cada script pubkey.
+    fn decode_raw_transaction(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let transaction = raw_transaction_param(params)?;
+        Ok(transaction_details_json(&TransactionDetails::from(
+            &transaction,
+        )))
+    }
+
+    /// Crea un PSBT sin firmar que envia los montos en bitcoins del objeto de outputs desde la wallet activa,
+    /// con el fee de prioridad media. Gasta los inputs recibidos, con su txid y vout, y si no alcanzan otras UTXO.
+    /// Devuelve el PSBT en base64 y su fee.
+    fn wallet_create_funded_psbt(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let (inputs, Some(JsonValue::Object(outputs))) = (params.first(), params.get(1)) else {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                "Expected inputs and outputs params",
+            ));
+        };
+        let required_inputs = match inputs {
+            None | Some(JsonValue::Null) => vec![],
+            Some(JsonValue::Array(inputs)) => inputs
+                .iter()
+                .map(out_point_param)
+                .collect::<Result<Vec<OutPoint>, RpcError>>()?,
+            Some(_) => return Err(RpcError::new(INVALID_PARAMS, "Invalid inputs")),
+        };
+        let mut satoshis = vec![];
+        for (address, amount) in outputs {
+            let Some(amount) = amount.as_f64().filter(|amount| *amount > 0.0) else { return Err(RpcError::new(INVALID_PARAMS, "Amount must be positive")) };
+            satoshis.push((
+                address.clone(),
+                (amount * SATOSHIS_PER_BITCOIN).round() as u64,
+            ));
+        }
+
+        let node_state = self.node_state_ref.lock()?;
+        let fee_rate = node_state.get_fee_rate(FeePriority::Medium);
+        let psbt = node_state.create_psbt(satoshis, Fee::Rate(fee_rate), required_inputs)?;
+        drop(node_state);
+
+        let mut fields = vec![("psbt", JsonValue::String(psbt.to_string()))];
+        if let Some(fee) = psbt.fee() {
+            fields.push(("fee", bitcoins(fee as i64)));
+        }
+        Ok(JsonValue::object(fields))
+    }
+
+    /// Firma un PSBT en base64 con las claves de la wallet activa y finaliza los inputs que quedan completos.
+    /// Devuelve el PSBT, si quedo completo y en ese caso la transaccion firmada en hexadecimal.
+    fn wallet_process_psbt(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let psbt = psbt_param(params.first())?;
+        let node_state = self.node_state_ref.lock()?;
+        let (psbt, complete) = node_state.process_psbt(psbt)?;
+        drop(node_state);
+
+        let mut fields = vec![
+            ("psbt", JsonValue::String(psbt.to_string())),
+            ("complete", JsonValue::Bool(complete)),
+        ];
+        if complete {
+            fields.push((
+                "hex",
+                JsonValue::String(bytes_to_hex(&psbt.extract()?.serialize())),
+            ));
+        }
+        Ok(JsonValue::object(fields))
+    }
+
+    /// Combina las firmas de varios PSBTs en base64 de la misma transaccion. Devuelve el PSBT combinado.
+    fn combine_psbt(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let Some(psbts) = params.first().and_then(JsonValue::as_array) else { return Err(RpcError::new(INVALID_PARAMS, "Expected txs param")) };
+        let mut psbts = psbts.iter().map(|psbt| psbt_param(Some(psbt)));
+        let Some(combined) = psbts.next() else { return Err(RpcError::new(INVALID_PARAMS, "Expected txs param")) };
+        let mut combined = combined?;
+        for psbt in psbts {
+            combined.combine(psbt?)?;
+        }
+        Ok(JsonValue::String(combined.to_string()))
+    }
+
+    /// Finaliza los inputs de un PSBT en base64 que tienen todas sus firmas.
+    /// Si quedo completo devuelve la transaccion firmada en hexadecimal, lista para sendrawtransaction,
+    /// sino devuelve el PSBT.
+    fn finalize_psbt(&self, params: &[JsonValue]) -> Result<JsonValue, RpcError> {
+        let mut psbt = psbt_param(params.first())?;
+        let complete = psbt.finalize();
+        let result = match complete {
+            true => (
+                "hex",
+                JsonValue::String(bytes_to_hex(&psbt.extract()?.serialize())),
+            ),
+            false => ("psbt", JsonValue::String(psbt.to_string())),
+        };
+        Ok(JsonValue::object(vec![
+            result,
+            ("complete", JsonValue::Bool(complete)),
+        ]))
+    }
+
+    /// Devuelve la height del ultimo header de la cadena.
+    fn get_block_count(&self) -> Result<JsonValue, RpcError> {
+        let node_state = self.node_state_ref.lock()?;
+        Ok(JsonValue::Number(node_state.get_best_height() as f64))
+    }
+
+    /// Devuelve la red, la height y el hash del ultimo header y el trabajo acumulado por la cadena.
+    fn get_blockchain_info(&self) -> Result<JsonValue, RpcError> {
+        let node_state = self.node_state_ref.lock()?;
+        let best_block_hash = node_state
+            .get_last_header_hash()
+            .unwrap_or(Network::current().genesis_hash());
+        Ok(JsonValue::object(vec![
+            (
+                "chain",
+                JsonValue::String(Network::current().name().to_string()),
+            ),
+            (
+                "headers",
+                JsonValue::Number(node_state.get_best_height() as f64),
+            ),
+            (
+                "bestblockhash",
+                JsonValue::String(best_block_hash.to_string()),
+            ),
+            (
+                "chainwork",
+                JsonValue::String(node_state.chain_work().to_string()),
+            ),
+        ]))
+    }
+
+    /// Devuelve los bytes enviados y recibidos por el nodo, en total y por tipo de mensaje.
+    fn get_net_totals(&self) -> Result<JsonValue, RpcError> {
+        let totals = self.node_state_ref.lock()?.net_totals()?;
+        let by_command = totals
+            .by_command
+            .into_iter()
+            .map(|(command, stats)| {
+                let stats = JsonValue::object(vec![
+                    ("msgsent", JsonValue::Number(stats.messages_sent as f64)),
+                    ("bytessent", JsonValue::Number(stats.bytes_sent as f64)),
+                    ("msgrecv", JsonValue::Number(stats.messages_received as f64)),
+                    ("bytesrecv", JsonValue::Number(stats.bytes_received as f64)),
+                ]);
+                (command, stats)
+            })
+            .collect();
+        Ok(JsonValue::object(vec![
+            (
+                "totalbytesrecv",
+                JsonValue::Number(totals.bytes_received as f64),
+            ),
+            (
+                "totalbytessent",
+                JsonValue::Number(totals.bytes_sent as f64),
+            ),
+            ("bycommand", JsonValue::Object(by_command)),
+        ]))
+    }
+
+    /// Solicita detener el nodo.
+    fn stop(&self) -> Result<JsonValue, RpcError> {
+        self.stop_sender.send(()).map_err(CustomError::from)?;
+        Ok(JsonValue::String(String::from("Node stopping")))
+    }
+}
+
+/// HttpRequest es un request HTTP recibido por el servidor.
+/// Los elementos son:
+/// - authorization: Valor del header Authorization, si se envio.
+/// - body: Body del request, de largo Content-Length.
+struct HttpRequest {
+    authorization: Option<String>,
+    body: String,
+}
+
+/// Lee los headers de un request HTTP y su body, de largo Content-Length.
+fn read_http_request(stream: &TcpStream) -> Result<HttpRequest, CustomError> {
+    let mut reader = BufReader::new(stream);
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .map_err(|_| CustomError::Network(NetworkError::CannotReadStream))?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| CustomError::Network(NetworkError::CannotReadStream))?;
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(CustomError::Network(NetworkError::CannotReadStream));
+    }
+
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| CustomError::Network(NetworkError::CannotReadStream))?;
+    let body = String::from_utf8(body)
+        .map_err(|_| CustomError::Network(NetworkError::CannotReadStream))?;
+    Ok(HttpRequest {
+        authorization,
+        body,
+    })
+}
+
+/// Convierte un monto en satoshis a un numero en bitcoins.
+fn bitcoins(satoshis: i64) -> JsonValue {
+    JsonValue::Number(satoshis as f64 / SATOSHIS_PER_BITCOIN)
+}
+
+/// Devuelve el detalle de una transaccion con los campos de decoderawtransaction de Bitcoin Core.
+fn transaction_details_json(details: &TransactionDetails) -> JsonValue {
+    let inputs = details
+        .inputs
+        .iter()
+        .map(|input| {
+            let mut fields = vec![
+                (
+                    "txid",
+                    JsonValue::String(input.previous_output.hash.to_string()),
+                ),
+                (
+                    "vout",
+                    JsonValue::Number(input.previous_output.index as f64),
+                ),
+                (
+                    "scriptSig",
+                    JsonValue::object(vec![(
+                        "hex",
+                        JsonValue::String(bytes_to_hex(&input.script_sig)),
+                    )]),
+                ),
+            ];
+            if !input.witness.is_empty() {
+                let witness = input
+                    .witness
+                    .iter()
+                    .map(|item| JsonValue::String(bytes_to_hex(item)))
+                    .collect();
+                fields.push(("txinwitness", JsonValue::Array(witness)));
+            }
+            fields.push(("sequence", JsonValue::Number(input.sequence as f64)));
+            JsonValue::object(fields)
+        })
+        .collect();
+    let outputs = details
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(n, output)| {
+            let mut script_pubkey = vec![
+                (
+                    "hex",
+                    JsonValue::String(bytes_to_hex(&output.script_pubkey)),
+                ),
+                (
+                    "type",
+                    JsonValue::String(output.output_type.name().to_string()),
+                ),
+            ];
+            if let Some(address) = &output.address {
+                script_pubkey.push(("address", JsonValue::String(address.clone())));
+            }
+            JsonValue::object(vec![
+                ("value", bitcoins(output.value as i64)),
+                ("n", JsonValue::Number(n as f64)),
+                ("scriptPubKey", JsonValue::object(script_pubkey)),
+            ])
+        })
+        .collect();
+    JsonValue::object(vec![
+        ("txid", JsonValue::String(details.txid.to_string())),
+        ("hash", JsonValue::String(details.wtxid.to_string())),
+        ("version", JsonValue::Number(details.version as f64)),
+        ("size", JsonValue::Number(details.size as f64)),
+        ("vsize", JsonValue::Number(details.vsize as f64)),
+        ("locktime", JsonValue::Number(details.lock_time as f64)),
+        ("vin", JsonValue::Array(inputs)),
+        ("vout", JsonValue::Array(outputs)),
+    ])
+}
+
+/// Parsea la transaccion en hexadecimal del primer parametro.
+fn raw_transaction_param(params: &[JsonValue]) -> Result<Transaction, RpcError> {
+    let Some(hex) = params.first().and_then(JsonValue::as_str) else { return Err(RpcError::new(INVALID_PARAMS, "Expected hexstring param")) };
+    hex_to_bytes(hex)
+        .and_then(|bytes| Transaction::parse(&mut bytes.as_slice()).ok())
+        .ok_or_else(|| RpcError::new(DESERIALIZATION_ERROR, "TX decode failed"))
+}
+
+/// Parsea un PSBT en base64.
+fn psbt_param(param: Option<&JsonValue>) -> Result<Psbt, RpcError> {
+    let Some(psbt) = param.and_then(JsonValue::as_str) else { return Err(RpcError::new(INVALID_PARAMS, "Expected psbt param")) };
+    psbt.parse::<Psbt>()
+        .map_err(|_| RpcError::new(DESERIALIZATION_ERROR, "TX decode failed"))
+}
+
+/// Parsea un outpoint a partir de un objeto con su txid y su vout.
+fn out_point_param(param: &JsonValue) -> Result<OutPoint, RpcError> {
+    let invalid = || RpcError::new(INVALID_PARAMS, "Expected txid and vout in inputs");
+    let hash = param
+        .get("txid")
+        .and_then(JsonValue::as_str)
+        .and_then(|txid| txid.parse::<Hash256>().ok())
+        .ok_or_else(invalid)?;
+    let index = param
+        .get("vout")
+        .and_then(JsonValue::as_f64)
+        .filter(|vout| vout.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(vout))
+        .ok_or_else(invalid)?;
+    Ok(OutPoint {
+        hash,
+        index: index as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_request() {
+        let request = RpcRequest::parse(
+            r#"{"jsonrpc":"2.0","id":"test","method":"sendtoaddress","params":["tb1q",0.1]}"#,
+        )
+        .unwrap();
+        assert_eq!(request.id, JsonValue::String(String::from("test")));
+        assert_eq!(request.method, "sendtoaddress");
+        assert_eq!(request.params.len(), 2);
+
+        let request = RpcRequest::parse(r#"{"id":1,"method":"getblockcount"}"#).unwrap();
+        assert!(request.params.is_empty());
+    }
+
+    #[test]
+    fn parse_invalid_request() {
+        assert_eq!(
+            RpcRequest::parse("{\"method\":").unwrap_err().code,
+            PARSE_ERROR
+        );
+        assert_eq!(
+            RpcRequest::parse(r#"{"id":1,"params":[]}"#)
+                .unwrap_err()
+                .code,
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            RpcRequest::parse(r#"{"id":1,"method":"getbalance","params":{}}"#)
+                .unwrap_err()
+                .code,
+            INVALID_REQUEST
+        );
+    }
+
+    #[test]
+    fn cookie_credentials_are_required() {
+        let data_dir = "tests/rpc_cookie";
+        let auth = RpcAuth::new(data_dir, None, None).unwrap();
+        let cookie = fs::read_to_string(auth.cookie_path().unwrap()).unwrap();
+        assert!(cookie.starts_with("__cookie__:"));
+        let header = format!("Basic {}", base64::encode(cookie.as_bytes()));
+        assert!(auth.is_authorized(Some(&header)));
+
+        // cada inicio genera una password nueva
+        let restarted = RpcAuth::new(data_dir, None, None).unwrap();
+        assert!(!restarted.is_authorized(Some(&header)));
+
+        assert!(!auth.is_authorized(None));
+        assert!(!auth.is_authorized(Some(&format!("Bearer {}", &header[6..]))));
+        let wrong = format!("{}0", cookie);
+        assert!(!auth.is_authorized(Some(&format!("Basic {}", base64::encode(wrong.as_bytes())))));
+
+        restarted.remove_cookie().unwrap();
+        assert!(!Path::new(data_dir).join(COOKIE_FILE).exists());
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn configured_credentials_replace_the_cookie() {
+        let auth = RpcAuth::new(
+            "tests/rpc_user",
+            Some(String::from("user")),
+            Some(String::from("secret")),
+        )
+        .unwrap();
+        assert!(auth.cookie_path().is_none());
+        assert!(!Path::new("tests/rpc_user").exists());
+        assert!(auth.is_authorized(Some(&format!("Basic {}", base64::encode(b"user:secret")))));
+        assert!(!auth.is_authorized(Some(&format!("Basic {}", base64::encode(b"user:secre")))));
+
+        assert!(RpcAuth::new("tests/rpc_user", Some(String::from("user")), None).is_err());
+    }
+
+    #[test]
+    fn serialize_response() {
+        assert_eq!(
+            response(JsonValue::Number(1.0), Ok(bitcoins(150_000))),
+            r#"{"result":0.0015,"error":null,"id":1}"#
+        );
+        assert_eq!(
+            response(
+                JsonValue::Null,
+                Err(RpcError::from(CustomError::Wallet(
+                    WalletError::WalletNotFound
+                )))
+            ),
+            r#"{"result":null,"error":{"code":-4,"message":"wallet not found"},"id":null}"#
+        );
+    }
+
+    #[test]
+    fn hex_params() {
+        assert_eq!(hex_to_bytes("00aBff"), Some(vec![0x00, 0xab, 0xff]));
+        assert_eq!(hex_to_bytes(&bytes_to_hex(&[1, 2, 3])), Some(vec![1, 2, 3]));
+        assert_eq!(hex_to_bytes("abc"), None);
+        assert_eq!(hex_to_bytes("+f"), None);
+        assert!(raw_transaction_param(&[JsonValue::String(String::from("0100"))]).is_err());
+        assert!(psbt_param(Some(&JsonValue::String(String::from("cHNidP8=")))).is_err());
+    }
+
+    #[test]
+    fn decoded_transaction_fields() {
+        let details = TransactionDetails::from(&Transaction {
+            version: 2,
+            inputs: vec![],
+            outputs: vec![],
+            lock_time: 0,
+        });
+        let decoded = transaction_details_json(&details);
+        assert_eq!(
+            decoded.get("txid"),
+            Some(&JsonValue::String(details.txid.to_string()))
+        );
+        assert_eq!(decoded.get("version"), Some(&JsonValue::Number(2.0)));
+        assert_eq!(decoded.get("vin"), Some(&JsonValue::Array(vec![])));
+        assert_eq!(decoded.get("vout"), Some(&JsonValue::Array(vec![])));
+    }
+
+    #[test]
+    fn out_point_params() {
+        let txid = "00".repeat(31) + "01";
+        let input = JsonValue::object(vec![
+            ("txid", JsonValue::String(txid)),
+            ("vout", JsonValue::Number(2.0)),
+        ]);
+        let out_point = out_point_param(&input).unwrap();
+        assert_eq!(out_point.hash.as_bytes()[0], 1);
+        assert_eq!(out_point.index, 2);
+
+        let input = JsonValue::object(vec![("vout", JsonValue::Number(2.0))]);
+        assert!(out_point_param(&input).is_err());
+    }
+}
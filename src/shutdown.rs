@@ -0,0 +1,158 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::CustomError,
+    logger::{send_log, Log, LogEntry},
+};
+
+/// Tiempo maximo que un loop espera sin revisar si se solicito el cierre del nodo.
+pub const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// ShutdownSignal indica a los loops del nodo que deben terminar.
+/// Se comparte entre los threads clonandola, todas las copias ven la misma solicitud de cierre.
+/// Los elementos son:
+/// - requested: Booleano compartido que indica si se solicito el cierre.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal {
+    requested: Arc<AtomicBool>,
+}
+
+impl ShutdownSignal {
+    /// Crea una señal sin cierre solicitado.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Solicita el cierre a todos los threads que comparten la señal.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Devuelve true si se solicito el cierre.
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Espera el tiempo recibido, o menos si se solicita el cierre mientras tanto.
+    /// Devuelve true si se solicito el cierre.
+    pub fn wait(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.is_requested() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::sleep(SHUTDOWN_POLL_INTERVAL.min(deadline - now));
+        }
+        true
+    }
+}
+
+/// ShutdownCoordinator registra los threads del nodo para detenerlos en orden al cerrarlo:
+/// primero les envia la señal de cierre y luego espera a que terminen, para que ninguno quede
+/// a mitad de la escritura de un archivo cuando se guarda el estado y termina el programa.
+/// Los elementos son:
+/// - signal: ShutdownSignal que reciben los threads registrados.
+/// - threads: Threads registrados con su nombre, para los logs.
+/// - logger_sender: Sender para enviar logs al logger.
+pub struct ShutdownCoordinator {
+    signal: ShutdownSignal,
+    threads: Vec<(String, JoinHandle<Result<(), CustomError>>)>,
+    logger_sender: mpsc::Sender<LogEntry>,
+}
+
+impl ShutdownCoordinator {
+    /// Crea el coordinador sin threads registrados.
+    pub fn new(logger_sender: mpsc::Sender<LogEntry>) -> Self {
+        Self {
+            signal: ShutdownSignal::new(),
+            threads: vec![],
+            logger_sender,
+        }
+    }
+
+    /// Devuelve la señal de cierre que deben revisar los threads registrados.
+    pub fn signal(&self) -> ShutdownSignal {
+        self.signal.clone()
+    }
+
+    /// Registra un thread para esperarlo al cerrar el nodo.
+    pub fn register(&mut self, name: &str, thread: JoinHandle<Result<(), CustomError>>) {
+        self.threads.push((name.to_string(), thread));
+    }
+
+    /// Envia la señal de cierre y espera a que terminen todos los threads registrados.
+    /// Los errores con los que terminaron los threads se registran en el log.
+    pub fn shutdown(&mut self) {
+        self.signal.request();
+        for (name, thread) in self.threads.drain(..) {
+            let error = match thread.join() {
+                Ok(Ok(())) => continue,
+                Ok(Err(error)) => error.to_string(),
+                Err(_) => String::from("thread panicked"),
+            };
+            send_log(
+                &self.logger_sender,
+                Log::Warning(format!("Error closing {}: {}", name, error)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn wait_returns_when_shutdown_is_requested() {
+        let signal = ShutdownSignal::new();
+        assert!(!signal.wait(Duration::from_millis(10)));
+
+        let other = signal.clone();
+        let start = Instant::now();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            other.request();
+        });
+        assert!(signal.wait(Duration::from_secs(30)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn shutdown_waits_for_registered_threads() {
+        let (logger_sender, logger_receiver) = mpsc::channel();
+        let mut coordinator = ShutdownCoordinator::new(logger_sender);
+        let finished = Arc::new(Mutex::new(vec![]));
+
+        for name in ["first", "second"] {
+            let signal = coordinator.signal();
+            let finished = finished.clone();
+            coordinator.register(
+                name,
+                thread::spawn(move || {
+                    while !signal.wait(Duration::from_secs(30)) {}
+                    finished.lock()?.push(name);
+                    match name {
+                        "first" => Ok(()),
+                        _ => Err(CustomError::CannotStartEventLoop),
+                    }
+                }),
+            );
+        }
+        coordinator.shutdown();
+
+        assert_eq!(*finished.lock().unwrap(), vec!["first", "second"]);
+        let logs: Vec<Log> = logger_receiver.try_iter().map(|entry| entry.log).collect();
+        assert_eq!(logs.len(), 1);
+        assert!(matches!(&logs[0], Log::Warning(log) if log.starts_with("Error closing second")));
+    }
+}
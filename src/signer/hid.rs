@@ -0,0 +1,155 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::PathBuf,
+};
+
+use crate::error::{CustomError, WalletError};
+
+/// Tamaño en bytes de los reportes HID que intercambian Ledger y Trezor.
+pub const HID_REPORT_SIZE: usize = 64;
+/// Directorio donde Linux expone los dispositivos hidraw.
+const HIDRAW_CLASS_PATH: &str = "/sys/class/hidraw";
+/// Directorio de los archivos de dispositivo de hidraw.
+const DEVICES_PATH: &str = "/dev";
+/// Id del reporte HID que se agrega antes de cada reporte escrito, los dispositivos no usan ids de reporte.
+const REPORT_ID: u8 = 0x00;
+
+/// Reporte HID de tamaño fijo.
+pub type HidReport = [u8; HID_REPORT_SIZE];
+
+/// HidDeviceInfo es un dispositivo HID conectado.
+/// Los elementos son:
+/// - path: Path del archivo de dispositivo hidraw.
+/// - vendor_id: Id USB del fabricante.
+/// - product_id: Id USB del producto.
+/// - interface: Numero de interfaz USB del dispositivo, las hardware wallets exponen varias.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HidDeviceInfo {
+    pub path: PathBuf,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub interface: u8,
+}
+
+impl HidDeviceInfo {
+    /// Devuelve los dispositivos HID conectados, leyendo los dispositivos hidraw que expone Linux.
+    /// En otros sistemas, o si no se puede leer el directorio, no devuelve ninguno.
+    pub fn enumerate() -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(HIDRAW_CLASS_PATH) else { return vec![] };
+        let mut devices: Vec<Self> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let uevent = fs::read_to_string(entry.path().join("device/uevent")).ok()?;
+                let path = PathBuf::from(DEVICES_PATH).join(entry.file_name());
+                Self::from_uevent(path, &uevent)
+            })
+            .collect();
+        devices.sort_by(|a, b| a.path.cmp(&b.path));
+        devices
+    }
+
+    /// Lee los ids y la interfaz del dispositivo del uevent de hidraw, con lineas como
+    /// "HID_ID=0003:00002C97:00001011" y "HID_PHYS=usb-0000:00:14.0-1/input0".
+    fn from_uevent(path: PathBuf, uevent: &str) -> Option<Self> {
+        let mut ids = None;
+        let mut interface = None;
+        for line in uevent.lines() {
+            match line.split_once('=') {
+                Some(("HID_ID", value)) => {
+                    let mut fields = value.split(':').skip(1);
+                    let vendor_id = u32::from_str_radix(fields.next()?, 16).ok()?;
+                    let product_id = u32::from_str_radix(fields.next()?, 16).ok()?;
+                    ids = Some((
+                        u16::try_from(vendor_id).ok()?,
+                        u16::try_from(product_id).ok()?,
+                    ));
+                }
+                Some(("HID_PHYS", value)) => {
+                    let (_, number) = value.rsplit_once("/input")?;
+                    interface = number.parse().ok();
+                }
+                _ => (),
+            }
+        }
+        let (vendor_id, product_id) = ids?;
+        Some(Self {
+            path,
+            vendor_id,
+            product_id,
+            interface: interface.unwrap_or(0),
+        })
+    }
+}
+
+/// HidDevice es un dispositivo HID abierto, al que se le escriben y del que se leen reportes.
+/// Los elementos son:
+/// - file: Archivo de dispositivo hidraw abierto para lectura y escritura.
+pub struct HidDevice {
+    file: File,
+}
+
+impl HidDevice {
+    /// Abre el dispositivo. Devuelve CustomError si no existe o si no hay permisos para abrirlo.
+    pub fn open(info: &HidDeviceInfo) -> Result<Self, CustomError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&info.path)
+            .map_err(|_| {
+                CustomError::Wallet(WalletError::HardwareWallet(format!(
+                    "cannot open {}, check the udev rules of the device",
+                    info.path.display()
+                )))
+            })?;
+        Ok(Self { file })
+    }
+
+    /// Escribe un reporte en el dispositivo.
+    pub fn write_report(&mut self, report: &HidReport) -> Result<(), CustomError> {
+        let mut buffer = vec![REPORT_ID];
+        buffer.extend(report);
+        self.file.write_all(&buffer).map_err(|_| {
+            CustomError::Wallet(WalletError::HardwareWallet(String::from(
+                "cannot write to the device",
+            )))
+        })
+    }
+
+    /// Lee un reporte del dispositivo, bloqueando hasta que envie uno.
+    pub fn read_report(&mut self) -> Result<HidReport, CustomError> {
+        let mut report = [0; HID_REPORT_SIZE];
+        self.file.read_exact(&mut report).map_err(|_| {
+            CustomError::Wallet(WalletError::HardwareWallet(String::from(
+                "cannot read from the device",
+            )))
+        })?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_info_from_uevent() {
+        let uevent = "DRIVER=hid-generic\nHID_ID=0003:00002C97:00001011\nHID_NAME=Ledger Nano S Plus\nHID_PHYS=usb-0000:00:14.0-1/input0\n";
+        let info = HidDeviceInfo::from_uevent(PathBuf::from("/dev/hidraw3"), uevent).unwrap();
+        assert_eq!(
+            info,
+            HidDeviceInfo {
+                path: PathBuf::from("/dev/hidraw3"),
+                vendor_id: 0x2c97,
+                product_id: 0x1011,
+                interface: 0,
+            }
+        );
+
+        let uevent = "HID_ID=0003:0000534C:00000001\nHID_PHYS=usb-0000:00:14.0-2/input1";
+        let info = HidDeviceInfo::from_uevent(PathBuf::from("/dev/hidraw4"), uevent).unwrap();
+        assert_eq!((info.vendor_id, info.interface), (0x534c, 1));
+
+        assert!(HidDeviceInfo::from_uevent(PathBuf::new(), "DRIVER=hid-generic").is_none());
+    }
+}
@@ -0,0 +1,421 @@
+use crate::{
+    error::{CustomError, ParseError, WalletError},
+    messages::transaction::{Transaction, SIGHASH_ALL},
+    parser::VarIntSerialize,
+    psbt::Psbt,
+    wallet::{get_p2pkh_script_pubkey, Wallet},
+};
+
+use super::{
+    add_hardware_signature, hardware_inputs, hardware_wallet_error,
+    hid::{HidDevice, HidDeviceInfo, HidReport, HID_REPORT_SIZE},
+    HardwareInput, Signer,
+};
+
+/// Id USB de Ledger.
+const LEDGER_VENDOR_ID: u16 = 0x2c97;
+/// Interfaz USB por la que la Ledger recibe APDUs, las demas son de FIDO U2F.
+const LEDGER_INTERFACE: u8 = 0;
+/// Canal y tag con los que se enmarcan los APDUs en los reportes HID.
+const CHANNEL: u16 = 0x0101;
+const TAG_APDU: u8 = 0x05;
+/// Tamaño del encabezado de cada reporte: canal, tag e indice de secuencia.
+const FRAME_HEADER_SIZE: usize = 5;
+
+/// Clase de los comandos de la app Bitcoin de Ledger.
+const CLA: u8 = 0xe0;
+const INS_GET_TRUSTED_INPUT: u8 = 0x42;
+const INS_HASH_INPUT_START: u8 = 0x44;
+const INS_HASH_SIGN: u8 = 0x48;
+const INS_HASH_INPUT_FINALIZE_FULL: u8 = 0x4a;
+/// P1 del primer APDU de un comando y de los siguientes.
+const P1_FIRST: u8 = 0x00;
+const P1_NEXT: u8 = 0x80;
+/// P1 de los APDUs con los outputs, indica si quedan mas.
+const P1_MORE: u8 = 0x00;
+const P1_LAST: u8 = 0x80;
+/// P2 de INS_HASH_INPUT_START: transaccion nueva, transaccion SegWit nueva (BIP 143) o continuacion de la anterior.
+const P2_NEW_TX: u8 = 0x00;
+const P2_NEW_SEGWIT_TX: u8 = 0x02;
+const P2_CONTINUE_TX: u8 = 0x80;
+/// Prefijo de los inputs enviados como trusted inputs, verificados por la Ledger con la transaccion previa.
+const TRUSTED_INPUT_PREFIX: u8 = 0x01;
+/// Tamaño maximo de cada bloque de script enviado en un APDU.
+const SCRIPT_BLOCK_SIZE: usize = 50;
+/// Primer byte de una firma DER, la Ledger lo usa para indicar la paridad de R.
+const DER_SEQUENCE: u8 = 0x30;
+
+const SW_OK: u16 = 0x9000;
+const SW_DENIED: u16 = 0x6985;
+const SW_LOCKED: u16 = 0x5515;
+const SW_INS_NOT_SUPPORTED: u16 = 0x6d00;
+const SW_CLA_NOT_SUPPORTED: u16 = 0x6e00;
+
+/// LedgerSigner firma con una Ledger conectada por USB, con los comandos de firma de la app Bitcoin.
+/// Cada input se envia como trusted input junto a la transaccion que gasta, para que la Ledger verifique su valor,
+/// y el usuario confirma los outputs y el fee en el dispositivo.
+/// Los elementos son:
+/// - device: Dispositivo HID de la Ledger.
+pub struct LedgerSigner {
+    device: HidDevice,
+}
+
+impl LedgerSigner {
+    /// Devuelve true si el dispositivo es la interfaz de una Ledger que recibe APDUs.
+    pub fn is_ledger(info: &HidDeviceInfo) -> bool {
+        info.vendor_id == LEDGER_VENDOR_ID && info.interface == LEDGER_INTERFACE
+    }
+
+    /// Abre la Ledger. Devuelve CustomError si no se pudo abrir el dispositivo.
+    pub fn open(info: &HidDeviceInfo) -> Result<Self, CustomError> {
+        Ok(Self {
+            device: HidDevice::open(info)?,
+        })
+    }
+
+    /// Envia un APDU a la Ledger y devuelve los datos de su respuesta.
+    /// Devuelve CustomError si la respuesta no es valida o si su status word indica un error.
+    fn exchange(&mut self, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<Vec<u8>, CustomError> {
+        let mut apdu = vec![CLA, ins, p1, p2, data.len() as u8];
+        apdu.extend(data);
+        for report in frame_apdu(&apdu) {
+            self.device.write_report(&report)?;
+        }
+
+        let mut reports = vec![];
+        let mut response = loop {
+            reports.push(self.device.read_report()?);
+            if let Some(response) = unframe_response(&reports)? {
+                break response;
+            }
+        };
+        if response.len() < 2 {
+            return Err(hardware_wallet_error("invalid response from the device"));
+        }
+        let status = response.split_off(response.len() - 2);
+        match u16::from_be_bytes([status[0], status[1]]) {
+            SW_OK => Ok(response),
+            SW_DENIED => Err(hardware_wallet_error("transaction rejected on the device")),
+            SW_LOCKED => Err(hardware_wallet_error("unlock the device")),
+            SW_INS_NOT_SUPPORTED | SW_CLA_NOT_SUPPORTED => {
+                Err(hardware_wallet_error("open the Bitcoin app on the device"))
+            }
+            status => Err(CustomError::Wallet(WalletError::HardwareWallet(format!(
+                "device returned status {:04x}",
+                status
+            )))),
+        }
+    }
+
+    /// Envia un script en bloques, con el sequence al final del ultimo bloque.
+    fn send_script(
+        &mut self,
+        ins: u8,
+        p2: u8,
+        script: &[u8],
+        sequence: u32,
+    ) -> Result<Vec<u8>, CustomError> {
+        let mut response = vec![];
+        for block in script_blocks(script, sequence) {
+            response = self.exchange(ins, P1_NEXT, p2, &block)?;
+        }
+        Ok(response)
+    }
+
+    /// Envia la transaccion que crea el output que gasta un input y devuelve el trusted input
+    /// que genera la Ledger, con el outpoint y el valor del output firmados por el dispositivo.
+    fn get_trusted_input(
+        &mut self,
+        previous_tx: &Transaction,
+        index: u32,
+    ) -> Result<Vec<u8>, CustomError> {
+        let mut data = index.to_be_bytes().to_vec();
+        data.extend(previous_tx.version.to_le_bytes());
+        data.extend(previous_tx.inputs.len().to_varint_bytes());
+        self.exchange(INS_GET_TRUSTED_INPUT, P1_FIRST, 0, &data)?;
+
+        for input in &previous_tx.inputs {
+            let mut data = input.previous_output.serialize();
+            data.extend(input.script_sig.len().to_varint_bytes());
+            self.exchange(INS_GET_TRUSTED_INPUT, P1_NEXT, 0, &data)?;
+            self.send_script(INS_GET_TRUSTED_INPUT, 0, &input.script_sig, input.sequence)?;
+        }
+        let outputs_count = previous_tx.outputs.len().to_varint_bytes();
+        self.exchange(INS_GET_TRUSTED_INPUT, P1_NEXT, 0, &outputs_count)?;
+        for output in &previous_tx.outputs {
+            for chunk in output.serialize().chunks(u8::MAX as usize) {
+                self.exchange(INS_GET_TRUSTED_INPUT, P1_NEXT, 0, chunk)?;
+            }
+        }
+        self.exchange(
+            INS_GET_TRUSTED_INPUT,
+            P1_NEXT,
+            0,
+            &previous_tx.lock_time.to_le_bytes(),
+        )
+    }
+
+    /// Inicia el hash de la transaccion a firmar enviando sus inputs, cada uno con su trusted input
+    /// y el script recibido, que es el script del output que gasta para el input que se firma y vacio para los demas.
+    fn start_hash(
+        &mut self,
+        transaction: &Transaction,
+        inputs: &[(usize, &[u8], &[u8])],
+        p2: u8,
+    ) -> Result<(), CustomError> {
+        let mut data = transaction.version.to_le_bytes().to_vec();
+        data.extend(inputs.len().to_varint_bytes());
+        self.exchange(INS_HASH_INPUT_START, P1_FIRST, p2, &data)?;
+
+        for (index, trusted_input, script) in inputs {
+            let mut data = vec![TRUSTED_INPUT_PREFIX, trusted_input.len() as u8];
+            data.extend(*trusted_input);
+            data.extend(script.len().to_varint_bytes());
+            self.exchange(INS_HASH_INPUT_START, P1_NEXT, p2, &data)?;
+            let sequence = transaction.inputs[*index].sequence;
+            self.send_script(INS_HASH_INPUT_START, p2, script, sequence)?;
+        }
+        Ok(())
+    }
+
+    /// Envia los outputs de la transaccion, que el usuario confirma en el dispositivo.
+    fn hash_outputs(&mut self, transaction: &Transaction) -> Result<(), CustomError> {
+        let mut data = transaction.outputs.len().to_varint_bytes();
+        for output in &transaction.outputs {
+            data.extend(output.serialize());
+        }
+        let blocks = data.chunks(SCRIPT_BLOCK_SIZE).count();
+        for (index, block) in data.chunks(SCRIPT_BLOCK_SIZE).enumerate() {
+            let p1 = match index + 1 == blocks {
+                true => P1_LAST,
+                false => P1_MORE,
+            };
+            self.exchange(INS_HASH_INPUT_FINALIZE_FULL, p1, 0, block)?;
+        }
+        Ok(())
+    }
+
+    /// Firma el hash de la transaccion con la clave del path recibido.
+    /// Devuelve la firma DER con el sighash type al final.
+    fn sign_hash(&mut self, path: &[u32], lock_time: u32) -> Result<Vec<u8>, CustomError> {
+        let mut data = serialize_path(path);
+        // largo del codigo de validacion del usuario, que no se usa
+        data.push(0);
+        data.extend(lock_time.to_be_bytes());
+        data.push(SIGHASH_ALL as u8);
+        let mut signature = self.exchange(INS_HASH_SIGN, 0, 0, &data)?;
+        match signature.first_mut() {
+            Some(first) => *first = DER_SEQUENCE,
+            None => return Err(hardware_wallet_error("invalid signature from the device")),
+        }
+        Ok(signature)
+    }
+
+    /// Firma los inputs P2PKH: por cada input se vuelve a enviar la transaccion con el script del output
+    /// que gasta ese input, como en la firma previa a SegWit.
+    fn sign_legacy_inputs(
+        &mut self,
+        transaction: &Transaction,
+        inputs: &[HardwareInput],
+        trusted_inputs: &[Vec<u8>],
+    ) -> Result<Vec<Vec<u8>>, CustomError> {
+        let mut signatures = vec![];
+        for (index, input) in inputs.iter().enumerate() {
+            let hash_inputs: Vec<(usize, &[u8], &[u8])> = trusted_inputs
+                .iter()
+                .enumerate()
+                .map(|(other_index, trusted_input)| {
+                    let script = match other_index == index {
+                        true => input.previous_output.script_pubkey.as_slice(),
+                        false => &[],
+                    };
+                    (other_index, trusted_input.as_slice(), script)
+                })
+                .collect();
+            let p2 = match index {
+                0 => P2_NEW_TX,
+                _ => P2_CONTINUE_TX,
+            };
+            self.start_hash(transaction, &hash_inputs, p2)?;
+            self.hash_outputs(transaction)?;
+            signatures.push(self.sign_hash(&input.key_origin.path, transaction.lock_time)?);
+        }
+        Ok(signatures)
+    }
+
+    /// Firma los inputs P2WPKH (BIP 143): se envian una vez todos los inputs y los outputs,
+    /// y luego cada input con su script code para firmarlo.
+    fn sign_segwit_inputs(
+        &mut self,
+        transaction: &Transaction,
+        inputs: &[HardwareInput],
+        trusted_inputs: &[Vec<u8>],
+    ) -> Result<Vec<Vec<u8>>, CustomError> {
+        let hash_inputs: Vec<(usize, &[u8], &[u8])> = trusted_inputs
+            .iter()
+            .enumerate()
+            .map(|(index, trusted_input)| (index, trusted_input.as_slice(), &[] as &[u8]))
+            .collect();
+        self.start_hash(transaction, &hash_inputs, P2_NEW_SEGWIT_TX)?;
+        self.hash_outputs(transaction)?;
+
+        let mut signatures = vec![];
+        for (index, input) in inputs.iter().enumerate() {
+            let Some(pubkey_hash) = input.previous_output.script_pubkey.get(2..) else { return Err(CustomError::Parse(ParseError::InvalidPsbt)) };
+            let script_code = get_p2pkh_script_pubkey(pubkey_hash);
+            self.start_hash(
+                transaction,
+                &[(
+                    index,
+                    trusted_inputs[index].as_slice(),
+                    script_code.as_slice(),
+                )],
+                P2_CONTINUE_TX,
+            )?;
+            signatures.push(self.sign_hash(&input.key_origin.path, transaction.lock_time)?);
+        }
+        Ok(signatures)
+    }
+}
+
+impl Signer for LedgerSigner {
+    fn name(&self) -> String {
+        String::from("Ledger")
+    }
+
+    fn sign_psbt(&mut self, psbt: &mut Psbt, wallet: &Wallet) -> Result<usize, CustomError> {
+        let inputs = hardware_inputs(psbt, wallet)?;
+        let segwit = inputs.iter().any(|input| input.segwit);
+        if inputs.iter().any(|input| input.segwit != segwit) {
+            return Err(hardware_wallet_error(
+                "P2PKH and P2WPKH inputs cannot be signed in the same transaction",
+            ));
+        }
+
+        let transaction = psbt.unsigned_tx.clone();
+        let mut trusted_inputs = vec![];
+        for (tx_input, input) in transaction.inputs.iter().zip(&inputs) {
+            trusted_inputs
+                .push(self.get_trusted_input(&input.previous_tx, tx_input.previous_output.index)?);
+        }
+        let signatures = match segwit {
+            true => self.sign_segwit_inputs(&transaction, &inputs, &trusted_inputs)?,
+            false => self.sign_legacy_inputs(&transaction, &inputs, &trusted_inputs)?,
+        };
+        for (index, (input, signature)) in inputs.iter().zip(signatures).enumerate() {
+            add_hardware_signature(psbt, index, input, signature)?;
+        }
+        Ok(inputs.len())
+    }
+}
+
+/// Enmarca un APDU en reportes HID: cada reporte empieza con el canal, el tag y su indice de secuencia,
+/// y el primero ademas con el largo del APDU.
+fn frame_apdu(apdu: &[u8]) -> Vec<HidReport> {
+    let mut data = (apdu.len() as u16).to_be_bytes().to_vec();
+    data.extend(apdu);
+    data.chunks(HID_REPORT_SIZE - FRAME_HEADER_SIZE)
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut report = [0; HID_REPORT_SIZE];
+            report[0..2].copy_from_slice(&CHANNEL.to_be_bytes());
+            report[2] = TAG_APDU;
+            report[3..5].copy_from_slice(&(sequence as u16).to_be_bytes());
+            report[FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + chunk.len()].copy_from_slice(chunk);
+            report
+        })
+        .collect()
+}
+
+/// Devuelve la respuesta enmarcada en los reportes recibidos, o None si todavia faltan reportes.
+/// Devuelve CustomError si algun reporte no tiene el canal, el tag o el indice de secuencia esperado.
+fn unframe_response(reports: &[HidReport]) -> Result<Option<Vec<u8>>, CustomError> {
+    let mut data = vec![];
+    for (sequence, report) in reports.iter().enumerate() {
+        if report[0..2] != CHANNEL.to_be_bytes()
+            || report[2] != TAG_APDU
+            || report[3..5] != (sequence as u16).to_be_bytes()
+        {
+            return Err(hardware_wallet_error("invalid response from the device"));
+        }
+        data.extend(&report[FRAME_HEADER_SIZE..]);
+    }
+    if data.len() < 2 {
+        return Ok(None);
+    }
+    let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+    match data.len() - 2 >= length {
+        true => Ok(Some(data[2..2 + length].to_vec())),
+        false => Ok(None),
+    }
+}
+
+/// Divide un script en los bloques que se envian en cada APDU, agregando el sequence al final del ultimo.
+fn script_blocks(script: &[u8], sequence: u32) -> Vec<Vec<u8>> {
+    let mut blocks: Vec<Vec<u8>> = script
+        .chunks(SCRIPT_BLOCK_SIZE)
+        .map(|block| block.to_vec())
+        .collect();
+    if blocks.is_empty() {
+        blocks.push(vec![]);
+    }
+    if let Some(last) = blocks.last_mut() {
+        last.extend(sequence.to_le_bytes());
+    }
+    blocks
+}
+
+/// Serializa un path de derivacion: la cantidad de indices y cada indice en big endian.
+fn serialize_path(path: &[u32]) -> Vec<u8> {
+    let mut buffer = vec![path.len() as u8];
+    for index in path {
+        buffer.extend(index.to_be_bytes());
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apdu_framing_round_trip() {
+        let apdu: Vec<u8> = (0..150).collect();
+        let reports = frame_apdu(&apdu);
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0][0..7], [0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 150]);
+        assert_eq!(reports[2][0..5], [0x01, 0x01, 0x05, 0x00, 0x02]);
+
+        assert_eq!(unframe_response(&reports[0..2]).unwrap(), None);
+        assert_eq!(unframe_response(&reports).unwrap(), Some(apdu));
+    }
+
+    #[test]
+    fn unexpected_frames_are_rejected() {
+        let reports = frame_apdu(&[0x90, 0x00]);
+        let mut wrong_channel = reports[0];
+        wrong_channel[1] = 0x02;
+        assert!(unframe_response(&[wrong_channel]).is_err());
+        assert!(unframe_response(&[reports[0], reports[0]]).is_err());
+    }
+
+    #[test]
+    fn scripts_are_split_in_blocks() {
+        let script = vec![7; 120];
+        let blocks = script_blocks(&script, 0xfffffffd);
+        assert_eq!(
+            blocks.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![50, 50, 24]
+        );
+        assert_eq!(blocks[2][20..], [0xfd, 0xff, 0xff, 0xff]);
+        assert_eq!(script_blocks(&[], 1), vec![vec![1, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn derivation_path_serialization() {
+        assert_eq!(
+            serialize_path(&[0x8000002c, 1]),
+            vec![2, 0x80, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x01]
+        );
+    }
+}
@@ -0,0 +1,261 @@
+pub mod hid;
+pub mod ledger;
+pub mod trezor;
+
+use crate::{
+    error::{CustomError, ParseError, WalletError},
+    messages::transaction::{find_signing_key, Transaction, SIGHASH_ALL},
+    psbt::Psbt,
+    structs::{script::classify::ScriptType, tx_output::TransactionOutput},
+    wallet::{KeyOrigin, Wallet},
+};
+
+use self::{hid::HidDeviceInfo, ledger::LedgerSigner, trezor::TrezorSigner};
+
+/// Funcion que le pide al usuario el PIN de la hardware wallet, ingresado segun la matriz que muestra el dispositivo.
+/// Devuelve None si el usuario cancela el ingreso.
+pub type PinRequest = Box<dyn FnMut() -> Option<String>>;
+
+/// Signer firma los inputs de un PSBT que gastan outputs de una wallet.
+/// Permite construir transacciones firmando con las claves guardadas en la wallet o fuera de ella,
+/// por ejemplo en una hardware wallet, sin que la wallet tenga sus private keys.
+pub trait Signer {
+    /// Devuelve el nombre con el que se muestra el signer.
+    fn name(&self) -> String;
+
+    /// Firma los inputs del PSBT que gastan outputs de la wallet y agrega las firmas a sus firmas parciales.
+    /// Devuelve la cantidad de inputs firmados.
+    fn sign_psbt(&mut self, psbt: &mut Psbt, wallet: &Wallet) -> Result<usize, CustomError>;
+}
+
+/// WalletSigner firma con las private keys guardadas en la wallet.
+pub struct WalletSigner;
+
+impl Signer for WalletSigner {
+    fn name(&self) -> String {
+        String::from("Wallet")
+    }
+
+    fn sign_psbt(&mut self, psbt: &mut Psbt, wallet: &Wallet) -> Result<usize, CustomError> {
+        psbt.sign(wallet)
+    }
+}
+
+/// Firma el PSBT con el signer, lo finaliza y devuelve la transaccion firmada, lista para enviar.
+/// Devuelve CustomError si no se pudo firmar o si algun input quedo sin las firmas que necesita.
+pub fn sign_transaction(
+    signer: &mut dyn Signer,
+    mut psbt: Psbt,
+    wallet: &Wallet,
+) -> Result<Transaction, CustomError> {
+    signer.sign_psbt(&mut psbt, wallet)?;
+    psbt.finalize();
+    psbt.extract()
+}
+
+/// Busca una hardware wallet conectada por USB, una Ledger o una Trezor, y devuelve su signer.
+/// request_pin se usa si la Trezor esta bloqueada y pide su PIN antes de firmar.
+/// Devuelve CustomError si no hay ninguna conectada o si no se pudo abrir.
+pub fn connect_hardware_wallet(request_pin: PinRequest) -> Result<Box<dyn Signer>, CustomError> {
+    for device in HidDeviceInfo::enumerate() {
+        if LedgerSigner::is_ledger(&device) {
+            return Ok(Box::new(LedgerSigner::open(&device)?));
+        }
+        if TrezorSigner::is_trezor(&device) {
+            return Ok(Box::new(TrezorSigner::open(&device, request_pin)?));
+        }
+    }
+    Err(CustomError::Wallet(WalletError::HardwareWalletNotFound))
+}
+
+/// HardwareInput es un input del PSBT que firma una hardware wallet.
+/// Los elementos son:
+/// - previous_output: Output que gasta el input.
+/// - previous_tx: Transaccion completa que crea el output, que las hardware wallets verifican para conocer su valor.
+/// - key_origin: Path de derivacion y public key de la clave que firma el input.
+/// - segwit: Indica si el output es P2WPKH, o P2PKH en caso contrario.
+struct HardwareInput {
+    previous_output: TransactionOutput,
+    previous_tx: Transaction,
+    key_origin: KeyOrigin,
+    segwit: bool,
+}
+
+/// Devuelve los datos de cada input del PSBT necesarios para firmarlo en una hardware wallet.
+/// Devuelve CustomError si algun input ya esta finalizado o firmado con otro sighash type, si no gasta un output
+/// P2PKH o P2WPKH de la wallet, o si el PSBT no tiene la transaccion que crea el output.
+fn hardware_inputs(psbt: &Psbt, wallet: &Wallet) -> Result<Vec<HardwareInput>, CustomError> {
+    let mut inputs = vec![];
+    for (index, input) in psbt.inputs.iter().enumerate() {
+        if input.is_finalized()
+            || input
+                .sighash_type
+                .is_some_and(|sighash_type| sighash_type != SIGHASH_ALL)
+        {
+            return Err(hardware_wallet_error(
+                "inputs must be unsigned and use SIGHASH_ALL",
+            ));
+        }
+        let Some(previous_output) = psbt.previous_output(index) else { return Err(CustomError::Parse(ParseError::InvalidPsbt)) };
+        let segwit = match ScriptType::classify(&previous_output.script_pubkey) {
+            ScriptType::P2PKH(_) => false,
+            ScriptType::P2WPKH(_) => true,
+            _ => {
+                return Err(hardware_wallet_error(
+                    "only P2PKH and P2WPKH inputs can be signed",
+                ))
+            }
+        };
+        let Some(key_origin) = wallet.get_key_origin(&previous_output.script_pubkey)? else {
+            return Err(hardware_wallet_error(
+                "all inputs must spend outputs of the wallet",
+            ));
+        };
+        let Some(previous_tx) = input.non_witness_utxo.clone() else {
+            return Err(hardware_wallet_error(
+                "the previous transaction of every input is required",
+            ));
+        };
+        inputs.push(HardwareInput {
+            previous_output,
+            previous_tx,
+            key_origin,
+            segwit,
+        });
+    }
+    Ok(inputs)
+}
+
+/// Agrega al PSBT la firma de un input devuelta por la hardware wallet, con el sighash type al final.
+/// Devuelve CustomError si la firma no es valida para la public key de la wallet, por ejemplo si la
+/// hardware wallet no tiene la cuenta de la que se importo la wallet.
+fn add_hardware_signature(
+    psbt: &mut Psbt,
+    index: usize,
+    input: &HardwareInput,
+    signature: Vec<u8>,
+) -> Result<(), CustomError> {
+    let sighash = psbt
+        .unsigned_tx
+        .input_sighash(index, &input.previous_output, None)?;
+    let public_key = &input.key_origin.public_key;
+    if find_signing_key(&sighash, &signature, std::slice::from_ref(public_key)).is_none() {
+        return Err(hardware_wallet_error(
+            "the device signed with a key that does not belong to the wallet",
+        ));
+    }
+    let partial_sigs = &mut psbt.inputs[index].partial_sigs;
+    partial_sigs.retain(|(key, _)| key != public_key);
+    partial_sigs.push((public_key.clone(), signature));
+    Ok(())
+}
+
+fn hardware_wallet_error(message: &str) -> CustomError {
+    CustomError::Wallet(WalletError::HardwareWallet(String::from(message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        states::utxo_state::UTXO,
+        structs::{hash256::Hash256, outpoint::OutPoint, tx_input::TransactionInput},
+        wallet::{get_p2pkh_script_pubkey, get_pubkey_hash},
+    };
+
+    use super::*;
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn hd_wallet() -> Wallet {
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        Wallet::from_mnemonic(String::from("hd"), MNEMONIC, "", &utxo_set).unwrap()
+    }
+
+    /// Devuelve un PSBT que gasta el primer output de recepcion de la wallet, con su transaccion completa.
+    fn psbt(wallet: &Wallet) -> Psbt {
+        let address = wallet.hd_account.as_ref().unwrap().receive_addresses[0].clone();
+        let previous_output = TransactionOutput {
+            value: 10000,
+            script_pubkey: get_p2pkh_script_pubkey(&get_pubkey_hash(address).unwrap()),
+        };
+        let previous_tx = Transaction {
+            version: 2,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: Hash256::new([1; 32]),
+                    index: 0,
+                },
+                script_sig: vec![],
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            outputs: vec![previous_output.clone()],
+            lock_time: 0,
+        };
+        let unsigned_tx = Transaction {
+            version: 2,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: previous_tx.hash(),
+                    index: 0,
+                },
+                script_sig: vec![],
+                sequence: 0xfffffffd,
+                witness: vec![],
+            }],
+            outputs: vec![TransactionOutput {
+                value: 9000,
+                script_pubkey: previous_output.script_pubkey.clone(),
+            }],
+            lock_time: 0,
+        };
+        let mut psbt = Psbt::new(unsigned_tx, vec![previous_output]).unwrap();
+        psbt.add_previous_tx(0, previous_tx).unwrap();
+        psbt
+    }
+
+    #[test]
+    fn wallet_signer_signs_transaction() {
+        let wallet = hd_wallet();
+        let transaction = sign_transaction(&mut WalletSigner, psbt(&wallet), &wallet).unwrap();
+        assert!(!transaction.inputs[0].script_sig.is_empty());
+    }
+
+    #[test]
+    fn hardware_inputs_require_wallet_keys_and_previous_txs() {
+        let wallet = hd_wallet();
+        let psbt = psbt(&wallet);
+        let inputs = hardware_inputs(&psbt, &wallet).unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert!(!inputs[0].segwit);
+        assert_eq!(inputs[0].key_origin.path.len(), 5);
+
+        let mut without_previous_tx = psbt.clone();
+        without_previous_tx.inputs[0].non_witness_utxo = None;
+        without_previous_tx.inputs[0].witness_utxo = psbt.previous_output(0);
+        assert!(hardware_inputs(&without_previous_tx, &wallet).is_err());
+
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        let other_wallet =
+            Wallet::from_mnemonic(String::from("other"), MNEMONIC, "passphrase", &utxo_set)
+                .unwrap();
+        assert!(hardware_inputs(&psbt, &other_wallet).is_err());
+    }
+
+    #[test]
+    fn hardware_signature_must_match_wallet_key() {
+        let wallet = hd_wallet();
+        let mut psbt = psbt(&wallet);
+        let inputs = hardware_inputs(&psbt, &wallet).unwrap();
+
+        let mut signed = psbt.clone();
+        signed.sign(&wallet).unwrap();
+        let (_, signature) = signed.inputs[0].partial_sigs[0].clone();
+        add_hardware_signature(&mut psbt, 0, &inputs[0], signature.clone()).unwrap();
+        assert_eq!(psbt.inputs[0].partial_sigs, signed.inputs[0].partial_sigs);
+
+        let mut other_input = psbt.clone();
+        other_input.unsigned_tx.outputs[0].value = 8000;
+        assert!(add_hardware_signature(&mut other_input, 0, &inputs[0], signature).is_err());
+    }
+}
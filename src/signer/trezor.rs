@@ -0,0 +1,575 @@
+use crate::{
+    error::{CustomError, WalletError},
+    messages::transaction::{Transaction, SIGHASH_ALL},
+    network::Network,
+    psbt::Psbt,
+    structs::hash256::Hash256,
+    wallet::{get_address, Wallet},
+};
+
+use super::{
+    add_hardware_signature, hardware_inputs, hardware_wallet_error,
+    hid::{HidDevice, HidDeviceInfo, HidReport, HID_REPORT_SIZE},
+    HardwareInput, PinRequest, Signer,
+};
+
+/// Ids USB de la Trezor One, que se comunica por HID.
+const TREZOR_VENDOR_ID: u16 = 0x534c;
+const TREZOR_ONE_PRODUCT_ID: u16 = 0x0001;
+/// Interfaz USB por la que la Trezor recibe mensajes, las demas son de debug y FIDO U2F.
+const TREZOR_INTERFACE: u8 = 0;
+/// Byte con el que empieza cada reporte, y bytes con los que empieza el encabezado de cada mensaje.
+const REPORT_MAGIC: u8 = b'?';
+const HEADER_MAGIC: [u8; 2] = *b"##";
+/// Tamaño del encabezado del primer reporte de un mensaje: bytes magicos, tipo y largo.
+const HEADER_SIZE: usize = 9;
+
+const MSG_INITIALIZE: u16 = 0;
+const MSG_FAILURE: u16 = 3;
+const MSG_SIGN_TX: u16 = 15;
+const MSG_FEATURES: u16 = 17;
+const MSG_PIN_MATRIX_REQUEST: u16 = 18;
+const MSG_PIN_MATRIX_ACK: u16 = 19;
+const MSG_CANCEL: u16 = 20;
+const MSG_TX_REQUEST: u16 = 21;
+const MSG_TX_ACK: u16 = 22;
+const MSG_BUTTON_REQUEST: u16 = 26;
+const MSG_BUTTON_ACK: u16 = 27;
+const MSG_PASSPHRASE_REQUEST: u16 = 41;
+
+/// Tipos de pedido de TxRequest: un input, un output, los datos de una transaccion previa o el fin de la firma.
+const TX_INPUT: u64 = 0;
+const TX_OUTPUT: u64 = 1;
+const TX_META: u64 = 2;
+const TX_FINISHED: u64 = 3;
+/// Tipos de script de los inputs y outputs.
+const SPEND_ADDRESS: u64 = 0;
+const SPEND_WITNESS: u64 = 3;
+const PAY_TO_ADDRESS: u64 = 0;
+
+/// Tipos de campo de protobuf que se usan: enteros varint y bytes con largo.
+/// Los campos fijos de 32 y 64 bits se leen para saltearlos.
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LENGTH_DELIMITED: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// TrezorSigner firma con una Trezor One conectada por USB, siguiendo el protocolo de firma de Trezor:
+/// la Trezor pide de a uno los inputs, los outputs y las transacciones previas, y devuelve las firmas
+/// luego de que el usuario confirma los outputs y el fee en el dispositivo.
+/// Los elementos son:
+/// - device: Dispositivo HID de la Trezor.
+/// - request_pin: Pide al usuario el PIN cuando la Trezor esta bloqueada.
+pub struct TrezorSigner {
+    device: HidDevice,
+    request_pin: PinRequest,
+}
+
+impl TrezorSigner {
+    /// Devuelve true si el dispositivo es la interfaz de una Trezor One que recibe mensajes.
+    pub fn is_trezor(info: &HidDeviceInfo) -> bool {
+        info.vendor_id == TREZOR_VENDOR_ID
+            && info.product_id == TREZOR_ONE_PRODUCT_ID
+            && info.interface == TREZOR_INTERFACE
+    }
+
+    /// Abre la Trezor. Devuelve CustomError si no se pudo abrir el dispositivo.
+    pub fn open(info: &HidDeviceInfo, request_pin: PinRequest) -> Result<Self, CustomError> {
+        Ok(Self {
+            device: HidDevice::open(info)?,
+            request_pin,
+        })
+    }
+
+    fn write_message(
+        &mut self,
+        message_type: u16,
+        message: &ProtoMessage,
+    ) -> Result<(), CustomError> {
+        for report in frame_message(message_type, &message.serialize()) {
+            self.device.write_report(&report)?;
+        }
+        Ok(())
+    }
+
+    fn read_message(&mut self) -> Result<(u16, ProtoMessage), CustomError> {
+        let mut reports = vec![];
+        loop {
+            reports.push(self.device.read_report()?);
+            if let Some((message_type, payload)) = unframe_message(&reports)? {
+                return Ok((message_type, ProtoMessage::parse(&payload)?));
+            }
+        }
+    }
+
+    /// Envia un mensaje y devuelve la respuesta de la Trezor, confirmando los pedidos de boton
+    /// mientras el usuario confirma en el dispositivo. Si la Trezor pide el PIN, se lo pide al usuario.
+    /// Devuelve CustomError si la Trezor responde con un error, el usuario cancela el PIN o se pide la passphrase.
+    fn call(
+        &mut self,
+        message_type: u16,
+        message: &ProtoMessage,
+    ) -> Result<(u16, ProtoMessage), CustomError> {
+        self.write_message(message_type, message)?;
+        loop {
+            let (response_type, response) = self.read_message()?;
+            match response_type {
+                MSG_BUTTON_REQUEST => {
+                    self.write_message(MSG_BUTTON_ACK, &ProtoMessage::default())?
+                }
+                MSG_FAILURE => {
+                    let message = response
+                        .get_bytes(2)
+                        .map(|message| String::from_utf8_lossy(message).to_string())
+                        .unwrap_or_default();
+                    return Err(CustomError::Wallet(WalletError::HardwareWallet(message)));
+                }
+                MSG_PIN_MATRIX_REQUEST => match (self.request_pin)() {
+                    Some(pin) if is_pin_matrix_entry(&pin) => self.write_message(
+                        MSG_PIN_MATRIX_ACK,
+                        &ProtoMessage::default().bytes(1, pin.as_bytes()),
+                    )?,
+                    _ => {
+                        self.write_message(MSG_CANCEL, &ProtoMessage::default())?;
+                        return Err(hardware_wallet_error("the PIN was not entered"));
+                    }
+                },
+                MSG_PASSPHRASE_REQUEST => {
+                    self.write_message(MSG_CANCEL, &ProtoMessage::default())?;
+                    return Err(hardware_wallet_error(
+                        "disable the passphrase of the device before signing",
+                    ));
+                }
+                _ => return Ok((response_type, response)),
+            }
+        }
+    }
+}
+
+impl Signer for TrezorSigner {
+    fn name(&self) -> String {
+        String::from("Trezor")
+    }
+
+    fn sign_psbt(&mut self, psbt: &mut Psbt, wallet: &Wallet) -> Result<usize, CustomError> {
+        let inputs = hardware_inputs(psbt, wallet)?;
+        let transaction = psbt.unsigned_tx.clone();
+        let mut addresses = vec![];
+        for output in &transaction.outputs {
+            addresses.push(get_address(&output.script_pubkey)?);
+        }
+
+        let (response_type, _) = self.call(MSG_INITIALIZE, &ProtoMessage::default())?;
+        if response_type != MSG_FEATURES {
+            return Err(hardware_wallet_error("unexpected response from the device"));
+        }
+        let sign_tx = ProtoMessage::default()
+            .uint(1, transaction.outputs.len() as u64)
+            .uint(2, transaction.inputs.len() as u64)
+            .bytes(3, coin_name(Network::current()).as_bytes())
+            .uint(4, transaction.version as u64)
+            .uint(5, transaction.lock_time as u64);
+        let mut response = self.call(MSG_SIGN_TX, &sign_tx)?;
+
+        let mut signatures = vec![None; inputs.len()];
+        loop {
+            let (MSG_TX_REQUEST, request) = response else { return Err(hardware_wallet_error("unexpected response from the device")) };
+            if let Some(serialized) = request.get_message(3)? {
+                if let (Some(index), Some(signature)) =
+                    (serialized.get_uint(1), serialized.get_bytes(2))
+                {
+                    let Some(slot) = signatures.get_mut(index as usize) else { return Err(invalid_request()) };
+                    let mut signature = signature.to_vec();
+                    signature.push(SIGHASH_ALL as u8);
+                    *slot = Some(signature);
+                }
+            }
+            let request_type = request.get_uint(1).unwrap_or(TX_INPUT);
+            if request_type == TX_FINISHED {
+                break;
+            }
+            let details = request.get_message(2)?.unwrap_or_default();
+            let index = details.get_uint(1).unwrap_or(0) as usize;
+            let ack = match details.get_bytes(2) {
+                Some(tx_hash) => {
+                    previous_tx_ack(find_previous_tx(&inputs, tx_hash)?, request_type, index)?
+                }
+                None => tx_ack(&transaction, &inputs, &addresses, request_type, index)?,
+            };
+            response = self.call(MSG_TX_ACK, &ProtoMessage::default().message(1, ack))?;
+        }
+
+        for (index, (input, signature)) in inputs.iter().zip(signatures).enumerate() {
+            let Some(signature) = signature else { return Err(hardware_wallet_error("the device did not sign every input")) };
+            add_hardware_signature(psbt, index, input, signature)?;
+        }
+        Ok(inputs.len())
+    }
+}
+
+/// Devuelve el nombre de la moneda con la que la Trezor firma en la red. Signet usa la de testnet.
+fn coin_name(network: Network) -> &'static str {
+    match network {
+        Network::Mainnet => "Bitcoin",
+        Network::Testnet | Network::Signet => "Testnet",
+        Network::Regtest => "Regtest",
+    }
+}
+
+/// Devuelve true si el PIN ingresado son posiciones validas de la matriz que muestra la Trezor:
+/// entre 1 y 9 digitos del 1 al 9.
+fn is_pin_matrix_entry(pin: &str) -> bool {
+    (1..=9).contains(&pin.len()) && pin.chars().all(|digit| ('1'..='9').contains(&digit))
+}
+
+/// Devuelve la respuesta a un pedido de un input o un output de la transaccion a firmar.
+/// Los inputs se envian con el path de su clave y los outputs con su direccion.
+fn tx_ack(
+    transaction: &Transaction,
+    inputs: &[HardwareInput],
+    addresses: &[String],
+    request_type: u64,
+    index: usize,
+) -> Result<ProtoMessage, CustomError> {
+    match request_type {
+        TX_INPUT => {
+            let (Some(tx_input), Some(input)) = (transaction.inputs.get(index), inputs.get(index))
+            else {
+                return Err(invalid_request());
+            };
+            let mut message = ProtoMessage::default();
+            for child in &input.key_origin.path {
+                message = message.uint(1, *child as u64);
+            }
+            let script_type = match input.segwit {
+                true => SPEND_WITNESS,
+                false => SPEND_ADDRESS,
+            };
+            let message = message
+                .bytes(2, &display_hash(&tx_input.previous_output.hash))
+                .uint(3, tx_input.previous_output.index as u64)
+                .uint(5, tx_input.sequence as u64)
+                .uint(6, script_type)
+                .uint(8, input.previous_output.value);
+            Ok(ProtoMessage::default().message(2, message))
+        }
+        TX_OUTPUT => {
+            let (Some(output), Some(address)) =
+                (transaction.outputs.get(index), addresses.get(index))
+            else {
+                return Err(invalid_request());
+            };
+            let message = ProtoMessage::default()
+                .bytes(1, address.as_bytes())
+                .uint(3, output.value)
+                .uint(4, PAY_TO_ADDRESS);
+            Ok(ProtoMessage::default().message(5, message))
+        }
+        _ => Err(invalid_request()),
+    }
+}
+
+/// Devuelve la respuesta a un pedido de los datos, un input o un output de una transaccion previa,
+/// que la Trezor usa para verificar el valor de los outputs que se gastan.
+fn previous_tx_ack(
+    previous_tx: &Transaction,
+    request_type: u64,
+    index: usize,
+) -> Result<ProtoMessage, CustomError> {
+    match request_type {
+        TX_META => Ok(ProtoMessage::default()
+            .uint(1, previous_tx.version as u64)
+            .uint(4, previous_tx.lock_time as u64)
+            .uint(6, previous_tx.inputs.len() as u64)
+            .uint(7, previous_tx.outputs.len() as u64)),
+        TX_INPUT => {
+            let Some(input) = previous_tx.inputs.get(index) else { return Err(invalid_request()) };
+            let message = ProtoMessage::default()
+                .bytes(2, &display_hash(&input.previous_output.hash))
+                .uint(3, input.previous_output.index as u64)
+                .bytes(4, &input.script_sig)
+                .uint(5, input.sequence as u64);
+            Ok(ProtoMessage::default().message(2, message))
+        }
+        TX_OUTPUT => {
+            let Some(output) = previous_tx.outputs.get(index) else { return Err(invalid_request()) };
+            let message = ProtoMessage::default()
+                .uint(1, output.value)
+                .bytes(2, &output.script_pubkey);
+            Ok(ProtoMessage::default().message(3, message))
+        }
+        _ => Err(invalid_request()),
+    }
+}
+
+/// Busca la transaccion previa que pide la Trezor, identificada por su hash en el orden en que se muestra.
+fn find_previous_tx<'a>(
+    inputs: &'a [HardwareInput],
+    tx_hash: &[u8],
+) -> Result<&'a Transaction, CustomError> {
+    inputs
+        .iter()
+        .map(|input| &input.previous_tx)
+        .find(|previous_tx| display_hash(&previous_tx.hash()) == tx_hash)
+        .ok_or_else(invalid_request)
+}
+
+/// Devuelve un hash en el orden en que se muestra, invertido respecto de como se serializa.
+fn display_hash(hash: &Hash256) -> Vec<u8> {
+    hash.as_bytes().iter().rev().copied().collect()
+}
+
+fn invalid_request() -> CustomError {
+    hardware_wallet_error("the device requested data that is not in the transaction")
+}
+
+/// Enmarca un mensaje en reportes HID: el primero tiene el encabezado con el tipo y el largo del mensaje,
+/// y todos empiezan con el byte magico.
+fn frame_message(message_type: u16, payload: &[u8]) -> Vec<HidReport> {
+    let mut data = HEADER_MAGIC.to_vec();
+    data.extend(message_type.to_be_bytes());
+    data.extend((payload.len() as u32).to_be_bytes());
+    data.extend(payload);
+    data.chunks(HID_REPORT_SIZE - 1)
+        .map(|chunk| {
+            let mut report = [0; HID_REPORT_SIZE];
+            report[0] = REPORT_MAGIC;
+            report[1..1 + chunk.len()].copy_from_slice(chunk);
+            report
+        })
+        .collect()
+}
+
+/// Devuelve el tipo y el contenido del mensaje enmarcado en los reportes recibidos, o None si todavia faltan reportes.
+/// Devuelve CustomError si algun reporte no empieza con los bytes magicos.
+fn unframe_message(reports: &[HidReport]) -> Result<Option<(u16, Vec<u8>)>, CustomError> {
+    let mut data = vec![];
+    for report in reports {
+        if report[0] != REPORT_MAGIC {
+            return Err(hardware_wallet_error("invalid response from the device"));
+        }
+        data.extend(&report[1..]);
+    }
+    if data[0..2] != HEADER_MAGIC {
+        return Err(hardware_wallet_error("invalid response from the device"));
+    }
+    let message_type = u16::from_be_bytes([data[2], data[3]]);
+    let length = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let payload = &data[HEADER_SIZE - 1..];
+    match payload.len() >= length {
+        true => Ok(Some((message_type, payload[..length].to_vec()))),
+        false => Ok(None),
+    }
+}
+
+/// ProtoValue es el valor de un campo de un mensaje protobuf.
+/// - Varint: Entero.
+/// - Bytes: Bytes, texto o un mensaje anidado serializado.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ProtoValue {
+    Varint(u64),
+    Bytes(Vec<u8>),
+}
+
+/// ProtoMessage es un mensaje protobuf, con los tipos de campo que usan los mensajes de Trezor.
+/// Los elementos son:
+/// - fields: Numero y valor de cada campo, en orden. Los campos repetidos aparecen varias veces.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ProtoMessage {
+    fields: Vec<(u64, ProtoValue)>,
+}
+
+impl ProtoMessage {
+    /// Agrega un campo entero.
+    fn uint(mut self, field: u64, value: u64) -> Self {
+        self.fields.push((field, ProtoValue::Varint(value)));
+        self
+    }
+
+    /// Agrega un campo de bytes o texto.
+    fn bytes(mut self, field: u64, value: &[u8]) -> Self {
+        self.fields.push((field, ProtoValue::Bytes(value.to_vec())));
+        self
+    }
+
+    /// Agrega un mensaje anidado.
+    fn message(self, field: u64, message: ProtoMessage) -> Self {
+        self.bytes(field, &message.serialize())
+    }
+
+    /// Devuelve el primer valor entero del campo.
+    fn get_uint(&self, field: u64) -> Option<u64> {
+        self.fields.iter().find_map(|(number, value)| match value {
+            ProtoValue::Varint(value) if *number == field => Some(*value),
+            _ => None,
+        })
+    }
+
+    /// Devuelve el primer valor de bytes del campo.
+    fn get_bytes(&self, field: u64) -> Option<&[u8]> {
+        self.fields.iter().find_map(|(number, value)| match value {
+            ProtoValue::Bytes(value) if *number == field => Some(value.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Devuelve el mensaje anidado en el campo, o None si no esta.
+    fn get_message(&self, field: u64) -> Result<Option<ProtoMessage>, CustomError> {
+        self.get_bytes(field).map(Self::parse).transpose()
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        for (field, value) in &self.fields {
+            match value {
+                ProtoValue::Varint(value) => {
+                    write_varint(&mut buffer, field << 3 | WIRE_VARINT);
+                    write_varint(&mut buffer, *value);
+                }
+                ProtoValue::Bytes(value) => {
+                    write_varint(&mut buffer, field << 3 | WIRE_LENGTH_DELIMITED);
+                    write_varint(&mut buffer, value.len() as u64);
+                    buffer.extend(value);
+                }
+            }
+        }
+        buffer
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Self, CustomError> {
+        let invalid = || hardware_wallet_error("invalid message from the device");
+        let mut message = Self::default();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let key = read_varint(bytes, &mut offset).ok_or_else(invalid)?;
+            let skip = match key & 0x07 {
+                WIRE_VARINT => {
+                    let value = read_varint(bytes, &mut offset).ok_or_else(invalid)?;
+                    message.fields.push((key >> 3, ProtoValue::Varint(value)));
+                    0
+                }
+                WIRE_LENGTH_DELIMITED => {
+                    let length = read_varint(bytes, &mut offset).ok_or_else(invalid)? as usize;
+                    let value = bytes
+                        .get(offset..offset.saturating_add(length))
+                        .ok_or_else(invalid)?;
+                    message
+                        .fields
+                        .push((key >> 3, ProtoValue::Bytes(value.to_vec())));
+                    length
+                }
+                WIRE_FIXED64 => 8,
+                WIRE_FIXED32 => 4,
+                _ => return Err(invalid()),
+            };
+            offset += skip;
+            if offset > bytes.len() {
+                return Err(invalid());
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// Escribe un entero como varint de protobuf: 7 bits por byte, el bit mas alto indica si siguen mas bytes.
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Lee un varint de protobuf desde el offset recibido, avanzandolo. Devuelve None si no termina o es muy largo.
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*offset)?;
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protobuf_varints() {
+        let mut buffer = vec![];
+        write_varint(&mut buffer, 300);
+        assert_eq!(buffer, vec![0xac, 0x02]);
+        let mut offset = 0;
+        assert_eq!(read_varint(&buffer, &mut offset), Some(300));
+        assert_eq!(offset, 2);
+        assert_eq!(read_varint(&[0x80], &mut 0), None);
+    }
+
+    #[test]
+    fn protobuf_message_round_trip() {
+        let input = ProtoMessage::default()
+            .uint(1, 0x8000002c)
+            .uint(1, 0x80000001)
+            .bytes(2, &[7; 32])
+            .uint(8, 10000);
+        let message = ProtoMessage::default().message(2, input.clone());
+        let parsed = ProtoMessage::parse(&message.serialize()).unwrap();
+        assert_eq!(parsed.get_message(2).unwrap(), Some(input.clone()));
+        assert_eq!(input.get_uint(1), Some(0x8000002c));
+        assert_eq!(input.get_uint(8), Some(10000));
+        assert_eq!(parsed.get_message(3).unwrap(), None);
+
+        // campo 1 fixed32, que se saltea
+        let parsed = ProtoMessage::parse(&[0x0d, 1, 2, 3, 4, 0x10, 0x05]).unwrap();
+        assert_eq!(parsed.fields, vec![(2, ProtoValue::Varint(5))]);
+        assert!(ProtoMessage::parse(&[0x12, 0x05, 0x01]).is_err());
+    }
+
+    #[test]
+    fn message_framing_round_trip() {
+        let payload: Vec<u8> = (0..100).collect();
+        let reports = frame_message(MSG_TX_ACK, &payload);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0][0..9], [b'?', b'#', b'#', 0, 22, 0, 0, 0, 100]);
+        assert_eq!(reports[1][0], b'?');
+
+        assert_eq!(unframe_message(&reports[0..1]).unwrap(), None);
+        assert_eq!(
+            unframe_message(&reports).unwrap(),
+            Some((MSG_TX_ACK, payload))
+        );
+
+        let mut invalid = reports[1];
+        invalid[0] = b'!';
+        assert!(unframe_message(&[reports[0], invalid]).is_err());
+    }
+
+    #[test]
+    fn previous_tx_requests() {
+        let previous_tx = Transaction {
+            version: 2,
+            inputs: vec![],
+            outputs: vec![crate::structs::tx_output::TransactionOutput {
+                value: 5000,
+                script_pubkey: vec![0x51],
+            }],
+            lock_time: 7,
+        };
+        let meta = previous_tx_ack(&previous_tx, TX_META, 0).unwrap();
+        assert_eq!(meta.get_uint(4), Some(7));
+        assert_eq!(meta.get_uint(7), Some(1));
+
+        let output = previous_tx_ack(&previous_tx, TX_OUTPUT, 0)
+            .unwrap()
+            .get_message(3)
+            .unwrap()
+            .unwrap();
+        assert_eq!(output.get_uint(1), Some(5000));
+        assert_eq!(output.get_bytes(2), Some([0x51].as_slice()));
+        assert!(previous_tx_ack(&previous_tx, TX_INPUT, 0).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{
+    error::{ChainError, CustomError},
+    messages::{
+        block::{merkle_root, Block, WITNESS_COMMITMENT_HEADER},
+        transaction::{find_signing_key, Transaction, SIGHASH_ALL},
+    },
+    network::Network,
+    parser::{BufferParser, Parser},
+    structs::{
+        block_header::BlockHeader,
+        hash256::Hash256,
+        outpoint::OutPoint,
+        script::{classify::ScriptType, Script, ScriptElement, OP_0, OP_RETURN},
+        tx_input::TransactionInput,
+        tx_output::TransactionOutput,
+    },
+};
+
+/// Prefijo del push del witness commitment del coinbase que contiene la solucion del bloque (BIP 325).
+const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+/// Solucion de un bloque: el script sig y los elementos del witness del input de to_sign.
+type Solution = (Vec<u8>, Vec<Vec<u8>>);
+
+/// Valida que el bloque este firmado por la autoridad de la red si es signet, resolviendo su challenge (BIP 325).
+/// En las demas redes, y para el bloque genesis, no hay nada que validar.
+/// Devuelve CustomError si la firma del bloque no resuelve el challenge.
+pub fn validate_block_signature(block: &Block) -> Result<(), CustomError> {
+    let network = Network::current();
+    let Some(challenge) = network.signet_challenge() else { return Ok(()) };
+    if *block.header.hash() == network.genesis_hash() {
+        return Ok(());
+    }
+    validate_signet_solution(block, &challenge)
+}
+
+/// Valida que la solucion del coinbase del bloque resuelva el challenge recibido (BIP 325).
+/// La solucion es el script sig y el witness de una transaccion virtual, to_sign, que gasta el output de otra, to_spend,
+/// cuyo script pubkey es el challenge y que compromete el header del bloque sin la solucion.
+/// Solo se soportan challenges multisig y P2PK, como el de la signet por defecto.
+/// Devuelve CustomError si el bloque no tiene witness commitment, la solucion no es valida o no resuelve el challenge.
+pub fn validate_signet_solution(block: &Block, challenge: &[u8]) -> Result<(), CustomError> {
+    let ((script_sig, witness), signet_merkle_root) = extract_solution(block)?;
+    let to_spend = to_spend_transaction(&block.header, &signet_merkle_root, challenge);
+    let to_sign = Transaction {
+        version: 0,
+        inputs: vec![TransactionInput {
+            previous_output: OutPoint {
+                hash: to_spend.hash(),
+                index: 0,
+            },
+            script_sig,
+            sequence: 0,
+            witness,
+        }],
+        outputs: vec![TransactionOutput {
+            value: 0,
+            script_pubkey: vec![OP_RETURN],
+        }],
+        lock_time: 0,
+    };
+    verify_challenge(&to_sign, challenge)
+}
+
+/// Extrae la solucion del push que empieza con SIGNET_HEADER en el witness commitment del coinbase.
+/// Devuelve el script sig y el witness de la solucion, que estan vacios si el bloque no tiene una,
+/// junto al merkle root del bloque calculado con el coinbase sin la solucion.
+fn extract_solution(block: &Block) -> Result<(Solution, Hash256), CustomError> {
+    let Some(mut coinbase) = block.transactions.first().cloned() else { return Err(CustomError::Chain(ChainError::InvalidSignetSolution)) };
+    let Some(commitment) = coinbase.outputs.iter_mut().rev().find(|output| {
+        output.script_pubkey.len() >= 38
+            && output.script_pubkey.starts_with(&WITNESS_COMMITMENT_HEADER)
+    }) else {
+        return Err(CustomError::Chain(ChainError::InvalidSignetSolution));
+    };
+
+    let mut script = Script::parse(&commitment.script_pubkey)
+        .map_err(|_| CustomError::Chain(ChainError::InvalidSignetSolution))?;
+    let solution = script
+        .elements
+        .iter_mut()
+        .find_map(|element| match element {
+            ScriptElement::Push(data)
+                if data.len() > SIGNET_HEADER.len() && data.starts_with(&SIGNET_HEADER) =>
+            {
+                Some(data.split_off(SIGNET_HEADER.len()))
+            }
+            _ => None,
+        });
+    let solution = match solution {
+        Some(solution) => {
+            // el bloque se firma con el push reducido al header
+            commitment.script_pubkey = script.serialize();
+            parse_solution(&solution)?
+        }
+        None => (vec![], vec![]),
+    };
+
+    let mut hashes = vec![coinbase.hash()];
+    hashes.extend(block.transactions.iter().skip(1).map(Transaction::hash));
+    let Some(signet_merkle_root) = merkle_root(hashes) else { return Err(CustomError::Chain(ChainError::InvalidSignetSolution)) };
+    Ok((solution, signet_merkle_root))
+}
+
+/// Parsea una solucion: el script sig con su largo y el witness con su cantidad de elementos, sin bytes sobrantes.
+fn parse_solution(solution: &[u8]) -> Result<Solution, CustomError> {
+    let mut parser = BufferParser::new(solution);
+    let extract_item = |parser: &mut BufferParser| -> Result<Vec<u8>, CustomError> {
+        let length = parser.extract_varint()? as usize;
+        Ok(parser.extract_buffer(length)?.to_vec())
+    };
+    let script_sig = extract_item(&mut parser)?;
+    let item_count = parser.extract_varint()? as usize;
+    let mut witness = vec![];
+    for _ in 0..item_count {
+        witness.push(extract_item(&mut parser)?);
+    }
+    if !parser.is_empty() {
+        return Err(CustomError::Chain(ChainError::InvalidSignetSolution));
+    }
+    Ok((script_sig, witness))
+}
+
+/// Crea la transaccion to_spend, cuyo unico output tiene el challenge como script pubkey
+/// y cuyo script sig compromete la version, el bloque anterior, el merkle root sin la solucion y el timestamp del bloque.
+fn to_spend_transaction(
+    header: &BlockHeader,
+    signet_merkle_root: &Hash256,
+    challenge: &[u8],
+) -> Transaction {
+    let mut block_data = header.version.to_le_bytes().to_vec();
+    block_data.extend(header.prev_block_hash.as_bytes());
+    block_data.extend(signet_merkle_root.as_bytes());
+    block_data.extend(header.timestamp.to_le_bytes());
+    Transaction {
+        version: 0,
+        inputs: vec![TransactionInput {
+            previous_output: OutPoint {
+                hash: Hash256::ZERO,
+                index: u32::MAX,
+            },
+            script_sig: Script::new(vec![
+                ScriptElement::Op(OP_0),
+                ScriptElement::Push(block_data),
+            ])
+            .serialize(),
+            sequence: 0,
+            witness: vec![],
+        }],
+        outputs: vec![TransactionOutput {
+            value: 0,
+            script_pubkey: challenge.to_vec(),
+        }],
+        lock_time: 0,
+    }
+}
+
+/// Verifica que el script sig de to_sign tenga las firmas que requiere el challenge, en el orden de sus public keys.
+/// Los challenges soportados no son witness programs, por lo que el witness debe estar vacio.
+fn verify_challenge(to_sign: &Transaction, challenge: &[u8]) -> Result<(), CustomError> {
+    let input = &to_sign.inputs[0];
+    let script_sig = Script::parse(&input.script_sig)
+        .map_err(|_| CustomError::Chain(ChainError::InvalidSignetSolution))?;
+    if !input.witness.is_empty() {
+        return Err(CustomError::Chain(ChainError::InvalidSignetSolution));
+    }
+
+    let multisig = Script::parse(challenge)
+        .ok()
+        .and_then(|challenge| challenge.multisig_keys());
+    let (pubkeys, signatures) = match (ScriptType::classify(challenge), multisig) {
+        (ScriptType::P2PK(pubkey), _) => match script_sig.elements.as_slice() {
+            [ScriptElement::Push(signature)] => (vec![pubkey], vec![signature.clone()]),
+            _ => return Err(CustomError::Chain(ChainError::InvalidSignetSolution)),
+        },
+        (_, Some((required, pubkeys))) => {
+            // OP_CHECKMULTISIG consume un elemento extra, que debe ser OP_0
+            let [ScriptElement::Op(OP_0), signatures @ ..] = script_sig.elements.as_slice() else { return Err(CustomError::Chain(ChainError::InvalidSignetSolution)) };
+            let signatures = signatures
+                .iter()
+                .map(|element| match element {
+                    ScriptElement::Push(signature) => Some(signature.clone()),
+                    ScriptElement::Op(_) => None,
+                })
+                .collect::<Option<Vec<Vec<u8>>>>()
+                .filter(|signatures| signatures.len() == required as usize)
+                .ok_or(CustomError::Chain(ChainError::InvalidSignetSolution))?;
+            (pubkeys, signatures)
+        }
+        _ => {
+            return Err(CustomError::Validation(String::from(
+                "Unsupported signet challenge",
+            )))
+        }
+    };
+
+    let sighash = to_sign.legacy_sighash(0, challenge);
+    let mut remaining_pubkeys = pubkeys.as_slice();
+    for signature in signatures {
+        if signature.last() != Some(&(SIGHASH_ALL as u8)) {
+            return Err(CustomError::Chain(ChainError::InvalidSignetSolution));
+        }
+        let Some(position) = find_signing_key(&sighash, &signature, remaining_pubkeys) else { return Err(CustomError::Chain(ChainError::InvalidSignetSolution)) };
+        remaining_pubkeys = &remaining_pubkeys[position + 1..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::messages::transaction::sign;
+
+    use super::*;
+
+    /// Crea un bloque firmado con la clave recibida para el challenge recibido.
+    fn signed_block(challenge: &[u8], privkey: &[u8]) -> Block {
+        let commitment_script = |solution: &[u8]| {
+            let mut signet_push = SIGNET_HEADER.to_vec();
+            signet_push.extend(solution);
+            let mut commitment = WITNESS_COMMITMENT_HEADER[2..].to_vec();
+            commitment.extend([0; 32]);
+            Script::new(vec![
+                ScriptElement::Op(OP_RETURN),
+                ScriptElement::Push(commitment),
+                ScriptElement::Push(signet_push),
+            ])
+            .serialize()
+        };
+        let coinbase = Transaction {
+            version: 2,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: Hash256::ZERO,
+                    index: u32::MAX,
+                },
+                script_sig: vec![1, 2, 3],
+                sequence: u32::MAX,
+                witness: vec![],
+            }],
+            outputs: vec![TransactionOutput {
+                value: 0,
+                script_pubkey: commitment_script(&[]),
+            }],
+            lock_time: 0,
+        };
+        let header = BlockHeader {
+            version: 0x20000000,
+            prev_block_hash: Hash256::new([7; 32]),
+            merkle_root: Hash256::ZERO,
+            timestamp: 1_600_000_000,
+            bits: 0x1e0377ae,
+            nonce: 0,
+            hash: Hash256::ZERO,
+            broadcasted: false,
+            block_downloaded: false,
+        };
+
+        // la firma compromete el merkle root sin la solucion
+        let signet_merkle_root = merkle_root(vec![coinbase.hash()]).unwrap();
+        let to_spend = to_spend_transaction(&header, &signet_merkle_root, challenge);
+        let mut to_sign = Transaction {
+            version: 0,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: to_spend.hash(),
+                    index: 0,
+                },
+                script_sig: vec![],
+                sequence: 0,
+                witness: vec![],
+            }],
+            outputs: vec![TransactionOutput {
+                value: 0,
+                script_pubkey: vec![OP_RETURN],
+            }],
+            lock_time: 0,
+        };
+        let (signature, _) = sign(&to_sign.legacy_sighash(0, challenge), privkey).unwrap();
+        to_sign.inputs[0].script_sig = Script::new(vec![
+            ScriptElement::Op(OP_0),
+            ScriptElement::Push(signature),
+        ])
+        .serialize();
+
+        let script_sig = &to_sign.inputs[0].script_sig;
+        let mut solution = vec![script_sig.len() as u8];
+        solution.extend(script_sig);
+        solution.push(0);
+
+        let mut coinbase = coinbase;
+        coinbase.outputs[0].script_pubkey = commitment_script(&solution);
+        let mut block = Block::new(header, vec![coinbase]);
+        block.header.merkle_root = merkle_root(vec![block.transactions[0].hash()]).unwrap();
+        block
+    }
+
+    fn challenge() -> Vec<u8> {
+        let (_, pubkey) = sign(&[1; 32], &[1; 32]).unwrap();
+        Script::multisig(1, &[pubkey]).unwrap().serialize()
+    }
+
+    #[test]
+    fn valid_signet_solution() {
+        let challenge = challenge();
+        let block = signed_block(&challenge, &[1; 32]);
+        assert!(block.create_merkle_root().is_ok());
+        assert!(validate_signet_solution(&block, &challenge).is_ok());
+    }
+
+    #[test]
+    fn invalid_signet_solution() {
+        let challenge = challenge();
+        let block = signed_block(&challenge, &[2; 32]);
+        assert!(validate_signet_solution(&block, &challenge).is_err());
+
+        let mut block = signed_block(&challenge, &[1; 32]);
+        block.header.timestamp += 1;
+        assert!(validate_signet_solution(&block, &challenge).is_err());
+
+        block.transactions[0].outputs.clear();
+        assert!(validate_signet_solution(&block, &challenge).is_err());
+    }
+
+    #[test]
+    fn default_signet_challenge_is_supported() {
+        let challenge = Network::Signet.signet_challenge().unwrap();
+        let Some((required, pubkeys)) = Script::parse(&challenge).unwrap().multisig_keys() else {
+            panic!("not a multisig challenge")
+        };
+        assert_eq!(required, 1);
+        assert_eq!(pubkeys.len(), 2);
+        assert!(Network::Mainnet.signet_challenge().is_none());
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, SocketAddrV6},
+};
+
+use chrono::Local;
+
+use crate::{
+    atomic_file::{read_atomic, write_atomic},
+    error::CustomError,
+    parser::{BufferParser, Parser},
+    structs::network_address::NetworkAddress,
+    utils::get_socket_address,
+};
+
+/// Servicio NODE_NETWORK, el peer puede enviar bloques completos.
+const NODE_NETWORK: u64 = 0x01;
+/// Cantidad maxima de direcciones guardadas.
+const MAX_ADDRESSES: usize = 2000;
+/// Cantidad de fallos consecutivos tras la cual se descarta una direccion a la que nunca nos pudimos conectar.
+const MAX_FAILURES: u32 = 3;
+/// Tiempo en milisegundos que se espera antes de volver a intentar conectarse a una direccion.
+const RETRY_COOLDOWN: i64 = 10 * 60 * 1000;
+const SUCCESS_SCORE: i64 = 2;
+const FAILURE_PENALTY: i64 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+/// AddrEntry es una direccion conocida junto a los resultados de las conexiones con ella.
+/// Los elementos son:
+/// - address: Direccion del nodo.
+/// - services: Servicios que ofrece el nodo.
+/// - timestamp: Ultima vez que se vio al nodo activo, segun los anuncios recibidos.
+/// - successes: Cantidad de conexiones exitosas.
+/// - failures: Cantidad de fallos consecutivos al conectarse.
+/// - last_attempt: Timestamp en milisegundos del ultimo intento de conexion.
+struct AddrEntry {
+    address: SocketAddrV6,
+    services: u64,
+    timestamp: u32,
+    successes: u32,
+    failures: u32,
+    last_attempt: i64,
+}
+
+impl AddrEntry {
+    fn new(address: SocketAddrV6, services: u64, timestamp: u32) -> Self {
+        Self {
+            address,
+            services,
+            timestamp,
+            successes: 0,
+            failures: 0,
+            last_attempt: 0,
+        }
+    }
+
+    /// Puntaje de la direccion, las conexiones exitosas lo aumentan y los fallos lo reducen.
+    fn score(&self) -> i64 {
+        self.successes as i64 * SUCCESS_SCORE - self.failures as i64 * FAILURE_PENALTY
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        buffer.extend(self.address.ip().octets());
+        buffer.extend(self.address.port().to_be_bytes());
+        buffer.extend(self.services.to_le_bytes());
+        buffer.extend(self.timestamp.to_le_bytes());
+        buffer.extend(self.successes.to_le_bytes());
+        buffer.extend(self.failures.to_le_bytes());
+        buffer.extend(self.last_attempt.to_le_bytes());
+        buffer
+    }
+
+    fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        Ok(Self {
+            address: parser.extract_address()?,
+            services: parser.extract_u64()?,
+            timestamp: parser.extract_u32()?,
+            successes: parser.extract_u32()?,
+            failures: parser.extract_u32()?,
+            last_attempt: parser.extract_i64()?,
+        })
+    }
+}
+
+/// AddrMan administra las direcciones de nodos conocidas, recibidas en mensajes addr y addrv2.
+/// Cada direccion tiene un puntaje segun los resultados de las conexiones con ella, y se guardan
+/// en disco para no depender unicamente de las semillas DNS al iniciar.
+/// Los elementos son:
+/// - path: Path del archivo donde se guardan las direcciones.
+/// - entries: Direcciones conocidas.
+pub struct AddrMan {
+    path: String,
+    entries: HashMap<SocketAddrV6, AddrEntry>,
+}
+
+impl AddrMan {
+    /// Inicializa las direcciones restaurandolas del archivo donde se guardan.
+    /// Si el archivo no existe o no se puede leer, comienza sin direcciones.
+    pub fn new(path: String) -> Result<Self, CustomError> {
+        let buffer = read_atomic(&path).unwrap_or_default();
+
+        let mut parser = BufferParser::new(&buffer);
+        let mut entries = HashMap::new();
+        while !parser.is_empty() {
+            let Ok(entry) = AddrEntry::parse(&mut parser) else { break };
+            entries.insert(entry.address, entry);
+        }
+        Ok(Self { path, entries })
+    }
+
+    /// Devuelve la cantidad de direcciones conocidas.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Devuelve true si no hay direcciones conocidas.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Devuelve las direcciones conocidas.
+    pub fn addresses(&self) -> impl Iterator<Item = SocketAddrV6> + '_ {
+        self.entries.keys().copied()
+    }
+
+    /// Agrega las direcciones anunciadas por un peer.
+    /// Se descartan las direcciones que no ofrecen bloques completos o a las que no es posible conectarse.
+    /// Devuelve la cantidad de direcciones nuevas.
+    pub fn add(&mut self, addresses: Vec<NetworkAddress>) -> Result<usize, CustomError> {
+        let mut new_addresses = 0;
+        for network_address in addresses {
+            if network_address.services & NODE_NETWORK == 0
+                || network_address.address.port() == 0
+                || network_address.address.ip().is_unspecified()
+            {
+                continue;
+            }
+
+            match self.entries.get_mut(&network_address.address) {
+                Some(entry) => {
+                    entry.services = network_address.services;
+                    entry.timestamp = entry.timestamp.max(network_address.timestamp);
+                }
+                None => {
+                    self.entries.insert(
+                        network_address.address,
+                        AddrEntry::new(
+                            network_address.address,
+                            network_address.services,
+                            network_address.timestamp,
+                        ),
+                    );
+                    new_addresses += 1;
+                }
+            }
+        }
+
+        if new_addresses > 0 {
+            self.evict();
+            self.save()?;
+        }
+        Ok(new_addresses)
+    }
+
+    /// Registra una conexion exitosa con la direccion, agregandola si no era conocida.
+    pub fn connected(&mut self, address: SocketAddrV6, services: u64) -> Result<(), CustomError> {
+        let now = Local::now();
+        let entry = self
+            .entries
+            .entry(address)
+            .or_insert_with(|| AddrEntry::new(address, services, now.timestamp() as u32));
+        entry.services = services;
+        entry.timestamp = now.timestamp() as u32;
+        entry.successes += 1;
+        entry.failures = 0;
+        entry.last_attempt = now.timestamp_millis();
+        self.save()
+    }
+
+    /// Registra un intento fallido de conexion con la direccion.
+    /// Si nunca nos pudimos conectar y fallo MAX_FAILURES veces, la direccion se descarta.
+    pub fn failed(&mut self, address: SocketAddrV6) -> Result<(), CustomError> {
+        let Some(entry) = self.entries.get_mut(&address) else { return Ok(()) };
+        entry.failures += 1;
+        entry.last_attempt = Local::now().timestamp_millis();
+        if entry.successes == 0 && entry.failures >= MAX_FAILURES {
+            self.entries.remove(&address);
+        }
+        self.save()
+    }
+
+    /// Devuelve hasta count direcciones a las que conectarse, ordenadas por puntaje y por ultima vez vistas.
+    /// Se excluyen las direcciones recibidas y las que se intentaron hace menos de RETRY_COOLDOWN.
+    pub fn candidates(&self, count: usize, exclude: &[SocketAddrV6]) -> Vec<SocketAddr> {
+        let now = Local::now().timestamp_millis();
+        let mut candidates: Vec<&AddrEntry> = self
+            .entries
+            .values()
+            .filter(|entry| !exclude.contains(&entry.address))
+            .filter(|entry| now - entry.last_attempt >= RETRY_COOLDOWN)
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.score()
+                .cmp(&a.score())
+                .then(b.timestamp.cmp(&a.timestamp))
+        });
+
+        candidates
+            .into_iter()
+            .take(count)
+            .map(|entry| get_socket_address(entry.address))
+            .collect()
+    }
+
+    /// Si se supera MAX_ADDRESSES, descarta las direcciones de menor puntaje y vistas hace mas tiempo.
+    fn evict(&mut self) {
+        if self.entries.len() <= MAX_ADDRESSES {
+            return;
+        }
+        let mut entries: Vec<&AddrEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| {
+            a.score()
+                .cmp(&b.score())
+                .then(a.timestamp.cmp(&b.timestamp))
+        });
+
+        let to_remove: Vec<SocketAddrV6> = entries
+            .iter()
+            .take(self.entries.len() - MAX_ADDRESSES)
+            .map(|entry| entry.address)
+            .collect();
+        for address in to_remove {
+            self.entries.remove(&address);
+        }
+    }
+
+    fn save(&self) -> Result<(), CustomError> {
+        let mut buffer = vec![];
+        for entry in self.entries.values() {
+            buffer.extend(entry.serialize());
+        }
+
+        write_atomic(&self.path, &buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::atomic_file::remove_atomic;
+
+    use super::*;
+
+    fn address(host: u8) -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv4Addr::new(10, 0, 0, host).to_ipv6_mapped(), 18333, 0, 0)
+    }
+
+    fn network_address(host: u8, services: u64, timestamp: u32) -> NetworkAddress {
+        NetworkAddress {
+            timestamp,
+            services,
+            address: address(host),
+        }
+    }
+
+    #[test]
+    fn add_addresses() {
+        let path = String::from("tests/test_addr_man_add.bin");
+        let mut addr_man = AddrMan::new(path.clone()).unwrap();
+        assert!(addr_man.is_empty());
+
+        let new_addresses = addr_man
+            .add(vec![
+                network_address(1, NODE_NETWORK, 1680000000),
+                network_address(2, 0x400, 1680000000),
+                network_address(1, NODE_NETWORK, 1680000100),
+            ])
+            .unwrap();
+        assert_eq!(new_addresses, 1);
+        assert_eq!(addr_man.len(), 1);
+
+        let restored = AddrMan::new(path.clone()).unwrap();
+        assert_eq!(restored.entries[&address(1)].timestamp, 1680000100);
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn candidates_are_sorted_by_score() {
+        let path = String::from("tests/test_addr_man_candidates.bin");
+        let mut addr_man = AddrMan::new(path.clone()).unwrap();
+        addr_man
+            .add(vec![
+                network_address(1, NODE_NETWORK, 1680000000),
+                network_address(2, NODE_NETWORK, 1680000100),
+                network_address(3, NODE_NETWORK, 1680000200),
+            ])
+            .unwrap();
+        assert_eq!(
+            addr_man.candidates(2, &[]),
+            vec![
+                get_socket_address(address(3)),
+                get_socket_address(address(2))
+            ]
+        );
+        assert_eq!(
+            addr_man.candidates(3, &[address(3)]),
+            vec![
+                get_socket_address(address(2)),
+                get_socket_address(address(1))
+            ]
+        );
+
+        // las direcciones intentadas recientemente no se devuelven
+        addr_man.failed(address(3)).unwrap();
+        addr_man.connected(address(1), NODE_NETWORK).unwrap();
+        assert_eq!(
+            addr_man.candidates(3, &[]),
+            vec![get_socket_address(address(2))]
+        );
+
+        for entry in addr_man.entries.values_mut() {
+            entry.last_attempt = 0;
+        }
+        assert_eq!(
+            addr_man.candidates(3, &[]),
+            vec![
+                get_socket_address(address(1)),
+                get_socket_address(address(2)),
+                get_socket_address(address(3))
+            ]
+        );
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn failing_addresses_are_removed() {
+        let path = String::from("tests/test_addr_man_failed.bin");
+        let mut addr_man = AddrMan::new(path.clone()).unwrap();
+        addr_man
+            .add(vec![network_address(1, NODE_NETWORK, 1680000000)])
+            .unwrap();
+        for _ in 0..MAX_FAILURES {
+            addr_man.failed(address(1)).unwrap();
+        }
+        assert!(addr_man.is_empty());
+
+        remove_atomic(&path).unwrap();
+    }
+}
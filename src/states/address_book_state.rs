@@ -0,0 +1,244 @@
+use std::str::FromStr;
+
+use crate::{
+    address::Address,
+    atomic_file::{read_atomic, write_atomic},
+    error::{CustomError, WalletError},
+    parser::{BufferParser, Parser},
+};
+
+/// Largo maximo en bytes del nombre de un contacto, ya que se serializa con un byte de largo.
+const MAX_LABEL_LENGTH: usize = 255;
+
+/// Contact es una direccion guardada en la libreta de direcciones.
+/// Los elementos son:
+/// - label: Nombre del contacto, unico en la libreta.
+/// - address: Direccion P2PKH o P2WPKH del contacto.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    pub label: String,
+    pub address: String,
+}
+
+impl Contact {
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![self.label.len() as u8];
+        buffer.extend(self.label.as_bytes());
+        buffer.push(self.address.len() as u8);
+        buffer.extend(self.address.as_bytes());
+        buffer
+    }
+
+    fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        let label_len = parser.extract_u8()? as usize;
+        let label = parser.extract_string(label_len)?;
+        let address_len = parser.extract_u8()? as usize;
+        let address = parser.extract_string(address_len)?;
+        Ok(Self { label, address })
+    }
+}
+
+/// AddressBook es la libreta de direcciones, asocia un nombre a las direcciones a las que se envian fondos.
+/// Los contactos se guardan en disco cada vez que se modifican.
+/// Los elementos son:
+/// - path: Path del archivo donde se guardan los contactos.
+/// - contacts: Contactos ordenados por nombre.
+pub struct AddressBook {
+    path: String,
+    contacts: Vec<Contact>,
+}
+
+impl AddressBook {
+    /// Inicializa la libreta restaurando los contactos del archivo donde se guardan.
+    /// Devuelve CustomError si el archivo no es valido.
+    pub fn new(path: String) -> Result<Self, CustomError> {
+        let buffer = read_atomic(&path)?;
+
+        let mut parser = BufferParser::new(&buffer);
+        let mut contacts = vec![];
+        while !parser.is_empty() {
+            contacts.push(Contact::parse(&mut parser)?);
+        }
+        Ok(Self { path, contacts })
+    }
+
+    /// Agrega un contacto.
+    /// Devuelve CustomError si el nombre o la direccion no son validos, o si ya hay un contacto con ese nombre.
+    pub fn add(&mut self, label: String, address: String) -> Result<(), CustomError> {
+        validate_contact(&label, &address)?;
+        if self.get(&label).is_some() {
+            return Err(CustomError::Wallet(WalletError::ContactAlreadyExists));
+        }
+        self.contacts.push(Contact { label, address });
+        self.save()
+    }
+
+    /// Reemplaza el nombre y la direccion de un contacto.
+    /// Devuelve CustomError si no existe el contacto, si los datos nuevos no son validos,
+    /// o si el nombre nuevo ya lo usa otro contacto.
+    pub fn update(
+        &mut self,
+        label: &str,
+        new_label: String,
+        new_address: String,
+    ) -> Result<(), CustomError> {
+        validate_contact(&new_label, &new_address)?;
+        if new_label != label && self.get(&new_label).is_some() {
+            return Err(CustomError::Wallet(WalletError::ContactAlreadyExists));
+        }
+        let Some(contact) = self
+            .contacts
+            .iter_mut()
+            .find(|contact| contact.label == label)
+        else {
+            return Err(CustomError::Wallet(WalletError::ContactNotFound));
+        };
+        contact.label = new_label;
+        contact.address = new_address;
+        self.save()
+    }
+
+    /// Elimina un contacto y lo devuelve.
+    /// Devuelve CustomError si no existe el contacto.
+    pub fn remove(&mut self, label: &str) -> Result<Contact, CustomError> {
+        let Some(position) = self
+            .contacts
+            .iter()
+            .position(|contact| contact.label == label)
+        else {
+            return Err(CustomError::Wallet(WalletError::ContactNotFound));
+        };
+        let contact = self.contacts.remove(position);
+        self.save()?;
+        Ok(contact)
+    }
+
+    /// Devuelve el contacto con el nombre recibido.
+    pub fn get(&self, label: &str) -> Option<&Contact> {
+        self.contacts.iter().find(|contact| contact.label == label)
+    }
+
+    /// Devuelve el nombre del contacto de una direccion, si esta guardada.
+    pub fn get_label(&self, address: &str) -> Option<&String> {
+        self.contacts
+            .iter()
+            .find(|contact| contact.address == address)
+            .map(|contact| &contact.label)
+    }
+
+    /// Devuelve todos los contactos, ordenados por nombre.
+    pub fn get_all(&self) -> &Vec<Contact> {
+        &self.contacts
+    }
+
+    fn save(&mut self) -> Result<(), CustomError> {
+        self.contacts.sort_by(|a, b| a.label.cmp(&b.label));
+
+        let mut buffer = vec![];
+        for contact in &self.contacts {
+            buffer.extend(contact.serialize());
+        }
+
+        write_atomic(&self.path, &buffer)
+    }
+}
+
+/// Verifica que el nombre no este vacio ni exceda MAX_LABEL_LENGTH y que la direccion sea valida.
+fn validate_contact(label: &str, address: &str) -> Result<(), CustomError> {
+    if label.trim().is_empty() || label.len() > MAX_LABEL_LENGTH {
+        return Err(CustomError::Validation(String::from(
+            "Invalid contact label",
+        )));
+    }
+    if let Err(CustomError::Validation(reason)) = Address::from_str(address) {
+        return Err(CustomError::Validation(format!(
+            "Invalid contact address: {}",
+            reason
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atomic_file::remove_atomic;
+
+    use super::*;
+
+    const ADDRESS: &str = "mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun";
+    const SEGWIT_ADDRESS: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+    #[test]
+    fn contacts_are_restored_sorted_by_label() {
+        let path = String::from("tests/test_address_book_restore.bin");
+        let mut address_book = AddressBook::new(path.clone()).unwrap();
+        address_book
+            .add(String::from("Satoshi"), String::from(ADDRESS))
+            .unwrap();
+        address_book
+            .add(String::from("Hal"), String::from(SEGWIT_ADDRESS))
+            .unwrap();
+
+        let restored = AddressBook::new(path.clone()).unwrap();
+        assert_eq!(restored.get_all().len(), 2);
+        assert_eq!(restored.get_all()[0].label, "Hal");
+        assert_eq!(restored.get_label(ADDRESS), Some(&String::from("Satoshi")));
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_contacts_are_rejected() {
+        let path = String::from("tests/test_address_book_invalid.bin");
+        let mut address_book = AddressBook::new(path.clone()).unwrap();
+        assert!(address_book
+            .add(String::from("Satoshi"), String::from("invalid"))
+            .is_err());
+        assert!(address_book
+            .add(String::from(" "), String::from(ADDRESS))
+            .is_err());
+
+        address_book
+            .add(String::from("Satoshi"), String::from(ADDRESS))
+            .unwrap();
+        assert!(matches!(
+            address_book.add(String::from("Satoshi"), String::from(SEGWIT_ADDRESS)),
+            Err(CustomError::Wallet(WalletError::ContactAlreadyExists))
+        ));
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn update_and_remove_contact() {
+        let path = String::from("tests/test_address_book_update.bin");
+        let mut address_book = AddressBook::new(path.clone()).unwrap();
+        address_book
+            .add(String::from("Satoshi"), String::from(ADDRESS))
+            .unwrap();
+
+        address_book
+            .update(
+                "Satoshi",
+                String::from("Nakamoto"),
+                String::from(SEGWIT_ADDRESS),
+            )
+            .unwrap();
+        assert!(address_book.get("Satoshi").is_none());
+        assert_eq!(
+            address_book.get("Nakamoto").unwrap().address,
+            SEGWIT_ADDRESS
+        );
+        assert!(matches!(
+            address_book.update("Satoshi", String::from("Hal"), String::from(ADDRESS)),
+            Err(CustomError::Wallet(WalletError::ContactNotFound))
+        ));
+
+        let removed = address_book.remove("Nakamoto").unwrap();
+        assert_eq!(removed.address, SEGWIT_ADDRESS);
+        assert!(address_book.get_all().is_empty());
+        assert!(AddressBook::new(path.clone()).unwrap().get_all().is_empty());
+
+        remove_atomic(&path).unwrap();
+    }
+}
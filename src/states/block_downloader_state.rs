@@ -0,0 +1,383 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddrV6,
+};
+
+use crate::{
+    error::CustomError,
+    structs::{hash256::Hash256, inventory::Inventory},
+    utils::get_current_timestamp_millis,
+};
+
+/// Cantidad maxima de bloques pedidos en simultaneo a un mismo peer.
+const MAX_BLOCKS_IN_FLIGHT_PER_PEER: usize = 16;
+/// Tiempo en milisegundos sin recibir bloques de un peer tras el cual se considera que se trabo.
+const BLOCK_REQUEST_TIMEOUT: u128 = 20000;
+/// Tiempo en milisegundos durante el cual no se le asignan bloques a un peer que se trabo.
+const STALLED_PEER_COOLDOWN: u128 = 60000;
+
+/// QueuedBlock es un bloque a la espera de ser asignado a un peer.
+/// Los elementos son:
+/// - inventory: Inventory con el que se pide el bloque.
+/// - avoid_peer: Peer que no pudo entregar el bloque, solo se le vuelve a asignar si no hay otro disponible.
+struct QueuedBlock {
+    inventory: Inventory,
+    avoid_peer: Option<SocketAddrV6>,
+}
+
+/// BlockRequest es un bloque pedido a un peer que todavia no fue recibido.
+/// Los elementos son:
+/// - inventory: Inventory con el que se pidio el bloque.
+/// - peer: Peer al que se le pidio el bloque.
+/// - requested_at: Timestamp en milisegundos del pedido o de la ultima entrega del peer.
+struct BlockRequest {
+    inventory: Inventory,
+    peer: SocketAddrV6,
+    requested_at: u128,
+}
+
+/// BlockDownloader reparte la descarga de bloques entre todos los peers conectados.
+/// Los bloques se encolan y se asignan a los peers con menos pedidos en curso, hasta
+/// MAX_BLOCKS_IN_FLIGHT_PER_PEER por peer. Si un peer no entrega bloques durante BLOCK_REQUEST_TIMEOUT
+/// sus pedidos se reasignan a otros peers y no recibe nuevos pedidos durante STALLED_PEER_COOLDOWN.
+/// Los elementos son:
+/// - queue: Bloques a la espera de ser asignados, en orden.
+/// - queued: Hashes de los bloques en la cola.
+/// - in_flight: Bloques pedidos a un peer que todavia no fueron recibidos.
+/// - stalled_peers: Peers que se trabaron y el timestamp en el que vuelven a estar disponibles.
+/// - request_timeout: Tiempo en milisegundos tras el cual un peer se considera trabado.
+pub struct BlockDownloader {
+    queue: VecDeque<QueuedBlock>,
+    queued: HashSet<Hash256>,
+    in_flight: HashMap<Hash256, BlockRequest>,
+    stalled_peers: HashMap<SocketAddrV6, u128>,
+    request_timeout: u128,
+}
+
+impl Default for BlockDownloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockDownloader {
+    /// Inicializa el downloader sin bloques pendientes.
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            queued: HashSet::new(),
+            in_flight: HashMap::new(),
+            stalled_peers: HashMap::new(),
+            request_timeout: BLOCK_REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Encola los bloques a descargar, ignorando los que ya estan en la cola o pedidos.
+    pub fn enqueue(&mut self, inventories: Vec<Inventory>) {
+        for inventory in inventories {
+            if self.queued.contains(&inventory.hash) || self.in_flight.contains_key(&inventory.hash)
+            {
+                continue;
+            }
+            self.queued.insert(inventory.hash);
+            self.queue.push_back(QueuedBlock {
+                inventory,
+                avoid_peer: None,
+            });
+        }
+    }
+
+    /// Vuelve a encolar bloques que no pudieron ser entregados, con prioridad sobre el resto de la cola.
+    /// Si el bloque estaba pedido, se evita volver a asignarlo al mismo peer.
+    pub fn retry(&mut self, inventories: Vec<Inventory>) {
+        for inventory in inventories.into_iter().rev() {
+            let avoid_peer = self
+                .in_flight
+                .remove(&inventory.hash)
+                .map(|request| request.peer);
+            if !self.queued.insert(inventory.hash) {
+                continue;
+            }
+            self.queue.push_front(QueuedBlock {
+                inventory,
+                avoid_peer,
+            });
+        }
+    }
+
+    /// Asigna los bloques de la cola a los peers recibidos y devuelve los inventories a pedir a cada uno.
+    /// Cada bloque se asigna al peer disponible con menos pedidos en curso.
+    pub fn assign(
+        &mut self,
+        peers: &[SocketAddrV6],
+    ) -> Result<HashMap<SocketAddrV6, Vec<Inventory>>, CustomError> {
+        let now = get_current_timestamp_millis()?;
+        self.stalled_peers
+            .retain(|_, available_at| *available_at > now);
+
+        let mut load: HashMap<SocketAddrV6, usize> = peers
+            .iter()
+            .filter(|peer| !self.stalled_peers.contains_key(peer))
+            .map(|peer| (*peer, 0))
+            .collect();
+        for request in self.in_flight.values() {
+            if let Some(count) = load.get_mut(&request.peer) {
+                *count += 1;
+            }
+        }
+
+        let mut assignments: HashMap<SocketAddrV6, Vec<Inventory>> = HashMap::new();
+        while let Some(queued_block) = self.queue.pop_front() {
+            if !self.queued.remove(&queued_block.inventory.hash) {
+                continue;
+            }
+            let Some(peer) = least_loaded_peer(&load, queued_block.avoid_peer) else {
+                self.queued.insert(queued_block.inventory.hash);
+                self.queue.push_front(queued_block);
+                break;
+            };
+
+            *load.entry(peer).or_insert(0) += 1;
+            self.in_flight.insert(
+                queued_block.inventory.hash,
+                BlockRequest {
+                    inventory: queued_block.inventory.clone(),
+                    peer,
+                    requested_at: now,
+                },
+            );
+            assignments
+                .entry(peer)
+                .or_default()
+                .push(queued_block.inventory);
+        }
+        Ok(assignments)
+    }
+
+    /// Registra la recepcion de un bloque.
+    /// Los demas pedidos al mismo peer se renuevan, ya que el peer sigue entregando bloques.
+    pub fn received(&mut self, block_hash: &Hash256) -> Result<(), CustomError> {
+        self.queued.remove(block_hash);
+        let Some(request) = self.in_flight.remove(block_hash) else { return Ok(()) };
+
+        let now = get_current_timestamp_millis()?;
+        for other_request in self.in_flight.values_mut() {
+            if other_request.peer == request.peer {
+                other_request.requested_at = now;
+            }
+        }
+        Ok(())
+    }
+
+    /// Descarta los bloques de la cola y los pedidos en curso, por ejemplo si dejaron de formar parte de la cadena.
+    /// Los bloques de la cola se descartan al asignarlos.
+    pub fn cancel(&mut self, block_hashes: &[Hash256]) {
+        for block_hash in block_hashes {
+            self.queued.remove(block_hash);
+            self.in_flight.remove(block_hash);
+        }
+    }
+
+    /// Vuelve a encolar los bloques pedidos a un peer, por ejemplo al desconectarse.
+    pub fn remove_peer(&mut self, address: SocketAddrV6) {
+        let inventories = self.take_peer_requests(address);
+        self.retry(inventories);
+    }
+
+    /// Busca los peers que no entregaron bloques durante el timeout y reasigna sus pedidos.
+    /// Devuelve los peers trabados, que no recibiran nuevos pedidos durante STALLED_PEER_COOLDOWN.
+    pub fn check_timeouts(&mut self) -> Result<Vec<SocketAddrV6>, CustomError> {
+        let now = get_current_timestamp_millis()?;
+        let mut stalled_peers = vec![];
+        for request in self.in_flight.values() {
+            if now.saturating_sub(request.requested_at) >= self.request_timeout
+                && !stalled_peers.contains(&request.peer)
+            {
+                stalled_peers.push(request.peer);
+            }
+        }
+
+        for peer in &stalled_peers {
+            self.stalled_peers
+                .insert(*peer, now + STALLED_PEER_COOLDOWN);
+            let inventories = self.take_peer_requests(*peer);
+            self.retry(inventories);
+        }
+        Ok(stalled_peers)
+    }
+
+    /// Devuelve la cantidad de bloques en la cola.
+    pub fn queued_count(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Devuelve la cantidad de bloques pedidos que todavia no fueron recibidos.
+    pub fn in_flight_count(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Devuelve true si no hay bloques en la cola ni pedidos.
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty() && self.in_flight.is_empty()
+    }
+
+    fn take_peer_requests(&mut self, address: SocketAddrV6) -> Vec<Inventory> {
+        self.in_flight
+            .values()
+            .filter(|request| request.peer == address)
+            .map(|request| request.inventory.clone())
+            .collect()
+    }
+}
+
+/// Devuelve el peer con menos pedidos en curso que todavia tenga lugar.
+/// Solo devuelve avoid_peer si es el unico peer disponible.
+fn least_loaded_peer(
+    load: &HashMap<SocketAddrV6, usize>,
+    avoid_peer: Option<SocketAddrV6>,
+) -> Option<SocketAddrV6> {
+    let available = load
+        .iter()
+        .filter(|(_, count)| **count < MAX_BLOCKS_IN_FLIGHT_PER_PEER);
+    let best = available
+        .clone()
+        .filter(|(peer, _)| Some(**peer) != avoid_peer)
+        .min_by_key(|(_, count)| **count)
+        .or_else(|| available.min_by_key(|(_, count)| **count));
+    best.map(|(peer, _)| *peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use crate::structs::inventory::InventoryType;
+
+    use super::*;
+
+    fn address(port: u16) -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv6Addr::LOCALHOST, port, 0, 0)
+    }
+
+    fn inventories(count: u8) -> Vec<Inventory> {
+        (0..count)
+            .map(|i| Inventory::new(InventoryType::Block, Hash256::new([i; 32])))
+            .collect()
+    }
+
+    #[test]
+    fn blocks_are_split_across_peers() {
+        let mut downloader = BlockDownloader::new();
+        downloader.enqueue(inventories(10));
+        downloader.enqueue(inventories(10));
+        assert_eq!(downloader.queued_count(), 10);
+        assert_eq!(downloader.in_flight_count(), 0);
+
+        let assignments = downloader.assign(&[address(1), address(2)]).unwrap();
+        assert_eq!(assignments[&address(1)].len(), 5);
+        assert_eq!(assignments[&address(2)].len(), 5);
+        assert_eq!(downloader.queued_count(), 0);
+        assert_eq!(downloader.in_flight_count(), 10);
+
+        downloader.received(&Hash256::new([0; 32])).unwrap();
+        assert_eq!(downloader.queued_count(), 0);
+        assert_eq!(downloader.in_flight_count(), 9);
+    }
+
+    #[test]
+    fn peers_are_limited_to_max_in_flight() {
+        let mut downloader = BlockDownloader::new();
+        downloader.enqueue(inventories(MAX_BLOCKS_IN_FLIGHT_PER_PEER as u8 + 4));
+
+        let assignments = downloader.assign(&[address(1)]).unwrap();
+        assert_eq!(
+            assignments[&address(1)].len(),
+            MAX_BLOCKS_IN_FLIGHT_PER_PEER
+        );
+        assert_eq!(downloader.queued_count(), 4);
+        assert_eq!(downloader.in_flight_count(), MAX_BLOCKS_IN_FLIGHT_PER_PEER);
+        assert!(downloader.assign(&[address(1)]).unwrap().is_empty());
+
+        let assignments = downloader.assign(&[address(1), address(2)]).unwrap();
+        assert_eq!(assignments[&address(2)].len(), 4);
+    }
+
+    #[test]
+    fn failed_blocks_are_reassigned_to_another_peer() {
+        let mut downloader = BlockDownloader::new();
+        downloader.enqueue(inventories(1));
+        downloader.assign(&[address(1)]).unwrap();
+
+        downloader.retry(inventories(1));
+        let assignments = downloader.assign(&[address(1), address(2)]).unwrap();
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[&address(2)], inventories(1));
+    }
+
+    #[test]
+    fn disconnected_peer_blocks_are_requeued() {
+        let mut downloader = BlockDownloader::new();
+        downloader.enqueue(inventories(4));
+        downloader.assign(&[address(1), address(2)]).unwrap();
+
+        downloader.remove_peer(address(1));
+        assert_eq!(downloader.queued_count(), 2);
+        assert_eq!(downloader.in_flight_count(), 2);
+        let assignments = downloader.assign(&[address(2)]).unwrap();
+        assert_eq!(assignments[&address(2)].len(), 2);
+    }
+
+    #[test]
+    fn cancelled_blocks_are_not_requested() {
+        let mut downloader = BlockDownloader::new();
+        downloader.enqueue(inventories(2));
+        downloader.assign(&[address(1)]).unwrap();
+        downloader.enqueue(inventories(4));
+
+        downloader.cancel(&[Hash256::new([0; 32]), Hash256::new([2; 32])]);
+        assert_eq!(downloader.queued_count(), 1);
+        assert_eq!(downloader.in_flight_count(), 1);
+
+        let assignments = downloader.assign(&[address(1)]).unwrap();
+        assert_eq!(
+            assignments[&address(1)],
+            vec![Inventory::new(InventoryType::Block, Hash256::new([3; 32]))]
+        );
+    }
+
+    #[test]
+    fn stalled_peer_work_is_reassigned() {
+        let mut downloader = BlockDownloader::new();
+        downloader.enqueue(inventories(2));
+        downloader.assign(&[address(1)]).unwrap();
+        assert!(downloader.check_timeouts().unwrap().is_empty());
+
+        downloader.request_timeout = 0;
+        assert_eq!(downloader.check_timeouts().unwrap(), vec![address(1)]);
+        assert_eq!(downloader.queued_count(), 2);
+        assert_eq!(downloader.in_flight_count(), 0);
+
+        let assignments = downloader.assign(&[address(1), address(2)]).unwrap();
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[&address(2)].len(), 2);
+
+        downloader.enqueue(vec![Inventory::new(
+            InventoryType::Block,
+            Hash256::new([9; 32]),
+        )]);
+        assert!(downloader.assign(&[address(1)]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn requests_renewed_after_now_do_not_overflow() {
+        let mut downloader = BlockDownloader::new();
+        downloader.enqueue(inventories(1));
+        downloader.assign(&[address(1)]).unwrap();
+        for request in downloader.in_flight.values_mut() {
+            request.requested_at = u128::MAX;
+        }
+
+        assert!(downloader.check_timeouts().unwrap().is_empty());
+        assert_eq!(downloader.in_flight_count(), 1);
+    }
+}
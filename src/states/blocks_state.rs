@@ -1,12 +1,15 @@
 use std::{
-    fs::read_dir,
+    collections::HashSet,
     sync::{mpsc::Sender, Arc, Mutex},
 };
 
 use crate::{
+    block_store::{BlockStore, PruneMode},
     error::CustomError,
-    logger::{send_log, Log},
+    headers_store::HeaderChain,
+    logger::{send_log, Log, LogEntry},
     messages::block::Block,
+    structs::hash256::Hash256,
     utils::get_current_timestamp_millis,
 };
 
@@ -15,7 +18,7 @@ use super::pending_blocks_state::PendingBlocks;
 /// BlocksIBDStats es una estructura que contiene los elementos necesarios para manejar las
 /// estadisticas de la descarga masiva de bloques.
 /// Solamente se utiliza cuando la cantidad de bloques a descargar
-/// es mayor al 2% de los headers posteriores al START_DATE_IBD.
+/// es mayor al 2% de los headers posteriores al inicio de la IBD de la red seleccionada.
 /// Los elementos son:
 /// - blocks_downloaded: Cantidad de bloques totales descargados.
 /// - checkpoint_timestamp: Timestamp del ultimo checkpoint.
@@ -32,14 +35,14 @@ struct BlocksIBDStats {
 /// BlocksState es una estructura que contiene los elementos necesarios para manejar los bloques.
 /// Los elementos son:
 /// - ibd_stats: Option<BLocksIBDStats> solamente se inicializa cuando corresponde.
-/// - store_path: Path de la carpeta donde se crea el directorio donde se encuentran los bloques.
+/// - store: BlockStore donde se guardan los bloques en disco.
 /// - logger_sender: Sender para enviar logs al logger.
 /// - pending_blocks_ref: Referencia a los bloques pendientes.
 /// - sync: Booleano que indica si el nodo esta sincronizado.
 pub struct BlocksState {
     ibd_stats: Option<BlocksIBDStats>,
-    store_path: String,
-    logger_sender: Sender<Log>,
+    store: BlockStore,
+    logger_sender: Sender<LogEntry>,
     pub pending_blocks_ref: Arc<Mutex<PendingBlocks>>,
     sync: bool,
 }
@@ -47,37 +50,32 @@ pub struct BlocksState {
 impl BlocksState {
     /// Inicializa el estado de los bloques.
     pub fn new(
-        store_path: String,
-        logger_sender: Sender<Log>,
+        store: BlockStore,
+        logger_sender: Sender<LogEntry>,
         pending_blocks_ref: Arc<Mutex<PendingBlocks>>,
     ) -> Self {
         Self {
             ibd_stats: None,
             pending_blocks_ref,
-            store_path,
+            store,
             logger_sender,
             sync: false,
         }
     }
 
     /// Se encarga de guardar en disco el bloque y eliminarlo de los bloques pendientes.
-    /// Si la cantidad de bloques a descargar es mayor al 2% de los headers posteriores al START_DATE_IBD
+    /// Si la cantidad de bloques a descargar es mayor al 2% de los headers posteriores al inicio de la IBD de la red seleccionada
     /// comienza los stats de la descarga.
     pub fn append_block(
         &mut self,
-        block_hash: &Vec<u8>,
+        block_hash: &Hash256,
         block: &Block,
         total_blocks: usize,
     ) -> Result<(), CustomError> {
-        let path = format!(
-            "{}/blocks/{}.bin",
-            self.store_path,
-            block.header.hash_as_string()
-        );
-        block.save(path)?;
+        self.store.save(block)?;
 
         if self.ibd_stats.is_none() {
-            let blocks_downloaded = read_dir(format!("{}/blocks", self.store_path))?.count();
+            let blocks_downloaded = self.store.count()?;
             let percentage = (blocks_downloaded * 100) / total_blocks;
 
             if percentage < 98_usize {
@@ -103,7 +101,7 @@ impl BlocksState {
         if self.is_synced() || self.ibd_stats.is_none() {
             send_log(
                 &self.logger_sender,
-                Log::Message(String::from("New block received")),
+                Log::Debug(String::from("New block received")),
             );
         } else {
             self.print_stats(total_blocks)?;
@@ -160,16 +158,62 @@ impl BlocksState {
         Ok(())
     }
 
+    /// Vuelve a marcar los bloques como no sincronizados, hasta que se reciban los bloques pendientes.
+    pub fn reset_sync(&mut self) {
+        self.sync = false;
+    }
+
     /// Devuelve el bloque correspondiente al hash pasado por parametro.
     pub fn get_block(&self, block_string_hash: String) -> Result<Block, CustomError> {
-        let path = format!("{}/blocks/{}.bin", self.store_path, block_string_hash);
-        Block::restore(path)
+        self.store.get(block_string_hash)
+    }
+
+    /// Actualiza el modo de poda de los bloques guardados.
+    pub fn set_prune_mode(&mut self, prune_mode: PruneMode) {
+        self.store.set_prune_mode(prune_mode);
+    }
+
+    /// Devuelve la cantidad de headers de la cadena cuyos bloques ya se podaron.
+    pub fn prune_height(&self) -> usize {
+        self.store.prune_height()
+    }
+
+    /// Elimina del disco los bloques de la cadena que ya no se conservan segun el modo de poda.
+    /// Solo se debe llamar una vez que el UTXO proceso los bloques.
+    pub fn prune(
+        &mut self,
+        headers: &impl HeaderChain,
+        wallet_blocks: &HashSet<Hash256>,
+    ) -> Result<(), CustomError> {
+        let removed = self.store.prune(headers, wallet_blocks)?;
+        if removed > 0 {
+            send_log(
+                &self.logger_sender,
+                Log::Debug(format!("{} blocks pruned from disk", removed)),
+            );
+        }
+        Ok(())
+    }
+
+    /// Guarda el checkpoint de los bloques descargados.
+    pub fn flush(&mut self) -> Result<(), CustomError> {
+        self.store.flush()
+    }
+
+    /// Ajusta la poda al descartar los headers a partir de la posicion len en una reorganizacion.
+    pub fn rollback(&mut self, len: usize) -> Result<(), CustomError> {
+        self.store.rollback(len)
     }
 
     /// Retorna el estado de sincronizacion de los bloques.
     pub fn is_synced(&self) -> bool {
         self.sync
     }
+
+    /// Devuelve la cantidad de bloques que faltan descargar.
+    pub fn pending_blocks_count(&self) -> Result<usize, CustomError> {
+        Ok(self.pending_blocks_ref.lock()?.len())
+    }
 }
 
 #[cfg(test)]
@@ -183,38 +227,43 @@ mod tests {
     fn blocks_state_append() {
         let store_path = "tests".to_string();
         let (logger_sender, _) = mpsc::channel();
-        let pending_blocks_ref = PendingBlocks::new(&store_path, &vec![]);
-        let mut blocks_state =
-            BlocksState::new(store_path.clone(), logger_sender, pending_blocks_ref);
+        let pending_blocks_ref = PendingBlocks::new(&vec![], 0, |_| false).unwrap();
+        let block_store = BlockStore::open(store_path.clone()).unwrap();
+        let mut blocks_state = BlocksState::new(block_store, logger_sender, pending_blocks_ref);
 
         let mut pending = blocks_state.pending_blocks_ref.lock().unwrap();
-        pending.append_block(vec![1, 2, 3]).unwrap();
+        pending.append_block(Hash256::new([1; 32])).unwrap();
         drop(pending);
 
         let mut block = blocks_state.get_block("test_block".to_string()).unwrap();
-        block.header.hash = vec![1, 2, 3];
+        block.header.hash = Hash256::new([1; 32]);
 
         blocks_state
-            .append_block(&vec![1, 2, 3], &block, 1)
+            .append_block(&Hash256::new([1; 32]), &block, 1)
             .unwrap();
 
         let pending = blocks_state.pending_blocks_ref.lock().unwrap();
         assert_eq!(pending.is_empty(), true);
 
-        assert!(Path::new(&format!("{}/blocks/010203.bin", store_path)).exists());
-        fs::remove_file(format!("{}/blocks/010203.bin", store_path)).unwrap();
+        let block_path = format!(
+            "{}/blocks/{}.bin",
+            store_path,
+            block.header.hash_as_string()
+        );
+        assert!(Path::new(&block_path).exists());
+        fs::remove_file(block_path).unwrap();
     }
 
     #[test]
     fn blocks_state_verify_sync() {
         let store_path = "tests".to_string();
         let (logger_sender, _) = mpsc::channel();
-        let pending_blocks_ref = PendingBlocks::new(&store_path, &vec![]);
-        let mut blocks_state =
-            BlocksState::new(store_path.clone(), logger_sender, pending_blocks_ref);
+        let pending_blocks_ref = PendingBlocks::new(&vec![], 0, |_| false).unwrap();
+        let block_store = BlockStore::open(store_path.clone()).unwrap();
+        let mut blocks_state = BlocksState::new(block_store, logger_sender, pending_blocks_ref);
 
         let mut pending = blocks_state.pending_blocks_ref.lock().unwrap();
-        pending.append_block(vec![1, 2, 3]).unwrap();
+        pending.append_block(Hash256::new([1; 32])).unwrap();
         drop(pending);
 
         assert_eq!(blocks_state.is_synced(), false);
@@ -222,7 +271,7 @@ mod tests {
         assert_eq!(blocks_state.is_synced(), false);
 
         let mut pending = blocks_state.pending_blocks_ref.lock().unwrap();
-        pending.remove_block(&vec![1, 2, 3]).unwrap();
+        pending.remove_block(&Hash256::new([1; 32])).unwrap();
         drop(pending);
 
         assert_eq!(blocks_state.is_synced(), false);
@@ -230,7 +279,7 @@ mod tests {
         assert_eq!(blocks_state.is_synced(), true);
 
         let mut pending = blocks_state.pending_blocks_ref.lock().unwrap();
-        pending.append_block(vec![1, 2, 3]).unwrap();
+        pending.append_block(Hash256::new([1; 32])).unwrap();
         drop(pending);
 
         assert_eq!(blocks_state.is_synced(), true);
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::CustomError,
+    headers_store::HeaderChain,
+    messages::{
+        cfheaders::CFHeaders, cfilter::CFilter, get_cfheaders::GetCFHeaders,
+        get_cfilters::GetCFilters,
+    },
+    structs::{block_filter::BASIC_FILTER_TYPE, hash256::Hash256},
+    utils::get_current_timestamp_millis,
+};
+
+/// Bit de servicios que indica que el peer sirve filtros compactos (BIP 157).
+pub const NODE_COMPACT_FILTERS: u64 = 1 << 6;
+/// Cantidad maxima de filtros que se pueden pedir en un getcfilters.
+const FILTERS_BATCH_SIZE: usize = 1000;
+/// Cantidad maxima de filter headers que se pueden pedir en un getcfheaders.
+const FILTER_HEADERS_BATCH_SIZE: usize = 2000;
+/// Tiempo en milisegundos tras el cual se vuelve a pedir un lote que no fue respondido.
+const FILTERS_REQUEST_TIMEOUT: u128 = 30000;
+
+/// FiltersRequest es el proximo mensaje a enviar para avanzar con la descarga de filtros.
+pub enum FiltersRequest {
+    Headers(GetCFHeaders),
+    Filters(GetCFilters),
+}
+
+/// FiltersBatch es un rango de bloques cuyos filtros se estan descargando.
+/// Los elementos son:
+/// - start_height: Altura del primer bloque del rango.
+/// - stop_hash: Hash del ultimo bloque del rango.
+/// - block_count: Cantidad de bloques del rango.
+/// - headers_only: Indica si el rango es anterior a la altura a escanear, solo se validan sus filter headers.
+/// - expected_filters: Hash de filtro esperado para cada bloque, None mientras se esperan los cfheaders.
+/// - last_filter_header: Filter header del ultimo bloque del rango.
+/// - requested_at: Timestamp en milisegundos del ultimo pedido.
+struct FiltersBatch {
+    start_height: usize,
+    stop_hash: Hash256,
+    block_count: usize,
+    headers_only: bool,
+    expected_filters: Option<HashMap<Hash256, Vec<u8>>>,
+    last_filter_header: Option<Vec<u8>>,
+    requested_at: u128,
+}
+
+impl FiltersBatch {
+    fn request(&self) -> FiltersRequest {
+        match self.expected_filters {
+            None => FiltersRequest::Headers(GetCFHeaders::new(
+                BASIC_FILTER_TYPE,
+                self.start_height as u32,
+                self.stop_hash,
+            )),
+            Some(_) => FiltersRequest::Filters(GetCFilters::new(
+                BASIC_FILTER_TYPE,
+                self.start_height as u32,
+                self.stop_hash,
+            )),
+        }
+    }
+}
+
+/// FiltersState es una estructura que contiene el progreso de la descarga de filtros compactos (BIP 157/158).
+/// Los filtros se descargan por lotes: primero los cfheaders del lote y luego los cfilters,
+/// validando cada filtro contra su hash.
+/// Los filter headers se encadenan desde un checkpoint conocido, por lo que entre el checkpoint y la altura
+/// a escanear solo se descargan y validan los cfheaders.
+/// Los elementos son:
+/// - next_height: Altura del proximo bloque a pedir.
+/// - scan_height: Altura del primer bloque cuyo filtro se descarga.
+/// - stop_height: Altura del ultimo bloque a escanear.
+/// - last_filter_header: Filter header del ultimo bloque procesado.
+/// - batch: Lote que se esta descargando.
+/// - matched_blocks: Bloques cuyo filtro coincidio con las wallets.
+pub struct FiltersState {
+    next_height: usize,
+    scan_height: usize,
+    stop_height: usize,
+    last_filter_header: Vec<u8>,
+    batch: Option<FiltersBatch>,
+    matched_blocks: Vec<Hash256>,
+}
+
+impl FiltersState {
+    /// Inicializa la descarga de filtros para los bloques entre start_height y stop_height inclusive.
+    /// checkpoint es la altura y el filter header de un bloque conocido, anterior a start_height,
+    /// con el que deben encadenar los filter headers recibidos.
+    pub fn new(start_height: usize, stop_height: usize, checkpoint: (usize, Vec<u8>)) -> Self {
+        let (checkpoint_height, checkpoint_header) = checkpoint;
+        let scan_height = start_height.max(checkpoint_height + 1);
+        Self {
+            next_height: if scan_height > stop_height {
+                scan_height
+            } else {
+                checkpoint_height + 1
+            },
+            scan_height,
+            stop_height,
+            last_filter_header: checkpoint_header,
+            batch: None,
+            matched_blocks: vec![],
+        }
+    }
+
+    /// Registra un bloque cuyo filtro coincidio con las wallets.
+    pub fn append_matched_block(&mut self, block_hash: Hash256) {
+        self.matched_blocks.push(block_hash);
+    }
+
+    /// Devuelve los bloques cuyo filtro coincidio con las wallets.
+    pub fn matched_blocks(&self) -> &[Hash256] {
+        &self.matched_blocks
+    }
+
+    /// Devuelve true si ya se descargaron todos los filtros.
+    pub fn is_finished(&self) -> bool {
+        self.batch.is_none() && self.next_height > self.stop_height
+    }
+
+    /// Devuelve el proximo mensaje a enviar, si corresponde.
+    /// Si hay un lote en curso solo se vuelve a pedir cuando paso el timeout.
+    pub fn next_request(
+        &mut self,
+        headers: &impl HeaderChain,
+    ) -> Result<Option<FiltersRequest>, CustomError> {
+        let now = get_current_timestamp_millis()?;
+
+        if let Some(batch) = &mut self.batch {
+            if now.saturating_sub(batch.requested_at) < FILTERS_REQUEST_TIMEOUT {
+                return Ok(None);
+            }
+            batch.requested_at = now;
+            return Ok(Some(batch.request()));
+        }
+
+        if self.next_height > self.stop_height {
+            return Ok(None);
+        }
+
+        let headers_only = self.next_height < self.scan_height;
+        let end_height = match headers_only {
+            true => (self.next_height + FILTER_HEADERS_BATCH_SIZE - 1).min(self.scan_height - 1),
+            false => (self.next_height + FILTERS_BATCH_SIZE - 1).min(self.stop_height),
+        };
+        // los headers no incluyen al genesis, el header de altura h esta en la posicion h - 1
+        let Some(stop_header) = headers.get(end_height - 1)? else { return Ok(None) };
+
+        let batch = FiltersBatch {
+            start_height: self.next_height,
+            stop_hash: *stop_header.hash(),
+            block_count: end_height - self.next_height + 1,
+            headers_only,
+            expected_filters: None,
+            last_filter_header: None,
+            requested_at: now,
+        };
+        let request = batch.request();
+        self.batch = Some(batch);
+        Ok(Some(request))
+    }
+
+    /// Registra los filter hashes recibidos en un cfheaders y devuelve el getcfilters del lote.
+    /// Si el lote es anterior a la altura a escanear, devuelve el pedido del lote siguiente.
+    /// Devuelve None si el mensaje no corresponde al lote en curso.
+    /// Devuelve CustomError si los filter headers no encadenan con los anteriores o con el checkpoint.
+    pub fn append_filter_headers(
+        &mut self,
+        cfheaders: &CFHeaders,
+        headers: &impl HeaderChain,
+    ) -> Result<Option<FiltersRequest>, CustomError> {
+        let Some(batch) = &mut self.batch else { return Ok(None) };
+        if batch.expected_filters.is_some() || batch.stop_hash != cfheaders.stop_hash {
+            return Ok(None);
+        }
+        if cfheaders.filter_hashes.len() != batch.block_count {
+            return Err(CustomError::Validation(String::from(
+                "Unexpected amount of filter headers",
+            )));
+        }
+        if self.last_filter_header != cfheaders.previous_filter_header {
+            return Err(CustomError::Validation(String::from(
+                "Filter headers do not connect",
+            )));
+        }
+
+        let Some(last_filter_header) = cfheaders.filter_headers().last().cloned() else { return Ok(None) };
+        if batch.headers_only {
+            self.next_height = batch.start_height + batch.block_count;
+            self.last_filter_header = last_filter_header;
+            self.batch = None;
+            return self.next_request(headers);
+        }
+
+        let batch_headers = headers.read_range(batch.start_height - 1, batch.block_count)?;
+        if batch_headers.len() != batch.block_count {
+            return Ok(None);
+        }
+        let mut expected_filters = HashMap::new();
+        for (header, filter_hash) in batch_headers.iter().zip(&cfheaders.filter_hashes) {
+            expected_filters.insert(*header.hash(), filter_hash.clone());
+        }
+
+        batch.last_filter_header = Some(last_filter_header);
+        batch.expected_filters = Some(expected_filters);
+        batch.requested_at = get_current_timestamp_millis()?;
+
+        Ok(Some(FiltersRequest::Filters(GetCFilters::new(
+            BASIC_FILTER_TYPE,
+            batch.start_height as u32,
+            batch.stop_hash,
+        ))))
+    }
+
+    /// Valida un cfilter recibido contra el hash esperado del lote en curso.
+    /// Devuelve false si el filtro no corresponde al lote o ya fue recibido.
+    /// Devuelve CustomError si el hash del filtro no coincide con el de los cfheaders, descartando el lote.
+    pub fn check_filter(&mut self, cfilter: &CFilter) -> Result<bool, CustomError> {
+        let Some(batch) = &mut self.batch else { return Ok(false) };
+        let Some(expected_filters) = &mut batch.expected_filters else { return Ok(false) };
+        let Some(expected_hash) = expected_filters.remove(&cfilter.block_hash) else { return Ok(false) };
+
+        if expected_hash != cfilter.filter_hash() {
+            // se descarta el lote para volver a pedirlo
+            self.batch = None;
+            return Err(CustomError::Validation(String::from(
+                "Compact filter does not match its filter header",
+            )));
+        }
+
+        if expected_filters.is_empty() {
+            self.next_height = batch.start_height + batch.block_count;
+            if let Some(last_filter_header) = batch.last_filter_header.take() {
+                self.last_filter_header = last_filter_header;
+            }
+            self.batch = None;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{
+        block_filter::{filter_hash, BlockFilter},
+        block_header::BlockHeader,
+    };
+
+    use super::*;
+
+    fn headers(count: u8) -> Vec<BlockHeader> {
+        (0..count)
+            .map(|i| BlockHeader {
+                bits: 0,
+                nonce: 0,
+                prev_block_hash: Hash256::ZERO,
+                timestamp: 0,
+                version: 0,
+                hash: Hash256::new([i; 32]),
+                merkle_root: Hash256::ZERO,
+                block_downloaded: false,
+                broadcasted: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filters_sync_batch() {
+        let headers = headers(3);
+        let mut filters = FiltersState::new(2, 3, (1, vec![0; 32]));
+
+        let Some(FiltersRequest::Headers(get_cfheaders)) = filters.next_request(&headers).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(get_cfheaders.start_height, 2);
+        assert_eq!(get_cfheaders.stop_hash, Hash256::new([2; 32]));
+        assert!(filters.next_request(&headers).unwrap().is_none());
+
+        let filter = BlockFilter::new(&Hash256::new([1; 32]), &[vec![1; 25]]).serialize();
+        let cfheaders = CFHeaders::new(
+            BASIC_FILTER_TYPE,
+            Hash256::new([2; 32]),
+            vec![0; 32],
+            vec![filter_hash(&filter), filter_hash(&filter)],
+        );
+        let Some(FiltersRequest::Filters(get_cfilters)) =
+            filters.append_filter_headers(&cfheaders, &headers).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(get_cfilters.start_height, 2);
+
+        let cfilter = CFilter::new(BASIC_FILTER_TYPE, Hash256::new([1; 32]), filter.clone());
+        assert!(filters.check_filter(&cfilter).unwrap());
+        assert!(!filters.check_filter(&cfilter).unwrap());
+        assert!(!filters.is_finished());
+
+        let cfilter = CFilter::new(BASIC_FILTER_TYPE, Hash256::new([2; 32]), filter);
+        assert!(filters.check_filter(&cfilter).unwrap());
+        assert!(filters.is_finished());
+    }
+
+    #[test]
+    fn invalid_filter_is_rejected() {
+        let headers = headers(1);
+        let mut filters = FiltersState::new(1, 1, (0, vec![0; 32]));
+        filters.next_request(&headers).unwrap();
+
+        let cfheaders = CFHeaders::new(
+            BASIC_FILTER_TYPE,
+            Hash256::ZERO,
+            vec![0; 32],
+            vec![vec![9; 32]],
+        );
+        filters.append_filter_headers(&cfheaders, &headers).unwrap();
+
+        let cfilter = CFilter::new(BASIC_FILTER_TYPE, Hash256::ZERO, vec![0]);
+        assert!(filters.check_filter(&cfilter).is_err());
+        assert!(!filters.is_finished());
+        assert!(filters.next_request(&headers).unwrap().is_some());
+    }
+
+    #[test]
+    fn filter_headers_with_wrong_count() {
+        let headers = headers(2);
+        let mut filters = FiltersState::new(1, 2, (0, vec![0; 32]));
+        filters.next_request(&headers).unwrap();
+
+        let cfheaders = CFHeaders::new(
+            BASIC_FILTER_TYPE,
+            Hash256::new([1; 32]),
+            vec![0; 32],
+            vec![vec![9; 32]],
+        );
+        assert!(filters.append_filter_headers(&cfheaders, &headers).is_err());
+    }
+
+    #[test]
+    fn filter_headers_are_verified_from_the_checkpoint() {
+        let headers = headers(3);
+        let mut filters = FiltersState::new(3, 3, (0, vec![7; 32]));
+
+        let Some(FiltersRequest::Headers(get_cfheaders)) = filters.next_request(&headers).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(get_cfheaders.start_height, 1);
+        assert_eq!(get_cfheaders.stop_hash, Hash256::new([1; 32]));
+
+        let filter_hashes = vec![vec![1; 32], vec![2; 32]];
+        let cfheaders = CFHeaders::new(
+            BASIC_FILTER_TYPE,
+            Hash256::new([1; 32]),
+            vec![0; 32],
+            filter_hashes.clone(),
+        );
+        assert!(filters.append_filter_headers(&cfheaders, &headers).is_err());
+
+        let cfheaders = CFHeaders::new(
+            BASIC_FILTER_TYPE,
+            Hash256::new([1; 32]),
+            vec![7; 32],
+            filter_hashes,
+        );
+        let Some(FiltersRequest::Headers(get_cfheaders)) =
+            filters.append_filter_headers(&cfheaders, &headers).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(get_cfheaders.start_height, 3);
+        assert_eq!(get_cfheaders.stop_hash, Hash256::new([2; 32]));
+
+        let cfheaders = CFHeaders::new(
+            BASIC_FILTER_TYPE,
+            Hash256::new([2; 32]),
+            vec![0; 32],
+            vec![vec![3; 32]],
+        );
+        assert!(filters.append_filter_headers(&cfheaders, &headers).is_err());
+        assert!(!filters.is_finished());
+    }
+}
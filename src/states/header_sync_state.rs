@@ -0,0 +1,161 @@
+use std::{collections::HashMap, net::SocketAddrV6};
+
+use crate::{
+    chain_validator::ChainWork,
+    structs::{block_header::BlockHeader, hash256::Hash256},
+};
+
+/// HeaderBranch es una rama de headers que un peer envio y que se aparta de la cadena del nodo,
+/// pero que todavia no acumula mas trabajo que ella.
+/// Los elementos son:
+/// - fork_len: Cantidad de headers de la cadena del nodo que comparte la rama.
+/// - headers: Headers de la rama posteriores al fork, en orden.
+/// - work: Trabajo acumulado por los headers de la rama, sin contar el de la cadena hasta el fork.
+#[derive(Debug, Clone)]
+pub struct HeaderBranch {
+    pub fork_len: usize,
+    pub headers: Vec<BlockHeader>,
+    pub work: ChainWork,
+}
+
+impl HeaderBranch {
+    /// Crea la rama que se aparta de la cadena despues de sus primeros fork_len headers.
+    pub fn new(fork_len: usize, headers: Vec<BlockHeader>) -> Self {
+        let work = ChainWork::of(&headers);
+        Self {
+            fork_len,
+            headers,
+            work,
+        }
+    }
+
+    /// Agrega a la rama los siguientes headers que envio el peer, sumando su trabajo al de la rama.
+    pub fn extend(&mut self, headers: &[BlockHeader]) {
+        self.work = self.work + ChainWork::of(headers);
+        self.headers.extend(headers.iter().cloned());
+    }
+
+    /// Devuelve el hash del ultimo header de la rama.
+    pub fn tip(&self) -> Option<&Hash256> {
+        self.headers.last().map(|header| header.hash())
+    }
+}
+
+/// HeaderSync lleva las ramas que sirven los peers de los que se descargan headers en simultaneo.
+/// Cada peer envia su cadena completa en mensajes de hasta MAX_HEADERS_PER_MESSAGE headers. Si la cadena de un peer
+/// se aparta de la del nodo, sus headers se acumulan hasta que la rama tenga mas trabajo que la cadena del nodo,
+/// en cuyo caso se reorganiza, o hasta que el peer la termine de enviar con menos trabajo.
+/// Los elementos son:
+/// - branches: Rama que sirve cada peer, si se aparta de la cadena del nodo.
+#[derive(Debug, Default)]
+pub struct HeaderSync {
+    branches: HashMap<SocketAddrV6, HeaderBranch>,
+}
+
+impl HeaderSync {
+    /// Inicializa el estado sin ramas.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Quita la rama del peer y la devuelve si los headers que siguen a prev_block_hash la extienden.
+    /// Si no la extienden, el peer cambio de cadena y la rama se descarta.
+    pub fn take_branch(
+        &mut self,
+        address: &SocketAddrV6,
+        prev_block_hash: &Hash256,
+    ) -> Option<HeaderBranch> {
+        self.branches
+            .remove(address)
+            .filter(|branch| branch.tip() == Some(prev_block_hash))
+    }
+
+    /// Guarda la rama del peer hasta recibir sus siguientes headers.
+    pub fn keep_branch(&mut self, address: SocketAddrV6, branch: HeaderBranch) {
+        self.branches.insert(address, branch);
+    }
+
+    /// Descarta la rama de un peer que se desconecto.
+    pub fn remove_peer(&mut self, address: &SocketAddrV6) {
+        self.branches.remove(address);
+    }
+
+    /// Devuelve la cantidad de peers que sirven una rama distinta a la cadena del nodo.
+    pub fn divergent_peers(&self) -> usize {
+        self.branches.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    fn address(port: u16) -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv6Addr::LOCALHOST, port, 0, 0)
+    }
+
+    fn branch(hashes: &[u8]) -> HeaderBranch {
+        let headers = hashes
+            .iter()
+            .map(|hash| BlockHeader {
+                version: 0,
+                prev_block_hash: Hash256::ZERO,
+                merkle_root: Hash256::ZERO,
+                timestamp: 0,
+                bits: 0x207fffff,
+                nonce: 0,
+                hash: Hash256::new([*hash; 32]),
+                broadcasted: false,
+                block_downloaded: false,
+            })
+            .collect();
+        HeaderBranch::new(10, headers)
+    }
+
+    #[test]
+    fn branch_work_accumulates_across_batches() {
+        let mut taken = branch(&[1, 2]);
+        let next = branch(&[3, 4, 5]);
+        assert_eq!(taken.work, ChainWork::of(&taken.headers));
+
+        taken.extend(&next.headers);
+        assert_eq!(taken.headers.len(), 5);
+        assert_eq!(taken.tip(), Some(&Hash256::new([5; 32])));
+        assert_eq!(taken.work, ChainWork::of(&taken.headers));
+        assert!(taken.work > next.work);
+    }
+
+    #[test]
+    fn branch_is_taken_when_extended() {
+        let mut header_sync = HeaderSync::new();
+        header_sync.keep_branch(address(1), branch(&[1, 2]));
+        header_sync.keep_branch(address(2), branch(&[3]));
+        assert_eq!(header_sync.divergent_peers(), 2);
+
+        let taken = header_sync
+            .take_branch(&address(1), &Hash256::new([2; 32]))
+            .unwrap();
+        assert_eq!(taken.fork_len, 10);
+        assert_eq!(taken.headers.len(), 2);
+        assert!(header_sync
+            .take_branch(&address(1), &Hash256::new([2; 32]))
+            .is_none());
+        assert_eq!(header_sync.divergent_peers(), 1);
+    }
+
+    #[test]
+    fn branch_is_discarded_when_peer_switches_chain() {
+        let mut header_sync = HeaderSync::new();
+        header_sync.keep_branch(address(1), branch(&[1, 2]));
+        assert!(header_sync
+            .take_branch(&address(1), &Hash256::new([1; 32]))
+            .is_none());
+        assert_eq!(header_sync.divergent_peers(), 0);
+
+        header_sync.keep_branch(address(2), branch(&[3]));
+        header_sync.remove_peer(&address(2));
+        assert_eq!(header_sync.divergent_peers(), 0);
+    }
+}
@@ -1,22 +1,22 @@
-use std::{
-    io::{Read, Write},
-    sync::mpsc::Sender,
-};
+use std::{collections::HashMap, sync::mpsc::Sender};
 
 use crate::{
-    error::CustomError,
-    logger::{send_log, Log},
+    block_locator::locator_indexes,
+    chain_validator::{ChainWork, DIFFICULTY_ADJUSTMENT_INTERVAL},
+    error::{ChainError, CustomError},
+    headers_store::{HeaderChain, HeadersStore},
+    logger::{send_log, Log, LogEntry},
     messages::get_headers::GetHeaders,
-    parser::BufferParser,
-    peer::GENESIS,
-    structs::block_header::BlockHeader,
-    utils::{
-        calculate_index_from_timestamp, get_current_timestamp, get_current_timestamp_millis,
-        open_new_file,
-    },
+    network::Network,
+    structs::{block_header::BlockHeader, hash256::Hash256},
+    utils::{get_current_timestamp, get_current_timestamp_millis},
 };
 
-use super::utxo_state::START_DATE_IBD;
+/// Cantidad maxima de headers que se envian en un mensaje headers.
+pub const MAX_HEADERS_PER_MESSAGE: usize = 2000;
+/// Cantidad minima de headers recientes que se mantienen en memoria, los necesarios para validar los headers nuevos.
+/// La ventana crece hasta el doble antes de descartar los mas antiguos, que se leen del archivo cuando se necesitan.
+const HEADERS_WINDOW_SIZE: usize = DIFFICULTY_ADJUSTMENT_INTERVAL;
 
 /// HeaderIBDStats es una estructura que contiene los elementos necesarios para manejar las
 /// estadisticas de la descarga masiva de headers.
@@ -33,16 +33,25 @@ struct HeaderIBDStats {
 }
 
 /// HeadersState es una estructura que contiene los elementos necesarios para manejar los headers.
+/// En memoria solo se mantienen los headers mas recientes, los anteriores se leen del archivo por rangos.
 /// Los elementos son:
-/// - headers: Headers del nodo.
+/// - headers: Ultimos headers del nodo, a partir de la posicion window_start.
+/// - window_start: Posicion en la cadena del primer header en memoria.
+/// - chain_work: Trabajo acumulado por la cadena hasta cada header en memoria, en el mismo orden que los headers.
+/// - positions: Posicion en la cadena de cada header, por su hash.
+/// - ibd_index: Posicion del ultimo header con timestamp anterior o igual al inicio del IBD, 0 si no hay ninguno.
 /// - logger_sender: Sender para enviar logs al logger.
-/// - path: Path del archivo donde se guardan los headers.
+/// - store: HeadersStore, archivo donde se guardan los headers.
 /// - ibd_stats: Option<HeaderIBDStats> solamente se inicializa cuando corresponde.
 /// - sync: Indica si los headers del nodo estan sincronizados con la red.
 pub struct HeadersState {
     headers: Vec<BlockHeader>,
-    logger_sender: Sender<Log>,
-    path: String,
+    window_start: usize,
+    chain_work: Vec<ChainWork>,
+    positions: HashMap<Hash256, usize>,
+    ibd_index: usize,
+    logger_sender: Sender<LogEntry>,
+    store: HeadersStore,
     ibd_stats: Option<HeaderIBDStats>,
     sync: bool,
 }
@@ -51,11 +60,15 @@ impl HeadersState {
     /// Inicializa los headers del nodo.
     /// Si el archivo donde se guardan los headers no existe, se crea.
     /// Si el archivo existe, se restauran los headers.
-    pub fn new(path: String, logger_sender: Sender<Log>) -> Result<Self, CustomError> {
+    pub fn new(path: String, logger_sender: Sender<LogEntry>) -> Result<Self, CustomError> {
         let mut headers = Self {
             headers: Vec::new(),
+            window_start: 0,
+            chain_work: Vec::new(),
+            positions: HashMap::new(),
+            ibd_index: 0,
             logger_sender: logger_sender.clone(),
-            path,
+            store: HeadersStore::open(path)?,
             ibd_stats: None,
             sync: false,
         };
@@ -69,72 +82,82 @@ impl HeadersState {
         Ok(headers)
     }
 
+    /// Lee del archivo el hash de todos los headers, para buscarlos por hash, y carga en memoria los ultimos
+    /// HEADERS_WINDOW_SIZE headers con su trabajo acumulado.
     fn restore(&mut self) -> Result<(), CustomError> {
-        let mut buffer = vec![];
-        let mut file = open_new_file(self.path.clone(), true)?;
-        file.read_to_end(&mut buffer)?;
+        self.positions = self.store.load_positions()?;
+        self.load_window(self.store.len())?;
+        self.ibd_index = self.index_from_timestamp(Network::current().ibd_start_timestamp())?;
+        Ok(())
+    }
 
-        let mut parser = BufferParser::new(buffer);
-        if parser.len() % 112 != 0 {
-            return Err(CustomError::SerializedBufferIsInvalid);
-        }
+    /// Carga en memoria los ultimos HEADERS_WINDOW_SIZE headers guardados antes de la posicion len.
+    fn load_window(&mut self, len: usize) -> Result<(), CustomError> {
+        self.window_start = len.saturating_sub(HEADERS_WINDOW_SIZE);
+        (self.headers, self.chain_work) = self
+            .store
+            .read_range_with_chain_work(self.window_start, len - self.window_start)?;
+        Ok(())
+    }
 
-        while !parser.is_empty() {
-            let header = BlockHeader::parse_from_backup(parser.extract_buffer(112)?.to_vec())?;
-            self.headers.push(header);
+    /// Descarta de memoria los headers mas antiguos si la ventana supera el doble de HEADERS_WINDOW_SIZE.
+    fn trim_window(&mut self) {
+        if self.headers.len() <= 2 * HEADERS_WINDOW_SIZE {
+            return;
         }
+        let removed = self.headers.len() - HEADERS_WINDOW_SIZE;
+        self.headers.drain(..removed);
+        self.chain_work.drain(..removed);
+        self.window_start += removed;
+    }
 
-        Ok(())
+    /// Devuelve el trabajo acumulado por la cadena de headers del nodo.
+    pub fn chain_work(&self) -> ChainWork {
+        self.chain_work.last().copied().unwrap_or(ChainWork::ZERO)
     }
 
-    fn save(&self, headers: &Vec<BlockHeader>) -> Result<(), CustomError> {
-        let mut file = open_new_file(self.path.clone(), true)?;
-        let mut buffer = vec![];
-        for header in headers {
-            let header_buffer: Vec<u8> = header.serialize_for_backup();
-            buffer.extend(header_buffer);
+    /// Devuelve el trabajo acumulado por los primeros len headers de la cadena.
+    /// Si el ultimo de ellos no esta en memoria, se lee del archivo.
+    pub fn chain_work_at(&self, len: usize) -> Result<ChainWork, CustomError> {
+        let Some(index) = len.checked_sub(1) else { return Ok(ChainWork::ZERO) };
+        if let Some(work) = index
+            .checked_sub(self.window_start)
+            .and_then(|index| self.chain_work.get(index))
+        {
+            return Ok(*work);
         }
-
-        file.write_all(buffer.as_slice())?;
-        Ok(())
+        let (_, chain_work) = self.store.read_range_with_chain_work(index, 1)?;
+        Ok(chain_work.last().copied().unwrap_or(ChainWork::ZERO))
     }
 
-    fn len(&self) -> usize {
-        self.headers.len()
+    /// Devuelve la posicion del ultimo header con timestamp anterior o igual al inicio del IBD, 0 si no hay ninguno.
+    pub fn ibd_start_index(&self) -> usize {
+        self.ibd_index
     }
 
     /// Devuelve la cantidad de headers posteriores a la fecha de inicio del IBD.
     pub fn total_headers_to_download(&self) -> usize {
-        self.len() - calculate_index_from_timestamp(&self.headers, START_DATE_IBD)
-    }
-
-    /// Devuelve todos los headers del nodo.
-    pub fn get_all(&self) -> &Vec<BlockHeader> {
-        &self.headers
+        self.len() - self.ibd_index
     }
 
-    /// Devuelve la posicion de un header en el vector de headers del nodo dado el hash del mismo.
+    /// Devuelve la posicion de un header en la cadena del nodo dado el hash del mismo.
     /// Si no se encuentra el header, devuelve 0.
-    pub fn get_header_index(&self, block_hash: &Vec<u8>) -> usize {
-        let position_from_end = self
-            .headers
-            .iter()
-            .rev()
-            .position(|header| header.hash() == block_hash);
-
-        if let Some(position_from_end) = position_from_end {
-            return self.headers.len() - position_from_end - 1;
-        }
+    pub fn get_header_index(&self, block_hash: &Hash256) -> usize {
+        self.find_header_position(block_hash).unwrap_or(0)
+    }
 
-        0
+    /// Devuelve la posicion de un header en la cadena del nodo dado el hash del mismo,
+    /// o None si no se encuentra.
+    pub fn find_header_position(&self, block_hash: &Hash256) -> Option<usize> {
+        self.positions.get(block_hash).copied()
     }
 
     /// Devuelve el hash del ultimo header del nodo.
-    pub fn get_last_header_hash(&self) -> Option<Vec<u8>> {
-        self.headers.last().map(|header| header.hash().clone())
+    pub fn get_last_header_hash(&self) -> Option<Hash256> {
+        self.headers.last().map(|header| *header.hash())
     }
 
-    /// Devuelve los ultimos count headers del nodo junto a su height.
+    /// Devuelve los ultimos count headers del nodo junto a su height, como maximo los que estan en memoria.
     pub fn get_last_headers(&self, count: usize) -> Vec<(usize, BlockHeader)> {
         let mut last_headers = vec![];
 
@@ -143,7 +166,9 @@ impl HeadersState {
             .enumerate()
             .rev()
             .take(count)
-            .for_each(|(index, header)| last_headers.push((index + 1, header.clone())));
+            .for_each(|(index, header)| {
+                last_headers.push((self.window_start + index + 1, header.clone()))
+            });
 
         last_headers
     }
@@ -154,11 +179,11 @@ impl HeadersState {
         if let Some(first_header) = headers.first() {
             let last_header = self.headers.last();
             let last_header_hash = last_header
-                .map(|header| header.hash().clone())
-                .unwrap_or(GENESIS.to_vec());
+                .map(|header| *header.hash())
+                .unwrap_or(Network::current().genesis_hash());
 
             if last_header_hash != first_header.prev_block_hash {
-                return Err(CustomError::BlockChainBroken);
+                return Err(CustomError::Chain(ChainError::BlockChainBroken));
             }
 
             let percentage = self.calculate_percentage_downloaded(first_header.timestamp)?;
@@ -167,19 +192,82 @@ impl HeadersState {
             }
         }
 
-        self.save(&headers)?;
+        let mut total_work = self.chain_work();
+        let mut chain_work: Vec<ChainWork> = headers
+            .iter()
+            .map(|header| {
+                total_work = total_work + ChainWork::of(std::slice::from_ref(header));
+                total_work
+            })
+            .collect();
+        self.store.append(&headers, &chain_work)?;
+        let ibd_start_timestamp = Network::current().ibd_start_timestamp();
+        for (index, header) in headers.iter().enumerate() {
+            let position = self.len() + index;
+            self.positions.insert(*header.hash(), position);
+            if header.timestamp <= ibd_start_timestamp {
+                self.ibd_index = position;
+            }
+        }
         let headers_count = headers.len();
         self.headers.append(&mut headers);
+        self.chain_work.append(&mut chain_work);
+        self.trim_window();
 
         self.print_status(headers_count)?;
         self.verify_headers_sync(headers_count)?;
         Ok(())
     }
 
+    /// Descarta los headers posteriores a la posicion len, tanto en memoria como en el archivo.
+    /// Devuelve los headers descartados, del mas antiguo al mas reciente.
+    pub fn rollback(&mut self, len: usize) -> Result<Vec<BlockHeader>, CustomError> {
+        if len >= self.len() {
+            return Ok(vec![]);
+        }
+        let removed = self.read_range(len, self.len() - len)?;
+        self.store.truncate(len)?;
+        for header in &removed {
+            self.positions.remove(header.hash());
+        }
+        match len.checked_sub(self.window_start) {
+            Some(window_len) => {
+                self.headers.truncate(window_len);
+                self.chain_work.truncate(window_len);
+            }
+            None => self.load_window(len)?,
+        }
+        if self.ibd_index >= len {
+            self.ibd_index = self.index_from_timestamp(Network::current().ibd_start_timestamp())?;
+        }
+
+        send_log(
+            &self.logger_sender,
+            Log::Message(format!(
+                "Headers rolled back: {}, total {}",
+                removed.len(),
+                self.len()
+            )),
+        );
+        Ok(removed)
+    }
+
+    /// Devuelve el block locator de la cadena de headers del nodo, ver block_locator.
+    /// Los headers del locator que no estan en memoria se leen del archivo.
+    pub fn get_block_locator(&self) -> Result<Vec<Hash256>, CustomError> {
+        let mut locator = vec![];
+        for index in locator_indexes(self.len()) {
+            if let Some(header) = self.get(index)? {
+                locator.push(*header.hash());
+            }
+        }
+        locator.push(Network::current().genesis_hash());
+        Ok(locator)
+    }
+
     fn calculate_percentage_downloaded(&self, received_timestamp: u32) -> Result<u64, CustomError> {
         let first_timestamp = self
-            .headers
-            .first()
+            .get(0)?
             .map(|header| header.timestamp)
             .unwrap_or(received_timestamp) as u64;
 
@@ -209,7 +297,7 @@ impl HeadersState {
                 Log::Message(format!(
                     "New headers: {}, total {}",
                     headers_count,
-                    self.headers.len()
+                    self.len()
                 )),
             );
         } else {
@@ -222,6 +310,7 @@ impl HeadersState {
     fn print_stats(&mut self, headers_count: usize) -> Result<(), CustomError> {
         let last_timestamp = self.headers.last().map(|h| h.timestamp).unwrap_or(0);
         let percentage = self.calculate_percentage_downloaded(last_timestamp)?;
+        let total = self.len();
 
         if let Some(ibd_stats) = &mut self.ibd_stats {
             ibd_stats.checkpoint_downloads += headers_count as u128;
@@ -241,7 +330,7 @@ impl HeadersState {
                         "Headers sync {}% at {} headers/s... total {}",
                         percentage,
                         headers_per_second,
-                        self.headers.len(),
+                        total,
                     )),
                 );
 
@@ -254,8 +343,13 @@ impl HeadersState {
         Ok(())
     }
 
-    /// Registra que un header tiene su bloque descargado.
-    pub fn set_downloaded(&mut self, block_hash: &Vec<u8>) {
+    /// Fuerza la escritura en disco del archivo de headers.
+    pub fn flush(&self) -> Result<(), CustomError> {
+        self.store.sync()
+    }
+
+    /// Registra que un header en memoria tiene su bloque descargado.
+    pub fn set_downloaded(&mut self, block_hash: &Hash256) {
         let downloaded_block = self
             .headers
             .iter_mut()
@@ -270,18 +364,19 @@ impl HeadersState {
     /// Devuelve un vector de headers que se deben enviar a un nodo a partir de un header hash.
     /// Los headers se envian unicamente si tienen al bloque anterior a ellos enviado y su bloque descargado.
     /// Todos los headers obtenidos se marcan como enviados.
-    pub fn get_headers_to_send(&mut self, block_hash: &Vec<u8>) -> Vec<BlockHeader> {
+    /// Solo se envian headers en memoria, los anteriores se leyeron del archivo y ya se consideran enviados.
+    pub fn get_headers_to_send(&mut self, block_hash: &Hash256) -> Vec<BlockHeader> {
         let downloaded_block_index = self.get_header_index(block_hash);
+        let Some(window_index) = downloaded_block_index.checked_sub(self.window_start) else { return vec![] };
 
-        let mut next_to_send = downloaded_block_index == 0;
-        if !next_to_send {
-            let downloaded_block_prev = self.headers[downloaded_block_index - 1].clone();
-            next_to_send = downloaded_block_prev.broadcasted
+        let next_to_send = match window_index.checked_sub(1) {
+            Some(prev_index) => self.headers[prev_index].broadcasted,
+            None => true,
         };
 
         let mut headers_to_send = vec![];
         if next_to_send {
-            for header in self.headers.iter_mut().skip(downloaded_block_index) {
+            for header in self.headers.iter_mut().skip(window_index) {
                 if header.block_downloaded {
                     headers_to_send.push(header.clone());
                     header.broadcasted = true;
@@ -313,58 +408,60 @@ impl HeadersState {
         self.sync
     }
 
-    /// Ante un mensaje get headers, devuelve los headers esperados de acuerdo al protocolo btc.
-    pub fn get_headers(&self, get_headers: GetHeaders) -> Vec<BlockHeader> {
-        let peer_last_header = get_headers
+    /// Ante un mensaje get headers, devuelve los headers esperados de acuerdo al protocolo btc:
+    /// los siguientes al primer hash del block locator que pertenece a la cadena, que va del mas nuevo al mas viejo,
+    /// hasta el header hash_stop inclusive o hasta MAX_HEADERS_PER_MESSAGE headers.
+    /// Si ningun hash pertenece a la cadena, se devuelven los headers desde el genesis.
+    pub fn get_headers(&self, get_headers: GetHeaders) -> Result<Vec<BlockHeader>, CustomError> {
+        let genesis_hash = Network::current().genesis_hash();
+        let start = get_headers
             .block_locator_hashes
-            .last()
-            .unwrap_or(&GENESIS.to_vec())
-            .clone();
-        if let Some(last_header) = self.headers.last() {
-            if peer_last_header == *last_header.hash() {
-                return vec![];
-            }
-        }
-
-        if peer_last_header == GENESIS.to_vec() {
-            return self.first_headers(get_headers.hash_stop);
-        }
-
-        self.get_requested_headers(peer_last_header, get_headers.hash_stop)
-    }
+            .iter()
+            .find_map(|hash| match *hash == genesis_hash {
+                true => Some(0),
+                false => self.find_header_position(hash).map(|position| position + 1),
+            })
+            .unwrap_or(0);
 
-    fn get_requested_headers(
-        &self,
-        peer_last_header: Vec<u8>,
-        hash_stop: Vec<u8>,
-    ) -> Vec<BlockHeader> {
         let mut headers = vec![];
-        let mut found = false;
-        for header in &self.headers {
-            if header.prev_block_hash == peer_last_header {
-                found = true;
-            }
-            if found {
-                headers.push(header.clone());
-            }
-            if headers.len() == 2000 || *header.hash() == hash_stop {
+        for header in self.read_range(start, MAX_HEADERS_PER_MESSAGE)? {
+            let is_stop = *header.hash() == get_headers.hash_stop;
+            headers.push(header);
+            if is_stop {
                 break;
             }
         }
+        Ok(headers)
+    }
+}
 
-        if !found {
-            return self.first_headers(hash_stop);
+impl HeaderChain for HeadersState {
+    fn len(&self) -> usize {
+        self.window_start + self.headers.len()
+    }
+
+    /// Devuelve hasta count headers a partir de la posicion start, leyendo del archivo los que no estan en memoria.
+    fn read_range(&self, start: usize, count: usize) -> Result<Vec<BlockHeader>, CustomError> {
+        let end = start.saturating_add(count).min(self.len());
+        if start >= end {
+            return Ok(vec![]);
         }
-        headers
+        let mut headers = match start < self.window_start {
+            true => self
+                .store
+                .read_range(start, self.window_start.min(end) - start)?,
+            false => vec![],
+        };
+        if end > self.window_start {
+            let window_from = start.saturating_sub(self.window_start);
+            headers.extend_from_slice(&self.headers[window_from..end - self.window_start]);
+        }
+        Ok(headers)
     }
 
-    fn first_headers(&self, hash_stop: Vec<u8>) -> Vec<BlockHeader> {
-        self.headers
-            .iter()
-            .take(2000)
-            .take_while(|block| block.hash != hash_stop)
-            .cloned()
-            .collect()
+    /// Devuelve la posicion del header con el hash recibido, o None si no esta en la cadena.
+    fn find_position(&self, block_hash: &Hash256) -> Result<Option<usize>, CustomError> {
+        Ok(self.find_header_position(block_hash))
     }
 }
 
@@ -396,7 +493,7 @@ mod tests {
         let headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
         let headers_to_download = headers.total_headers_to_download();
-        // 2 headers in the file, only one after START_DATE_IBD
+        // 2 headers in the file, only one after the IBD start
         assert_eq!(headers_to_download, 1);
     }
 
@@ -406,12 +503,86 @@ mod tests {
         let headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let first_hash = headers.headers[0].hash.clone();
-        let second_hash = headers.headers[1].hash.clone();
+        let first_hash = headers.headers[0].hash;
+        let second_hash = headers.headers[1].hash;
 
         assert_eq!(headers.get_header_index(&first_hash), 0);
         assert_eq!(headers.get_header_index(&second_hash), 1);
-        assert_eq!(headers.get_header_index(&vec![0; 32]), 0);
+        assert_eq!(headers.get_header_index(&Hash256::new([0; 32])), 0);
+    }
+
+    #[test]
+    fn headers_find_header_position() {
+        let (logger_sender, _) = mpsc::channel();
+        let headers =
+            HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
+
+        let second_hash = headers.headers[1].hash;
+        assert_eq!(headers.find_header_position(&second_hash), Some(1));
+        assert_eq!(headers.find_header_position(&Hash256::new([0; 32])), None);
+    }
+
+    #[test]
+    fn headers_rollback() {
+        let (logger_sender, _) = mpsc::channel();
+        fs::copy("tests/test_headers.bin", "tests/test_headers_rollback.bin").unwrap();
+        let mut headers =
+            HeadersState::new("tests/test_headers_rollback.bin".to_string(), logger_sender)
+                .unwrap();
+        let second_hash = headers.headers[1].hash;
+
+        let chain_work = headers.chain_work_at(1).unwrap();
+        assert!(headers.chain_work() > chain_work);
+
+        let removed = headers.rollback(1).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(headers.chain_work(), chain_work);
+        assert_eq!(removed[0].hash, second_hash);
+        assert_eq!(headers.headers.len(), 1);
+        assert_eq!(headers.find_header_position(&second_hash), None);
+        assert!(headers.rollback(1).unwrap().is_empty());
+
+        let (logger_sender, _) = mpsc::channel();
+        let restored =
+            HeadersState::new("tests/test_headers_rollback.bin".to_string(), logger_sender)
+                .unwrap();
+        assert_eq!(restored.headers.len(), 1);
+        assert_eq!(restored.chain_work(), chain_work);
+
+        remove_file("tests/test_headers_rollback.bin").unwrap();
+    }
+
+    #[test]
+    fn headers_get_block_locator() {
+        let (logger_sender, _) = mpsc::channel();
+        let mut headers =
+            HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
+        assert_eq!(
+            headers.get_block_locator().unwrap(),
+            vec![
+                headers.headers[1].hash,
+                headers.headers[0].hash,
+                Network::current().genesis_hash()
+            ]
+        );
+
+        let header = headers.headers[0].clone();
+        headers.headers = (0..30)
+            .map(|index| {
+                let mut header = header.clone();
+                header.hash = Hash256::new([index; 32]);
+                header
+            })
+            .collect();
+        let locator = headers.get_block_locator().unwrap();
+        let heights: Vec<u8> = locator[..locator.len() - 1]
+            .iter()
+            .map(|hash| hash.as_bytes()[0] + 1)
+            .collect();
+        assert_eq!(
+            heights,
+            vec![30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 19, 15, 7]
+        );
     }
 
     #[test]
@@ -420,8 +591,8 @@ mod tests {
         let headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let first_hash = headers.headers[0].hash.clone();
-        let second_hash = headers.headers[1].hash.clone();
+        let first_hash = headers.headers[0].hash;
+        let second_hash = headers.headers[1].hash;
 
         let last_header_zero = headers.get_last_headers(0);
         let last_headers_one = headers.get_last_headers(1);
@@ -455,7 +626,8 @@ mod tests {
         headers.headers[1].block_downloaded = false;
 
         assert_eq!(headers.headers[1].block_downloaded, false);
-        headers.set_downloaded(&headers.headers[1].hash.clone());
+        let block_hash = headers.headers[1].hash;
+        headers.set_downloaded(&block_hash);
         assert_eq!(headers.headers[1].block_downloaded, true);
     }
 
@@ -465,14 +637,16 @@ mod tests {
         let mut headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let first_hash = headers.headers[0].hash.clone();
+        let first_hash = headers.headers[0].hash;
         headers.headers[0].block_downloaded = false;
         headers.headers[1].block_downloaded = false;
 
         headers.headers[0].broadcasted = false;
         headers.headers[1].broadcasted = false;
 
-        headers.set_downloaded(&headers.headers[0].hash.clone());
+        let block_hash = headers.headers[0].hash;
+
+        headers.set_downloaded(&block_hash);
 
         let headers_to_send = headers.get_headers_to_send(&first_hash);
         assert_eq!(headers_to_send.len(), 1);
@@ -485,14 +659,16 @@ mod tests {
         let mut headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let second_hash = headers.headers[1].hash.clone();
+        let second_hash = headers.headers[1].hash;
         headers.headers[0].block_downloaded = true;
         headers.headers[1].block_downloaded = false;
 
         headers.headers[0].broadcasted = true;
         headers.headers[1].broadcasted = false;
 
-        headers.set_downloaded(&headers.headers[1].hash.clone());
+        let block_hash = headers.headers[1].hash;
+
+        headers.set_downloaded(&block_hash);
 
         let headers_to_send = headers.get_headers_to_send(&second_hash);
         assert_eq!(headers_to_send.len(), 1);
@@ -505,15 +681,17 @@ mod tests {
         let mut headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let first_hash = headers.headers[0].hash.clone();
-        let second_hash = headers.headers[1].hash.clone();
+        let first_hash = headers.headers[0].hash;
+        let second_hash = headers.headers[1].hash;
         headers.headers[0].block_downloaded = false;
         headers.headers[1].block_downloaded = true;
 
         headers.headers[0].broadcasted = false;
         headers.headers[1].broadcasted = false;
 
-        headers.set_downloaded(&headers.headers[0].hash.clone());
+        let block_hash = headers.headers[0].hash;
+
+        headers.set_downloaded(&block_hash);
         let headers_to_send = headers.get_headers_to_send(&first_hash);
 
         assert_eq!(headers_to_send.len(), 2);
@@ -527,14 +705,16 @@ mod tests {
         let mut headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let second_hash = headers.headers[1].hash.clone();
+        let second_hash = headers.headers[1].hash;
         headers.headers[0].block_downloaded = false;
         headers.headers[1].block_downloaded = false;
 
         headers.headers[0].broadcasted = false;
         headers.headers[1].broadcasted = false;
 
-        headers.set_downloaded(&headers.headers[1].hash.clone());
+        let block_hash = headers.headers[1].hash;
+
+        headers.set_downloaded(&block_hash);
         let headers_to_send = headers.get_headers_to_send(&second_hash);
 
         assert_eq!(headers_to_send.len(), 0);
@@ -562,12 +742,18 @@ mod tests {
     }
 
     #[test]
-    fn headers_get_all() {
+    fn headers_read_range() {
         let (logger_sender, _) = mpsc::channel();
         let headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        assert_eq!(headers.get_all().len(), 2);
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.read_range(0, 10).unwrap().len(), 2);
+        assert_eq!(
+            headers.read_range(1, 10).unwrap()[0].hash,
+            headers.headers[1].hash
+        );
+        assert!(headers.read_range(2, 10).unwrap().is_empty());
     }
 
     #[test]
@@ -578,10 +764,10 @@ mod tests {
 
         assert_eq!(
             headers.get_last_header_hash().unwrap(),
-            vec![
+            Hash256::new([
                 32, 120, 42, 0, 82, 85, 182, 87, 105, 110, 160, 87, 213, 185, 143, 52, 222, 252,
                 247, 81, 150, 246, 79, 110, 234, 200, 2, 108, 0, 0, 0, 0
-            ]
+            ])
         );
     }
 
@@ -594,16 +780,16 @@ mod tests {
 
         let mut new_headers = Headers::new();
         new_headers.headers.push(BlockHeader {
-            prev_block_hash: vec![
+            prev_block_hash: Hash256::new([
                 32, 120, 42, 0, 82, 85, 182, 87, 105, 110, 160, 87, 213, 185, 143, 52, 222, 252,
                 247, 81, 150, 246, 79, 110, 234, 200, 2, 108, 0, 0, 0, 0,
-            ],
-            merkle_root: vec![],
+            ]),
+            merkle_root: Hash256::ZERO,
             version: 0,
             timestamp: 1677449562,
             bits: 0,
             nonce: 0,
-            hash: vec![],
+            hash: Hash256::ZERO,
             block_downloaded: true,
             broadcasted: true,
         });
@@ -623,13 +809,13 @@ mod tests {
 
         let mut new_headers = Headers::new();
         new_headers.headers.push(BlockHeader {
-            prev_block_hash: vec![1, 2, 3],
-            merkle_root: vec![],
+            prev_block_hash: Hash256::new([1; 32]),
+            merkle_root: Hash256::ZERO,
             version: 0,
             timestamp: 1677449562,
             bits: 0,
             nonce: 0,
-            hash: vec![],
+            hash: Hash256::ZERO,
             block_downloaded: true,
             broadcasted: true,
         });
@@ -664,11 +850,15 @@ mod tests {
         let headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let getheaders = GetHeaders::new(1, vec![], vec![0; 32]);
-        assert_eq!(headers.get_headers(getheaders).len(), 2);
+        let getheaders = GetHeaders::new(1, vec![], Hash256::new([0; 32]));
+        assert_eq!(headers.get_headers(getheaders).unwrap().len(), 2);
 
-        let getheaders = GetHeaders::new(1, vec![GENESIS.to_vec()], vec![0; 32]);
-        assert_eq!(headers.get_headers(getheaders).len(), 2);
+        let getheaders = GetHeaders::new(
+            1,
+            vec![Network::current().genesis_hash()],
+            Hash256::new([0; 32]),
+        );
+        assert_eq!(headers.get_headers(getheaders).unwrap().len(), 2);
     }
 
     #[test]
@@ -680,9 +870,9 @@ mod tests {
         let getheaders = GetHeaders::new(
             1,
             vec![headers.get_last_header_hash().unwrap()],
-            vec![0; 32],
+            Hash256::new([0; 32]),
         );
-        assert_eq!(headers.get_headers(getheaders).len(), 0);
+        assert_eq!(headers.get_headers(getheaders).unwrap().len(), 0);
     }
 
     #[test]
@@ -691,26 +881,26 @@ mod tests {
         let mut headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let second_hash = headers.headers[1].hash.clone();
+        let second_hash = headers.headers[1].hash;
 
         let new_header = BlockHeader {
-            prev_block_hash: vec![
+            prev_block_hash: Hash256::new([
                 32, 120, 42, 0, 82, 85, 182, 87, 105, 110, 160, 87, 213, 185, 143, 52, 222, 252,
                 247, 81, 150, 246, 79, 110, 234, 200, 2, 108, 0, 0, 0, 0,
-            ],
-            merkle_root: vec![],
+            ]),
+            merkle_root: Hash256::ZERO,
             version: 0,
             timestamp: 1677449562,
             bits: 0,
             nonce: 0,
-            hash: vec![1, 2, 3],
+            hash: Hash256::new([1; 32]),
             block_downloaded: true,
             broadcasted: true,
         };
         headers.headers.push(new_header.clone());
 
-        let getheaders = GetHeaders::new(1, vec![headers.headers[0].hash.clone()], vec![0; 32]);
-        let getheaders = headers.get_headers(getheaders);
+        let getheaders = GetHeaders::new(1, vec![headers.headers[0].hash], Hash256::new([0; 32]));
+        let getheaders = headers.get_headers(getheaders).unwrap();
         assert_eq!(getheaders.len(), 2);
         assert_eq!(getheaders[0].hash, second_hash);
         assert_eq!(getheaders[1].hash, new_header.hash);
@@ -722,30 +912,26 @@ mod tests {
         let mut headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let second_hash = headers.headers[1].hash.clone();
+        let second_hash = headers.headers[1].hash;
 
         let new_header = BlockHeader {
-            prev_block_hash: vec![
+            prev_block_hash: Hash256::new([
                 32, 120, 42, 0, 82, 85, 182, 87, 105, 110, 160, 87, 213, 185, 143, 52, 222, 252,
                 247, 81, 150, 246, 79, 110, 234, 200, 2, 108, 0, 0, 0, 0,
-            ],
-            merkle_root: vec![],
+            ]),
+            merkle_root: Hash256::ZERO,
             version: 0,
             timestamp: 1677449562,
             bits: 0,
             nonce: 0,
-            hash: vec![1, 2, 3],
+            hash: Hash256::new([1; 32]),
             block_downloaded: true,
             broadcasted: true,
         };
         headers.headers.push(new_header.clone());
 
-        let getheaders = GetHeaders::new(
-            1,
-            vec![headers.headers[0].hash.clone()],
-            second_hash.clone(),
-        );
-        let getheaders = headers.get_headers(getheaders);
+        let getheaders = GetHeaders::new(1, vec![headers.headers[0].hash], second_hash);
+        let getheaders = headers.get_headers(getheaders).unwrap();
         assert_eq!(getheaders.len(), 1);
         assert_eq!(getheaders[0].hash, second_hash);
     }
@@ -756,13 +942,153 @@ mod tests {
         let headers =
             HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
 
-        let first_hash = headers.headers[0].hash.clone();
-        let second_hash = headers.headers[1].hash.clone();
+        let first_hash = headers.headers[0].hash;
+        let second_hash = headers.headers[1].hash;
 
-        let getheaders = GetHeaders::new(1, vec![vec![1, 2, 3]], vec![0; 32]);
-        let getheaders = headers.get_headers(getheaders);
+        let getheaders = GetHeaders::new(1, vec![Hash256::new([1; 32])], Hash256::new([0; 32]));
+        let getheaders = headers.get_headers(getheaders).unwrap();
         assert_eq!(getheaders.len(), 2);
         assert_eq!(getheaders[0].hash, first_hash);
         assert_eq!(getheaders[1].hash, second_hash);
     }
+
+    /// Agrega count headers encadenados al final de los headers en memoria, sin guardarlos en el archivo.
+    fn push_headers(headers: &mut HeadersState, count: usize) {
+        for index in 0..count {
+            let mut hash = [2; 32];
+            hash[..8].copy_from_slice(&(index as u64).to_le_bytes());
+            let position = headers.len();
+            headers.positions.insert(Hash256::new(hash), position);
+            headers.headers.push(BlockHeader {
+                prev_block_hash: *headers.headers.last().unwrap().hash(),
+                merkle_root: Hash256::ZERO,
+                version: 0,
+                timestamp: 1677449562,
+                bits: 0,
+                nonce: 0,
+                hash: Hash256::new(hash),
+                block_downloaded: true,
+                broadcasted: true,
+            });
+        }
+    }
+
+    #[test]
+    fn headers_get_headers_from_the_newest_known_locator_hash() {
+        let (logger_sender, _) = mpsc::channel();
+        let mut headers =
+            HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
+        push_headers(&mut headers, 5);
+
+        // el peer esta en un fork desconocido a partir del header 4
+        let getheaders = GetHeaders::new(
+            1,
+            vec![
+                Hash256::new([1; 32]),
+                headers.headers[3].hash,
+                headers.headers[1].hash,
+                Network::current().genesis_hash(),
+            ],
+            Hash256::ZERO,
+        );
+        let getheaders = headers.get_headers(getheaders).unwrap();
+        assert_eq!(getheaders.len(), 3);
+        assert_eq!(getheaders[0].hash, headers.headers[4].hash);
+        assert_eq!(getheaders[2].hash, headers.headers[6].hash);
+    }
+
+    #[test]
+    fn headers_get_headers_sends_at_most_2000_headers() {
+        let (logger_sender, _) = mpsc::channel();
+        let mut headers =
+            HeadersState::new("tests/test_headers.bin".to_string(), logger_sender).unwrap();
+        push_headers(&mut headers, 2500);
+
+        let getheaders = GetHeaders::new(1, vec![headers.headers[1].hash], Hash256::ZERO);
+        let getheaders = headers.get_headers(getheaders).unwrap();
+        assert_eq!(getheaders.len(), MAX_HEADERS_PER_MESSAGE);
+        assert_eq!(getheaders[0].hash, headers.headers[2].hash);
+
+        // el peer pide los siguientes a partir del ultimo header recibido
+        let getheaders = GetHeaders::new(1, vec![getheaders[1999].hash], Hash256::ZERO);
+        let getheaders = headers.get_headers(getheaders).unwrap();
+        assert_eq!(getheaders.len(), 500);
+        assert_eq!(getheaders[0].hash, headers.headers[2002].hash);
+    }
+
+    /// Mina count headers encadenados a partir del hash recibido, con la dificultad minima de regtest
+    /// para que se puedan leer del archivo.
+    fn mine_headers(prev_block_hash: Hash256, count: usize) -> Vec<BlockHeader> {
+        let mut headers: Vec<BlockHeader> = vec![];
+        for index in 0..count {
+            let mut header = BlockHeader {
+                prev_block_hash: headers.last().map_or(prev_block_hash, |last| last.hash),
+                merkle_root: Hash256::ZERO,
+                version: 1,
+                timestamp: Network::current().ibd_start_timestamp() + 1 + index as u32,
+                bits: 0x207fffff,
+                nonce: 0,
+                hash: Hash256::ZERO,
+                block_downloaded: true,
+                broadcasted: true,
+            };
+            headers.push(loop {
+                header.nonce += 1;
+                if let Ok(header) = BlockHeader::parse(header.serialize()) {
+                    break header;
+                }
+            });
+        }
+        headers
+    }
+
+    #[test]
+    fn headers_keep_a_bounded_window_in_memory() {
+        let (logger_sender, _) = mpsc::channel();
+        let path = "tests/test_headers_window.bin".to_string();
+        fs::copy("tests/test_headers.bin", &path).unwrap();
+        let mut headers = HeadersState::new(path.clone(), logger_sender).unwrap();
+        let first_hash = headers.headers[0].hash;
+        let last_hash = headers.get_last_header_hash().unwrap();
+
+        let mined = mine_headers(last_hash, 2 * HEADERS_WINDOW_SIZE + 1);
+        for batch in mined.chunks(MAX_HEADERS_PER_MESSAGE) {
+            headers.append_headers(batch.to_vec()).unwrap();
+        }
+        let len = mined.len() + 2;
+        assert_eq!(headers.len(), len);
+        assert_eq!(headers.headers.len(), HEADERS_WINDOW_SIZE);
+        assert_eq!(headers.window_start, len - HEADERS_WINDOW_SIZE);
+        let chain_work = headers.chain_work();
+
+        // al reiniciar solo se cargan los ultimos headers, los anteriores se leen del archivo
+        let (logger_sender, _) = mpsc::channel();
+        let mut restored = HeadersState::new(path.clone(), logger_sender).unwrap();
+        assert_eq!(restored.len(), len);
+        assert_eq!(restored.headers.len(), HEADERS_WINDOW_SIZE);
+        assert_eq!(restored.chain_work(), chain_work);
+        assert_eq!(restored.find_header_position(&first_hash), Some(0));
+        assert_eq!(restored.read_range(0, 1).unwrap()[0].hash, first_hash);
+        assert_eq!(restored.get(2).unwrap().unwrap().hash, mined[0].hash);
+        assert_eq!(
+            restored.chain_work_at(3).unwrap(),
+            headers.chain_work_at(2).unwrap() + ChainWork::of(&mined[..1])
+        );
+        assert_eq!(
+            restored.get_block_locator().unwrap(),
+            headers.get_block_locator().unwrap()
+        );
+        assert_eq!(restored.total_headers_to_download(), len - 1);
+
+        // al descartar headers anteriores a la ventana se vuelve a cargar desde el archivo
+        let removed = restored.rollback(10).unwrap();
+        assert_eq!(removed.len(), len - 10);
+        assert_eq!(removed[0].hash, mined[8].hash);
+        assert_eq!(restored.len(), 10);
+        assert_eq!(restored.window_start, 0);
+        assert_eq!(restored.get_last_header_hash(), Some(mined[7].hash));
+        assert_eq!(restored.find_header_position(&mined[8].hash), None);
+
+        remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,564 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use chrono::{TimeZone, Utc};
+
+use crate::{
+    atomic_file::{read_atomic, write_atomic},
+    error::CustomError,
+    parser::{BufferParser, Parser},
+    structs::{hash256::Hash256, movement::Movement, script::classify::OutputType},
+    utils::open_new_file,
+};
+
+/// Largo maximo en bytes de la etiqueta de una transaccion, ya que se serializa con un byte de largo.
+const MAX_LABEL_LENGTH: usize = 255;
+/// Cantidad de satoshis en un bitcoin, para exportar los montos en BTC.
+const SATOSHIS_PER_BITCOIN: u64 = 100_000_000;
+/// Primera fila del CSV exportado, con los nombres de las columnas.
+const CSV_HEADER: &str = "date,txid,direction,amount,fee,confirmations,label";
+
+/// Direction es el sentido de una transaccion respecto a la wallet.
+/// - Received: La transaccion aumenta el balance de la wallet.
+/// - Sent: La transaccion disminuye el balance de la wallet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+/// HistoryEntry es una transaccion del historial de la wallet.
+/// Los elementos son:
+/// - tx_hash: Hash de la transaccion.
+/// - direction: Sentido de la transaccion.
+/// - amount: Variacion del balance de la wallet en satoshis, en valor absoluto.
+/// - block_hash: Hash del bloque que confirma la transaccion, None si esta pendiente.
+/// - block_height: Height del bloque que confirma la transaccion, None si esta pendiente.
+/// - timestamp: Timestamp del bloque que confirma la transaccion, None si esta pendiente.
+/// - address: Direccion de la otra parte de la transaccion, None si no se conoce.
+/// - output_type: Tipo del output de esa direccion, None si no se conoce.
+/// - label: Etiqueta que el usuario le puso a la transaccion, None si no tiene.
+/// - fee: Fee que pago la wallet al enviar la transaccion, None si la recibio o no se conoce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub tx_hash: Hash256,
+    pub direction: Direction,
+    pub amount: u64,
+    pub block_hash: Option<Hash256>,
+    pub block_height: Option<usize>,
+    pub timestamp: Option<u32>,
+    pub address: Option<String>,
+    pub output_type: Option<OutputType>,
+    pub label: Option<String>,
+    pub fee: Option<u64>,
+}
+
+impl HistoryEntry {
+    /// Devuelve la cantidad de confirmaciones de la transaccion en una cadena con best_height bloques.
+    /// Las transacciones pendientes tienen 0 confirmaciones.
+    pub fn confirmations(&self, best_height: usize) -> usize {
+        match self.block_height {
+            Some(block_height) if block_height <= best_height => best_height - block_height + 1,
+            _ => 0,
+        }
+    }
+}
+
+/// History es el historial de transacciones de cada wallet cargada, por su public key.
+/// Se reconstruye a partir de los movimientos de cada wallet y sus transacciones pendientes,
+/// agrupando los movimientos de una misma transaccion, por lo que cambiar la wallet activa no requiere escanear.
+/// Las etiquetas de las transacciones no se pueden reconstruir, por lo que se guardan en disco cada vez que se modifican.
+/// Los elementos son:
+/// - entries: Transacciones de cada wallet, primero las pendientes y luego de la mas reciente a la mas antigua.
+/// - pending: Movimientos de las pending txs de cada wallet.
+/// - best_height: Height del ultimo header de la cadena, para calcular las confirmaciones.
+/// - labels: Etiqueta de cada transaccion etiquetada, por su hash.
+/// - labels_path: Path del archivo donde se guardan las etiquetas, None si no se guardan.
+pub struct History {
+    entries: HashMap<String, Vec<HistoryEntry>>,
+    pending: HashMap<String, Vec<Movement>>,
+    best_height: usize,
+    labels: HashMap<Hash256, String>,
+    labels_path: Option<String>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    /// Inicializa el historial sin transacciones, con etiquetas que no se guardan en disco.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            pending: HashMap::new(),
+            best_height: 0,
+            labels: HashMap::new(),
+            labels_path: None,
+        }
+    }
+
+    /// Inicializa el historial sin transacciones, restaurando las etiquetas del archivo donde se guardan.
+    /// Devuelve CustomError si el archivo no es valido.
+    pub fn with_labels(path: String) -> Result<Self, CustomError> {
+        let buffer = read_atomic(&path)?;
+
+        let mut parser = BufferParser::new(&buffer);
+        let mut labels = HashMap::new();
+        while !parser.is_empty() {
+            let tx_hash_len = parser.extract_u8()? as usize;
+            let tx_hash = Hash256::from_slice(parser.extract_buffer(tx_hash_len)?)?;
+            let label_len = parser.extract_u8()? as usize;
+            labels.insert(tx_hash, parser.extract_string(label_len)?);
+        }
+        Ok(Self {
+            labels,
+            labels_path: Some(path),
+            ..Self::new()
+        })
+    }
+
+    /// Reemplaza el historial de una wallet con sus movimientos confirmados y pendientes.
+    /// Los movimientos pendientes de una transaccion que ya esta confirmada se descartan, al igual que los repetidos,
+    /// por lo que una transaccion pasa de pendiente a confirmada en cuanto se procesa su bloque.
+    /// block devuelve la height y el timestamp del bloque de un movimiento confirmado, o None si el bloque
+    /// no forma parte de la cadena, en cuyo caso el movimiento se descarta.
+    pub fn update<F>(
+        &mut self,
+        wallet_pubkey: &str,
+        confirmed: &[Movement],
+        pending: &[Movement],
+        block: F,
+    ) where
+        F: Fn(&Hash256) -> Option<(usize, u32)>,
+    {
+        let mut values: HashMap<Hash256, i64> = HashMap::new();
+        let mut blocks: HashMap<Hash256, (Hash256, usize, u32)> = HashMap::new();
+        let mut counterparts: HashMap<Hash256, (Option<String>, Option<OutputType>)> =
+            HashMap::new();
+        let mut fees: HashMap<Hash256, u64> = HashMap::new();
+        for movement in confirmed {
+            let Some(block_hash) = &movement.block_hash else { continue };
+            let Some((height, timestamp)) = block(block_hash) else { continue };
+            *values.entry(movement.tx_hash).or_insert(0) += movement.value;
+            blocks.insert(movement.tx_hash, (*block_hash, height, timestamp));
+            insert_counterpart(&mut counterparts, movement);
+            insert_fee(&mut fees, movement);
+        }
+        let mut pending_txs = HashSet::new();
+        let pending: Vec<Movement> = pending
+            .iter()
+            .filter(|movement| {
+                !blocks.contains_key(&movement.tx_hash) && pending_txs.insert(movement.tx_hash)
+            })
+            .cloned()
+            .collect();
+        for movement in &pending {
+            values.insert(movement.tx_hash, movement.value);
+            insert_counterpart(&mut counterparts, movement);
+            insert_fee(&mut fees, movement);
+        }
+
+        let mut entries: Vec<HistoryEntry> = values
+            .into_iter()
+            .map(|(tx_hash, value)| {
+                let (block_hash, block_height, timestamp) = match blocks.remove(&tx_hash) {
+                    Some((block_hash, height, timestamp)) => {
+                        (Some(block_hash), Some(height), Some(timestamp))
+                    }
+                    None => (None, None, None),
+                };
+                let (address, output_type) = counterparts.remove(&tx_hash).unwrap_or_default();
+                HistoryEntry {
+                    address,
+                    output_type,
+                    label: self.labels.get(&tx_hash).cloned(),
+                    fee: fees.remove(&tx_hash),
+                    timestamp,
+                    tx_hash,
+                    direction: match value < 0 {
+                        true => Direction::Sent,
+                        false => Direction::Received,
+                    },
+                    amount: value.unsigned_abs(),
+                    block_hash,
+                    block_height,
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| match (a.block_height, b.block_height) {
+            (None, None) => a.tx_hash.cmp(&b.tx_hash),
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (Some(a_height), Some(b_height)) => b_height
+                .cmp(&a_height)
+                .then_with(|| a.tx_hash.cmp(&b.tx_hash)),
+        });
+        self.entries.insert(wallet_pubkey.to_string(), entries);
+        self.pending.insert(wallet_pubkey.to_string(), pending);
+    }
+
+    /// Actualiza la height del ultimo header de la cadena.
+    pub fn set_best_height(&mut self, best_height: usize) {
+        self.best_height = best_height;
+    }
+
+    /// Devuelve la height del ultimo header de la cadena.
+    pub fn get_best_height(&self) -> usize {
+        self.best_height
+    }
+
+    /// Devuelve las transacciones del historial de una wallet.
+    pub fn get_entries(&self, wallet_pubkey: &str) -> &[HistoryEntry] {
+        self.entries
+            .get(wallet_pubkey)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Devuelve las transacciones pendientes del historial de una wallet, ordenadas por hash.
+    pub fn get_pending_entries(&self, wallet_pubkey: &str) -> &[HistoryEntry] {
+        let entries = self.get_entries(wallet_pubkey);
+        let pending = entries.partition_point(|entry| entry.block_height.is_none());
+        &entries[..pending]
+    }
+
+    /// Devuelve los movimientos de las pending txs de una wallet.
+    pub fn get_pending(&self, wallet_pubkey: &str) -> &[Movement] {
+        self.pending
+            .get(wallet_pubkey)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Le pone una etiqueta a una transaccion, reemplazando la que tenia, y la guarda.
+    /// Una etiqueta vacia elimina la que tenia la transaccion.
+    /// Devuelve CustomError si la etiqueta excede MAX_LABEL_LENGTH o no se pudo guardar.
+    pub fn set_label(&mut self, tx_hash: &Hash256, label: &str) -> Result<(), CustomError> {
+        let label = label.trim();
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(CustomError::Validation(String::from(
+                "Invalid transaction label",
+            )));
+        }
+        let label = match label.is_empty() {
+            true => {
+                self.labels.remove(tx_hash);
+                None
+            }
+            false => {
+                self.labels.insert(*tx_hash, label.to_string());
+                Some(label.to_string())
+            }
+        };
+        for entry in self.entries.values_mut().flatten() {
+            if entry.tx_hash == *tx_hash {
+                entry.label = label.clone();
+            }
+        }
+        self.save_labels()
+    }
+
+    /// Devuelve la etiqueta de una transaccion, si tiene.
+    pub fn get_label(&self, tx_hash: &Hash256) -> Option<&String> {
+        self.labels.get(tx_hash)
+    }
+
+    /// Exporta el historial de una wallet a un archivo CSV, reemplazando su contenido.
+    /// Las columnas son la fecha en UTC del bloque que confirma la transaccion, su hash, su sentido,
+    /// el monto y el fee en BTC, sus confirmaciones y su etiqueta. Las transacciones pendientes no tienen fecha
+    /// y las que no pagan fee la wallet no tienen fee.
+    /// Devuelve CustomError si no se pudo escribir el archivo.
+    pub fn export_csv(&self, wallet_pubkey: &str, path: String) -> Result<(), CustomError> {
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for entry in self.get_entries(wallet_pubkey) {
+            let date = entry
+                .timestamp
+                .and_then(|timestamp| Utc.timestamp_opt(timestamp as i64, 0).single())
+                .map(|datetime| datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            let direction = match entry.direction {
+                Direction::Received => "received",
+                Direction::Sent => "sent",
+            };
+            let fields = [
+                date,
+                entry.tx_hash.to_string(),
+                direction.to_string(),
+                format_btc(entry.amount),
+                entry.fee.map(format_btc).unwrap_or_default(),
+                entry.confirmations(self.best_height).to_string(),
+                csv_field(entry.label.as_deref().unwrap_or_default()),
+            ];
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+
+        let mut file = open_new_file(path, false)?;
+        file.set_len(0)?;
+        file.write_all(csv.as_bytes())?;
+        Ok(())
+    }
+
+    fn save_labels(&self) -> Result<(), CustomError> {
+        let Some(path) = &self.labels_path else { return Ok(()) };
+        let mut buffer = vec![];
+        for (tx_hash, label) in &self.labels {
+            buffer.push(tx_hash.as_bytes().len() as u8);
+            buffer.extend(tx_hash.as_bytes());
+            buffer.push(label.len() as u8);
+            buffer.extend(label.as_bytes());
+        }
+
+        write_atomic(path, &buffer)
+    }
+}
+
+/// Guarda la direccion y el tipo de output del movimiento como la otra parte de su transaccion,
+/// si los tiene y la transaccion todavia no tiene otra parte.
+fn insert_counterpart(
+    counterparts: &mut HashMap<Hash256, (Option<String>, Option<OutputType>)>,
+    movement: &Movement,
+) {
+    if movement.address.is_some() || movement.output_type.is_some() {
+        counterparts
+            .entry(movement.tx_hash)
+            .or_insert((movement.address.clone(), movement.output_type));
+    }
+}
+
+/// Guarda el fee del movimiento como el fee de su transaccion, si lo tiene.
+fn insert_fee(fees: &mut HashMap<Hash256, u64>, movement: &Movement) {
+    if let Some(fee) = movement.fee {
+        fees.entry(movement.tx_hash).or_insert(fee);
+    }
+}
+
+/// Devuelve un monto en satoshis en BTC, con los 8 decimales.
+fn format_btc(satoshis: u64) -> String {
+    format!(
+        "{}.{:08}",
+        satoshis / SATOSHIS_PER_BITCOIN,
+        satoshis % SATOSHIS_PER_BITCOIN
+    )
+}
+
+/// Devuelve un campo del CSV, entre comillas y con sus comillas duplicadas si tiene comas, comillas o saltos de linea.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{read_to_string, remove_file};
+
+    use crate::atomic_file::remove_atomic;
+
+    use super::*;
+
+    fn movement(tx_hash: u8, value: i64, block_hash: Option<u8>) -> Movement {
+        Movement {
+            tx_hash: Hash256::new([tx_hash; 32]),
+            value,
+            block_hash: block_hash.map(|block_hash| Hash256::new([block_hash; 32])),
+            address: Some(format!("address{}", tx_hash)),
+            output_type: Some(OutputType::P2WPKH),
+            fee: None,
+        }
+    }
+
+    fn block_height(block_hash: &Hash256) -> Option<(usize, u32)> {
+        match block_hash.as_bytes()[0] {
+            0 => None,
+            height => Some((height as usize, 1_700_000_000 + height as u32 * 600)),
+        }
+    }
+
+    #[test]
+    fn history_update() {
+        let mut history = History::new();
+        let confirmed = vec![
+            movement(1, 1000, Some(10)),
+            // dos outputs de la misma transaccion
+            movement(2, 300, Some(12)),
+            movement(2, 200, Some(12)),
+            movement(3, -700, Some(15)),
+            // bloque que no forma parte de la cadena
+            movement(4, 100, Some(0)),
+        ];
+        let pending = vec![movement(5, -50, None)];
+        history.update("wallet", &confirmed, &pending, block_height);
+
+        let entries = history.get_entries("wallet");
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].tx_hash, Hash256::new([5; 32]));
+        assert_eq!(entries[0].direction, Direction::Sent);
+        assert_eq!(entries[0].block_height, None);
+        assert_eq!(entries[1].tx_hash, Hash256::new([3; 32]));
+        assert_eq!(entries[1].amount, 700);
+        assert_eq!(entries[2].tx_hash, Hash256::new([2; 32]));
+        assert_eq!(entries[2].direction, Direction::Received);
+        assert_eq!(entries[2].amount, 500);
+        assert_eq!(entries[2].address, Some(String::from("address2")));
+        assert_eq!(entries[2].output_type, Some(OutputType::P2WPKH));
+        assert_eq!(entries[3].block_height, Some(10));
+    }
+
+    #[test]
+    fn entry_confirmations() {
+        let mut history = History::new();
+        history.update(
+            "wallet",
+            &[movement(1, 1000, Some(10))],
+            &[movement(2, 1000, None)],
+            block_height,
+        );
+        history.set_best_height(12);
+
+        let best_height = history.get_best_height();
+        let entries = history.get_entries("wallet");
+        assert_eq!(entries[0].confirmations(best_height), 0);
+        assert_eq!(entries[1].confirmations(best_height), 3);
+    }
+
+    #[test]
+    fn history_of_each_wallet() {
+        let mut history = History::new();
+        history.update(
+            "first",
+            &[movement(1, 1000, Some(10))],
+            &[movement(2, -300, None)],
+            block_height,
+        );
+        history.update("second", &[movement(3, 500, Some(11))], &[], block_height);
+
+        assert_eq!(history.get_entries("first").len(), 2);
+        assert_eq!(history.get_pending("first").len(), 1);
+        assert_eq!(
+            history.get_entries("second")[0].tx_hash,
+            Hash256::new([3; 32])
+        );
+        assert!(history.get_pending("second").is_empty());
+        assert!(history.get_entries("unknown").is_empty());
+    }
+
+    #[test]
+    fn confirmed_and_repeated_pending_txs_are_discarded() {
+        let mut history = History::new();
+        history.update(
+            "wallet",
+            &[movement(1, 1000, Some(10))],
+            &[
+                movement(1, 1000, None),
+                movement(3, -300, None),
+                movement(2, 500, None),
+                movement(3, -300, None),
+            ],
+            block_height,
+        );
+
+        let pending: Vec<Hash256> = history
+            .get_pending_entries("wallet")
+            .iter()
+            .map(|entry| entry.tx_hash)
+            .collect();
+        assert_eq!(pending, vec![Hash256::new([2; 32]), Hash256::new([3; 32])]);
+        assert_eq!(history.get_pending("wallet").len(), 2);
+        assert_eq!(history.get_entries("wallet").len(), 3);
+        assert_eq!(history.get_entries("wallet")[2].block_height, Some(10));
+    }
+
+    #[test]
+    fn labels_are_shown_and_restored() {
+        let path = String::from("tests/test_history_labels.bin");
+        let mut history = History::with_labels(path.clone()).unwrap();
+        history.update(
+            "wallet",
+            &[movement(1, -1000, Some(10))],
+            &[movement(2, -300, None)],
+            block_height,
+        );
+
+        history.set_label(&Hash256::new([1; 32]), " rent ").unwrap();
+        assert_eq!(
+            history.get_entries("wallet")[1].label,
+            Some(String::from("rent"))
+        );
+        assert_eq!(history.get_entries("wallet")[0].label, None);
+
+        let mut restored = History::with_labels(path.clone()).unwrap();
+        assert_eq!(
+            restored.get_label(&Hash256::new([1; 32])),
+            Some(&String::from("rent"))
+        );
+        restored.update("wallet", &[movement(1, -1000, Some(10))], &[], block_height);
+        assert_eq!(
+            restored.get_entries("wallet")[0].label,
+            Some(String::from("rent"))
+        );
+
+        restored.set_label(&Hash256::new([1; 32]), "").unwrap();
+        assert!(restored.get_entries("wallet")[0].label.is_none());
+        assert!(History::with_labels(path.clone())
+            .unwrap()
+            .get_label(&Hash256::new([1; 32]))
+            .is_none());
+        assert!(restored
+            .set_label(&Hash256::new([1; 32]), &"a".repeat(256))
+            .is_err());
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn export_history_to_csv() {
+        let path = String::from("tests/test_history_export.csv");
+        let mut history = History::new();
+        let mut sent = movement(3, -1500, Some(10));
+        sent.fee = Some(500);
+        history.update(
+            "wallet",
+            &[movement(1, 100_000_000, Some(5)), sent],
+            &[movement(2, 2000, None)],
+            block_height,
+        );
+        history.set_best_height(12);
+        history
+            .set_label(&Hash256::new([3; 32]), "rent, \"march\"")
+            .unwrap();
+
+        history.export_csv("wallet", path.clone()).unwrap();
+        let csv = read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(
+            lines[1],
+            format!(",{},received,0.00002000,,0,", "02".repeat(32))
+        );
+        assert_eq!(
+            lines[2],
+            format!(
+                "2023-11-14 23:53:20,{},sent,0.00001500,0.00000500,3,\"rent, \"\"march\"\"\"",
+                "03".repeat(32)
+            )
+        );
+        assert_eq!(
+            lines[3],
+            format!(
+                "2023-11-14 23:03:20,{},received,1.00000000,,8,",
+                "01".repeat(32)
+            )
+        );
+
+        remove_file(path).unwrap();
+    }
+}
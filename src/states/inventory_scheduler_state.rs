@@ -0,0 +1,319 @@
+use std::{collections::HashMap, net::SocketAddrV6};
+
+use crate::{
+    error::CustomError,
+    structs::{hash256::Hash256, inventory::Inventory},
+    utils::get_current_timestamp_millis,
+};
+
+/// Tiempo en milisegundos que se espera un inventory pedido antes de pedirlo a otro peer.
+const INVENTORY_REQUEST_TIMEOUT: u128 = 60000;
+/// Tiempo en milisegundos que se espera antes del primer reintento, luego se duplica en cada intento fallido.
+const RETRY_BASE_DELAY: u128 = 2000;
+/// Cantidad de pedidos fallidos tras la cual se deja de pedir un inventory.
+const MAX_REQUEST_ATTEMPTS: u32 = 5;
+
+/// InventoryRequest es un inventory pedido a los peers que todavia no fue recibido.
+/// Los elementos son:
+/// - inventory: Inventory con el que se pide.
+/// - peer: Peer al que se le pidio, None si espera para volver a pedirse.
+/// - tried_peers: Peers que no lo entregaron.
+/// - attempts: Cantidad de pedidos fallidos.
+/// - deadline: Timestamp en milisegundos en que vence el pedido en curso, o a partir del cual se vuelve a pedir.
+struct InventoryRequest {
+    inventory: Inventory,
+    peer: Option<SocketAddrV6>,
+    tried_peers: Vec<SocketAddrV6>,
+    attempts: u32,
+    deadline: u128,
+}
+
+impl InventoryRequest {
+    /// Registra un pedido fallido y programa el proximo con backoff exponencial.
+    /// Devuelve false si se alcanzo MAX_REQUEST_ATTEMPTS y hay que dejar de pedirlo.
+    fn fail(&mut self, now: u128, retry_delay: u128) -> bool {
+        if let Some(peer) = self.peer.take() {
+            if !self.tried_peers.contains(&peer) {
+                self.tried_peers.push(peer);
+            }
+        }
+        self.attempts += 1;
+        self.deadline = now + retry_delay * 2_u128.pow(self.attempts - 1);
+        self.attempts < MAX_REQUEST_ATTEMPTS
+    }
+}
+
+/// InventoryScheduler lleva los inventories que no son bloques (transacciones) pedidos a los peers, y a que peer
+/// se le pidio cada uno. Si un peer responde notfound, no responde dentro de INVENTORY_REQUEST_TIMEOUT o se desconecta,
+/// el inventory se vuelve a pedir a otro peer que no lo haya intentado, esperando RETRY_BASE_DELAY con backoff exponencial.
+/// Los bloques los reparte el BlockDownloader.
+/// Los elementos son:
+/// - requests: Inventories pedidos que todavia no fueron recibidos, por hash.
+/// - request_timeout: Tiempo en milisegundos que se espera cada pedido.
+/// - retry_delay: Tiempo en milisegundos que se espera antes del primer reintento.
+pub struct InventoryScheduler {
+    requests: HashMap<Hash256, InventoryRequest>,
+    request_timeout: u128,
+    retry_delay: u128,
+}
+
+impl Default for InventoryScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InventoryScheduler {
+    /// Inicializa el scheduler sin pedidos.
+    pub fn new() -> Self {
+        Self {
+            requests: HashMap::new(),
+            request_timeout: INVENTORY_REQUEST_TIMEOUT,
+            retry_delay: RETRY_BASE_DELAY,
+        }
+    }
+
+    /// Registra los inventories a pedir a un peer y devuelve los que hay que enviarle,
+    /// descartando los que ya estan pedidos a otro peer o esperando para volver a pedirse.
+    pub fn request(
+        &mut self,
+        peer: SocketAddrV6,
+        inventories: Vec<Inventory>,
+    ) -> Result<Vec<Inventory>, CustomError> {
+        let now = get_current_timestamp_millis()?;
+        let mut to_request = vec![];
+        for inventory in inventories {
+            if self.requests.contains_key(&inventory.hash) {
+                continue;
+            }
+            self.requests.insert(
+                inventory.hash,
+                InventoryRequest {
+                    inventory: inventory.clone(),
+                    peer: Some(peer),
+                    tried_peers: vec![],
+                    attempts: 0,
+                    deadline: now + self.request_timeout,
+                },
+            );
+            to_request.push(inventory);
+        }
+        Ok(to_request)
+    }
+
+    /// Registra que un peer respondio notfound, los inventories se vuelven a pedir a otro peer luego del backoff.
+    /// Se ignoran los inventories que no se le pidieron a ese peer.
+    pub fn not_found(
+        &mut self,
+        peer: SocketAddrV6,
+        inventories: &[Inventory],
+    ) -> Result<(), CustomError> {
+        let hashes = inventories
+            .iter()
+            .filter(|inventory| {
+                self.requests
+                    .get(&inventory.hash)
+                    .map(|request| request.peer == Some(peer))
+                    .unwrap_or(false)
+            })
+            .map(|inventory| inventory.hash)
+            .collect();
+        self.fail(hashes, get_current_timestamp_millis()?);
+        Ok(())
+    }
+
+    /// Registra que no se pudieron pedir los inventories, se vuelven a pedir a otro peer luego del backoff.
+    pub fn failed(&mut self, inventories: &[Inventory]) -> Result<(), CustomError> {
+        let hashes = inventories.iter().map(|inventory| inventory.hash).collect();
+        self.fail(hashes, get_current_timestamp_millis()?);
+        Ok(())
+    }
+
+    /// Registra la recepcion de un inventory.
+    pub fn received(&mut self, hash: &Hash256) {
+        self.requests.remove(hash);
+    }
+
+    /// Vuelve a pedir a otros peers los inventories pedidos a un peer al desconectarse.
+    /// No cuenta como un intento fallido, por lo que se vuelven a pedir sin esperar.
+    pub fn remove_peer(&mut self, address: SocketAddrV6) {
+        for request in self.requests.values_mut() {
+            if request.peer == Some(address) {
+                request.peer = None;
+                request.tried_peers.push(address);
+                request.deadline = 0;
+            }
+        }
+    }
+
+    /// Reparte los inventories que esperan volver a pedirse entre los peers recibidos que todavia no los intentaron
+    /// entregar, y devuelve los inventories a pedir a cada uno. Los pedidos en curso vencidos se consideran fallidos.
+    /// Si ningun peer puede intentarlo, se cuenta como otro intento fallido.
+    pub fn assign(
+        &mut self,
+        peers: &[SocketAddrV6],
+    ) -> Result<HashMap<SocketAddrV6, Vec<Inventory>>, CustomError> {
+        let now = get_current_timestamp_millis()?;
+        let expired = self
+            .requests
+            .iter()
+            .filter(|(_, request)| request.peer.is_some() && request.deadline <= now)
+            .map(|(hash, _)| *hash)
+            .collect();
+        self.fail(expired, now);
+
+        let mut assignments: HashMap<SocketAddrV6, Vec<Inventory>> = HashMap::new();
+        let mut dropped = vec![];
+        for (hash, request) in self.requests.iter_mut() {
+            if request.peer.is_some() || request.deadline > now {
+                continue;
+            }
+            let peer = peers
+                .iter()
+                .filter(|peer| !request.tried_peers.contains(peer))
+                .min_by_key(|peer| assignments.get(peer).map(Vec::len).unwrap_or(0));
+            let Some(peer) = peer else {
+                if !request.fail(now, self.retry_delay) {
+                    dropped.push(*hash);
+                }
+                continue;
+            };
+
+            request.peer = Some(*peer);
+            request.deadline = now + self.request_timeout;
+            assignments
+                .entry(*peer)
+                .or_default()
+                .push(request.inventory.clone());
+        }
+        for hash in dropped {
+            self.requests.remove(&hash);
+        }
+        Ok(assignments)
+    }
+
+    fn fail(&mut self, hashes: Vec<Hash256>, now: u128) {
+        for hash in hashes {
+            let Some(request) = self.requests.get_mut(&hash) else { continue };
+            if !request.fail(now, self.retry_delay) {
+                self.requests.remove(&hash);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use crate::structs::inventory::InventoryType;
+
+    use super::*;
+
+    fn address(port: u16) -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv6Addr::LOCALHOST, port, 0, 0)
+    }
+
+    fn inventories(count: u8) -> Vec<Inventory> {
+        (0..count)
+            .map(|i| Inventory::new(InventoryType::Tx, Hash256::new([i; 32])))
+            .collect()
+    }
+
+    #[test]
+    fn inventories_are_requested_once() {
+        let mut scheduler = InventoryScheduler::new();
+        assert_eq!(
+            scheduler.request(address(1), inventories(2)).unwrap(),
+            inventories(2)
+        );
+        assert_eq!(
+            scheduler.request(address(2), inventories(3)).unwrap(),
+            vec![Inventory::new(InventoryType::Tx, Hash256::new([2; 32]))]
+        );
+
+        scheduler.received(&Hash256::new([0; 32]));
+        assert_eq!(scheduler.requests.len(), 2);
+        assert!(scheduler
+            .assign(&[address(1), address(2)])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn not_found_is_retried_with_another_peer_after_backoff() {
+        let mut scheduler = InventoryScheduler::new();
+        scheduler.request(address(1), inventories(1)).unwrap();
+
+        // solo se tiene en cuenta el notfound del peer al que se le pidio
+        scheduler.not_found(address(2), &inventories(1)).unwrap();
+        assert_eq!(scheduler.requests[&Hash256::new([0; 32])].attempts, 0);
+
+        scheduler.not_found(address(1), &inventories(1)).unwrap();
+        assert!(scheduler
+            .assign(&[address(1), address(2)])
+            .unwrap()
+            .is_empty());
+
+        scheduler.retry_delay = 0;
+        scheduler.failed(&inventories(1)).unwrap();
+        let assignments = scheduler.assign(&[address(1), address(2)]).unwrap();
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[&address(2)], inventories(1));
+    }
+
+    #[test]
+    fn backoff_doubles_on_each_attempt() {
+        let mut scheduler = InventoryScheduler::new();
+        scheduler.request(address(1), inventories(1)).unwrap();
+
+        let mut deadlines = vec![];
+        for _ in 0..3 {
+            let now = get_current_timestamp_millis().unwrap();
+            scheduler.failed(&inventories(1)).unwrap();
+            deadlines.push(scheduler.requests[&Hash256::new([0; 32])].deadline - now);
+        }
+        assert!(deadlines[0] >= RETRY_BASE_DELAY && deadlines[0] < 2 * RETRY_BASE_DELAY);
+        assert!(deadlines[1] >= 2 * RETRY_BASE_DELAY && deadlines[1] < 3 * RETRY_BASE_DELAY);
+        assert!(deadlines[2] >= 4 * RETRY_BASE_DELAY && deadlines[2] < 5 * RETRY_BASE_DELAY);
+    }
+
+    #[test]
+    fn requests_are_dropped_after_max_attempts() {
+        let mut scheduler = InventoryScheduler::new();
+        scheduler.retry_delay = 0;
+        scheduler.request(address(1), inventories(1)).unwrap();
+        scheduler.not_found(address(1), &inventories(1)).unwrap();
+
+        // el unico peer conectado ya lo intento, cada reparto cuenta como un intento fallido
+        for _ in 1..MAX_REQUEST_ATTEMPTS {
+            assert!(scheduler.assign(&[address(1)]).unwrap().is_empty());
+        }
+        assert!(scheduler.requests.is_empty());
+    }
+
+    #[test]
+    fn disconnected_peer_requests_are_reassigned() {
+        let mut scheduler = InventoryScheduler::new();
+        scheduler.request(address(1), inventories(2)).unwrap();
+
+        scheduler.remove_peer(address(1));
+        let assignments = scheduler
+            .assign(&[address(1), address(2), address(3)])
+            .unwrap();
+        assert_eq!(assignments[&address(2)].len(), 1);
+        assert_eq!(assignments[&address(3)].len(), 1);
+    }
+
+    #[test]
+    fn expired_requests_are_reassigned() {
+        let mut scheduler = InventoryScheduler::new();
+        scheduler.retry_delay = 0;
+        scheduler.request_timeout = 0;
+        scheduler.request(address(1), inventories(1)).unwrap();
+
+        let assignments = scheduler.assign(&[address(1), address(2)]).unwrap();
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[&address(2)], inventories(1));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+
+use crate::{
+    atomic_file::{read_atomic, write_atomic},
+    error::{CustomError, ParseError},
+    structs::outpoint::OutPoint,
+};
+
+/// Tamaño en bytes de un outpoint serializado.
+const OUTPOINT_SIZE: usize = 36;
+
+/// LockedUTXO son las UTXO que el usuario bloqueo para reservarlas, que la seleccion de monedas no usa
+/// para financiar transacciones hasta que se desbloqueen.
+/// Se guardan en disco cada vez que se modifican, para que se mantengan al reiniciar el nodo.
+/// Los elementos son:
+/// - path: Path del archivo donde se guardan los outpoints bloqueados.
+/// - out_points: Outpoints de las UTXO bloqueadas.
+pub struct LockedUTXO {
+    path: String,
+    out_points: HashSet<OutPoint>,
+}
+
+impl LockedUTXO {
+    /// Inicializa las UTXO bloqueadas restaurandolas del archivo donde se guardan.
+    /// Devuelve CustomError si el archivo no es valido.
+    pub fn new(path: String) -> Result<Self, CustomError> {
+        let buffer = read_atomic(&path)?;
+        if buffer.len() % OUTPOINT_SIZE != 0 {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+
+        let mut out_points = HashSet::new();
+        for chunk in buffer.chunks(OUTPOINT_SIZE) {
+            out_points.insert(OutPoint::parse(chunk.to_vec())?);
+        }
+        Ok(Self { path, out_points })
+    }
+
+    /// Bloquea una UTXO. Devuelve false si ya estaba bloqueada.
+    pub fn lock(&mut self, out_point: OutPoint) -> Result<bool, CustomError> {
+        if !self.out_points.insert(out_point) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Desbloquea una UTXO. Devuelve false si no estaba bloqueada.
+    pub fn unlock(&mut self, out_point: &OutPoint) -> Result<bool, CustomError> {
+        if !self.out_points.remove(out_point) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Devuelve true si la UTXO esta bloqueada.
+    pub fn is_locked(&self, out_point: &OutPoint) -> bool {
+        self.out_points.contains(out_point)
+    }
+
+    /// Devuelve los outpoints de las UTXO bloqueadas.
+    pub fn get_all(&self) -> &HashSet<OutPoint> {
+        &self.out_points
+    }
+
+    fn save(&self) -> Result<(), CustomError> {
+        let mut buffer = vec![];
+        for out_point in &self.out_points {
+            buffer.extend(out_point.serialize());
+        }
+        write_atomic(&self.path, &buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{atomic_file::remove_atomic, structs::hash256::Hash256};
+
+    use super::*;
+
+    fn out_point(index: u32) -> OutPoint {
+        OutPoint {
+            hash: Hash256::from([index as u8; 32]),
+            index,
+        }
+    }
+
+    #[test]
+    fn locked_utxo_are_restored() {
+        let path = String::from("tests/test_locked_utxo_restore.bin");
+        let mut locked = LockedUTXO::new(path.clone()).unwrap();
+        assert!(locked.lock(out_point(1)).unwrap());
+        assert!(locked.lock(out_point(2)).unwrap());
+        assert!(!locked.lock(out_point(1)).unwrap());
+        assert!(locked.unlock(&out_point(2)).unwrap());
+        assert!(!locked.unlock(&out_point(3)).unwrap());
+
+        let restored = LockedUTXO::new(path.clone()).unwrap();
+        assert_eq!(restored.get_all().len(), 1);
+        assert!(restored.is_locked(&out_point(1)));
+        assert!(!restored.is_locked(&out_point(2)));
+
+        remove_atomic(&path).unwrap();
+    }
+}
@@ -0,0 +1,651 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::CustomError,
+    messages::{block::Block, transaction::Transaction},
+    structs::{
+        hash256::Hash256, movement::Movement, outpoint::OutPoint, tx_output::TransactionOutput,
+    },
+    utils::get_current_timestamp,
+    wallet::Wallet,
+};
+
+use super::utxo_state::UTXO;
+
+/// Tiempo en segundos que una transaccion puede permanecer en el mempool sin confirmarse.
+const MEMPOOL_EXPIRY: u64 = 14 * 24 * 60 * 60;
+/// Cantidad maxima de transacciones en el mempool, al superarla se descartan las mas antiguas.
+const MAX_MEMPOOL_TXS: usize = 5000;
+/// Tiempo en segundos que se espera una transaccion pedida antes de volver a pedirla.
+const TX_REQUEST_TIMEOUT: u64 = 60;
+
+/// MempoolEntry es una transaccion sin confirmar junto al momento en que se recibio.
+/// Los elementos son:
+/// - transaction: Transaccion sin confirmar.
+/// - received_at: Timestamp del momento en que se recibio.
+struct MempoolEntry {
+    transaction: Transaction,
+    received_at: u64,
+}
+
+/// Mempool es una estructura que contiene las transacciones sin confirmar, recibidas de los peers
+/// o enviadas por el nodo, hasta que se incluyen en un bloque.
+/// Si dos transacciones gastan el mismo output, se conserva la primera recibida.
+/// Los elementos son:
+/// - tx_set: HashMap que contiene los hashes de las transacciones pendientes con su MempoolEntry.
+/// - spent_outputs: HashMap con los outputs gastados por las transacciones pendientes y el hash de la transaccion que los gasta.
+/// - wtxids: HashMap con el wtxid de cada transaccion pendiente y su hash, para los peers que anuncian por wtxid (BIP 339).
+/// - requested: HashMap con los hashes de las transacciones pedidas a los peers y el timestamp del pedido.
+pub struct Mempool {
+    tx_set: HashMap<Hash256, MempoolEntry>,
+    wtxids: HashMap<Hash256, Hash256>,
+    spent_outputs: HashMap<OutPoint, Hash256>,
+    requested: HashMap<Hash256, u64>,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Mempool::new()
+    }
+}
+
+impl Mempool {
+    /// Inicializa la estructura.
+    pub fn new() -> Self {
+        Mempool {
+            tx_set: HashMap::new(),
+            wtxids: HashMap::new(),
+            spent_outputs: HashMap::new(),
+            requested: HashMap::new(),
+        }
+    }
+
+    /// Recibe los hashes de las transacciones anunciadas por un peer y devuelve las que hay que pedirle,
+    /// es decir las que no estan en el mempool ni fueron pedidas hace menos de TX_REQUEST_TIMEOUT.
+    /// Los hashes pueden ser txids o wtxids, segun como anuncie las transacciones el peer.
+    pub fn request(&mut self, tx_hashes: Vec<Hash256>) -> Result<Vec<Hash256>, CustomError> {
+        let now = get_current_timestamp()?;
+        self.requested
+            .retain(|_, requested_at| *requested_at + TX_REQUEST_TIMEOUT > now);
+
+        let mut to_request = vec![];
+        for tx_hash in tx_hashes {
+            if self.get_txid(&tx_hash).is_some() || self.requested.contains_key(&tx_hash) {
+                continue;
+            }
+            self.requested.insert(tx_hash, now);
+            to_request.push(tx_hash);
+        }
+        Ok(to_request)
+    }
+
+    /// Agrega una transaccion a la lista de transacciones pendientes, devuelve true si es una transaccion que no teniamos.
+    /// Si la transaccion gasta un output ya gastado por otra transaccion pendiente, se descarta.
+    pub fn append_pending_tx(&mut self, transaction: Transaction) -> Result<bool, CustomError> {
+        let tx_hash = transaction.hash();
+        let wtxid = transaction.wtxid();
+        self.requested.remove(&tx_hash);
+        self.requested.remove(&wtxid);
+
+        if self.tx_set.contains_key(&tx_hash)
+            || transaction
+                .inputs
+                .iter()
+                .any(|tx_in| self.spent_outputs.contains_key(&tx_in.previous_output))
+        {
+            return Ok(false);
+        }
+
+        for tx_in in &transaction.inputs {
+            self.spent_outputs
+                .insert(tx_in.previous_output.clone(), tx_hash);
+        }
+        self.wtxids.insert(wtxid, tx_hash);
+        self.tx_set.insert(
+            tx_hash,
+            MempoolEntry {
+                transaction,
+                received_at: get_current_timestamp()?,
+            },
+        );
+
+        if self.tx_set.len() > MAX_MEMPOOL_TXS {
+            self.evict_oldest();
+        }
+        Ok(true)
+    }
+
+    /// Agrega una transaccion que reemplaza a las transacciones pendientes que gastan sus mismos outputs (BIP 125).
+    /// Las transacciones reemplazadas se eliminan junto a las que gastan sus outputs.
+    /// Devuelve true si la transaccion se agrego.
+    pub fn replace_pending_tx(&mut self, transaction: Transaction) -> Result<bool, CustomError> {
+        for tx_in in &transaction.inputs {
+            if let Some(conflict_hash) = self.spent_outputs.get(&tx_in.previous_output) {
+                let conflict_hash = *conflict_hash;
+                self.remove_with_descendants(&conflict_hash);
+            }
+        }
+        self.append_pending_tx(transaction)
+    }
+
+    /// Actualiza la lista de transacciones pendientes, eliminando las transacciones que esten en el bloque,
+    /// las que gastan los mismos outputs que ellas y las que llevan mas de MEMPOOL_EXPIRY sin confirmarse.
+    pub fn update_pending_tx(&mut self, block: &Block) -> Result<(), CustomError> {
+        self.remove_confirmed(&block.transactions);
+
+        let now = get_current_timestamp()?;
+        let expired: Vec<Hash256> = self
+            .tx_set
+            .iter()
+            .filter(|(_, entry)| entry.received_at + MEMPOOL_EXPIRY < now)
+            .map(|(tx_hash, _)| *tx_hash)
+            .collect();
+        for tx_hash in expired {
+            self.remove_with_descendants(&tx_hash);
+        }
+        Ok(())
+    }
+
+    /// Elimina las transacciones confirmadas en un bloque y las que gastan los mismos outputs que ellas.
+    pub fn remove_confirmed(&mut self, transactions: &[Transaction]) {
+        for tx in transactions {
+            self.remove(&tx.hash());
+            for tx_in in &tx.inputs {
+                if let Some(conflict_hash) = self.spent_outputs.get(&tx_in.previous_output) {
+                    let conflict_hash = *conflict_hash;
+                    self.remove_with_descendants(&conflict_hash);
+                }
+            }
+        }
+    }
+
+    /// Devuelve las transacciones pendientes que pertenecen a la wallet.
+    pub fn from_wallet(&self, wallet: &Wallet, utxo: &UTXO) -> Result<Vec<Movement>, CustomError> {
+        let mut pending_movements = vec![];
+
+        for entry in self.tx_set.values() {
+            if let Some(mov) = wallet.get_movement(&entry.transaction, utxo)? {
+                pending_movements.push(mov);
+            }
+        }
+        Ok(pending_movements)
+    }
+
+    /// Devuelve los outputs de las transacciones pendientes que pagan a alguno de los scripts recibidos,
+    /// por ejemplo los de una wallet, y que ninguna otra transaccion pendiente gasta, junto a sus outpoints.
+    pub fn unspent_outputs(
+        &self,
+        script_pubkeys: &[Vec<u8>],
+    ) -> Vec<(OutPoint, TransactionOutput)> {
+        let mut unspent_outputs = vec![];
+        for (tx_hash, entry) in &self.tx_set {
+            for (index, output) in entry.transaction.outputs.iter().enumerate() {
+                let out_point = OutPoint {
+                    hash: *tx_hash,
+                    index: index as u32,
+                };
+                if script_pubkeys.contains(&output.script_pubkey)
+                    && !self.spent_outputs.contains_key(&out_point)
+                {
+                    unspent_outputs.push((out_point, output.clone()));
+                }
+            }
+        }
+        unspent_outputs
+    }
+
+    /// Devuelve el fee que paga una transaccion, buscando los outputs que gasta en las UTXO y en las transacciones pendientes.
+    /// Devuelve None si no se encuentra alguno de ellos.
+    pub fn fee(&self, transaction: &Transaction, utxo: &UTXO) -> Option<u64> {
+        let mut inputs_value = 0;
+        for tx_in in &transaction.inputs {
+            inputs_value += match utxo.tx_set.get(&tx_in.previous_output) {
+                Some(utxo_value) => utxo_value.tx_out.value,
+                None => self.get_output(&tx_in.previous_output)?.value,
+            };
+        }
+        let outputs_value: u64 = transaction.outputs.iter().map(|output| output.value).sum();
+        inputs_value.checked_sub(outputs_value)
+    }
+
+    /// Devuelve el hash de una transaccion pendiente a partir de su hash o su wtxid.
+    pub fn get_txid(&self, hash: &Hash256) -> Option<Hash256> {
+        if self.tx_set.contains_key(hash) {
+            return Some(*hash);
+        }
+        self.wtxids.get(hash).copied()
+    }
+
+    /// Devuelve una transaccion pendiente en base a su hash o su wtxid.
+    pub fn get_pending_tx(&self, tx_hash: &Hash256) -> Option<Transaction> {
+        self.tx_set
+            .get(&self.get_txid(tx_hash)?)
+            .map(|entry| entry.transaction.clone())
+    }
+
+    /// Devuelve todas las transacciones pendientes.
+    pub fn get_all(&self) -> Vec<Transaction> {
+        self.tx_set
+            .values()
+            .map(|entry| entry.transaction.clone())
+            .collect()
+    }
+
+    /// Devuelve la cantidad de transacciones pendientes.
+    pub fn len(&self) -> usize {
+        self.tx_set.len()
+    }
+
+    /// Devuelve true si no hay transacciones pendientes.
+    pub fn is_empty(&self) -> bool {
+        self.tx_set.is_empty()
+    }
+
+    fn get_output(&self, out_point: &OutPoint) -> Option<&TransactionOutput> {
+        self.tx_set
+            .get(&out_point.hash)?
+            .transaction
+            .outputs
+            .get(out_point.index as usize)
+    }
+
+    fn remove(&mut self, tx_hash: &Hash256) -> Option<Transaction> {
+        let entry = self.tx_set.remove(tx_hash)?;
+        self.wtxids.remove(&entry.transaction.wtxid());
+        for tx_in in &entry.transaction.inputs {
+            if self.spent_outputs.get(&tx_in.previous_output) == Some(tx_hash) {
+                self.spent_outputs.remove(&tx_in.previous_output);
+            }
+        }
+        Some(entry.transaction)
+    }
+
+    /// Elimina una transaccion y las que gastan sus outputs, que dejan de ser validas sin ella.
+    fn remove_with_descendants(&mut self, tx_hash: &Hash256) {
+        let mut to_remove = vec![*tx_hash];
+        while let Some(tx_hash) = to_remove.pop() {
+            let Some(transaction) = self.remove(&tx_hash) else { continue };
+            for index in 0..transaction.outputs.len() {
+                let out_point = OutPoint {
+                    hash: tx_hash,
+                    index: index as u32,
+                };
+                if let Some(descendant_hash) = self.spent_outputs.get(&out_point) {
+                    to_remove.push(*descendant_hash);
+                }
+            }
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .tx_set
+            .iter()
+            .min_by_key(|(_, entry)| entry.received_at)
+            .map(|(tx_hash, _)| *tx_hash);
+        if let Some(tx_hash) = oldest {
+            self.remove_with_descendants(&tx_hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        states::{utxo_state::UTXOValue, wallets_state::WalletsState},
+        structs::{block_header::BlockHeader, tx_input::TransactionInput},
+    };
+
+    use super::*;
+
+    #[test]
+    fn pendings_txs_creation() {
+        let mempool = Mempool::new();
+        assert_eq!(mempool.tx_set.len(), 0);
+        let mempool = Mempool::default();
+        assert_eq!(mempool.tx_set.len(), 0);
+    }
+
+    #[test]
+    fn append_pending_tx() {
+        let mut mempool = Mempool::new();
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![],
+            outputs: vec![],
+            lock_time: 0,
+        };
+        let tx_hash = tx.hash();
+        mempool.append_pending_tx(tx).unwrap();
+        assert_eq!(mempool.tx_set.len(), 1);
+        assert!(mempool.tx_set.contains_key(&tx_hash));
+    }
+
+    #[test]
+    fn append_existing_pending_tx() {
+        let mut mempool = Mempool::new();
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![],
+            outputs: vec![],
+            lock_time: 0,
+        };
+        let tx_hash = tx.hash();
+
+        let updated = mempool.append_pending_tx(tx.clone()).unwrap();
+        assert!(updated);
+        let updated = mempool.append_pending_tx(tx).unwrap();
+        assert!(!updated);
+
+        assert_eq!(mempool.tx_set.len(), 1);
+        assert!(mempool.tx_set.contains_key(&tx_hash));
+    }
+
+    #[test]
+    fn update_pendings() {
+        let mut mempool = Mempool::new();
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![],
+            outputs: vec![],
+            lock_time: 0,
+        };
+
+        let block = Block {
+            header: BlockHeader {
+                version: 536887296,
+                prev_block_hash: Hash256::ZERO,
+                merkle_root: Hash256::ZERO,
+                timestamp: 1686626483,
+                bits: 421617023,
+                nonce: 3878826733,
+                hash: Hash256::ZERO,
+                block_downloaded: true,
+                broadcasted: true,
+            },
+
+            transactions: vec![tx.clone()],
+        };
+
+        let updated = mempool.append_pending_tx(tx).unwrap();
+        assert!(updated);
+        assert_eq!(mempool.tx_set.len(), 1);
+
+        mempool.update_pending_tx(&block).unwrap();
+        assert_eq!(mempool.tx_set.len(), 0);
+    }
+
+    #[test]
+    fn pendings_from_wallet() {
+        let mut wallets = WalletsState::new("tests/test_wallets.bin".to_string()).unwrap();
+        wallets
+            .set_active("mhzZUxRkPzNpCsQHemTakuJa5xhCajxyVm")
+            .unwrap();
+
+        let mut mempool = Mempool::new();
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![],
+            outputs: vec![TransactionOutput {
+                value: 100,
+                script_pubkey: vec![
+                    118, 169, 20, 27, 40, 219, 33, 69, 20, 4, 108, 105, 234, 87, 71, 50, 50, 154,
+                    22, 16, 220, 64, 85, 136, 172,
+                ],
+            }],
+            lock_time: 0,
+        };
+
+        mempool.append_pending_tx(tx).unwrap();
+
+        let pendings_from_wallet = mempool
+            .from_wallet(
+                wallets.get_active().unwrap(),
+                &UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(pendings_from_wallet.len(), 1);
+        assert_eq!(pendings_from_wallet[0].value, 100);
+    }
+
+    fn spending_tx(previous_output: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![TransactionInput {
+                previous_output,
+                script_sig: vec![],
+                sequence: 0,
+                witness: vec![],
+            }],
+            outputs: vec![TransactionOutput {
+                value,
+                script_pubkey: vec![],
+            }],
+            lock_time: 0,
+        }
+    }
+
+    #[test]
+    fn unspent_outputs_of_pending_txs() {
+        let mut mempool = Mempool::new();
+        let mut tx = spending_tx(
+            OutPoint {
+                hash: Hash256::new([1; 32]),
+                index: 0,
+            },
+            100,
+        );
+        tx.outputs[0].script_pubkey = vec![1];
+        tx.outputs.push(TransactionOutput {
+            value: 200,
+            script_pubkey: vec![1],
+        });
+        let child = spending_tx(
+            OutPoint {
+                hash: tx.hash(),
+                index: 0,
+            },
+            90,
+        );
+        mempool.append_pending_tx(tx.clone()).unwrap();
+        mempool.append_pending_tx(child).unwrap();
+
+        let unspent_outputs = mempool.unspent_outputs(&[vec![1]]);
+        assert_eq!(unspent_outputs.len(), 1);
+        assert_eq!(
+            unspent_outputs[0].0,
+            OutPoint {
+                hash: tx.hash(),
+                index: 1,
+            }
+        );
+        assert_eq!(unspent_outputs[0].1.value, 200);
+        assert!(mempool.unspent_outputs(&[vec![2]]).is_empty());
+    }
+
+    #[test]
+    fn fee_of_pending_txs() {
+        let mut mempool = Mempool::new();
+        let mut utxo = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        let out_point = OutPoint {
+            hash: Hash256::new([1; 32]),
+            index: 0,
+        };
+        utxo.tx_set.insert(
+            out_point.clone(),
+            UTXOValue {
+                tx_out: TransactionOutput {
+                    value: 1000,
+                    script_pubkey: vec![],
+                },
+                block_hash: Hash256::new([0; 32]),
+                block_timestamp: 0,
+            },
+        );
+        let tx = spending_tx(out_point, 900);
+        let child = spending_tx(
+            OutPoint {
+                hash: tx.hash(),
+                index: 0,
+            },
+            600,
+        );
+        mempool.append_pending_tx(tx.clone()).unwrap();
+
+        assert_eq!(mempool.fee(&tx, &utxo), Some(100));
+        assert_eq!(mempool.fee(&child, &utxo), Some(300));
+        let unknown = spending_tx(
+            OutPoint {
+                hash: Hash256::new([2; 32]),
+                index: 0,
+            },
+            100,
+        );
+        assert_eq!(mempool.fee(&unknown, &utxo), None);
+    }
+
+    #[test]
+    fn request_announced_txs() {
+        let mut mempool = Mempool::new();
+        let tx = spending_tx(
+            OutPoint {
+                hash: Hash256::new([1; 32]),
+                index: 0,
+            },
+            100,
+        );
+        mempool.append_pending_tx(tx.clone()).unwrap();
+
+        let to_request = mempool
+            .request(vec![
+                tx.hash(),
+                Hash256::new([2; 32]),
+                Hash256::new([3; 32]),
+            ])
+            .unwrap();
+        assert_eq!(
+            to_request,
+            vec![Hash256::new([2; 32]), Hash256::new([3; 32])]
+        );
+
+        // las transacciones ya pedidas no se vuelven a pedir
+        let to_request = mempool
+            .request(vec![Hash256::new([2; 32]), Hash256::new([4; 32])])
+            .unwrap();
+        assert_eq!(to_request, vec![Hash256::new([4; 32])]);
+    }
+
+    #[test]
+    fn request_txs_announced_by_wtxid() {
+        let mut mempool = Mempool::new();
+        let mut tx = spending_tx(
+            OutPoint {
+                hash: Hash256::new([1; 32]),
+                index: 0,
+            },
+            100,
+        );
+        tx.inputs[0].witness = vec![vec![1; 72], vec![2; 33]];
+        let (tx_hash, wtxid) = (tx.hash(), tx.wtxid());
+        assert_ne!(tx_hash, wtxid);
+
+        assert_eq!(mempool.request(vec![wtxid]).unwrap(), vec![wtxid]);
+        mempool.append_pending_tx(tx).unwrap();
+        assert!(mempool.request(vec![wtxid]).unwrap().is_empty());
+        assert_eq!(mempool.get_txid(&wtxid), Some(tx_hash));
+        assert!(mempool.get_pending_tx(&wtxid).is_some());
+
+        mempool.remove(&tx_hash);
+        assert_eq!(mempool.get_txid(&wtxid), None);
+    }
+
+    #[test]
+    fn double_spends_are_rejected() {
+        let mut mempool = Mempool::new();
+        let out_point = OutPoint {
+            hash: Hash256::new([1; 32]),
+            index: 0,
+        };
+        assert!(mempool
+            .append_pending_tx(spending_tx(out_point.clone(), 100))
+            .unwrap());
+        assert!(!mempool
+            .append_pending_tx(spending_tx(out_point, 90))
+            .unwrap());
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn conflicts_are_removed_with_descendants() {
+        let mut mempool = Mempool::new();
+        let out_point = OutPoint {
+            hash: Hash256::new([1; 32]),
+            index: 0,
+        };
+        let tx = spending_tx(out_point.clone(), 100);
+        let child = spending_tx(
+            OutPoint {
+                hash: tx.hash(),
+                index: 0,
+            },
+            90,
+        );
+        let unrelated = spending_tx(
+            OutPoint {
+                hash: Hash256::new([2; 32]),
+                index: 0,
+            },
+            50,
+        );
+        mempool.append_pending_tx(tx).unwrap();
+        mempool.append_pending_tx(child).unwrap();
+        mempool.append_pending_tx(unrelated.clone()).unwrap();
+
+        let block = Block {
+            header: BlockHeader {
+                version: 1,
+                prev_block_hash: Hash256::ZERO,
+                merkle_root: Hash256::ZERO,
+                timestamp: 1686626483,
+                bits: 0,
+                nonce: 0,
+                hash: Hash256::ZERO,
+                block_downloaded: true,
+                broadcasted: true,
+            },
+            transactions: vec![spending_tx(out_point, 80)],
+        };
+        mempool.update_pending_tx(&block).unwrap();
+
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.get_pending_tx(&unrelated.hash()).is_some());
+        assert_eq!(mempool.spent_outputs.len(), 1);
+    }
+
+    #[test]
+    fn replace_pending_tx() {
+        let mut mempool = Mempool::new();
+        let out_point = OutPoint {
+            hash: Hash256::new([1; 32]),
+            index: 0,
+        };
+        let tx = spending_tx(out_point.clone(), 100);
+        let child = spending_tx(
+            OutPoint {
+                hash: tx.hash(),
+                index: 0,
+            },
+            90,
+        );
+        mempool.append_pending_tx(tx.clone()).unwrap();
+        mempool.append_pending_tx(child).unwrap();
+
+        let replacement = spending_tx(out_point, 80);
+        assert!(mempool.replace_pending_tx(replacement.clone()).unwrap());
+
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.get_pending_tx(&tx.hash()).is_none());
+        assert!(mempool.get_pending_tx(&replacement.hash()).is_some());
+    }
+}
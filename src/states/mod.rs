@@ -1,6 +1,18 @@
+pub mod addr_man_state;
+pub mod address_book_state;
+pub mod block_downloader_state;
 pub mod blocks_state;
+pub mod filters_state;
+pub mod header_sync_state;
 pub mod headers_state;
+pub mod history_state;
+pub mod inventory_scheduler_state;
+pub mod locked_utxo_state;
+pub mod mempool_state;
+pub mod peer_info_state;
+pub mod peer_score_state;
 pub mod pending_blocks_state;
-pub mod pending_txs_state;
+pub mod sync_progress_state;
+pub mod tx_broadcasts_state;
 pub mod utxo_state;
 pub mod wallets_state;
@@ -0,0 +1,239 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddrV6,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// PeerInfo es la informacion de un peer conectado que se muestra al usuario.
+/// Los elementos son:
+/// - address: Direccion del peer.
+/// - user_agent: User agent que informo el peer en el handshake.
+/// - version: Version de protocolo negociada con el peer.
+/// - start_height: Altura de la cadena del peer al momento del handshake.
+/// - ping: Latencia del ultimo ping respondido por el peer, None si todavia no respondio ninguno.
+/// - bytes_sent: Cantidad de bytes enviados al peer.
+/// - bytes_received: Cantidad de bytes recibidos del peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub address: SocketAddrV6,
+    pub user_agent: String,
+    pub version: i32,
+    pub start_height: usize,
+    pub ping: Option<Duration>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl PeerInfo {
+    /// Crea la informacion de un peer que acaba de completar el handshake, sin trafico registrado.
+    pub fn new(
+        address: SocketAddrV6,
+        user_agent: String,
+        version: i32,
+        start_height: usize,
+    ) -> Self {
+        Self {
+            address,
+            user_agent,
+            version,
+            start_height,
+            ping: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+        }
+    }
+}
+
+/// MessageStats es el trafico de un tipo de mensaje.
+/// Los elementos son:
+/// - messages_sent: Cantidad de mensajes enviados.
+/// - bytes_sent: Bytes enviados, incluyendo el header de cada mensaje.
+/// - messages_received: Cantidad de mensajes recibidos.
+/// - bytes_received: Bytes recibidos, incluyendo el header de cada mensaje.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MessageStats {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+}
+
+/// NetTotals es el trafico del nodo con todos los peers desde que se inicio, incluyendo a los ya desconectados.
+/// Los elementos son:
+/// - bytes_sent: Bytes enviados.
+/// - bytes_received: Bytes recibidos.
+/// - by_command: Trafico de cada tipo de mensaje, por comando.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct NetTotals {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub by_command: BTreeMap<String, MessageStats>,
+}
+
+/// PeerInfoRegistry contiene la informacion de los peers conectados y el trafico total del nodo.
+/// El NetworkLoop agrega y elimina a los peers al atender y cerrar sus conexiones,
+/// y el PeerStreamLoop de cada uno registra su latencia y su trafico.
+/// Los elementos son:
+/// - peers: Informacion de cada peer, por direccion.
+/// - totals: Trafico total del nodo y de cada tipo de mensaje.
+#[derive(Debug, Default)]
+pub struct PeerInfoRegistry {
+    peers: HashMap<SocketAddrV6, PeerInfo>,
+    totals: NetTotals,
+}
+
+impl PeerInfoRegistry {
+    /// Inicializa el registro sin peers, para compartirlo entre threads.
+    pub fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    /// Agrega un peer, reemplazando la informacion anterior si ya estaba.
+    pub fn insert(&mut self, info: PeerInfo) {
+        self.peers.insert(info.address, info);
+    }
+
+    /// Elimina un peer desconectado.
+    pub fn remove(&mut self, address: &SocketAddrV6) {
+        self.peers.remove(address);
+    }
+
+    /// Registra la latencia del ultimo ping respondido por el peer.
+    pub fn record_ping(&mut self, address: &SocketAddrV6, ping: Duration) {
+        if let Some(info) = self.peers.get_mut(address) {
+            info.ping = Some(ping);
+        }
+    }
+
+    /// Registra bytes enviados al peer.
+    pub fn record_sent(&mut self, address: &SocketAddrV6, bytes: usize) {
+        self.totals.bytes_sent += bytes as u64;
+        if let Some(info) = self.peers.get_mut(address) {
+            info.bytes_sent += bytes as u64;
+        }
+    }
+
+    /// Registra bytes recibidos del peer.
+    pub fn record_received(&mut self, address: &SocketAddrV6, bytes: usize) {
+        self.totals.bytes_received += bytes as u64;
+        if let Some(info) = self.peers.get_mut(address) {
+            info.bytes_received += bytes as u64;
+        }
+    }
+
+    /// Registra un mensaje enviado con su comando y su tamaño, incluyendo el header.
+    pub fn record_message_sent(&mut self, command: &str, bytes: usize) {
+        let stats = self.command_stats(command);
+        stats.messages_sent += 1;
+        stats.bytes_sent += bytes as u64;
+    }
+
+    /// Registra un mensaje recibido con su comando y su tamaño, incluyendo el header.
+    pub fn record_message_received(&mut self, command: &str, bytes: usize) {
+        let stats = self.command_stats(command);
+        stats.messages_received += 1;
+        stats.bytes_received += bytes as u64;
+    }
+
+    /// Devuelve el trafico total del nodo y de cada tipo de mensaje.
+    pub fn get_totals(&self) -> NetTotals {
+        self.totals.clone()
+    }
+
+    /// Devuelve la informacion de todos los peers, ordenada por direccion.
+    pub fn get_all(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.peers.values().cloned().collect();
+        peers.sort_by_key(|info| info.address);
+        peers
+    }
+
+    fn command_stats(&mut self, command: &str) -> &mut MessageStats {
+        self.totals
+            .by_command
+            .entry(command.to_string())
+            .or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    fn address(port: u16) -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv6Addr::LOCALHOST, port, 0, 0)
+    }
+
+    #[test]
+    fn records_ping_and_traffic_of_connected_peers() {
+        let mut registry = PeerInfoRegistry::default();
+        registry.insert(PeerInfo::new(
+            address(18334),
+            "/Satoshi:25.0.0/".to_string(),
+            70016,
+            2_500_000,
+        ));
+        registry.insert(PeerInfo::new(
+            address(18333),
+            "/btcd:0.23.3/".to_string(),
+            70015,
+            2_400_000,
+        ));
+
+        registry.record_ping(&address(18334), Duration::from_millis(120));
+        registry.record_sent(&address(18334), 24);
+        registry.record_received(&address(18334), 100);
+        registry.record_received(&address(18334), 50);
+        // los peers desconectados se ignoran
+        registry.record_received(&address(18335), 50);
+
+        let peers = registry.get_all();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].address, address(18333));
+        assert_eq!(peers[0].ping, None);
+        assert_eq!(peers[1].ping, Some(Duration::from_millis(120)));
+        assert_eq!(peers[1].bytes_sent, 24);
+        assert_eq!(peers[1].bytes_received, 150);
+
+        registry.remove(&address(18333));
+        assert_eq!(registry.get_all().len(), 1);
+    }
+
+    #[test]
+    fn totals_include_disconnected_peers_and_each_command() {
+        let mut registry = PeerInfoRegistry::default();
+        registry.insert(PeerInfo::new(
+            address(18333),
+            "/Satoshi:25.0.0/".to_string(),
+            70016,
+            2_500_000,
+        ));
+        registry.record_sent(&address(18333), 32);
+        registry.record_message_sent("ping", 32);
+        registry.record_received(&address(18333), 56);
+        registry.record_message_received("pong", 32);
+        registry.record_message_received("verack", 24);
+        registry.remove(&address(18333));
+        registry.record_message_received("pong", 32);
+
+        let totals = registry.get_totals();
+        assert_eq!(totals.bytes_sent, 32);
+        assert_eq!(totals.bytes_received, 56);
+        assert_eq!(
+            totals.by_command.keys().collect::<Vec<_>>(),
+            vec!["ping", "pong", "verack"]
+        );
+        assert_eq!(
+            totals.by_command["pong"],
+            MessageStats {
+                messages_sent: 0,
+                bytes_sent: 0,
+                messages_received: 2,
+                bytes_received: 64,
+            }
+        );
+        assert_eq!(totals.by_command["ping"].messages_sent, 1);
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    net::{Ipv6Addr, SocketAddrV6},
+};
+
+use chrono::Local;
+
+use crate::{
+    atomic_file::{read_atomic, write_atomic},
+    error::{CustomError, ParseError},
+    parser::{BufferParser, Parser},
+};
+
+/// Puntaje a partir del cual se desconecta y se banea a un peer.
+const BAN_THRESHOLD: u32 = 100;
+/// Tiempo en segundos que se banea a un peer si no se configura otro.
+pub const DEFAULT_BAN_TIME: u64 = 24 * 60 * 60;
+
+/// Misbehavior es una falta cometida por un peer, cada una con su penalizacion.
+/// - InvalidHeaders: Envio headers que no cumplen las reglas de consenso, se banea en el momento.
+/// - InvalidBlock: Envio un bloque cuyo merkle root no coincide con el del header, se banea en el momento.
+/// - ProtocolViolation: Envio un mensaje que no se pudo parsear.
+/// - LowWorkChain: Termino de enviar una cadena que se aparta de la del nodo con menos trabajo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misbehavior {
+    InvalidHeaders,
+    InvalidBlock,
+    ProtocolViolation,
+    LowWorkChain,
+}
+
+impl Misbehavior {
+    /// Devuelve el puntaje que suma la falta.
+    pub fn penalty(&self) -> u32 {
+        match self {
+            Misbehavior::InvalidHeaders | Misbehavior::InvalidBlock => BAN_THRESHOLD,
+            Misbehavior::ProtocolViolation => 10,
+            // un peer honesto puede quedar en una rama abandonada por una carrera de bloques
+            Misbehavior::LowWorkChain => 20,
+        }
+    }
+
+    /// Devuelve la descripcion de la falta.
+    pub fn description(&self) -> &str {
+        match self {
+            Misbehavior::InvalidHeaders => "invalid headers",
+            Misbehavior::InvalidBlock => "invalid block",
+            Misbehavior::ProtocolViolation => "protocol violation",
+            Misbehavior::LowWorkChain => "low work chain",
+        }
+    }
+}
+
+/// PeerScore lleva el puntaje de las faltas de los peers y las direcciones baneadas.
+/// El puntaje se lleva por IP, sin importar el puerto, para que un peer no pueda evitarlo reconectandose.
+/// Cuando una IP alcanza BAN_THRESHOLD se banea durante ban_time.
+/// Los baneos se guardan en disco para que se mantengan al reiniciar el nodo.
+/// Los elementos son:
+/// - path: Path del archivo donde se guardan los baneos.
+/// - ban_time: Tiempo en segundos que dura un baneo.
+/// - scores: Puntaje de las faltas de cada IP.
+/// - banned: IPs baneadas y el timestamp en segundos en que vence su baneo.
+pub struct PeerScore {
+    path: String,
+    ban_time: u64,
+    scores: HashMap<Ipv6Addr, u32>,
+    banned: HashMap<Ipv6Addr, i64>,
+}
+
+impl PeerScore {
+    /// Inicializa los puntajes restaurando los baneos del archivo donde se guardan.
+    /// Los baneos vencidos se descartan.
+    pub fn new(path: String) -> Result<Self, CustomError> {
+        let buffer = read_atomic(&path)?;
+
+        let now = Local::now().timestamp();
+        let mut parser = BufferParser::new(&buffer);
+        let mut banned = HashMap::new();
+        while !parser.is_empty() {
+            let Ok((ip, until)) = parse_ban(&mut parser) else { break };
+            if until > now {
+                banned.insert(ip, until);
+            }
+        }
+        Ok(Self {
+            path,
+            ban_time: DEFAULT_BAN_TIME,
+            scores: HashMap::new(),
+            banned,
+        })
+    }
+
+    /// Actualiza el tiempo que duran los baneos nuevos.
+    pub fn set_ban_time(&mut self, ban_time: u64) {
+        self.ban_time = ban_time;
+    }
+
+    /// Suma la penalizacion de una falta al puntaje del peer.
+    /// Devuelve true si el peer alcanzo BAN_THRESHOLD y se baneo, en ese caso hay que desconectarlo.
+    pub fn misbehaving(
+        &mut self,
+        address: SocketAddrV6,
+        misbehavior: Misbehavior,
+    ) -> Result<bool, CustomError> {
+        let score = self.scores.entry(*address.ip()).or_insert(0);
+        *score += misbehavior.penalty();
+        if *score < BAN_THRESHOLD {
+            return Ok(false);
+        }
+
+        self.scores.remove(address.ip());
+        let until = Local::now().timestamp() + self.ban_time as i64;
+        self.banned.insert(*address.ip(), until);
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Devuelve true si la IP de la direccion esta baneada.
+    pub fn is_banned(&self, address: &SocketAddrV6) -> bool {
+        self.banned
+            .get(address.ip())
+            .map(|until| *until > Local::now().timestamp())
+            .unwrap_or(false)
+    }
+
+    fn save(&mut self) -> Result<(), CustomError> {
+        let now = Local::now().timestamp();
+        self.banned.retain(|_, until| *until > now);
+
+        let mut buffer = vec![];
+        for (ip, until) in &self.banned {
+            buffer.extend(ip.octets());
+            buffer.extend(until.to_le_bytes());
+        }
+
+        write_atomic(&self.path, &buffer)
+    }
+}
+
+/// Lee un baneo guardado: la IP y el timestamp en que vence.
+fn parse_ban(parser: &mut BufferParser) -> Result<(Ipv6Addr, i64), CustomError> {
+    let octets: [u8; 16] = parser
+        .extract_buffer(16)?
+        .try_into()
+        .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+    Ok((Ipv6Addr::from(octets), parser.extract_i64()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::atomic_file::remove_atomic;
+
+    use super::*;
+
+    fn address(host: u8, port: u16) -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv4Addr::new(10, 0, 0, host).to_ipv6_mapped(), port, 0, 0)
+    }
+
+    #[test]
+    fn ban_after_threshold() {
+        let path = String::from("tests/test_peer_score_threshold.bin");
+        let mut peer_score = PeerScore::new(path.clone()).unwrap();
+
+        // el puntaje se acumula aunque el peer se reconecte desde otro puerto
+        for port in 0..9 {
+            assert!(!peer_score
+                .misbehaving(address(1, 18000 + port), Misbehavior::ProtocolViolation)
+                .unwrap());
+        }
+        assert!(!peer_score.is_banned(&address(1, 18333)));
+        assert!(peer_score
+            .misbehaving(address(1, 18333), Misbehavior::ProtocolViolation)
+            .unwrap());
+
+        // se banea la IP, sin importar el puerto
+        assert!(peer_score.is_banned(&address(1, 18334)));
+        assert!(!peer_score.is_banned(&address(2, 18333)));
+
+        assert!(peer_score
+            .misbehaving(address(2, 18333), Misbehavior::InvalidHeaders)
+            .unwrap());
+        assert!(peer_score.is_banned(&address(2, 18333)));
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn bans_are_restored_until_they_expire() {
+        let path = String::from("tests/test_peer_score_restore.bin");
+        let mut peer_score = PeerScore::new(path.clone()).unwrap();
+        peer_score
+            .misbehaving(address(1, 18333), Misbehavior::InvalidBlock)
+            .unwrap();
+
+        peer_score.set_ban_time(0);
+        peer_score
+            .misbehaving(address(2, 18333), Misbehavior::InvalidBlock)
+            .unwrap();
+        assert!(!peer_score.is_banned(&address(2, 18333)));
+
+        let restored = PeerScore::new(path.clone()).unwrap();
+        assert!(restored.is_banned(&address(1, 18333)));
+        assert_eq!(restored.banned.len(), 1);
+
+        remove_atomic(&path).unwrap();
+    }
+}
@@ -1,56 +1,63 @@
 use std::{
     collections::HashMap,
-    path::Path,
     sync::{Arc, Mutex},
 };
 
 use crate::{
     error::CustomError,
-    structs::block_header::BlockHeader,
-    utils::{calculate_index_from_timestamp, get_current_timestamp},
+    headers_store::{HeaderChain, HEADERS_READ_BATCH},
+    network::Network,
+    structs::hash256::Hash256,
+    utils::get_current_timestamp,
 };
 
-use super::utxo_state::START_DATE_IBD;
-
 /// PendingBlocks es una estructura para manejar los bloques solicitados pendientes de recibir.
 /// Los elementos son:
 /// - blocks: HashMap que contiene los bloques pendientes de recibir y un timestamp del momento en el que se generó.
 /// - stale_time: Tiempo en segundos que debe pasar para que una peticion de bloque sea considerada como vencida.
 pub struct PendingBlocks {
-    blocks: HashMap<Vec<u8>, u64>,
+    blocks: HashMap<Hash256, u64>,
     stale_time: u64,
 }
 
 impl PendingBlocks {
-    #[must_use]
-    /// Inicializa la estructura.
-    pub fn new(store_path: &String, headers: &Vec<BlockHeader>) -> Arc<Mutex<Self>> {
+    /// Inicializa la estructura con los bloques posteriores al inicio de la IBD de la red seleccionada que is_downloaded indica que no se descargaron.
+    /// Los bloques de los primeros prune_height headers ya se podaron, por lo que no se vuelven a pedir.
+    /// Los headers se leen por lotes de HEADERS_READ_BATCH.
+    pub fn new(
+        headers: &impl HeaderChain,
+        prune_height: usize,
+        is_downloaded: impl Fn(&Hash256) -> bool,
+    ) -> Result<Arc<Mutex<Self>>, CustomError> {
         let mut blocks = HashMap::new();
-        let starting_index = calculate_index_from_timestamp(headers, START_DATE_IBD) + 1;
-
-        for header in headers.iter().skip(starting_index) {
-            let path = format!("{}/blocks/{}.bin", store_path, header.hash_as_string());
-
-            if !Path::new(&path).exists() {
-                blocks.insert(header.hash().clone(), 0_u64);
+        let mut starting_index =
+            (headers.index_from_timestamp(Network::current().ibd_start_timestamp())? + 1)
+                .max(prune_height);
+
+        while starting_index < headers.len() {
+            for header in headers.read_range(starting_index, HEADERS_READ_BATCH)? {
+                if !is_downloaded(header.hash()) {
+                    blocks.insert(*header.hash(), 0_u64);
+                }
             }
+            starting_index += HEADERS_READ_BATCH;
         }
 
-        Arc::new(Mutex::new(Self {
+        Ok(Arc::new(Mutex::new(Self {
             blocks,
             stale_time: 5,
-        }))
+        })))
     }
 
     /// Agrega un bloque a la lista de bloques pendientes de recibir con el timestamp actual.
-    pub fn append_block(&mut self, block_hash: Vec<u8>) -> Result<(), CustomError> {
+    pub fn append_block(&mut self, block_hash: Hash256) -> Result<(), CustomError> {
         let current_time = get_current_timestamp()?;
         self.blocks.insert(block_hash, current_time);
         Ok(())
     }
 
     /// Elimina un bloque de la lista de bloques pendientes de recibir.
-    pub fn remove_block(&mut self, block_hash: &Vec<u8>) -> Result<(), CustomError> {
+    pub fn remove_block(&mut self, block_hash: &Hash256) -> Result<(), CustomError> {
         self.blocks.remove(block_hash);
         Ok(())
     }
@@ -60,17 +67,22 @@ impl PendingBlocks {
         self.blocks.drain();
     }
 
+    /// Devuelve los hashes de los bloques pendientes de recibir.
+    pub fn get_blocks(&self) -> Vec<Hash256> {
+        self.blocks.keys().cloned().collect()
+    }
+
     pub fn len(&self) -> usize {
         self.blocks.len()
     }
 
     /// Devuelve los bloques pendientes de recibir que ya vencieron.
-    pub fn get_stale_requests(&mut self) -> Result<Vec<Vec<u8>>, CustomError> {
+    pub fn get_stale_requests(&mut self) -> Result<Vec<Hash256>, CustomError> {
         let mut to_remove = Vec::new();
 
         for (block_hash, timestamp) in &self.blocks {
             if *timestamp + self.stale_time < get_current_timestamp()? {
-                to_remove.push(block_hash.clone());
+                to_remove.push(*block_hash);
             }
         }
 
@@ -82,7 +94,7 @@ impl PendingBlocks {
     }
 
     /// Devuelve true si el bloque esta en la lista de bloques pendientes de recibir.
-    pub fn is_block_pending(&self, block_hash: &Vec<u8>) -> bool {
+    pub fn is_block_pending(&self, block_hash: &Hash256) -> bool {
         self.blocks.contains_key(block_hash)
     }
 
@@ -97,11 +109,13 @@ mod tests {
 
     use std::{thread, time::Duration};
 
+    use crate::structs::block_header::BlockHeader;
+
     use super::*;
 
     #[test]
     fn pending_blocks_creation() {
-        let pending_blocks = PendingBlocks::new(&"".to_string(), &vec![]);
+        let pending_blocks = PendingBlocks::new(&vec![], 0, |_| false).unwrap();
         let pending_blocks = pending_blocks.lock().unwrap();
 
         assert_eq!(pending_blocks.is_empty(), true);
@@ -109,11 +123,11 @@ mod tests {
 
     #[test]
     fn append_block() {
-        let pending_blocks = PendingBlocks::new(&"".to_string(), &vec![]);
+        let pending_blocks = PendingBlocks::new(&vec![], 0, |_| false).unwrap();
         let mut pending_blocks = pending_blocks.lock().unwrap();
 
-        let block_hash = vec![1, 2, 3, 4, 5];
-        pending_blocks.append_block(block_hash.clone()).unwrap();
+        let block_hash = Hash256::new([1; 32]);
+        pending_blocks.append_block(block_hash).unwrap();
 
         assert_eq!(pending_blocks.is_block_pending(&block_hash), true);
         assert_eq!(pending_blocks.is_empty(), false);
@@ -121,11 +135,11 @@ mod tests {
 
     #[test]
     fn remove_block() {
-        let pending_blocks = PendingBlocks::new(&"".to_string(), &vec![]);
+        let pending_blocks = PendingBlocks::new(&vec![], 0, |_| false).unwrap();
         let mut pending_blocks = pending_blocks.lock().unwrap();
 
-        let block_hash = vec![1, 2, 3, 4, 5];
-        pending_blocks.append_block(block_hash.clone()).unwrap();
+        let block_hash = Hash256::new([1; 32]);
+        pending_blocks.append_block(block_hash).unwrap();
         assert_eq!(pending_blocks.is_empty(), false);
         pending_blocks.remove_block(&block_hash).unwrap();
 
@@ -135,13 +149,13 @@ mod tests {
 
     #[test]
     fn drain() {
-        let pending_blocks = PendingBlocks::new(&"".to_string(), &vec![]);
+        let pending_blocks = PendingBlocks::new(&vec![], 0, |_| false).unwrap();
         let mut pending_blocks = pending_blocks.lock().unwrap();
 
-        let block_hash = vec![1, 2, 3, 4, 5];
-        let block_hash2 = vec![6, 7, 8, 9, 10];
-        pending_blocks.append_block(block_hash.clone()).unwrap();
-        pending_blocks.append_block(block_hash2.clone()).unwrap();
+        let block_hash = Hash256::new([1; 32]);
+        let block_hash2 = Hash256::new([6; 32]);
+        pending_blocks.append_block(block_hash).unwrap();
+        pending_blocks.append_block(block_hash2).unwrap();
 
         assert_eq!(pending_blocks.is_empty(), false);
         pending_blocks.drain();
@@ -150,12 +164,12 @@ mod tests {
 
     #[test]
     fn get_stale_requests() {
-        let pending_blocks = PendingBlocks::new(&"".to_string(), &vec![]);
+        let pending_blocks = PendingBlocks::new(&vec![], 0, |_| false).unwrap();
         let mut pending_blocks = pending_blocks.lock().unwrap();
 
-        let block_hash = vec![1, 2, 3, 4, 5];
+        let block_hash = Hash256::new([1; 32]);
 
-        pending_blocks.append_block(block_hash.clone()).unwrap();
+        pending_blocks.append_block(block_hash).unwrap();
 
         let stale_requests = pending_blocks.get_stale_requests().unwrap();
         assert_eq!(stale_requests.len(), 0);
@@ -172,11 +186,11 @@ mod tests {
         let old_header = BlockHeader {
             bits: 0,
             nonce: 0,
-            prev_block_hash: vec![],
-            timestamp: START_DATE_IBD - 1,
+            prev_block_hash: Hash256::ZERO,
+            timestamp: Network::current().ibd_start_timestamp() - 1,
             version: 0,
-            hash: vec![1, 2, 3],
-            merkle_root: vec![],
+            hash: Hash256::new([1; 32]),
+            merkle_root: Hash256::ZERO,
             block_downloaded: true,
             broadcasted: true,
         };
@@ -184,24 +198,28 @@ mod tests {
         let lost_header = BlockHeader {
             bits: 0,
             nonce: 0,
-            prev_block_hash: vec![],
-            timestamp: START_DATE_IBD + 1,
+            prev_block_hash: Hash256::ZERO,
+            timestamp: Network::current().ibd_start_timestamp() + 1,
             version: 0,
-            hash: vec![
+            hash: Hash256::new([
                 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7,
                 8, 9, 1, 2,
-            ],
-            merkle_root: vec![],
+            ]),
+            merkle_root: Hash256::ZERO,
             block_downloaded: true,
             broadcasted: true,
         };
 
-        let pending_blocks =
-            PendingBlocks::new(&"".to_string(), &vec![old_header, lost_header.clone()]);
+        let headers = vec![old_header, lost_header.clone()];
+        let pending_blocks = PendingBlocks::new(&headers, 0, |_| false).unwrap();
 
         let pending_blocks = pending_blocks.lock().unwrap();
         assert_eq!(pending_blocks.is_empty(), false);
         assert_eq!(pending_blocks.len(), 1);
         assert_eq!(pending_blocks.is_block_pending(&lost_header.hash), true);
+
+        // los bloques podados no se vuelven a pedir
+        let pending_blocks = PendingBlocks::new(&headers, 2, |_| false).unwrap();
+        assert!(pending_blocks.lock().unwrap().is_empty());
     }
 }
@@ -0,0 +1,291 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Tiempo durante el que se conservan las muestras con las que se estima la velocidad de descarga.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Tiempo minimo entre dos muestras consecutivas.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// SyncMilestone es una enumeracion de las etapas que alcanza el nodo al sincronizarse con la red.
+/// Las etapas son:
+/// - HeadersSynced: Se descargaron los headers hasta la altura de los peers.
+/// - BlocksSynced: Se descargaron los bloques desde la fecha de inicio del IBD.
+/// - Synced: Se genero el UTXO y el nodo esta listo para usarse.
+/// - FiltersScanned: Se terminaron de escanear los filtros compactos de los bloques anteriores al IBD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMilestone {
+    HeadersSynced,
+    BlocksSynced,
+    Synced,
+    FiltersScanned,
+}
+
+impl SyncMilestone {
+    /// Devuelve la descripcion de la etapa, para mostrarla al usuario.
+    pub fn description(&self) -> &'static str {
+        match self {
+            SyncMilestone::HeadersSynced => "Headers sync completed",
+            SyncMilestone::BlocksSynced => "Blocks sync completed",
+            SyncMilestone::Synced => "Node synced with the network",
+            SyncMilestone::FiltersScanned => "Compact filters scan completed",
+        }
+    }
+}
+
+/// SyncProgress es el progreso de la descarga inicial de headers y bloques.
+/// Los elementos son:
+/// - headers: Cantidad de headers descargados, es la altura actual de la cadena.
+/// - peer_height: Altura de la cadena informada por los peers en el handshake.
+/// - blocks_downloaded: Cantidad de bloques descargados desde la fecha de inicio del IBD.
+/// - blocks_to_download: Cantidad de bloques a descargar desde la fecha de inicio del IBD.
+/// - eta: Tiempo restante estimado en segundos de la etapa actual, None si todavia no se puede estimar.
+/// - synced: Booleano que indica si el nodo termino de sincronizarse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SyncProgress {
+    pub headers: usize,
+    pub peer_height: usize,
+    pub blocks_downloaded: usize,
+    pub blocks_to_download: usize,
+    pub eta: Option<u64>,
+    pub synced: bool,
+}
+
+impl SyncProgress {
+    /// Devuelve true mientras se estan descargando headers.
+    pub fn downloading_headers(&self) -> bool {
+        self.headers < self.peer_height
+    }
+
+    /// Devuelve la fraccion completada de la etapa actual, entre 0 y 1.
+    pub fn fraction(&self) -> f64 {
+        if self.synced {
+            return 1.0;
+        }
+        let (done, total) = match self.downloading_headers() {
+            true => (self.headers, self.peer_height),
+            false => (self.blocks_downloaded, self.blocks_to_download),
+        };
+        match total {
+            0 => 0.0,
+            total => (done as f64 / total as f64).min(1.0),
+        }
+    }
+
+    /// Devuelve el progreso como texto, por ejemplo "Blocks 120/800 (3 min left)".
+    pub fn description(&self) -> String {
+        if self.synced {
+            return format!("Synced at height {}", self.headers);
+        }
+        let progress = match self.downloading_headers() {
+            true => format!("Headers {}/{}", self.headers, self.peer_height),
+            false => format!(
+                "Blocks {}/{}",
+                self.blocks_downloaded, self.blocks_to_download
+            ),
+        };
+        match self.eta {
+            Some(eta) => format!("{} ({} left)", progress, format_duration(eta)),
+            None => progress,
+        }
+    }
+}
+
+/// Devuelve una duracion en segundos como texto legible, por ejemplo "1 h 5 min".
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{} s", seconds),
+        60..=3599 => format!("{} min", seconds / 60),
+        _ => format!("{} h {} min", seconds / 3600, (seconds % 3600) / 60),
+    }
+}
+
+/// RateEstimator estima la velocidad de una descarga a partir de muestras de la cantidad descargada.
+/// Los elementos son:
+/// - samples: Muestras de la ultima ventana de tiempo, con el instante y la cantidad descargada.
+/// - current: Ultima cantidad descargada registrada y su instante.
+#[derive(Debug, Default)]
+struct RateEstimator {
+    samples: VecDeque<(Instant, usize)>,
+    current: Option<(Instant, usize)>,
+}
+
+impl RateEstimator {
+    /// Registra la cantidad descargada hasta el instante recibido.
+    fn record(&mut self, now: Instant, count: usize) {
+        self.current = Some((now, count));
+        let sample_due = match self.samples.back() {
+            Some((last, _)) => now.duration_since(*last) >= SAMPLE_INTERVAL,
+            None => true,
+        };
+        if sample_due {
+            self.samples.push_back((now, count));
+        }
+        while let Some((oldest, _)) = self.samples.front() {
+            if now.duration_since(*oldest) <= RATE_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    /// Devuelve el tiempo estimado en segundos para descargar la cantidad restante,
+    /// o None si no hay muestras suficientes o la descarga no avanzo.
+    fn eta(&self, remaining: usize) -> Option<u64> {
+        let (oldest, oldest_count) = self.samples.front()?;
+        let (now, count) = self.current?;
+        let elapsed = now.duration_since(*oldest).as_secs_f64();
+        let downloaded = count.checked_sub(*oldest_count).filter(|d| *d > 0)?;
+        if elapsed <= 0.0 {
+            return None;
+        }
+        Some((remaining as f64 * elapsed / downloaded as f64).ceil() as u64)
+    }
+
+    /// Descarta las muestras, por ejemplo si la cantidad descargada retrocedio por una reorganizacion.
+    fn reset(&mut self) {
+        self.samples.clear();
+        self.current = None;
+    }
+}
+
+/// SyncProgressTracker mantiene el progreso de la sincronizacion a medida que se descargan headers y bloques,
+/// y estima el tiempo restante con la velocidad de descarga del ultimo minuto.
+/// Los elementos son:
+/// - progress: Ultimo progreso registrado.
+/// - headers_rate: Estimador de la velocidad de descarga de headers.
+/// - blocks_rate: Estimador de la velocidad de descarga de bloques.
+/// - milestones: Etapas de la sincronizacion que ya se alcanzaron.
+#[derive(Debug, Default)]
+pub struct SyncProgressTracker {
+    progress: SyncProgress,
+    headers_rate: RateEstimator,
+    blocks_rate: RateEstimator,
+    milestones: Vec<SyncMilestone>,
+}
+
+impl SyncProgressTracker {
+    /// Inicializa el tracker sin progreso registrado.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registra la cantidad de headers descargados y la altura de la cadena de los peers.
+    pub fn update_headers(&mut self, now: Instant, headers: usize, peer_height: usize) {
+        if headers < self.progress.headers {
+            self.headers_rate.reset();
+        }
+        self.progress.headers = headers;
+        self.progress.peer_height = peer_height.max(headers);
+        self.headers_rate.record(now, headers);
+        self.update_eta();
+    }
+
+    /// Registra la cantidad de bloques descargados y la cantidad total a descargar.
+    pub fn update_blocks(
+        &mut self,
+        now: Instant,
+        blocks_downloaded: usize,
+        blocks_to_download: usize,
+    ) {
+        if blocks_downloaded < self.progress.blocks_downloaded {
+            self.blocks_rate.reset();
+        }
+        self.progress.blocks_downloaded = blocks_downloaded;
+        self.progress.blocks_to_download = blocks_to_download;
+        self.blocks_rate.record(now, blocks_downloaded);
+        self.update_eta();
+    }
+
+    /// Registra si el nodo termino de sincronizarse.
+    pub fn set_synced(&mut self, synced: bool) {
+        self.progress.synced = synced;
+        self.update_eta();
+    }
+
+    /// Devuelve el progreso actual.
+    pub fn get(&self) -> SyncProgress {
+        self.progress
+    }
+
+    /// Registra que se alcanzo una etapa de la sincronizacion.
+    /// Devuelve true si es la primera vez que se alcanza, para avisarla una sola vez.
+    pub fn reach(&mut self, milestone: SyncMilestone) -> bool {
+        if self.milestones.contains(&milestone) {
+            return false;
+        }
+        self.milestones.push(milestone);
+        true
+    }
+
+    fn update_eta(&mut self) {
+        let progress = &self.progress;
+        self.progress.eta = match (progress.synced, progress.downloading_headers()) {
+            (true, _) => None,
+            (false, true) => self
+                .headers_rate
+                .eta(progress.peer_height - progress.headers),
+            (false, false) => self.blocks_rate.eta(
+                progress
+                    .blocks_to_download
+                    .saturating_sub(progress.blocks_downloaded),
+            ),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_remaining_time_from_download_rate() {
+        let start = Instant::now();
+        let mut tracker = SyncProgressTracker::new();
+        tracker.update_headers(start, 0, 10_000);
+        assert_eq!(tracker.get().eta, None);
+
+        tracker.update_headers(start + Duration::from_secs(2), 2_000, 10_000);
+        let progress = tracker.get();
+        assert!(progress.downloading_headers());
+        assert_eq!(progress.eta, Some(8));
+        assert_eq!(progress.fraction(), 0.2);
+        assert_eq!(progress.description(), "Headers 2000/10000 (8 s left)");
+
+        tracker.update_headers(start + Duration::from_secs(10), 10_000, 10_000);
+        tracker.update_blocks(start + Duration::from_secs(10), 0, 600);
+        tracker.update_blocks(start + Duration::from_secs(70), 60, 600);
+        let progress = tracker.get();
+        assert!(!progress.downloading_headers());
+        assert_eq!(progress.eta, Some(540));
+        assert_eq!(progress.fraction(), 0.1);
+        assert_eq!(progress.description(), "Blocks 60/600 (9 min left)");
+
+        tracker.set_synced(true);
+        assert_eq!(tracker.get().eta, None);
+        assert_eq!(tracker.get().fraction(), 1.0);
+    }
+
+    #[test]
+    fn milestones_are_reached_once() {
+        let mut tracker = SyncProgressTracker::new();
+        assert!(tracker.reach(SyncMilestone::HeadersSynced));
+        assert!(!tracker.reach(SyncMilestone::HeadersSynced));
+        assert!(tracker.reach(SyncMilestone::Synced));
+        assert_eq!(
+            SyncMilestone::Synced.description(),
+            "Node synced with the network"
+        );
+    }
+
+    #[test]
+    fn peer_height_is_never_below_the_chain() {
+        let mut tracker = SyncProgressTracker::new();
+        tracker.update_headers(Instant::now(), 500, 0);
+        let progress = tracker.get();
+        assert_eq!(progress.peer_height, 500);
+        assert!(!progress.downloading_headers());
+        assert_eq!(progress.fraction(), 0.0);
+    }
+}
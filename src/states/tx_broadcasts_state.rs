@@ -0,0 +1,201 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddrV6,
+};
+
+use crate::{discovery::shuffle, structs::hash256::Hash256};
+
+/// BroadcastStatus es el estado de propagacion de una transaccion enviada por el nodo.
+/// - Sent: La transaccion se envio a la cantidad de peers indicada.
+/// - Announced: La transaccion fue anunciada por la cantidad de peers indicada, de los peers a los que no se envio.
+///   Un peer nunca anuncia una transaccion al peer del que la recibio, por lo que solo estos peers prueban que se propago.
+/// - Rejected: Un peer rechazo la transaccion con el motivo indicado.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BroadcastStatus {
+    Sent(usize),
+    Announced(usize, usize),
+    Rejected(SocketAddrV6, String),
+}
+
+/// TxBroadcast es el seguimiento de una transaccion enviada.
+/// Los elementos son:
+/// - sent_to: Peers a los que se envio la transaccion.
+/// - listeners: Cantidad de peers a los que no se envio la transaccion, que deberian recibirla de la red.
+/// - announced_by: Peers a los que no se envio la transaccion y que la anunciaron con un inv.
+/// - rejected_by: Peers que rechazaron la transaccion y el motivo.
+struct TxBroadcast {
+    sent_to: HashSet<SocketAddrV6>,
+    listeners: usize,
+    announced_by: HashSet<SocketAddrV6>,
+    rejected_by: HashMap<SocketAddrV6, String>,
+}
+
+/// TxBroadcasts es una estructura que contiene el estado de propagacion de las transacciones enviadas por el nodo.
+/// Una transaccion deja de seguirse cuando se confirma en un bloque.
+pub struct TxBroadcasts {
+    broadcasts: HashMap<Hash256, TxBroadcast>,
+}
+
+impl Default for TxBroadcasts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxBroadcasts {
+    /// Inicializa el seguimiento sin transacciones.
+    pub fn new() -> Self {
+        Self {
+            broadcasts: HashMap::new(),
+        }
+    }
+
+    /// Registra una transaccion enviada a los peers de sent_to, de un total de peers conectados que la pueden recibir.
+    pub fn append(
+        &mut self,
+        tx_hash: Hash256,
+        sent_to: Vec<SocketAddrV6>,
+        peers: usize,
+    ) -> BroadcastStatus {
+        let sent_to: HashSet<SocketAddrV6> = sent_to.into_iter().collect();
+        let status = BroadcastStatus::Sent(sent_to.len());
+        self.broadcasts.insert(
+            tx_hash,
+            TxBroadcast {
+                listeners: peers.saturating_sub(sent_to.len()),
+                sent_to,
+                announced_by: HashSet::new(),
+                rejected_by: HashMap::new(),
+            },
+        );
+        status
+    }
+
+    /// Registra que un peer anuncio la transaccion.
+    /// Devuelve None si la transaccion no es seguida, si se le envio al peer o si el peer ya la habia anunciado.
+    pub fn announced(
+        &mut self,
+        tx_hash: &Hash256,
+        address: SocketAddrV6,
+    ) -> Option<BroadcastStatus> {
+        let broadcast = self.broadcasts.get_mut(tx_hash)?;
+        if broadcast.sent_to.contains(&address) || !broadcast.announced_by.insert(address) {
+            return None;
+        }
+        Some(BroadcastStatus::Announced(
+            broadcast.announced_by.len(),
+            broadcast.listeners,
+        ))
+    }
+
+    /// Registra que un peer rechazo la transaccion.
+    /// Devuelve None si la transaccion no es seguida o el peer ya la habia rechazado.
+    pub fn rejected(
+        &mut self,
+        tx_hash: &Hash256,
+        address: SocketAddrV6,
+        reason: String,
+    ) -> Option<BroadcastStatus> {
+        let broadcast = self.broadcasts.get_mut(tx_hash)?;
+        if broadcast.rejected_by.contains_key(&address) {
+            return None;
+        }
+        broadcast.rejected_by.insert(address, reason.clone());
+        Some(BroadcastStatus::Rejected(address, reason))
+    }
+
+    /// Deja de seguir las transacciones recibidas, por ejemplo al confirmarse en un bloque.
+    pub fn remove(&mut self, tx_hashes: &[Hash256]) {
+        for tx_hash in tx_hashes {
+            self.broadcasts.remove(tx_hash);
+        }
+    }
+}
+
+/// Elige al azar los peers a los que se envia una transaccion propia: la mitad de los peers, redondeando hacia arriba.
+/// Los demas peers deben recibirla de la red, por lo que sus anuncios confirman que se propago.
+pub fn select_broadcast_peers(mut peers: Vec<SocketAddrV6>) -> Vec<SocketAddrV6> {
+    shuffle(&mut peers);
+    peers.truncate(peers.len().div_ceil(2));
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    fn address(port: u16) -> SocketAddrV6 {
+        SocketAddrV6::new(Ipv6Addr::LOCALHOST, port, 0, 0)
+    }
+
+    #[test]
+    fn track_announcements() {
+        let mut broadcasts = TxBroadcasts::new();
+        assert_eq!(
+            broadcasts.append(Hash256::new([1; 32]), vec![address(1)], 4),
+            BroadcastStatus::Sent(1)
+        );
+
+        // el peer al que se envio la transaccion no la anuncia de vuelta
+        assert_eq!(
+            broadcasts.announced(&Hash256::new([1; 32]), address(1)),
+            None
+        );
+        assert_eq!(
+            broadcasts.announced(&Hash256::new([1; 32]), address(2)),
+            Some(BroadcastStatus::Announced(1, 3))
+        );
+        assert_eq!(
+            broadcasts.announced(&Hash256::new([1; 32]), address(2)),
+            None
+        );
+        assert_eq!(
+            broadcasts.announced(&Hash256::new([1; 32]), address(3)),
+            Some(BroadcastStatus::Announced(2, 3))
+        );
+        assert_eq!(
+            broadcasts.announced(&Hash256::new([2; 32]), address(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn track_rejections() {
+        let mut broadcasts = TxBroadcasts::new();
+        broadcasts.append(Hash256::new([1; 32]), vec![address(1)], 2);
+
+        let status = broadcasts.rejected(&Hash256::new([1; 32]), address(1), String::from("dust"));
+        assert_eq!(
+            status,
+            Some(BroadcastStatus::Rejected(address(1), String::from("dust")))
+        );
+        assert_eq!(
+            broadcasts.rejected(&Hash256::new([1; 32]), address(1), String::from("dust")),
+            None
+        );
+    }
+
+    #[test]
+    fn confirmed_transactions_are_removed() {
+        let mut broadcasts = TxBroadcasts::new();
+        broadcasts.append(Hash256::new([1; 32]), vec![address(1)], 2);
+        broadcasts.remove(&[Hash256::new([1; 32])]);
+        assert_eq!(
+            broadcasts.announced(&Hash256::new([1; 32]), address(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn broadcast_to_half_of_the_peers() {
+        let peers: Vec<SocketAddrV6> = (1..=5).map(address).collect();
+        let selected = select_broadcast_peers(peers.clone());
+
+        assert_eq!(selected.len(), 3);
+        assert!(selected.iter().all(|peer| peers.contains(peer)));
+        assert_eq!(select_broadcast_peers(vec![address(1)]), vec![address(1)]);
+        assert!(select_broadcast_peers(vec![]).is_empty());
+    }
+}
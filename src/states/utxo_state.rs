@@ -1,25 +1,19 @@
 use crate::{
-    error::CustomError,
-    logger::{send_log, Log},
+    atomic_file::{read_atomic, remove_atomic, write_atomic},
+    error::{CustomError, StorageError},
+    headers_store::{HeaderChain, HEADERS_READ_BATCH},
+    logger::{send_log, Log, LogEntry},
     messages::block::Block,
-    parser::BufferParser,
+    network::Network,
+    parser::{BufferParser, Parser},
     structs::tx_output::TransactionOutput,
-    structs::{block_header::BlockHeader, outpoint::OutPoint},
-    utils::{calculate_index_from_timestamp, open_new_file},
+    structs::{block_header::hash_as_string, hash256::Hash256, outpoint::OutPoint},
     wallet::Wallet,
 };
 use std::{
-    collections::HashMap,
-    fs::remove_file,
-    io::{Read, Write},
-    path::Path,
-    process::exit,
-    sync::mpsc::Sender,
-    vec,
+    collections::HashMap, fs::create_dir_all, path::Path, process::exit, sync::mpsc::Sender, vec,
 };
 
-pub const START_DATE_IBD: u32 = 1681095630;
-
 #[derive(Debug, PartialEq, Clone)]
 /// UTXOValue es una estructura que contiene los valores que necesitamos guardar de las UTXO.
 /// Los elementos son:
@@ -28,15 +22,37 @@ pub const START_DATE_IBD: u32 = 1681095630;
 /// - block_timestamp: Timestamp del bloque donde se encuentra el UTXO.
 pub struct UTXOValue {
     pub tx_out: TransactionOutput,
-    pub block_hash: Vec<u8>,
+    pub block_hash: Hash256,
     pub block_timestamp: u32,
 }
 
+impl UTXOValue {
+    /// Serializa el valor de la UTXO.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.tx_out.serialize();
+        buffer.extend(self.block_hash.as_bytes());
+        buffer.extend(self.block_timestamp.to_le_bytes());
+        buffer
+    }
+
+    /// Parsea un valor de UTXO serializado.
+    pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        Ok(Self {
+            tx_out: TransactionOutput::parse(parser)?,
+            block_hash: parser.extract_hash32()?.into(),
+            block_timestamp: parser.extract_u32()?,
+        })
+    }
+}
+
 #[derive(PartialEq)]
 /// UTXO es una estructura que contiene los elementos necesarios para manejar las UTXO.
+/// Por cada bloque recibido una vez sincronizado, se guardan en la carpeta undo los outputs que gasto,
+/// para poder deshacerlo ante una reorganizacion de la cadena.
 /// Los elementos son:
 /// - tx_set: HashMap que contiene las UTXO con su OutPoint y UTXOValue.
 /// - sync: Indica si las UTXO estan sincronizadas con la red.
+/// - last_block_hash: Hash del ultimo bloque procesado.
 /// - store_path: Path de la carpeta store.
 /// - path: Path del archivo donde se guardan las UTXO.
 /// El UTXO tiene un sistema de guardado tipo checkpoint
@@ -44,6 +60,7 @@ pub struct UTXOValue {
 pub struct UTXO {
     pub tx_set: HashMap<OutPoint, UTXOValue>,
     sync: bool,
+    last_block_hash: Option<Hash256>,
     store_path: String,
     path: String,
 }
@@ -55,6 +72,7 @@ impl UTXO {
         Ok(Self {
             tx_set: HashMap::new(),
             sync: false,
+            last_block_hash: None,
             store_path,
             path,
         })
@@ -63,9 +81,9 @@ impl UTXO {
     /// Devuelve el balance de una wallet.
     pub fn wallet_balance(&self, wallet: &Wallet) -> Result<u64, CustomError> {
         let mut balance = 0;
-        let pubkey_hash = wallet.get_pubkey_hash()?;
+        let pubkey_hashes = wallet.get_pubkey_hashes()?;
         for value in self.tx_set.values() {
-            if value.tx_out.is_sent_to_key(&pubkey_hash)? {
+            if value.tx_out.is_sent_to_any_key(&pubkey_hashes)? {
                 balance += value.tx_out.value;
             }
         }
@@ -77,11 +95,11 @@ impl UTXO {
         &self,
         wallet: &Wallet,
     ) -> Result<Vec<(OutPoint, UTXOValue)>, CustomError> {
-        let pubkey_hash = wallet.get_pubkey_hash()?;
+        let pubkey_hashes = wallet.get_pubkey_hashes()?;
 
         let mut active_wallet_utxo = vec![];
         for (out_point, value) in &self.tx_set {
-            if value.tx_out.is_sent_to_key(&pubkey_hash)? {
+            if value.tx_out.is_sent_to_any_key(&pubkey_hashes)? {
                 active_wallet_utxo.push((out_point.clone(), value.clone()));
             }
         }
@@ -94,23 +112,47 @@ impl UTXO {
         self.sync
     }
 
+    /// Guarda las UTXO en su archivo, si ya se generaron.
+    pub fn flush(&mut self) -> Result<(), CustomError> {
+        match (self.sync, self.last_block_hash) {
+            (true, Some(block_hash)) => self.save(block_hash),
+            _ => Ok(()),
+        }
+    }
+
+    /// Devuelve el hash del ultimo bloque procesado.
+    pub fn last_block_hash(&self) -> Option<&Hash256> {
+        self.last_block_hash.as_ref()
+    }
+
     /// Genera las UTXO a partir de los headers.
-    /// Si el archivo donde se guardan las UTXO no existe, se crea.
+    /// Si el archivo donde se guardan las UTXO no existe, se crea, procesando antes del IBD los bloques
+    /// historicos recibidos, ordenados por altura, por ejemplo los que coincidieron con los filtros compactos.
     /// Si el archivo existe, se restauran las UTXO hasta ese punto y se recorren unicamente
     /// los bloques posteriores al bloque del hash guardado en el archivo.
     pub fn generate(
         &mut self,
-        headers: &Vec<BlockHeader>,
-        logger_sender: &mut Sender<Log>,
+        headers: &impl HeaderChain,
+        historical_blocks: &[Hash256],
+        logger_sender: &mut Sender<LogEntry>,
     ) -> Result<(), CustomError> {
-        let last_block_hash = self.restore_utxo()?.unwrap_or_else(|| {
-            let first_block_index = calculate_index_from_timestamp(headers, START_DATE_IBD);
-            headers[first_block_index].hash().clone()
-        });
+        let last_block_hash = match self.restore_utxo()? {
+            Some(last_block_hash) => last_block_hash,
+            None => {
+                self.update_from_historical_blocks(historical_blocks)?;
+                let first_block_index =
+                    headers.index_from_timestamp(Network::current().ibd_start_timestamp())?;
+                headers
+                    .get(first_block_index)?
+                    .map(|header| *header.hash())
+                    .unwrap_or_default()
+            }
+        };
 
         let new_last_block_hash = self.update(headers, last_block_hash, logger_sender)?;
 
         self.sync = true;
+        self.last_block_hash = Some(new_last_block_hash);
         self.save(new_last_block_hash)?;
 
         send_log(
@@ -127,12 +169,10 @@ impl UTXO {
     /// Restaura las UTXO a partir del archivo donde se guardan.
     /// Obtiene los datos del UTXO.
     /// Obtiene el hash del ultimo bloque procesado y lo retorna
-    fn restore_utxo(&mut self) -> Result<Option<Vec<u8>>, CustomError> {
+    /// Si el archivo esta dañado y no tiene backup, las UTXO se vuelven a generar desde el inicio del IBD.
+    fn restore_utxo(&mut self) -> Result<Option<Hash256>, CustomError> {
         let path = format!("{}/{}", self.store_path, self.path);
-        let mut file = open_new_file(path, false)?;
-
-        let mut saved_utxo_buffer = vec![];
-        file.read_to_end(&mut saved_utxo_buffer)?;
+        let saved_utxo_buffer = read_atomic(&path).unwrap_or_default();
 
         let (last_block_hash, tx_set) = match Self::parse(saved_utxo_buffer) {
             Ok((last_block_hash, tx_set)) => (Some(last_block_hash), tx_set),
@@ -148,20 +188,15 @@ impl UTXO {
     /// Retorna el hash del ultimo bloque procesado.
     fn update(
         &mut self,
-        headers: &Vec<BlockHeader>,
-        last_block_hash: Vec<u8>,
-        logger_sender: &mut Sender<Log>,
-    ) -> Result<Vec<u8>, CustomError> {
+        headers: &impl HeaderChain,
+        last_block_hash: Hash256,
+        logger_sender: &mut Sender<LogEntry>,
+    ) -> Result<Hash256, CustomError> {
         let mut last_block_hash = last_block_hash;
 
-        let block_position = headers
-            .iter()
-            .rev()
-            .position(|h| *h.hash() == last_block_hash);
-
-        let starting_index = match block_position {
-            Some(position) => headers.len() - position,
-            None => calculate_index_from_timestamp(headers, START_DATE_IBD),
+        let starting_index = match headers.find_position(&last_block_hash)? {
+            Some(position) => position + 1,
+            None => headers.index_from_timestamp(Network::current().ibd_start_timestamp())?,
         };
 
         send_log(
@@ -176,20 +211,46 @@ impl UTXO {
         Ok(last_block_hash)
     }
 
+    /// Actualiza las UTXO con los bloques anteriores al IBD guardados en disco.
+    /// Los bloques que no estan guardados se ignoran, ya que se podan los que no tienen transacciones de las wallets.
+    fn update_from_historical_blocks(
+        &mut self,
+        block_hashes: &[Hash256],
+    ) -> Result<(), CustomError> {
+        for block_hash in block_hashes {
+            let path = format!(
+                "{}/blocks/{}.bin",
+                self.store_path,
+                hash_as_string(block_hash)
+            );
+            let Ok(block) = Block::restore(path) else { continue };
+            self.update_from_block(&block, false)?;
+        }
+        Ok(())
+    }
+
     /// Actualiza las UTXO a partir de los headers y el indice recibido.
+    /// Los headers se leen por lotes de HEADERS_READ_BATCH.
     /// Se encarga de informar el progreso de la actualizacion.
     fn update_from_headers(
         &mut self,
-        headers: &Vec<BlockHeader>,
+        headers: &impl HeaderChain,
         starting_index: usize,
-        logger_sender: &mut Sender<Log>,
-        last_block_hash: &mut Vec<u8>,
+        logger_sender: &mut Sender<LogEntry>,
+        last_block_hash: &mut Hash256,
     ) -> Result<(), CustomError> {
+        let total = headers.len().saturating_sub(starting_index);
         let mut i = 0;
         let mut percentage = 0;
-        Ok(
-            for (_index, header) in headers.iter().enumerate().skip(starting_index) {
-                if i > (headers.len() - starting_index) / 10 {
+        let mut start = starting_index;
+        while start < headers.len() {
+            let batch = headers.read_range(start, HEADERS_READ_BATCH)?;
+            if batch.is_empty() {
+                break;
+            }
+            start += batch.len();
+            for header in batch {
+                if i > total / 10 {
                     percentage += 10;
                     send_log(
                         logger_sender,
@@ -202,52 +263,45 @@ impl UTXO {
                     Ok(block) => block,
                     Err(_) => {
                         send_log(
-                        logger_sender,
-                        Log::Message(String::from(
-                            "Error generating UTXO (block file broken), please restart the app.",
-                        )),
-                    );
+                            logger_sender,
+                            Log::Message(String::from(
+                                "Error generating UTXO (block file broken), please restart the app.",
+                            )),
+                        );
                         exit(0);
                     }
                 };
                 self.update_from_block(&block, false)?;
                 drop(block);
-                *last_block_hash = header.hash().clone();
+                *last_block_hash = *header.hash();
                 i += 1;
-            },
-        )
+            }
+        }
+        Ok(())
     }
 
     /// Serializa las utxo y el hash del ultimo bloque procesado.
-    fn serialize(&mut self, block_hash: Vec<u8>) -> Vec<u8> {
-        let mut buffer = vec![];
-        buffer.extend(block_hash);
+    fn serialize(&mut self, block_hash: Hash256) -> Vec<u8> {
+        let mut buffer = block_hash.to_vec();
         buffer.extend((self.tx_set.len() as u64).to_le_bytes());
 
         for (out_point, value) in &self.tx_set {
             buffer.extend(out_point.serialize());
-            buffer.extend(value.tx_out.serialize());
-            buffer.extend(value.block_hash.clone());
-            buffer.extend(value.block_timestamp.to_le_bytes());
+            buffer.extend(value.serialize());
         }
         buffer
     }
 
-    pub fn parse(buffer: Vec<u8>) -> Result<(Vec<u8>, HashMap<OutPoint, UTXOValue>), CustomError> {
-        let mut parser = BufferParser::new(buffer);
+    pub fn parse(buffer: Vec<u8>) -> Result<(Hash256, HashMap<OutPoint, UTXOValue>), CustomError> {
+        let mut parser = BufferParser::new(&buffer);
 
-        let last_block_hash = parser.extract_buffer(32)?.to_vec();
+        let last_block_hash = parser.extract_hash32()?.into();
         let tx_set_len = parser.extract_u64()? as usize;
         let mut tx_set: HashMap<OutPoint, UTXOValue> = HashMap::new();
 
         for _i in 0..tx_set_len {
             let out_point = OutPoint::parse(parser.extract_buffer(36)?.to_vec())?;
-
-            let value = UTXOValue {
-                tx_out: TransactionOutput::parse(&mut parser)?,
-                block_hash: parser.extract_buffer(32)?.to_vec(),
-                block_timestamp: parser.extract_u32()?,
-            };
+            let value = UTXOValue::parse(&mut parser)?;
             tx_set.insert(out_point, value);
         }
 
@@ -255,45 +309,110 @@ impl UTXO {
     }
 
     /// Actualiza las UTXO a partir de un bloque, eliminando los outputs gastados y agregando los nuevos outputs.
-    /// Si save es true, guarda el UTXO actualizado en disco.
+    /// Si save es true, guarda el UTXO actualizado en disco junto a los outputs gastados por el bloque.
     pub fn update_from_block(&mut self, block: &Block, save: bool) -> Result<(), CustomError> {
+        let mut spent = vec![];
         for tx in &block.transactions {
             for tx_in in &tx.inputs {
-                self.tx_set.remove(&tx_in.previous_output);
+                let Some(value) = self.tx_set.remove(&tx_in.previous_output) else { continue };
+                // los outputs creados y gastados en el mismo bloque no se restauran al deshacerlo
+                if value.block_hash != *block.header.hash() {
+                    spent.push((tx_in.previous_output.clone(), value));
+                }
             }
             for (index, tx_out) in tx.outputs.iter().enumerate() {
                 let out_point = OutPoint {
-                    hash: tx.hash().clone(),
+                    hash: tx.hash(),
                     index: index as u32,
                 };
                 let value = UTXOValue {
                     tx_out: tx_out.clone(),
-                    block_hash: block.header.hash().clone(),
+                    block_hash: *block.header.hash(),
                     block_timestamp: block.header.timestamp,
                 };
                 self.tx_set.insert(out_point.clone(), value);
             }
         }
 
+        self.last_block_hash = Some(*block.header.hash());
         if save {
-            self.save(block.header.hash().clone())?;
+            self.save_undo(block, &spent)?;
+            self.save(*block.header.hash())?;
         }
 
         Ok(())
     }
 
-    fn save(&mut self, block_hash: Vec<u8>) -> Result<(), CustomError> {
-        let buffer = self.serialize(block_hash);
+    /// Deshace el ultimo bloque procesado, eliminando los outputs que creo y restaurando los que gasto.
+    /// Devuelve CustomError si el bloque no es el ultimo procesado o no se guardaron los outputs que gasto.
+    pub fn undo_block(&mut self, block: &Block) -> Result<(), CustomError> {
+        if self.last_block_hash.as_ref() != Some(block.header.hash()) {
+            return Err(CustomError::Storage(StorageError::CannotUndoBlock));
+        }
+        let path = self.undo_path(block);
+        if !Path::new(&path).exists() {
+            return Err(CustomError::Storage(StorageError::CannotUndoBlock));
+        }
 
-        let path = format!("{}/{}", self.store_path, self.path);
-        if Path::new(&path).exists() {
-            remove_file(path.clone())?;
+        let buffer = read_atomic(&path)?;
+        let mut parser = BufferParser::new(&buffer);
+        let mut spent = vec![];
+        for _ in 0..parser.extract_u32()? {
+            let out_point = OutPoint::parse(parser.extract_buffer(36)?.to_vec())?;
+            spent.push((out_point, UTXOValue::parse(&mut parser)?));
         }
-        let mut file = open_new_file(path, false)?;
 
-        file.write_all(&buffer)?;
+        for tx in block.transactions.iter().rev() {
+            for index in 0..tx.outputs.len() {
+                self.tx_set.remove(&OutPoint {
+                    hash: tx.hash(),
+                    index: index as u32,
+                });
+            }
+        }
+        for (out_point, value) in spent {
+            self.tx_set.insert(out_point, value);
+        }
+
+        self.last_block_hash = Some(block.header.prev_block_hash);
+        self.save(block.header.prev_block_hash)?;
+        remove_atomic(&path)?;
         Ok(())
     }
+
+    /// Descarta las UTXO y el archivo donde se guardan, para volver a generarlas desde el inicio del IBD.
+    pub fn reset(&mut self) -> Result<(), CustomError> {
+        self.tx_set.clear();
+        self.sync = false;
+        self.last_block_hash = None;
+
+        remove_atomic(&format!("{}/{}", self.store_path, self.path))
+    }
+
+    /// Guarda los outputs gastados por el bloque, necesarios para deshacerlo.
+    fn save_undo(&self, block: &Block, spent: &[(OutPoint, UTXOValue)]) -> Result<(), CustomError> {
+        let mut buffer = (spent.len() as u32).to_le_bytes().to_vec();
+        for (out_point, value) in spent {
+            buffer.extend(out_point.serialize());
+            buffer.extend(value.serialize());
+        }
+
+        create_dir_all(format!("{}/undo", self.store_path))?;
+        write_atomic(&self.undo_path(block), &buffer)
+    }
+
+    fn undo_path(&self, block: &Block) -> String {
+        format!(
+            "{}/undo/{}.bin",
+            self.store_path,
+            block.header.hash_as_string()
+        )
+    }
+
+    fn save(&mut self, block_hash: Hash256) -> Result<(), CustomError> {
+        let buffer = self.serialize(block_hash);
+        write_atomic(&format!("{}/{}", self.store_path, self.path), &buffer)
+    }
 }
 
 #[cfg(test)]
@@ -305,7 +424,10 @@ mod tests {
     use gtk::glib::{self, Priority};
 
     use crate::{
-        logger::Logger, messages::transaction::Transaction, structs::tx_input::TransactionInput,
+        logger::Logger,
+        messages::transaction::Transaction,
+        structs::{block_header::BlockHeader, tx_input::TransactionInput},
+        utils::calculate_index_from_timestamp,
         wallet::get_script_pubkey,
     };
 
@@ -318,10 +440,10 @@ mod tests {
         let mut utxo_set = UTXO::new(store_path.clone(), filename.clone()).unwrap();
 
         let key1 = OutPoint {
-            hash: vec![
+            hash: Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
                 24, 25, 26, 27, 28, 29, 30, 31, 32,
-            ],
+            ]),
             index: 1,
         };
         let value1 = UTXOValue {
@@ -332,17 +454,17 @@ mod tests {
                 ))
                 .unwrap(),
             },
-            block_hash: vec![
+            block_hash: Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
                 24, 25, 26, 27, 28, 29, 30, 31, 32,
-            ],
+            ]),
             block_timestamp: 1680000000,
         };
         let key2 = OutPoint {
-            hash: vec![
+            hash: Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
                 24, 25, 26, 27, 28, 29, 30, 31, 32,
-            ],
+            ]),
             index: 2,
         };
         let value2 = UTXOValue {
@@ -353,31 +475,31 @@ mod tests {
                 ))
                 .unwrap(),
             },
-            block_hash: vec![
+            block_hash: Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
                 24, 25, 26, 27, 28, 29, 30, 31, 32,
-            ],
+            ]),
             block_timestamp: 1680000001,
         };
         let key3 = OutPoint {
-            hash: vec![
+            hash: Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
                 24, 25, 26, 27, 28, 29, 30, 31, 32,
-            ],
+            ]),
             index: 3,
         };
         let value3 = UTXOValue {
             tx_out: TransactionOutput {
                 value: 300,
                 script_pubkey: get_script_pubkey(String::from(
-                    "badnpccEgq7azndWHFTzvFuFuywCsUvTRu",
+                    "n3RURSMj9XANG4FkvA8HXWyiH6djPp85VU",
                 ))
                 .unwrap(),
             },
-            block_hash: vec![
+            block_hash: Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
                 24, 25, 26, 27, 28, 29, 30, 31, 32,
-            ],
+            ]),
             block_timestamp: 1680000002,
         };
         utxo_set.tx_set.insert(key1, value1);
@@ -387,10 +509,10 @@ mod tests {
         assert_eq!(utxo_set.tx_set.len(), 3);
 
         utxo_set
-            .save(vec![
+            .save(Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
                 24, 25, 26, 27, 28, 29, 30, 31, 32,
-            ])
+            ]))
             .unwrap();
 
         let mut utxo_set2 = UTXO::new(store_path.clone(), filename.clone()).unwrap();
@@ -399,47 +521,43 @@ mod tests {
         assert_eq!(utxo_set2.tx_set.len(), 3);
         assert_eq!(utxo_set2.tx_set, utxo_set.tx_set);
 
-        fs::remove_file(format!("{}/{}", store_path, filename)).unwrap();
+        remove_atomic(&format!("{}/{}", store_path, filename)).unwrap();
     }
 
     #[test]
     fn starting_index_calculation() {
         let header1 = BlockHeader {
             version: 536870912,
-            prev_block_hash: [
+            prev_block_hash: Hash256::new([
                 37, 167, 68, 172, 119, 180, 173, 121, 130, 113, 230, 183, 81, 26, 52, 142, 31, 52,
                 247, 233, 68, 123, 190, 78, 10, 195, 189, 99, 0, 0, 0, 0,
-            ]
-            .to_vec(),
-            merkle_root: [
+            ]),
+            merkle_root: Hash256::new([
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 248, 125, 43, 136, 39, 116, 186, 43,
                 114, 204, 35, 144, 47, 194, 229, 44, 97, 83, 110, 112, 229, 230,
-            ]
-            .to_vec(),
+            ]),
             timestamp: 1,
             bits: 486604799,
             nonce: 409655068,
-            hash: vec![],
+            hash: Hash256::ZERO,
             block_downloaded: true,
             broadcasted: true,
         };
 
         let header2 = BlockHeader {
             version: 536870912,
-            prev_block_hash: [
+            prev_block_hash: Hash256::new([
                 37, 167, 68, 172, 119, 180, 173, 121, 130, 113, 230, 183, 81, 26, 52, 142, 31, 52,
                 247, 233, 68, 123, 190, 78, 10, 195, 189, 99, 0, 0, 0, 0,
-            ]
-            .to_vec(),
-            merkle_root: [
+            ]),
+            merkle_root: Hash256::new([
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 248, 125, 43, 136, 39, 116, 186, 43,
                 114, 204, 35, 144, 47, 194, 229, 44, 97, 83, 110, 112, 229, 230,
-            ]
-            .to_vec(),
+            ]),
             timestamp: 3,
             bits: 486604799,
             nonce: 409655068,
-            hash: vec![],
+            hash: Hash256::ZERO,
             block_downloaded: true,
             broadcasted: true,
         };
@@ -467,17 +585,16 @@ mod tests {
         let filename = String::from("test_utxo.bin");
         let store_path = String::from("tests");
         let mut utxo_set = UTXO::new(store_path, filename.clone()).unwrap();
-        let block_hash = vec![
+        let block_hash = Hash256::new([
             127, 47, 239, 163, 175, 36, 146, 56, 212, 168, 146, 23, 101, 29, 205, 186, 7, 67, 240,
             23, 75, 32, 175, 14, 221, 106, 150, 247, 21, 243, 205, 109,
-        ];
+        ]);
 
         let key: OutPoint = OutPoint {
-            hash: [
+            hash: Hash256::new([
                 252, 47, 239, 163, 175, 36, 146, 56, 212, 168, 146, 23, 101, 29, 205, 186, 7, 67,
                 240, 23, 75, 32, 175, 14, 221, 106, 150, 247, 21, 243, 205, 109,
-            ]
-            .to_vec(),
+            ]),
             index: 0,
         };
         let value = UTXOValue {
@@ -488,12 +605,12 @@ mod tests {
                 ))
                 .unwrap(),
             },
-            block_hash: block_hash.clone(),
+            block_hash,
             block_timestamp: 1680000000,
         };
         utxo_set.tx_set.insert(key, value);
 
-        let buffer = utxo_set.serialize(block_hash.clone());
+        let buffer = utxo_set.serialize(block_hash);
         let (last_block_hash, parsed_tx_set) = UTXO::parse(buffer).unwrap();
         assert_eq!(last_block_hash, block_hash);
         assert_eq!(utxo_set.tx_set, parsed_tx_set);
@@ -513,24 +630,25 @@ mod tests {
                 bits: 486604799,
                 block_downloaded: true,
                 broadcasted: true,
-                hash: vec![
+                hash: Hash256::new([
                     1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6,
                     7, 8, 9, 0, 1, 2,
-                ],
-                merkle_root: vec![],
+                ]),
+                merkle_root: Hash256::ZERO,
                 nonce: 409655068,
-                prev_block_hash: vec![],
+                prev_block_hash: Hash256::ZERO,
                 timestamp: 1680000000,
                 version: 21123123,
             },
             transactions: vec![Transaction {
                 inputs: vec![TransactionInput {
                     previous_output: OutPoint {
-                        hash: vec![],
+                        hash: Hash256::ZERO,
                         index: 0,
                     },
                     script_sig: vec![],
                     sequence: 0,
+                    witness: vec![],
                 }],
                 outputs: vec![TransactionOutput {
                     script_pubkey: vec![1, 2],
@@ -546,25 +664,53 @@ mod tests {
         let real_path = format!("tests/blocks/{}.bin", block.header.hash_as_string());
         block.save(real_path.clone()).unwrap();
 
-        if Path::new("tests/test_utxo.bin").exists() {
-            fs::remove_file("tests/test_utxo.bin").unwrap();
-        }
+        remove_atomic("tests/test_utxo.bin").unwrap();
         let filename = String::from("test_utxo.bin");
         let store_path = String::from("tests");
         let mut utxo_set = UTXO::new(store_path, filename.clone()).unwrap();
 
         let headers = vec![block_old.header.clone(), block.header.clone()];
         utxo_set
-            .generate(&headers, &mut logger_sender.clone())
+            .generate(&headers, &[], &mut logger_sender.clone())
             .unwrap();
 
         // // solo tienen que estar los utxo del segundo bloque
         assert_eq!(utxo_set.tx_set.len(), 42);
         assert_eq!(utxo_set.is_synced(), true);
 
+        // los bloques historicos, como los que coinciden con los filtros, se procesan aunque sean anteriores al IBD
+        remove_atomic("tests/test_utxo.bin").unwrap();
+        let mut header = block_old.header.clone();
+        header.bits = 0x207fffff;
+        let historical_block = Block {
+            header: loop {
+                // se mina con la dificultad minima de regtest para que el bloque se pueda restaurar
+                header.nonce += 1;
+                if let Ok(header) = BlockHeader::parse(header.serialize()) {
+                    break header;
+                }
+            },
+            transactions: block_old.transactions.clone(),
+        };
+        let old_path = format!(
+            "tests/blocks/{}.bin",
+            historical_block.header.hash_as_string()
+        );
+        historical_block.save(old_path.clone()).unwrap();
+        let mut utxo_set = UTXO::new(String::from("tests"), filename).unwrap();
+        utxo_set
+            .generate(
+                &headers,
+                &[*historical_block.header.hash()],
+                &mut logger_sender.clone(),
+            )
+            .unwrap();
+        assert_eq!(utxo_set.tx_set.len(), 43);
+
         fs::remove_file("tests/test_log.txt").unwrap();
-        fs::remove_file("tests/test_utxo.bin").unwrap();
+        remove_atomic("tests/test_utxo.bin").unwrap();
         fs::remove_file(real_path).unwrap();
+        fs::remove_file(old_path).unwrap();
     }
 
     #[test]
@@ -581,7 +727,7 @@ mod tests {
         .unwrap();
 
         let key1 = OutPoint {
-            hash: vec![],
+            hash: Hash256::ZERO,
             index: 1,
         };
         let tx_out1 = TransactionOutput {
@@ -591,11 +737,11 @@ mod tests {
         };
         let value1 = UTXOValue {
             tx_out: tx_out1.clone(),
-            block_hash: vec![],
+            block_hash: Hash256::ZERO,
             block_timestamp: 1680000000,
         };
         let key2 = OutPoint {
-            hash: vec![],
+            hash: Hash256::ZERO,
             index: 2,
         };
         let tx_out2 = TransactionOutput {
@@ -605,22 +751,22 @@ mod tests {
         };
         let value2 = UTXOValue {
             tx_out: tx_out2.clone(),
-            block_hash: vec![],
+            block_hash: Hash256::ZERO,
             block_timestamp: 1680000001,
         };
         let tx_out3 = TransactionOutput {
             value: 100,
-            script_pubkey: get_script_pubkey(String::from("badnpccDgq7azndWHFTzvFuZuywCsUvTRu"))
+            script_pubkey: get_script_pubkey(String::from("n3RURSMi9XANG4FkvA8HXWz2H6djNrykoj"))
                 .unwrap(),
         };
 
         let key3 = OutPoint {
-            hash: vec![],
+            hash: Hash256::ZERO,
             index: 3,
         };
         let value3 = UTXOValue {
             tx_out: tx_out3.clone(),
-            block_hash: vec![],
+            block_hash: Hash256::ZERO,
             block_timestamp: 1680000002,
         };
         utxo_set.tx_set.insert(key1.clone(), value1.clone());
@@ -652,7 +798,7 @@ mod tests {
         .unwrap();
 
         let key1 = OutPoint {
-            hash: vec![],
+            hash: Hash256::ZERO,
             index: 1,
         };
         let value1 = UTXOValue {
@@ -663,11 +809,11 @@ mod tests {
                 ))
                 .unwrap(),
             },
-            block_hash: vec![],
+            block_hash: Hash256::ZERO,
             block_timestamp: 1680000000,
         };
         let key2 = OutPoint {
-            hash: vec![],
+            hash: Hash256::ZERO,
             index: 2,
         };
         let value2 = UTXOValue {
@@ -678,22 +824,22 @@ mod tests {
                 ))
                 .unwrap(),
             },
-            block_hash: vec![],
+            block_hash: Hash256::ZERO,
             block_timestamp: 1680000001,
         };
         let key3 = OutPoint {
-            hash: vec![],
+            hash: Hash256::ZERO,
             index: 3,
         };
         let value3 = UTXOValue {
             tx_out: TransactionOutput {
                 value: 300,
                 script_pubkey: get_script_pubkey(String::from(
-                    "badnpccEgq7azndWHFTzvFuFuywCsUvTRu",
+                    "n3RURSMj9XANG4FkvA8HXWyiH6djPp85VU",
                 ))
                 .unwrap(),
             },
-            block_hash: vec![],
+            block_hash: Hash256::ZERO,
             block_timestamp: 1680000002,
         };
         utxo_set.tx_set.insert(key1, value1);
@@ -701,4 +847,69 @@ mod tests {
         utxo_set.tx_set.insert(key3, value3);
         assert_eq!(utxo_set.wallet_balance(&wallet).unwrap(), 300);
     }
+
+    #[test]
+    fn undo_block() {
+        let store_path = String::from("tests");
+        let filename = String::from("test_utxo_undo.bin");
+        let mut utxo = UTXO::new(store_path, filename).unwrap();
+
+        let block = |hash: u8, inputs: Vec<OutPoint>, values: Vec<u64>| Block {
+            header: BlockHeader {
+                version: 0,
+                prev_block_hash: Hash256::new([hash - 1; 32]),
+                merkle_root: Hash256::ZERO,
+                timestamp: 1680000000,
+                bits: 0,
+                nonce: 0,
+                hash: Hash256::new([hash; 32]),
+                broadcasted: false,
+                block_downloaded: false,
+            },
+            transactions: vec![Transaction {
+                version: hash as u32,
+                inputs: inputs
+                    .into_iter()
+                    .map(|previous_output| TransactionInput {
+                        previous_output,
+                        script_sig: vec![],
+                        sequence: 0,
+                        witness: vec![],
+                    })
+                    .collect(),
+                outputs: values
+                    .into_iter()
+                    .map(|value| TransactionOutput {
+                        value,
+                        script_pubkey: vec![],
+                    })
+                    .collect(),
+                lock_time: 0,
+            }],
+        };
+
+        let first_block = block(1, vec![], vec![100, 200]);
+        utxo.update_from_block(&first_block, true).unwrap();
+        let tx_set = utxo.tx_set.clone();
+
+        let spent = OutPoint {
+            hash: first_block.transactions[0].hash(),
+            index: 0,
+        };
+        let second_block = block(2, vec![spent], vec![50]);
+        utxo.update_from_block(&second_block, true).unwrap();
+        assert_eq!(utxo.tx_set.len(), 2);
+
+        // solo se puede deshacer el ultimo bloque procesado
+        assert!(utxo.undo_block(&first_block).is_err());
+        utxo.undo_block(&second_block).unwrap();
+        assert_eq!(utxo.tx_set, tx_set);
+        assert_eq!(utxo.last_block_hash(), Some(&Hash256::new([1; 32])));
+        assert!(utxo.undo_block(&second_block).is_err());
+
+        utxo.reset().unwrap();
+        assert!(utxo.tx_set.is_empty());
+        assert!(!Path::new("tests/test_utxo_undo.bin").exists());
+        fs::remove_dir_all("tests/undo").unwrap();
+    }
 }
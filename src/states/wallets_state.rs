@@ -1,8 +1,9 @@
-use std::io::{Read, Write};
-
 use crate::{
-    error::CustomError, messages::block::Block, parser::BufferParser, utils::open_new_file,
+    error::{CustomError, WalletError},
+    messages::block::Block,
+    structs::hash256::Hash256,
     wallet::Wallet,
+    wallet_store::WalletStore,
 };
 
 use super::utxo_state::UTXO;
@@ -11,53 +12,101 @@ use super::utxo_state::UTXO;
 /// Los elementos son:
 /// - wallets: Vector de wallets.
 /// - active_pubkey: Public key del wallet activo.
-/// - path: Path del archivo donde se guardan los wallets.
+/// - store: WalletStore que guarda los wallets en su archivo, encriptado si tiene passphrase.
+/// - locked: Indica si el archivo esta encriptado y todavia no se ingreso la passphrase.
+/// - processed_height: Altura hasta la que los wallets procesaron todos los bloques, se guarda junto a ellos.
 pub struct WalletsState {
     wallets: Vec<Wallet>,
     active_pubkey: Option<String>,
-    path: String,
+    store: WalletStore,
+    locked: bool,
+    processed_height: usize,
 }
 
 impl WalletsState {
     /// Inicializa los wallets del nodo.
     /// Si el archivo donde se guardan los wallets no existe, se crea.
     /// Si el archivo existe, se restauran los wallets.
+    /// Si el archivo esta encriptado, los wallets quedan bloqueados hasta que se ingrese la passphrase.
     pub fn new(path: String) -> Result<Self, CustomError> {
+        let store = WalletStore::new(path);
+        let locked = store.is_encrypted()?;
         let mut wallets = Self {
             wallets: Vec::new(),
             active_pubkey: None,
-            path,
+            store,
+            locked,
+            processed_height: 0,
         };
-        wallets.restore()?;
+        if !locked {
+            wallets.processed_height = wallets.restore()?.unwrap_or_default();
+        }
         Ok(wallets)
     }
 
-    fn restore(&mut self) -> Result<(), CustomError> {
-        let mut file = open_new_file(self.path.clone(), false)?;
-        let mut buffer = vec![];
-        file.read_to_end(&mut buffer)?;
-        let mut parser = BufferParser::new(buffer);
+    fn restore(&mut self) -> Result<Option<usize>, CustomError> {
+        let (wallets, processed_height) = self.store.load()?;
+        self.wallets = wallets;
+        Ok(processed_height)
+    }
 
-        let mut wallets = vec![];
-        while !parser.is_empty() {
-            let wallet = Wallet::parse(&mut parser)?;
-            wallets.push(wallet);
+    fn save(&self) -> Result<(), CustomError> {
+        if self.locked {
+            return Err(CustomError::Wallet(WalletError::WalletsLocked));
         }
+        self.store.save(&self.wallets, self.processed_height)
+    }
 
-        self.wallets = wallets;
-        Ok(())
+    /// Registra la altura hasta la que los wallets procesaron todos los bloques, que se guarda en el proximo guardado.
+    /// Mientras estan bloqueados los wallets no procesan bloques, por lo que la altura no cambia.
+    pub fn set_processed_height(&mut self, height: usize) {
+        if !self.locked {
+            self.processed_height = height;
+        }
     }
 
-    fn save(&self) -> Result<(), CustomError> {
-        let mut file = open_new_file(self.path.clone(), false)?;
+    /// Guarda los wallets en su archivo. Si estan encriptados y bloqueados no se guardan,
+    /// ya que no se modificaron desde que se cargaron.
+    pub fn flush(&self) -> Result<(), CustomError> {
+        match self.locked {
+            true => Ok(()),
+            false => self.save(),
+        }
+    }
+
+    /// Devuelve true si los wallets estan encriptados y todavia no se ingreso la passphrase.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
 
-        let mut buffer = vec![];
-        for wallet in &self.wallets {
-            buffer.append(&mut wallet.serialize());
+    /// Desencripta los wallets con la passphrase recibida.
+    /// Devuelve la altura hasta la que los wallets procesaron todos los bloques antes de bloquearse,
+    /// None si el archivo no la guarda.
+    /// Devuelve CustomError si la passphrase es incorrecta.
+    pub fn unlock(&mut self, passphrase: String) -> Result<Option<usize>, CustomError> {
+        self.store.set_passphrase(Some(passphrase));
+        let processed_height = match self.restore() {
+            Ok(processed_height) => processed_height,
+            Err(error) => {
+                self.store.set_passphrase(None);
+                return Err(error);
+            }
+        };
+        self.locked = false;
+        if let Some(height) = processed_height {
+            self.processed_height = height;
         }
+        Ok(processed_height)
+    }
 
-        file.write_all(&buffer)?;
-        Ok(())
+    /// Establece la passphrase con la que se encriptan los wallets y los vuelve a guardar.
+    /// Con una passphrase vacia los wallets se guardan sin encriptar.
+    pub fn set_passphrase(&mut self, passphrase: String) -> Result<(), CustomError> {
+        if self.locked {
+            return Err(CustomError::Wallet(WalletError::WalletsLocked));
+        }
+        self.store.set_passphrase(Some(passphrase));
+        self.save()
     }
 
     /// Establece la wallet activa.
@@ -77,6 +126,9 @@ impl WalletsState {
 
     /// Agrega una wallet a la lista de wallets.
     pub fn append(&mut self, new_wallet: Wallet) -> Result<(), CustomError> {
+        if self.locked {
+            return Err(CustomError::Wallet(WalletError::WalletsLocked));
+        }
         if self
             .wallets
             .iter()
@@ -91,6 +143,30 @@ impl WalletsState {
         Ok(())
     }
 
+    /// Agrega un multisig a la wallet activa y guarda las wallets.
+    /// Devuelve la direccion P2SH del multisig.
+    /// Devuelve CustomError si no hay una wallet activa o si el multisig no es valido para ella.
+    pub fn add_multisig_to_active(
+        &mut self,
+        required: u8,
+        pubkeys: &[Vec<u8>],
+    ) -> Result<String, CustomError> {
+        if self.locked {
+            return Err(CustomError::Wallet(WalletError::WalletsLocked));
+        }
+        let Some(active_pubkey) = &self.active_pubkey else { return Err(CustomError::Wallet(WalletError::WalletNotFound)) };
+        let Some(active_wallet) = self
+            .wallets
+            .iter_mut()
+            .find(|wallet| wallet.pubkey == *active_pubkey)
+        else {
+            return Err(CustomError::Wallet(WalletError::WalletNotFound));
+        };
+        let address = active_wallet.add_multisig(required, pubkeys)?;
+        self.save()?;
+        Ok(address)
+    }
+
     /// Devuelve la wallet activa.
     pub fn get_active(&self) -> Option<&Wallet> {
         match self.active_pubkey {
@@ -108,9 +184,10 @@ impl WalletsState {
 
         for tx in &block.transactions {
             for wallet in &mut self.wallets {
-                let movement = tx.get_movement(&wallet.get_pubkey_hash()?, utxo)?;
+                wallets_updated |= wallet.mark_used_addresses(&tx.outputs)?;
+                let movement = wallet.get_movement(tx, utxo)?;
                 if let Some(mut movement) = movement {
-                    movement.block_hash = Some(block.header.hash().clone());
+                    movement.block_hash = Some(*block.header.hash());
                     wallet.update_history(movement);
                     wallets_updated = true;
                 }
@@ -121,17 +198,38 @@ impl WalletsState {
         }
         Ok(wallets_updated && self.active_pubkey.is_some())
     }
+
+    /// Elimina del historial de las wallets los movimientos de los bloques recibidos,
+    /// que dejaron de formar parte de la cadena tras una reorganizacion.
+    pub fn rollback(&mut self, block_hashes: &[Hash256]) -> Result<bool, CustomError> {
+        let mut wallets_updated = false;
+        for wallet in &mut self.wallets {
+            let history_len = wallet.history.len();
+            wallet
+                .history
+                .retain(|movement| match &movement.block_hash {
+                    Some(block_hash) => !block_hashes.contains(block_hash),
+                    None => true,
+                });
+            wallets_updated |= wallet.history.len() != history_len;
+        }
+        if wallets_updated {
+            self.save()?;
+        }
+        Ok(wallets_updated && self.active_pubkey.is_some())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs::{self, remove_file};
+    use std::{fs, path::Path};
 
     use crate::{
+        atomic_file::remove_atomic,
         messages::transaction::Transaction,
         structs::{
-            block_header::BlockHeader, outpoint::OutPoint, tx_input::TransactionInput,
-            tx_output::TransactionOutput,
+            block_header::BlockHeader, movement::Movement, outpoint::OutPoint,
+            tx_input::TransactionInput, tx_output::TransactionOutput,
         },
     };
 
@@ -143,7 +241,7 @@ mod tests {
         assert_eq!(wallets.wallets.len(), 0);
         assert_eq!(wallets.active_pubkey, None);
 
-        remove_file("tests/wallets_empty.bin".to_string()).unwrap();
+        assert!(!Path::new("tests/wallets_empty.bin").exists());
     }
 
     #[test]
@@ -175,7 +273,7 @@ mod tests {
         wallets.append(new_wallet).unwrap();
         assert_eq!(wallets.wallets.len(), 2);
 
-        remove_file("tests/test_wallets_append.bin".to_string()).unwrap();
+        remove_atomic("tests/test_wallets_append.bin").unwrap();
     }
 
     #[test]
@@ -201,7 +299,7 @@ mod tests {
         let result = wallets.append(new_wallet);
         assert!(result.is_err());
 
-        remove_file("tests/test_wallets_append_duplicated.bin".to_string()).unwrap();
+        remove_atomic("tests/test_wallets_append_duplicated.bin").unwrap();
     }
 
     #[test]
@@ -223,7 +321,36 @@ mod tests {
         let wallets2 = WalletsState::new("tests/save_wallets.bin".to_string()).unwrap();
         assert_eq!(wallets2.wallets.len(), 1);
 
-        remove_file("tests/save_wallets.bin".to_string()).unwrap();
+        remove_atomic("tests/save_wallets.bin").unwrap();
+    }
+
+    #[test]
+    fn encrypt_and_unlock_wallets() {
+        fs::copy("tests/test_wallets.bin", "tests/test_wallets_encrypted.bin").unwrap();
+
+        let mut wallets =
+            WalletsState::new("tests/test_wallets_encrypted.bin".to_string()).unwrap();
+        assert!(!wallets.is_locked());
+        wallets.set_processed_height(42);
+        wallets.set_passphrase(String::from("passphrase")).unwrap();
+
+        let mut wallets =
+            WalletsState::new("tests/test_wallets_encrypted.bin".to_string()).unwrap();
+        assert!(wallets.is_locked());
+        assert_eq!(wallets.get_all().len(), 0);
+        assert!(wallets.unlock(String::from("wrong")).is_err());
+        assert!(wallets.is_locked());
+
+        // los bloques procesados mientras estaban bloqueados se escanean desde la altura guardada
+        wallets.set_processed_height(50);
+        assert_eq!(
+            wallets.unlock(String::from("passphrase")).unwrap(),
+            Some(42)
+        );
+        assert!(!wallets.is_locked());
+        assert_eq!(wallets.get_all()[0].name, "wallet 1");
+
+        remove_atomic("tests/test_wallets_encrypted.bin").unwrap();
     }
 
     #[test]
@@ -292,12 +419,12 @@ mod tests {
         let block = Block {
             header: BlockHeader {
                 version: 536887296,
-                prev_block_hash: vec![],
-                merkle_root: vec![],
+                prev_block_hash: Hash256::ZERO,
+                merkle_root: Hash256::ZERO,
                 timestamp: 1686626483,
                 bits: 421617023,
                 nonce: 3878826733,
-                hash: vec![],
+                hash: Hash256::ZERO,
                 block_downloaded: true,
                 broadcasted: true,
             },
@@ -305,11 +432,12 @@ mod tests {
                 version: 1,
                 inputs: vec![TransactionInput {
                     previous_output: OutPoint {
-                        hash: vec![],
+                        hash: Hash256::ZERO,
                         index: 4294967295,
                     },
                     script_sig: vec![],
                     sequence: 4294967295,
+                    witness: vec![],
                 }],
                 outputs: vec![TransactionOutput {
                     value: 2366975,
@@ -329,6 +457,41 @@ mod tests {
         assert_eq!(updated, true);
         assert_eq!(wallets.get_active().unwrap().history.len(), 1);
 
-        remove_file("tests/test_wallets_update.bin".to_string()).unwrap();
+        remove_atomic("tests/test_wallets_update.bin").unwrap();
+    }
+
+    #[test]
+    fn rollback_wallets_history() {
+        fs::copy("tests/test_wallets.bin", "tests/test_wallets_rollback.bin").unwrap();
+
+        let mut wallets = WalletsState::new("tests/test_wallets_rollback.bin".to_string()).unwrap();
+        wallets
+            .set_active("mhzZUxRkPzNpCsQHemTakuJa5xhCajxyVm")
+            .unwrap();
+
+        let wallet = &mut wallets.wallets[0];
+        for block_hash in [
+            Some(Hash256::new([1; 32])),
+            Some(Hash256::new([2; 32])),
+            None,
+        ] {
+            wallet.update_history(Movement {
+                tx_hash: Hash256::new([3; 32]),
+                value: 100,
+                block_hash,
+                address: None,
+                output_type: None,
+                fee: None,
+            });
+        }
+
+        assert!(wallets.rollback(&[Hash256::new([2; 32])]).unwrap());
+        assert_eq!(wallets.get_active().unwrap().history.len(), 2);
+        assert!(!wallets.rollback(&[Hash256::new([2; 32])]).unwrap());
+
+        let restored = WalletsState::new("tests/test_wallets_rollback.bin".to_string()).unwrap();
+        assert_eq!(restored.wallets[0].history.len(), 2);
+
+        remove_atomic("tests/test_wallets_rollback.bin").unwrap();
     }
 }
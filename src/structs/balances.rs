@@ -0,0 +1,85 @@
+use super::movement::Movement;
+
+/// Cantidad de confirmaciones que necesita un output de coinbase para poder gastarse.
+pub const COINBASE_MATURITY: usize = 100;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// Balances es el balance de una wallet desglosado segun que tan seguros son sus fondos.
+/// Los elementos son:
+/// - confirmed: Suma de los outputs confirmados que se pueden gastar.
+/// - trusted_pending: Variacion del balance por las transacciones pendientes enviadas por la wallet, negativa al gastar fondos.
+/// - untrusted_pending: Suma de lo recibido en transacciones pendientes enviadas por otros, que todavia pueden no confirmarse.
+/// - immature: Suma de los outputs de coinbase que todavia no tienen COINBASE_MATURITY confirmaciones.
+pub struct Balances {
+    pub confirmed: u64,
+    pub trusted_pending: i64,
+    pub untrusted_pending: u64,
+    pub immature: u64,
+}
+
+impl Balances {
+    /// Calcula los balances a partir de los outputs confirmados de la wallet y los movimientos de sus transacciones pendientes.
+    /// Cada output tiene su valor y, si es de una coinbase, su cantidad de confirmaciones.
+    pub fn new(outputs: &[(u64, Option<usize>)], pending: &[Movement]) -> Self {
+        let mut balances = Self::default();
+        for (value, coinbase_confirmations) in outputs {
+            match coinbase_confirmations {
+                Some(confirmations) if *confirmations < COINBASE_MATURITY => {
+                    balances.immature += value
+                }
+                _ => balances.confirmed += value,
+            }
+        }
+        for movement in pending {
+            // las transacciones que gastan fondos de la wallet las envio la propia wallet
+            match movement.value < 0 {
+                true => balances.trusted_pending += movement.value,
+                false => balances.untrusted_pending += movement.value as u64,
+            }
+        }
+        balances
+    }
+
+    /// Devuelve el balance total, contando los fondos pendientes e inmaduros.
+    pub fn total(&self) -> i64 {
+        (self.confirmed + self.untrusted_pending + self.immature) as i64 + self.trusted_pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::hash256::Hash256;
+
+    use super::*;
+
+    fn pending(value: i64) -> Movement {
+        Movement {
+            tx_hash: Hash256::ZERO,
+            value,
+            block_hash: None,
+            address: None,
+            output_type: None,
+            fee: None,
+        }
+    }
+
+    #[test]
+    fn balances_are_split_by_maturity_and_origin() {
+        let outputs = [
+            (1000, None),
+            (5000, Some(COINBASE_MATURITY - 1)),
+            (2000, Some(COINBASE_MATURITY)),
+        ];
+        let balances = Balances::new(&outputs, &[pending(-300), pending(400)]);
+        assert_eq!(
+            balances,
+            Balances {
+                confirmed: 3000,
+                trusted_pending: -300,
+                untrusted_pending: 400,
+                immature: 5000,
+            }
+        );
+        assert_eq!(balances.total(), 8100);
+    }
+}
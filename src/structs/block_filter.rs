@@ -0,0 +1,322 @@
+use bitcoin_hashes::{sha256d, siphash24, Hash};
+
+use crate::{
+    error::{CustomError, ParseError},
+    network::Network,
+    parser::{BufferParser, Parser, VarIntSerialize},
+    structs::hash256::Hash256,
+    utils::hex_to_bytes,
+};
+
+/// Parametro P de Golomb-Rice del filtro basico de BIP 158.
+const FILTER_P: u8 = 19;
+/// Parametro M del filtro basico de BIP 158.
+const FILTER_M: u64 = 784931;
+/// Script del unico output del bloque genesis, comun a todas las redes.
+const GENESIS_OUTPUT_SCRIPT: &str = "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac";
+
+/// Tipo del filtro basico de BIP 158.
+pub const BASIC_FILTER_TYPE: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+/// BlockFilter es un filtro compacto de bloque (Golomb-coded set) definido en BIP 158.
+/// Permite saber si un bloque puede contener alguno de los scripts de nuestras wallets sin descargarlo.
+/// Los elementos son:
+/// - n: Cantidad de elementos del filtro.
+/// - content: Bits del set codificado con Golomb-Rice.
+pub struct BlockFilter {
+    pub n: u64,
+    pub content: Vec<u8>,
+}
+
+impl BlockFilter {
+    /// Crea un filtro para un bloque a partir de sus elementos (scripts).
+    pub fn new(block_hash: &Hash256, elements: &[Vec<u8>]) -> Self {
+        let mut unique: Vec<&Vec<u8>> = elements.iter().filter(|e| !e.is_empty()).collect();
+        unique.sort();
+        unique.dedup();
+
+        let n = unique.len() as u64;
+        let (k0, k1) = filter_keys(block_hash);
+        let mut values: Vec<u64> = unique
+            .iter()
+            .map(|element| hash_to_range(k0, k1, element, n * FILTER_M))
+            .collect();
+        values.sort();
+
+        let mut writer = BitWriter::default();
+        let mut last = 0;
+        for value in values {
+            golomb_encode(&mut writer, value - last);
+            last = value;
+        }
+
+        Self {
+            n,
+            content: writer.into_bytes(),
+        }
+    }
+
+    /// Parsea un filtro a partir de los bytes recibidos en un mensaje cfilter.
+    pub fn parse(buffer: Vec<u8>) -> Result<Self, CustomError> {
+        let mut parser = BufferParser::new(&buffer);
+        let n = parser.extract_varint()?;
+        let size = parser.len();
+        let content = parser.extract_buffer(size)?.to_vec();
+        Ok(Self { n, content })
+    }
+
+    /// Serializa el filtro con el formato del mensaje cfilter.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = vec![];
+        buffer.extend((self.n as usize).to_varint_bytes());
+        buffer.extend(&self.content);
+        buffer
+    }
+
+    /// Devuelve true si alguno de los elementos recibidos puede estar en el filtro.
+    /// Devuelve CustomError si el filtro esta mal codificado o su cantidad de elementos no es valida.
+    pub fn match_any(
+        &self,
+        block_hash: &Hash256,
+        queries: &[Vec<u8>],
+    ) -> Result<bool, CustomError> {
+        if self.n == 0 || queries.is_empty() {
+            return Ok(false);
+        }
+
+        let range = self
+            .n
+            .checked_mul(FILTER_M)
+            .ok_or(CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+        let (k0, k1) = filter_keys(block_hash);
+        let mut query_values: Vec<u64> = queries
+            .iter()
+            .map(|query| hash_to_range(k0, k1, query, range))
+            .collect();
+        query_values.sort();
+
+        let mut reader = BitReader::new(&self.content);
+        let mut queries = query_values.iter().peekable();
+        let mut value = 0;
+        for _ in 0..self.n {
+            value += golomb_decode(&mut reader)?;
+            while let Some(query) = queries.peek() {
+                if **query == value {
+                    return Ok(true);
+                }
+                if **query > value {
+                    break;
+                }
+                queries.next();
+            }
+            if queries.peek().is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Devuelve el hash del filtro, utilizado para encadenar los filter headers.
+    pub fn hash(&self) -> Vec<u8> {
+        filter_hash(&self.serialize())
+    }
+}
+
+/// Calcula el hash de un filtro serializado.
+pub fn filter_hash(serialized_filter: &[u8]) -> Vec<u8> {
+    sha256d::Hash::hash(serialized_filter)
+        .to_byte_array()
+        .to_vec()
+}
+
+/// Calcula el filter header de un bloque a partir del hash de su filtro y el filter header anterior.
+pub fn filter_header(filter_hash: &[u8], previous_filter_header: &[u8]) -> Vec<u8> {
+    let mut buffer = filter_hash.to_vec();
+    buffer.extend(previous_filter_header);
+    sha256d::Hash::hash(&buffer).to_byte_array().to_vec()
+}
+
+/// Calcula el filter header del bloque genesis de la red a partir de su filtro, sin depender de ningun peer.
+/// Es el checkpoint desde el que se validan los filter headers recibidos.
+pub fn genesis_filter_header(network: Network) -> Vec<u8> {
+    let script = hex_to_bytes(GENESIS_OUTPUT_SCRIPT).unwrap_or_default();
+    let filter = BlockFilter::new(&network.genesis_hash(), &[script]);
+    filter_header(&filter.hash(), &[0; 32])
+}
+
+/// Las claves de siphash son los primeros 16 bytes del hash del bloque.
+fn filter_keys(block_hash: &Hash256) -> (u64, u64) {
+    let bytes = block_hash.as_bytes();
+    let mut k0 = [0_u8; 8];
+    let mut k1 = [0_u8; 8];
+    k0.copy_from_slice(&bytes[0..8]);
+    k1.copy_from_slice(&bytes[8..16]);
+    (u64::from_le_bytes(k0), u64::from_le_bytes(k1))
+}
+
+/// Mapea el siphash de un elemento al rango [0, f).
+fn hash_to_range(k0: u64, k1: u64, element: &[u8], f: u64) -> u64 {
+    let hash = siphash24::Hash::hash_to_u64_with_keys(k0, k1, element);
+    ((hash as u128 * f as u128) >> 64) as u64
+}
+
+fn golomb_encode(writer: &mut BitWriter, delta: u64) {
+    let quotient = delta >> FILTER_P;
+    for _ in 0..quotient {
+        writer.write_bit(true);
+    }
+    writer.write_bit(false);
+    writer.write_bits(delta, FILTER_P);
+}
+
+fn golomb_decode(reader: &mut BitReader) -> Result<u64, CustomError> {
+    let mut quotient = 0;
+    while reader.read_bit()? {
+        quotient += 1;
+    }
+    let remainder = reader.read_bits(FILTER_P)?;
+    Ok((quotient << FILTER_P) + remainder)
+}
+
+/// Escribe bits de mayor a menor significancia.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_count: usize,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bit_count == self.bytes.len() * 8 {
+            self.bytes.push(0);
+        }
+        if bit {
+            if let Some(last) = self.bytes.last_mut() {
+                *last |= 1 << (7 - self.bit_count % 8);
+            }
+        }
+        self.bit_count += 1;
+    }
+
+    fn write_bits(&mut self, value: u64, count: u8) {
+        for i in (0..count).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Lee bits de mayor a menor significancia.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn read_bit(&mut self) -> Result<bool, CustomError> {
+        let byte = self
+            .bytes
+            .get(self.position / 8)
+            .ok_or(CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+        let bit = (byte >> (7 - self.position % 8)) & 1 == 1;
+        self.position += 1;
+        Ok(bit)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u64, CustomError> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::Network;
+
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn genesis_script() -> Vec<u8> {
+        from_hex("4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac")
+    }
+
+    #[test]
+    fn genesis_block_filter() {
+        let filter = BlockFilter::new(&Network::Testnet.genesis_hash(), &[genesis_script()]);
+        assert_eq!(filter.serialize(), from_hex("019dfca8"));
+
+        let mut expected_header =
+            from_hex("21584579b7eb08997773e5aeff3a7f932700042d0ed2a6129012b7d7ae81b750");
+        expected_header.reverse();
+        assert_eq!(filter_header(&filter.hash(), &[0; 32]), expected_header);
+    }
+
+    #[test]
+    fn filter_matches_elements() {
+        let elements: Vec<Vec<u8>> = (0..50_u8).map(|i| vec![i; 25]).collect();
+        let filter = BlockFilter::new(&Network::Testnet.genesis_hash(), &elements);
+        let parsed = BlockFilter::parse(filter.serialize()).unwrap();
+
+        assert_eq!(parsed.n, 50);
+        assert!(parsed
+            .match_any(&Network::Testnet.genesis_hash(), &[vec![7; 25]])
+            .unwrap());
+        assert!(parsed
+            .match_any(
+                &Network::Testnet.genesis_hash(),
+                &[vec![200; 25], vec![49; 25]]
+            )
+            .unwrap());
+        assert!(!parsed
+            .match_any(&Network::Testnet.genesis_hash(), &[vec![200; 25]])
+            .unwrap());
+    }
+
+    #[test]
+    fn empty_filter_does_not_match() {
+        let filter = BlockFilter::new(&Network::Testnet.genesis_hash(), &[]);
+        assert_eq!(filter.serialize(), vec![0]);
+        assert!(!filter
+            .match_any(&Network::Testnet.genesis_hash(), &[vec![1; 25]])
+            .unwrap());
+    }
+
+    #[test]
+    fn genesis_filter_header_matches_bip158() {
+        // vector de BIP 158 para el genesis de testnet, en el orden en que lo muestran los exploradores
+        let mut header = genesis_filter_header(Network::Testnet);
+        header.reverse();
+        assert_eq!(
+            header,
+            from_hex("21584579b7eb08997773e5aeff3a7f932700042d0ed2a6129012b7d7ae81b750")
+        );
+    }
+
+    #[test]
+    fn filter_with_too_many_elements_is_invalid() {
+        let filter = BlockFilter {
+            n: u64::MAX,
+            content: vec![0; 4],
+        };
+        assert!(filter
+            .match_any(&Network::Testnet.genesis_hash(), &[vec![1; 25]])
+            .is_err());
+    }
+}
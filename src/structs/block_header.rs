@@ -1,6 +1,8 @@
-use bitcoin_hashes::{sha256d, Hash};
-
-use crate::{error::CustomError, parser::BufferParser};
+use crate::{
+    error::{ChainError, CustomError, ParseError},
+    parser::{BufferParser, Parser},
+    structs::hash256::Hash256,
+};
 
 #[derive(Debug, Clone)]
 ///Esta estructura representa el header de un bloque, el cual contiene la siguiente información:
@@ -12,12 +14,12 @@ use crate::{error::CustomError, parser::BufferParser};
 /// - Nonce: Número aleatorio que se utiliza para generar el hash del bloque
 pub struct BlockHeader {
     pub version: i32,
-    pub prev_block_hash: Vec<u8>,
-    pub merkle_root: Vec<u8>,
+    pub prev_block_hash: Hash256,
+    pub merkle_root: Hash256,
     pub timestamp: u32,
     pub bits: u32,
     pub nonce: u32,
-    pub hash: Vec<u8>,
+    pub hash: Hash256,
     pub broadcasted: bool,
     pub block_downloaded: bool,
 }
@@ -27,8 +29,8 @@ impl BlockHeader {
     pub fn serialize(&self) -> Vec<u8> {
         let mut buffer: Vec<u8> = vec![];
         buffer.extend(&self.version.to_le_bytes());
-        buffer.extend(&self.prev_block_hash);
-        buffer.extend(&self.merkle_root);
+        buffer.extend(self.prev_block_hash.as_bytes());
+        buffer.extend(self.merkle_root.as_bytes());
         buffer.extend(&self.timestamp.to_le_bytes());
         buffer.extend(&self.bits.to_le_bytes());
         buffer.extend(&self.nonce.to_le_bytes());
@@ -39,12 +41,12 @@ impl BlockHeader {
     pub fn serialize_for_backup(&self) -> Vec<u8> {
         let mut buffer: Vec<u8> = vec![];
         buffer.extend(&self.version.to_le_bytes());
-        buffer.extend(&self.prev_block_hash);
-        buffer.extend(&self.merkle_root);
+        buffer.extend(self.prev_block_hash.as_bytes());
+        buffer.extend(self.merkle_root.as_bytes());
         buffer.extend(&self.timestamp.to_le_bytes());
         buffer.extend(&self.bits.to_le_bytes());
         buffer.extend(&self.nonce.to_le_bytes());
-        buffer.extend(&self.hash);
+        buffer.extend(self.hash.as_bytes());
 
         buffer
     }
@@ -52,17 +54,17 @@ impl BlockHeader {
     ///Esta funcion se encarga de dado un vector de bytes, parsearlo a un BlockHeader con todos sus campos correspondientes
     /// Tambien se encarga de validar que el header sea valido, es decir, que cumpla con la proof of work, esto solo lo hace si el parametro validate es true.
     pub fn parse(buffer: Vec<u8>) -> Result<Self, CustomError> {
-        let hash = sha256d::Hash::hash(&buffer).to_byte_array().to_vec();
+        let hash = Hash256::hash(&buffer);
 
-        let mut parser = BufferParser::new(buffer);
+        let mut parser = BufferParser::new(&buffer);
         if parser.len() < 80 {
-            return Err(CustomError::SerializedBufferIsInvalid);
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
         }
 
         let block_header = BlockHeader {
             version: parser.extract_i32()?,
-            prev_block_hash: parser.extract_buffer(32)?.to_vec(),
-            merkle_root: parser.extract_buffer(32)?.to_vec(),
+            prev_block_hash: parser.extract_hash32()?.into(),
+            merkle_root: parser.extract_hash32()?.into(),
             timestamp: parser.extract_u32()?,
             bits: parser.extract_u32()?,
             nonce: parser.extract_u32()?,
@@ -72,77 +74,92 @@ impl BlockHeader {
         };
 
         if !(block_header.validate()) {
-            return Err(CustomError::HeaderInvalidPoW);
+            return Err(CustomError::Chain(ChainError::HeaderInvalidPoW));
         }
 
         Ok(block_header)
     }
 
     pub fn parse_from_backup(buffer: Vec<u8>) -> Result<Self, CustomError> {
-        let mut parser = BufferParser::new(buffer);
+        let mut parser = BufferParser::new(&buffer);
         if parser.len() < 112 {
-            return Err(CustomError::SerializedBufferIsInvalid);
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
         }
 
         let block_header = BlockHeader {
             version: parser.extract_i32()?,
-            prev_block_hash: parser.extract_buffer(32)?.to_vec(),
-            merkle_root: parser.extract_buffer(32)?.to_vec(),
+            prev_block_hash: parser.extract_hash32()?.into(),
+            merkle_root: parser.extract_hash32()?.into(),
             timestamp: parser.extract_u32()?,
             bits: parser.extract_u32()?,
             nonce: parser.extract_u32()?,
-            hash: parser.extract_buffer(32)?.to_vec(),
+            hash: parser.extract_hash32()?.into(),
             block_downloaded: true,
             broadcasted: true,
         };
 
         if !(block_header.validate()) {
-            return Err(CustomError::HeaderInvalidPoW);
+            return Err(CustomError::Chain(ChainError::HeaderInvalidPoW));
         }
 
         Ok(block_header)
     }
 
     ///Esta funcion se encarga de validar la proof of work de un bloque.
+    /// Compara el hash con el target de sus bits, ambos como enteros de 256 bits en big endian.
     fn validate(&self) -> bool {
-        let hash = self.hash();
-        let bits_vec = self.bits.to_be_bytes().to_vec();
-
-        let leading_zeros_start = bits_vec[0] as usize;
-        let leading_zeros = hash[leading_zeros_start..32].to_vec();
-
-        if leading_zeros.iter().any(|zero| *zero != 0_u8) {
-            return false;
-        }
-
-        let mut significants = hash[(leading_zeros_start - 3)..leading_zeros_start].to_vec();
-        significants.reverse();
-
-        let mut bits_vec_pos = 1;
-        for hash_byte in significants {
-            if hash_byte != bits_vec[bits_vec_pos] {
-                return hash_byte < bits_vec[bits_vec_pos];
-            }
-            bits_vec_pos += 1;
-        }
-        false
+        let Some(target) = target_from_bits(self.bits) else { return false };
+        let mut hash = *self.hash().as_bytes();
+        hash.reverse();
+        hash <= target
     }
 
     /// Esta funcion se encarga de calcular el hash del header de un bloque
-    pub fn hash(&self) -> &Vec<u8> {
+    pub fn hash(&self) -> &Hash256 {
         &self.hash
     }
 
     /// Esta funcion se encarga de calcular el hash del header de un bloque y devolverlo como un string
     pub fn hash_as_string(&self) -> String {
-        hash_as_string(self.hash().clone())
+        hash_as_string(self.hash())
     }
 }
 
-/// Esta funcion se encarga de convertir un vector de bytes en hexa que forma un hash a un string
-pub fn hash_as_string(hash: Vec<u8>) -> String {
-    let mut filename = String::with_capacity(2 * hash.len());
-    for byte in hash {
+/// Expande los bits de un header a su target, un entero de 256 bits en big endian.
+/// Los bits tienen el formato compacto de Bitcoin: el primer byte es el tamaño del target en bytes
+/// y los otros tres son sus bytes mas significativos, en el que el bit mas alto es el signo.
+/// Devuelve None si el target es negativo, es cero o no entra en 256 bits, ya que ningun header puede cumplirlo.
+pub fn target_from_bits(bits: u32) -> Option<[u8; 32]> {
+    let size = (bits >> 24) as usize;
+    let mantissa = bits & 0x007fffff;
+    if mantissa == 0 || bits & 0x00800000 != 0 {
+        return None;
+    }
+
+    let mut target = [0_u8; 32];
+    if size <= 3 {
+        let value = mantissa >> (8 * (3 - size));
+        target[28..].copy_from_slice(&value.to_be_bytes());
+    } else {
+        for (index, byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+            match (32 + index).checked_sub(size) {
+                Some(position) if position < 32 => target[position] = *byte,
+                _ if *byte != 0 => return None,
+                _ => {}
+            }
+        }
+    }
+
+    if target == [0; 32] {
+        return None;
+    }
+    Some(target)
+}
+
+/// Esta funcion se encarga de convertir un hash a un string en hexa, que se usa como nombre del archivo del bloque
+pub fn hash_as_string(hash: &Hash256) -> String {
+    let mut filename = String::with_capacity(64);
+    for byte in hash.as_bytes() {
         filename.push_str(format!("{:02X}", byte).as_str());
     }
     filename
@@ -150,7 +167,33 @@ pub fn hash_as_string(hash: Vec<u8>) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::structs::block_header::BlockHeader;
+    use crate::structs::{
+        block_header::{target_from_bits, BlockHeader},
+        hash256::Hash256,
+    };
+
+    /// Devuelve un header con los bits recibidos y el hash recibido en big endian.
+    fn header_with_hash(bits: u32, mut hash: [u8; 32]) -> BlockHeader {
+        hash.reverse();
+        BlockHeader {
+            version: 1,
+            prev_block_hash: Hash256::ZERO,
+            merkle_root: Hash256::ZERO,
+            timestamp: 0,
+            bits,
+            nonce: 0,
+            hash: Hash256::new(hash),
+            block_downloaded: false,
+            broadcasted: false,
+        }
+    }
+
+    /// Devuelve el target en big endian con los bytes recibidos desde la posicion start.
+    fn target(start: usize, bytes: &[u8]) -> [u8; 32] {
+        let mut target = [0; 32];
+        target[start..start + bytes.len()].copy_from_slice(bytes);
+        target
+    }
 
     #[test]
     fn blockheader_serialize_and_parse() {
@@ -197,21 +240,21 @@ mod tests {
     fn valid_pow_header() {
         let valid_header = BlockHeader {
             version: 2,
-            prev_block_hash: vec![
+            prev_block_hash: Hash256::new([
                 61, 8, 52, 163, 234, 98, 255, 92, 186, 170, 164, 90, 56, 131, 46, 171, 52, 239,
                 104, 223, 166, 65, 183, 217, 36, 6, 53, 63, 0, 0, 0, 0,
-            ],
-            merkle_root: vec![
+            ]),
+            merkle_root: Hash256::new([
                 45, 107, 6, 225, 181, 124, 4, 88, 86, 174, 58, 59, 113, 215, 174, 42, 209, 149,
                 142, 110, 166, 53, 244, 88, 6, 76, 228, 77, 7, 10, 189, 126,
-            ],
+            ]),
             timestamp: 1347149007,
             bits: 476726600,
             nonce: 240236131,
-            hash: vec![
+            hash: Hash256::new([
                 10, 110, 89, 244, 38, 172, 240, 48, 75, 251, 139, 33, 16, 164, 179, 154, 22, 123,
                 120, 81, 209, 213, 111, 183, 7, 9, 162, 49, 0, 0, 0, 0,
-            ],
+            ]),
             block_downloaded: false,
             broadcasted: false,
         };
@@ -224,25 +267,71 @@ mod tests {
     fn invalid_pow_header() {
         let valid_header = BlockHeader {
             version: 2,
-            prev_block_hash: vec![
+            prev_block_hash: Hash256::new([
                 61, 8, 52, 163, 234, 98, 255, 92, 186, 170, 164, 90, 56, 131, 46, 171, 52, 239,
                 104, 223, 166, 65, 183, 217, 36, 6, 53, 63, 0, 0, 0, 0,
-            ],
-            merkle_root: vec![
+            ]),
+            merkle_root: Hash256::new([
                 45, 107, 6, 225, 181, 124, 4, 88, 86, 174, 58, 59, 113, 215, 174, 42, 209, 149,
                 142, 110, 166, 53, 244, 88, 6, 76, 228, 77, 7, 10, 189, 126,
-            ],
+            ]),
             timestamp: 1347149007,
             bits: 476726600,
             nonce: 123123,
-            hash: vec![
+            hash: Hash256::new([
                 116, 18, 66, 212, 76, 145, 158, 131, 46, 212, 244, 136, 96, 84, 11, 220, 121, 121,
                 78, 50, 3, 197, 235, 49, 172, 32, 11, 104, 118, 114, 161, 104,
-            ],
+            ]),
             block_downloaded: false,
             broadcasted: false,
         };
 
         assert!(!valid_header.validate());
     }
+
+    #[test]
+    fn expand_compact_targets() {
+        assert_eq!(target_from_bits(0x1d00ffff), Some(target(4, &[0xff, 0xff])));
+        assert_eq!(
+            target_from_bits(0x1b0404cb),
+            Some(target(5, &[0x04, 0x04, 0xcb]))
+        );
+        // con tamaño menor o igual a 3 los bytes sobrantes de la mantisa se descartan
+        assert_eq!(
+            target_from_bits(0x03123456),
+            Some(target(29, &[0x12, 0x34, 0x56]))
+        );
+        assert_eq!(
+            target_from_bits(0x02123456),
+            Some(target(30, &[0x12, 0x34]))
+        );
+        assert_eq!(target_from_bits(0x01123456), Some(target(31, &[0x12])));
+        assert_eq!(target_from_bits(0x01003456), None);
+        assert_eq!(target_from_bits(0x00123456), None);
+        // el bit mas alto de la mantisa es el signo
+        assert_eq!(target_from_bits(0x04923456), None);
+        assert_eq!(target_from_bits(0x01fedcba), None);
+        assert_eq!(target_from_bits(0x04800000), None);
+        // targets que no entran en 256 bits
+        assert_eq!(target_from_bits(0x220000ff), Some(target(0, &[0xff])));
+        assert_eq!(target_from_bits(0x2200ffff), None);
+        assert_eq!(target_from_bits(0x21010000), None);
+        assert_eq!(target_from_bits(0xff123456), None);
+    }
+
+    #[test]
+    fn hash_equal_to_target_is_valid() {
+        let limit = target(4, &[0xff, 0xff]);
+        assert!(header_with_hash(0x1d00ffff, limit).validate());
+
+        let mut above = limit;
+        above[6] = 1;
+        assert!(!header_with_hash(0x1d00ffff, above).validate());
+
+        // los targets chicos y los negativos no se pueden comparar con el algoritmo anterior
+        assert!(header_with_hash(0x03123456, target(29, &[0x12, 0x34, 0x56])).validate());
+        assert!(!header_with_hash(0x03123456, target(29, &[0x12, 0x34, 0x57])).validate());
+        assert!(!header_with_hash(0x04923456, [0; 32]).validate());
+        assert!(!header_with_hash(0x01003456, [0; 32]).validate());
+    }
 }
@@ -0,0 +1,221 @@
+use crate::{
+    error::{CustomError, ParseError},
+    parser::{Parser, VarIntSerialize},
+    structs::script::{Script, ScriptElement},
+};
+
+/// Tamaño maximo del filtro en bytes permitido por BIP 37.
+const MAX_BLOOM_FILTER_SIZE: usize = 36000;
+/// Cantidad maxima de funciones de hash permitida por BIP 37.
+const MAX_HASH_FUNCS: u32 = 50;
+/// Constante utilizada para generar las semillas de cada funcion de hash.
+const SEED_MULTIPLIER: u32 = 0xFBA4C795;
+
+/// El filtro no se actualiza al encontrar coincidencias.
+pub const BLOOM_UPDATE_NONE: u8 = 0;
+/// El filtro agrega el outpoint de cualquier output que coincida, para detectar cuando se gasta.
+pub const BLOOM_UPDATE_ALL: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+/// BloomFilter es un filtro probabilistico que permite a un peer saber que transacciones nos interesan (BIP 37).
+/// Los elementos son:
+/// - filter: Campo de bits del filtro.
+/// - n_hash_funcs: Cantidad de funciones de hash aplicadas a cada elemento.
+/// - n_tweak: Valor aleatorio que se suma a la semilla de cada funcion de hash.
+/// - n_flags: Indica como debe actualizar el peer el filtro al encontrar coincidencias.
+pub struct BloomFilter {
+    pub filter: Vec<u8>,
+    pub n_hash_funcs: u32,
+    pub n_tweak: u32,
+    pub n_flags: u8,
+}
+
+impl BloomFilter {
+    /// Crea un filtro vacio dimensionado para la cantidad de elementos y la tasa de falsos positivos recibidas.
+    pub fn new(elements: usize, false_positive_rate: f64, n_tweak: u32, n_flags: u8) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let elements = elements.max(1) as f64;
+
+        let filter_bytes =
+            (-1.0 / (ln2 * ln2) * elements * false_positive_rate.ln() / 8.0) as usize;
+        let filter_bytes = filter_bytes.clamp(1, MAX_BLOOM_FILTER_SIZE);
+        let n_hash_funcs = ((filter_bytes * 8) as f64 / elements * ln2) as u32;
+
+        Self {
+            filter: vec![0; filter_bytes],
+            n_hash_funcs: n_hash_funcs.clamp(1, MAX_HASH_FUNCS),
+            n_tweak,
+            n_flags,
+        }
+    }
+
+    /// Calcula el indice del bit correspondiente a un elemento para la funcion de hash numero hash_num.
+    fn bit_index(&self, hash_num: u32, data: &[u8]) -> usize {
+        let seed = hash_num
+            .wrapping_mul(SEED_MULTIPLIER)
+            .wrapping_add(self.n_tweak);
+        murmur3(seed, data) as usize % (self.filter.len() * 8)
+    }
+
+    /// Agrega un elemento al filtro.
+    pub fn insert(&mut self, data: &[u8]) {
+        for hash_num in 0..self.n_hash_funcs {
+            let index = self.bit_index(hash_num, data);
+            self.filter[index >> 3] |= 1 << (7 & index);
+        }
+    }
+
+    /// Agrega al filtro los datos de un script pubkey, que son los elementos que un peer compara con los outputs.
+    /// Un script P2PKH o P2WPKH aporta el pubkey hash, y un script P2SH el hash del redeem script.
+    pub fn insert_script_pubkey(&mut self, script_pubkey: &Script) {
+        for element in &script_pubkey.elements {
+            if let ScriptElement::Push(data) = element {
+                self.insert(data);
+            }
+        }
+    }
+
+    /// Devuelve true si el elemento puede estar en el filtro.
+    pub fn contains(&self, data: &[u8]) -> bool {
+        (0..self.n_hash_funcs).all(|hash_num| {
+            let index = self.bit_index(hash_num, data);
+            self.filter[index >> 3] & (1 << (7 & index)) != 0
+        })
+    }
+
+    /// Serializa el filtro con el formato del mensaje filterload.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = vec![];
+        buffer.extend(self.filter.len().to_varint_bytes());
+        buffer.extend(&self.filter);
+        buffer.extend(self.n_hash_funcs.to_le_bytes());
+        buffer.extend(self.n_tweak.to_le_bytes());
+        buffer.push(self.n_flags);
+        buffer
+    }
+
+    /// Parsea un filtro con el formato del mensaje filterload.
+    /// Devuelve CustomError si el filtro excede los limites de BIP 37.
+    pub fn parse(parser: &mut impl Parser) -> Result<Self, CustomError> {
+        let filter_bytes = parser.extract_varint()? as usize;
+        if filter_bytes > MAX_BLOOM_FILTER_SIZE {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        let filter = parser.extract_bytes(filter_bytes)?;
+        let n_hash_funcs = parser.extract_u32()?;
+        if n_hash_funcs > MAX_HASH_FUNCS {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        let n_tweak = parser.extract_u32()?;
+        let n_flags = parser.extract_u8()?;
+
+        Ok(Self {
+            filter,
+            n_hash_funcs,
+            n_tweak,
+            n_flags,
+        })
+    }
+}
+
+/// Implementacion de MurmurHash3 (x86, 32 bits), la funcion de hash que utiliza BIP 37.
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+
+    let mut h1 = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in chunks.by_ref() {
+        let mut k1 = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k1 = k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h1 ^= k1;
+        h1 = h1.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k1: u32 = 0;
+        for (i, byte) in tail.iter().enumerate() {
+            k1 ^= (*byte as u32) << (8 * i);
+        }
+        k1 = k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h1 ^= k1;
+    }
+
+    h1 ^= data.len() as u32;
+    h1 ^= h1 >> 16;
+    h1 = h1.wrapping_mul(0x85ebca6b);
+    h1 ^= h1 >> 13;
+    h1 = h1.wrapping_mul(0xc2b2ae35);
+    h1 ^= h1 >> 16;
+    h1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::BufferParser;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn murmur3_known_values() {
+        assert_eq!(murmur3(0, &[]), 0);
+        assert_eq!(murmur3(0xFBA4C795, &[]), 0x6a396f08);
+        assert_eq!(murmur3(0, &[0x00]), 0x514E28B7);
+        assert_eq!(murmur3(0, &[0x21, 0x43, 0x65, 0x87]), 0xF55B516B);
+    }
+
+    #[test]
+    fn insert_and_serialize() {
+        let mut filter = BloomFilter::new(3, 0.01, 0, BLOOM_UPDATE_ALL);
+
+        filter.insert(&from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8"));
+        assert!(filter.contains(&from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+        assert!(!filter.contains(&from_hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+
+        filter.insert(&from_hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee"));
+        filter.insert(&from_hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5"));
+
+        assert_eq!(filter.serialize(), from_hex("03614e9b050000000000000001"));
+    }
+
+    #[test]
+    fn insert_script_pubkeys() {
+        let pubkey_hash = from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8");
+        let script_hash = from_hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee");
+        let mut filter = BloomFilter::new(2, 0.0001, 0, BLOOM_UPDATE_ALL);
+
+        filter.insert_script_pubkey(&Script::p2wpkh(&pubkey_hash));
+        filter.insert_script_pubkey(&Script::p2sh(&script_hash));
+        assert!(filter.contains(&pubkey_hash));
+        assert!(filter.contains(&script_hash));
+        assert!(!filter.contains(&Script::p2wpkh(&pubkey_hash).serialize()));
+    }
+
+    #[test]
+    fn serialize_and_parse() {
+        let mut filter = BloomFilter::new(10, 0.001, 2147483649, BLOOM_UPDATE_NONE);
+        filter.insert(&[1, 2, 3]);
+
+        let serialized = filter.serialize();
+
+        let mut parser = BufferParser::new(&serialized);
+        let parsed = BloomFilter::parse(&mut parser).unwrap();
+        assert_eq!(parsed, filter);
+        assert!(parsed.contains(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn parse_filter_too_big() {
+        let mut buffer = 36001_usize.to_varint_bytes();
+        buffer.extend(vec![0; 36010]);
+        let mut parser = BufferParser::new(&buffer);
+        assert!(BloomFilter::parse(&mut parser).is_err());
+    }
+}
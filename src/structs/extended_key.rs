@@ -0,0 +1,343 @@
+use bitcoin_hashes::{hash160, hmac, sha512, Hash, HashEngine};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+
+use crate::{
+    address::Address, encoding::base58, error::CustomError, network::Network, parser::BufferParser,
+};
+
+/// Indices a partir de este valor generan derivaciones endurecidas (hardened).
+pub const HARDENED: u32 = 0x8000_0000;
+/// Version de las public keys extendidas de mainnet (xpub).
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+/// Version de las public keys extendidas de testnet (tpub).
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+/// Tamaño en bytes de una public key extendida serializada segun BIP 32, sin el checksum.
+const XPUB_SIZE: usize = 78;
+
+#[derive(Debug, Clone, PartialEq)]
+/// ExtendedKey es una private key extendida segun BIP 32.
+/// Permite derivar claves hijas de forma deterministica a partir de una semilla.
+/// Los elementos son:
+/// - secret_key: Private key de 32 bytes.
+/// - chain_code: Chain code de 32 bytes utilizado en la derivacion.
+pub struct ExtendedKey {
+    pub secret_key: Vec<u8>,
+    pub chain_code: Vec<u8>,
+}
+
+impl ExtendedKey {
+    /// Genera la clave maestra a partir de una semilla (por ejemplo la de un mnemonico BIP 39).
+    /// Devuelve CustomError si la semilla genera una clave invalida.
+    pub fn from_seed(seed: &[u8]) -> Result<Self, CustomError> {
+        let hash = hmac_sha512(b"Bitcoin seed", seed);
+        Self::from_hmac(&hash)
+    }
+
+    /// Deriva la clave hija con el indice recibido.
+    /// Si el indice es mayor o igual a HARDENED la derivacion es endurecida.
+    /// Devuelve CustomError si la clave derivada es invalida.
+    pub fn derive_child(&self, index: u32) -> Result<Self, CustomError> {
+        let secp = Secp256k1::new();
+        let secret_key = self.get_secret_key()?;
+
+        let mut data = vec![];
+        if index >= HARDENED {
+            data.push(0);
+            data.extend(&self.secret_key);
+        } else {
+            data.extend(PublicKey::from_secret_key(&secp, &secret_key).serialize());
+        }
+        data.extend(index.to_be_bytes());
+
+        let hash = hmac_sha512(&self.chain_code, &data);
+        let mut tweak = [0_u8; 32];
+        tweak.copy_from_slice(&hash[0..32]);
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| invalid_key())?;
+        let child_key = secret_key.add_tweak(&tweak).map_err(|_| invalid_key())?;
+
+        Ok(Self {
+            secret_key: child_key.secret_bytes().to_vec(),
+            chain_code: hash[32..64].to_vec(),
+        })
+    }
+
+    /// Deriva la clave correspondiente a un path, por ejemplo [44 | HARDENED, 1 | HARDENED, 0 | HARDENED].
+    pub fn derive_path(&self, path: &[u32]) -> Result<Self, CustomError> {
+        let mut key = self.clone();
+        for index in path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    /// Devuelve la public key extendida correspondiente, que permite derivar las public keys hijas no endurecidas.
+    pub fn extended_public_key(&self) -> Result<ExtendedPublicKey, CustomError> {
+        Ok(ExtendedPublicKey {
+            public_key: self.public_key()?,
+            chain_code: self.chain_code.clone(),
+        })
+    }
+
+    /// Devuelve la public key comprimida de la clave.
+    pub fn public_key(&self) -> Result<Vec<u8>, CustomError> {
+        let secp = Secp256k1::new();
+        let secret_key = self.get_secret_key()?;
+        Ok(PublicKey::from_secret_key(&secp, &secret_key)
+            .serialize()
+            .to_vec())
+    }
+
+    /// Devuelve el hash de la public key (hash160).
+    pub fn public_key_hash(&self) -> Result<Vec<u8>, CustomError> {
+        Ok(hash160::Hash::hash(&self.public_key()?)
+            .to_byte_array()
+            .to_vec())
+    }
+
+    /// Devuelve la direccion P2PKH de la clave en la red seleccionada.
+    pub fn address(&self) -> Result<String, CustomError> {
+        Ok(Address::p2pkh(&self.public_key_hash()?, Network::current()).to_string())
+    }
+
+    /// Devuelve la private key en formato WIF de la red seleccionada, para una public key comprimida.
+    pub fn wif(&self) -> String {
+        let mut payload = vec![Network::current().wif_prefix()];
+        payload.extend(&self.secret_key);
+        payload.push(0x01);
+        base58::encode_check(&payload)
+    }
+
+    /// Serializa la clave.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.secret_key.clone();
+        buffer.extend(&self.chain_code);
+        buffer
+    }
+
+    /// Parsea una clave serializada.
+    pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        let secret_key = parser.extract_buffer(32)?.to_vec();
+        let chain_code = parser.extract_buffer(32)?.to_vec();
+        Ok(Self {
+            secret_key,
+            chain_code,
+        })
+    }
+
+    fn from_hmac(hash: &[u8; 64]) -> Result<Self, CustomError> {
+        SecretKey::from_slice(&hash[0..32]).map_err(|_| invalid_key())?;
+        Ok(Self {
+            secret_key: hash[0..32].to_vec(),
+            chain_code: hash[32..64].to_vec(),
+        })
+    }
+
+    fn get_secret_key(&self) -> Result<SecretKey, CustomError> {
+        SecretKey::from_slice(&self.secret_key).map_err(|_| invalid_key())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// ExtendedPublicKey es una public key extendida segun BIP 32 (xpub).
+/// Permite derivar las public keys hijas no endurecidas sin conocer la private key, por lo que
+/// alcanza para generar las direcciones de una wallet watch-only.
+/// Los elementos son:
+/// - public_key: Public key comprimida de 33 bytes.
+/// - chain_code: Chain code de 32 bytes utilizado en la derivacion.
+pub struct ExtendedPublicKey {
+    pub public_key: Vec<u8>,
+    pub chain_code: Vec<u8>,
+}
+
+impl ExtendedPublicKey {
+    /// Lee una public key extendida en formato xpub o tpub.
+    /// Devuelve CustomError si no esta bien codificada o la public key es invalida.
+    pub fn from_xpub(xpub: &str) -> Result<Self, CustomError> {
+        let payload = base58::decode_check(xpub).map_err(|_| invalid_key())?;
+        if payload.len() != XPUB_SIZE {
+            return Err(invalid_key());
+        }
+        if payload[0..4] != XPUB_VERSION && payload[0..4] != TPUB_VERSION {
+            return Err(invalid_key());
+        }
+
+        let public_key = payload[45..78].to_vec();
+        PublicKey::from_slice(&public_key).map_err(|_| invalid_key())?;
+        Ok(Self {
+            public_key,
+            chain_code: payload[13..45].to_vec(),
+        })
+    }
+
+    /// Deriva la public key hija con el indice recibido.
+    /// Devuelve CustomError si el indice es endurecido, ya que requiere la private key, o si la clave derivada es invalida.
+    pub fn derive_child(&self, index: u32) -> Result<Self, CustomError> {
+        if index >= HARDENED {
+            return Err(invalid_key());
+        }
+        let secp = Secp256k1::new();
+        let public_key = PublicKey::from_slice(&self.public_key).map_err(|_| invalid_key())?;
+
+        let mut data = self.public_key.clone();
+        data.extend(index.to_be_bytes());
+
+        let hash = hmac_sha512(&self.chain_code, &data);
+        let mut tweak = [0_u8; 32];
+        tweak.copy_from_slice(&hash[0..32]);
+        let tweak = Scalar::from_be_bytes(tweak).map_err(|_| invalid_key())?;
+        let child_key = public_key
+            .add_exp_tweak(&secp, &tweak)
+            .map_err(|_| invalid_key())?;
+
+        Ok(Self {
+            public_key: child_key.serialize().to_vec(),
+            chain_code: hash[32..64].to_vec(),
+        })
+    }
+
+    /// Deriva la public key correspondiente a un path de indices no endurecidos.
+    pub fn derive_path(&self, path: &[u32]) -> Result<Self, CustomError> {
+        let mut key = self.clone();
+        for index in path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+
+    /// Devuelve el hash de la public key (hash160).
+    pub fn public_key_hash(&self) -> Vec<u8> {
+        hash160::Hash::hash(&self.public_key)
+            .to_byte_array()
+            .to_vec()
+    }
+
+    /// Devuelve la direccion P2PKH de la clave en la red seleccionada.
+    pub fn address(&self) -> String {
+        Address::p2pkh(&self.public_key_hash(), Network::current()).to_string()
+    }
+
+    /// Serializa la clave.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.public_key.clone();
+        buffer.extend(&self.chain_code);
+        buffer
+    }
+
+    /// Parsea una clave serializada.
+    pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        let public_key = parser.extract_buffer(33)?.to_vec();
+        let chain_code = parser.extract_buffer(32)?.to_vec();
+        Ok(Self {
+            public_key,
+            chain_code,
+        })
+    }
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(key);
+    engine.input(data);
+    hmac::Hmac::<sha512::Hash>::from_engine(engine).to_byte_array()
+}
+
+fn invalid_key() -> CustomError {
+    CustomError::Validation(String::from("Invalid extended key"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wallet::get_privkey_hash;
+
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn master_key_from_seed() {
+        let master = ExtendedKey::from_seed(&from_hex("000102030405060708090a0b0c0d0e0f")).unwrap();
+        assert_eq!(
+            master.secret_key,
+            from_hex("e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35")
+        );
+        assert_eq!(
+            master.chain_code,
+            from_hex("873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508")
+        );
+    }
+
+    #[test]
+    fn derive_hardened_and_normal_children() {
+        let master = ExtendedKey::from_seed(&from_hex("000102030405060708090a0b0c0d0e0f")).unwrap();
+
+        let child = master.derive_child(HARDENED).unwrap();
+        assert_eq!(
+            child.secret_key,
+            from_hex("edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea")
+        );
+
+        let child = master.derive_path(&[HARDENED, 1]).unwrap();
+        assert_eq!(
+            child.secret_key,
+            from_hex("3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368")
+        );
+        assert_eq!(
+            child.chain_code,
+            from_hex("2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19")
+        );
+    }
+
+    #[test]
+    fn serialize_and_parse() {
+        let master = ExtendedKey::from_seed(&[7; 32]).unwrap();
+        let serialized = master.serialize();
+        let mut parser = BufferParser::new(&serialized);
+        assert_eq!(ExtendedKey::parse(&mut parser).unwrap(), master);
+    }
+
+    #[test]
+    fn extended_public_key_from_xpub() {
+        let master = ExtendedKey::from_seed(&from_hex("000102030405060708090a0b0c0d0e0f")).unwrap();
+        let xpub = ExtendedPublicKey::from_xpub("xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8").unwrap();
+        assert_eq!(xpub, master.extended_public_key().unwrap());
+        assert_eq!(
+            xpub.public_key,
+            from_hex("0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2")
+        );
+
+        assert!(ExtendedPublicKey::from_xpub("xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet9").is_err());
+        assert!(ExtendedPublicKey::from_xpub("invalid").is_err());
+    }
+
+    #[test]
+    fn public_derivation_matches_private_derivation() {
+        let account = ExtendedKey::from_seed(&[7; 32])
+            .unwrap()
+            .derive_path(&[44 | HARDENED, 1 | HARDENED, HARDENED])
+            .unwrap();
+        let xpub = account.extended_public_key().unwrap();
+
+        let child = account.derive_path(&[0, 5]).unwrap();
+        let public_child = xpub.derive_path(&[0, 5]).unwrap();
+        assert_eq!(public_child, child.extended_public_key().unwrap());
+        assert_eq!(public_child.address(), child.address().unwrap());
+
+        assert!(xpub.derive_child(HARDENED).is_err());
+
+        let serialized = xpub.serialize();
+
+        let mut parser = BufferParser::new(&serialized);
+        assert_eq!(ExtendedPublicKey::parse(&mut parser).unwrap(), xpub);
+    }
+
+    #[test]
+    fn wif_roundtrip() {
+        let master = ExtendedKey::from_seed(&[7; 32]).unwrap();
+        assert_eq!(get_privkey_hash(master.wif()).unwrap(), master.secret_key);
+        assert_eq!(master.address().unwrap().len(), 34);
+    }
+}
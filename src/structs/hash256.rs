@@ -0,0 +1,106 @@
+use std::{fmt, str::FromStr};
+
+use bitcoin_hashes::{sha256d, Hash};
+
+use crate::{
+    error::{CustomError, ParseError},
+    utils::{hash_to_hex, hex_to_hash},
+};
+
+/// Hash256 es un hash de 32 bytes, como el de los bloques y las transacciones.
+/// Los bytes se guardan en el orden en que viajan por la red (little endian), y se muestran
+/// invertidos, en el orden en que los muestran los exploradores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Hash256([u8; 32]);
+
+impl Hash256 {
+    /// Hash con todos los bytes en 0, por ejemplo el hash del bloque anterior al genesis.
+    pub const ZERO: Self = Self([0; 32]);
+
+    /// Crea el hash a partir de sus bytes.
+    pub const fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Calcula el doble SHA256 de los datos recibidos.
+    pub fn hash(data: &[u8]) -> Self {
+        Self(sha256d::Hash::hash(data).to_byte_array())
+    }
+
+    /// Crea el hash a partir de un slice.
+    /// Devuelve CustomError si el slice no tiene 32 bytes.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, CustomError> {
+        Ok(Self(bytes.try_into().map_err(|_| {
+            CustomError::Parse(ParseError::SerializedBufferIsInvalid)
+        })?))
+    }
+
+    /// Devuelve los bytes del hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Devuelve los bytes del hash en un vector.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+}
+
+impl From<[u8; 32]> for Hash256 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Hash256 {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Hash256 {
+    /// Muestra el hash en hexadecimal con los bytes invertidos, como los exploradores.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hash_to_hex(&self.0))
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = CustomError;
+
+    /// Parsea un hash en hexadecimal con los bytes invertidos, como lo muestran los exploradores.
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        let bytes =
+            hex_to_hash(hex).ok_or(CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+        Self::from_slice(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HASH: &str = "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943";
+
+    #[test]
+    fn display_and_parse_in_explorer_order() {
+        let hash = Hash256::from_str(GENESIS_HASH).unwrap();
+        assert_eq!(hash.as_bytes()[31], 0x00);
+        assert_eq!(hash.as_bytes()[0], 0x43);
+        assert_eq!(hash.to_string(), GENESIS_HASH);
+
+        assert!(Hash256::from_str("00").is_err());
+        assert!(Hash256::from_slice(&[0; 31]).is_err());
+    }
+
+    #[test]
+    fn hashes_are_ordered_by_their_bytes() {
+        let mut hashes = vec![Hash256::new([2; 32]), Hash256::ZERO, Hash256::new([1; 32])];
+        hashes.sort();
+        assert_eq!(
+            hashes,
+            vec![Hash256::ZERO, Hash256::new([1; 32]), Hash256::new([2; 32])]
+        );
+        assert_eq!(Hash256::hash(b"").to_vec().len(), 32);
+    }
+}
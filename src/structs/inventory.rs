@@ -1,4 +1,8 @@
-use crate::{error::CustomError, parser::BufferParser};
+use crate::{
+    error::{CustomError, ParseError},
+    parser::{BufferParser, Parser},
+    structs::hash256::Hash256,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 /// Este enum contiene los tipos de inventarios que se pueden enviar:
@@ -6,14 +10,16 @@ use crate::{error::CustomError, parser::BufferParser};
 /// - Block = 2
 /// - FilteredBlock = 3
 /// - CompactBlock = 4
-/// - WitnessTx = 5
-/// - WitnessBlock = 6
-/// - FilteredWitnessBlock = 7
+/// - Wtx = 5, transaccion identificada por su wtxid (BIP 339)
+/// - WitnessTx = 0x40000001
+/// - WitnessBlock = 0x40000002
+/// - FilteredWitnessBlock = 0x40000003
 pub enum InventoryType {
     Tx,
     Block,
     FilteredBlock,
     CompactBlock,
+    Wtx,
     WitnessTx,
     WitnessBlock,
     FilteredWitnessBlock,
@@ -23,12 +29,12 @@ pub enum InventoryType {
 ///Esta es la estructura de un inventario, la cual contiene un tipo de inventario y un hash del inventario en si.
 pub struct Inventory {
     pub inventory_type: InventoryType,
-    pub hash: Vec<u8>,
+    pub hash: Hash256,
 }
 
 impl Inventory {
     /// Esta funcion se encarga de crear un nuevo inventario, dado un tipo de inventario y un hash recibidos como parametros.
-    pub fn new(inventory_type: InventoryType, hash: Vec<u8>) -> Self {
+    pub fn new(inventory_type: InventoryType, hash: Hash256) -> Self {
         Self {
             inventory_type,
             hash,
@@ -43,12 +49,13 @@ impl Inventory {
             InventoryType::Block => 2_u32,
             InventoryType::FilteredBlock => 3_u32,
             InventoryType::CompactBlock => 4_u32,
+            InventoryType::Wtx => 5_u32,
             InventoryType::WitnessTx => 0x40000001,
             InventoryType::WitnessBlock => 0x40000002,
             InventoryType::FilteredWitnessBlock => 0x40000003,
         };
         buffer.extend(inventory_type.to_le_bytes());
-        buffer.extend(&self.hash);
+        buffer.extend(self.hash.as_bytes());
         buffer
     }
 
@@ -58,32 +65,36 @@ impl Inventory {
     /// - El tipo de inventario no es valido
     /// - El hash no tiene 32 bytes de longitud
     pub fn parse(buffer: Vec<u8>) -> Result<Self, CustomError> {
-        let mut parser = BufferParser::new(buffer);
+        let mut parser = BufferParser::new(&buffer);
         if parser.len() != 36 {
-            return Err(CustomError::SerializedBufferIsInvalid);
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
         }
         let inventory_type = match parser.extract_u32()? {
             1_u32 => InventoryType::Tx,
             2_u32 => InventoryType::Block,
             3_u32 => InventoryType::FilteredBlock,
             4_u32 => InventoryType::CompactBlock,
+            5_u32 => InventoryType::Wtx,
             0x40000001 => InventoryType::WitnessTx,
             0x40000002 => InventoryType::WitnessBlock,
             0x40000003 => InventoryType::FilteredWitnessBlock,
             _ => {
-                return Err(CustomError::SerializedBufferIsInvalid);
+                return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
             }
         };
         Ok(Self {
             inventory_type,
-            hash: parser.extract_buffer(32)?.to_vec(),
+            hash: parser.extract_hash32()?.into(),
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::structs::inventory::{Inventory, InventoryType};
+    use crate::structs::{
+        hash256::Hash256,
+        inventory::{Inventory, InventoryType},
+    };
 
     #[test]
     fn inventory_block_serialize_and_parse() {
@@ -93,7 +104,7 @@ mod tests {
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
             ]
-            .to_vec(),
+            .into(),
         );
         let buffer = inventory.serialize();
         let parsed_inventory = Inventory::parse(buffer).unwrap();
@@ -121,7 +132,7 @@ mod tests {
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
             ]
-            .to_vec(),
+            .into(),
         );
         let buffer = inventory.serialize();
         let parsed_inventory = Inventory::parse(buffer).unwrap();
@@ -136,7 +147,7 @@ mod tests {
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
             ]
-            .to_vec(),
+            .into(),
         );
         let buffer = inventory.serialize();
         let parsed_inventory = Inventory::parse(buffer).unwrap();
@@ -151,13 +162,29 @@ mod tests {
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
             ]
-            .to_vec(),
+            .into(),
         );
         let buffer = inventory.serialize();
         let parsed_inventory = Inventory::parse(buffer).unwrap();
         assert_eq!(inventory, parsed_inventory);
     }
 
+    #[test]
+    fn inventory_wtx_serialize_and_parse() {
+        let inventory = Inventory::new(
+            InventoryType::Wtx,
+            [
+                220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
+                124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
+            ]
+            .into(),
+        );
+        let buffer = inventory.serialize();
+        assert_eq!(buffer[..4], [5, 0, 0, 0]);
+        let parsed_inventory = Inventory::parse(buffer).unwrap();
+        assert_eq!(inventory, parsed_inventory);
+    }
+
     #[test]
     fn inventory_witness_tx_serialize_and_parse() {
         let inventory = Inventory::new(
@@ -166,7 +193,7 @@ mod tests {
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
             ]
-            .to_vec(),
+            .into(),
         );
         let buffer = inventory.serialize();
         let parsed_inventory = Inventory::parse(buffer).unwrap();
@@ -181,7 +208,7 @@ mod tests {
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
             ]
-            .to_vec(),
+            .into(),
         );
         let buffer = inventory.serialize();
         let parsed_inventory = Inventory::parse(buffer).unwrap();
@@ -196,7 +223,7 @@ mod tests {
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
                 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
             ]
-            .to_vec(),
+            .into(),
         );
         let buffer = inventory.serialize();
         let parsed_inventory = Inventory::parse(buffer).unwrap();
@@ -207,14 +234,13 @@ mod tests {
     fn inventory_invalid_buffer() {
         let inventory = Inventory {
             inventory_type: InventoryType::Block,
-            hash: [
+            hash: Hash256::new([
                 220, 9, 210, 68, 121, 44, 33, 165, 243, 235, 28, 125, 43, 136, 29, 116, 190, 43,
-                124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220, 100, 200, 129,
-                233, 45, 56, 82, 56, 124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 46,
-            ]
-            .to_vec(),
+                124, 200, 30, 144, 40, 190, 229, 44, 93, 83, 110, 112, 225, 220,
+            ]),
         };
-        let buffer = inventory.serialize();
+        let mut buffer = inventory.serialize();
+        buffer.extend([100, 200, 129, 233, 45, 56, 82, 56]);
         let parsed_inventory = Inventory::parse(buffer);
         assert!(parsed_inventory.is_err());
     }
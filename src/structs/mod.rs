@@ -1,6 +1,15 @@
+pub mod balances;
+pub mod block_filter;
 pub mod block_header;
+pub mod bloom_filter;
+pub mod extended_key;
+pub mod hash256;
 pub mod inventory;
 pub mod movement;
+pub mod network_address;
 pub mod outpoint;
+pub mod script;
+pub mod transaction_details;
+pub mod transaction_summary;
 pub mod tx_input;
 pub mod tx_output;
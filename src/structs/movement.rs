@@ -1,4 +1,8 @@
-use crate::{error::CustomError, parser::BufferParser};
+use crate::{
+    error::CustomError,
+    parser::{BufferParser, Parser},
+    structs::{hash256::Hash256, script::classify::OutputType},
+};
 
 #[derive(Clone, Debug)]
 
@@ -6,28 +10,62 @@ use crate::{error::CustomError, parser::BufferParser};
 /// - tx_hash: Hash de la transaccion
 /// - value: Valor de la transaccion
 /// - block_hash: Hash del bloque en el que se encuentra la transaccion, en caso de una transaccion pendiente, no validada, este campo es None
+/// - address: Direccion de la otra parte: el primer destinatario si la wallet envia fondos, o la direccion de la wallet que los recibe
+/// - output_type: Tipo del output del que se obtuvo la direccion, None si la transaccion no tiene ese output
+/// - fee: Fee que pago la wallet al enviar la transaccion, None si la recibe o si no se conocen todos sus inputs
 pub struct Movement {
-    pub tx_hash: Vec<u8>,
+    pub tx_hash: Hash256,
     pub value: i64,
-    pub block_hash: Option<Vec<u8>>,
+    pub block_hash: Option<Hash256>,
+    pub address: Option<String>,
+    pub output_type: Option<OutputType>,
+    pub fee: Option<u64>,
 }
 
+/// Flag que indica que el movement serializado tiene block hash.
+const BLOCK_HASH_FLAG: u8 = 1;
+/// Flag que indica que el movement serializado tiene direccion.
+/// Los movements guardados antes de registrar la direccion solo usan BLOCK_HASH_FLAG, por lo que se siguen pudiendo leer.
+const ADDRESS_FLAG: u8 = 2;
+/// Flag que indica que el movement serializado tiene el tipo de output.
+const OUTPUT_TYPE_FLAG: u8 = 4;
+/// Flag que indica que el movement serializado tiene el fee.
+const FEE_FLAG: u8 = 8;
+
 impl Movement {
     /// Esta funcion se encarga de serializar un movement en un vector de bytes.
     pub fn serialize(&self) -> Vec<u8> {
         let mut buffer = Vec::new();
-        buffer.push(self.tx_hash.len() as u8);
-        buffer.extend(self.tx_hash.clone());
+        buffer.push(self.tx_hash.as_bytes().len() as u8);
+        buffer.extend(self.tx_hash.as_bytes());
         buffer.extend(self.value.to_le_bytes());
-        match self.block_hash.clone() {
-            Some(block_hash) => {
-                buffer.push(1);
-                buffer.push(block_hash.len() as u8);
-                buffer.extend(block_hash);
-            }
-            None => {
-                buffer.push(0);
-            }
+        let mut flags = 0;
+        if self.block_hash.is_some() {
+            flags |= BLOCK_HASH_FLAG;
+        }
+        if self.address.is_some() {
+            flags |= ADDRESS_FLAG;
+        }
+        if self.output_type.is_some() {
+            flags |= OUTPUT_TYPE_FLAG;
+        }
+        if self.fee.is_some() {
+            flags |= FEE_FLAG;
+        }
+        buffer.push(flags);
+        if let Some(block_hash) = &self.block_hash {
+            buffer.push(block_hash.as_bytes().len() as u8);
+            buffer.extend(block_hash.as_bytes());
+        }
+        if let Some(address) = &self.address {
+            buffer.push(address.len() as u8);
+            buffer.extend(address.as_bytes());
+        }
+        if let Some(output_type) = self.output_type {
+            buffer.push(output_type.to_byte());
+        }
+        if let Some(fee) = self.fee {
+            buffer.extend(fee.to_le_bytes());
         }
         buffer
     }
@@ -37,88 +75,145 @@ impl Movement {
     /// - Falla alguna extraccion del BufferParser
     pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
         let tx_hash_len = parser.extract_u8()? as usize;
-        let tx_hash = parser.extract_buffer(tx_hash_len)?.to_vec();
+        let tx_hash = Hash256::from_slice(parser.extract_buffer(tx_hash_len)?)?;
         let value = parser.extract_i64()?;
-        let block_hash_present = parser.extract_u8()?;
-        let block_hash = match block_hash_present {
+        let flags = parser.extract_u8()?;
+        if flags & !(BLOCK_HASH_FLAG | ADDRESS_FLAG | OUTPUT_TYPE_FLAG | FEE_FLAG) != 0 {
+            return Err(CustomError::Validation(String::from(
+                "Movement flags incorrectly formatted",
+            )));
+        }
+        let block_hash = match flags & BLOCK_HASH_FLAG {
             0 => None,
-            1 => {
+            _ => {
                 let block_hash_len = parser.extract_u8()? as usize;
-                Some(parser.extract_buffer(block_hash_len)?.to_vec())
+                Some(Hash256::from_slice(parser.extract_buffer(block_hash_len)?)?)
             }
+        };
+        let address = match flags & ADDRESS_FLAG {
+            0 => None,
             _ => {
-                return Err(CustomError::Validation(String::from(
-                    "Block hash presence incorrectly formatted",
-                )))
+                let address_len = parser.extract_u8()? as usize;
+                Some(parser.extract_string(address_len)?)
             }
         };
+        let output_type = match flags & OUTPUT_TYPE_FLAG {
+            0 => None,
+            _ => match OutputType::from_byte(parser.extract_u8()?) {
+                Some(output_type) => Some(output_type),
+                None => {
+                    return Err(CustomError::Validation(String::from(
+                        "Movement output type incorrectly formatted",
+                    )))
+                }
+            },
+        };
+        let fee = match flags & FEE_FLAG {
+            0 => None,
+            _ => Some(parser.extract_u64()?),
+        };
 
         Ok(Self {
             tx_hash,
             value,
             block_hash,
+            address,
+            output_type,
+            fee,
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parser::BufferParser, structs::movement::Movement};
+    use crate::{
+        parser::BufferParser,
+        structs::{hash256::Hash256, movement::Movement, script::classify::OutputType},
+    };
 
     #[test]
     fn movement_serialization() {
         let movement = Movement {
-            tx_hash: vec![
+            tx_hash: Hash256::new([
                 158, 58, 146, 241, 218, 207, 194, 196, 103, 192, 89, 27, 56, 110, 195, 138, 29,
                 177, 167, 47, 144, 191, 102, 68, 45, 70, 88, 237, 140, 224, 130, 115,
-            ],
+            ]),
             value: 500,
-            block_hash: Some(vec![
+            block_hash: Some(Hash256::new([
                 167, 131, 118, 190, 70, 199, 31, 2, 255, 135, 123, 36, 232, 182, 60, 178, 165, 110,
                 47, 11, 50, 1, 133, 106, 59, 195, 153, 210, 59, 21, 163, 41,
-            ]),
+            ])),
+            address: None,
+            output_type: None,
+            fee: None,
         };
         let serialized_movement = movement.serialize();
-        let mut parser = BufferParser::new(serialized_movement);
+        let mut parser = BufferParser::new(&serialized_movement);
         let parsed_movement = Movement::parse(&mut parser).unwrap();
         assert_eq!(
             parsed_movement.tx_hash,
-            vec![
+            Hash256::new([
                 158, 58, 146, 241, 218, 207, 194, 196, 103, 192, 89, 27, 56, 110, 195, 138, 29,
                 177, 167, 47, 144, 191, 102, 68, 45, 70, 88, 237, 140, 224, 130, 115
-            ]
+            ])
         );
         assert_eq!(parsed_movement.value, 500);
         assert_eq!(
             parsed_movement.block_hash,
-            Some(vec![
+            Some(Hash256::new([
                 167, 131, 118, 190, 70, 199, 31, 2, 255, 135, 123, 36, 232, 182, 60, 178, 165, 110,
                 47, 11, 50, 1, 133, 106, 59, 195, 153, 210, 59, 21, 163, 41
-            ])
+            ]))
         );
     }
 
     #[test]
     fn movement_without_block_hash() {
         let movement = Movement {
-            tx_hash: vec![
+            tx_hash: Hash256::new([
                 158, 58, 146, 241, 218, 207, 194, 196, 103, 192, 89, 27, 56, 110, 195, 138, 29,
                 177, 167, 47, 144, 191, 102, 68, 45, 70, 88, 237, 140, 224, 130, 115,
-            ],
+            ]),
             value: 500,
             block_hash: None,
+            address: None,
+            output_type: None,
+            fee: None,
         };
         let serialized_movement = movement.serialize();
-        let mut parser = BufferParser::new(serialized_movement);
+        let mut parser = BufferParser::new(&serialized_movement);
         let parsed_movement = Movement::parse(&mut parser).unwrap();
         assert_eq!(
             parsed_movement.tx_hash,
-            vec![
+            Hash256::new([
                 158, 58, 146, 241, 218, 207, 194, 196, 103, 192, 89, 27, 56, 110, 195, 138, 29,
                 177, 167, 47, 144, 191, 102, 68, 45, 70, 88, 237, 140, 224, 130, 115
-            ]
+            ])
         );
         assert_eq!(parsed_movement.value, 500);
         assert_eq!(parsed_movement.block_hash, None);
     }
+
+    #[test]
+    fn movement_with_address() {
+        let movement = Movement {
+            tx_hash: Hash256::new([1; 32]),
+            value: -700,
+            block_hash: Some(Hash256::new([2; 32])),
+            address: Some(String::from("mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun")),
+            output_type: Some(OutputType::P2PKH),
+            fee: Some(300),
+        };
+        let serialized = movement.serialize();
+        let mut parser = BufferParser::new(&serialized);
+        let parsed_movement = Movement::parse(&mut parser).unwrap();
+        assert_eq!(parsed_movement.block_hash, Some(Hash256::new([2; 32])));
+        assert_eq!(
+            parsed_movement.address,
+            Some(String::from("mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun"))
+        );
+        assert_eq!(parsed_movement.output_type, Some(OutputType::P2PKH));
+        assert_eq!(parsed_movement.fee, Some(300));
+        assert!(parser.is_empty());
+    }
 }
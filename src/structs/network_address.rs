@@ -0,0 +1,169 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV6};
+
+use crate::{
+    error::{CustomError, ParseError},
+    parser::{Parser, VarIntSerialize},
+};
+
+/// Identificador de red de una direccion IPv4 en addrv2 (BIP 155).
+const ADDRV2_NETWORK_IPV4: u8 = 0x01;
+/// Identificador de red de una direccion IPv6 en addrv2 (BIP 155).
+const ADDRV2_NETWORK_IPV6: u8 = 0x02;
+/// Tamaño maximo de una direccion en addrv2 (BIP 155).
+const ADDRV2_MAX_ADDRESS_SIZE: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+/// NetworkAddress representa la direccion de un nodo anunciada en los mensajes addr y addrv2.
+/// Los elementos son:
+/// - timestamp: Ultima vez que se vio al nodo activo.
+/// - services: Servicios que ofrece el nodo.
+/// - address: Direccion del nodo, las direcciones IPv4 se mapean a IPv6.
+pub struct NetworkAddress {
+    pub timestamp: u32,
+    pub services: u64,
+    pub address: SocketAddrV6,
+}
+
+impl NetworkAddress {
+    /// Serializa la direccion con el formato del mensaje addr.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        buffer.extend(self.timestamp.to_le_bytes());
+        buffer.extend(self.services.to_le_bytes());
+        buffer.extend(self.address.ip().octets());
+        buffer.extend(self.address.port().to_be_bytes());
+        buffer
+    }
+
+    /// Parsea una direccion con el formato del mensaje addr.
+    pub fn parse(parser: &mut impl Parser) -> Result<Self, CustomError> {
+        Ok(Self {
+            timestamp: parser.extract_u32()?,
+            services: parser.extract_u64()?,
+            address: parser.extract_address()?,
+        })
+    }
+
+    /// Serializa la direccion con el formato del mensaje addrv2.
+    /// Las direcciones IPv4 mapeadas a IPv6 se serializan como IPv4.
+    pub fn serialize_v2(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        buffer.extend(self.timestamp.to_le_bytes());
+        buffer.extend((self.services as usize).to_varint_bytes());
+        match self.address.ip().to_ipv4_mapped() {
+            Some(ipv4) => {
+                buffer.push(ADDRV2_NETWORK_IPV4);
+                buffer.extend(4_usize.to_varint_bytes());
+                buffer.extend(ipv4.octets());
+            }
+            None => {
+                buffer.push(ADDRV2_NETWORK_IPV6);
+                buffer.extend(16_usize.to_varint_bytes());
+                buffer.extend(self.address.ip().octets());
+            }
+        }
+        buffer.extend(self.address.port().to_be_bytes());
+        buffer
+    }
+
+    /// Parsea una direccion con el formato del mensaje addrv2.
+    /// Devuelve None si la direccion es de una red a la que el nodo no se puede conectar (Tor, I2P, CJDNS).
+    pub fn parse_v2(parser: &mut impl Parser) -> Result<Option<Self>, CustomError> {
+        let timestamp = parser.extract_u32()?;
+        let services = parser.extract_varint()?;
+        let network = parser.extract_u8()?;
+        let address_size = parser.extract_varint()? as usize;
+        if address_size > ADDRV2_MAX_ADDRESS_SIZE {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        let address = parser.extract_bytes(address_size)?;
+        let port = u16::from_be_bytes([parser.extract_u8()?, parser.extract_u8()?]);
+
+        let ip = match (network, address.len()) {
+            (ADDRV2_NETWORK_IPV4, 4) => {
+                Ipv4Addr::new(address[0], address[1], address[2], address[3]).to_ipv6_mapped()
+            }
+            (ADDRV2_NETWORK_IPV6, 16) => {
+                let mut octets = [0_u8; 16];
+                octets.copy_from_slice(&address);
+                Ipv6Addr::from(octets)
+            }
+            (ADDRV2_NETWORK_IPV4, _) | (ADDRV2_NETWORK_IPV6, _) => {
+                return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid))
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            timestamp,
+            services,
+            address: SocketAddrV6::new(ip, port, 0, 0),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::BufferParser;
+
+    fn ipv4_address() -> NetworkAddress {
+        NetworkAddress {
+            timestamp: 1680000000,
+            services: 0x409,
+            address: SocketAddrV6::new(Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped(), 18333, 0, 0),
+        }
+    }
+
+    #[test]
+    fn serialize_and_parse() {
+        let address = ipv4_address();
+        let serialized = address.serialize();
+        assert_eq!(serialized.len(), 30);
+
+        let parsed = NetworkAddress::parse(&mut BufferParser::new(&serialized)).unwrap();
+        assert_eq!(parsed, address);
+    }
+
+    #[test]
+    fn serialize_and_parse_v2() {
+        let address = ipv4_address();
+        let serialized = address.serialize_v2();
+        assert_eq!(
+            serialized,
+            vec![0, 0xc4, 0x22, 0x64, 0xfd, 0x09, 0x04, 1, 4, 10, 0, 0, 1, 0x47, 0x9d]
+        );
+        let parsed = NetworkAddress::parse_v2(&mut BufferParser::new(&serialized)).unwrap();
+        assert_eq!(parsed, Some(address));
+
+        let address = NetworkAddress {
+            timestamp: 1680000000,
+            services: 1,
+            address: SocketAddrV6::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 8333, 0, 0),
+        };
+        let parsed =
+            NetworkAddress::parse_v2(&mut BufferParser::new(&address.serialize_v2())).unwrap();
+        assert_eq!(parsed, Some(address));
+    }
+
+    #[test]
+    fn parse_v2_unsupported_network() {
+        // direccion Tor v3 de 32 bytes
+        let mut buffer = vec![0, 0xc4, 0x22, 0x64, 0x01, 0x04, 32];
+        buffer.extend([7; 32]);
+        buffer.extend([0x47, 0x9d]);
+
+        let mut parser = BufferParser::new(&buffer);
+        assert_eq!(NetworkAddress::parse_v2(&mut parser).unwrap(), None);
+        assert!(parser.is_empty());
+    }
+
+    #[test]
+    fn parse_v2_invalid_address_size() {
+        // direccion IPv4 de 16 bytes
+        let mut buffer = vec![0, 0xc4, 0x22, 0x64, 0x01, 0x01, 16];
+        buffer.extend([0; 16]);
+        buffer.extend([0x47, 0x9d]);
+        assert!(NetworkAddress::parse_v2(&mut BufferParser::new(&buffer)).is_err());
+    }
+}
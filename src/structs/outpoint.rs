@@ -1,4 +1,8 @@
-use crate::{error::CustomError, parser::BufferParser};
+use crate::{
+    error::CustomError,
+    parser::{BufferParser, Parser},
+    structs::hash256::Hash256,
+};
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 
@@ -6,7 +10,7 @@ use crate::{error::CustomError, parser::BufferParser};
 /// - hash: Hash de la transaccion
 /// - index: Indice de la transaccion
 pub struct OutPoint {
-    pub hash: Vec<u8>,
+    pub hash: Hash256,
     pub index: u32,
 }
 
@@ -14,15 +18,15 @@ impl OutPoint {
     /// Esta funcion se encarga de serializar un outpoint en un vector de bytes.
     pub fn serialize(&self) -> Vec<u8> {
         let mut buffer: Vec<u8> = vec![];
-        buffer.extend(self.hash.clone());
+        buffer.extend(self.hash.as_bytes());
         buffer.extend(self.index.to_le_bytes());
         buffer
     }
 
     /// Esta funcion se encarga de parsear un outpoint a partir de un vector de bytes.
     pub fn parse(buffer: Vec<u8>) -> Result<Self, CustomError> {
-        let mut parser = BufferParser::new(buffer);
-        let hash = parser.extract_buffer(32)?.to_vec();
+        let mut parser = BufferParser::new(&buffer);
+        let hash = parser.extract_hash32()?.into();
         let index = parser.extract_u32()?;
         Ok(Self { hash, index })
     }
@@ -30,15 +34,15 @@ impl OutPoint {
 
 #[cfg(test)]
 mod tests {
-    use crate::structs::outpoint::OutPoint;
+    use crate::structs::{hash256::Hash256, outpoint::OutPoint};
 
     #[test]
     fn serialize_and_parse() {
         let outpoint = OutPoint {
-            hash: vec![
+            hash: Hash256::new([
                 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 1, 2, 3, 4, 5, 6, 7,
                 8, 9, 10, 1, 2,
-            ],
+            ]),
             index: 0,
         };
         let serialized = outpoint.serialize();
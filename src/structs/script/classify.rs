@@ -0,0 +1,284 @@
+use bitcoin_hashes::{hash160, Hash};
+
+use super::{
+    is_public_key, Script, ScriptElement, HASH160_LEN, OP_0, OP_1, OP_16, OP_CHECKSIG, OP_DUP,
+    OP_EQUALVERIFY, OP_HASH160, OP_RETURN,
+};
+
+/// Largo en bytes de los witness programs P2WSH y P2TR.
+const WITNESS_PROGRAM_32_LEN: usize = 32;
+
+/// ScriptType es el tipo de un script pubkey, junto con los datos que identifican a su destinatario.
+/// - P2PK: Public key que puede gastar el output.
+/// - P2PKH: Hash de la public key que puede gastar el output.
+/// - P2SH: Hash del redeem script.
+/// - P2WPKH: Hash de la public key, witness program de 20 bytes de version 0.
+/// - P2WSH: Hash SHA256 del witness script, witness program de 32 bytes de version 0.
+/// - P2TR: Output key taproot, witness program de 32 bytes de version 1.
+/// - NullData: Datos de un output OP_RETURN, que no se puede gastar.
+/// - NonStandard: Cualquier otro script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptType {
+    P2PK(Vec<u8>),
+    P2PKH(Vec<u8>),
+    P2SH(Vec<u8>),
+    P2WPKH(Vec<u8>),
+    P2WSH(Vec<u8>),
+    P2TR(Vec<u8>),
+    NullData(Vec<u8>),
+    NonStandard,
+}
+
+impl ScriptType {
+    /// Clasifica un script pubkey segun los templates estandar.
+    /// Los scripts que no se pueden parsear o que no usan los pushes minimos del template son NonStandard,
+    /// salvo los OP_RETURN, que pueden tener cualquier push.
+    pub fn classify(script_pubkey: &[u8]) -> Self {
+        let Ok(script) = Script::parse(script_pubkey) else { return Self::NonStandard };
+        if let [ScriptElement::Op(OP_RETURN), data @ ..] = script.elements.as_slice() {
+            return classify_null_data(data);
+        }
+        if script.serialize() != script_pubkey {
+            return Self::NonStandard;
+        }
+
+        match script.elements.as_slice() {
+            [ScriptElement::Push(pubkey), ScriptElement::Op(OP_CHECKSIG)]
+                if is_public_key(pubkey) =>
+            {
+                Self::P2PK(pubkey.clone())
+            }
+            [ScriptElement::Op(OP_DUP), ScriptElement::Op(OP_HASH160), ScriptElement::Push(pubkey_hash), ScriptElement::Op(OP_EQUALVERIFY), ScriptElement::Op(OP_CHECKSIG)]
+                if pubkey_hash.len() == HASH160_LEN =>
+            {
+                Self::P2PKH(pubkey_hash.clone())
+            }
+            [ScriptElement::Op(OP_0), ScriptElement::Push(program)] => match program.len() {
+                HASH160_LEN => Self::P2WPKH(program.clone()),
+                WITNESS_PROGRAM_32_LEN => Self::P2WSH(program.clone()),
+                _ => Self::NonStandard,
+            },
+            [ScriptElement::Op(OP_1), ScriptElement::Push(program)]
+                if program.len() == WITNESS_PROGRAM_32_LEN =>
+            {
+                Self::P2TR(program.clone())
+            }
+            _ => match script.p2sh_script_hash() {
+                Some(script_hash) => Self::P2SH(script_hash.to_vec()),
+                None => Self::NonStandard,
+            },
+        }
+    }
+
+    /// Devuelve el hash160 que identifica al dueño del output, como se guarda en las wallets:
+    /// el hash de la public key para P2PK, P2PKH y P2WPKH, y el hash del redeem script para P2SH.
+    /// Devuelve None para los demas tipos.
+    pub fn owner_hash(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::P2PK(pubkey) => Some(hash160::Hash::hash(pubkey).to_byte_array().to_vec()),
+            Self::P2PKH(hash) | Self::P2WPKH(hash) | Self::P2SH(hash) => Some(hash.clone()),
+            _ => None,
+        }
+    }
+
+    /// Devuelve el tipo de output, sin sus datos.
+    pub fn output_type(&self) -> OutputType {
+        match self {
+            Self::P2PK(_) => OutputType::P2PK,
+            Self::P2PKH(_) => OutputType::P2PKH,
+            Self::P2SH(_) => OutputType::P2SH,
+            Self::P2WPKH(_) => OutputType::P2WPKH,
+            Self::P2WSH(_) => OutputType::P2WSH,
+            Self::P2TR(_) => OutputType::P2TR,
+            Self::NullData(_) => OutputType::NullData,
+            Self::NonStandard => OutputType::NonStandard,
+        }
+    }
+}
+
+/// OutputType es el tipo de un output sin sus datos, para guardarlo en el historial y mostrarlo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputType {
+    P2PK,
+    P2PKH,
+    P2SH,
+    P2WPKH,
+    P2WSH,
+    P2TR,
+    NullData,
+    NonStandard,
+}
+
+impl OutputType {
+    /// Devuelve el nombre con el que se muestra el tipo de output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::P2PK => "P2PK",
+            Self::P2PKH => "P2PKH",
+            Self::P2SH => "P2SH",
+            Self::P2WPKH => "P2WPKH",
+            Self::P2WSH => "P2WSH",
+            Self::P2TR => "P2TR",
+            Self::NullData => "OP_RETURN",
+            Self::NonStandard => "Non-standard",
+        }
+    }
+
+    /// Devuelve el byte con el que se serializa el tipo de output.
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::NonStandard => 0,
+            Self::P2PK => 1,
+            Self::P2PKH => 2,
+            Self::P2SH => 3,
+            Self::P2WPKH => 4,
+            Self::P2WSH => 5,
+            Self::P2TR => 6,
+            Self::NullData => 7,
+        }
+    }
+
+    /// Devuelve el tipo de output serializado con el byte recibido, o None si no corresponde a ninguno.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::NonStandard),
+            1 => Some(Self::P2PK),
+            2 => Some(Self::P2PKH),
+            3 => Some(Self::P2SH),
+            4 => Some(Self::P2WPKH),
+            5 => Some(Self::P2WSH),
+            6 => Some(Self::P2TR),
+            7 => Some(Self::NullData),
+            _ => None,
+        }
+    }
+}
+
+/// Clasifica los elementos que siguen a OP_RETURN: si son todos pushes es un output de datos, sino es NonStandard.
+fn classify_null_data(elements: &[ScriptElement]) -> ScriptType {
+    let mut data = vec![];
+    for element in elements {
+        match element {
+            ScriptElement::Push(push) => data.extend(push),
+            ScriptElement::Op(opcode) if *opcode == OP_0 || (OP_1..=OP_16).contains(opcode) => (),
+            ScriptElement::Op(_) => return ScriptType::NonStandard,
+        }
+    }
+    ScriptType::NullData(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const PUBKEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const PUBKEY_HASH: &str = "751e76e8199196d454941c45d1b3a323f1433bd6";
+
+    #[test]
+    fn standard_scripts_are_classified() {
+        let pubkey_hash = from_hex(PUBKEY_HASH);
+        let mut p2pk = vec![33];
+        p2pk.extend(from_hex(PUBKEY));
+        p2pk.push(OP_CHECKSIG);
+        let mut p2wsh = vec![OP_0, 32];
+        p2wsh.extend([7; 32]);
+        let mut p2tr = vec![OP_1, 32];
+        p2tr.extend([9; 32]);
+
+        assert_eq!(
+            ScriptType::classify(&p2pk),
+            ScriptType::P2PK(from_hex(PUBKEY))
+        );
+        assert_eq!(
+            ScriptType::classify(&Script::p2pkh(&pubkey_hash).serialize()),
+            ScriptType::P2PKH(pubkey_hash.clone())
+        );
+        assert_eq!(
+            ScriptType::classify(&Script::p2sh(&[3; 20]).serialize()),
+            ScriptType::P2SH(vec![3; 20])
+        );
+        assert_eq!(
+            ScriptType::classify(&Script::p2wpkh(&pubkey_hash).serialize()),
+            ScriptType::P2WPKH(pubkey_hash)
+        );
+        assert_eq!(ScriptType::classify(&p2wsh), ScriptType::P2WSH(vec![7; 32]));
+        assert_eq!(ScriptType::classify(&p2tr), ScriptType::P2TR(vec![9; 32]));
+    }
+
+    #[test]
+    fn null_data_scripts_are_classified() {
+        assert_eq!(
+            ScriptType::classify(&[OP_RETURN]),
+            ScriptType::NullData(vec![])
+        );
+        assert_eq!(
+            ScriptType::classify(&[OP_RETURN, 0x02, 0xca, 0xfe, 0x4c, 0x01, 0x00]),
+            ScriptType::NullData(vec![0xca, 0xfe, 0x00])
+        );
+        assert_eq!(
+            ScriptType::classify(&[OP_RETURN, OP_DUP]),
+            ScriptType::NonStandard
+        );
+        assert_eq!(
+            ScriptType::classify(&[OP_RETURN, 0x02]).output_type(),
+            OutputType::NonStandard
+        );
+    }
+
+    #[test]
+    fn non_standard_scripts_are_rejected() {
+        let mut non_minimal_p2pkh = vec![OP_DUP, OP_HASH160, 0x4c, 20];
+        non_minimal_p2pkh.extend([1; 20]);
+        non_minimal_p2pkh.extend([OP_EQUALVERIFY, OP_CHECKSIG]);
+        let mut witness_v0_21 = vec![OP_0, 21];
+        witness_v0_21.extend([1; 21]);
+        let mut p2pk_invalid_key = vec![33, 0x05];
+        p2pk_invalid_key.extend([1; 32]);
+        p2pk_invalid_key.push(OP_CHECKSIG);
+
+        for script_pubkey in [
+            vec![],
+            non_minimal_p2pkh,
+            witness_v0_21,
+            p2pk_invalid_key,
+            vec![OP_DUP, OP_HASH160, 20, 1],
+        ] {
+            assert_eq!(
+                ScriptType::classify(&script_pubkey),
+                ScriptType::NonStandard
+            );
+        }
+    }
+
+    #[test]
+    fn owner_hash_of_scripts() {
+        let pubkey_hash = from_hex(PUBKEY_HASH);
+        assert_eq!(
+            ScriptType::P2PK(from_hex(PUBKEY)).owner_hash(),
+            Some(pubkey_hash.clone())
+        );
+        assert_eq!(
+            ScriptType::P2WPKH(pubkey_hash.clone()).owner_hash(),
+            Some(pubkey_hash)
+        );
+        assert_eq!(ScriptType::P2WSH(vec![7; 32]).owner_hash(), None);
+        assert_eq!(ScriptType::NullData(vec![]).owner_hash(), None);
+    }
+
+    #[test]
+    fn output_type_serialization() {
+        for byte in 0..8 {
+            let output_type = OutputType::from_byte(byte).unwrap();
+            assert_eq!(output_type.to_byte(), byte);
+        }
+        assert_eq!(OutputType::from_byte(8), None);
+        assert_eq!(OutputType::NullData.name(), "OP_RETURN");
+    }
+}
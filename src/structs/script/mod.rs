@@ -0,0 +1,325 @@
+use bitcoin_hashes::{hash160, Hash};
+
+use crate::{
+    error::CustomError,
+    parser::{BufferParser, Parser},
+};
+
+pub mod classify;
+
+pub const OP_0: u8 = 0x00;
+pub const OP_PUSHDATA1: u8 = 0x4c;
+pub const OP_PUSHDATA2: u8 = 0x4d;
+pub const OP_PUSHDATA4: u8 = 0x4e;
+pub const OP_1: u8 = 0x51;
+pub const OP_16: u8 = 0x60;
+pub const OP_RETURN: u8 = 0x6a;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_EQUAL: u8 = 0x87;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_HASH160: u8 = 0xa9;
+pub const OP_CHECKSIG: u8 = 0xac;
+pub const OP_CHECKMULTISIG: u8 = 0xae;
+
+/// Cantidad maxima de claves de un script multisig estandar.
+pub const MAX_MULTISIG_KEYS: usize = 16;
+/// Tamaño maximo en bytes de un elemento del stack, y por lo tanto de un redeem script P2SH.
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// Tamaño maximo en bytes de los datos de un output OP_RETURN para que los nodos lo retransmitan.
+pub const MAX_NULL_DATA_SIZE: usize = 80;
+/// Largo en bytes de los hashes de los scripts P2PKH, P2WPKH y P2SH.
+const HASH160_LEN: usize = 20;
+
+/// ScriptElement es un elemento de un script.
+/// - Op: Opcode que no agrega datos al stack, por ejemplo OP_DUP.
+/// - Push: Datos que se agregan al stack, se serializan con el opcode de push minimo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptElement {
+    Op(u8),
+    Push(Vec<u8>),
+}
+
+/// Script es un bitcoin script representado como una secuencia de opcodes y datos,
+/// en lugar de un vector de bytes opaco.
+/// Los elementos son:
+/// - elements: Elementos del script, en el orden en que se ejecutan.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Script {
+    pub elements: Vec<ScriptElement>,
+}
+
+impl Script {
+    /// Inicializa un script con los elementos recibidos.
+    pub fn new(elements: Vec<ScriptElement>) -> Self {
+        Self { elements }
+    }
+
+    /// Genera el script P2PKH de un hash de public key:
+    /// OP_DUP OP_HASH160 <pubkey hash> OP_EQUALVERIFY OP_CHECKSIG.
+    pub fn p2pkh(pubkey_hash: &[u8]) -> Self {
+        Self::new(vec![
+            ScriptElement::Op(OP_DUP),
+            ScriptElement::Op(OP_HASH160),
+            ScriptElement::Push(pubkey_hash.to_vec()),
+            ScriptElement::Op(OP_EQUALVERIFY),
+            ScriptElement::Op(OP_CHECKSIG),
+        ])
+    }
+
+    /// Genera el script P2WPKH de un hash de public key: OP_0 <pubkey hash>.
+    pub fn p2wpkh(pubkey_hash: &[u8]) -> Self {
+        Self::new(vec![
+            ScriptElement::Op(OP_0),
+            ScriptElement::Push(pubkey_hash.to_vec()),
+        ])
+    }
+
+    /// Genera el script de un witness program de cualquier version: OP_n <witness program>, con n entre 0 y 16.
+    pub fn witness_program(witness_version: u8, program: &[u8]) -> Self {
+        let version = match witness_version {
+            0 => OP_0,
+            witness_version => small_integer_op(witness_version),
+        };
+        Self::new(vec![
+            ScriptElement::Op(version),
+            ScriptElement::Push(program.to_vec()),
+        ])
+    }
+
+    /// Genera el script P2SH de un hash de redeem script: OP_HASH160 <script hash> OP_EQUAL.
+    pub fn p2sh(script_hash: &[u8]) -> Self {
+        Self::new(vec![
+            ScriptElement::Op(OP_HASH160),
+            ScriptElement::Push(script_hash.to_vec()),
+            ScriptElement::Op(OP_EQUAL),
+        ])
+    }
+
+    /// Genera el script de un output de datos, que no se puede gastar: OP_RETURN <data>.
+    pub fn null_data(data: &[u8]) -> Self {
+        Self::new(vec![
+            ScriptElement::Op(OP_RETURN),
+            ScriptElement::Push(data.to_vec()),
+        ])
+    }
+
+    /// Genera un redeem script multisig m-of-n: OP_m <pubkey 1> ... <pubkey n> OP_n OP_CHECKMULTISIG.
+    /// Devuelve CustomError si no se cumple 1 <= m <= n <= MAX_MULTISIG_KEYS, si alguna public key no esta serializada
+    /// en formato comprimido o sin comprimir, o si el script excede MAX_SCRIPT_ELEMENT_SIZE y no se puede usar en P2SH.
+    pub fn multisig(required: u8, pubkeys: &[Vec<u8>]) -> Result<Self, CustomError> {
+        if required == 0 || required as usize > pubkeys.len() || pubkeys.len() > MAX_MULTISIG_KEYS {
+            return Err(CustomError::Validation(String::from(
+                "Multisig must require between 1 and the amount of public keys, up to 16",
+            )));
+        }
+        if !pubkeys.iter().all(|pubkey| is_public_key(pubkey)) {
+            return Err(CustomError::Validation(String::from(
+                "Multisig public key incorrectly formatted",
+            )));
+        }
+
+        let mut elements = vec![ScriptElement::Op(small_integer_op(required))];
+        elements.extend(pubkeys.iter().cloned().map(ScriptElement::Push));
+        elements.push(ScriptElement::Op(small_integer_op(pubkeys.len() as u8)));
+        elements.push(ScriptElement::Op(OP_CHECKMULTISIG));
+
+        let script = Self::new(elements);
+        if script.serialize().len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(CustomError::Validation(String::from(
+                "Multisig redeem script is too long",
+            )));
+        }
+        Ok(script)
+    }
+
+    /// Parsea un script a partir de sus bytes.
+    /// Devuelve CustomError si algun push tiene menos datos que los que indica.
+    pub fn parse(bytes: &[u8]) -> Result<Self, CustomError> {
+        let mut parser = BufferParser::new(bytes);
+        let mut elements = vec![];
+        while !parser.is_empty() {
+            let opcode = parser.extract_u8()?;
+            let push_len = match opcode {
+                0x01..=0x4b => opcode as usize,
+                OP_PUSHDATA1 => parser.extract_u8()? as usize,
+                OP_PUSHDATA2 => parser.extract_u16()? as usize,
+                OP_PUSHDATA4 => parser.extract_u32()? as usize,
+                _ => {
+                    elements.push(ScriptElement::Op(opcode));
+                    continue;
+                }
+            };
+            elements.push(ScriptElement::Push(
+                parser.extract_buffer(push_len)?.to_vec(),
+            ));
+        }
+        Ok(Self { elements })
+    }
+
+    /// Serializa el script. Cada push se serializa con el opcode de push mas corto para su largo.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        for element in &self.elements {
+            match element {
+                ScriptElement::Op(opcode) => buffer.push(*opcode),
+                ScriptElement::Push(data) => buffer.extend(serialize_push(data)),
+            }
+        }
+        buffer
+    }
+
+    /// Devuelve el hash160 del script serializado, que identifica a un redeem script en su script P2SH.
+    pub fn hash160(&self) -> Vec<u8> {
+        hash160::Hash::hash(&self.serialize())
+            .to_byte_array()
+            .to_vec()
+    }
+
+    /// Devuelve el hash del redeem script si el script es P2SH.
+    pub fn p2sh_script_hash(&self) -> Option<&[u8]> {
+        match self.elements.as_slice() {
+            [ScriptElement::Op(OP_HASH160), ScriptElement::Push(script_hash), ScriptElement::Op(OP_EQUAL)]
+                if script_hash.len() == HASH160_LEN =>
+            {
+                Some(script_hash)
+            }
+            _ => None,
+        }
+    }
+
+    /// Devuelve la cantidad de firmas requeridas y las public keys si el script es un multisig m-of-n.
+    pub fn multisig_keys(&self) -> Option<(u8, Vec<Vec<u8>>)> {
+        let [ScriptElement::Op(required), keys @ .., ScriptElement::Op(total), ScriptElement::Op(OP_CHECKMULTISIG)] =
+            self.elements.as_slice()
+        else {
+            return None;
+        };
+        let required = small_integer(*required)?;
+        let total = small_integer(*total)?;
+        let pubkeys: Vec<Vec<u8>> = keys
+            .iter()
+            .map(|key| match key {
+                ScriptElement::Push(pubkey) if is_public_key(pubkey) => Some(pubkey.clone()),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        if required > total || total as usize != pubkeys.len() {
+            return None;
+        }
+        Some((required, pubkeys))
+    }
+}
+
+/// Devuelve el opcode que agrega al stack un entero entre 1 y 16 (OP_1 a OP_16).
+fn small_integer_op(value: u8) -> u8 {
+    OP_1 + value - 1
+}
+
+/// Devuelve el entero que agrega al stack un opcode entre OP_1 y OP_16.
+fn small_integer(opcode: u8) -> Option<u8> {
+    (OP_1..=OP_16).contains(&opcode).then(|| opcode - OP_1 + 1)
+}
+
+/// Devuelve true si los bytes tienen el formato de una public key comprimida (33 bytes) o sin comprimir (65 bytes).
+fn is_public_key(pubkey: &[u8]) -> bool {
+    matches!(
+        (pubkey.len(), pubkey.first()),
+        (33, Some(0x02 | 0x03)) | (65, Some(0x04))
+    )
+}
+
+/// Serializa un push de datos con el opcode mas corto para su largo.
+fn serialize_push(data: &[u8]) -> Vec<u8> {
+    let mut buffer = match data.len() {
+        0 => vec![OP_0],
+        len @ 0x01..=0x4b => vec![len as u8],
+        len @ 0x4c..=0xff => vec![OP_PUSHDATA1, len as u8],
+        len @ 0x100..=0xffff => {
+            let mut buffer = vec![OP_PUSHDATA2];
+            buffer.extend((len as u16).to_le_bytes());
+            buffer
+        }
+        len => {
+            let mut buffer = vec![OP_PUSHDATA4];
+            buffer.extend((len as u32).to_le_bytes());
+            buffer
+        }
+    };
+    buffer.extend(data);
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const PUBKEY_1: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const PUBKEY_2: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const PUBKEY_3: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    #[test]
+    fn p2pkh_script_roundtrip() {
+        let pubkey_hash = from_hex("1aa0cd1cbea6e7458a7abad512a9d9ea1afb225e");
+        let script = Script::p2pkh(&pubkey_hash);
+        let bytes = script.serialize();
+        assert_eq!(
+            bytes,
+            from_hex("76a9141aa0cd1cbea6e7458a7abad512a9d9ea1afb225e88ac")
+        );
+        assert_eq!(Script::parse(&bytes).unwrap(), script);
+    }
+
+    #[test]
+    fn multisig_redeem_script() {
+        let pubkeys = vec![from_hex(PUBKEY_1), from_hex(PUBKEY_2), from_hex(PUBKEY_3)];
+        let script = Script::multisig(2, &pubkeys).unwrap();
+        let bytes = script.serialize();
+
+        assert_eq!(bytes.len(), 3 + 3 * 34);
+        assert_eq!(bytes[0], 0x52);
+        assert_eq!(bytes[1], 33);
+        assert_eq!(&bytes[bytes.len() - 2..], &[0x53, OP_CHECKMULTISIG]);
+
+        let parsed = Script::parse(&bytes).unwrap();
+        assert_eq!(parsed.multisig_keys(), Some((2, pubkeys.clone())));
+        assert_eq!(Script::p2pkh(&[0; 20]).multisig_keys(), None);
+
+        let p2sh = Script::p2sh(&script.hash160());
+        assert_eq!(p2sh.serialize().len(), 23);
+        assert_eq!(
+            Script::parse(&p2sh.serialize()).unwrap().p2sh_script_hash(),
+            Some(script.hash160().as_slice())
+        );
+    }
+
+    #[test]
+    fn invalid_multisig_is_rejected() {
+        let pubkeys = vec![from_hex(PUBKEY_1), from_hex(PUBKEY_2)];
+        assert!(Script::multisig(0, &pubkeys).is_err());
+        assert!(Script::multisig(3, &pubkeys).is_err());
+        assert!(Script::multisig(1, &[vec![0x02; 20]]).is_err());
+        assert!(Script::multisig(1, &vec![from_hex(PUBKEY_1); 17]).is_err());
+    }
+
+    #[test]
+    fn pushes_use_the_minimal_opcode() {
+        assert_eq!(serialize_push(&[]), vec![OP_0]);
+        assert_eq!(serialize_push(&[0xab; 75])[0], 75);
+        assert_eq!(serialize_push(&[0xab; 76])[..2], [OP_PUSHDATA1, 76]);
+        assert_eq!(
+            serialize_push(&[0xab; 300])[..3],
+            [OP_PUSHDATA2, 0x2c, 0x01]
+        );
+
+        let script = Script::new(vec![ScriptElement::Push(vec![0xab; 300])]);
+        assert_eq!(Script::parse(&script.serialize()).unwrap(), script);
+        assert!(Script::parse(&[OP_PUSHDATA1, 10, 0x01]).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+
+use crate::{
+    error::{CustomError, ParseError},
+    message::Message,
+    messages::transaction::Transaction,
+    parser::BufferParser,
+    structs::{
+        hash256::Hash256,
+        outpoint::OutPoint,
+        script::classify::{OutputType, ScriptType},
+    },
+    utils::{bytes_to_hex, hex_to_bytes},
+    wallet::get_address,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+/// InputDetails es el detalle de un input de una transaccion decodificada.
+/// Los elementos son:
+/// - previous_output: Outpoint que gasta el input.
+/// - script_sig: Script sig del input.
+/// - witness: Elementos del witness del input, vacio si no tiene.
+/// - sequence: Sequence del input.
+pub struct InputDetails {
+    pub previous_output: OutPoint,
+    pub script_sig: Vec<u8>,
+    pub witness: Vec<Vec<u8>>,
+    pub sequence: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// OutputDetails es el detalle de un output de una transaccion decodificada.
+/// Los elementos son:
+/// - value: Valor del output en satoshis.
+/// - script_pubkey: Script pubkey del output.
+/// - output_type: Tipo del script pubkey.
+/// - address: Direccion a la que envia fondos el output, si su tipo tiene una.
+/// - data: Datos del output si es un OP_RETURN.
+pub struct OutputDetails {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+    pub output_type: OutputType,
+    pub address: Option<String>,
+    pub data: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// TransactionDetails es el detalle de una transaccion serializada, para inspeccionarla,
+/// por ejemplo para entender por que un peer rechazo su difusion.
+/// Los elementos son:
+/// - txid: Hash de la transaccion sin witness.
+/// - wtxid: Hash de la transaccion con witness, igual al txid si no tiene.
+/// - version: Version de la transaccion.
+/// - lock_time: Lock time de la transaccion.
+/// - size: Tamaño de la transaccion serializada, en bytes.
+/// - vsize: Tamaño virtual de la transaccion (BIP 141).
+/// - replaceable: Si la transaccion indica que puede ser reemplazada (BIP 125).
+/// - inputs: Detalle de cada input, en orden.
+/// - outputs: Detalle de cada output, en orden.
+pub struct TransactionDetails {
+    pub txid: Hash256,
+    pub wtxid: Hash256,
+    pub version: u32,
+    pub lock_time: u32,
+    pub size: usize,
+    pub vsize: u64,
+    pub replaceable: bool,
+    pub inputs: Vec<InputDetails>,
+    pub outputs: Vec<OutputDetails>,
+}
+
+impl TransactionDetails {
+    /// Decodifica una transaccion serializada, con o sin witness.
+    /// Devuelve CustomError si los bytes no son una transaccion valida o sobran bytes luego de ella.
+    pub fn decode(bytes: &[u8]) -> Result<Self, CustomError> {
+        let mut parser = BufferParser::new(bytes);
+        let transaction = Transaction::parse_from_parser(&mut parser)?;
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self::from(&transaction))
+    }
+}
+
+impl From<&Transaction> for TransactionDetails {
+    fn from(transaction: &Transaction) -> Self {
+        let inputs = transaction
+            .inputs
+            .iter()
+            .map(|input| InputDetails {
+                previous_output: input.previous_output.clone(),
+                script_sig: input.script_sig.clone(),
+                witness: input.witness.clone(),
+                sequence: input.sequence,
+            })
+            .collect();
+        let outputs = transaction
+            .outputs
+            .iter()
+            .map(|output| {
+                let script_type = ScriptType::classify(&output.script_pubkey);
+                OutputDetails {
+                    value: output.value,
+                    script_pubkey: output.script_pubkey.clone(),
+                    output_type: script_type.output_type(),
+                    address: get_address(&output.script_pubkey).ok(),
+                    data: match script_type {
+                        ScriptType::NullData(data) => Some(data),
+                        _ => None,
+                    },
+                }
+            })
+            .collect();
+        Self {
+            txid: transaction.hash(),
+            wtxid: transaction.wtxid(),
+            version: transaction.version,
+            lock_time: transaction.lock_time,
+            size: transaction.serialize().len(),
+            vsize: transaction.vsize(),
+            replaceable: transaction.signals_rbf(),
+            inputs,
+            outputs,
+        }
+    }
+}
+
+impl fmt::Display for TransactionDetails {
+    /// Muestra el detalle de la transaccion, con un input o un output por linea.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Txid: {}", self.txid)?;
+        writeln!(f, "Wtxid: {}", self.wtxid)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Lock time: {}", self.lock_time)?;
+        writeln!(f, "Size: {} bytes ({} vB)", self.size, self.vsize)?;
+        writeln!(
+            f,
+            "Replaceable: {}",
+            if self.replaceable { "yes" } else { "no" }
+        )?;
+        writeln!(f, "Inputs:")?;
+        for (i, input) in self.inputs.iter().enumerate() {
+            writeln!(
+                f,
+                "  {}: {}:{} (sequence {:#010x}, {} witness items)",
+                i,
+                input.previous_output.hash,
+                input.previous_output.index,
+                input.sequence,
+                input.witness.len()
+            )?;
+        }
+        write!(f, "Outputs:")?;
+        for (i, output) in self.outputs.iter().enumerate() {
+            write!(
+                f,
+                "\n  {}: {} sat, {}",
+                i,
+                output.value,
+                output.output_type.name()
+            )?;
+            if let Some(address) = &output.address {
+                write!(f, ", {}", address)?;
+            }
+            if let Some(data) = &output.data {
+                write!(f, ", data {}", bytes_to_hex(data))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodifica los bytes de una transaccion serializada, por ejemplo leidos de un archivo.
+/// Devuelve CustomError si los bytes no son una transaccion valida.
+pub fn decode_raw_transaction(bytes: &[u8]) -> Result<TransactionDetails, CustomError> {
+    TransactionDetails::decode(bytes)
+}
+
+/// Decodifica una transaccion serializada en hexadecimal, ignorando los espacios al principio y al final.
+/// Devuelve CustomError si el texto no es hexadecimal o no es una transaccion valida.
+pub fn decode_raw_transaction_hex(hex: &str) -> Result<TransactionDetails, CustomError> {
+    let Some(bytes) = hex_to_bytes(hex.trim()) else {
+        return Err(CustomError::Validation(String::from(
+            "The transaction is not valid hex",
+        )));
+    };
+    decode_raw_transaction(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Transaccion de los test vectors de BIP 143, con un input P2PK y otro P2WPKH.
+    const SEGWIT_TX: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+    #[test]
+    fn decode_segwit_transaction() {
+        let details = decode_raw_transaction_hex(&format!(" {SEGWIT_TX}\n")).unwrap();
+        assert_eq!(
+            decode_raw_transaction(&hex_to_bytes(SEGWIT_TX).unwrap()).unwrap(),
+            details
+        );
+        assert_eq!(
+            details.txid.to_string(),
+            "e8151a2af31c368a35053ddd4bdb285a8595c769a3ad83e0fa02314a602d4609"
+        );
+        assert_ne!(details.wtxid, details.txid);
+        assert_eq!(details.version, 1);
+        assert_eq!(details.lock_time, 0x11);
+        assert_eq!(details.size, SEGWIT_TX.len() / 2);
+        assert!(details.replaceable);
+
+        assert_eq!(details.inputs.len(), 2);
+        assert_eq!(details.inputs[0].sequence, 0xffffffee);
+        assert!(details.inputs[0].witness.is_empty());
+        assert_eq!(details.inputs[1].previous_output.index, 1);
+        assert_eq!(details.inputs[1].witness.len(), 2);
+
+        assert_eq!(details.outputs.len(), 2);
+        assert_eq!(details.outputs[0].value, 112_340_000);
+        assert_eq!(details.outputs[1].value, 223_450_000);
+        for output in &details.outputs {
+            assert_eq!(output.output_type, OutputType::P2PKH);
+            assert!(output.address.is_some());
+            assert!(output.data.is_none());
+        }
+        assert!(details.to_string().contains("  1: 223450000 sat, P2PKH, "));
+    }
+
+    #[test]
+    fn decode_invalid_transaction() {
+        assert!(decode_raw_transaction_hex("not hex").is_err());
+        assert!(decode_raw_transaction_hex(&SEGWIT_TX[..SEGWIT_TX.len() - 2]).is_err());
+        assert!(decode_raw_transaction_hex(&format!("{SEGWIT_TX}00")).is_err());
+        assert!(decode_raw_transaction(&[1, 0, 0, 0]).is_err());
+    }
+}
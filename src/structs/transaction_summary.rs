@@ -0,0 +1,100 @@
+use std::fmt;
+
+use crate::utils::bytes_to_hex;
+
+use super::outpoint::OutPoint;
+
+#[derive(Debug, Clone, PartialEq)]
+/// TransactionSummary es el detalle de una transaccion de la wallet antes de difundirla, para que el usuario la revise.
+/// Los elementos son:
+/// - inputs: UTXO que gasta la transaccion y su valor.
+/// - outputs: Direcciones a las que se envian fondos y el valor enviado a cada una, en el orden de los outputs de la transaccion.
+/// - change: Direccion de cambio de la wallet y el valor que vuelve a ella, si la transaccion tiene cambio.
+/// - data: Datos del output OP_RETURN, si lo tiene.
+/// - fee: Fee de la transaccion en satoshis.
+/// - size: Tamaño virtual estimado de la transaccion firmada, en bytes virtuales.
+pub struct TransactionSummary {
+    pub inputs: Vec<(OutPoint, u64)>,
+    pub outputs: Vec<(String, u64)>,
+    pub change: Option<(String, u64)>,
+    pub data: Option<Vec<u8>>,
+    pub fee: u64,
+    pub size: u64,
+}
+
+impl TransactionSummary {
+    /// Devuelve el fee rate de la transaccion en satoshis por byte virtual.
+    pub fn fee_rate(&self) -> f64 {
+        match self.size {
+            0 => 0.0,
+            size => self.fee as f64 / size as f64,
+        }
+    }
+
+    /// Devuelve la suma de los valores enviados, sin contar el cambio.
+    pub fn amount(&self) -> u64 {
+        self.outputs.iter().map(|(_, value)| value).sum()
+    }
+}
+
+impl fmt::Display for TransactionSummary {
+    /// Muestra el detalle de la transaccion, un dato por linea.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Inputs:")?;
+        for (out_point, value) in &self.inputs {
+            writeln!(
+                f,
+                "  {}:{} ({} sat)",
+                out_point.hash, out_point.index, value
+            )?;
+        }
+        writeln!(f, "Outputs:")?;
+        for (address, value) in &self.outputs {
+            writeln!(f, "  {} ({} sat)", address, value)?;
+        }
+        match &self.change {
+            Some((address, value)) => writeln!(f, "Change: {} ({} sat)", address, value)?,
+            None => writeln!(f, "Change: none")?,
+        }
+        if let Some(data) = &self.data {
+            writeln!(f, "OP_RETURN: {}", bytes_to_hex(data))?;
+        }
+        writeln!(f, "Fee: {} sat ({:.2} sat/vB)", self.fee, self.fee_rate())?;
+        write!(f, "Size: {} vB", self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::hash256::Hash256;
+
+    use super::*;
+
+    #[test]
+    fn summary_shows_every_detail() {
+        let summary = TransactionSummary {
+            inputs: vec![(
+                OutPoint {
+                    hash: Hash256::ZERO,
+                    index: 1,
+                },
+                5000,
+            )],
+            outputs: vec![(String::from("receiver"), 3000)],
+            change: Some((String::from("change"), 1774)),
+            data: Some(vec![0xca, 0xfe]),
+            fee: 226,
+            size: 226,
+        };
+        assert_eq!(summary.fee_rate(), 1.0);
+        assert_eq!(summary.amount(), 3000);
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "Inputs:\n  {}:1 (5000 sat)\nOutputs:\n  receiver (3000 sat)\nChange: change (1774 sat)\n\
+                 OP_RETURN: cafe\nFee: 226 sat (1.00 sat/vB)\nSize: 226 vB",
+                Hash256::ZERO
+            )
+        );
+    }
+}
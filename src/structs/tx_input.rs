@@ -1,6 +1,6 @@
 use crate::{
     error::CustomError,
-    parser::{BufferParser, VarIntSerialize},
+    parser::{Parser, VarIntSerialize},
 };
 
 use super::outpoint::OutPoint;
@@ -11,14 +11,17 @@ use super::outpoint::OutPoint;
 /// - previous_output: Outpoint de la transaccion que genero el input
 /// - script_sig: Script que se debe ejecutar para firmar transacciones
 /// - sequence: Numero de version definido por el usuario
+/// - witness: Stack de elementos del witness (BIP 144), vacio si el input no tiene witness
 pub struct TransactionInput {
     pub previous_output: OutPoint,
     pub script_sig: Vec<u8>,
     pub sequence: u32,
+    pub witness: Vec<Vec<u8>>,
 }
 
 impl TransactionInput {
     /// Esta funcion se encarga de serializar un input en un vector de bytes.
+    /// El witness no forma parte de esta serializacion, se serializa aparte con serialize_witness.
     pub fn serialize(&self) -> Vec<u8> {
         let mut buffer: Vec<u8> = vec![];
         buffer.extend(self.previous_output.serialize());
@@ -28,43 +31,95 @@ impl TransactionInput {
         buffer
     }
 
-    /// Esta funcion se encarga de parsear un input a partir de un BufferParser.
+    /// Esta funcion se encarga de parsear un input a partir de un parser.
     /// Devuelve CustomError si:
-    /// - Falla alguna de las extracciones del BufferParser
-    pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
-        let previous_output = OutPoint::parse(parser.extract_buffer(36)?.to_vec())?;
+    /// - Falla alguna de las extracciones del parser
+    pub fn parse(parser: &mut impl Parser) -> Result<Self, CustomError> {
+        let previous_output = OutPoint::parse(parser.extract_bytes(36)?)?;
         let script_sig_length = parser.extract_varint()? as usize;
-        let script_sig = parser.extract_buffer(script_sig_length)?.to_vec();
+        let script_sig = parser.extract_bytes(script_sig_length)?;
         let sequence = parser.extract_u32()?;
         Ok(Self {
             previous_output,
             script_sig,
             sequence,
+            witness: vec![],
         })
     }
+
+    /// Esta funcion se encarga de serializar el stack del witness del input.
+    pub fn serialize_witness(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = vec![];
+        buffer.extend(self.witness.len().to_varint_bytes());
+        for item in &self.witness {
+            buffer.extend(item.len().to_varint_bytes());
+            buffer.extend(item);
+        }
+        buffer
+    }
+
+    /// Esta funcion se encarga de parsear el stack del witness del input a partir de un parser.
+    /// Devuelve CustomError si:
+    /// - Falla alguna de las extracciones del parser
+    pub fn parse_witness(&mut self, parser: &mut impl Parser) -> Result<(), CustomError> {
+        let item_count = parser.extract_varint()? as usize;
+        let mut witness = vec![];
+        for _ in 0..item_count {
+            let item_length = parser.extract_varint()? as usize;
+            witness.push(parser.extract_bytes(item_length)?);
+        }
+        self.witness = witness;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::BufferParser;
+    use crate::{parser::BufferParser, structs::hash256::Hash256};
 
     #[test]
     fn serialize_and_parse() {
         let input = TransactionInput {
             previous_output: OutPoint {
-                hash: vec![
+                hash: Hash256::new([
                     1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 1, 2, 3, 4, 5, 6,
                     7, 8, 9, 10, 1, 2,
-                ],
+                ]),
                 index: 0,
             },
             script_sig: vec![4, 5, 6],
             sequence: 0xffffffff,
+            witness: vec![],
         };
         let serialize = input.serialize();
-        let mut parser = BufferParser::new(serialize);
+        let mut parser = BufferParser::new(&serialize);
         let parsed_input = TransactionInput::parse(&mut parser).unwrap();
         assert_eq!(input, parsed_input);
     }
+
+    #[test]
+    fn serialize_and_parse_witness() {
+        let mut input = TransactionInput {
+            previous_output: OutPoint {
+                hash: Hash256::new([1; 32]),
+                index: 0,
+            },
+            script_sig: vec![],
+            sequence: 0xffffffff,
+            witness: vec![vec![7; 71], vec![], vec![3; 33]],
+        };
+        let serialized = input.serialize_witness();
+        assert_eq!(serialized.len(), 1 + 72 + 1 + 34);
+
+        let mut parsed_input =
+            TransactionInput::parse(&mut BufferParser::new(&input.serialize())).unwrap();
+        parsed_input
+            .parse_witness(&mut BufferParser::new(&serialized))
+            .unwrap();
+        assert_eq!(input, parsed_input);
+
+        input.witness = vec![];
+        assert_eq!(input.serialize_witness(), vec![0]);
+    }
 }
@@ -1,6 +1,7 @@
 use crate::{
     error::CustomError,
-    parser::{BufferParser, VarIntSerialize},
+    parser::{Parser, VarIntSerialize},
+    structs::script::classify::ScriptType,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,47 +24,57 @@ impl TransactionOutput {
         buffer
     }
 
-    /// Esta funcion se encarga de parsear un output a partir de un BufferParser.
-    pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+    /// Esta funcion se encarga de parsear un output a partir de un parser.
+    pub fn parse(parser: &mut impl Parser) -> Result<Self, CustomError> {
         let value = parser.extract_u64()?;
         let script_pk_length = parser.extract_varint()? as usize;
-        let script_pubkey = parser.extract_buffer(script_pk_length)?.to_vec();
+        let script_pubkey = parser.extract_bytes(script_pk_length)?;
         Ok(Self {
             value,
             script_pubkey,
         })
     }
 
-    /// Esta funcion se encarga de verificar si un output esta enviado a una clave publica del tipo P2PKH.
+    /// Esta funcion se encarga de verificar si un output esta enviado a una clave publica, ya sea del tipo P2PK, P2PKH o P2WPKH,
+    /// o a un redeem script del tipo P2SH, en cuyo caso el hash recibido es el del redeem script.
     pub fn is_sent_to_key(&self, public_key_hash: &Vec<u8>) -> Result<bool, CustomError> {
-        let parser = &mut BufferParser::new(self.script_pubkey.clone());
-        match parser.extract_u8() {
-            Ok(0x76) => compare_p2pkh(parser, public_key_hash),
-            _ => Ok(false),
-        }
+        self.is_sent_to_any_key(std::slice::from_ref(public_key_hash))
+    }
+
+    /// Devuelve el tipo del script pubkey del output, con los datos que identifican a su destinatario.
+    pub fn script_type(&self) -> ScriptType {
+        ScriptType::classify(&self.script_pubkey)
+    }
+
+    /// Devuelve true si el script pubkey del output es del tipo P2SH: OP_HASH160 seguido de un hash de 20 bytes y OP_EQUAL.
+    pub fn is_p2sh(&self) -> bool {
+        matches!(self.script_type(), ScriptType::P2SH(_))
     }
-}
 
-/// Esta funcion se encarga de comparar un script pubkey con una clave publica del tipo P2PKH.
-fn compare_p2pkh(
-    parser: &mut BufferParser,
-    public_key_hash: &Vec<u8>,
-) -> Result<bool, CustomError> {
-    match parser.extract_u8() {
-        Ok(0xa9) => (),
-        _ => return Ok(false),
+    /// Devuelve true si el script pubkey del output es del tipo P2WPKH: OP_0 seguido de un hash de 20 bytes.
+    pub fn is_p2wpkh(&self) -> bool {
+        matches!(self.script_type(), ScriptType::P2WPKH(_))
     }
-    match parser.extract_u8() {
-        Ok(0x14) => (),
-        _ => return Ok(false),
+
+    /// Devuelve true si el script pubkey del output es del tipo P2PK: una public key seguida de OP_CHECKSIG.
+    pub fn is_p2pk(&self) -> bool {
+        matches!(self.script_type(), ScriptType::P2PK(_))
     }
-    let hash = parser.extract_buffer(20)?.to_vec();
 
-    Ok(hash == *public_key_hash)
+    /// Esta funcion se encarga de verificar si un output esta enviado a alguna de las claves publicas recibidas.
+    /// El script pubkey se clasifica una sola vez y se compara el hash de su destinatario con cada clave.
+    pub fn is_sent_to_any_key(&self, public_key_hashes: &[Vec<u8>]) -> Result<bool, CustomError> {
+        Ok(self
+            .script_type()
+            .owner_hash()
+            .is_some_and(|owner_hash| public_key_hashes.contains(&owner_hash)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use bitcoin_hashes::{hash160, Hash};
+
     use crate::{
         messages::transaction::Transaction, parser::BufferParser, states::utxo_state::UTXO,
         structs::tx_output::TransactionOutput, wallet::Wallet,
@@ -76,7 +87,7 @@ mod tests {
             script_pubkey: vec![4, 5, 6],
         };
         let serialized = output.serialize();
-        let mut parser = BufferParser::new(serialized);
+        let mut parser = BufferParser::new(&serialized);
         let parsed_output = TransactionOutput::parse(&mut parser).unwrap();
         assert_eq!(output, parsed_output);
     }
@@ -114,7 +125,7 @@ mod tests {
         ];
         let public_key_hash = wallet.get_pubkey_hash().unwrap();
 
-        let mut parser = BufferParser::new(buffer);
+        let mut parser = BufferParser::new(&buffer);
         let tx = Transaction::parse_from_parser(&mut parser).unwrap();
         let tx_outputs = tx.outputs.clone();
         for output in tx_outputs {
@@ -122,4 +133,36 @@ mod tests {
         }
         assert_eq!(found, false);
     }
+
+    #[test]
+    fn is_sent_to_p2wpkh_key() {
+        let public_key_hash = vec![7; 20];
+        let mut script_pubkey = vec![0x00, 0x14];
+        script_pubkey.extend(&public_key_hash);
+        let output = TransactionOutput {
+            value: 100,
+            script_pubkey,
+        };
+        assert!(output.is_p2wpkh());
+        assert!(output.is_sent_to_key(&public_key_hash).unwrap());
+        assert!(!output.is_sent_to_key(&vec![8; 20]).unwrap());
+    }
+
+    #[test]
+    fn is_sent_to_p2pk_key() {
+        let public_key = vec![0x02; 33];
+        let mut script_pubkey = vec![33];
+        script_pubkey.extend(&public_key);
+        script_pubkey.push(0xac);
+        let output = TransactionOutput {
+            value: 100,
+            script_pubkey,
+        };
+        let public_key_hash = hash160::Hash::hash(&public_key).to_byte_array().to_vec();
+        assert!(output.is_p2pk());
+        assert!(output
+            .is_sent_to_any_key(&[vec![8; 20], public_key_hash])
+            .unwrap());
+        assert!(!output.is_sent_to_any_key(&[vec![8; 20]]).unwrap());
+    }
 }
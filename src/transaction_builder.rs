@@ -0,0 +1,1140 @@
+use std::{cmp::Reverse, str::FromStr};
+
+use crate::{
+    address::Address,
+    error::{CustomError, WalletError},
+    fee_estimator::MIN_FEE_RATE,
+    messages::transaction::Transaction,
+    psbt::Psbt,
+    signer::{sign_transaction, Signer},
+    states::utxo_state::UTXOValue,
+    structs::{
+        outpoint::OutPoint,
+        script::{Script, MAX_NULL_DATA_SIZE},
+        transaction_summary::TransactionSummary,
+        tx_output::TransactionOutput,
+    },
+    wallet::Wallet,
+};
+
+/// Tamaño aproximado en bytes de los campos fijos de una transaccion.
+const TX_OVERHEAD_SIZE: u64 = 10;
+/// Tamaño aproximado en bytes de un input P2PKH firmado.
+const P2PKH_INPUT_SIZE: u64 = 148;
+/// Tamaño virtual aproximado de un input P2WPKH firmado, el witness cuenta un cuarto (BIP 141).
+const P2WPKH_INPUT_VSIZE: u64 = 68;
+/// Tamaño en bytes de un output P2PKH.
+const P2PKH_OUTPUT_SIZE: u64 = 34;
+/// Tamaño en bytes de un output sin su script pubkey: el valor y el largo del script.
+const OUTPUT_OVERHEAD_SIZE: u64 = 9;
+/// Valor minimo de un output para que los nodos lo retransmitan.
+pub const DUST_LIMIT: u64 = 546;
+/// Fee total maximo por defecto en satoshis (0.1 BTC), un fee mayor se considera un error.
+pub const DEFAULT_MAX_FEE: u64 = 10_000_000;
+/// Confirmaciones minimas por defecto de las UTXO que se gastan, todas las UTXO de la wallet estan confirmadas.
+pub const DEFAULT_SPEND_MIN_CONFIRMATIONS: usize = 1;
+
+/// Fee de la transaccion a construir.
+/// - Fixed: Fee total en satoshis.
+/// - Rate: Satoshis por byte, el fee se calcula segun el tamaño estimado de la transaccion.
+#[derive(Debug, Clone, Copy)]
+pub enum Fee {
+    Fixed(u64),
+    Rate(u64),
+}
+
+/// Payment es un pago pedido por el usuario, a partir del cual se construye la transaccion de la wallet activa.
+/// Los elementos son:
+/// - outputs: Direcciones a las que se envian fondos y el valor enviado a cada una, en el orden de los outputs.
+/// - fee: Fee de la transaccion.
+/// - inputs: Outpoints elegidos en el coin control, si no hay ninguno se seleccionan las UTXO de la wallet.
+/// - data: Datos que se agregan a la transaccion en un output OP_RETURN, si los hay.
+/// - subtract_fee_from: Direccion de cuyo output se descuenta el fee, si no se paga ademas de los outputs.
+#[derive(Debug, Clone)]
+pub struct Payment {
+    pub outputs: Vec<(String, u64)>,
+    pub fee: Fee,
+    pub inputs: Vec<OutPoint>,
+    pub data: Option<Vec<u8>>,
+    pub subtract_fee_from: Option<String>,
+}
+
+impl Payment {
+    /// Crea un pago a las direcciones recibidas que selecciona las UTXO de la wallet, no agrega datos
+    /// y paga el fee ademas de los outputs.
+    pub fn new(outputs: Vec<(String, u64)>, fee: Fee) -> Self {
+        Self {
+            outputs,
+            fee,
+            inputs: vec![],
+            data: None,
+            subtract_fee_from: None,
+        }
+    }
+}
+
+/// TransactionBuilder construye una transaccion firmada a partir de las UTXO de una wallet.
+/// Selecciona las UTXO de mayor valor hasta cubrir los outputs y el fee, y envia el cambio
+/// a la direccion de cambio de la wallet.
+/// Los elementos son:
+/// - wallet: Wallet que envia los fondos y firma los inputs.
+/// - utxo: UTXO disponibles de la wallet.
+/// - outputs: Direcciones a las que se envian fondos y el valor enviado a cada una, en el orden en que se agregaron.
+/// - fee: Fee de la transaccion.
+/// - replaced_fee: Fee de la transaccion que se reemplaza (BIP 125), si la transaccion es un reemplazo.
+/// - max_fee: Fee total maximo en satoshis, para evitar pagar un fee absurdo por error.
+/// - required_inputs: Outpoints que la transaccion debe gastar, antes de seleccionar otras UTXO.
+/// - excluded_inputs: Outpoints que no se seleccionan para financiar la transaccion, salvo que se deban gastar.
+/// - replaceable: Indica si la transaccion puede ser reemplazada por otra que pague mas fee (BIP 125).
+/// - data: Datos del output OP_RETURN de la transaccion, si lo tiene.
+/// - max_output: Direccion que recibe todo el balance que queda luego de los demas outputs y el fee, si se envia el maximo.
+/// - subtract_fee_from: Direccion de cuyo output se descuenta el fee, en lugar de pagarlo ademas de los outputs.
+pub struct TransactionBuilder<'a> {
+    wallet: &'a Wallet,
+    utxo: Vec<(OutPoint, UTXOValue)>,
+    outputs: Vec<(String, u64)>,
+    fee: Fee,
+    replaced_fee: Option<u64>,
+    max_fee: u64,
+    required_inputs: Vec<OutPoint>,
+    excluded_inputs: Vec<OutPoint>,
+    replaceable: bool,
+    data: Option<Vec<u8>>,
+    max_output: Option<String>,
+    subtract_fee_from: Option<String>,
+}
+
+impl<'a> TransactionBuilder<'a> {
+    /// Inicializa el builder con la wallet que envia los fondos y sus UTXO.
+    /// Las UTXO P2SH de los multisigs de la wallet no se seleccionan, ya que requieren las firmas de otras claves.
+    pub fn new(wallet: &'a Wallet, mut utxo: Vec<(OutPoint, UTXOValue)>) -> Self {
+        utxo.retain(|(_, value)| !value.tx_out.is_p2sh());
+        Self {
+            wallet,
+            utxo,
+            outputs: vec![],
+            fee: Fee::Fixed(0),
+            replaced_fee: None,
+            max_fee: DEFAULT_MAX_FEE,
+            required_inputs: vec![],
+            excluded_inputs: vec![],
+            replaceable: true,
+            data: None,
+            max_output: None,
+            subtract_fee_from: None,
+        }
+    }
+
+    /// Agrega un output que envia amount satoshis a la direccion recibida, despues de los ya agregados.
+    /// Cada direccion puede recibir un solo output, si se repite build devuelve un error.
+    pub fn add_output(mut self, address: String, amount: u64) -> Self {
+        self.outputs.push((address, amount));
+        self
+    }
+
+    /// Agrega varios outputs a la transaccion, en el orden recibido.
+    pub fn outputs(mut self, outputs: Vec<(String, u64)>) -> Self {
+        for (address, amount) in outputs {
+            self = self.add_output(address, amount);
+        }
+        self
+    }
+
+    /// Establece un fee fijo en satoshis.
+    pub fn fee(mut self, fee: u64) -> Self {
+        self.fee = Fee::Fixed(fee);
+        self
+    }
+
+    /// Establece el fee en satoshis por byte.
+    pub fn fee_rate(mut self, satoshis_per_byte: u64) -> Self {
+        self.fee = Fee::Rate(satoshis_per_byte);
+        self
+    }
+
+    /// Indica que la transaccion reemplaza a otra que paga replaced_fee satoshis (BIP 125).
+    /// El fee es al menos el fee reemplazado mas el fee minimo de relay del tamaño de la nueva transaccion,
+    /// que crece si se agregan inputs o el cambio.
+    pub fn replaces(mut self, replaced_fee: u64) -> Self {
+        self.replaced_fee = Some(replaced_fee);
+        self
+    }
+
+    /// Establece el fee total maximo en satoshis. Por defecto es DEFAULT_MAX_FEE.
+    pub fn max_fee(mut self, max_fee: u64) -> Self {
+        self.max_fee = max_fee;
+        self
+    }
+
+    /// Establece los outpoints que la transaccion debe gastar. Si no alcanzan para cubrir
+    /// los outputs y el fee se agregan otras UTXO de la wallet.
+    pub fn must_spend(mut self, out_points: Vec<OutPoint>) -> Self {
+        self.required_inputs = out_points;
+        self
+    }
+
+    /// Establece las UTXO que financian la transaccion (coin control). Se gastan todas y no se
+    /// seleccionan otras UTXO de la wallet, por lo que si no alcanzan para cubrir los outputs y el fee
+    /// build devuelve un error.
+    pub fn with_inputs(mut self, out_points: Vec<OutPoint>) -> Self {
+        self.utxo
+            .retain(|(out_point, _)| out_points.contains(out_point));
+        self.must_spend(out_points)
+    }
+
+    /// Agrega outpoints que la seleccion de UTXO no puede elegir, por ejemplo por no tener suficientes confirmaciones.
+    /// Se siguen gastando si estan entre los outpoints que la transaccion debe gastar.
+    pub fn exclude_inputs(mut self, out_points: Vec<OutPoint>) -> Self {
+        self.excluded_inputs.extend(out_points);
+        self
+    }
+
+    /// Establece si la transaccion indica que puede ser reemplazada (BIP 125). Por defecto es true.
+    pub fn replaceable(mut self, replaceable: bool) -> Self {
+        self.replaceable = replaceable;
+        self
+    }
+
+    /// Agrega a la transaccion un output OP_RETURN con los datos recibidos, que no se puede gastar y tiene valor 0.
+    /// Sirve para dejar registro de los datos en la blockchain, por ejemplo el hash de un documento.
+    pub fn op_return(mut self, data: Vec<u8>) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Envia a la direccion recibida todo lo que se puede gastar, gastando todas las UTXO que se pueden seleccionar
+    /// y descontando del monto enviado los demas outputs y el fee. La transaccion no tiene cambio.
+    pub fn send_max(mut self, address: String) -> Self {
+        if !self
+            .outputs
+            .iter()
+            .any(|(output_address, _)| *output_address == address)
+        {
+            self.outputs.push((address.clone(), 0));
+        }
+        self.max_output = Some(address);
+        self
+    }
+
+    /// Descuenta el fee del output de la direccion recibida, que recibe su monto menos el fee,
+    /// como subtractfeefromamount en Bitcoin Core. La direccion debe estar entre los outputs.
+    /// No tiene efecto si se envia el maximo, ya que en ese caso el fee siempre se descuenta.
+    pub fn subtract_fee_from(mut self, address: String) -> Self {
+        self.subtract_fee_from = Some(address);
+        self
+    }
+
+    /// Devuelve el monto que recibe la direccion de send_max: el valor de las UTXO que se pueden seleccionar,
+    /// menos los demas outputs y el fee de una transaccion que las gasta a todas sin cambio.
+    /// Devuelve CustomError si no se llamo a send_max, o si el monto no llega al limite de dust.
+    pub fn max_amount(&self) -> Result<u64, CustomError> {
+        let Some(address) = &self.max_output else { return Err(CustomError::Wallet(WalletError::InvalidTransferFields)) };
+        let inputs: Vec<(OutPoint, TransactionOutput)> = self
+            .utxo
+            .iter()
+            .filter(|(out_point, _)| {
+                !self.excluded_inputs.contains(out_point)
+                    || self.required_inputs.contains(out_point)
+            })
+            .map(|(out_point, value)| (out_point.clone(), value.tx_out.clone()))
+            .collect();
+        let balance: u64 = inputs.iter().map(|(_, output)| output.value).sum();
+        let outputs_value: u64 = self
+            .outputs
+            .iter()
+            .filter(|(output_address, _)| output_address != address)
+            .map(|(_, amount)| amount)
+            .sum();
+        match balance.checked_sub(outputs_value + self.calculate_fee(&inputs, false)) {
+            Some(amount) if amount >= DUST_LIMIT => Ok(amount),
+            _ => Err(CustomError::Wallet(WalletError::InsufficientFunds)),
+        }
+    }
+
+    /// Selecciona las UTXO, agrega el cambio y firma la transaccion.
+    /// Devuelve CustomError si:
+    /// - No hay outputs o alguno tiene valor 0.
+    /// - La direccion de algun output no es valida o se repite en otro output.
+    /// - Algun output tiene un valor menor al limite de dust.
+    /// - La direccion de la que se descuenta el fee no esta entre los outputs, o su monto no alcanza para pagarlo.
+    /// - Los datos del output OP_RETURN superan MAX_NULL_DATA_SIZE.
+    /// - El valor de los outputs supera el balance que puede gastar la wallet.
+    /// - Alguno de los outpoints que se deben gastar no esta entre las UTXO de la wallet.
+    /// - La wallet no tiene fondos suficientes para cubrir los outputs y el fee.
+    /// - El fee supera el fee maximo.
+    /// - No se pudo firmar la transaccion.
+    pub fn build(mut self) -> Result<Transaction, CustomError> {
+        let inputs = self.select_inputs()?;
+        let wallet = self.wallet;
+        let (mut transaction, previous_outputs) = self.create_unsigned(inputs)?;
+        transaction.get_script_sigs(wallet, &previous_outputs)?;
+        Ok(transaction)
+    }
+
+    /// Selecciona las UTXO y agrega el cambio como build, pero devuelve la transaccion sin firmar en un PSBT (BIP 174),
+    /// para firmarla fuera de la wallet, por ejemplo en una hardware wallet o con los cosigners de un multisig.
+    /// No requiere las claves de la wallet, por lo que sirve para wallets watch-only.
+    /// Devuelve CustomError en los mismos casos que build, salvo los de firma.
+    pub fn build_psbt(mut self) -> Result<Psbt, CustomError> {
+        let inputs = self.select_inputs()?;
+        let (transaction, previous_outputs) = self.create_unsigned(inputs)?;
+        Psbt::new(transaction, previous_outputs)
+    }
+
+    /// Selecciona las UTXO y agrega el cambio como build, pero firma la transaccion con el signer recibido,
+    /// por ejemplo una hardware wallet, en lugar de con las claves de la wallet.
+    /// Los signers de hardware wallets requieren ademas la transaccion que crea cada output gastado, por lo que
+    /// para ellos se firma el PSBT de build_psbt luego de agregarlas con Psbt::add_previous_tx.
+    /// Devuelve CustomError en los mismos casos que build, o si el signer no firmo todos los inputs.
+    pub fn build_with_signer(self, signer: &mut dyn Signer) -> Result<Transaction, CustomError> {
+        let wallet = self.wallet;
+        let psbt = self.build_psbt()?;
+        sign_transaction(signer, psbt, wallet)
+    }
+
+    /// Selecciona las UTXO y agrega el cambio como build, pero devuelve el detalle de la transaccion en lugar de crearla,
+    /// para que el usuario la revise antes de enviarla. Con las mismas UTXO, build crea la misma transaccion.
+    /// El tamaño es el estimado para la transaccion firmada, el mismo con el que se calcula el fee.
+    /// Devuelve CustomError en los mismos casos que build, salvo los de firma.
+    pub fn summary(mut self) -> Result<TransactionSummary, CustomError> {
+        let inputs = self.select_inputs()?;
+        let size = self.transaction_vsize(&inputs, false);
+        let inputs_value: u64 = inputs.iter().map(|(_, output)| output.value).sum();
+        let outputs_value: u64 = self.outputs.iter().map(|(_, amount)| amount).sum();
+
+        let change_address = self.wallet.get_change_address();
+        let change = self
+            .outputs
+            .iter()
+            .position(|(address, _)| *address == change_address)
+            .map(|index| self.outputs.remove(index));
+        Ok(TransactionSummary {
+            inputs: inputs
+                .into_iter()
+                .map(|(out_point, output)| (out_point, output.value))
+                .collect(),
+            outputs: self.outputs,
+            change,
+            data: self.data,
+            fee: inputs_value - outputs_value,
+            size,
+        })
+    }
+
+    /// Crea la transaccion sin firmar que gasta los inputs seleccionados, con el output OP_RETURN al final si lo tiene.
+    /// Devuelve la transaccion junto a los outputs que gasta cada input.
+    fn create_unsigned(
+        self,
+        inputs: Vec<(OutPoint, TransactionOutput)>,
+    ) -> Result<(Transaction, Vec<TransactionOutput>), CustomError> {
+        let (mut transaction, previous_outputs) =
+            Transaction::create_unsigned(inputs, self.outputs, self.replaceable)?;
+        if let Some(data) = self.data {
+            transaction.outputs.push(TransactionOutput {
+                value: 0,
+                script_pubkey: Script::null_data(&data).serialize(),
+            });
+        }
+        Ok((transaction, previous_outputs))
+    }
+
+    /// Selecciona las UTXO que gasta la transaccion y agrega el cambio a los outputs.
+    /// Devuelve las UTXO seleccionadas junto a sus outputs.
+    fn select_inputs(&mut self) -> Result<Vec<(OutPoint, TransactionOutput)>, CustomError> {
+        if let Some(address) = self.max_output.clone() {
+            let amount = self.max_amount()?;
+            for (output_address, output_amount) in self.outputs.iter_mut() {
+                if *output_address == address {
+                    *output_amount = amount;
+                }
+            }
+            self.required_inputs = self
+                .utxo
+                .iter()
+                .filter(|(out_point, _)| {
+                    !self.excluded_inputs.contains(out_point)
+                        || self.required_inputs.contains(out_point)
+                })
+                .map(|(out_point, _)| out_point.clone())
+                .collect();
+        }
+        if self.max_output.is_some() {
+            self.subtract_fee_from = None;
+        }
+        if self.outputs.is_empty() || self.outputs.iter().any(|(_, amount)| *amount == 0) {
+            return Err(CustomError::Wallet(WalletError::InvalidTransferFields));
+        }
+        if let Some(address) = &self.subtract_fee_from {
+            if !self
+                .outputs
+                .iter()
+                .any(|(output_address, _)| output_address == address)
+            {
+                return Err(CustomError::Wallet(WalletError::InvalidTransferFields));
+            }
+        }
+        // se valida cada destinatario antes de seleccionar las UTXO, para indicar cual no es valido
+        for (index, (address, _)) in self.outputs.iter().enumerate() {
+            if self.outputs[..index]
+                .iter()
+                .any(|(other_address, _)| other_address == address)
+            {
+                return Err(CustomError::Validation(format!(
+                    "Receiver {}: the address {} already receives another output",
+                    index + 1,
+                    address
+                )));
+            }
+            Address::from_str(address).map_err(|error| match error {
+                CustomError::Validation(reason) => CustomError::Validation(format!(
+                    "Invalid recipient address {}: {}",
+                    address, reason
+                )),
+                error => error,
+            })?;
+        }
+        if self.outputs.iter().any(|(_, amount)| *amount < DUST_LIMIT) {
+            return Err(CustomError::Wallet(WalletError::DustOutput));
+        }
+        if self
+            .data
+            .as_ref()
+            .is_some_and(|data| data.len() > MAX_NULL_DATA_SIZE)
+        {
+            return Err(CustomError::Validation(format!(
+                "OP_RETURN data can't exceed {} bytes",
+                MAX_NULL_DATA_SIZE
+            )));
+        }
+        let outputs_value: u64 = self.outputs.iter().map(|(_, amount)| amount).sum();
+        self.utxo.retain(|(out_point, _)| {
+            !self.excluded_inputs.contains(out_point) || self.required_inputs.contains(out_point)
+        });
+        let balance: u64 = self.utxo.iter().map(|(_, value)| value.tx_out.value).sum();
+        if outputs_value > balance {
+            return Err(CustomError::Wallet(WalletError::AmountExceedsBalance));
+        }
+
+        self.utxo
+            .sort_by_key(|(_, value)| Reverse(value.tx_out.value));
+
+        let mut inputs = vec![];
+        let mut inputs_value = 0;
+        for required_input in &self.required_inputs {
+            let Some((out_point, value)) = self
+                .utxo
+                .iter()
+                .find(|(out_point, _)| out_point == required_input)
+            else {
+                return Err(CustomError::Wallet(WalletError::InsufficientFunds));
+            };
+            inputs.push((out_point.clone(), value.tx_out.clone()));
+            inputs_value += value.tx_out.value;
+        }
+        for (out_point, value) in &self.utxo {
+            if inputs_value >= outputs_value + self.fee_on_top(&inputs) {
+                break;
+            }
+            if self.required_inputs.contains(out_point) {
+                continue;
+            }
+            inputs.push((out_point.clone(), value.tx_out.clone()));
+            inputs_value += value.tx_out.value;
+        }
+
+        if let Some(address) = self.subtract_fee_from.clone() {
+            let Some(change) = inputs_value.checked_sub(outputs_value) else { return Err(CustomError::Wallet(WalletError::InsufficientFunds)) };
+            self.subtract_fee(&address, &inputs, change)?;
+            return Ok(inputs);
+        }
+
+        let fee = self.calculate_fee(&inputs, true);
+        if inputs_value < outputs_value + fee {
+            // sin output de cambio la transaccion es mas chica y puede alcanzar
+            if inputs_value < outputs_value + self.calculate_fee(&inputs, false) {
+                return Err(CustomError::Wallet(WalletError::InsufficientFunds));
+            }
+            self.check_fee(inputs_value - outputs_value)?;
+        } else {
+            self.check_fee(fee)?;
+            self.add_change(inputs_value - outputs_value - fee);
+        }
+        Ok(inputs)
+    }
+
+    /// Descuenta el fee del output de la direccion recibida y agrega el cambio, si no es dust.
+    /// Si el cambio es dust queda como fee, pero no se descuenta del output.
+    /// Devuelve CustomError si el monto del output no alcanza para pagar el fee o el fee supera el fee maximo.
+    fn subtract_fee(
+        &mut self,
+        address: &str,
+        inputs: &[(OutPoint, TransactionOutput)],
+        change: u64,
+    ) -> Result<(), CustomError> {
+        let with_change = !self.is_dust_change(change);
+        let fee = self.calculate_fee(inputs, with_change);
+        let Some((_, amount)) = self
+            .outputs
+            .iter_mut()
+            .find(|(output_address, _)| output_address == address)
+        else {
+            return Err(CustomError::Wallet(WalletError::InvalidTransferFields));
+        };
+        match amount.checked_sub(fee) {
+            Some(reduced_amount) if reduced_amount >= DUST_LIMIT => *amount = reduced_amount,
+            _ => {
+                return Err(CustomError::Validation(String::from(
+                    "The amount is too small to pay the fee",
+                )))
+            }
+        }
+        match with_change {
+            true => {
+                self.check_fee(fee)?;
+                self.add_change(change);
+            }
+            false => self.check_fee(fee + change)?,
+        }
+        Ok(())
+    }
+
+    /// Devuelve el fee que hay que cubrir ademas de los outputs al seleccionar las UTXO,
+    /// que es 0 si el fee se descuenta de un output.
+    fn fee_on_top(&self, inputs: &[(OutPoint, TransactionOutput)]) -> u64 {
+        match self.subtract_fee_from {
+            Some(_) => 0,
+            None => self.calculate_fee(inputs, true),
+        }
+    }
+
+    /// Devuelve true si el cambio es dust: si es menor al limite de dust con un fee por byte, o si es 0 con un fee fijo.
+    fn is_dust_change(&self, change: u64) -> bool {
+        match self.fee {
+            Fee::Fixed(_) => change == 0,
+            Fee::Rate(_) => change < DUST_LIMIT,
+        }
+    }
+
+    /// Agrega el cambio a los outputs, salvo que sea dust con un fee por byte, en cuyo caso queda como fee.
+    fn add_change(&mut self, change: u64) {
+        if !self.is_dust_change(change) {
+            let change_address = self.wallet.get_change_address();
+            match self
+                .outputs
+                .iter_mut()
+                .find(|(address, _)| *address == change_address)
+            {
+                Some((_, amount)) => *amount += change,
+                None => self.outputs.push((change_address, change)),
+            }
+        }
+    }
+
+    /// Devuelve CustomError si el fee supera el fee maximo.
+    fn check_fee(&self, fee: u64) -> Result<(), CustomError> {
+        match fee > self.max_fee {
+            true => Err(CustomError::Wallet(WalletError::AbsurdFee)),
+            false => Ok(()),
+        }
+    }
+
+    fn calculate_fee(&self, inputs: &[(OutPoint, TransactionOutput)], with_change: bool) -> u64 {
+        let fee = match self.fee {
+            Fee::Fixed(fee) => fee,
+            Fee::Rate(satoshis_per_byte) => {
+                satoshis_per_byte * self.transaction_vsize(inputs, with_change)
+            }
+        };
+        let min_fee = self.replaced_fee.map_or(0, |replaced_fee| {
+            replaced_fee + MIN_FEE_RATE * self.transaction_vsize(inputs, with_change)
+        });
+        fee.max(min_fee)
+    }
+
+    /// Estima el tamaño virtual de la transaccion firmada que gasta los inputs recibidos,
+    /// con los outputs, el del cambio si se agrega y el output OP_RETURN si hay datos.
+    fn transaction_vsize(
+        &self,
+        inputs: &[(OutPoint, TransactionOutput)],
+        with_change: bool,
+    ) -> u64 {
+        let outputs = self.outputs.len() + with_change as usize;
+        let segwit_inputs = inputs
+            .iter()
+            .filter(|(_, previous_output)| previous_output.is_p2wpkh())
+            .count();
+        let data_size = self.data.as_ref().map_or(0, |data| {
+            OUTPUT_OVERHEAD_SIZE + Script::null_data(data).serialize().len() as u64
+        });
+        estimate_vsize(inputs.len() - segwit_inputs, segwit_inputs, outputs) + data_size
+    }
+}
+
+/// Estima el tamaño en bytes de una transaccion P2PKH.
+pub fn estimate_size(inputs: usize, outputs: usize) -> u64 {
+    TX_OVERHEAD_SIZE + P2PKH_INPUT_SIZE * inputs as u64 + P2PKH_OUTPUT_SIZE * outputs as u64
+}
+
+/// Estima el tamaño virtual de una transaccion con inputs P2PKH y P2WPKH.
+/// Sin inputs P2WPKH coincide con estimate_size.
+pub fn estimate_vsize(p2pkh_inputs: usize, p2wpkh_inputs: usize, outputs: usize) -> u64 {
+    estimate_size(p2pkh_inputs, outputs) + P2WPKH_INPUT_VSIZE * p2wpkh_inputs as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        signer::WalletSigner,
+        structs::{hash256::Hash256, script::classify::ScriptType, tx_output::TransactionOutput},
+        wallet::get_script_pubkey,
+    };
+
+    use super::*;
+
+    const SENDER: &str = "mscatccDgq7azndWHFTzvEuZuywCsUvTRu";
+    const RECEIVER: &str = "mhzZUxRkPzNpCsQHemTakuJa5xhCajxyVm";
+
+    fn wallet() -> Wallet {
+        Wallet {
+            name: String::from("test"),
+            pubkey: String::from(SENDER),
+            privkey: Some(String::from(
+                "cNpwEsaVLhju18SJowLtdCNaJtvMvqL4jtFLm2FXw7vZjg4sRWvH",
+            )),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        }
+    }
+
+    fn utxo(values: &[u64]) -> Vec<(OutPoint, UTXOValue)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                (
+                    OutPoint {
+                        hash: Hash256::new([i as u8; 32]),
+                        index: 0,
+                    },
+                    UTXOValue {
+                        tx_out: TransactionOutput {
+                            value: *value,
+                            script_pubkey: get_script_pubkey(String::from(SENDER)).unwrap(),
+                        },
+                        block_hash: Hash256::new([0; 32]),
+                        block_timestamp: 0,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn output_value(tx: &Transaction, address: &str) -> Option<u64> {
+        let script_pubkey = get_script_pubkey(String::from(address)).unwrap();
+        tx.outputs
+            .iter()
+            .find(|output| output.script_pubkey == script_pubkey)
+            .map(|output| output.value)
+    }
+
+    #[test]
+    fn build_with_fixed_fee() {
+        let wallet = wallet();
+        let tx = TransactionBuilder::new(&wallet, utxo(&[1000, 5000, 2000]))
+            .add_output(String::from(RECEIVER), 5500)
+            .fee(100)
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.inputs.len(), 2);
+        assert!(tx.inputs.iter().all(|input| !input.script_sig.is_empty()));
+        assert_eq!(output_value(&tx, RECEIVER), Some(5500));
+        assert_eq!(output_value(&tx, SENDER), Some(1400));
+    }
+
+    #[test]
+    fn build_with_fee_rate() {
+        let wallet = wallet();
+        let tx = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 50000)
+            .fee_rate(2)
+            .build()
+            .unwrap();
+
+        let fee = 2 * estimate_size(1, 2);
+        assert_eq!(output_value(&tx, SENDER), Some(50000 - fee));
+    }
+
+    #[test]
+    fn dust_change_is_left_as_fee() {
+        let wallet = wallet();
+        let fee = estimate_size(1, 1);
+        let tx = TransactionBuilder::new(&wallet, utxo(&[10000 + fee + 100]))
+            .add_output(String::from(RECEIVER), 10000)
+            .fee_rate(1)
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(output_value(&tx, RECEIVER), Some(10000));
+    }
+
+    #[test]
+    fn build_spending_p2wpkh_outputs() {
+        let wallet = wallet();
+        let mut utxo = utxo(&[100000]);
+        utxo[0].1.tx_out.script_pubkey =
+            get_script_pubkey(wallet.get_bech32_address().unwrap()).unwrap();
+        let tx = TransactionBuilder::new(&wallet, utxo)
+            .add_output(wallet.get_bech32_address().unwrap(), 50000)
+            .fee_rate(2)
+            .build()
+            .unwrap();
+
+        assert!(tx.has_witness());
+        assert!(tx.inputs[0].script_sig.is_empty());
+        let fee = 2 * estimate_vsize(0, 1, 2);
+        assert_eq!(output_value(&tx, SENDER), Some(50000 - fee));
+        assert_eq!(
+            output_value(&tx, &wallet.get_bech32_address().unwrap()),
+            Some(50000)
+        );
+    }
+
+    #[test]
+    fn build_replaceable_by_default() {
+        let wallet = wallet();
+        let tx = TransactionBuilder::new(&wallet, utxo(&[10000]))
+            .add_output(String::from(RECEIVER), 5000)
+            .fee(100)
+            .build()
+            .unwrap();
+        assert!(tx.signals_rbf());
+
+        let tx = TransactionBuilder::new(&wallet, utxo(&[10000]))
+            .add_output(String::from(RECEIVER), 5000)
+            .fee(100)
+            .replaceable(false)
+            .build()
+            .unwrap();
+        assert!(!tx.signals_rbf());
+    }
+
+    #[test]
+    fn build_spending_required_inputs() {
+        let wallet = wallet();
+        let utxo = utxo(&[1000, 5000, 2000]);
+        let required = utxo[0].0.clone();
+        let tx = TransactionBuilder::new(&wallet, utxo)
+            .add_output(String::from(RECEIVER), 600)
+            .fee(100)
+            .must_spend(vec![required.clone()])
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.inputs[0].previous_output, required);
+        assert_eq!(output_value(&tx, SENDER), Some(300));
+    }
+
+    #[test]
+    fn build_with_unknown_required_input() {
+        let wallet = wallet();
+        let result = TransactionBuilder::new(&wallet, utxo(&[1000]))
+            .add_output(String::from(RECEIVER), 600)
+            .must_spend(vec![OutPoint {
+                hash: Hash256::new([9; 32]),
+                index: 0,
+            }])
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::InsufficientFunds))
+        ));
+    }
+
+    #[test]
+    fn build_with_inputs_spends_only_selected_utxo() {
+        let wallet = wallet();
+        let utxo = utxo(&[1000, 5000, 2000]);
+        let selected = vec![utxo[0].0.clone(), utxo[2].0.clone()];
+        let tx = TransactionBuilder::new(&wallet, utxo)
+            .add_output(String::from(RECEIVER), 600)
+            .fee(100)
+            .with_inputs(selected.clone())
+            .build()
+            .unwrap();
+
+        let inputs: Vec<OutPoint> = tx
+            .inputs
+            .iter()
+            .map(|input| input.previous_output.clone())
+            .collect();
+        assert_eq!(inputs, selected);
+        assert_eq!(output_value(&tx, SENDER), Some(2300));
+    }
+
+    #[test]
+    fn build_with_insufficient_selected_inputs() {
+        let wallet = wallet();
+        let utxo = utxo(&[1000, 5000]);
+        let selected = vec![utxo[0].0.clone()];
+        let result = TransactionBuilder::new(&wallet, utxo)
+            .add_output(String::from(RECEIVER), 950)
+            .fee(100)
+            .with_inputs(selected)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::InsufficientFunds))
+        ));
+    }
+
+    #[test]
+    fn excluded_inputs_are_not_selected() {
+        let wallet = wallet();
+        let utxo = utxo(&[1000, 5000, 2000]);
+        let excluded = utxo[1].0.clone();
+        let tx = TransactionBuilder::new(&wallet, utxo.clone())
+            .add_output(String::from(RECEIVER), 2500)
+            .fee(100)
+            .exclude_inputs(vec![excluded.clone()])
+            .build()
+            .unwrap();
+        assert_eq!(tx.inputs.len(), 2);
+        assert!(tx
+            .inputs
+            .iter()
+            .all(|input| input.previous_output != excluded));
+
+        let result = TransactionBuilder::new(&wallet, utxo.clone())
+            .add_output(String::from(RECEIVER), 4000)
+            .fee(100)
+            .exclude_inputs(vec![excluded.clone()])
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::AmountExceedsBalance))
+        ));
+
+        // se gastan si se deben gastar
+        let tx = TransactionBuilder::new(&wallet, utxo)
+            .add_output(String::from(RECEIVER), 4000)
+            .fee(100)
+            .exclude_inputs(vec![excluded.clone()])
+            .must_spend(vec![excluded.clone()])
+            .build()
+            .unwrap();
+        assert_eq!(tx.inputs[0].previous_output, excluded);
+    }
+
+    #[test]
+    fn build_with_op_return_data() {
+        let wallet = wallet();
+        let tx = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 50000)
+            .fee_rate(1)
+            .op_return(b"hello".to_vec())
+            .build()
+            .unwrap();
+
+        let data_output = tx.outputs.last().unwrap();
+        assert_eq!(data_output.value, 0);
+        assert_eq!(
+            ScriptType::classify(&data_output.script_pubkey),
+            ScriptType::NullData(b"hello".to_vec())
+        );
+        // el output OP_RETURN ocupa 9 bytes mas su script de 7 bytes
+        let fee = estimate_size(1, 2) + 16;
+        assert_eq!(output_value(&tx, SENDER), Some(50000 - fee));
+
+        let result = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 50000)
+            .fee_rate(1)
+            .op_return(vec![0; MAX_NULL_DATA_SIZE + 1])
+            .build();
+        assert!(matches!(result, Err(CustomError::Validation(_))));
+    }
+
+    #[test]
+    fn build_replacement_pays_replaced_fee_and_relay_fee() {
+        let wallet = wallet();
+        let tx = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 50000)
+            .fee_rate(1)
+            .replaces(5000)
+            .build()
+            .unwrap();
+        let fee = 5000 + MIN_FEE_RATE * estimate_vsize(1, 0, 2);
+        assert_eq!(output_value(&tx, SENDER), Some(50000 - fee));
+    }
+
+    #[test]
+    fn replacement_relay_fee_covers_added_inputs_and_change() {
+        let wallet = wallet();
+        let utxo = utxo(&[5500, 100000]);
+        let required = utxo[0].0.clone();
+        let tx = TransactionBuilder::new(&wallet, utxo)
+            .add_output(String::from(RECEIVER), 5000)
+            .fee_rate(1)
+            .must_spend(vec![required])
+            .replaces(400)
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.inputs.len(), 2);
+        let fee = 400 + MIN_FEE_RATE * estimate_vsize(2, 0, 2);
+        assert_eq!(output_value(&tx, SENDER), Some(105500 - 5000 - fee));
+    }
+
+    #[test]
+    fn build_with_insufficient_funds() {
+        let wallet = wallet();
+        let result = TransactionBuilder::new(&wallet, utxo(&[1000, 2000]))
+            .add_output(String::from(RECEIVER), 3000)
+            .fee(1)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::InsufficientFunds))
+        ));
+    }
+
+    #[test]
+    fn build_with_dust_output() {
+        let wallet = wallet();
+        let result = TransactionBuilder::new(&wallet, utxo(&[10000]))
+            .add_output(String::from(RECEIVER), DUST_LIMIT - 1)
+            .fee(100)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::DustOutput))
+        ));
+    }
+
+    #[test]
+    fn build_batch_payment() {
+        let wallet = wallet();
+        let segwit_receiver = wallet.get_bech32_address().unwrap();
+        let tx = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 15000)
+            .add_output(segwit_receiver.clone(), 20000)
+            .fee_rate(1)
+            .build()
+            .unwrap();
+
+        // los outputs quedan en el orden en que se agregaron, con el cambio al final
+        assert_eq!(tx.outputs.len(), 3);
+        assert_eq!(
+            tx.outputs[0].script_pubkey,
+            get_script_pubkey(String::from(RECEIVER)).unwrap()
+        );
+        assert_eq!(tx.outputs[0].value, 15000);
+        assert_eq!(
+            tx.outputs[1].script_pubkey,
+            get_script_pubkey(segwit_receiver.clone()).unwrap()
+        );
+        assert_eq!(tx.outputs[1].value, 20000);
+        let fee = estimate_size(1, 3);
+        assert_eq!(output_value(&tx, SENDER), Some(65000 - fee));
+
+        let result = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(segwit_receiver.clone(), 20000)
+            .add_output(String::from(RECEIVER), 10000)
+            .add_output(String::from(RECEIVER), 5000)
+            .fee_rate(1)
+            .build();
+        assert!(
+            matches!(result, Err(CustomError::Validation(message)) if message.starts_with("Receiver 3:"))
+        );
+
+        let result = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 10000)
+            .add_output(String::from("invalid"), 10000)
+            .fee_rate(1)
+            .build();
+        assert!(
+            matches!(result, Err(CustomError::Validation(message)) if message.contains("invalid"))
+        );
+    }
+
+    #[test]
+    fn build_sending_max() {
+        let wallet = wallet();
+        let wallet_utxo = utxo(&[1000, 5000, 2000]);
+        let builder = TransactionBuilder::new(&wallet, wallet_utxo.clone())
+            .send_max(String::from(RECEIVER))
+            .fee_rate(1);
+        let amount = builder.max_amount().unwrap();
+        assert_eq!(amount, 8000 - estimate_size(3, 1));
+
+        let tx = builder.build().unwrap();
+        assert_eq!(tx.inputs.len(), 3);
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(output_value(&tx, RECEIVER), Some(amount));
+
+        // los demas outputs se descuentan y las UTXO excluidas no se gastan
+        let segwit_receiver = wallet.get_bech32_address().unwrap();
+        let tx = TransactionBuilder::new(&wallet, wallet_utxo.clone())
+            .add_output(segwit_receiver.clone(), 1000)
+            .send_max(String::from(RECEIVER))
+            .exclude_inputs(vec![wallet_utxo[0].0.clone()])
+            .fee_rate(1)
+            .build()
+            .unwrap();
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(output_value(&tx, &segwit_receiver), Some(1000));
+        assert_eq!(
+            output_value(&tx, RECEIVER),
+            Some(6000 - estimate_size(2, 2))
+        );
+
+        let result = TransactionBuilder::new(&wallet, utxo(&[600]))
+            .send_max(String::from(RECEIVER))
+            .fee_rate(1)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::InsufficientFunds))
+        ));
+    }
+
+    #[test]
+    fn build_subtracting_fee_from_amount() {
+        let wallet = wallet();
+        let tx = TransactionBuilder::new(&wallet, utxo(&[5000]))
+            .add_output(String::from(RECEIVER), 3000)
+            .subtract_fee_from(String::from(RECEIVER))
+            .fee_rate(1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            output_value(&tx, RECEIVER),
+            Some(3000 - estimate_size(1, 2))
+        );
+        assert_eq!(output_value(&tx, &wallet.get_change_address()), Some(2000));
+
+        // se puede enviar todo el balance sin cambio
+        let tx = TransactionBuilder::new(&wallet, utxo(&[1000, 5000, 2000]))
+            .add_output(String::from(RECEIVER), 8000)
+            .subtract_fee_from(String::from(RECEIVER))
+            .fee_rate(1)
+            .build()
+            .unwrap();
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(
+            output_value(&tx, RECEIVER),
+            Some(8000 - estimate_size(3, 1))
+        );
+
+        let result = TransactionBuilder::new(&wallet, utxo(&[5000]))
+            .add_output(String::from(RECEIVER), 600)
+            .subtract_fee_from(String::from(RECEIVER))
+            .fee_rate(1)
+            .build();
+        assert!(matches!(result, Err(CustomError::Validation(_))));
+
+        let result = TransactionBuilder::new(&wallet, utxo(&[5000]))
+            .add_output(String::from(RECEIVER), 3000)
+            .subtract_fee_from(wallet.get_change_address())
+            .fee_rate(1)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::InvalidTransferFields))
+        ));
+    }
+
+    #[test]
+    fn summary_matches_built_transaction() {
+        let wallet = wallet();
+        let builder = || {
+            TransactionBuilder::new(&wallet, utxo(&[1000, 5000, 2000]))
+                .add_output(String::from(RECEIVER), 3000)
+                .op_return(b"summary".to_vec())
+                .fee_rate(2)
+        };
+        let summary = builder().summary().unwrap();
+        let tx = builder().build().unwrap();
+
+        assert_eq!(summary.inputs.len(), tx.inputs.len());
+        assert_eq!(summary.outputs, vec![(String::from(RECEIVER), 3000)]);
+        let change_address = wallet.get_change_address();
+        assert_eq!(
+            summary.change,
+            output_value(&tx, &change_address).map(|value| (change_address, value))
+        );
+        assert_eq!(summary.data, Some(b"summary".to_vec()));
+        let inputs_value: u64 = summary.inputs.iter().map(|(_, value)| value).sum();
+        let outputs_value: u64 = tx.outputs.iter().map(|output| output.value).sum();
+        assert_eq!(summary.fee, inputs_value - outputs_value);
+        assert_eq!(summary.fee_rate(), 2.0);
+    }
+
+    #[test]
+    fn build_exceeding_balance() {
+        let wallet = wallet();
+        let result = TransactionBuilder::new(&wallet, utxo(&[1000, 2000]))
+            .add_output(String::from(RECEIVER), 3001)
+            .fee(1)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::AmountExceedsBalance))
+        ));
+    }
+
+    #[test]
+    fn build_with_absurd_fee() {
+        let wallet = wallet();
+        let result = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 50000)
+            .fee(20000)
+            .max_fee(10000)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::AbsurdFee))
+        ));
+
+        let result = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 50000)
+            .fee_rate(200)
+            .max_fee(10000)
+            .build();
+        assert!(matches!(
+            result,
+            Err(CustomError::Wallet(WalletError::AbsurdFee))
+        ));
+
+        let tx = TransactionBuilder::new(&wallet, utxo(&[100000]))
+            .add_output(String::from(RECEIVER), 50000)
+            .fee(10000)
+            .max_fee(10000)
+            .build()
+            .unwrap();
+        assert_eq!(output_value(&tx, SENDER), Some(40000));
+    }
+
+    #[test]
+    fn build_without_outputs() {
+        let wallet = wallet();
+        let result = TransactionBuilder::new(&wallet, utxo(&[1000])).build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn build_with_wallet_signer() {
+        let wallet = wallet();
+        let builder = || {
+            // sin cambio la transaccion tiene un solo output, y su orden no varia entre builders
+            TransactionBuilder::new(&wallet, utxo(&[1000, 5000, 2000]))
+                .add_output(String::from(RECEIVER), 6900)
+                .fee(100)
+        };
+        let tx = builder().build_with_signer(&mut WalletSigner).unwrap();
+        assert_eq!(tx.wtxid(), builder().build().unwrap().wtxid());
+    }
+}
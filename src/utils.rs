@@ -1,24 +1,32 @@
 use std::{
     fs::OpenOptions,
     net::{SocketAddr, SocketAddrV6, TcpStream, ToSocketAddrs},
+    panic, thread,
     time::{Duration, SystemTime},
     vec::IntoIter,
 };
 
-use crate::{error::CustomError, structs::block_header::BlockHeader};
+use crate::{
+    error::{CustomError, NetworkError},
+    proxy,
+    structs::block_header::BlockHeader,
+};
 
 /// get_addresses resuelve la direccion del seed y devuelve un iterador de direcciones.
 pub fn get_addresses(seed: String, port: u16) -> Result<IntoIter<SocketAddr>, CustomError> {
     (seed, port)
         .to_socket_addrs()
-        .map_err(|_| CustomError::CannotResolveSeedAddress)
+        .map_err(|_| CustomError::Network(NetworkError::CannotResolveSeedAddress))
 }
 
-/// open_stream abre un stream a la direccion recibida.
+/// open_stream abre un stream a la direccion recibida, a traves del proxy SOCKS5 si hay uno seleccionado.
 /// Devuelve un error si no se puede conectar.
 pub fn open_stream(address: SocketAddr) -> Result<TcpStream, CustomError> {
+    if let Some(proxy) = proxy::current() {
+        return proxy::connect(proxy, address, Duration::from_secs(5));
+    }
     TcpStream::connect_timeout(&address, Duration::from_millis(500))
-        .map_err(|_| CustomError::CannotConnectToNode)
+        .map_err(|_| CustomError::Network(NetworkError::CannotConnectToNode))
 }
 
 /// get_address_v6 devuelve una direccion ipv6 desde un address.
@@ -32,6 +40,15 @@ pub fn get_address_v6(address: SocketAddr) -> SocketAddrV6 {
     SocketAddrV6::new(ip_v6, address.port(), 0, 0)
 }
 
+/// get_socket_address devuelve un address a partir de una direccion ipv6.
+/// Si la direccion es una ipv4 mapeada a ipv6, devuelve un address ipv4.
+pub fn get_socket_address(address: SocketAddrV6) -> SocketAddr {
+    match address.ip().to_ipv4_mapped() {
+        Some(ip_v4) => SocketAddr::from((ip_v4, address.port())),
+        None => SocketAddr::V6(address),
+    }
+}
+
 /// open_new_file abre un archivo en la ubicacion recibida.
 /// Si el archivo no existe, lo crea.
 /// Si el archivo existe, lo abre.
@@ -74,6 +91,78 @@ pub fn calculate_index_from_timestamp(headers: &Vec<BlockHeader>, last_timestamp
     }
 }
 
+/// hash_to_hex convierte un hash en little endian a hexadecimal, en el orden en que lo muestran los exploradores.
+pub fn hash_to_hex(hash: &[u8]) -> String {
+    hash.iter()
+        .rev()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// hex_to_hash convierte un hash en hexadecimal, en el orden en que lo muestran los exploradores, a little endian.
+/// Devuelve None si no es un hash de 32 bytes en hexadecimal.
+pub fn hex_to_hash(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    hash.reverse();
+    Some(hash)
+}
+
+/// bytes_to_hex convierte bytes a hexadecimal, en el mismo orden.
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// hex_to_bytes convierte un texto en hexadecimal a bytes, en el mismo orden.
+/// Devuelve None si el texto no tiene una cantidad par de digitos hexadecimales.
+pub fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some((hex_digit(*high)? << 4) | hex_digit(*low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|digit| digit as u8)
+}
+
+/// Cantidad minima de elementos para que parallel_map reparta el calculo entre varios threads.
+/// Con menos elementos, crear los threads cuesta mas de lo que se gana.
+const PARALLEL_MIN_ITEMS: usize = 256;
+
+/// parallel_map aplica la funcion a cada elemento y devuelve los resultados en el mismo orden.
+/// Si hay al menos PARALLEL_MIN_ITEMS elementos, los reparte en partes iguales entre un thread por nucleo disponible.
+pub fn parallel_map<T: Sync, U: Send>(items: &[T], function: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    if threads == 1 || items.len() < PARALLEL_MIN_ITEMS {
+        return items.iter().map(function).collect();
+    }
+
+    let function = &function;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(items.len().div_ceil(threads))
+            .map(|chunk| scope.spawn(move || chunk.iter().map(function).collect::<Vec<U>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|error| panic::resume_unwind(error))
+            })
+            .collect()
+    })
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -113,6 +202,15 @@ mod tests {
         assert_eq!(address_v6.port(), 8333);
     }
 
+    #[test]
+    fn get_socket_address_with_mapped_ipv4_returns_ipv4_address() {
+        let address = SocketAddr::from(([127, 0, 0, 1], 8333));
+        assert_eq!(get_socket_address(get_address_v6(address)), address);
+
+        let address = SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 8333));
+        assert_eq!(get_socket_address(get_address_v6(address)), address);
+    }
+
     #[test]
     fn get_address_v6_with_ipv6_address_returns_the_same_address() {
         let address = SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 8333));
@@ -148,4 +246,25 @@ mod tests {
 
         remove_file("tests/does_exist_copy.txt").unwrap();
     }
+
+    #[test]
+    fn hash_to_hex_reverses_the_hash_and_hex_to_hash_restores_it() {
+        let hash: Vec<u8> = (0..32).collect();
+        let hex = hash_to_hex(&hash);
+        assert!(hex.starts_with("1f1e1d"));
+        assert_eq!(hex_to_hash(&hex), Some(hash));
+        assert_eq!(hex_to_hash("1f1e"), None);
+        assert_eq!(hex_to_hash(&"zz".repeat(32)), None);
+    }
+
+    #[test]
+    fn parallel_map_keeps_the_order() {
+        let items: Vec<usize> = (0..PARALLEL_MIN_ITEMS * 3 + 1).collect();
+        let doubled = parallel_map(&items, |item| item * 2);
+        assert_eq!(
+            doubled,
+            items.iter().map(|item| item * 2).collect::<Vec<_>>()
+        );
+        assert_eq!(parallel_map(&items[..3], |item| item + 1), vec![1, 2, 3]);
+    }
 }
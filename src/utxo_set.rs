@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+
+use crate::{
+    atomic_file::{read_atomic, write_atomic},
+    error::CustomError,
+    messages::block::Block,
+    parser::{BufferParser, Parser},
+    states::utxo_state::{UTXOValue, UTXO},
+    structs::{hash256::Hash256, outpoint::OutPoint},
+    wallet::Wallet,
+};
+
+#[derive(Debug, Default, PartialEq)]
+/// WalletUTXO son las UTXO de una wallet junto a su balance.
+/// Los elementos son:
+/// - utxo: UTXO de la wallet con su OutPoint y UTXOValue.
+/// - balance: Suma de los valores de las UTXO de la wallet.
+struct WalletUTXO {
+    utxo: HashMap<OutPoint, UTXOValue>,
+    balance: u64,
+}
+
+impl WalletUTXO {
+    fn insert(&mut self, out_point: OutPoint, value: UTXOValue) {
+        self.balance += value.tx_out.value;
+        if let Some(previous) = self.utxo.insert(out_point, value) {
+            self.balance -= previous.tx_out.value;
+        }
+    }
+
+    fn remove(&mut self, out_point: &OutPoint) -> bool {
+        match self.utxo.remove(out_point) {
+            Some(value) => {
+                self.balance -= value.tx_out.value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// UTXOSet mantiene en disco las UTXO de cada wallet y su balance.
+/// Se actualiza con cada bloque recibido, por lo que obtener el balance o las UTXO de una wallet
+/// no requiere recorrer el UTXO completo.
+/// Si el ultimo bloque procesado no coincide con el del UTXO completo, se regenera a partir de este.
+/// Los elementos son:
+/// - path: Path del archivo donde se guarda el set.
+/// - last_block_hash: Hash del ultimo bloque procesado.
+/// - wallets: UTXO de cada wallet, por su public key.
+pub struct UTXOSet {
+    path: String,
+    last_block_hash: Option<Hash256>,
+    wallets: HashMap<String, WalletUTXO>,
+}
+
+impl UTXOSet {
+    /// Inicializa el set restaurandolo del archivo donde se guarda.
+    /// Si el archivo no existe o no se puede leer, el set comienza vacio.
+    pub fn new(path: String) -> Result<Self, CustomError> {
+        let buffer = read_atomic(&path).unwrap_or_default();
+
+        let (last_block_hash, wallets) = match parse(buffer) {
+            Ok((last_block_hash, wallets)) => (last_block_hash, wallets),
+            Err(_) => (None, HashMap::new()),
+        };
+        Ok(Self {
+            path,
+            last_block_hash,
+            wallets,
+        })
+    }
+
+    /// Sincroniza el set con el UTXO completo.
+    /// Si el ultimo bloque procesado es distinto, regenera las UTXO de todas las wallets;
+    /// si no, solo las de las wallets que todavia no estan en el set.
+    pub fn sync(&mut self, wallets: &[Wallet], utxo: &UTXO) -> Result<(), CustomError> {
+        if self.last_block_hash.as_ref() != utxo.last_block_hash() {
+            self.wallets.clear();
+            self.last_block_hash = utxo.last_block_hash().copied();
+        }
+
+        let mut updated = false;
+        for wallet in wallets {
+            if !self.wallets.contains_key(&wallet.pubkey) {
+                self.insert_wallet(wallet, utxo)?;
+                updated = true;
+            }
+        }
+        if updated {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Agrega una wallet nueva al set, buscando sus UTXO en el UTXO completo.
+    pub fn append_wallet(&mut self, wallet: &Wallet, utxo: &UTXO) -> Result<(), CustomError> {
+        self.insert_wallet(wallet, utxo)?;
+        self.save()
+    }
+
+    /// Actualiza las UTXO de las wallets con un bloque nuevo, eliminando los outputs gastados
+    /// y agregando los outputs enviados a las wallets.
+    /// Devuelve true si alguna wallet fue modificada.
+    pub fn update_from_block(
+        &mut self,
+        block: &Block,
+        wallets: &[Wallet],
+    ) -> Result<bool, CustomError> {
+        let mut pubkey_hashes = vec![];
+        for wallet in wallets {
+            pubkey_hashes.push((wallet.pubkey.clone(), wallet.get_pubkey_hashes()?));
+        }
+
+        let mut updated = false;
+        for tx in &block.transactions {
+            for tx_in in &tx.inputs {
+                for wallet_utxo in self.wallets.values_mut() {
+                    updated |= wallet_utxo.remove(&tx_in.previous_output);
+                }
+            }
+            for (index, tx_out) in tx.outputs.iter().enumerate() {
+                for (pubkey, hashes) in &pubkey_hashes {
+                    if !tx_out.is_sent_to_any_key(hashes)? {
+                        continue;
+                    }
+                    let out_point = OutPoint {
+                        hash: tx.hash(),
+                        index: index as u32,
+                    };
+                    let value = UTXOValue {
+                        tx_out: tx_out.clone(),
+                        block_hash: *block.header.hash(),
+                        block_timestamp: block.header.timestamp,
+                    };
+                    self.wallets
+                        .entry(pubkey.clone())
+                        .or_default()
+                        .insert(out_point, value);
+                    updated = true;
+                }
+            }
+        }
+
+        self.last_block_hash = Some(*block.header.hash());
+        self.save()?;
+        Ok(updated)
+    }
+
+    /// Devuelve el balance de una wallet.
+    pub fn balance(&self, wallet: &Wallet) -> u64 {
+        self.wallets
+            .get(&wallet.pubkey)
+            .map(|wallet_utxo| wallet_utxo.balance)
+            .unwrap_or(0)
+    }
+
+    /// Devuelve las UTXO de una wallet.
+    pub fn wallet_utxo(&self, wallet: &Wallet) -> Vec<(OutPoint, UTXOValue)> {
+        match self.wallets.get(&wallet.pubkey) {
+            Some(wallet_utxo) => wallet_utxo
+                .utxo
+                .iter()
+                .map(|(out_point, value)| (out_point.clone(), value.clone()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    fn insert_wallet(&mut self, wallet: &Wallet, utxo: &UTXO) -> Result<(), CustomError> {
+        let mut wallet_utxo = WalletUTXO::default();
+        for (out_point, value) in utxo.generate_wallet_utxo(wallet)? {
+            wallet_utxo.insert(out_point, value);
+        }
+        self.wallets.insert(wallet.pubkey.clone(), wallet_utxo);
+        Ok(())
+    }
+
+    /// Guarda las UTXO de cada wallet en su archivo.
+    pub fn flush(&self) -> Result<(), CustomError> {
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), CustomError> {
+        write_atomic(&self.path, &self.serialize())
+    }
+
+    /// Serializa el hash del ultimo bloque procesado y las UTXO de cada wallet.
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        match &self.last_block_hash {
+            Some(block_hash) => {
+                buffer.push(1);
+                buffer.extend(block_hash.as_bytes());
+            }
+            None => buffer.push(0),
+        }
+
+        buffer.extend((self.wallets.len() as u32).to_le_bytes());
+        for (pubkey, wallet_utxo) in &self.wallets {
+            buffer.push(pubkey.len() as u8);
+            buffer.extend(pubkey.as_bytes());
+            buffer.extend((wallet_utxo.utxo.len() as u32).to_le_bytes());
+            for (out_point, value) in &wallet_utxo.utxo {
+                buffer.extend(out_point.serialize());
+                buffer.extend(value.serialize());
+            }
+        }
+        buffer
+    }
+}
+
+type ParsedUTXOSet = (Option<Hash256>, HashMap<String, WalletUTXO>);
+
+fn parse(buffer: Vec<u8>) -> Result<ParsedUTXOSet, CustomError> {
+    let mut parser = BufferParser::new(&buffer);
+    let last_block_hash = match parser.extract_u8()? {
+        0 => None,
+        _ => Some(parser.extract_hash32()?.into()),
+    };
+
+    let mut wallets = HashMap::new();
+    for _ in 0..parser.extract_u32()? {
+        let pubkey_len = parser.extract_u8()? as usize;
+        let pubkey = parser.extract_string(pubkey_len)?;
+
+        let mut wallet_utxo = WalletUTXO::default();
+        for _ in 0..parser.extract_u32()? {
+            let out_point = OutPoint::parse(parser.extract_buffer(36)?.to_vec())?;
+            wallet_utxo.insert(out_point, UTXOValue::parse(&mut parser)?);
+        }
+        wallets.insert(pubkey, wallet_utxo);
+    }
+    Ok((last_block_hash, wallets))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        atomic_file::remove_atomic,
+        messages::transaction::Transaction,
+        structs::{
+            block_header::BlockHeader, tx_input::TransactionInput, tx_output::TransactionOutput,
+        },
+        wallet::get_script_pubkey,
+    };
+
+    use super::*;
+
+    const ADDRESS: &str = "mscatccDgq7azndWHFTzvEuZuywCsUvTRu";
+
+    fn wallet() -> Wallet {
+        Wallet {
+            name: String::from("test"),
+            pubkey: String::from(ADDRESS),
+            privkey: Some(String::from(
+                "cNpwEsaVLhju18SJowLtdCNaJtvMvqL4jtFLm2FXw7vZjg4sRWvH",
+            )),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        }
+    }
+
+    fn block(inputs: Vec<OutPoint>, values: Vec<u64>) -> Block {
+        Block {
+            header: BlockHeader {
+                version: 0,
+                prev_block_hash: Hash256::ZERO,
+                merkle_root: Hash256::ZERO,
+                timestamp: 1680000000,
+                bits: 0,
+                nonce: 0,
+                hash: Hash256::new([7; 32]),
+                broadcasted: false,
+                block_downloaded: false,
+            },
+            transactions: vec![Transaction {
+                version: 1,
+                inputs: inputs
+                    .into_iter()
+                    .map(|previous_output| TransactionInput {
+                        previous_output,
+                        script_sig: vec![],
+                        sequence: 0,
+                        witness: vec![],
+                    })
+                    .collect(),
+                outputs: values
+                    .into_iter()
+                    .map(|value| TransactionOutput {
+                        value,
+                        script_pubkey: get_script_pubkey(String::from(ADDRESS)).unwrap(),
+                    })
+                    .collect(),
+                lock_time: 0,
+            }],
+        }
+    }
+
+    #[test]
+    fn balance_is_updated_with_blocks() {
+        let path = String::from("tests/test_utxo_set_update.bin");
+        let wallet = wallet();
+        let mut utxo_set = UTXOSet::new(path.clone()).unwrap();
+        assert_eq!(utxo_set.balance(&wallet), 0);
+
+        let first_block = block(vec![], vec![100, 200]);
+        assert!(utxo_set
+            .update_from_block(&first_block, std::slice::from_ref(&wallet))
+            .unwrap());
+        assert_eq!(utxo_set.balance(&wallet), 300);
+
+        let spent = OutPoint {
+            hash: first_block.transactions[0].hash(),
+            index: 0,
+        };
+        utxo_set
+            .update_from_block(&block(vec![spent], vec![]), std::slice::from_ref(&wallet))
+            .unwrap();
+        assert_eq!(utxo_set.balance(&wallet), 200);
+        assert_eq!(utxo_set.wallet_utxo(&wallet).len(), 1);
+
+        let restored = UTXOSet::new(path.clone()).unwrap();
+        assert_eq!(restored.balance(&wallet), 200);
+        assert_eq!(restored.last_block_hash, Some(Hash256::new([7; 32])));
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn sync_with_full_utxo() {
+        let path = String::from("tests/test_utxo_set_sync.bin");
+        let wallet = wallet();
+        let mut utxo = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        utxo.update_from_block(&block(vec![], vec![500]), false)
+            .unwrap();
+
+        let mut utxo_set = UTXOSet::new(path.clone()).unwrap();
+        utxo_set.sync(std::slice::from_ref(&wallet), &utxo).unwrap();
+        assert_eq!(utxo_set.balance(&wallet), 500);
+        assert_eq!(utxo_set.last_block_hash.as_ref(), utxo.last_block_hash());
+
+        remove_atomic(&path).unwrap();
+    }
+}
@@ -1,19 +1,61 @@
+use std::str::FromStr;
+
+use bip39::{Language, Mnemonic};
+use bitcoin_hashes::{hash160, sha256d, Hash};
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, PublicKey, Secp256k1, SecretKey,
+};
+
 use crate::{
-    error::CustomError, parser::BufferParser, states::utxo_state::UTXO, structs::movement::Movement,
+    address::{Address, Payload},
+    base64,
+    encoding::base58,
+    error::{CustomError, ParseError, WalletError},
+    messages::transaction::Transaction,
+    network::Network,
+    parser::{BufferParser, Parser, VarIntSerialize},
+    states::utxo_state::UTXO,
+    structs::{
+        extended_key::{ExtendedKey, ExtendedPublicKey, HARDENED},
+        hash256::Hash256,
+        movement::Movement,
+        script::{classify::ScriptType, Script},
+        tx_output::TransactionOutput,
+    },
+    wallet_store::WalletBackup,
 };
 
+/// Cantidad de direcciones sin uso que se derivan por delante de la ultima utilizada (BIP 44).
+pub const GAP_LIMIT: u32 = 20;
+const RECEIVE_CHAIN: u32 = 0;
+const CHANGE_CHAIN: u32 = 1;
+/// Flags de las wallets serializadas, que indican que datos opcionales tienen.
+const HD_FLAG: u8 = 1;
+const MULTISIG_FLAG: u8 = 2;
+/// Prefijo de los mensajes firmados, para que la firma de un mensaje no sirva para firmar otra cosa.
+const SIGNED_MESSAGE_MAGIC: &str = "Bitcoin Signed Message:\n";
+/// Primer byte de las firmas de mensajes segun el tipo de direccion de la clave, al que se le suma el recovery id (BIP 137).
+const UNCOMPRESSED_P2PKH_HEADER: u8 = 27;
+const P2PKH_HEADER: u8 = 31;
+const P2WPKH_HEADER: u8 = 39;
+
 #[derive(Clone, Debug)]
 /// Wallet es una estructura que contiene los elementos necesarios para manejar las wallets.
 /// Los elementos son:
 /// - name: Nombre de la wallet.
 /// - pubkey: Public key de la wallet.
-/// - privkey: Private key de la wallet.
+/// - privkey: Private key de la wallet, None si es una wallet watch-only.
 /// - history: Historial de Movements de la wallet.
+/// - hd_account: Cuenta BIP 44 de la wallet, si fue creada a partir de un mnemonico o de una public key extendida.
+/// - multisig_scripts: Redeem scripts multisig de los que participa alguna clave de la wallet, cuyos outputs P2SH le pertenecen.
 pub struct Wallet {
     pub name: String,
     pub pubkey: String,
-    pub privkey: String,
+    pub privkey: Option<String>,
     pub history: Vec<Movement>,
+    pub hd_account: Option<HDAccount>,
+    pub multisig_scripts: Vec<Script>,
 }
 
 impl Wallet {
@@ -30,60 +72,294 @@ impl Wallet {
                 "Name, public key and private key must not be empty".to_string(),
             ));
         }
-        if pubkey.len() != 34 {
+        if get_pubkey_hash(pubkey.clone()).is_err() {
+            return Err(CustomError::Validation(format!(
+                "Public key must be a P2PKH or P2WPKH {} address",
+                Network::current().name()
+            )));
+        }
+        let mut wallet = Self {
+            name,
+            pubkey,
+            privkey: Some(privkey),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        };
+        wallet.generate_history(utxo_set)?;
+        Ok(wallet)
+    }
+
+    /// Inicializa una wallet watch-only, sin private key, que permite seguir su balance e historial pero no enviar.
+    /// La clave puede ser una direccion P2PKH o P2WPKH de la red seleccionada, o la public key extendida (xpub o tpub)
+    /// de una cuenta BIP 44, de la que se derivan las direcciones como en una wallet HD.
+    pub fn watch_only(name: String, key: String, utxo_set: &UTXO) -> Result<Self, CustomError> {
+        if name.is_empty() || key.is_empty() {
             return Err(CustomError::Validation(
-                "Public key must be 34 characters long".to_string(),
+                "Name and public key must not be empty".to_string(),
             ));
         }
+        let (pubkey, hd_account) = match ExtendedPublicKey::from_xpub(&key) {
+            Ok(account_xpub) => {
+                let account = HDAccount::watch_only(account_xpub)?;
+                (account.receive_addresses[0].clone(), Some(account))
+            }
+            Err(_) if get_pubkey_hash(key.clone()).is_ok() => (key, None),
+            Err(_) => {
+                return Err(CustomError::Validation(format!(
+                    "Public key must be a P2PKH or P2WPKH {} address or an extended public key",
+                    Network::current().name()
+                )))
+            }
+        };
+
         let mut wallet = Self {
             name,
             pubkey,
-            privkey,
+            privkey: None,
+            history: vec![],
+            hd_account,
+            multisig_scripts: vec![],
+        };
+        wallet.mark_used_in_utxo(utxo_set)?;
+        wallet.generate_history(utxo_set)?;
+        Ok(wallet)
+    }
+
+    /// Inicializa una wallet HD a partir de un mnemonico BIP 39 y su passphrase.
+    /// Deriva la cuenta m/44'/1'/0' y busca en el utxo las direcciones utilizadas,
+    /// derivando nuevas direcciones hasta tener GAP_LIMIT direcciones sin uso en cada cadena.
+    /// La public key y private key de la wallet son las de la primera direccion de recepcion.
+    pub fn from_mnemonic(
+        name: String,
+        mnemonic: &str,
+        passphrase: &str,
+        utxo_set: &UTXO,
+    ) -> Result<Self, CustomError> {
+        if name.is_empty() {
+            return Err(CustomError::Validation(
+                "Name must not be empty".to_string(),
+            ));
+        }
+        let mnemonic = Mnemonic::parse_in(Language::English, mnemonic)
+            .map_err(|_| CustomError::Validation("Invalid mnemonic".to_string()))?;
+        let master_key = ExtendedKey::from_seed(&mnemonic.to_seed(passphrase))?;
+        let account = HDAccount::new(master_key.derive_path(&account_path())?)?;
+
+        let first_key = account.derive_key(RECEIVE_CHAIN, 0)?;
+        let mut wallet = Self {
+            name,
+            pubkey: first_key.address()?,
+            privkey: Some(first_key.wif()),
             history: vec![],
+            hd_account: Some(account),
+            multisig_scripts: vec![],
+        };
+        wallet.mark_used_in_utxo(utxo_set)?;
+        wallet.generate_history(utxo_set)?;
+        Ok(wallet)
+    }
+
+    /// Inicializa una wallet a partir de una private key en formato WIF de la red seleccionada,
+    /// cuya public key es la direccion P2PKH de la clave.
+    /// Devuelve CustomError si el nombre esta vacio o la clave no es una private key WIF valida.
+    pub fn from_wif(name: String, wif: &str, utxo_set: &UTXO) -> Result<Self, CustomError> {
+        let secret_key = decode_wif(wif)?;
+        let secret_key = SecretKey::from_slice(&secret_key).map_err(|_| invalid_wif())?;
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        let address =
+            Address::p2pkh(&get_hash160(&public_key.serialize()), Network::current()).to_string();
+        Self::new(name, address, wif.trim().to_string(), utxo_set)
+    }
+
+    /// Devuelve la private key de la wallet en formato WIF de la red seleccionada, para importarla en otra wallet.
+    /// En una wallet HD es la clave de la primera direccion de recepcion.
+    /// Devuelve CustomError si la wallet es watch-only o su private key no es una clave WIF valida.
+    pub fn export_wif(&self) -> Result<String, CustomError> {
+        let Some(privkey) = &self.privkey else { return Err(CustomError::Wallet(WalletError::WatchOnlyWallet)) };
+        Ok(encode_wif(&decode_wif(privkey)?))
+    }
+
+    /// Firma un mensaje con la private key de la wallet, para demostrar que se es dueño de su direccion.
+    /// Devuelve la firma en base64, en el formato de los mensajes firmados de Bitcoin Core (BIP 137).
+    /// Devuelve CustomError si la wallet es watch-only o su private key no es una clave WIF valida.
+    pub fn sign_message(&self, message: &str) -> Result<String, CustomError> {
+        let Some(privkey) = &self.privkey else { return Err(CustomError::Wallet(WalletError::WatchOnlyWallet)) };
+        let secret_key = SecretKey::from_slice(&decode_wif(privkey)?).map_err(|_| invalid_wif())?;
+        let signature =
+            Secp256k1::new().sign_ecdsa_recoverable(&signed_message_hash(message)?, &secret_key);
+        let (recovery_id, compact_signature) = signature.serialize_compact();
+
+        let header = match Address::from_str(&self.pubkey)?.is_segwit() {
+            true => P2WPKH_HEADER,
+            false => P2PKH_HEADER,
         };
+        let mut buffer = vec![header + recovery_id.to_i32() as u8];
+        buffer.extend(compact_signature);
+        Ok(base64::encode(&buffer))
+    }
+
+    /// Guarda en path una copia de seguridad de la wallet encriptada con la passphrase, junto a las etiquetas
+    /// de sus transacciones. Ver WalletBackup.
+    /// Devuelve CustomError si la passphrase esta vacia o no se puede escribir el archivo.
+    pub fn export_backup(
+        &self,
+        path: &str,
+        passphrase: &str,
+        labels: Vec<(Hash256, String)>,
+    ) -> Result<(), CustomError> {
+        WalletBackup {
+            wallet: self.clone(),
+            labels,
+        }
+        .save(path, passphrase)
+    }
+
+    /// Restaura una wallet de la copia de seguridad guardada en path con export_backup.
+    /// Genera un historial a partir del utxo, y devuelve la wallet con las etiquetas de sus transacciones.
+    /// Devuelve CustomError si el archivo no es una copia de seguridad o la passphrase es incorrecta.
+    pub fn restore_backup(
+        path: &str,
+        passphrase: &str,
+        utxo_set: &UTXO,
+    ) -> Result<(Self, Vec<(Hash256, String)>), CustomError> {
+        let WalletBackup { mut wallet, labels } = WalletBackup::load(path, passphrase)?;
+        wallet.mark_used_in_utxo(utxo_set)?;
+        wallet.generate_history(utxo_set)?;
+        Ok((wallet, labels))
+    }
+
+    /// Marca como utilizadas las direcciones de la wallet HD que tienen outputs en el utxo,
+    /// derivando nuevas direcciones hasta tener GAP_LIMIT direcciones sin uso en cada cadena.
+    fn mark_used_in_utxo(&mut self, utxo_set: &UTXO) -> Result<(), CustomError> {
+        let outputs: Vec<TransactionOutput> = utxo_set
+            .tx_set
+            .values()
+            .map(|value| value.tx_out.clone())
+            .collect();
+        while self.mark_used_addresses(&outputs)? {}
+        Ok(())
+    }
+
+    fn generate_history(&mut self, utxo_set: &UTXO) -> Result<(), CustomError> {
+        let pubkey_hashes = self.get_pubkey_hashes()?;
         for (outpoint, value) in &utxo_set.tx_set {
-            if value.tx_out.is_sent_to_key(&wallet.get_pubkey_hash()?)? {
-                wallet.history.push(Movement {
-                    tx_hash: outpoint.hash.clone(),
+            if value.tx_out.is_sent_to_any_key(&pubkey_hashes)? {
+                self.history.push(Movement {
+                    tx_hash: outpoint.hash,
                     value: value.tx_out.value as i64,
-                    block_hash: Some(value.block_hash.clone()),
+                    block_hash: Some(value.block_hash),
+                    address: get_address(&value.tx_out.script_pubkey).ok(),
+                    output_type: Some(value.tx_out.script_type().output_type()),
+                    fee: None,
                 });
             }
         }
-        Ok(wallet)
+        Ok(())
     }
 
     /// Serializa la wallet.
+    /// Comienza con sus flags, que indican si es HD y si tiene multisigs, seguidos del nombre y las claves
+    /// con su largo como compact size. Los redeem scripts de los multisigs se guardan al final.
+    /// Las wallets watch-only se guardan con una private key vacia.
     pub fn serialize(&self) -> Vec<u8> {
-        let mut buffer = Vec::new();
-        buffer.push(self.name.len() as u8);
-        buffer.extend(self.name.as_bytes());
-        buffer.push(self.pubkey.len() as u8);
-        buffer.extend(self.pubkey.as_bytes());
-        buffer.push(self.privkey.len() as u8);
-        buffer.extend(self.privkey.as_bytes());
+        let mut flags = 0;
+        if self.hd_account.is_some() {
+            flags |= HD_FLAG;
+        }
+        if !self.multisig_scripts.is_empty() {
+            flags |= MULTISIG_FLAG;
+        }
+        let mut buffer = vec![flags];
+        let privkey = self.privkey.clone().unwrap_or_default();
+        for field in [&self.name, &self.pubkey, &privkey] {
+            buffer.extend(field.len().to_varint_bytes());
+            buffer.extend(field.as_bytes());
+        }
         buffer.extend((self.history.len() as u32).to_le_bytes());
-        for movement in self.history.clone() {
+        for movement in &self.history {
             buffer.extend(movement.serialize());
         }
+        if let Some(hd_account) = &self.hd_account {
+            buffer.extend(hd_account.serialize());
+        }
+        if !self.multisig_scripts.is_empty() {
+            buffer.extend(self.multisig_scripts.len().to_varint_bytes());
+            for script in &self.multisig_scripts {
+                let script = script.serialize();
+                buffer.extend(script.len().to_varint_bytes());
+                buffer.extend(script);
+            }
+        }
         buffer
     }
 
     /// Deserializa la wallet.
+    /// Devuelve CustomError si tiene flags desconocidos o el buffer no alcanza.
     pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
-        let name_len = parser.extract_u8()? as usize;
+        let flags = parser.extract_u8()?;
+        if flags & !(HD_FLAG | MULTISIG_FLAG) != 0 {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        let name_len = parser.extract_compact_size()? as usize;
+        let name = parser.extract_string(name_len)?;
+        let pubkey_len = parser.extract_compact_size()? as usize;
+        let pubkey = parser.extract_string(pubkey_len)?;
+        let privkey_len = parser.extract_compact_size()? as usize;
+        let privkey = match privkey_len {
+            0 => None,
+            _ => Some(parser.extract_string(privkey_len)?),
+        };
+        let (history, hd_account) = Self::parse_history_and_account(parser, flags, &privkey)?;
+
+        let mut multisig_scripts = vec![];
+        if flags & MULTISIG_FLAG != 0 {
+            for _ in 0..parser.extract_compact_size()? {
+                let script_len = parser.extract_compact_size()? as usize;
+                multisig_scripts.push(Script::parse(parser.extract_buffer(script_len)?)?);
+            }
+        }
+
+        Ok(Self {
+            name,
+            pubkey,
+            privkey,
+            history,
+            hd_account,
+            multisig_scripts,
+        })
+    }
+
+    /// Deserializa una wallet guardada antes de que el archivo de wallets tuviera encabezado.
+    /// Las wallets HD comienzan con un 0, que nunca puede ser el largo del nombre de una wallet comun,
+    /// y las wallets con multisigs comienzan con dos 0 seguidos de sus flags.
+    /// Los largos del nombre y las claves se guardan en un byte.
+    pub fn parse_legacy(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        let (flags, name_len) = match parser.extract_u8()? {
+            0 => match parser.extract_u8()? {
+                0 => (parser.extract_u8()?, parser.extract_u8()? as usize),
+                name_len => (HD_FLAG, name_len as usize),
+            },
+            name_len => (0, name_len as usize),
+        };
         let name = parser.extract_string(name_len)?;
 
         let pubkey_len = parser.extract_u8()? as usize;
         let pubkey = parser.extract_string(pubkey_len)?;
 
         let privkey_len = parser.extract_u8()? as usize;
-        let privkey = parser.extract_string(privkey_len)?;
+        let privkey = match privkey_len {
+            0 => None,
+            _ => Some(parser.extract_string(privkey_len)?),
+        };
+        let (history, hd_account) = Self::parse_history_and_account(parser, flags, &privkey)?;
 
-        let history_len = parser.extract_u32()? as usize;
-        let mut history = Vec::new();
-        for _ in 0..history_len {
-            history.push(Movement::parse(parser)?);
+        let mut multisig_scripts = vec![];
+        if flags & MULTISIG_FLAG != 0 {
+            for _ in 0..parser.extract_u8()? {
+                let script_len = parser.extract_u16()? as usize;
+                multisig_scripts.push(Script::parse(parser.extract_buffer(script_len)?)?);
+            }
         }
 
         Ok(Self {
@@ -91,17 +367,80 @@ impl Wallet {
             pubkey,
             privkey,
             history,
+            hd_account,
+            multisig_scripts,
         })
     }
 
+    /// Deserializa el historial de la wallet y su cuenta HD si los flags lo indican.
+    fn parse_history_and_account(
+        parser: &mut BufferParser,
+        flags: u8,
+        privkey: &Option<String>,
+    ) -> Result<(Vec<Movement>, Option<HDAccount>), CustomError> {
+        let history_len = parser.extract_u32()? as usize;
+        let mut history = Vec::new();
+        for _ in 0..history_len {
+            history.push(Movement::parse(parser)?);
+        }
+
+        let hd_account = match (flags & HD_FLAG != 0, privkey) {
+            (true, Some(_)) => Some(HDAccount::parse(parser)?),
+            (true, None) => Some(HDAccount::parse_watch_only(parser)?),
+            (false, _) => None,
+        };
+        Ok((history, hd_account))
+    }
+
     /// Devuelve el hash de la public key de la wallet.
     pub fn get_pubkey_hash(&self) -> Result<Vec<u8>, CustomError> {
         get_pubkey_hash(self.pubkey.clone())
     }
 
+    /// Devuelve los hashes de todas las public keys de la wallet.
+    /// Para una wallet HD incluye todas las direcciones derivadas de recepcion y de cambio.
+    /// Incluye tambien los hashes de los redeem scripts multisig, que identifican a los outputs P2SH de la wallet.
+    pub fn get_pubkey_hashes(&self) -> Result<Vec<Vec<u8>>, CustomError> {
+        let mut hashes = self.get_key_hashes()?;
+        hashes.extend(self.multisig_scripts.iter().map(Script::hash160));
+        Ok(hashes)
+    }
+
+    /// Devuelve los hashes de las public keys de la wallet, sin los de sus multisigs.
+    fn get_key_hashes(&self) -> Result<Vec<Vec<u8>>, CustomError> {
+        match &self.hd_account {
+            Some(hd_account) => hd_account
+                .addresses()
+                .map(|address| get_pubkey_hash(address.clone()))
+                .collect(),
+            None => Ok(vec![self.get_pubkey_hash()?]),
+        }
+    }
+
+    /// Devuelve true si la wallet es watch-only, es decir que no tiene private key y no puede firmar transacciones.
+    pub fn is_watch_only(&self) -> bool {
+        self.privkey.is_none()
+    }
+
     /// Devuelve el hash de la private key de la wallet.
+    /// Devuelve CustomError si la wallet es watch-only.
     pub fn get_privkey_hash(&self) -> Result<Vec<u8>, CustomError> {
-        get_privkey_hash(self.privkey.clone())
+        let Some(privkey) = &self.privkey else { return Err(CustomError::Wallet(WalletError::WatchOnlyWallet)) };
+        get_privkey_hash(privkey.clone())
+    }
+
+    /// Devuelve el hash de la private key que puede gastar un output con el script pubkey recibido.
+    /// Para una wallet comun es siempre su private key.
+    /// Devuelve CustomError si la wallet es watch-only o si la wallet HD no tiene una direccion con ese script pubkey.
+    pub fn get_signing_key(&self, script_pubkey: &[u8]) -> Result<Vec<u8>, CustomError> {
+        if self.is_watch_only() {
+            return Err(CustomError::Wallet(WalletError::WatchOnlyWallet));
+        }
+        let Some(hd_account) = &self.hd_account else { return self.get_privkey_hash() };
+        hd_account
+            .find_key(script_pubkey)?
+            .map(|key| key.secret_key)
+            .ok_or(CustomError::Wallet(WalletError::CannotSignTx))
     }
 
     /// Devuelve el script pubkey de la wallet.
@@ -109,6 +448,124 @@ impl Wallet {
         get_script_pubkey(self.pubkey.clone())
     }
 
+    /// Devuelve los script pubkeys de todas las direcciones de la wallet.
+    /// Cada clave puede recibir tanto en su direccion P2PKH como en su direccion P2WPKH,
+    /// y cada multisig en su direccion P2SH.
+    pub fn get_script_pubkeys(&self) -> Result<Vec<Vec<u8>>, CustomError> {
+        let mut script_pubkeys = vec![];
+        for pubkey_hash in self.get_key_hashes()? {
+            script_pubkeys.push(get_p2pkh_script_pubkey(&pubkey_hash));
+            script_pubkeys.push(get_p2wpkh_script_pubkey(&pubkey_hash));
+        }
+        for script in &self.multisig_scripts {
+            script_pubkeys.push(Script::p2sh(&script.hash160()).serialize());
+        }
+        Ok(script_pubkeys)
+    }
+
+    /// Agrega a la wallet un redeem script multisig m-of-n del que participa alguna de sus claves,
+    /// para reconocer los outputs P2SH que le envian fondos y poder co-firmar los gastos.
+    /// Devuelve la direccion P2SH del multisig.
+    /// Devuelve CustomError si el multisig no es valido o si ninguna de sus public keys es de la wallet.
+    pub fn add_multisig(
+        &mut self,
+        required: u8,
+        pubkeys: &[Vec<u8>],
+    ) -> Result<String, CustomError> {
+        let script = Script::multisig(required, pubkeys)?;
+        let key_hashes = self.get_key_hashes()?;
+        if !pubkeys
+            .iter()
+            .any(|pubkey| key_hashes.contains(&get_hash160(pubkey)))
+        {
+            return Err(CustomError::Validation(String::from(
+                "Multisig must include a public key of the wallet",
+            )));
+        }
+
+        let address = Address::p2sh(&script.hash160(), Network::current()).to_string();
+        if !self.multisig_scripts.contains(&script) {
+            self.multisig_scripts.push(script);
+        }
+        Ok(address)
+    }
+
+    /// Devuelve el redeem script multisig de la wallet al que envia fondos un script pubkey P2SH.
+    pub fn find_multisig(&self, script_pubkey: &[u8]) -> Option<&Script> {
+        let script_pubkey = Script::parse(script_pubkey).ok()?;
+        let script_hash = script_pubkey.p2sh_script_hash()?;
+        self.multisig_scripts
+            .iter()
+            .find(|script| script.hash160() == script_hash)
+    }
+
+    /// Devuelve el hash de la private key con la que la wallet firma un multisig,
+    /// la de la primera de sus public keys que es de la wallet.
+    /// Devuelve CustomError si la wallet es watch-only o si el script no es un multisig con una clave de la wallet.
+    pub fn get_multisig_signing_key(&self, redeem_script: &Script) -> Result<Vec<u8>, CustomError> {
+        if self.is_watch_only() {
+            return Err(CustomError::Wallet(WalletError::WatchOnlyWallet));
+        }
+        let Some((_, pubkeys)) = redeem_script.multisig_keys() else { return Err(CustomError::Wallet(WalletError::CannotSignTx)) };
+        let key_hashes = self.get_key_hashes()?;
+        let Some(pubkey_hash) = pubkeys
+            .iter()
+            .map(|pubkey| get_hash160(pubkey))
+            .find(|pubkey_hash| key_hashes.contains(pubkey_hash))
+        else {
+            return Err(CustomError::Wallet(WalletError::CannotSignTx));
+        };
+        self.get_signing_key(&get_p2pkh_script_pubkey(&pubkey_hash))
+    }
+
+    /// Devuelve el path de derivacion y la public key de la clave que puede gastar un output con el script pubkey recibido,
+    /// para firmarlo con la hardware wallet de la que se importo la cuenta.
+    /// Devuelve None si la wallet no es HD o si no tiene una direccion con ese script pubkey.
+    pub fn get_key_origin(&self, script_pubkey: &[u8]) -> Result<Option<KeyOrigin>, CustomError> {
+        match &self.hd_account {
+            Some(hd_account) => hd_account.find_key_origin(script_pubkey),
+            None => Ok(None),
+        }
+    }
+
+    /// Devuelve la direccion bech32 (P2WPKH) de la wallet, correspondiente a la misma clave que su public key.
+    pub fn get_bech32_address(&self) -> Result<String, CustomError> {
+        Ok(Address::p2wpkh(&self.get_pubkey_hash()?, Network::current()).to_string())
+    }
+
+    /// Devuelve la direccion en la que recibir fondos.
+    /// Para una wallet HD es la primera direccion de recepcion sin uso, que cambia una vez que recibe fondos.
+    pub fn get_receive_address(&self) -> String {
+        match &self.hd_account {
+            Some(hd_account) => hd_account.receive_address(),
+            None => self.pubkey.clone(),
+        }
+    }
+
+    /// Devuelve la direccion a la que se envia el cambio de una transaccion.
+    /// Para una wallet HD es la primera direccion de cambio sin uso.
+    pub fn get_change_address(&self) -> String {
+        match &self.hd_account {
+            Some(hd_account) => hd_account.change_address(),
+            None => self.pubkey.clone(),
+        }
+    }
+
+    /// Marca como utilizadas las direcciones de la wallet HD que reciben alguno de los outputs,
+    /// derivando nuevas direcciones para respetar el gap limit.
+    /// Devuelve true si se derivaron direcciones nuevas.
+    pub fn mark_used_addresses(
+        &mut self,
+        outputs: &[TransactionOutput],
+    ) -> Result<bool, CustomError> {
+        let Some(hd_account) = &mut self.hd_account else { return Ok(false) };
+        let mut updated = false;
+        for output in outputs {
+            updated |= hd_account.mark_used(&output.script_pubkey)?;
+        }
+        Ok(updated)
+    }
+
     /// Actualiza el historial de la wallet.
     pub fn update_history(&mut self, movement: Movement) {
         self.history.push(movement);
@@ -118,15 +575,309 @@ impl Wallet {
     pub fn get_history(&self) -> Vec<Movement> {
         self.history.clone()
     }
+
+    /// Devuelve el movimiento de la wallet en una transaccion, considerando todas sus direcciones.
+    pub fn get_movement(
+        &self,
+        tx: &Transaction,
+        utxo: &UTXO,
+    ) -> Result<Option<Movement>, CustomError> {
+        tx.get_movement(&self.get_pubkey_hashes()?, utxo)
+    }
 }
 
-/// Devuelve el hash de una public key.
-pub fn get_pubkey_hash(pubkey: String) -> Result<Vec<u8>, CustomError> {
-    let decoded_pubkey = bs58::decode(pubkey)
-        .into_vec()
-        .map_err(|_| CustomError::Validation(String::from("User PubKey incorrectly formatted")))?;
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// KeyOrigin es el origen de una clave de una cuenta HD, lo que necesita una hardware wallet para firmar con ella.
+/// Los elementos son:
+/// - path: Path de derivacion completo de la clave desde la master key, con los indices endurecidos.
+/// - public_key: Public key comprimida de la clave.
+pub struct KeyOrigin {
+    pub path: Vec<u32>,
+    pub public_key: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+/// HDAccount es una cuenta BIP 44 de una wallet HD.
+/// Las direcciones se derivan de la public key extendida de la cuenta, por lo que una cuenta watch-only,
+/// sin la private key extendida, tambien puede generarlas.
+/// Los elementos son:
+/// - account_key: Clave extendida de la cuenta (m/44'/1'/0'), None si la cuenta es watch-only.
+/// - account_xpub: Public key extendida de la cuenta.
+/// - receive_addresses: Direcciones derivadas de la cadena de recepcion.
+/// - change_addresses: Direcciones derivadas de la cadena de cambio.
+/// - used_receive: Cantidad de direcciones de recepcion hasta la ultima utilizada.
+/// - used_change: Cantidad de direcciones de cambio hasta la ultima utilizada.
+pub struct HDAccount {
+    pub account_key: Option<ExtendedKey>,
+    pub account_xpub: ExtendedPublicKey,
+    pub receive_addresses: Vec<String>,
+    pub change_addresses: Vec<String>,
+    pub used_receive: u32,
+    pub used_change: u32,
+}
+
+impl HDAccount {
+    /// Inicializa la cuenta derivando GAP_LIMIT direcciones en cada cadena.
+    pub fn new(account_key: ExtendedKey) -> Result<Self, CustomError> {
+        let mut account = Self {
+            account_xpub: account_key.extended_public_key()?,
+            account_key: Some(account_key),
+            receive_addresses: vec![],
+            change_addresses: vec![],
+            used_receive: 0,
+            used_change: 0,
+        };
+        account.derive_addresses()?;
+        Ok(account)
+    }
+
+    /// Inicializa una cuenta watch-only a partir de su public key extendida, derivando GAP_LIMIT direcciones en cada cadena.
+    pub fn watch_only(account_xpub: ExtendedPublicKey) -> Result<Self, CustomError> {
+        let mut account = Self {
+            account_key: None,
+            account_xpub,
+            receive_addresses: vec![],
+            change_addresses: vec![],
+            used_receive: 0,
+            used_change: 0,
+        };
+        account.derive_addresses()?;
+        Ok(account)
+    }
+
+    /// Deriva la clave de una direccion: m/44'/1'/0'/chain/index.
+    /// Devuelve CustomError si la cuenta es watch-only.
+    pub fn derive_key(&self, chain: u32, index: u32) -> Result<ExtendedKey, CustomError> {
+        let Some(account_key) = &self.account_key else { return Err(CustomError::Wallet(WalletError::WatchOnlyWallet)) };
+        account_key.derive_path(&[chain, index])
+    }
+
+    /// Devuelve todas las direcciones derivadas, primero las de recepcion y luego las de cambio.
+    pub fn addresses(&self) -> impl Iterator<Item = &String> {
+        self.receive_addresses
+            .iter()
+            .chain(self.change_addresses.iter())
+    }
+
+    /// Devuelve la primera direccion de recepcion sin uso.
+    pub fn receive_address(&self) -> String {
+        self.receive_addresses[self.used_receive as usize].clone()
+    }
+
+    /// Devuelve la primera direccion de cambio sin uso.
+    pub fn change_address(&self) -> String {
+        self.change_addresses[self.used_change as usize].clone()
+    }
+
+    /// Busca la clave de la direccion con el script pubkey recibido.
+    pub fn find_key(&self, script_pubkey: &[u8]) -> Result<Option<ExtendedKey>, CustomError> {
+        match self.find_address(script_pubkey)? {
+            Some((chain, index)) => Ok(Some(self.derive_key(chain, index)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Busca el path completo (m/44'/coin_type'/0'/chain/index) y la public key de la direccion con el script pubkey recibido.
+    /// Solo requiere la public key extendida, por lo que sirve para cuentas watch-only.
+    pub fn find_key_origin(&self, script_pubkey: &[u8]) -> Result<Option<KeyOrigin>, CustomError> {
+        let Some((chain, index)) = self.find_address(script_pubkey)? else { return Ok(None) };
+        let mut path = account_path().to_vec();
+        path.extend([chain, index]);
+        Ok(Some(KeyOrigin {
+            path,
+            public_key: self.account_xpub.derive_path(&[chain, index])?.public_key,
+        }))
+    }
+
+    /// Marca como utilizada la direccion con el script pubkey recibido.
+    /// Devuelve true si se derivaron direcciones nuevas.
+    fn mark_used(&mut self, script_pubkey: &[u8]) -> Result<bool, CustomError> {
+        let Some((chain, index)) = self.find_address(script_pubkey)? else { return Ok(false) };
+        let used = match chain {
+            RECEIVE_CHAIN => &mut self.used_receive,
+            _ => &mut self.used_change,
+        };
+        if index < *used {
+            return Ok(false);
+        }
+        *used = index + 1;
+        self.derive_addresses()?;
+        Ok(true)
+    }
+
+    fn find_address(&self, script_pubkey: &[u8]) -> Result<Option<(u32, u32)>, CustomError> {
+        let chains = [
+            (RECEIVE_CHAIN, &self.receive_addresses),
+            (CHANGE_CHAIN, &self.change_addresses),
+        ];
+        let owner_hash = match ScriptType::classify(script_pubkey) {
+            script_type @ (ScriptType::P2PK(_) | ScriptType::P2PKH(_) | ScriptType::P2WPKH(_)) => {
+                script_type.owner_hash()
+            }
+            _ => None,
+        };
+        let Some(owner_hash) = owner_hash else { return Ok(None) };
+        for (chain, addresses) in chains {
+            for (index, address) in addresses.iter().enumerate() {
+                if get_pubkey_hash(address.clone())? == owner_hash {
+                    return Ok(Some((chain, index as u32)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Deriva direcciones hasta tener GAP_LIMIT direcciones sin uso en cada cadena.
+    fn derive_addresses(&mut self) -> Result<(), CustomError> {
+        while (self.receive_addresses.len() as u32) < self.used_receive + GAP_LIMIT {
+            let index = self.receive_addresses.len() as u32;
+            let address = self
+                .account_xpub
+                .derive_path(&[RECEIVE_CHAIN, index])?
+                .address();
+            self.receive_addresses.push(address);
+        }
+        while (self.change_addresses.len() as u32) < self.used_change + GAP_LIMIT {
+            let index = self.change_addresses.len() as u32;
+            let address = self
+                .account_xpub
+                .derive_path(&[CHANGE_CHAIN, index])?
+                .address();
+            self.change_addresses.push(address);
+        }
+        Ok(())
+    }
+
+    /// Serializa la cuenta. Las direcciones no se guardan, se vuelven a derivar al parsear.
+    /// Se guarda la private key extendida, o la public key extendida si la cuenta es watch-only.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = match &self.account_key {
+            Some(account_key) => account_key.serialize(),
+            None => self.account_xpub.serialize(),
+        };
+        buffer.extend(self.used_receive.to_le_bytes());
+        buffer.extend(self.used_change.to_le_bytes());
+        buffer
+    }
 
-    match decoded_pubkey.get(1..21) {
+    /// Deserializa la cuenta.
+    pub fn parse(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        let account = Self::new(ExtendedKey::parse(parser)?)?;
+        account.parse_used_addresses(parser)
+    }
+
+    /// Deserializa una cuenta watch-only.
+    pub fn parse_watch_only(parser: &mut BufferParser) -> Result<Self, CustomError> {
+        let account = Self::watch_only(ExtendedPublicKey::parse(parser)?)?;
+        account.parse_used_addresses(parser)
+    }
+
+    fn parse_used_addresses(mut self, parser: &mut BufferParser) -> Result<Self, CustomError> {
+        self.used_receive = parser.extract_u32()?;
+        self.used_change = parser.extract_u32()?;
+        self.derive_addresses()?;
+        Ok(self)
+    }
+}
+
+/// Devuelve el path de la cuenta BIP 44 de las wallets en la red seleccionada: m/44'/coin_type'/0'.
+fn account_path() -> [u32; 3] {
+    [
+        44 | HARDENED,
+        Network::current().coin_type() | HARDENED,
+        HARDENED,
+    ]
+}
+
+/// Verifica la firma de un mensaje firmado con la clave de una direccion, en el formato de sign_message.
+/// Devuelve true si la clave que firmo el mensaje es la de la direccion.
+/// Devuelve CustomError si la direccion no es de la red seleccionada o la firma no es valida.
+pub fn verify_message(address: &str, signature: &str, message: &str) -> Result<bool, CustomError> {
+    let address = Address::from_str(address)?;
+    let signature = base64::decode(signature.trim()).map_err(|_| invalid_message_signature())?;
+    if signature.len() != 65
+        || !(UNCOMPRESSED_P2PKH_HEADER..P2WPKH_HEADER + 4).contains(&signature[0])
+    {
+        return Err(invalid_message_signature());
+    }
+    let header = signature[0] - UNCOMPRESSED_P2PKH_HEADER;
+    let recovery_id =
+        RecoveryId::from_i32((header % 4) as i32).map_err(|_| invalid_message_signature())?;
+    let signature = RecoverableSignature::from_compact(&signature[1..], recovery_id)
+        .map_err(|_| invalid_message_signature())?;
+    let Ok(public_key) = Secp256k1::new().recover_ecdsa(&signed_message_hash(message)?, &signature)
+    else {
+        return Ok(false);
+    };
+
+    // cada tipo de direccion tiene 4 headers, uno por recovery id
+    let signer_payload = match header / 4 {
+        0 => Payload::PubkeyHash(get_hash160(&public_key.serialize_uncompressed())),
+        1 => Payload::PubkeyHash(get_hash160(&public_key.serialize())),
+        2 => {
+            let redeem_script = get_p2wpkh_script_pubkey(&get_hash160(&public_key.serialize()));
+            Payload::ScriptHash(get_hash160(&redeem_script))
+        }
+        _ => Payload::WitnessProgram(0, get_hash160(&public_key.serialize())),
+    };
+    Ok(signer_payload == address.payload)
+}
+
+/// Devuelve el hash que se firma para un mensaje: el doble sha256 del prefijo y el mensaje, cada uno precedido por su largo.
+fn signed_message_hash(message: &str) -> Result<Message, CustomError> {
+    let mut buffer = SIGNED_MESSAGE_MAGIC.len().to_varint_bytes();
+    buffer.extend(SIGNED_MESSAGE_MAGIC.as_bytes());
+    buffer.extend(message.len().to_varint_bytes());
+    buffer.extend(message.as_bytes());
+    Message::from_slice(&sha256d::Hash::hash(&buffer).to_byte_array())
+        .map_err(|_| invalid_message_signature())
+}
+
+fn invalid_message_signature() -> CustomError {
+    CustomError::Validation(String::from("Invalid message signature"))
+}
+
+/// Codifica una private key de 32 bytes en formato WIF de la red seleccionada, para una public key comprimida.
+pub fn encode_wif(secret_key: &[u8]) -> String {
+    let mut payload = vec![Network::current().wif_prefix()];
+    payload.extend(secret_key);
+    payload.push(0x01);
+    base58::encode_check(&payload)
+}
+
+/// Decodifica una private key en formato WIF de la red seleccionada y devuelve sus 32 bytes.
+/// Devuelve CustomError si el checksum es invalido, si la clave es de otra red
+/// o si corresponde a una public key sin comprimir, que la wallet no soporta.
+pub fn decode_wif(wif: &str) -> Result<Vec<u8>, CustomError> {
+    let payload = base58::decode_check(wif.trim()).map_err(|_| invalid_wif())?;
+    let Some((prefix, key)) = payload.split_first() else { return Err(invalid_wif()) };
+    if *prefix != Network::current().wif_prefix() {
+        return Err(invalid_wif());
+    }
+    match key {
+        [secret_key @ .., 0x01] if secret_key.len() == 32 => Ok(secret_key.to_vec()),
+        secret_key if secret_key.len() == 32 => Err(CustomError::Validation(String::from(
+            "Uncompressed private keys are not supported",
+        ))),
+        _ => Err(invalid_wif()),
+    }
+}
+
+fn invalid_wif() -> CustomError {
+    CustomError::Validation(format!(
+        "Private key must be a {} WIF key",
+        Network::current().name()
+    ))
+}
+
+/// Devuelve el hash160 de una public key, el hash que aparece en sus direcciones.
+fn get_hash160(pubkey: &[u8]) -> Vec<u8> {
+    hash160::Hash::hash(pubkey).to_byte_array().to_vec()
+}
+
+/// Devuelve el hash de una public key, a partir de su direccion P2PKH o P2WPKH de la red seleccionada.
+pub fn get_pubkey_hash(pubkey: String) -> Result<Vec<u8>, CustomError> {
+    match Address::from_str(&pubkey)?.pubkey_hash() {
         Some(pubkey_hash) => Ok(pubkey_hash.to_vec()),
         None => Err(CustomError::Validation(String::from(
             "User PubKey incorrectly formatted",
@@ -136,8 +887,7 @@ pub fn get_pubkey_hash(pubkey: String) -> Result<Vec<u8>, CustomError> {
 
 /// Devuelve el hash de una private key.
 pub fn get_privkey_hash(privkey: String) -> Result<Vec<u8>, CustomError> {
-    let decoded_privkey = bs58::decode(privkey)
-        .into_vec()
+    let decoded_privkey = base58::decode_check(&privkey)
         .map_err(|_| CustomError::Validation(String::from("User PrivKey incorrectly formatted")))?;
 
     match decoded_privkey.get(1..33) {
@@ -148,16 +898,29 @@ pub fn get_privkey_hash(privkey: String) -> Result<Vec<u8>, CustomError> {
     }
 }
 
-/// Devuelve el script pubkey de una public key.
+/// Devuelve el script pubkey de una direccion de cualquier red.
+/// Para las direcciones bech32 es OP_0 seguido del witness program, para las P2SH es un script P2SH
+/// y para las P2PKH es un script P2PKH.
+/// Devuelve CustomError::Validation con el motivo si la direccion no es valida.
 pub fn get_script_pubkey(pubkey: String) -> Result<Vec<u8>, CustomError> {
-    let mut script_pubkey = Vec::new();
-    script_pubkey.push(0x76);
-    script_pubkey.push(0xa9);
-    script_pubkey.push(0x14);
-    script_pubkey.extend(get_pubkey_hash(pubkey)?);
-    script_pubkey.push(0x88);
-    script_pubkey.push(0xac);
-    Ok(script_pubkey)
+    Ok(Address::decode(&pubkey)?.script_pubkey())
+}
+
+/// Devuelve la direccion a la que envia fondos un script pubkey P2PKH, P2SH, P2WPKH, P2WSH o P2TR.
+/// Es la operacion inversa de get_script_pubkey.
+/// Devuelve CustomError si el script pubkey no es de ninguno de esos tipos.
+pub fn get_address(script_pubkey: &[u8]) -> Result<String, CustomError> {
+    Ok(Address::from_script_pubkey(script_pubkey, Network::current())?.to_string())
+}
+
+/// Devuelve el script pubkey P2PKH de un hash de public key.
+pub fn get_p2pkh_script_pubkey(pubkey_hash: &[u8]) -> Vec<u8> {
+    Script::p2pkh(pubkey_hash).serialize()
+}
+
+/// Devuelve el script pubkey P2WPKH de un hash de public key.
+pub fn get_p2wpkh_script_pubkey(pubkey_hash: &[u8]) -> Vec<u8> {
+    Script::p2wpkh(pubkey_hash).serialize()
 }
 
 #[cfg(test)]
@@ -182,7 +945,7 @@ mod tests {
             wallet.pubkey,
             String::from("mscatccDgq7azndWHFTzvEuZuywCsUvTRu")
         );
-        assert_eq!(wallet.privkey, String::from("privkey"));
+        assert_eq!(wallet.privkey, Some(String::from("privkey")));
         assert_eq!(wallet.history.len(), 0);
     }
 
@@ -239,15 +1002,17 @@ mod tests {
         let wallet = Wallet {
             name: String::from("test"),
             pubkey: String::from("pubkey"),
-            privkey: String::from("privkey"),
+            privkey: Some(String::from("privkey")),
             history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
         };
         let serialized_wallet = wallet.serialize();
-        let mut parser = BufferParser::new(serialized_wallet);
+        let mut parser = BufferParser::new(&serialized_wallet);
         let parsed_wallet = Wallet::parse(&mut parser).unwrap();
         assert_eq!(parsed_wallet.name, String::from("test"));
         assert_eq!(parsed_wallet.pubkey, String::from("pubkey"));
-        assert_eq!(parsed_wallet.privkey, String::from("privkey"));
+        assert_eq!(parsed_wallet.privkey, Some(String::from("privkey")));
     }
 
     #[test]
@@ -255,29 +1020,34 @@ mod tests {
         let mut wallet = Wallet {
             name: String::from("test"),
             pubkey: String::from("pubkey"),
-            privkey: String::from("privkey"),
+            privkey: Some(String::from("privkey")),
             history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
         };
         wallet.update_history(Movement {
-            tx_hash: vec![
+            tx_hash: Hash256::new([
                 158, 58, 146, 241, 218, 207, 194, 196, 103, 192, 89, 27, 56, 110, 195, 138, 29,
                 177, 167, 47, 144, 191, 102, 68, 45, 70, 88, 237, 140, 224, 130, 115,
-            ],
+            ]),
             value: 500,
-            block_hash: Some(vec![
+            block_hash: Some(Hash256::new([
                 167, 131, 118, 190, 70, 199, 31, 2, 255, 135, 123, 36, 232, 182, 60, 178, 98, 181,
                 242, 112, 111, 183, 22, 128, 11, 0, 0, 0, 0, 0, 0, 0,
-            ]),
+            ])),
+            address: None,
+            output_type: None,
+            fee: None,
         });
         let serialized_wallet = wallet.serialize();
-        let mut parser = BufferParser::new(serialized_wallet);
+        let mut parser = BufferParser::new(&serialized_wallet);
         let parsed_wallet = Wallet::parse(&mut parser).unwrap();
         assert_eq!(
             parsed_wallet.history[0].block_hash,
-            Some(vec![
+            Some(Hash256::new([
                 167, 131, 118, 190, 70, 199, 31, 2, 255, 135, 123, 36, 232, 182, 60, 178, 98, 181,
                 242, 112, 111, 183, 22, 128, 11, 0, 0, 0, 0, 0, 0, 0
-            ])
+            ]))
         );
     }
 
@@ -286,8 +1056,10 @@ mod tests {
         let wallet = Wallet {
             name: String::from("test"),
             pubkey: String::from("mscatccDgq7azndWHFTzvEuZuywCsUvTRu"),
-            privkey: String::from("privkey"),
+            privkey: Some(String::from("privkey")),
             history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
         };
         let pubkey_hash = wallet.get_pubkey_hash().unwrap();
         assert_eq!(
@@ -304,8 +1076,10 @@ mod tests {
         let wallet = Wallet {
             name: String::from("test"),
             pubkey: String::from("mscatccDgq7azndWHFTzvEuZuywCsUvTRu"),
-            privkey: String::from("privkey"),
+            privkey: Some(String::from("privkey")),
             history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
         };
         let script_pubkey = wallet.get_script_pubkey().unwrap();
         assert_eq!(
@@ -322,8 +1096,12 @@ mod tests {
         let wallet = Wallet {
             name: String::from("test"),
             pubkey: String::from("pubkey"),
-            privkey: String::from("cNpwEsaVLhju18SJowLtdCNaJtvMvqL4jtFLm2FXw7vZjg4sRWvH"),
+            privkey: Some(String::from(
+                "cNpwEsaVLhju18SJowLtdCNaJtvMvqL4jtFLm2FXw7vZjg4sRWvH",
+            )),
             history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
         };
         let privkey_hash = wallet.get_privkey_hash().unwrap();
         assert_eq!(
@@ -340,10 +1118,408 @@ mod tests {
         let wallet = Wallet {
             name: String::from("test"),
             pubkey: String::from("pubkey"),
-            privkey: String::from("test"),
+            privkey: Some(String::from("test")),
             history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
         };
         let privkey_hash = wallet.get_privkey_hash();
         assert!(privkey_hash.is_err());
     }
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn hd_wallet() -> Wallet {
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        Wallet::from_mnemonic(String::from("hd"), MNEMONIC, "", &utxo_set).unwrap()
+    }
+
+    #[test]
+    fn hd_wallet_from_mnemonic() {
+        let wallet = hd_wallet();
+        assert_eq!(wallet.pubkey, "mkpZhYtJu2r87Js3pDiWJDmPte2NRZ8bJV");
+        assert_eq!(
+            wallet.get_privkey_hash().unwrap(),
+            wallet
+                .get_signing_key(&wallet.get_script_pubkey().unwrap())
+                .unwrap()
+        );
+        assert_eq!(
+            wallet.get_pubkey_hashes().unwrap().len(),
+            2 * GAP_LIMIT as usize
+        );
+        assert_ne!(wallet.get_change_address(), wallet.pubkey);
+    }
+
+    #[test]
+    fn signed_messages_are_verified() {
+        let wallet = hd_wallet();
+        let signature = wallet.sign_message("hello world").unwrap();
+        assert_eq!(base64::decode(&signature).unwrap().len(), 65);
+        assert!(verify_message(&wallet.pubkey, &signature, "hello world").unwrap());
+        assert!(!verify_message(&wallet.pubkey, &signature, "hello world!").unwrap());
+        assert!(!verify_message(
+            &wallet.get_bech32_address().unwrap(),
+            &signature,
+            "hello world"
+        )
+        .unwrap());
+
+        // las wallets bech32 firman con el header de P2WPKH
+        let segwit_wallet = Wallet {
+            pubkey: wallet.get_bech32_address().unwrap(),
+            ..wallet.clone()
+        };
+        let signature = segwit_wallet.sign_message("hello world").unwrap();
+        assert!(base64::decode(&signature).unwrap()[0] >= P2WPKH_HEADER);
+        assert!(verify_message(&segwit_wallet.pubkey, &signature, "hello world").unwrap());
+        assert!(!verify_message(&wallet.pubkey, &signature, "hello world").unwrap());
+    }
+
+    #[test]
+    fn invalid_message_signatures_are_rejected() {
+        let wallet = hd_wallet();
+        assert!(verify_message(&wallet.pubkey, "not base64!", "hello").is_err());
+        assert!(verify_message(&wallet.pubkey, &base64::encode(&[31; 64]), "hello").is_err());
+        assert!(
+            verify_message("invalid", &wallet.sign_message("hello").unwrap(), "hello").is_err()
+        );
+
+        let watch_only = Wallet {
+            privkey: None,
+            ..wallet
+        };
+        assert!(matches!(
+            watch_only.sign_message("hello"),
+            Err(CustomError::Wallet(WalletError::WatchOnlyWallet))
+        ));
+    }
+
+    #[test]
+    fn wallet_from_wif() {
+        let hd_wallet = hd_wallet();
+        let wif = hd_wallet.export_wif().unwrap();
+        assert_eq!(Some(&wif), hd_wallet.privkey.as_ref());
+
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        let wallet = Wallet::from_wif(String::from("imported"), &wif, &utxo_set).unwrap();
+        assert_eq!(wallet.pubkey, hd_wallet.pubkey);
+        assert_eq!(
+            wallet.get_privkey_hash().unwrap(),
+            hd_wallet.get_privkey_hash().unwrap()
+        );
+        assert_eq!(wallet.export_wif().unwrap(), wif);
+        assert_eq!(
+            encode_wif(&decode_wif(&wif).unwrap()),
+            wif,
+            "encoding is the inverse of decoding"
+        );
+    }
+
+    #[test]
+    fn invalid_wif_keys_are_rejected() {
+        let wif = hd_wallet().export_wif().unwrap();
+        let mut decoded = base58::decode(&wif).unwrap();
+
+        // checksum invalido
+        let last = decoded.len() - 1;
+        decoded[last] ^= 1;
+        assert!(decode_wif(&base58::encode(&decoded)).is_err());
+
+        // clave de mainnet
+        let mut payload = vec![Network::Mainnet.wif_prefix()];
+        payload.extend(decode_wif(&wif).unwrap());
+        payload.push(0x01);
+        assert!(decode_wif(&base58::encode_check(&payload)).is_err());
+
+        // public key sin comprimir
+        let mut payload = vec![Network::current().wif_prefix()];
+        payload.extend(decode_wif(&wif).unwrap());
+        assert!(decode_wif(&base58::encode_check(&payload)).is_err());
+
+        let watch_only = Wallet {
+            privkey: None,
+            ..hd_wallet()
+        };
+        assert!(watch_only.export_wif().is_err());
+    }
+
+    #[test]
+    fn hd_wallet_with_invalid_mnemonic() {
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        let wallet = Wallet::from_mnemonic(
+            String::from("hd"),
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon",
+            "",
+            &utxo_set,
+        );
+        assert!(wallet.is_err());
+    }
+
+    #[test]
+    fn hd_wallet_gap_limit() {
+        let mut wallet = hd_wallet();
+        let hd_account = wallet.hd_account.clone().unwrap();
+        let output = TransactionOutput {
+            value: 100,
+            script_pubkey: get_script_pubkey(hd_account.receive_addresses[5].clone()).unwrap(),
+        };
+
+        assert!(wallet
+            .mark_used_addresses(std::slice::from_ref(&output))
+            .unwrap());
+        assert!(!wallet.mark_used_addresses(&[output]).unwrap());
+
+        let hd_account = wallet.hd_account.clone().unwrap();
+        assert_eq!(hd_account.used_receive, 6);
+        assert_eq!(hd_account.receive_addresses.len(), 6 + GAP_LIMIT as usize);
+        assert_eq!(hd_account.change_addresses.len(), GAP_LIMIT as usize);
+        assert_eq!(
+            wallet.get_receive_address(),
+            hd_account.receive_addresses[6]
+        );
+    }
+
+    #[test]
+    fn hd_wallet_serialization() {
+        let mut wallet = hd_wallet();
+        let change_script = get_script_pubkey(wallet.get_change_address()).unwrap();
+        wallet
+            .mark_used_addresses(&[TransactionOutput {
+                value: 100,
+                script_pubkey: change_script.clone(),
+            }])
+            .unwrap();
+
+        let legacy_wallet = Wallet {
+            name: String::from("test"),
+            pubkey: String::from("pubkey"),
+            privkey: Some(String::from("privkey")),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        };
+        let mut buffer = wallet.serialize();
+        buffer.extend(legacy_wallet.serialize());
+
+        let mut parser = BufferParser::new(&buffer);
+        let parsed_wallet = Wallet::parse(&mut parser).unwrap();
+        let parsed_legacy_wallet = Wallet::parse(&mut parser).unwrap();
+        assert!(parser.is_empty());
+
+        assert_eq!(parsed_wallet.pubkey, wallet.pubkey);
+        assert_eq!(parsed_wallet.hd_account.unwrap().used_change, 1);
+        assert_eq!(wallet.get_signing_key(&change_script).unwrap().len(), 32);
+        assert_eq!(parsed_legacy_wallet.name, "test");
+        assert!(parsed_legacy_wallet.hd_account.is_none());
+    }
+
+    #[test]
+    fn multisig_wallet() {
+        let mut wallet = hd_wallet();
+        let own_key = wallet
+            .hd_account
+            .clone()
+            .unwrap()
+            .derive_key(RECEIVE_CHAIN, 2)
+            .unwrap();
+        // public key de la private key 1, que no es de la wallet
+        let other_pubkey =
+            from_hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        let pubkeys = vec![other_pubkey.clone(), own_key.public_key().unwrap()];
+
+        assert!(wallet.add_multisig(1, &[other_pubkey]).is_err());
+        let address = wallet.add_multisig(2, &pubkeys).unwrap();
+        assert_eq!(wallet.add_multisig(2, &pubkeys).unwrap(), address);
+        assert_eq!(wallet.multisig_scripts.len(), 1);
+
+        assert!(address.starts_with('2'));
+        assert!(Address::from_str(&address).is_ok());
+        assert!(get_pubkey_hash(address.clone()).is_err());
+        let script_pubkey = get_script_pubkey(address.clone()).unwrap();
+        assert_eq!(get_address(&script_pubkey).unwrap(), address);
+        assert!(wallet
+            .get_script_pubkeys()
+            .unwrap()
+            .contains(&script_pubkey));
+
+        let output = TransactionOutput {
+            value: 100,
+            script_pubkey: script_pubkey.clone(),
+        };
+        assert!(output
+            .is_sent_to_any_key(&wallet.get_pubkey_hashes().unwrap())
+            .unwrap());
+        let redeem_script = wallet.find_multisig(&script_pubkey).unwrap().clone();
+        assert_eq!(
+            wallet.get_multisig_signing_key(&redeem_script).unwrap(),
+            own_key.secret_key
+        );
+
+        let parsed_wallet = Wallet::parse(&mut BufferParser::new(&wallet.serialize())).unwrap();
+        assert_eq!(parsed_wallet.multisig_scripts, vec![redeem_script]);
+        assert_eq!(parsed_wallet.pubkey, wallet.pubkey);
+        assert!(parsed_wallet.hd_account.is_some());
+    }
+
+    #[test]
+    fn wallet_bech32_address() {
+        let wallet = Wallet {
+            name: String::from("test"),
+            pubkey: String::from("mscatccDgq7azndWHFTzvEuZuywCsUvTRu"),
+            privkey: Some(String::from("privkey")),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        };
+        let bech32_address = wallet.get_bech32_address().unwrap();
+        assert!(bech32_address.starts_with("tb1q"));
+        assert!(Address::from_str(&bech32_address).is_ok());
+        assert_eq!(
+            get_pubkey_hash(bech32_address.clone()).unwrap(),
+            wallet.get_pubkey_hash().unwrap()
+        );
+
+        let mut script_pubkey = vec![0x00, 0x14];
+        script_pubkey.extend(wallet.get_pubkey_hash().unwrap());
+        assert_eq!(get_script_pubkey(bech32_address).unwrap(), script_pubkey);
+        assert!(wallet
+            .get_script_pubkeys()
+            .unwrap()
+            .contains(&script_pubkey));
+    }
+
+    #[test]
+    fn wallet_creation_with_bech32_pubkey() {
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        let wallet = Wallet::new(
+            String::from("test"),
+            String::from("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"),
+            String::from("privkey"),
+            &utxo_set,
+        )
+        .unwrap();
+        assert_eq!(wallet.get_script_pubkey().unwrap().len(), 22);
+    }
+
+    #[test]
+    fn hd_wallet_signs_p2wpkh_outputs() {
+        let wallet = hd_wallet();
+        let hd_account = wallet.hd_account.clone().unwrap();
+        let pubkey_hash = get_pubkey_hash(hd_account.receive_addresses[3].clone()).unwrap();
+        let signing_key = wallet
+            .get_signing_key(&get_p2wpkh_script_pubkey(&pubkey_hash))
+            .unwrap();
+        assert_eq!(
+            signing_key,
+            hd_account.derive_key(RECEIVE_CHAIN, 3).unwrap().secret_key
+        );
+    }
+
+    #[test]
+    fn hd_wallet_key_origin() {
+        let wallet = hd_wallet();
+        let hd_account = wallet.hd_account.clone().unwrap();
+        let pubkey_hash = get_pubkey_hash(hd_account.change_addresses[2].clone()).unwrap();
+        let key_origin = wallet
+            .get_key_origin(&get_p2pkh_script_pubkey(&pubkey_hash))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            key_origin.path,
+            vec![44 | HARDENED, 1 | HARDENED, HARDENED, CHANGE_CHAIN, 2]
+        );
+        assert_eq!(
+            key_origin.public_key,
+            hd_account
+                .derive_key(CHANGE_CHAIN, 2)
+                .unwrap()
+                .public_key()
+                .unwrap()
+        );
+        assert_eq!(wallet.get_key_origin(&[0x6a]).unwrap(), None);
+    }
+
+    #[test]
+    fn address_from_script_pubkey() {
+        for address in [
+            "mscatccDgq7azndWHFTzvEuZuywCsUvTRu",
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+        ] {
+            let script_pubkey = get_script_pubkey(String::from(address)).unwrap();
+            assert_eq!(get_address(&script_pubkey).unwrap(), address);
+        }
+        assert!(get_address(&[0x6a, 0x01, 0x00]).is_err());
+    }
+
+    #[test]
+    fn watch_only_wallet_from_address() {
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        let wallet = Wallet::watch_only(
+            String::from("cold"),
+            String::from("mscatccDgq7azndWHFTzvEuZuywCsUvTRu"),
+            &utxo_set,
+        )
+        .unwrap();
+        assert!(wallet.is_watch_only());
+        assert!(matches!(
+            wallet.get_signing_key(&wallet.get_script_pubkey().unwrap()),
+            Err(CustomError::Wallet(WalletError::WatchOnlyWallet))
+        ));
+
+        let serialized = wallet.serialize();
+
+        let mut parser = BufferParser::new(&serialized);
+        let parsed_wallet = Wallet::parse(&mut parser).unwrap();
+        assert!(parser.is_empty());
+        assert!(parsed_wallet.is_watch_only());
+        assert_eq!(parsed_wallet.pubkey, wallet.pubkey);
+
+        assert!(
+            Wallet::watch_only(String::from("cold"), String::from("invalid"), &utxo_set).is_err()
+        );
+    }
+
+    #[test]
+    fn watch_only_wallet_from_xpub() {
+        let utxo_set = UTXO::new(String::from("tests"), String::from("test_utxo.bin")).unwrap();
+        // xpub de m/0' del vector de prueba 1 de BIP 32
+        let xpub = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+        let wallet =
+            Wallet::watch_only(String::from("cold"), String::from(xpub), &utxo_set).unwrap();
+        assert!(wallet.is_watch_only());
+
+        // deriva las mismas direcciones que la cuenta con la private key extendida
+        let seed: Vec<u8> = (0..16).collect();
+        let account_key = ExtendedKey::from_seed(&seed)
+            .unwrap()
+            .derive_child(HARDENED)
+            .unwrap();
+        let account = HDAccount::new(account_key).unwrap();
+        let hd_account = wallet.hd_account.clone().unwrap();
+        assert_eq!(hd_account.receive_addresses, account.receive_addresses);
+        assert_eq!(hd_account.change_addresses, account.change_addresses);
+        assert_eq!(wallet.pubkey, account.receive_addresses[0]);
+        assert!(wallet
+            .get_signing_key(&wallet.get_script_pubkey().unwrap())
+            .is_err());
+
+        let serialized = wallet.serialize();
+
+        let mut parser = BufferParser::new(&serialized);
+        let parsed_wallet = Wallet::parse(&mut parser).unwrap();
+        assert!(parser.is_empty());
+        assert!(parsed_wallet.is_watch_only());
+        assert_eq!(
+            parsed_wallet.hd_account.unwrap().receive_addresses,
+            account.receive_addresses
+        );
+    }
 }
@@ -0,0 +1,454 @@
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use hmac::Hmac;
+use pbkdf2::pbkdf2;
+use sha2::Sha256;
+
+use crate::{
+    atomic_file::{read_atomic, write_atomic},
+    error::{CustomError, ParseError, WalletError},
+    parser::{BufferParser, Parser, VarIntSerialize},
+    structs::hash256::Hash256,
+    wallet::Wallet,
+};
+
+/// Bytes iniciales de un archivo de wallets encriptado.
+const ENCRYPTED_MAGIC: &[u8; 8] = b"BTCWENC1";
+/// Bytes iniciales de una copia de seguridad de una wallet.
+const BACKUP_MAGIC: &[u8; 8] = b"BTCWBAK2";
+/// Bytes iniciales de las copias de seguridad con las wallets serializadas sin encabezado.
+const LEGACY_BACKUP_MAGIC: &[u8; 8] = b"BTCWBAK1";
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+/// Iteraciones de PBKDF2 para derivar la clave de encriptacion a partir de la passphrase.
+const PBKDF2_ROUNDS: u32 = 100_000;
+/// Bytes iniciales de las wallets guardadas, antes de encriptarlas.
+const WALLETS_MAGIC: &[u8; 4] = b"RZWL";
+/// Version del formato de las wallets guardadas.
+const WALLETS_VERSION: u32 = 1;
+/// Tipo de registro de una wallet.
+const WALLET_RECORD: u8 = 1;
+/// Tipo de registro de la altura del ultimo bloque procesado por las wallets.
+const PROCESSED_HEIGHT_RECORD: u8 = 2;
+
+/// WalletStore se encarga de guardar y restaurar las wallets en su archivo.
+/// Si tiene una passphrase, el archivo se encripta con AES-256-GCM usando una clave derivada
+/// de la passphrase con PBKDF2-HMAC-SHA256. Los archivos sin encriptar se siguen pudiendo leer.
+/// El formato del archivo encriptado es: magic (8 bytes) | salt (16 bytes) | nonce (12 bytes) | wallets encriptadas.
+/// Las wallets se guardan con un magic y la version del formato, seguidos de registros que comienzan con su tipo:
+/// las wallets y la altura del ultimo bloque que procesaron, para escanear al desbloquearlas
+/// los bloques recibidos mientras estaban bloqueadas. Los archivos anteriores no tienen encabezado
+/// y solo guardan las wallets, una detras de otra.
+/// El archivo se guarda de forma atomica, ver atomic_file.
+/// Los elementos son:
+/// - path: Path del archivo donde se guardan las wallets.
+/// - passphrase: Passphrase con la que se encripta el archivo, None si se guarda sin encriptar.
+pub struct WalletStore {
+    path: String,
+    passphrase: Option<String>,
+}
+
+impl WalletStore {
+    /// Inicializa el store sin passphrase.
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            passphrase: None,
+        }
+    }
+
+    /// Establece la passphrase utilizada para encriptar y desencriptar el archivo.
+    pub fn set_passphrase(&mut self, passphrase: Option<String>) {
+        self.passphrase = passphrase.filter(|passphrase| !passphrase.is_empty());
+    }
+
+    /// Devuelve true si el archivo de wallets esta encriptado.
+    pub fn is_encrypted(&self) -> Result<bool, CustomError> {
+        Ok(self.read()?.starts_with(ENCRYPTED_MAGIC))
+    }
+
+    /// Restaura las wallets del archivo, desencriptandolo si es necesario.
+    /// Devuelve las wallets junto a la altura del ultimo bloque que procesaron, None si el archivo no la guarda.
+    /// Devuelve CustomError si:
+    /// - El archivo esta encriptado y no hay passphrase.
+    /// - La passphrase es incorrecta.
+    pub fn load(&self) -> Result<(Vec<Wallet>, Option<usize>), CustomError> {
+        let mut buffer = self.read()?;
+        if buffer.starts_with(ENCRYPTED_MAGIC) {
+            let Some(passphrase) = &self.passphrase else { return Err(CustomError::Wallet(WalletError::WalletsLocked)) };
+            buffer = decrypt(&buffer[ENCRYPTED_MAGIC.len()..], passphrase)?;
+        }
+
+        let mut parser = BufferParser::new(&buffer);
+        let mut wallets = vec![];
+        if !buffer.starts_with(WALLETS_MAGIC) {
+            while !parser.is_empty() {
+                wallets.push(Wallet::parse_legacy(&mut parser)?);
+            }
+            return Ok((wallets, None));
+        }
+
+        parser.extract_buffer(WALLETS_MAGIC.len())?;
+        if parser.extract_u32()? != WALLETS_VERSION {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        let mut processed_height = None;
+        while !parser.is_empty() {
+            match parser.extract_u8()? {
+                WALLET_RECORD => wallets.push(Wallet::parse(&mut parser)?),
+                PROCESSED_HEIGHT_RECORD => processed_height = Some(parser.extract_u32()? as usize),
+                _ => return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid)),
+            }
+        }
+        Ok((wallets, processed_height))
+    }
+
+    /// Guarda las wallets en el archivo junto a la altura del ultimo bloque que procesaron, encriptandolo si hay passphrase.
+    pub fn save(&self, wallets: &[Wallet], processed_height: usize) -> Result<(), CustomError> {
+        let mut buffer = WALLETS_MAGIC.to_vec();
+        buffer.extend(WALLETS_VERSION.to_le_bytes());
+        for wallet in wallets {
+            buffer.push(WALLET_RECORD);
+            buffer.append(&mut wallet.serialize());
+        }
+        buffer.push(PROCESSED_HEIGHT_RECORD);
+        buffer.extend((processed_height as u32).to_le_bytes());
+        if let Some(passphrase) = &self.passphrase {
+            let mut encrypted = ENCRYPTED_MAGIC.to_vec();
+            encrypted.extend(encrypt(&buffer, passphrase)?);
+            buffer = encrypted;
+        }
+
+        write_atomic(&self.path, &buffer)
+    }
+
+    fn read(&self) -> Result<Vec<u8>, CustomError> {
+        read_atomic(&self.path)
+    }
+}
+
+/// WalletBackup es la copia de seguridad de una wallet, que se guarda encriptada con una passphrase
+/// de la misma forma que el archivo de wallets.
+/// Guarda las claves de la wallet, su cuenta HD con las direcciones utilizadas y sus multisigs, pero no su historial,
+/// que se vuelve a generar al restaurarla.
+/// El formato del archivo es: magic (8 bytes) | salt (16 bytes) | nonce (12 bytes) | wallet y etiquetas encriptadas.
+/// Las copias de seguridad anteriores guardan la wallet sin encabezado y el largo de cada etiqueta en un byte.
+/// Los elementos son:
+/// - wallet: Wallet, sin historial.
+/// - labels: Etiquetas de las transacciones de la wallet.
+pub struct WalletBackup {
+    pub wallet: Wallet,
+    pub labels: Vec<(Hash256, String)>,
+}
+
+impl WalletBackup {
+    /// Guarda la copia de seguridad en el archivo, encriptada con la passphrase.
+    /// Devuelve CustomError si la passphrase esta vacia o no se puede escribir el archivo.
+    pub fn save(&self, path: &str, passphrase: &str) -> Result<(), CustomError> {
+        if passphrase.is_empty() {
+            return Err(CustomError::Validation(String::from(
+                "The backup passphrase cannot be empty",
+            )));
+        }
+        let mut wallet = self.wallet.clone();
+        wallet.history = vec![];
+        let mut buffer = wallet.serialize();
+        buffer.extend((self.labels.len() as u32).to_le_bytes());
+        for (tx_hash, label) in &self.labels {
+            buffer.extend(tx_hash.as_bytes());
+            buffer.extend(label.len().to_varint_bytes());
+            buffer.extend(label.as_bytes());
+        }
+
+        let mut encrypted = BACKUP_MAGIC.to_vec();
+        encrypted.extend(encrypt(&buffer, passphrase)?);
+        std::fs::write(path, encrypted)?;
+        Ok(())
+    }
+
+    /// Restaura la copia de seguridad del archivo, desencriptandola con la passphrase.
+    /// Devuelve CustomError si:
+    /// - No se puede leer el archivo o no es una copia de seguridad.
+    /// - La passphrase es incorrecta.
+    pub fn load(path: &str, passphrase: &str) -> Result<Self, CustomError> {
+        let buffer = std::fs::read(path)?;
+        let is_legacy = buffer.starts_with(LEGACY_BACKUP_MAGIC);
+        if !buffer.starts_with(BACKUP_MAGIC) && !is_legacy {
+            return Err(CustomError::Validation(String::from(
+                "The file is not a wallet backup",
+            )));
+        }
+        let buffer = decrypt(&buffer[BACKUP_MAGIC.len()..], passphrase)?;
+
+        let mut parser = BufferParser::new(&buffer);
+        let wallet = match is_legacy {
+            true => Wallet::parse_legacy(&mut parser)?,
+            false => Wallet::parse(&mut parser)?,
+        };
+        let mut labels = vec![];
+        for _ in 0..parser.extract_u32()? {
+            let tx_hash: Hash256 = parser.extract_hash32()?.into();
+            let label_length = match is_legacy {
+                true => parser.extract_u8()? as usize,
+                false => parser.extract_compact_size()? as usize,
+            };
+            let label = String::from_utf8(parser.extract_buffer(label_length)?.to_vec())
+                .map_err(|_| CustomError::Parse(ParseError::SerializedBufferIsInvalid))?;
+            labels.push((tx_hash, label));
+        }
+        if !parser.is_empty() {
+            return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+        }
+        Ok(Self { wallet, labels })
+    }
+}
+
+fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, CustomError> {
+    let mut salt = [0_u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0_u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+
+    let cipher = Aes256Gcm::new_from_slice(&derive_key(passphrase, &salt, PBKDF2_ROUNDS)?)
+        .map_err(|_| CustomError::Wallet(WalletError::InvalidPassphrase))?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| CustomError::Wallet(WalletError::InvalidPassphrase))?;
+
+    let mut buffer = salt.to_vec();
+    buffer.extend(nonce);
+    buffer.extend(ciphertext);
+    Ok(buffer)
+}
+
+fn decrypt(buffer: &[u8], passphrase: &str) -> Result<Vec<u8>, CustomError> {
+    if buffer.len() < SALT_SIZE + NONCE_SIZE {
+        return Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid));
+    }
+    let (salt, buffer) = buffer.split_at(SALT_SIZE);
+    let (nonce, ciphertext) = buffer.split_at(NONCE_SIZE);
+
+    let cipher = Aes256Gcm::new_from_slice(&derive_key(passphrase, salt, PBKDF2_ROUNDS)?)
+        .map_err(|_| CustomError::Wallet(WalletError::InvalidPassphrase))?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CustomError::Wallet(WalletError::InvalidPassphrase))
+}
+
+/// Deriva una clave de 32 bytes con PBKDF2-HMAC-SHA256.
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> Result<[u8; 32], CustomError> {
+    let mut key = [0_u8; 32];
+    pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, &mut key)
+        .map_err(|_| CustomError::Wallet(WalletError::InvalidPassphrase))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atomic_file::remove_atomic;
+
+    use super::*;
+
+    fn wallet(name: &str) -> Wallet {
+        Wallet {
+            name: String::from(name),
+            pubkey: String::from("mscatccDgq7azndWHFTzvEuZuywCsUvTRu"),
+            privkey: Some(String::from(
+                "cNpwEsaVLhju18SJowLtdCNaJtvMvqL4jtFLm2FXw7vZjg4sRWvH",
+            )),
+            history: vec![],
+            hd_account: None,
+            multisig_scripts: vec![],
+        }
+    }
+
+    #[test]
+    fn pbkdf2_known_values() {
+        let key: String = derive_key("password", b"salt", 1)
+            .unwrap()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            key,
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+
+        let key: String = derive_key("password", b"salt", 4096)
+            .unwrap()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            key,
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn encrypted_store_roundtrip() {
+        let path = String::from("tests/encrypted_wallets.bin");
+        let mut store = WalletStore::new(path.clone());
+        store.set_passphrase(Some(String::from("passphrase")));
+        store
+            .save(&[wallet("first"), wallet("second")], 120)
+            .unwrap();
+        assert!(store.is_encrypted().unwrap());
+
+        let (wallets, processed_height) = store.load().unwrap();
+        assert_eq!(wallets.len(), 2);
+        assert_eq!(wallets[1].name, "second");
+        assert_eq!(processed_height, Some(120));
+
+        let mut locked_store = WalletStore::new(path.clone());
+        assert!(matches!(
+            locked_store.load(),
+            Err(CustomError::Wallet(WalletError::WalletsLocked))
+        ));
+        locked_store.set_passphrase(Some(String::from("wrong")));
+        assert!(matches!(
+            locked_store.load(),
+            Err(CustomError::Wallet(WalletError::InvalidPassphrase))
+        ));
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn plaintext_store_is_still_readable() {
+        let path = String::from("tests/plaintext_wallets.bin");
+        let store = WalletStore::new(path.clone());
+        store.save(&[wallet("first")], 0).unwrap();
+        assert!(!store.is_encrypted().unwrap());
+
+        let mut store = WalletStore::new(path.clone());
+        store.set_passphrase(Some(String::from("passphrase")));
+        assert_eq!(store.load().unwrap().0[0].name, "first");
+        store.save(&store.load().unwrap().0, 0).unwrap();
+        assert!(store.is_encrypted().unwrap());
+
+        remove_atomic(&path).unwrap();
+    }
+
+    /// Serializa la wallet como se guardaba antes de que el archivo tuviera encabezado.
+    fn legacy_wallet(name: &str) -> Vec<u8> {
+        let wallet = wallet(name);
+        let privkey = wallet.privkey.unwrap();
+        let mut buffer = vec![];
+        for field in [&wallet.name, &wallet.pubkey, &privkey] {
+            buffer.push(field.len() as u8);
+            buffer.extend(field.as_bytes());
+        }
+        buffer.extend(0_u32.to_le_bytes());
+        buffer
+    }
+
+    #[test]
+    fn files_without_processed_height_are_still_readable() {
+        let path = String::from("tests/legacy_wallets.bin");
+        let mut buffer = legacy_wallet("first");
+        buffer.extend(legacy_wallet("second"));
+        write_atomic(&path, &buffer).unwrap();
+
+        let (wallets, processed_height) = WalletStore::new(path.clone()).load().unwrap();
+        assert_eq!(wallets.len(), 2);
+        assert_eq!(wallets[0].name, "first");
+        assert_eq!(wallets[1].privkey, wallet("second").privkey);
+        assert_eq!(processed_height, None);
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn wallets_with_long_fields_are_not_truncated() {
+        let path = String::from("tests/long_name_wallets.bin");
+        let name = "n".repeat(300);
+        let store = WalletStore::new(path.clone());
+        store.save(&[wallet(&name), wallet("second")], 7).unwrap();
+
+        let (wallets, processed_height) = store.load().unwrap();
+        assert_eq!(wallets.len(), 2);
+        assert_eq!(wallets[0].name, name);
+        assert_eq!(wallets[1].name, "second");
+        assert_eq!(processed_height, Some(7));
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn unknown_records_are_rejected() {
+        let path = String::from("tests/unknown_record_wallets.bin");
+        let store = WalletStore::new(path.clone());
+        store.save(&[wallet("first")], 0).unwrap();
+        let mut buffer = read_atomic(&path).unwrap();
+        buffer.extend([9, 0]);
+        write_atomic(&path, &buffer).unwrap();
+
+        assert!(matches!(
+            store.load(),
+            Err(CustomError::Parse(ParseError::SerializedBufferIsInvalid))
+        ));
+
+        remove_atomic(&path).unwrap();
+    }
+
+    #[test]
+    fn wallet_backup_roundtrip() {
+        let path = "tests/wallet_backup.bin";
+        let backup = WalletBackup {
+            wallet: wallet("backup"),
+            labels: vec![
+                (Hash256::from([7; 32]), String::from("rent")),
+                (Hash256::from([8; 32]), "l".repeat(300)),
+            ],
+        };
+        backup.save(path, "passphrase").unwrap();
+
+        let restored = WalletBackup::load(path, "passphrase").unwrap();
+        assert_eq!(restored.wallet.name, "backup");
+        assert_eq!(restored.wallet.privkey, backup.wallet.privkey);
+        assert_eq!(restored.labels, backup.labels);
+        assert!(matches!(
+            WalletBackup::load(path, "wrong"),
+            Err(CustomError::Wallet(WalletError::InvalidPassphrase))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn legacy_wallet_backup_is_still_readable() {
+        let path = "tests/legacy_wallet_backup.bin";
+        let mut buffer = legacy_wallet("backup");
+        buffer.extend(1_u32.to_le_bytes());
+        buffer.extend([7; 32]);
+        buffer.push(4);
+        buffer.extend(b"rent");
+        let mut file = LEGACY_BACKUP_MAGIC.to_vec();
+        file.extend(encrypt(&buffer, "passphrase").unwrap());
+        std::fs::write(path, file).unwrap();
+
+        let restored = WalletBackup::load(path, "passphrase").unwrap();
+        assert_eq!(restored.wallet.name, "backup");
+        assert_eq!(
+            restored.labels,
+            vec![(Hash256::from([7; 32]), String::from("rent"))]
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn wallet_backup_requires_a_passphrase() {
+        let backup = WalletBackup {
+            wallet: wallet("backup"),
+            labels: vec![],
+        };
+        assert!(matches!(
+            backup.save("tests/empty_passphrase_backup.bin", ""),
+            Err(CustomError::Validation(_))
+        ));
+    }
+}
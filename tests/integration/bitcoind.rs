@@ -0,0 +1,194 @@
+use std::{
+    env, fs,
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bitcoin::{base64, json::JsonValue};
+use ureq::Agent;
+
+/// Usuario y contraseña del servidor RPC de bitcoind.
+const RPC_USER: &str = "regtest";
+const RPC_PASSWORD: &str = "regtest";
+/// Tiempo maximo que se espera a que bitcoind acepte pedidos RPC.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bitcoind es un bitcoind de regtest iniciado para una prueba, con su propio directorio de datos.
+/// El ejecutable se toma de la variable de entorno BITCOIND o, si no esta, del PATH.
+/// Al liberarse detiene el proceso y elimina su directorio de datos.
+/// Los elementos son:
+/// - process: Proceso de bitcoind.
+/// - data_dir: Directorio de datos de bitcoind.
+/// - p2p_port: Puerto en el que bitcoind acepta peers.
+/// - rpc_port: Puerto del servidor RPC.
+/// - agent: Cliente HTTP con el que se hacen los pedidos RPC.
+pub struct Bitcoind {
+    process: Child,
+    data_dir: PathBuf,
+    p2p_port: u16,
+    rpc_port: u16,
+    agent: Agent,
+}
+
+impl Bitcoind {
+    /// Inicia bitcoind en regtest con una wallet cargada y espera a que acepte pedidos RPC.
+    /// Los peers locales tienen permisos de whitelist, para que sus transacciones se acepten sin esperar,
+    /// y se atienden los bloom filters que carga el nodo de la wallet.
+    pub fn start(data_dir: &Path) -> Self {
+        fs::create_dir_all(data_dir).unwrap();
+        let p2p_port = free_port();
+        let rpc_port = free_port();
+        let executable = env::var("BITCOIND").unwrap_or_else(|_| "bitcoind".to_string());
+        let process = Command::new(executable)
+            .arg("-regtest")
+            .arg(format!("-datadir={}", data_dir.display()))
+            .arg(format!("-port={p2p_port}"))
+            .arg(format!("-rpcport={rpc_port}"))
+            .arg(format!("-rpcuser={RPC_USER}"))
+            .arg(format!("-rpcpassword={RPC_PASSWORD}"))
+            .arg("-bind=127.0.0.1")
+            .arg("-listen=1")
+            .arg("-server=1")
+            .arg("-whitelist=127.0.0.1")
+            .arg("-peerbloomfilters=1")
+            .arg("-fallbackfee=0.0002")
+            .arg("-printtoconsole=0")
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("bitcoind must be installed, or its path set in the BITCOIND variable");
+
+        let bitcoind = Self {
+            process,
+            data_dir: data_dir.to_path_buf(),
+            p2p_port,
+            rpc_port,
+            agent: Agent::config_builder()
+                .http_status_as_error(false)
+                .build()
+                .into(),
+        };
+        bitcoind.wait_for_rpc();
+        bitcoind.rpc("createwallet", vec![JsonValue::String("miner".to_string())]);
+        bitcoind
+    }
+
+    /// Devuelve la direccion en la que bitcoind acepta peers.
+    pub fn p2p_address(&self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, self.p2p_port))
+    }
+
+    /// Ejecuta un metodo RPC y devuelve su resultado.
+    /// Falla la prueba si bitcoind responde con un error.
+    pub fn rpc(&self, method: &str, params: Vec<JsonValue>) -> JsonValue {
+        self.try_rpc(method, params)
+            .unwrap_or_else(|error| panic!("bitcoind {method} failed: {error}"))
+    }
+
+    /// Devuelve una direccion P2PKH nueva de la wallet de bitcoind, del formato que acepta la wallet del nodo.
+    pub fn new_address(&self) -> String {
+        let address = self.rpc(
+            "getnewaddress",
+            vec![
+                JsonValue::String(String::new()),
+                JsonValue::String("legacy".to_string()),
+            ],
+        );
+        address.as_str().unwrap().to_string()
+    }
+
+    /// Mina bloques, cuya recompensa va a la wallet de bitcoind, y devuelve la altura de la cadena.
+    pub fn mine(&self, blocks: usize) -> usize {
+        let address = self.new_address();
+        self.rpc(
+            "generatetoaddress",
+            vec![JsonValue::Number(blocks as f64), JsonValue::String(address)],
+        );
+        self.block_count()
+    }
+
+    /// Devuelve la altura de la cadena de bitcoind.
+    pub fn block_count(&self) -> usize {
+        self.rpc("getblockcount", vec![]).as_f64().unwrap() as usize
+    }
+
+    /// Envia un monto en bitcoins a una direccion y devuelve el txid.
+    pub fn send_to_address(&self, address: &str, amount: f64) -> String {
+        let txid = self.rpc(
+            "sendtoaddress",
+            vec![
+                JsonValue::String(address.to_string()),
+                JsonValue::Number(amount),
+            ],
+        );
+        txid.as_str().unwrap().to_string()
+    }
+
+    /// Devuelve los txid de las transacciones en el mempool de bitcoind.
+    pub fn mempool(&self) -> Vec<String> {
+        self.rpc("getrawmempool", vec![])
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|txid| txid.as_str().map(str::to_string))
+            .collect()
+    }
+
+    fn try_rpc(&self, method: &str, params: Vec<JsonValue>) -> Result<JsonValue, String> {
+        let request = JsonValue::object(vec![
+            ("jsonrpc", JsonValue::String("1.0".to_string())),
+            ("id", JsonValue::String("integration".to_string())),
+            ("method", JsonValue::String(method.to_string())),
+            ("params", JsonValue::Array(params)),
+        ]);
+        let credentials = base64::encode(format!("{RPC_USER}:{RPC_PASSWORD}").as_bytes());
+        let body = self
+            .agent
+            .post(&format!("http://127.0.0.1:{}/", self.rpc_port))
+            .header("Authorization", &format!("Basic {credentials}"))
+            .send(request.to_string())
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|error| error.to_string())?;
+
+        let response = JsonValue::parse(&body).map_err(|_| body.clone())?;
+        match response.get("error") {
+            Some(JsonValue::Null) | None => {
+                Ok(response.get("result").cloned().unwrap_or(JsonValue::Null))
+            }
+            Some(error) => Err(error.to_string()),
+        }
+    }
+
+    fn wait_for_rpc(&self) {
+        let start = Instant::now();
+        while self.try_rpc("getblockchaininfo", vec![]).is_err() {
+            assert!(
+                start.elapsed() < STARTUP_TIMEOUT,
+                "bitcoind did not start in {:?}",
+                STARTUP_TIMEOUT
+            );
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+impl Drop for Bitcoind {
+    fn drop(&mut self) {
+        if self.try_rpc("stop", vec![]).is_err() {
+            let _ = self.process.kill();
+        }
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+/// Devuelve un puerto local libre, pidiendole uno al sistema operativo.
+fn free_port() -> u16 {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
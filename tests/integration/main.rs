@@ -0,0 +1,267 @@
+//! Pruebas de punta a punta contra un bitcoind de regtest.
+//! Requieren bitcoind instalado, por lo que se ignoran por defecto. Se corren con:
+//! BITCOIND=/path/to/bitcoind cargo test --test integration -- --ignored
+
+mod bitcoind;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bitcoin::{
+    config::Config,
+    fee_estimator::FeePriority,
+    gui::init::GUIEvents,
+    logger::{Log, LogEntry, Logger},
+    loops::node_action_loop::NodeAction,
+    network::Network,
+    node::{Node, NodeHandle},
+    node_state::NodeState,
+    states::history_state::Direction,
+    transaction_builder::{Fee, Payment},
+};
+use gtk::glib;
+
+use bitcoind::Bitcoind;
+
+/// Mnemonico de la wallet HD con la que se prueba el nodo.
+const MNEMONIC: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+/// Tiempo maximo que se espera a que el nodo alcance un estado esperado.
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// Cantidad de satoshis en un bitcoin.
+const COIN: u64 = 100_000_000;
+/// Cantidad de bloques que deben pasar para poder gastar la recompensa de un bloque.
+const COINBASE_MATURITY: usize = 100;
+
+/// TestDir es un directorio temporal para una prueba, que se elimina al liberarse.
+struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("btc-wallet-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// RegtestNode es un nodo de la wallet conectado unicamente al bitcoind de la prueba.
+/// No escucha conexiones entrantes ni consulta el precio de bitcoin, para que las pruebas no dependan de la red.
+/// Los elementos son:
+/// - node_state_ref: Referencia al estado del nodo.
+/// - node_handle: NodeHandle con el que se detiene el nodo.
+/// - logger: Logger del nodo.
+/// - _gui_receiver: Receiver de los eventos de la interfaz grafica, se conserva para que el nodo pueda enviarlos.
+struct RegtestNode {
+    node_state_ref: Arc<Mutex<NodeState>>,
+    node_handle: NodeHandle,
+    logger: Logger,
+    _gui_receiver: glib::Receiver<GUIEvents>,
+}
+
+impl RegtestNode {
+    /// Inicia un nodo con el store del directorio recibido.
+    /// Si el store no tiene wallets se agrega la wallet HD del mnemonico de prueba.
+    fn start(dir: &Path, bitcoind: &Bitcoind) -> Self {
+        let config_path = dir.join("config.txt");
+        fs::write(
+            &config_path,
+            format!(
+                "network=regtest\n\
+                 peers=[{}]\n\
+                 npeers=1\n\
+                 protocol_version=70016\n\
+                 client_only=true\n\
+                 log={}\n\
+                 store_path={}\n\
+                 exchange_rate_url=\n\
+                 desktop_notifications=false\n",
+                bitcoind.p2p_address(),
+                dir.join("node.log").display(),
+                dir.join("store").display(),
+            ),
+        )
+        .unwrap();
+        let config = Config::from_file(config_path.to_str().unwrap()).unwrap();
+        Network::select(config.network);
+
+        let (gui_sender, gui_receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let logger = Logger::new(&config.log_file, gui_sender.clone()).unwrap();
+        let node_state_ref =
+            NodeState::new(logger.get_sender(), gui_sender.clone(), &config.store_path).unwrap();
+
+        let mut node_state = node_state_ref.lock().unwrap();
+        if node_state.get_active_wallet_receive_address().is_err() {
+            node_state
+                .append_wallet(String::from("regtest"), String::new(), MNEMONIC.to_string())
+                .unwrap();
+        }
+        drop(node_state);
+
+        let node = Node::new(&config, &logger, node_state_ref.clone()).unwrap();
+        let node_handle = node.spawn(config.peer_addresses().unwrap(), gui_sender);
+        Self {
+            node_state_ref,
+            node_handle,
+            logger,
+            _gui_receiver: gui_receiver,
+        }
+    }
+
+    /// Espera a que el estado del nodo cumpla la condicion, fallando la prueba si no la cumple antes del TIMEOUT.
+    fn wait_until(&self, description: &str, condition: impl Fn(&mut NodeState) -> bool) {
+        let start = Instant::now();
+        while !condition(&mut self.node_state_ref.lock().unwrap()) {
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "timed out waiting until {description}"
+            );
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    /// Espera a que el nodo termine de sincronizar la cadena de bitcoind hasta la altura recibida.
+    fn wait_until_synced(&self, height: usize) {
+        self.wait_until(&format!("synced at height {height}"), |node_state| {
+            node_state.is_synced() && node_state.get_best_height() == height
+        });
+    }
+
+    /// Devuelve el resultado de ejecutar la funcion con el estado del nodo.
+    fn with_state<T>(&self, function: impl FnOnce(&mut NodeState) -> T) -> T {
+        function(&mut self.node_state_ref.lock().unwrap())
+    }
+
+    /// Detiene el nodo y el logger y espera a que terminen, con el estado del nodo guardado en disco.
+    fn stop(self) {
+        self.node_handle.stop().unwrap();
+        self.logger.tx.send(LogEntry::new(Log::Terminate)).unwrap();
+        self.logger.thread.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+#[ignore = "requires bitcoind"]
+fn receives_and_sends_funds_through_bitcoind() {
+    let dir = TestDir::new("funds");
+    let bitcoind = Bitcoind::start(&dir.path.join("bitcoind"));
+    let height = bitcoind.mine(COINBASE_MATURITY + 1);
+
+    let node = RegtestNode::start(&dir.path, &bitcoind);
+    node.wait_until_synced(height);
+    assert_eq!(
+        node.with_state(|node_state| node_state.get_active_wallet_balances().unwrap().confirmed),
+        0
+    );
+
+    // bitcoind envia 1.5 BTC a la wallet y los confirma en un bloque
+    let address =
+        node.with_state(|node_state| node_state.get_active_wallet_receive_address().unwrap());
+    let received_txid = bitcoind.send_to_address(&address, 1.5);
+    let height = bitcoind.mine(1);
+    node.wait_until("the received funds are confirmed", |node_state| {
+        node_state.get_best_height() == height
+            && node_state.get_active_wallet_balances().unwrap().confirmed == 150_000_000
+    });
+
+    let (history, best_height) =
+        node.with_state(|node_state| node_state.get_active_wallet_history());
+    assert_eq!(best_height, height);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].tx_hash.to_string(), received_txid);
+    assert_eq!(history[0].direction, Direction::Received);
+    assert_eq!(history[0].amount, 150_000_000);
+    assert_eq!(history[0].block_height, Some(height));
+
+    // la wallet le devuelve 0.5 BTC a bitcoind
+    let bitcoind_address = bitcoind.new_address();
+    let transaction = node.with_state(|node_state| {
+        let fee_rate = node_state.get_fee_rate(FeePriority::Medium);
+        node_state
+            .make_transaction(Payment::new(
+                vec![(bitcoind_address, COIN / 2)],
+                Fee::Rate(fee_rate),
+            ))
+            .unwrap()
+    });
+    let sent_txid = transaction.hash().to_string();
+    node.node_handle
+        .node_action_sender
+        .send(NodeAction::SendTransaction(transaction))
+        .unwrap();
+
+    let start = Instant::now();
+    while !bitcoind.mempool().contains(&sent_txid) {
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "bitcoind did not receive the transaction"
+        );
+        thread::sleep(Duration::from_millis(250));
+    }
+    let height = bitcoind.mine(1);
+    node.wait_until("the sent funds are confirmed", |node_state| {
+        let (history, _) = node_state.get_active_wallet_history();
+        history.iter().any(|entry| {
+            entry.tx_hash.to_string() == sent_txid && entry.block_height == Some(height)
+        })
+    });
+
+    let (history, _) = node.with_state(|node_state| node_state.get_active_wallet_history());
+    let sent = history
+        .iter()
+        .find(|entry| entry.tx_hash.to_string() == sent_txid)
+        .unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(sent.direction, Direction::Sent);
+    assert!(sent.amount > COIN / 2);
+    assert_eq!(
+        node.with_state(|node_state| node_state.get_active_wallet_balances().unwrap().confirmed),
+        150_000_000 - sent.amount
+    );
+
+    node.stop();
+}
+
+#[test]
+#[ignore = "requires bitcoind"]
+fn restarts_from_the_saved_store() {
+    let dir = TestDir::new("restart");
+    let bitcoind = Bitcoind::start(&dir.path.join("bitcoind"));
+    let height = bitcoind.mine(COINBASE_MATURITY + 1);
+
+    let node = RegtestNode::start(&dir.path, &bitcoind);
+    node.wait_until_synced(height);
+    let address =
+        node.with_state(|node_state| node_state.get_active_wallet_receive_address().unwrap());
+    bitcoind.send_to_address(&address, 2.0);
+    bitcoind.mine(1);
+    node.wait_until("the received funds are confirmed", |node_state| {
+        node_state.get_active_wallet_balances().unwrap().confirmed == 2 * COIN
+    });
+    node.stop();
+
+    // el nodo retoma la sincronizacion desde el store guardado al detenerse
+    let height = bitcoind.mine(5);
+    let node = RegtestNode::start(&dir.path, &bitcoind);
+    node.wait_until_synced(height);
+    assert_eq!(
+        node.with_state(|node_state| node_state.get_active_wallet_balances().unwrap().confirmed),
+        2 * COIN
+    );
+    let (history, _) = node.with_state(|node_state| node_state.get_active_wallet_history());
+    assert_eq!(history.len(), 1);
+    node.stop();
+}
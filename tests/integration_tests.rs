@@ -4,7 +4,7 @@ mod tests {
         fs::{self, File},
         io::{BufRead, BufReader},
         net::{Ipv6Addr, SocketAddrV6},
-        sync::{mpsc, Arc, Mutex},
+        sync::mpsc,
         thread,
         time::Duration,
     };
@@ -13,12 +13,18 @@ mod tests {
         config::Config,
         logger::Logger,
         loops::{
-            peer_action_loop::PeerAction, pending_blocks_loop::pending_blocks_loop,
-            tcp_listener_loop::TcpListenerLoop,
+            network_loop::NetworkLoop,
+            tcp_listener_loop::{TcpListenerLoop, DEFAULT_MAX_INBOUND},
         },
         node::Node,
         node_state::NodeState,
         peer::Peer,
+        shutdown::ShutdownSignal,
+        states::peer_info_state::PeerInfoRegistry,
+        structs::{
+            hash256::Hash256,
+            inventory::{Inventory, InventoryType},
+        },
         utils::get_addresses,
     };
     use gtk::glib::{self, Priority};
@@ -46,20 +52,26 @@ mod tests {
         let (gui_sender, _gui_receiver) = glib::MainContext::channel(Priority::default());
         let mut addresses =
             get_addresses("seed.testnet.bitcoin.sprovoost.nl".to_string(), 18333).unwrap();
-        let (_peer_action_sender, receiver) = mpsc::channel();
-        let peer_action_receiver = Arc::new(Mutex::new(receiver));
+        let (_peer_action_sender, peer_action_receiver) = mpsc::channel();
         let (node_action_sender, _node_action_receiver) = mpsc::channel();
         let logger = Logger::new(&String::from("tests/test_log2.txt"), gui_sender.clone()).unwrap();
         let logger_sender = logger.get_sender();
+        let (network_loop, network) = NetworkLoop::new(
+            peer_action_receiver,
+            node_action_sender,
+            logger_sender.clone(),
+            PeerInfoRegistry::new(),
+        )
+        .unwrap();
+        let _network_thread = network_loop.spawn();
 
         let peer = Peer::call(
             addresses.next().unwrap(),
             SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 18333, 0, 0),
             0,
             70012,
-            peer_action_receiver.clone(),
+            &network,
             logger_sender.clone(),
-            node_action_sender.clone(),
         );
 
         assert!(peer.is_ok());
@@ -70,9 +82,8 @@ mod tests {
             SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 18333, 0, 0),
             0,
             70012,
-            peer_action_receiver,
+            &network,
             logger_sender,
-            node_action_sender,
         );
 
         assert!(peer2.is_ok());
@@ -99,29 +110,19 @@ mod tests {
         let logger = Logger::new(&String::from("tests/test_log3.txt"), gui_sender.clone()).unwrap();
         let logger_sender = logger.get_sender();
 
-        let (peer_action_sender, receiver) = mpsc::channel();
-        let peer_action_receiver = Arc::new(Mutex::new(receiver));
-
-        let store_path = String::from("tests");
-        let node_state_ref =
-            NodeState::new(logger_sender.clone(), gui_sender, &store_path).unwrap();
-        let node_state = node_state_ref.clone();
-        let mut node_state = node_state.lock().unwrap();
-        node_state.append_pending_block(vec![1, 2, 3]).unwrap();
-        assert_eq!(node_state.is_pending_blocks_empty().unwrap(), false);
+        let store_path = String::from("tests/store3");
+        let node_state_ref = NodeState::new(logger_sender, gui_sender, &store_path).unwrap();
+        let mut node_state = node_state_ref.lock().unwrap();
+        let block_hash = Hash256::new([1; 32]);
+        node_state
+            .append_pending_blocks(vec![Inventory::new(InventoryType::Block, block_hash)])
+            .unwrap();
+        assert!(!node_state.is_pending_blocks_empty().unwrap());
+        assert!(node_state.is_block_pending(&block_hash).unwrap());
         drop(node_state);
 
-        pending_blocks_loop(node_state_ref, peer_action_sender, logger_sender);
-
-        thread::sleep(Duration::from_secs(5));
-
-        let message = peer_action_receiver.lock().unwrap().recv().unwrap();
-        if let PeerAction::GetData(_) = message {
-            assert!(true);
-        } else {
-            assert!(false);
-        }
         fs::remove_file("tests/test_log3.txt").unwrap();
+        fs::remove_dir_all("tests/store3").unwrap();
     }
 
     #[test]
@@ -131,24 +132,32 @@ mod tests {
         let logger = Logger::new(&String::from("tests/test_log4.txt"), gui_sender.clone()).unwrap();
         let logger_sender = logger.get_sender();
 
-        let (_peer_action_sender, receiver) = mpsc::channel();
-        let peer_action_receiver = Arc::new(Mutex::new(receiver));
-
+        let (_peer_action_sender, peer_action_receiver) = mpsc::channel();
         let (node_action_sender, _node_action_receiver) = mpsc::channel();
+        let (network_loop, network) = NetworkLoop::new(
+            peer_action_receiver,
+            node_action_sender,
+            logger_sender.clone(),
+            PeerInfoRegistry::new(),
+        )
+        .unwrap();
+        let _network_thread = network_loop.spawn();
 
-        let store_path = String::from("tests");
+        let store_path = String::from("tests/store4");
         let node_state_ref =
             NodeState::new(logger_sender.clone(), gui_sender, &store_path).unwrap();
 
-        let _tcp_listener = TcpListenerLoop::spawn(
-            logger_sender.clone(),
-            node_state_ref.clone(),
-            SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 18334, 0, 0),
-            123,
-            70015,
-            peer_action_receiver.clone(),
-            node_action_sender.clone(),
-        );
+        let _tcp_listener = TcpListenerLoop {
+            logger_sender: logger_sender.clone(),
+            node_state_ref: node_state_ref.clone(),
+            address: SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 18334, 0, 0),
+            services: 123,
+            version: 70015,
+            max_inbound: DEFAULT_MAX_INBOUND,
+            network: network.clone(),
+            shutdown: ShutdownSignal::new(),
+        }
+        .spawn();
         let mut addresses = get_addresses("127.0.0.1".to_string(), 18334).unwrap();
         thread::sleep(Duration::from_secs(5));
 
@@ -156,14 +165,14 @@ mod tests {
             addresses.next().unwrap(),
             SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2), 18334, 0, 0),
             1,
-            13,
-            peer_action_receiver.clone(),
+            70012,
+            &network,
             logger_sender.clone(),
-            node_action_sender.clone(),
         )
         .unwrap();
 
-        assert_eq!(peer.version, 70015);
+        // se negocia la menor de las versiones de protocolo
+        assert_eq!(peer.version, 70012);
         assert_eq!(peer.services, 123);
 
         thread::sleep(Duration::from_secs(1));
@@ -171,10 +180,11 @@ mod tests {
         let mut node_state = node_state_ref.lock().unwrap();
         let peers = node_state.get_peers();
         assert_eq!(peers.len(), 1);
-        assert_eq!(peers[0].version, 13);
+        assert_eq!(peers[0].version, 70012);
         assert_eq!(peers[0].services, 1);
         drop(node_state);
 
         fs::remove_file("tests/test_log4.txt").unwrap();
+        fs::remove_dir_all("tests/store4").unwrap();
     }
 }